@@ -3,33 +3,68 @@ use std::fs::File;
 use std::str;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
+use axum::Router;
 use clap::Args;
 use clap::Parser;
 use clap::Subcommand;
 use daemonize::Daemonize;
 use futures::lock::Mutex;
 use libc::kill;
+use rings_node::config::BootstrapConfig;
+use rings_node::config::BootstrapMode;
+use rings_node::doctor;
+use rings_node::logger::init_tracing;
+use rings_node::logger::init_tracing_with_writer;
+use rings_node::logger::LogFormat;
 use rings_node::logger::LogLevel;
-use rings_node::logger::Logger;
+use rings_node::logger::RotatingFileWriter;
 use rings_node::prelude::rings_core::async_trait;
+use rings_node::prelude::rings_core::clock::DEFAULT_MAX_CLOCK_SKEW_MS;
+use rings_node::prelude::rings_core::dht::routing::RoutingStrategy;
+use rings_node::prelude::rings_core::dht::routing::TagPreferencePolicy;
 use rings_node::prelude::rings_core::dht::PeerRing;
 use rings_node::prelude::rings_core::dht::Stabilization;
-use rings_node::prelude::rings_core::dht::TStabilize;
 use rings_node::prelude::rings_core::ecc::SecretKey;
+use rings_node::prelude::rings_core::history::MessageHistory;
+use rings_node::prelude::rings_core::history::DEFAULT_MAX_AGE_MS;
+use rings_node::prelude::rings_core::history::DEFAULT_MAX_RECORDS;
 use rings_node::prelude::rings_core::message;
+use rings_node::prelude::rings_core::message::codec::Codec;
 use rings_node::prelude::rings_core::message::CustomMessage;
 use rings_node::prelude::rings_core::message::MaybeEncrypted;
 use rings_node::prelude::rings_core::message::Message;
 use rings_node::prelude::rings_core::message::MessageHandler;
 use rings_node::prelude::rings_core::message::MessagePayload;
+use rings_node::prelude::rings_core::message::TopologyPolicy;
+use rings_node::prelude::rings_core::message::DEFAULT_NETWORK_ID;
+use rings_node::prelude::rings_core::pex::DEFAULT_MAX_CONNECTIONS;
 use rings_node::prelude::rings_core::prelude::url;
+use rings_node::prelude::rings_core::pubkey::derive_encryption_key;
+use rings_node::prelude::rings_core::replay::DEFAULT_REPLAY_WINDOW_MS;
 use rings_node::prelude::rings_core::session::SessionManager;
+use rings_node::prelude::rings_core::storage::cipher::load_salt;
+use rings_node::prelude::rings_core::storage::StorageCipher;
 use rings_node::prelude::rings_core::swarm::Swarm;
+use rings_node::prelude::rings_core::tags::PeerTags;
+use rings_node::prelude::rings_core::types::ice_transport::IceTransportPolicy;
+use rings_node::prelude::rings_core::types::ice_transport::IpFamily;
 use rings_node::prelude::rings_core::types::message::MessageListener;
-use rings_node::service::run_service;
+use rings_node::prelude::rings_core::version::VersionPolicy;
+use rings_node::processor::Processor;
+use rings_node::service::control::run_control_socket;
+use rings_node::service::control::send_control_request;
+use rings_node::service::control::ControlRequest;
+use rings_node::service::control::ControlResponse;
+use rings_node::service::run_bootstrap;
+use rings_node::service::run_mdns;
+use rings_node::service::run_service_with_routes;
 use rings_node::service::run_udp_turn;
+use rings_node::service::turn_credential::turn_credential_router;
+use rings_node::service::turn_credential::TurnCredentials;
 use tokio::signal;
+use tokio::sync::Notify;
 
 #[derive(Parser, Debug)]
 #[clap(about)]
@@ -37,14 +72,46 @@ struct Cli {
     #[clap(long, short = 'v', default_value_t = LogLevel::Info, arg_enum)]
     log_level: LogLevel,
 
+    /// Per module log levels, eg: `info,rings_core::swarm=debug`, overrides `log-level`.
+    #[clap(long, env = "RUST_LOG")]
+    log_filter: Option<String>,
+
+    /// Format of logs, default to json in log file, text otherwise.
+    #[clap(long, arg_enum)]
+    log_format: Option<LogFormat>,
+
     #[clap(subcommand)]
     command: Command,
 }
 
+impl Cli {
+    /// Log to `log_file`, or to stdout if it's None.
+    fn init_tracing(&self, log_file: Option<RotatingFileWriter>) -> AnyhowResult<()> {
+        let filter = self.log_filter.as_deref();
+        match log_file {
+            Some(w) => init_tracing_with_writer(
+                self.log_level.clone(),
+                filter,
+                self.log_format.unwrap_or(LogFormat::Json),
+                w,
+            ),
+            None => init_tracing(
+                self.log_level.clone(),
+                filter,
+                self.log_format.unwrap_or(LogFormat::Text),
+            ),
+        }
+    }
+}
+
 #[derive(Subcommand, Debug)]
 enum Command {
     Run(Box<RunArgs>),
     Shutdown(ShutdownArgs),
+    /// Stop a running daemon gracefully via control socket.
+    Stop(ControlArgs),
+    /// Show status of a running daemon via control socket.
+    Status(ControlArgs),
 }
 
 #[derive(Args, Debug)]
@@ -52,6 +119,18 @@ struct RunArgs {
     #[clap(long, short = 'b', default_value = "127.0.0.1:50000", env)]
     pub http_addr: String,
 
+    /// Also serve jsonrpc on this unix socket.
+    #[clap(long)]
+    pub rpc_socket: Option<String>,
+
+    /// HTTP callers presenting this bearer token may use admin methods like `sendFile`.
+    #[clap(long, env = "RINGS_ADMIN_TOKEN")]
+    pub admin_token: Option<String>,
+
+    /// `sendFile` and `fetchFile` are confined to this directory.
+    #[clap(long)]
+    pub share_dir: Option<String>,
+
     #[clap(long, short = 's', default_value = "stun://stun.l.google.com:19302")]
     pub ice_server: Vec<String>,
 
@@ -66,6 +145,8 @@ struct RunArgs {
     #[clap(long = "key", short = 'k', env)]
     pub eth_key: SecretKey,
 
+    /// Detach from terminal and run in background.
+    /// When managed by systemd, run in foreground with `Type=simple` instead.
     #[clap(short = 'd')]
     pub daemonize: bool,
 
@@ -109,8 +190,188 @@ struct RunArgs {
     #[clap(long)]
     pub without_turn: bool,
 
+    /// Shared secret of ephemeral TURN credentials, enables `GET /turn` on http server.
+    #[clap(long, env)]
+    pub turn_secret: Option<String>,
+
+    /// Lifetime of ephemeral TURN credentials, in seconds.
+    #[clap(long, default_value = "86400")]
+    pub turn_credential_ttl: u64,
+
+    /// Max authenticated TURN requests of each ephemeral credential.
+    #[clap(long)]
+    pub turn_credential_quota: Option<u64>,
+
     #[clap(long, default_value = "20")]
     pub stabilize_timeout: usize,
+
+    /// Adapt interval of stabilization to churn, from `stabilize-timeout` up to N seconds.
+    #[clap(long)]
+    pub stabilize_max_interval: Option<usize>,
+
+    /// Advertise this node as relay capable, only for nodes with public address.
+    #[clap(long)]
+    pub relay: bool,
+
+    /// Echo custom messages back to their senders, for benchmark.
+    #[clap(long)]
+    pub echo: bool,
+
+    /// Advertise this node and connect to nodes of the same network on LAN by mDNS.
+    #[clap(long)]
+    pub mdns: bool,
+
+    /// `manual`, `static`, `dns` or `cache`, how seeds to join the ring are found.
+    #[clap(long, default_value = "manual")]
+    pub bootstrap: BootstrapMode,
+
+    /// URL of jsonrpc server of a seed node, for `static` bootstrap.
+    #[clap(long = "seed")]
+    pub seeds: Vec<String>,
+
+    /// Name of TXT records of seed URLs, for `dns` bootstrap, like `_rings.example.org`.
+    #[clap(long)]
+    pub bootstrap_dns_name: Option<String>,
+
+    /// Nameserver asked for `bootstrap-dns-name`, nameserver of system if unset.
+    #[clap(long)]
+    pub bootstrap_dns_server: Option<String>,
+
+    /// Resolve `bootstrap-dns-name` again every N seconds.
+    #[clap(long, default_value = "300")]
+    pub bootstrap_refresh_secs: u64,
+
+    /// Save connected seeds here, they are dialed by `cache` bootstrap.
+    #[clap(long)]
+    pub bootstrap_cache_path: Option<String>,
+
+    /// Network to join, nodes of different networks never connect to each other.
+    #[clap(long, default_value = DEFAULT_NETWORK_ID)]
+    pub network_id: String,
+
+    /// `warn` or `refuse` peers without common protocol version.
+    #[clap(long, default_value = "warn")]
+    pub version_policy: VersionPolicy,
+
+    /// `all`, `relay` or `no-host`, local ICE candidates gathered and sent to peers. `relay`
+    /// needs a TURN server in `ice_servers`.
+    #[clap(long, default_value = "all")]
+    pub ice_transport_policy: IceTransportPolicy,
+
+    /// `dual`, `prefer-ipv4`, `prefer-ipv6`, `ipv4` or `ipv6`, address families of local ICE
+    /// candidates sent to peers.
+    #[clap(long, default_value = "dual")]
+    pub ip_family: IpFamily,
+
+    /// Answer offers without a nonce issued by this node, like manual handshakes, refused by
+    /// default.
+    #[clap(long)]
+    pub allow_handshake_without_nonce: bool,
+
+    /// Keep peers in DHT for N ms after their transports fail, waiting for them to migrate.
+    #[clap(long, default_value = "15000")]
+    pub migration_window_ms: u64,
+
+    /// Decompress and verify N received payloads at once on blocking threads, 0 to do it inline.
+    #[clap(long, default_value = "0")]
+    pub verify_workers: usize,
+
+    /// `chord` or `latency` aware choice of next hop.
+    #[clap(long, default_value = "chord")]
+    pub routing: RoutingStrategy,
+
+    /// Prefer next hops tagged `key=value`, like `region=eu`, see `tagPeer`.
+    #[clap(long)]
+    pub prefer_tag: Option<String>,
+
+    /// Persist tags of peers here, they are kept in memory if unset.
+    #[clap(long)]
+    pub tags_path: Option<String>,
+
+    /// Accept this codec for payloads, `none`, `gzip` or `zstd`, in order of preference.
+    #[clap(long = "codec")]
+    pub codecs: Vec<Codec>,
+
+    /// Skip compression of payloads smaller than N bytes.
+    #[clap(long, default_value = "1024")]
+    pub compress_threshold: usize,
+
+    /// Record latest N payloads for debugging, retrieved by `capturedPayloads`.
+    #[clap(long, default_value = "0")]
+    pub capture_size: usize,
+
+    /// Dial peers not connected on sending messages, queuing N messages for each, 0 to disable.
+    #[clap(long, default_value = "0")]
+    pub lazy_dial_queue: usize,
+
+    /// Look up and connect N fingers at the same time on joining a ring, 1 to disable.
+    #[clap(long, default_value = "4")]
+    pub join_parallelism: usize,
+
+    /// Connect every peer reported on finding successors (eager), or only successors and
+    /// fingers, dialing others lazily (structured).
+    #[clap(long, default_value = "eager")]
+    pub topology_policy: TopologyPolicy,
+
+    /// Shed application messages when more than N received payloads wait, 0 to disable.
+    #[clap(long, default_value = "1024")]
+    pub shed_queue: usize,
+
+    /// Shed application messages when handlers take more than N percent of time, 0 to disable.
+    #[clap(long, default_value = "80")]
+    pub shed_cpu_budget: u8,
+
+    /// Tolerate clock skew of peers up to N milliseconds on expiring their payloads, 0 to disable.
+    #[clap(long, default_value_t = DEFAULT_MAX_CLOCK_SKEW_MS)]
+    pub max_clock_skew_ms: u64,
+
+    /// Reject payloads signed more than N milliseconds ago or already received, 0 to disable.
+    #[clap(long, default_value_t = DEFAULT_REPLAY_WINDOW_MS)]
+    pub replay_window_ms: u64,
+
+    /// Keep at most N peers connected, ring neighbours are kept even over it.
+    #[clap(long, default_value_t = DEFAULT_MAX_CONNECTIONS)]
+    pub max_connections: usize,
+
+    /// Check local clock against this SNTP server at startup, like `pool.ntp.org:123`.
+    #[clap(long)]
+    pub ntp_server: Option<String>,
+
+    /// Persist received custom messages here, retrieved by `listMessages`.
+    #[clap(long)]
+    pub history_path: Option<String>,
+
+    /// Prune persisted messages older than N milliseconds, 0 is unlimited.
+    #[clap(long, default_value_t = DEFAULT_MAX_AGE_MS)]
+    pub history_max_age_ms: u64,
+
+    /// Keep at most N persisted messages, the oldest are pruned, 0 is unlimited.
+    #[clap(long, default_value_t = DEFAULT_MAX_RECORDS)]
+    pub history_max_records: usize,
+
+    /// Encrypt persisted messages, with a key derived from `storage-password` or key of node.
+    #[clap(long)]
+    pub encrypt_at_rest: bool,
+
+    /// Password of encryption at rest, keeps messages readable after key of node changes.
+    #[clap(long, env)]
+    pub storage_password: Option<String>,
+
+    /// Write logs to this file, default to `/tmp/rings-node/rings-node.log` when daemonized.
+    #[clap(long)]
+    pub log_file: Option<String>,
+
+    /// Rotate log file when it's larger than this size, in bytes.
+    #[clap(long, default_value = "10485760")]
+    pub log_max_size: u64,
+
+    /// Number of rotated log files to keep.
+    #[clap(long, default_value = "5")]
+    pub log_max_files: usize,
+
+    /// Unix socket for `stop` and `status` commands.
+    #[clap(long, default_value = "/tmp/rings-node.sock")]
+    pub control_socket: String,
 }
 
 #[derive(Args, Debug)]
@@ -119,9 +380,17 @@ struct ShutdownArgs {
     pub pid_file: String,
 }
 
+#[derive(Args, Debug)]
+struct ControlArgs {
+    #[clap(long, default_value = "/tmp/rings-node.sock")]
+    pub control_socket: String,
+}
+
 async fn run_jobs(args: &RunArgs) -> anyhow::Result<()> {
+    if let Some(server) = &args.ntp_server {
+        doctor::warn_clock(server, args.max_clock_skew_ms).await;
+    }
     let key: &SecretKey = &args.eth_key;
-    let dht = Arc::new(Mutex::new(PeerRing::new(key.address().into())));
 
     let (auth, s_key) = SessionManager::gen_unsign_info(
         key.address(),
@@ -132,6 +401,15 @@ async fn run_jobs(args: &RunArgs) -> anyhow::Result<()> {
     let session = SessionManager::new(&sig, &auth, &s_key);
 
     let mut ice_servers = args.ice_server.clone();
+    let turn_credentials = match (&args.turn_secret, args.without_turn) {
+        (Some(secret), false) => Some(Arc::new(TurnCredentials::new(
+            secret,
+            Duration::from_secs(args.turn_credential_ttl),
+            args.turn_credential_quota,
+            vec![format!("turn://{}:{}", args.public_ip, args.turn_port)],
+        ))),
+        _ => None,
+    };
     let turn_server = if !args.without_turn {
         let mut turn_url = url::Url::from_str("turn://0.0.0.0:3567").unwrap();
         turn_url.set_port(Some(args.turn_port)).unwrap();
@@ -147,6 +425,7 @@ async fn run_jobs(args: &RunArgs) -> anyhow::Result<()> {
                 args.turn_username.as_str(),
                 args.turn_password.as_str(),
                 args.turn_realm.as_str(),
+                turn_credentials.clone(),
             )
             .await?,
         )
@@ -155,42 +434,141 @@ async fn run_jobs(args: &RunArgs) -> anyhow::Result<()> {
     };
 
     let ice_servers = ice_servers.join(";");
-    let swarm = Arc::new(Swarm::new(&ice_servers, key.address(), session));
+    let tags = Arc::new(match &args.tags_path {
+        Some(path) => PeerTags::open(path)?,
+        None => PeerTags::new(),
+    });
+    let codecs = match args.codecs.is_empty() {
+        true => Codec::supported(),
+        false => args.codecs.clone(),
+    };
+    let swarm = Arc::new(
+        Swarm::builder(key.address(), session)
+            .with_ice_servers(&ice_servers)
+            .with_network_id(args.network_id.as_str())
+            .with_relay(args.relay)
+            .with_version_policy(args.version_policy)
+            .with_ice_transport_policy(args.ice_transport_policy)
+            .with_ip_family(args.ip_family)
+            .with_handshake_nonce_required(!args.allow_handshake_without_nonce)
+            .with_migration_window(args.migration_window_ms)
+            .with_verify_workers(args.verify_workers)
+            .with_compression(&codecs, args.compress_threshold)
+            .with_max_connections(args.max_connections)
+            .with_encryption_key(derive_encryption_key(key)?)
+            .with_tags(tags.clone())
+            .with_capture(args.capture_size)
+            .with_max_clock_skew(args.max_clock_skew_ms)
+            .with_replay_window(args.replay_window_ms)
+            .build()?,
+    );
+    let mut routing = args.routing.build(swarm.route_stats());
+    if let Some(tag) = &args.prefer_tag {
+        let (key, value) = tag
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("prefer-tag should be `key=value`: {}", tag))?;
+        routing = Arc::new(TagPreferencePolicy::new(tags, key, value, routing));
+    }
+    let dht = Arc::new(Mutex::new(
+        PeerRing::new(key.address().into())
+            .with_routing_policy(routing)
+            .with_route_cache(swarm.route_cache()),
+    ));
 
     // let listen_event = MessageHandler::new(dht.clone(), swarm.clone());
     let message_callback = MessageCallback {};
-    let listen_event = Arc::new(MessageHandler::new_with_callback(
-        dht.clone(),
-        swarm.clone(),
-        Box::new(message_callback),
-    ));
-    let stabilization = Arc::new(Stabilization::new(
-        dht.clone(),
-        swarm.clone(),
-        args.stabilize_timeout,
-    ));
+    let mut listen_event =
+        MessageHandler::new_with_callback(dht.clone(), swarm.clone(), Box::new(message_callback))
+            .with_lazy_dial(args.lazy_dial_queue)
+            .with_join_parallelism(args.join_parallelism)
+            .with_topology_policy(args.topology_policy)
+            .with_overload_guard(args.shed_queue, args.shed_cpu_budget)
+            .with_echo(args.echo);
+    if let Some(path) = &args.history_path {
+        let mut history = MessageHistory::open(path)?;
+        if args.encrypt_at_rest {
+            history = history.with_cipher(match &args.storage_password {
+                Some(p) => StorageCipher::from_password(p, &load_salt(path)?),
+                None => StorageCipher::from_secret_key(key),
+            });
+        }
+        let history = history.with_retention(args.history_max_age_ms, args.history_max_records)?;
+        listen_event = listen_event.with_history(Arc::new(history));
+    }
+    let listen_event = Arc::new(listen_event);
+    let stabilization = Arc::new(
+        Stabilization::new(dht.clone(), swarm.clone()).with_interval(
+            args.stabilize_timeout,
+            args.stabilize_max_interval
+                .unwrap_or(args.stabilize_timeout),
+        ),
+    );
     let http_addr = args.http_addr.clone();
+    let rpc_socket = args.rpc_socket.clone();
     let listen_event_1 = listen_event.clone();
-    let listen_event_2 = listen_event.clone();
-    let stabilization_1 = stabilization.clone();
-    let stabilization_2 = stabilization.clone();
-    let j = tokio::spawn(futures::future::join3(
-        async {
-            listen_event_1.listen().await;
-            AnyhowResult::Ok(())
-        },
-        async {
-            run_service(http_addr, swarm, listen_event_2, stabilization_1).await?;
-            AnyhowResult::Ok(())
-        },
-        async {
-            stabilization_2.wait().await;
-            AnyhowResult::Ok(())
+    let processor = Processor::from((swarm.clone(), listen_event.clone(), stabilization.clone()))
+        .with_admin_token(args.admin_token.clone())
+        .with_share_dir(args.share_dir.clone());
+    let control_swarm = swarm.clone();
+    let routes = match turn_credentials {
+        Some(c) => turn_credential_router(c, args.admin_token.clone()),
+        None => Router::new(),
+    };
+    let mdns = match args.mdns {
+        true => {
+            let processor = processor.clone();
+            let http_addr = args.http_addr.clone();
+            Some(tokio::spawn(async move {
+                if let Err(e) = run_mdns(http_addr, processor).await {
+                    tracing::error!("mDNS discovery stopped: {}", e);
+                }
+            }))
+        }
+        false => None,
+    };
+    let bootstrap = tokio::spawn(run_bootstrap(
+        BootstrapConfig {
+            mode: args.bootstrap,
+            seeds: args.seeds.clone(),
+            dns_name: args.bootstrap_dns_name.clone(),
+            dns_server: args.bootstrap_dns_server.clone(),
+            refresh_secs: args.bootstrap_refresh_secs.max(1),
+            cache_path: args.bootstrap_cache_path.clone(),
         },
+        processor.clone(),
+    ));
+    let j = tokio::spawn(async move { listen_event_1.listen().await });
+    let stabilization_task = stabilization.spawn();
+    // service stops by itself after the swarm is drained
+    let mut service = tokio::spawn(run_service_with_routes(
+        http_addr, rpc_socket, processor, None, routes,
+    ));
+    let stop = Arc::new(Notify::new());
+    let control = tokio::spawn(run_control_socket(
+        args.control_socket.clone(),
+        args.http_addr.clone(),
+        control_swarm,
+        stop.clone(),
     ));
-    signal::ctrl_c().await.expect("failed to listen for event");
+    tokio::select! {
+        r = signal::ctrl_c() => r.expect("failed to listen for event"),
+        _ = stop.notified() => tracing::info!("Stop requested from control socket"),
+        r = &mut service => match r {
+            Ok(Ok(())) => tracing::info!("Service stopped after drain"),
+            Ok(Err(e)) => tracing::error!("Service failed: {}", e),
+            Err(e) => tracing::error!("Service panicked: {}", e),
+        },
+    }
     println!("\nClosing connection now...");
     j.abort();
+    bootstrap.abort();
+    stabilization_task.stop();
+    service.abort();
+    control.abort();
+    if let Some(m) = mdns {
+        m.abort();
+    }
+    let _ = fs::remove_file(args.control_socket.as_str());
     if let Some(s) = turn_server {
         if let Err(e) = s.close().await {
             println!("close turn_server failed, {}", e);
@@ -215,19 +593,47 @@ impl message::MessageCallback for MessageCallback {
     ) {
         if let Ok(msg) = handler.decrypt_msg(msg) {
             if let Ok(msg) = str::from_utf8(&msg.0) {
-                log::info!("[MESSAGE] custom_message: {:?}", msg);
+                tracing::info!("[MESSAGE] custom_message: {:?}", msg);
             } else {
-                log::info!("[MESSAGE] custom_message: {:?}", msg);
+                tracing::info!("[MESSAGE] custom_message: {:?}", msg);
             }
         } else {
-            log::info!("[MESSAGE] custom_message: {:?}", msg);
+            tracing::info!("[MESSAGE] custom_message: {:?}", msg);
         }
     }
     async fn builtin_message(&self, _handler: &MessageHandler, _ctx: &MessagePayload<Message>) {}
 }
 
-fn run_daemon(args: &RunArgs) -> AnyhowResult<()> {
+/// Return pid in `pid_file` if that process is still alive.
+fn running_pid(pid_file: &str) -> Option<i32> {
+    let pid: i32 = fs::read_to_string(pid_file).ok()?.trim().parse().ok()?;
+    // signal 0 only checks existence of process
+    if unsafe { kill(pid, 0) } == 0 {
+        Some(pid)
+    } else {
+        None
+    }
+}
+
+fn init_logger(cli: &Cli, args: &RunArgs) -> AnyhowResult<()> {
+    let log_file = match (&args.log_file, args.daemonize) {
+        (Some(f), _) => Some(f.to_owned()),
+        (None, true) => Some("/tmp/rings-node/rings-node.log".to_owned()),
+        (None, false) => None,
+    };
+    let writer = log_file
+        .map(|f| RotatingFileWriter::new(f, args.log_max_size, args.log_max_files))
+        .transpose()?;
+    cli.init_tracing(writer)
+}
+
+fn run_daemon(cli: &Cli, args: &RunArgs) -> AnyhowResult<()> {
     if args.daemonize {
+        if let Some(pid) = running_pid(args.pid_file.as_str()) {
+            anyhow::bail!("daemon is already running, pid: {}", pid);
+        }
+        // remove stale pid file
+        let _ = fs::remove_file(args.pid_file.as_str());
         fs::create_dir_all("/tmp/rings-node")?;
         let stdout = File::create("/tmp/rings-node/info.log")?;
         let stderr = File::create("/tmp/rings-node/err.log")?;
@@ -244,37 +650,64 @@ fn run_daemon(args: &RunArgs) -> AnyhowResult<()> {
             panic!("{}", e);
         }
     }
+    init_logger(cli, args)?;
     let rt = tokio::runtime::Runtime::new().unwrap();
     rt.block_on(async {
         if let Err(e) = run_jobs(args).await {
             panic!("{}", e);
         }
     });
+    if args.daemonize {
+        let _ = fs::remove_file(args.pid_file.as_str());
+    }
     Ok(())
 }
 
 fn shutdown_daemon(args: &ShutdownArgs) -> anyhow::Result<()> {
-    let pid: i32 = fs::read_to_string(args.pid_file.as_str())?.parse()?;
+    let pid: i32 = fs::read_to_string(args.pid_file.as_str())?.trim().parse()?;
     unsafe {
         kill(pid, 9);
     }
+    let _ = fs::remove_file(args.pid_file.as_str());
     println!("Killed: {}", pid);
     Ok(())
 }
 
+fn control_daemon(args: &ControlArgs, req: ControlRequest) -> anyhow::Result<()> {
+    let rt = tokio::runtime::Runtime::new()?;
+    match rt.block_on(send_control_request(args.control_socket.as_str(), req))? {
+        ControlResponse::Status(status) => println!("{}", serde_json::to_string_pretty(&status)?),
+        ControlResponse::Stopping => println!("Stopping"),
+        ControlResponse::Error(e) => anyhow::bail!("{}", e),
+    }
+    Ok(())
+}
+
 fn main() {
     dotenv::dotenv().ok();
     let cli = Cli::parse();
-    Logger::init(cli.log_level.into()).expect("log err");
 
-    match cli.command {
+    match &cli.command {
         Command::Run(args) => {
-            if let Err(e) = run_daemon(&args) {
+            if let Err(e) = run_daemon(&cli, args) {
                 panic!("{}", e);
             }
         }
         Command::Shutdown(args) => {
-            if let Err(e) = shutdown_daemon(&args) {
+            cli.init_tracing(None).expect("log err");
+            if let Err(e) = shutdown_daemon(args) {
+                panic!("{}", e);
+            }
+        }
+        Command::Stop(args) => {
+            cli.init_tracing(None).expect("log err");
+            if let Err(e) = control_daemon(args, ControlRequest::Stop) {
+                panic!("{}", e);
+            }
+        }
+        Command::Status(args) => {
+            cli.init_tracing(None).expect("log err");
+            if let Err(e) = control_daemon(args, ControlRequest::Status) {
                 panic!("{}", e);
             }
         }
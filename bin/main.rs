@@ -1,182 +1,1228 @@
 #![feature(async_closure)]
-use std::sync::Arc;
+
+use std::time::Duration;
 
 use clap::Args;
 use clap::Parser;
 use clap::Subcommand;
-use futures::lock::Mutex;
-use rings_core::dht::PeerRing;
-use rings_core::dht::Stabilization;
-use rings_core::dht::TStabilize;
+#[cfg(feature = "chaos")]
+use rings_core::chaos::FaultConfig;
+use rings_core::dht::routing::RoutingStrategy;
+use rings_core::dht::Did;
 use rings_core::ecc::SecretKey;
-use rings_core::message::MessageHandler;
-use rings_core::session::SessionManager;
-use rings_core::swarm::Swarm;
+use rings_core::history::HistoryFilter;
+use rings_core::known_peers::TofuPolicy;
+use rings_core::message::codec::Codec;
+use rings_core::message::TopologyPolicy;
+use rings_core::rotation::RotationRecord;
+use rings_core::types::ice_transport::IceTransportPolicy;
+use rings_core::types::ice_transport::IpFamily;
 use rings_core::types::message::MessageListener;
+use rings_core::version::VersionPolicy;
 use rings_node::cli::Client;
+use rings_node::config::Config;
+use rings_node::config::MetricsProtocol;
+use rings_node::config::DEFAULT_CONFIG_PATH;
+use rings_node::doctor;
+use rings_node::jsonrpc_client::HttpOptions;
+use rings_node::jsonrpc_client::RetryPolicy;
+use rings_node::logger::init_tracing;
+use rings_node::logger::LogFormat;
 use rings_node::logger::LogLevel;
-use rings_node::logger::Logger;
+use rings_node::node::Node;
+use rings_node::processor::PeerFilter;
+use rings_node::processor::StabilizationControl;
+use rings_node::service::run_dns_stub;
+use rings_node::service::run_mdns;
 use rings_node::service::run_service;
+use rings_node::service::run_socks5_proxy;
+use rings_node::service::run_tunnel;
+
+#[derive(Parser, Debug)]
+#[clap(about, version, author)]
+struct Cli {
+    #[clap(long, short = 'v', default_value_t = LogLevel::Info, arg_enum, env)]
+    log_level: LogLevel,
+
+    #[clap(
+        long,
+        env = "RUST_LOG",
+        help = "per module log levels, eg: info,rings_core::swarm=debug, overrides log-level."
+    )]
+    log_filter: Option<String>,
+
+    #[clap(long, default_value_t = LogFormat::Text, arg_enum, env)]
+    log_format: LogFormat,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+#[clap(rename_all = "kebab-case")]
+enum Command {
+    #[clap(about = "daemon")]
+    Run(Daemon),
+    #[clap(subcommand)]
+    Connect(ConnectCommand),
+    #[clap(subcommand)]
+    Sdp(SdpCommand),
+    #[clap(subcommand)]
+    Peer(PeerCommand),
+    #[clap(subcommand)]
+    Pending(PendingCommand),
+    Send(Send),
+    Info(InfoArgs),
+    Whois(WhoisArgs),
+    Crawl(CrawlArgs),
+    Benchmark(BenchmarkArgs),
+    #[cfg(feature = "chaos")]
+    Chaos(ChaosArgs),
+    Drain(DrainArgs),
+    RotateIdentity(RotateIdentityArgs),
+    Capture(CaptureArgs),
+    History(HistoryArgs),
+    NewSecretKey,
+    Doctor(DoctorArgs),
+    #[clap(subcommand)]
+    Config(ConfigCommand),
+    #[clap(subcommand)]
+    State(StateCommand),
+    #[clap(subcommand)]
+    Presence(PresenceCommand),
+    #[clap(subcommand)]
+    Group(GroupCommand),
+    #[clap(subcommand)]
+    Topic(TopicCommand),
+    #[clap(subcommand)]
+    File(FileCommand),
+    #[clap(subcommand)]
+    Erasure(ErasureCommand),
+    #[clap(subcommand)]
+    Service(ServiceCommand),
+    #[clap(subcommand)]
+    Ens(EnsCommand),
+    #[clap(subcommand)]
+    Stabilization(StabilizationCommand),
+    #[clap(subcommand)]
+    Data(DataCommand),
+}
+
+#[derive(Args, Debug)]
+#[clap(about)]
+struct Daemon {
+    #[clap(
+        long,
+        short = 'c',
+        help = "config file, default to rings.toml if exists.",
+        env = "RINGS_CONFIG"
+    )]
+    pub config: Option<String>,
+
+    #[clap(long, short = 'b')]
+    pub http_addr: Option<String>,
+
+    #[clap(long, help = "also serve jsonrpc on this unix socket.")]
+    pub rpc_socket: Option<String>,
+
+    #[clap(
+        long,
+        env = "RINGS_ADMIN_TOKEN",
+        help = "HTTP callers presenting this bearer token may use admin methods like sendFile."
+    )]
+    pub admin_token: Option<String>,
+
+    #[clap(long, help = "sendFile and fetchFile are confined to this directory.")]
+    pub share_dir: Option<String>,
+
+    #[clap(long, short = 's')]
+    pub ice_servers: Option<String>,
+
+    #[clap(long = "eth", short = 'e')]
+    pub eth_endpoint: Option<String>,
+
+    #[clap(long)]
+    pub network_id: Option<String>,
+
+    #[clap(long, help = "warn or refuse peers without common protocol version.")]
+    pub version_policy: Option<VersionPolicy>,
+
+    #[clap(
+        long,
+        help = "all, relay or no-host, local ICE candidates sent to peers, relay needs a TURN server."
+    )]
+    pub ice_transport_policy: Option<IceTransportPolicy>,
+
+    #[clap(
+        long,
+        help = "dual, prefer-ipv4, prefer-ipv6, ipv4 or ipv6, address families of local ICE candidates."
+    )]
+    pub ip_family: Option<IpFamily>,
+
+    #[clap(
+        long,
+        help = "answer offers without a nonce issued by this node, like manual handshakes, refused by default."
+    )]
+    pub allow_handshake_without_nonce: bool,
+
+    #[clap(
+        long,
+        help = "keep peers in DHT for N ms after their transports fail, waiting for them to migrate."
+    )]
+    pub migration_window_ms: Option<u64>,
+
+    #[clap(
+        long,
+        help = "decompress and verify N received payloads at once on blocking threads, 0 to do it inline."
+    )]
+    pub verify_workers: Option<usize>,
+
+    #[clap(long, help = "chord or latency aware choice of next hop.")]
+    pub routing: Option<RoutingStrategy>,
+
+    #[clap(long, help = "prefer next hops tagged key=value.")]
+    pub prefer_tag: Option<String>,
+
+    #[clap(long = "key", short = 'k')]
+    pub eth_key: Option<String>,
+
+    #[clap(long)]
+    pub keystore: Option<String>,
+
+    #[clap(long)]
+    pub storage_path: Option<String>,
+
+    #[clap(
+        long,
+        help = "encrypt persisted vnodes and messages, with key of node by default."
+    )]
+    pub encrypt_at_rest: bool,
+
+    #[clap(
+        long,
+        help = "derive key of encryption at rest from this password instead."
+    )]
+    pub storage_password: Option<String>,
+
+    #[clap(long)]
+    pub stabilize_timeout: Option<usize>,
+
+    #[clap(
+        long,
+        help = "adapt interval of stabilization to churn, from stabilize-timeout up to N seconds."
+    )]
+    pub stabilize_max_interval: Option<usize>,
+
+    #[clap(
+        long = "codec",
+        help = "accept this codec for payloads, none, gzip or zstd, in order of preference."
+    )]
+    pub codecs: Vec<Codec>,
+
+    #[clap(long, help = "skip compression of payloads smaller than N bytes.")]
+    pub compress_threshold: Option<usize>,
+
+    #[clap(long, help = "record latest N payloads for debugging.")]
+    pub capture_size: Option<usize>,
+
+    #[clap(
+        long,
+        help = "dial peers not connected on sending, queuing N messages for each."
+    )]
+    pub lazy_dial_queue: Option<usize>,
+
+    #[clap(
+        long,
+        help = "look up and connect N fingers at the same time on joining."
+    )]
+    pub join_parallelism: Option<usize>,
+
+    #[clap(
+        long,
+        help = "eager or structured, which connects only successors and fingers found at once."
+    )]
+    pub topology_policy: Option<TopologyPolicy>,
+
+    #[clap(
+        long,
+        help = "shed application messages when more than N received payloads wait, 0 to disable."
+    )]
+    pub shed_queue: Option<usize>,
+
+    #[clap(
+        long,
+        help = "shed application messages when handlers take more than N percent of time."
+    )]
+    pub shed_cpu_budget: Option<u8>,
+
+    #[clap(
+        long,
+        help = "tolerate clock skew of peers up to N milliseconds on expiring payloads, 0 to disable."
+    )]
+    pub max_clock_skew_ms: Option<u64>,
+
+    #[clap(
+        long,
+        help = "reject payloads signed more than N milliseconds ago or already received, 0 to disable."
+    )]
+    pub replay_window_ms: Option<u64>,
+
+    #[clap(
+        long,
+        help = "persist payloads in replay window here, to reject them after restart."
+    )]
+    pub replay_path: Option<String>,
+
+    #[clap(
+        long,
+        help = "keep at most N peers connected, ring neighbours are kept even over it."
+    )]
+    pub max_connections: Option<usize>,
+
+    #[clap(
+        long,
+        help = "relay at most N bytes per minute for each peer which can't connect others, 0 is unlimited."
+    )]
+    pub relay_budget: Option<usize>,
+
+    #[clap(
+        long,
+        help = "refuse relaying for a peer more than N bytes in an hour, 0 is unlimited."
+    )]
+    pub relay_quota_bytes: Option<u64>,
+
+    #[clap(
+        long,
+        help = "refuse relaying for a peer more than N messages in an hour, 0 is unlimited."
+    )]
+    pub relay_quota_messages: Option<u64>,
+
+    #[clap(
+        long,
+        help = "delay messages of peers near their relay quota by N milliseconds."
+    )]
+    pub relay_throttle_ms: Option<u64>,
+
+    #[clap(long, help = "check local clock against this SNTP server at startup.")]
+    pub ntp_server: Option<String>,
+
+    #[clap(long, help = "persist received messages here, for listMessages.")]
+    pub history_path: Option<String>,
+
+    #[clap(
+        long,
+        help = "prune persisted messages older than N milliseconds, 0 is unlimited."
+    )]
+    pub history_max_age_ms: Option<u64>,
+
+    #[clap(long, help = "keep at most N persisted messages, 0 is unlimited.")]
+    pub history_max_records: Option<usize>,
+
+    #[clap(long, help = "persist tags of peers here.")]
+    pub tags_path: Option<String>,
+
+    #[clap(long, help = "persist session keys of peers seen here.")]
+    pub known_peers_path: Option<String>,
+
+    #[clap(long, help = "persist keys of groups this node is a member of here.")]
+    pub group_keys_path: Option<String>,
+
+    #[clap(
+        long,
+        help = "warn or refuse peers whose session key differs from the one of first contact."
+    )]
+    pub tofu_policy: Option<TofuPolicy>,
+
+    #[clap(long, help = "disable stabilization of chord ring.")]
+    pub without_stabilization: bool,
+
+    #[clap(long, help = "advertise this node as relay capable.")]
+    pub relay: bool,
+
+    #[clap(
+        long,
+        help = "echo custom messages back to their senders, for benchmark."
+    )]
+    pub echo: bool,
+
+    #[clap(
+        long,
+        help = "advertise this node and connect to nodes of the same network on LAN by mDNS."
+    )]
+    pub mdns: bool,
+
+    #[clap(
+        long,
+        help = "run as seed, answer offers of anyone, close idle transports, store no app data."
+    )]
+    pub seed: bool,
+
+    #[clap(
+        long,
+        help = "run a SOCKS5 proxy on this address, tunneling through socks5-exit."
+    )]
+    pub socks5_addr: Option<String>,
+
+    #[clap(
+        long,
+        help = "DID of exit peer of SOCKS5 proxy, should be connected directly."
+    )]
+    pub socks5_exit: Option<String>,
+
+    #[clap(
+        long,
+        help = "username:password clients of SOCKS5 proxy authenticate with, required by socks5-addr."
+    )]
+    pub socks5_auth: Option<String>,
+
+    #[clap(
+        long = "exit-peer",
+        help = "allow peer of this DID to use the node as SOCKS5 exit."
+    )]
+    pub exit_peers: Vec<String>,
+
+    #[clap(
+        long,
+        help = "let exit peers reach private, loopback and link-local addresses, refused by default."
+    )]
+    pub exit_allow_private: bool,
+
+    #[clap(long, help = "expose this local HTTP service to gateway of peers.")]
+    pub http_service: Option<String>,
+
+    #[clap(long, help = "run a DNS stub resolving <name>.rings to the gateway.")]
+    pub dns_addr: Option<String>,
+
+    #[clap(long, help = "ethereum rpc endpoint to resolve ENS names as DIDs.")]
+    pub ens_endpoint: Option<String>,
+
+    #[clap(
+        long,
+        help = "push metrics to this statsd host:port, or OTLP/HTTP url with --metrics-protocol otlp."
+    )]
+    pub metrics_endpoint: Option<String>,
+
+    #[clap(long, help = "statsd or otlp, protocol of pushing metrics.")]
+    pub metrics_protocol: Option<MetricsProtocol>,
+
+    #[clap(
+        long = "public-endpoint",
+        help = "announce this public endpoint in manifest of node."
+    )]
+    pub public_endpoints: Vec<String>,
+}
+
+impl Daemon {
+    /// Load config file and environment variables, then override with command line flags.
+    fn load_config(&self) -> rings_node::error::Result<Config> {
+        let mut config = Config::load(self.config.as_deref())?;
+        if let Some(v) = &self.http_addr {
+            config.http_addr = v.to_owned();
+        }
+        if let Some(v) = &self.rpc_socket {
+            config.rpc_socket = Some(v.to_owned());
+        }
+        if let Some(v) = &self.admin_token {
+            config.admin_token = Some(v.to_owned());
+        }
+        if let Some(v) = &self.share_dir {
+            config.share_dir = Some(v.to_owned());
+        }
+        if let Some(v) = &self.ice_servers {
+            config.ice_servers = v.to_owned();
+        }
+        if let Some(v) = &self.eth_endpoint {
+            config.eth_endpoint = v.to_owned();
+        }
+        if let Some(v) = &self.network_id {
+            config.network_id = v.to_owned();
+        }
+        if let Some(v) = self.version_policy {
+            config.version_policy = v;
+        }
+        if let Some(v) = self.ice_transport_policy {
+            config.ice_transport_policy = v;
+        }
+        if let Some(v) = self.ip_family {
+            config.ip_family = v;
+        }
+        if self.allow_handshake_without_nonce {
+            config.require_handshake_nonce = false;
+        }
+        if let Some(v) = self.migration_window_ms {
+            config.migration_window_ms = v;
+        }
+        if let Some(v) = self.verify_workers {
+            config.verify_workers = v;
+        }
+        if let Some(v) = self.routing {
+            config.routing = v;
+        }
+        if let Some(v) = &self.prefer_tag {
+            config.prefer_tag = Some(v.to_owned());
+        }
+        if let Some(v) = &self.eth_key {
+            config.eth_key = Some(v.to_owned());
+            config.keystore = None;
+        }
+        if let Some(v) = &self.keystore {
+            config.keystore = Some(v.to_owned());
+            config.eth_key = None;
+        }
+        if let Some(v) = &self.storage_path {
+            config.storage_path = Some(v.to_owned());
+        }
+        if self.encrypt_at_rest {
+            config.encrypt_at_rest = true;
+        }
+        if let Some(v) = &self.storage_password {
+            config.storage_password = Some(v.to_owned());
+        }
+        if let Some(v) = self.stabilize_timeout {
+            config.stabilize_timeout = v;
+        }
+        if let Some(v) = self.stabilize_max_interval {
+            config.stabilize_max_interval = Some(v);
+        }
+        if !self.codecs.is_empty() {
+            config.codecs = self.codecs.clone();
+        }
+        if let Some(v) = self.compress_threshold {
+            config.compress_threshold = v;
+        }
+        if let Some(v) = self.capture_size {
+            config.capture_size = v;
+        }
+        if let Some(v) = self.lazy_dial_queue {
+            config.lazy_dial_queue = v;
+        }
+        if let Some(v) = self.join_parallelism {
+            config.join_parallelism = v;
+        }
+        if let Some(v) = self.topology_policy {
+            config.topology_policy = v;
+        }
+        if let Some(v) = self.shed_queue {
+            config.shed_queue = v;
+        }
+        if let Some(v) = self.shed_cpu_budget {
+            config.shed_cpu_budget = v;
+        }
+        if let Some(v) = self.max_clock_skew_ms {
+            config.max_clock_skew_ms = v;
+        }
+        if let Some(v) = self.replay_window_ms {
+            config.replay_window_ms = v;
+        }
+        if let Some(v) = &self.replay_path {
+            config.replay_path = Some(v.to_owned());
+        }
+        if let Some(v) = self.max_connections {
+            config.max_connections = v;
+        }
+        if let Some(v) = self.relay_budget {
+            config.relay_budget = v;
+        }
+        if let Some(v) = self.relay_quota_bytes {
+            config.relay_quota_bytes = v;
+        }
+        if let Some(v) = self.relay_quota_messages {
+            config.relay_quota_messages = v;
+        }
+        if let Some(v) = self.relay_throttle_ms {
+            config.relay_throttle_ms = v;
+        }
+        if let Some(v) = &self.ntp_server {
+            config.ntp_server = Some(v.to_owned());
+        }
+        if let Some(v) = &self.history_path {
+            config.history_path = Some(v.to_owned());
+        }
+        if let Some(v) = self.history_max_age_ms {
+            config.history_max_age_ms = v;
+        }
+        if let Some(v) = self.history_max_records {
+            config.history_max_records = v;
+        }
+        if let Some(v) = &self.tags_path {
+            config.tags_path = Some(v.to_owned());
+        }
+        if let Some(v) = &self.known_peers_path {
+            config.known_peers_path = Some(v.to_owned());
+        }
+        if let Some(v) = &self.group_keys_path {
+            config.group_keys_path = Some(v.to_owned());
+        }
+        if let Some(v) = self.tofu_policy {
+            config.tofu_policy = v;
+        }
+        if self.without_stabilization {
+            config.features.stabilization = false;
+        }
+        if self.relay {
+            config.features.relay = true;
+        }
+        if self.echo {
+            config.features.echo = true;
+        }
+        if self.mdns {
+            config.features.mdns = true;
+        }
+        if self.seed {
+            config.seed.enabled = true;
+        }
+        if let Some(v) = &self.socks5_addr {
+            config.socks5_addr = Some(v.to_owned());
+        }
+        if let Some(v) = &self.socks5_exit {
+            config.socks5_exit = Some(v.to_owned());
+        }
+        if let Some(v) = &self.socks5_auth {
+            config.socks5_auth = Some(v.to_owned());
+        }
+        if !self.exit_peers.is_empty() {
+            config.exit_peers = self.exit_peers.clone();
+        }
+        if self.exit_allow_private {
+            config.exit_allow_private = true;
+        }
+        if let Some(v) = &self.http_service {
+            config.http_service = Some(v.to_owned());
+        }
+        if let Some(v) = &self.dns_addr {
+            config.dns_addr = Some(v.to_owned());
+        }
+        if let Some(v) = &self.ens_endpoint {
+            config.ens_endpoint = Some(v.to_owned());
+        }
+        if !self.public_endpoints.is_empty() {
+            config.public_endpoints = self.public_endpoints.clone();
+        }
+        if let Some(v) = &self.metrics_endpoint {
+            config.metrics.endpoint = Some(v.to_owned());
+        }
+        if let Some(v) = self.metrics_protocol {
+            config.metrics.protocol = v;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+#[derive(Subcommand, Debug)]
+#[clap(rename_all = "kebab-case")]
+enum ConfigCommand {
+    Init(ConfigInitArgs),
+    Show(ConfigShowArgs),
+}
+
+#[derive(Args, Debug)]
+struct ConfigInitArgs {
+    #[clap(default_value = DEFAULT_CONFIG_PATH)]
+    path: String,
+
+    #[clap(long, short = 'f', help = "overwrite existing file.")]
+    force: bool,
+}
+
+#[derive(Args, Debug)]
+struct ConfigShowArgs {
+    #[clap(flatten)]
+    daemon: Daemon,
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "check environment of node before running it")]
+struct DoctorArgs {
+    #[clap(flatten)]
+    daemon: Daemon,
+
+    #[clap(long, help = "also connect two nodes in process through ice servers.")]
+    loopback: bool,
+}
+
+#[derive(Args, Debug)]
+struct ClientArgs {
+    #[clap(
+        long,
+        short = 'u',
+        default_value = "http://127.0.0.1:50000",
+        help = "rings-node endpoint url."
+    )]
+    endpoint_url: String,
+
+    #[clap(long, help = "Fail calls not finished in this many milliseconds.")]
+    timeout_ms: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Fail calls not connected to node in this many milliseconds."
+    )]
+    connect_timeout_ms: Option<u64>,
+
+    #[clap(
+        long,
+        default_value = "2",
+        help = "Retry failed calls this many times, calls with side effects are retried only if node is not reached."
+    )]
+    retries: usize,
+
+    #[clap(
+        long,
+        env = "RINGS_ADMIN_TOKEN",
+        help = "Token of node operator, required by methods like sendFile."
+    )]
+    admin_token: Option<String>,
+}
+
+impl ClientArgs {
+    async fn new_client(&self) -> anyhow::Result<Client> {
+        let mut options = HttpOptions::default();
+        if let Some(ms) = self.connect_timeout_ms {
+            options.connect_timeout = Duration::from_millis(ms);
+        }
+        let mut client = Client::new_with_options(self.endpoint_url.as_str(), &options)
+            .await?
+            .with_retry(RetryPolicy {
+                max_retries: self.retries,
+                ..Default::default()
+            });
+        if let Some(ms) = self.timeout_ms {
+            client = client.with_timeout(Duration::from_millis(ms));
+        }
+        if let Some(token) = &self.admin_token {
+            client = client.with_admin_token(token);
+        }
+        Ok(client)
+    }
+}
+
+#[derive(Subcommand, Debug)]
+#[clap(rename_all = "kebab-case")]
+enum ConnectCommand {
+    #[clap()]
+    Node(ConnectUrlArgs),
+    #[clap()]
+    Address(ConnectWithAddressArgs),
+}
+
+#[derive(Args, Debug)]
+#[clap(about)]
+struct ConnectUrlArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    #[clap()]
+    node_url: String,
+}
+
+#[derive(Args, Debug)]
+#[clap(about)]
+struct ConnectWithAddressArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    #[clap()]
+    address: String,
+}
+
+#[derive(Subcommand, Debug)]
+#[clap(rename_all = "kebab-case")]
+enum SdpCommand {
+    #[clap()]
+    Offer(SdpOffer),
+    #[clap(about)]
+    Answer(SdpAnswer),
+    #[clap(about)]
+    AcceptAnswer(SdpAcceptAnswer),
+}
+
+#[derive(Args, Debug)]
+#[clap(about)]
+struct SdpOffer {
+    #[clap(long = "key", short = 'k', env)]
+    pub eth_key: SecretKey,
+    #[clap(
+        long,
+        short = 's',
+        default_value = "stun://stun.l.google.com:19302",
+        env
+    )]
+    pub ice_server: String,
+    #[clap(flatten)]
+    client_args: ClientArgs,
+}
+
+#[derive(Args, Debug)]
+struct SdpAnswer {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+    ice: String,
+}
+
+#[derive(Args, Debug)]
+struct SdpAcceptAnswer {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    #[clap(help = "transport_id of pending transport.")]
+    transport_id: String,
+
+    #[clap(help = "ice from remote.")]
+    ice: String,
+}
+
+#[derive(Subcommand, Debug)]
+#[clap(rename_all = "kebab-case")]
+enum PeerCommand {
+    List(PeerListArgs),
+    Disconnect(PeerDisconnect),
+    Tag(PeerTagArgs),
+    Known(PeerKnownArgs),
+}
+
+#[derive(Args, Debug)]
+struct PeerListArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    #[clap(long, help = "only peers connected or not.")]
+    connected: Option<bool>,
+
+    #[clap(long, help = "only peers with DID starts with this hex prefix.")]
+    did_prefix: Option<String>,
+
+    #[clap(long, help = "only peers tagged `key=value`, can be repeated.")]
+    tag: Vec<String>,
+
+    #[clap(long)]
+    limit: Option<usize>,
+
+    #[clap(long, help = "next cursor of previous page.")]
+    cursor: Option<String>,
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "show version and network of node")]
+struct InfoArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "show verified manifest of a node")]
+struct WhoisArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    did: String,
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "walk the ring, show liveness of nodes and estimated size of network")]
+struct CrawlArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
 
-#[derive(Parser, Debug)]
-#[clap(about, version, author)]
-struct Cli {
-    #[clap(long, short = 'v', default_value_t = LogLevel::Info, arg_enum, env)]
-    log_level: LogLevel,
+    #[clap(long, help = "nodes visited at most.")]
+    max_nodes: Option<usize>,
 
-    #[clap(subcommand)]
-    command: Command,
+    #[clap(long, help = "fetch manifests of visited nodes.")]
+    with_manifests: bool,
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "measure throughput and latency of echoes of a peer running with --echo")]
+struct BenchmarkArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    did: String,
+
+    #[clap(long, help = "bytes of each message.")]
+    size: Option<usize>,
+
+    #[clap(long, help = "messages sent.")]
+    count: Option<usize>,
+
+    #[clap(long, help = "messages in flight at the same time.")]
+    concurrency: Option<usize>,
+}
+
+#[cfg(feature = "chaos")]
+#[derive(Args, Debug)]
+#[clap(about = "inject faults to outbound payloads of node, show them if no fault is set")]
+struct ChaosArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    #[clap(long, help = "ratio of payloads dropped, unset faults are disabled.")]
+    drop: Option<f64>,
+
+    #[clap(long, help = "delay of every payload, in milliseconds.")]
+    delay_ms: Option<u64>,
+
+    #[clap(long, help = "ratio of payloads sent with corrupted relay.")]
+    corrupt: Option<f64>,
+
+    #[clap(long, help = "ratio of payloads which kill their transports.")]
+    kill: Option<f64>,
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "leave the ring gracefully, then stop the node")]
+struct DrainArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "rotate identity of node to a new key, then restart the node with it")]
+struct RotateIdentityArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    #[clap(long = "key", short = 'k', env, help = "current key of node.")]
+    pub eth_key: SecretKey,
+
+    #[clap(long, env, help = "new key of node.")]
+    pub new_eth_key: SecretKey,
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "show payloads recorded by packet capture")]
+struct CaptureArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    #[clap(long, help = "clear records after showing them.")]
+    clear: bool,
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "list received messages, needs history enabled on node")]
+struct HistoryArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    #[clap(long, help = "only messages from this address.")]
+    sender: Option<String>,
+
+    #[clap(long)]
+    tx_id: Option<String>,
+
+    #[clap(long, help = "received at or after, in milliseconds since epoch.")]
+    since: Option<u128>,
+
+    #[clap(long, help = "received before, in milliseconds since epoch.")]
+    until: Option<u128>,
+
+    #[clap(long)]
+    limit: Option<usize>,
+
+    #[clap(long, help = "next cursor of previous page.")]
+    cursor: Option<String>,
 }
 
 #[derive(Subcommand, Debug)]
 #[clap(rename_all = "kebab-case")]
-enum Command {
-    #[clap(about = "daemon")]
-    Run(Daemon),
-    #[clap(subcommand)]
-    Connect(ConnectCommand),
-    #[clap(subcommand)]
-    Sdp(SdpCommand),
-    #[clap(subcommand)]
-    Peer(PeerCommand),
-    #[clap(subcommand)]
-    Pending(PendingCommand),
-    Send(Send),
-    NewSecretKey,
+enum StateCommand {
+    Export(StateExportArgs),
+    Import(StateImportArgs),
 }
 
 #[derive(Args, Debug)]
-#[clap(about)]
-struct Daemon {
-    #[clap(long, short = 'b', default_value = "127.0.0.1:50000", env)]
-    pub http_addr: String,
+#[clap(about = "export DHT and peers of node as a JSON snapshot")]
+struct StateExportArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
 
-    #[clap(
-        long,
-        short = 's',
-        default_value = "stun://stun.l.google.com:19302",
-        env
-    )]
-    pub ice_servers: String,
+    #[clap(long, short = 'o', help = "write snapshot to file instead of stdout.")]
+    output: Option<String>,
+}
 
-    #[clap(
-        long = "eth",
-        short = 'e',
-        default_value = "http://127.0.0.1:8545",
-        env
-    )]
-    pub eth_endpoint: String,
+#[derive(Args, Debug)]
+#[clap(about = "load DHT of a snapshot into node")]
+struct StateImportArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
 
-    #[clap(long = "key", short = 'k', env)]
-    pub eth_key: SecretKey,
+    path: String,
+}
 
-    #[clap(long, default_value = "20")]
-    pub stabilize_timeout: usize,
+#[derive(Subcommand, Debug)]
+#[clap(rename_all = "kebab-case")]
+enum PresenceCommand {
+    #[clap(about = "check if a DID is online, and via which node")]
+    Query(PresenceArgs),
+    #[clap(about = "track presence of a DID, changes are logged by node")]
+    Track(PresenceArgs),
+    #[clap(about = "stop tracking presence of a DID")]
+    Untrack(PresenceArgs),
 }
 
 #[derive(Args, Debug)]
-struct ClientArgs {
+struct PresenceArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    did: String,
+}
+
+#[derive(Subcommand, Debug)]
+#[clap(rename_all = "kebab-case")]
+enum GroupCommand {
+    Create(GroupCreateArgs),
+    Send(GroupSendArgs),
+    Fetch(GroupFetchArgs),
+    RotateKey(GroupRotateKeyArgs),
+    FetchKey(GroupFetchKeyArgs),
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "create a group administrated by node, or replace its members")]
+struct GroupCreateArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    name: String,
+
+    #[clap(help = "addresses of members.")]
+    members: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "send message to members of a group")]
+struct GroupSendArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    name: String,
+
+    text: String,
+
     #[clap(
         long,
-        short = 'u',
-        default_value = "http://127.0.0.1:50000",
-        help = "rings-node endpoint url."
+        help = "keep message in inboxes of offline members for N seconds, default to 7 days."
     )]
-    endpoint_url: String,
+    offline_ttl: Option<u64>,
 }
 
-impl ClientArgs {
-    async fn new_client(&self) -> anyhow::Result<Client> {
-        Client::new(self.endpoint_url.as_str()).await
-    }
+#[derive(Args, Debug)]
+#[clap(about = "show members of a group")]
+struct GroupFetchArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    name: String,
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "establish a new key of a group administrated by node, shared with its members")]
+struct GroupRotateKeyArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    name: String,
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "fetch key of a group shared with node, group messages are sealed by it since then")]
+struct GroupFetchKeyArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    name: String,
 }
 
 #[derive(Subcommand, Debug)]
 #[clap(rename_all = "kebab-case")]
-enum ConnectCommand {
-    #[clap()]
-    Node(ConnectUrlArgs),
-    #[clap()]
-    Address(ConnectWithAddressArgs),
+enum TopicCommand {
+    Publish(TopicPublishArgs),
+    Fetch(TopicFetchArgs),
 }
 
 #[derive(Args, Debug)]
-#[clap(about)]
-struct ConnectUrlArgs {
+#[clap(about = "append a record to a topic, signed by node")]
+struct TopicPublishArgs {
     #[clap(flatten)]
     client_args: ClientArgs,
 
-    #[clap()]
-    node_url: String,
+    name: String,
+
+    data: String,
 }
 
 #[derive(Args, Debug)]
-#[clap(about)]
-struct ConnectWithAddressArgs {
+#[clap(about = "show history of a topic, with forks of publishers")]
+struct TopicFetchArgs {
     #[clap(flatten)]
     client_args: ClientArgs,
 
-    #[clap()]
-    address: String,
+    name: String,
 }
 
 #[derive(Subcommand, Debug)]
 #[clap(rename_all = "kebab-case")]
-enum SdpCommand {
-    #[clap()]
-    Offer(SdpOffer),
-    #[clap(about)]
-    Answer(SdpAnswer),
-    #[clap(about)]
-    AcceptAnswer(SdpAcceptAnswer),
+enum FileCommand {
+    Send(FileSendArgs),
+    Fetch(FileFetchArgs),
 }
 
 #[derive(Args, Debug)]
-#[clap(about)]
-struct SdpOffer {
-    #[clap(long = "key", short = 'k', env)]
-    pub eth_key: SecretKey,
+#[clap(about = "store a file of node on DHT, prints id to fetch it")]
+struct FileSendArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    #[clap(help = "path of file in share directory of node.")]
+    path: String,
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "fetch a file from DHT to node, run again to resume")]
+struct FileFetchArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    id: String,
+
     #[clap(
         long,
-        short = 's',
-        default_value = "stun://stun.l.google.com:19302",
-        env
+        short = 'o',
+        help = "path in share directory of node, default to name of file."
     )]
-    pub ice_server: String,
+    output: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+#[clap(rename_all = "kebab-case")]
+enum ErasureCommand {
+    Store(ErasureStoreArgs),
+    Fetch(ErasureFetchArgs),
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "store a value erasure coded on DHT, prints id to fetch it")]
+struct ErasureStoreArgs {
     #[clap(flatten)]
     client_args: ClientArgs,
+
+    value: String,
+
+    #[clap(long, help = "shards value is split into, default to 4.")]
+    data_shards: Option<usize>,
+
+    #[clap(long, help = "shards lost at most before value is lost, default to 2.")]
+    parity_shards: Option<usize>,
 }
 
 #[derive(Args, Debug)]
-struct SdpAnswer {
+#[clap(about = "fetch a value erasure coded on DHT")]
+struct ErasureFetchArgs {
     #[clap(flatten)]
     client_args: ClientArgs,
-    ice: String,
+
+    id: String,
+}
+
+#[derive(Subcommand, Debug)]
+#[clap(rename_all = "kebab-case")]
+enum ServiceCommand {
+    Register(ServiceArgs),
+    Unregister(ServiceArgs),
+    Resolve(ServiceArgs),
 }
 
 #[derive(Args, Debug)]
-struct SdpAcceptAnswer {
+#[clap(about = "provide, stop providing or find providers of a service")]
+struct ServiceArgs {
     #[clap(flatten)]
     client_args: ClientArgs,
 
-    #[clap(help = "transport_id of pending transport.")]
-    transport_id: String,
+    name: String,
+}
 
-    #[clap(help = "ice from remote.")]
-    ice: String,
+#[derive(Subcommand, Debug)]
+#[clap(rename_all = "kebab-case")]
+enum EnsCommand {
+    Resolve(EnsArgs),
+    Reverse(EnsArgs),
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "resolve ENS name to address, or address to its verified name")]
+struct EnsArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    #[clap(help = "ENS name to resolve, or address to reverse.")]
+    name: String,
 }
 
 #[derive(Subcommand, Debug)]
 #[clap(rename_all = "kebab-case")]
-enum PeerCommand {
-    List(PeerListArgs),
-    Disconnect(PeerDisconnect),
+enum StabilizationCommand {
+    Status(StabilizationArgs),
+    Pause(StabilizationArgs),
+    Resume(StabilizationArgs),
+    Trigger(StabilizationArgs),
+    Interval(StabilizationIntervalArgs),
+    Repair(StabilizationRepairArgs),
 }
 
 #[derive(Args, Debug)]
-struct PeerListArgs {
+#[clap(about = "show, pause, resume or trigger stabilization of a running node")]
+struct StabilizationArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "verify successors, fingers and stored data of DHT now, after a partition heals")]
+struct StabilizationRepairArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "run stabilization every `min` to `max` seconds")]
+struct StabilizationIntervalArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    min: usize,
+    max: usize,
+}
+
+#[derive(Subcommand, Debug)]
+#[clap(rename_all = "kebab-case")]
+enum DataCommand {
+    List(DataListArgs),
+    Delete(DataDeleteArgs),
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "list data of DHT stored by a running node")]
+struct DataListArgs {
     #[clap(flatten)]
     client_args: ClientArgs,
+
+    #[clap(long)]
+    limit: Option<usize>,
+
+    #[clap(long, help = "next cursor of previous page.")]
+    cursor: Option<String>,
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "delete data of DHT stored by a running node, it's not deleted on other nodes")]
+struct DataDeleteArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    #[clap(help = "key of data, as listed.")]
+    key: String,
 }
 
 #[derive(Args, Debug)]
@@ -185,6 +1231,27 @@ struct PeerDisconnect {
     client_args: ClientArgs,
     address: String,
 }
+
+#[derive(Args, Debug)]
+#[clap(about = "set a local tag of peer, tag `acl=deny` refuses it")]
+struct PeerTagArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+
+    did: String,
+
+    key: String,
+
+    #[clap(help = "tag is removed if it's not given.")]
+    value: Option<String>,
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "list peers seen, with public keys of their first contact")]
+struct PeerKnownArgs {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+}
 #[derive(Subcommand, Debug)]
 #[clap(rename_all = "kebab-case")]
 enum PendingCommand {
@@ -196,6 +1263,15 @@ enum PendingCommand {
 struct PendingList {
     #[clap(flatten)]
     client_args: ClientArgs,
+
+    #[clap(long, help = "only transports connected or not.")]
+    connected: Option<bool>,
+
+    #[clap(long)]
+    limit: Option<usize>,
+
+    #[clap(long, help = "next cursor of previous page.")]
+    cursor: Option<String>,
 }
 
 #[derive(Args, Debug)]
@@ -214,62 +1290,87 @@ struct Send {
     to_address: String,
     #[clap()]
     text: String,
+    #[clap(
+        long,
+        help = "if address is offline, keep message in its inbox for N seconds."
+    )]
+    offline_ttl: Option<u64>,
+    #[clap(
+        long,
+        help = "encrypt message to public key which address published to DHT."
+    )]
+    encrypt: bool,
 }
 
-async fn daemon_run(
-    http_addr: String,
-    key: &SecretKey,
-    stuns: &str,
-    stabilize_timeout: usize,
-) -> anyhow::Result<()> {
+async fn daemon_run(config: Config) -> anyhow::Result<()> {
     // TODO support run daemonize
-    let dht = Arc::new(Mutex::new(PeerRing::new(key.address().into())));
-    let (auth, temp_key) = SessionManager::gen_unsign_info(
-        key.address(),
-        Some(rings_core::session::Ttl::Never),
-        None,
-    )?;
-    let sig = key.sign(&auth.to_string()?).to_vec();
-    let session = SessionManager::new(&sig, &auth, &temp_key);
-    let swarm = Arc::new(Swarm::new(stuns, key.address(), session.clone()));
-    let listen_event = Arc::new(MessageHandler::new(dht.clone(), swarm.clone()));
-    let stabilize = Arc::new(Stabilization::new(
-        dht.clone(),
-        swarm.clone(),
-        stabilize_timeout,
-    ));
-    let swarm_clone = swarm.clone();
-
-    let (_, _, _) = futures::join!(
-        listen_event.clone().listen(),
-        run_service(
-            http_addr.to_owned(),
-            swarm_clone,
-            listen_event,
-            stabilize.clone()
-        ),
-        stabilize.wait(),
-    );
+    if let Some(server) = &config.ntp_server {
+        doctor::warn_clock(server, config.max_clock_skew_ms).await;
+    }
+    let node = Node::builder().with_config(config.clone()).build().await?;
+    let processor = node.processor().clone();
+    let listen_event = processor.msg_handler.clone();
+    let socks5_exit = config.socks5_exit()?;
+    let exit_peers = config.exit_peers()?;
+    node.start();
+    if config.features.mdns {
+        let (http_addr, processor) = (config.http_addr.clone(), processor.clone());
+        tokio::spawn(async move {
+            if let Err(e) = run_mdns(http_addr, processor).await {
+                tracing::error!("mDNS discovery stopped: {}", e);
+            }
+        });
+    }
 
-    Ok(())
+    // service stops after the swarm is drained, others run forever
+    tokio::select! {
+        r = run_service(
+            config.http_addr.to_owned(),
+            config.rpc_socket.to_owned(),
+            processor.clone(),
+            node.handshake_limiter(),
+        ) => r,
+        r = async {
+            match (&config.socks5_addr, socks5_exit, config.socks5_auth()?) {
+                (Some(addr), Some(exit), Some(auth)) => {
+                    run_socks5_proxy(addr.to_owned(), exit, auth, listen_event.clone()).await
+                }
+                _ => futures::future::pending().await,
+            }
+        } => r,
+        r = async {
+            if exit_peers.is_empty() && config.http_service.is_none() {
+                futures::future::pending().await
+            } else {
+                let http_service = config.http_service.clone();
+                run_tunnel(
+                    exit_peers.clone(),
+                    config.exit_allow_private,
+                    http_service,
+                    listen_event.clone(),
+                )
+                .await
+            }
+        } => r,
+        r = async {
+            match &config.dns_addr {
+                Some(addr) => {
+                    run_dns_stub(addr.to_owned(), config.gateway_ips()?, processor.clone()).await
+                }
+                None => futures::future::pending().await,
+            }
+        } => r,
+    }
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     dotenv::dotenv().ok();
     let cli = Cli::parse();
-    Logger::init(cli.log_level.into())?;
+    init_tracing(cli.log_level, cli.log_filter.as_deref(), cli.log_format)?;
 
     if let Err(e) = match cli.command {
-        Command::Run(args) => {
-            daemon_run(
-                args.http_addr,
-                &args.eth_key,
-                args.ice_servers.as_str(),
-                args.stabilize_timeout,
-            )
-            .await
-        }
+        Command::Run(args) => daemon_run(args.load_config()?).await,
         Command::Connect(ConnectCommand::Node(args)) => {
             args.client_args
                 .new_client()
@@ -316,10 +1417,25 @@ async fn main() -> anyhow::Result<()> {
             Ok(())
         }
         Command::Peer(PeerCommand::List(args)) => {
+            let tags = args
+                .tag
+                .iter()
+                .map(|t| {
+                    t.split_once('=')
+                        .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                        .ok_or_else(|| anyhow::anyhow!("tag should be `key=value`: {}", t))
+                })
+                .collect::<anyhow::Result<_>>()?;
+            let filter = PeerFilter {
+                connected: args.connected,
+                did_prefix: args.did_prefix,
+                tags,
+                limit: args.limit,
+            };
             args.client_args
                 .new_client()
                 .await?
-                .list_peers()
+                .list_peers(&filter, args.cursor.as_deref())
                 .await?
                 .display();
             Ok(())
@@ -333,11 +1449,34 @@ async fn main() -> anyhow::Result<()> {
                 .display();
             Ok(())
         }
+        Command::Peer(PeerCommand::Tag(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .tag_peer(&args.did, &args.key, args.value.as_deref())
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Peer(PeerCommand::Known(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .list_known_peers()
+                .await?
+                .display();
+            Ok(())
+        }
         Command::Pending(PendingCommand::List(args)) => {
+            let filter = PeerFilter {
+                connected: args.connected,
+                limit: args.limit,
+                ..Default::default()
+            };
             args.client_args
                 .new_client()
                 .await?
-                .list_pendings()
+                .list_pendings(&filter, args.cursor.as_deref())
                 .await?
                 .display();
             Ok(())
@@ -355,7 +1494,113 @@ async fn main() -> anyhow::Result<()> {
             args.client_args
                 .new_client()
                 .await?
-                .send_message(args.to_address.as_str(), args.text.as_str())
+                .send_message(
+                    args.to_address.as_str(),
+                    args.text.as_str(),
+                    args.offline_ttl,
+                    args.encrypt,
+                )
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Info(args) => {
+            args.client_args
+                .new_client()
+                .await?
+                .node_info()
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Whois(args) => {
+            args.client_args
+                .new_client()
+                .await?
+                .whois(args.did.as_str())
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Crawl(args) => {
+            args.client_args
+                .new_client()
+                .await?
+                .crawl(args.max_nodes, args.with_manifests)
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Benchmark(args) => {
+            args.client_args
+                .new_client()
+                .await?
+                .benchmark(args.did.as_str(), args.size, args.count, args.concurrency)
+                .await?
+                .display();
+            Ok(())
+        }
+        #[cfg(feature = "chaos")]
+        Command::Chaos(args) => {
+            let config = (args.drop.is_some()
+                || args.delay_ms.is_some()
+                || args.corrupt.is_some()
+                || args.kill.is_some())
+            .then(|| FaultConfig {
+                drop_ratio: args.drop.unwrap_or(0.0),
+                delay_ms: args.delay_ms.unwrap_or(0),
+                corrupt_ratio: args.corrupt.unwrap_or(0.0),
+                kill_ratio: args.kill.unwrap_or(0.0),
+            });
+            args.client_args
+                .new_client()
+                .await?
+                .inject_faults(config)
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Drain(args) => {
+            args.client_args
+                .new_client()
+                .await?
+                .drain()
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::RotateIdentity(args) => {
+            // record is signed here, keys are never sent to node
+            let record = RotationRecord::new(&args.eth_key, &args.new_eth_key)?;
+            args.client_args
+                .new_client()
+                .await?
+                .rotate_identity(&record)
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Capture(args) => {
+            args.client_args
+                .new_client()
+                .await?
+                .captured_payloads(args.clear)
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::History(args) => {
+            let filter = HistoryFilter {
+                sender: args.sender.as_deref().map(str::parse::<Did>).transpose()?,
+                tx_id: args.tx_id,
+                since_ms: args.since,
+                until_ms: args.until,
+                limit: args.limit,
+            };
+            args.client_args
+                .new_client()
+                .await?
+                .list_messages(&filter, args.cursor.as_deref())
                 .await?
                 .display();
             Ok(())
@@ -365,6 +1610,276 @@ async fn main() -> anyhow::Result<()> {
             println!("New secretKey: {}", k.to_string());
             Ok(())
         }
+        Command::Doctor(args) => {
+            let checks = doctor::run(&args.daemon.load_config()?, args.loopback).await;
+            for check in checks.iter() {
+                println!("{}", check);
+            }
+            let failed = checks
+                .iter()
+                .filter(|c| c.status == doctor::Status::Fail)
+                .count();
+            if failed > 0 {
+                return Err(anyhow::anyhow!("{} checks failed", failed));
+            }
+            Ok(())
+        }
+        Command::Config(ConfigCommand::Init(args)) => {
+            Config::init_file(args.path.as_str(), args.force)?;
+            println!("Config file created: {}", args.path);
+            Ok(())
+        }
+        Command::Config(ConfigCommand::Show(args)) => {
+            println!("{}", args.daemon.load_config()?.redacted().to_toml()?);
+            Ok(())
+        }
+        Command::State(StateCommand::Export(args)) => {
+            let output = args.client_args.new_client().await?.export_state().await?;
+            match args.output {
+                Some(path) => {
+                    std::fs::write(&path, serde_json::to_vec_pretty(&output.result)?)?;
+                    println!("State exported: {}", path);
+                }
+                None => output.display(),
+            }
+            Ok(())
+        }
+        Command::State(StateCommand::Import(args)) => {
+            let snapshot = serde_json::from_slice(&std::fs::read(&args.path)?)?;
+            args.client_args
+                .new_client()
+                .await?
+                .import_state(&snapshot)
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Presence(PresenceCommand::Query(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .query_presence(args.did.as_str())
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Presence(PresenceCommand::Track(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .track_presence(args.did.as_str(), true)
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Presence(PresenceCommand::Untrack(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .track_presence(args.did.as_str(), false)
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Group(GroupCommand::Create(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .create_group(args.name.as_str(), &args.members)
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Group(GroupCommand::Send(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .send_to_group(args.name.as_str(), args.text.as_str(), args.offline_ttl)
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Group(GroupCommand::Fetch(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .fetch_group(args.name.as_str())
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Group(GroupCommand::RotateKey(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .rotate_group_key(args.name.as_str())
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Group(GroupCommand::FetchKey(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .fetch_group_key(args.name.as_str())
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Topic(TopicCommand::Publish(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .publish_topic(args.name.as_str(), args.data.as_str())
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Topic(TopicCommand::Fetch(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .fetch_topic(args.name.as_str())
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::File(FileCommand::Send(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .send_file(args.path.as_str())
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::File(FileCommand::Fetch(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .fetch_file(args.id.as_str(), args.output.as_deref())
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Erasure(ErasureCommand::Store(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .store_erasure(args.value.as_bytes(), args.data_shards, args.parity_shards)
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Erasure(ErasureCommand::Fetch(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .fetch_erasure(args.id.as_str())
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Service(ServiceCommand::Register(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .register_service(args.name.as_str(), true)
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Service(ServiceCommand::Unregister(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .register_service(args.name.as_str(), false)
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Service(ServiceCommand::Resolve(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .resolve_service(args.name.as_str())
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Ens(EnsCommand::Resolve(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .ens_resolve(args.name.as_str(), false)
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Ens(EnsCommand::Reverse(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .ens_resolve(args.name.as_str(), true)
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Data(DataCommand::List(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .list_local_data(args.cursor.as_deref(), args.limit)
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Data(DataCommand::Delete(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .delete_local_data(args.key.as_str())
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Stabilization(command) => {
+            let (client_args, control) = match command {
+                StabilizationCommand::Repair(args) => {
+                    args.client_args
+                        .new_client()
+                        .await?
+                        .repair_dht()
+                        .await?
+                        .display();
+                    return Ok(());
+                }
+                StabilizationCommand::Status(args) => (args.client_args, None),
+                StabilizationCommand::Pause(args) => {
+                    (args.client_args, Some(StabilizationControl::Pause))
+                }
+                StabilizationCommand::Resume(args) => {
+                    (args.client_args, Some(StabilizationControl::Resume))
+                }
+                StabilizationCommand::Trigger(args) => {
+                    (args.client_args, Some(StabilizationControl::Trigger))
+                }
+                StabilizationCommand::Interval(args) => (
+                    args.client_args,
+                    Some(StabilizationControl::Interval {
+                        min: args.min,
+                        max: args.max,
+                    }),
+                ),
+            };
+            client_args
+                .new_client()
+                .await?
+                .stabilization(control)
+                .await?
+                .display();
+            Ok(())
+        }
     } {
         return Err(e);
     }
@@ -0,0 +1,215 @@
+//! Benchmarks of hot paths, compare with a baseline to catch regressions:
+//! ```shell
+//! cargo bench -p rings-core --features test-utils --bench hot_paths -- --save-baseline master
+//! cargo bench -p rings-core --features test-utils --bench hot_paths -- --baseline master
+//! ```
+//! `receive/workers/0` is receiving inline, as swarm did before [VerifyPool], compare it with
+//! more workers for gain of the pool on this machine. Criterion prints the throughput of each,
+//! in payloads per second.
+//!
+//! [VerifyPool]: rings_core::verify_pool::VerifyPool
+use async_trait::async_trait;
+use criterion::black_box;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BenchmarkId;
+use criterion::Criterion;
+use criterion::Throughput;
+use futures::StreamExt;
+use rings_core::address::Address;
+use rings_core::dht::Chord;
+use rings_core::dht::Did;
+use rings_core::dht::PeerRing;
+use rings_core::ecc::SecretKey;
+use rings_core::err::Result;
+use rings_core::message::CustomMessage;
+use rings_core::message::Decoder;
+use rings_core::message::Encoder;
+use rings_core::message::MaybeEncrypted;
+use rings_core::message::Message;
+use rings_core::message::MessagePayload;
+use rings_core::message::MessageRelay;
+use rings_core::message::OriginVerificationGen;
+use rings_core::message::PayloadSender;
+use rings_core::message::RelayMethod;
+use rings_core::session::SessionManager;
+use rings_core::swarm::Swarm;
+use rings_core::types::channel::Event;
+
+fn random_did() -> Did {
+    SecretKey::random().address().into()
+}
+
+fn new_session() -> SessionManager {
+    SessionManager::new_with_seckey(&SecretKey::random()).unwrap()
+}
+
+fn new_payload(session: &SessionManager, size: usize) -> MessagePayload<Message> {
+    let data = Message::CustomMessage(MaybeEncrypted::Plain(CustomMessage(vec![42u8; size])));
+    MessagePayload::new_send(data, session, random_did(), random_did()).unwrap()
+}
+
+fn bench_codec(c: &mut Criterion) {
+    let session = new_session();
+    let mut group = c.benchmark_group("codec");
+    for size in [64, 1024, 16 * 1024] {
+        let payload = new_payload(&session, size);
+        group.throughput(Throughput::Bytes(size as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("json_gzip_encode", size),
+            &payload,
+            |b, p| b.iter(|| black_box(p.encode().unwrap())),
+        );
+        let encoded = payload.encode().unwrap();
+        group.bench_with_input(
+            BenchmarkId::new("json_gzip_decode", size),
+            &encoded,
+            |b, e| b.iter(|| black_box(MessagePayload::<Message>::from_encoded(e).unwrap())),
+        );
+
+        // proposed binary format, for comparison only
+        group.bench_with_input(
+            BenchmarkId::new("bincode_encode", size),
+            &payload,
+            |b, p| b.iter(|| black_box(bincode::serialize(p).unwrap())),
+        );
+        let bin = bincode::serialize(&payload).unwrap();
+        group.bench_with_input(BenchmarkId::new("bincode_decode", size), &bin, |b, bin| {
+            b.iter(|| black_box(bincode::deserialize::<MessagePayload<Message>>(bin).unwrap()))
+        });
+    }
+    group.finish();
+}
+
+fn bench_verify(c: &mut Criterion) {
+    let payload = new_payload(&new_session(), 1024);
+    c.bench_function("verify", |b| b.iter(|| black_box(payload.verify())));
+}
+
+// a batch of payloads received by swarm, loaded by [Swarm::iter_messages] with verify workers
+fn bench_receive(c: &mut Criterion) {
+    const BATCH: usize = 64;
+    let peer = new_session();
+    let from = peer.authorizer().unwrap();
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let _guard = rt.enter();
+    let mut group = c.benchmark_group("receive");
+    group.throughput(Throughput::Elements(BATCH as u64));
+    for workers in [0, 2, 4, 8] {
+        let key = SecretKey::random();
+        // the same payloads are received by every iteration, so replay is not checked
+        let swarm = Swarm::builder(
+            key.address(),
+            SessionManager::new_with_seckey(&key).unwrap(),
+        )
+        .with_ice_servers("stun://stun.l.google.com:19302")
+        .with_verify_workers(workers)
+        .with_replay_window(0)
+        .build()
+        .unwrap();
+        let received = (0..BATCH)
+            .map(|_| {
+                let data =
+                    Message::CustomMessage(MaybeEncrypted::Plain(CustomMessage(vec![42u8; 1024])));
+                let payload = MessagePayload::new_direct(data, &peer, key.address().into());
+                payload.unwrap().encode().unwrap().as_bytes().to_vec()
+            })
+            .collect::<Vec<_>>();
+        group.bench_with_input(BenchmarkId::new("workers", workers), &swarm, |b, swarm| {
+            b.iter(|| {
+                rt.block_on(async {
+                    for msg in received.iter() {
+                        let ev = Event::DataChannelMessage(from, msg.clone());
+                        swarm.inject_event(ev).await.unwrap();
+                    }
+                    let loaded = swarm.iter_messages().take(BATCH).count().await;
+                    assert_eq!(loaded, BATCH);
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Sender of relay node, payloads are encoded as swarm does before sending, then dropped.
+struct Relay {
+    session: SessionManager,
+}
+
+#[async_trait]
+impl PayloadSender<Message> for Relay {
+    fn session_manager(&self) -> &SessionManager {
+        &self.session
+    }
+
+    async fn do_send_payload(
+        &self,
+        _address: &Address,
+        payload: MessagePayload<Message>,
+    ) -> Result<()> {
+        black_box(payload.encode()?);
+        Ok(())
+    }
+}
+
+// what a relay node does before sending to next hop, by [MessageRelay::relay] and
+// [PayloadSender::transpond_payload] of handlers
+fn bench_transpond(c: &mut Criterion) {
+    let origin = new_session();
+    let current = random_did();
+    let next = random_did();
+    let relay = Relay {
+        session: new_session(),
+    };
+    let payload = MessagePayload::new(
+        Message::CustomMessage(MaybeEncrypted::Plain(CustomMessage(vec![42u8; 1024]))),
+        &origin,
+        OriginVerificationGen::Origin,
+        MessageRelay::new(
+            RelayMethod::SEND,
+            vec![random_did(), random_did()],
+            None,
+            Some(current),
+            random_did(),
+        ),
+    )
+    .unwrap();
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    c.bench_function("transpond", |b| {
+        b.iter(|| {
+            let mut r = payload.relay.clone();
+            r.relay(current, Some(next)).unwrap();
+            rt.block_on(relay.transpond_payload(&payload, r)).unwrap()
+        })
+    });
+}
+
+fn bench_find_successor(c: &mut Criterion) {
+    let mut group = c.benchmark_group("find_successor");
+    for peers in [10, 100, 1000] {
+        let mut ring = PeerRing::new(random_did());
+        for _ in 0..peers {
+            ring.join(random_did());
+        }
+        let targets = (0..100).map(|_| random_did()).collect::<Vec<_>>();
+        group.bench_with_input(BenchmarkId::from_parameter(peers), &ring, |b, ring| {
+            let mut i = 0;
+            b.iter(|| {
+                i = (i + 1) % targets.len();
+                black_box(ring.find_successor(targets[i]).unwrap())
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_codec,
+    bench_verify,
+    bench_receive,
+    bench_transpond,
+    bench_find_successor
+);
+criterion_main!(benches);
@@ -0,0 +1,445 @@
+//! Accounting of messages relayed for others, for operators of community relays.
+//!
+//! Every [RelayedData](crate::message::RelayedData) forwarded by this node is charged to its
+//! origin in [RelayAccounting], which is the signer of the message, never a node taken from
+//! its relay path. An origin is relayed at most a budget of bytes per minute, and a pluggable
+//! [RelayPolicy] decides, before forwarding, whether a message of an origin is relayed at
+//! once, delayed, or refused, like [QuotaPolicy] does for origins exceeding their quota.
+//! Delayed messages are queued, at most [MAX_QUEUED] of each origin, instead of holding up
+//! the handler. At most [MAX_ORIGINS] origins are accounted in a period, messages of new
+//! origins are refused once it's full, so usage to be settled is never dropped.
+//!
+//! Once a period, stabilization signs the usage of the period as a [SignedUsageStatement],
+//! and hands it to [SettlementHook]s, which may publish it or settle it with the origins.
+//! Usage is reset for the next period then.
+use std::collections::BTreeMap;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::dht::Did;
+use crate::err::Result;
+use crate::message::MessageVerification;
+use crate::session::SessionManager;
+use crate::utils;
+
+/// Usage is settled this often by default, in ms.
+pub const DEFAULT_STATEMENT_PERIOD_MS: u128 = 60 * 60 * 1000;
+/// Bytes relayed for each origin per minute by default.
+pub const DEFAULT_RELAY_BUDGET: usize = 16 * 1024 * 1024;
+const BUDGET_WINDOW_MS: u128 = 60 * 1000;
+/// Origins accounted in a period at most.
+pub const MAX_ORIGINS: usize = 16 * 1024;
+/// Delayed messages of an origin waiting to be relayed at most.
+pub const MAX_QUEUED: usize = 32;
+
+/// Messages and bytes relayed for an origin in a period.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelayUsage {
+    pub origin: Did,
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+/// What to do with a message to be relayed for an origin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelayDecision {
+    Allow,
+    /// Relay it after this many ms.
+    Throttle(u64),
+    Deny,
+}
+
+/// Decides whether messages of an origin are relayed, by its usage in current period.
+pub trait RelayPolicy: Send + Sync {
+    /// `usage` is of `origin` in current period, before the message of `bytes` is charged.
+    fn decide(&self, usage: &RelayUsage, bytes: usize) -> RelayDecision;
+}
+
+/// Refuse origins over `max_bytes` or `max_messages` in a period, and delay their messages by
+/// `throttle_ms` once they use 3/4 of either. 0 is unlimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaPolicy {
+    pub max_bytes: u64,
+    pub max_messages: u64,
+    pub throttle_ms: u64,
+}
+
+impl RelayPolicy for QuotaPolicy {
+    fn decide(&self, usage: &RelayUsage, bytes: usize) -> RelayDecision {
+        let bytes = usage.bytes + bytes as u64;
+        let messages = usage.messages + 1;
+        let over = |used: u64, max: u64| max > 0 && used > max;
+        let near = |used: u64, max: u64| max > 0 && used * 4 > max * 3;
+        if over(bytes, self.max_bytes) || over(messages, self.max_messages) {
+            RelayDecision::Deny
+        } else if self.throttle_ms > 0
+            && (near(bytes, self.max_bytes) || near(messages, self.max_messages))
+        {
+            RelayDecision::Throttle(self.throttle_ms)
+        } else {
+            RelayDecision::Allow
+        }
+    }
+}
+
+/// Usage of a period, stated by the relay.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct UsageStatement {
+    pub relay: Did,
+    pub since_ms: u128,
+    pub until_ms: u128,
+    /// Ordered by origin.
+    pub usage: Vec<RelayUsage>,
+}
+
+/// Statement signed by session of the relay, so origins and settlers can check it.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SignedUsageStatement {
+    pub statement: UsageStatement,
+    pub verification: MessageVerification,
+}
+
+impl SignedUsageStatement {
+    pub fn new(session_manager: &SessionManager, statement: UsageStatement) -> Result<Self> {
+        let ts_ms = utils::get_epoch_ms();
+        let ttl_ms = usize::MAX;
+        let msg = MessageVerification::pack_msg(&statement, ts_ms, ttl_ms)?;
+        let verification = MessageVerification {
+            session: session_manager.session()?,
+            sig: session_manager.sign(&msg)?,
+            ttl_ms,
+            ts_ms,
+        };
+        Ok(Self {
+            statement,
+            verification,
+        })
+    }
+
+    /// Check signature, and statement is signed by its relay.
+    pub fn verify(&self) -> bool {
+        Did::from(self.verification.session.auth.authorizer) == self.statement.relay
+            && self.verification.verify(&self.statement)
+    }
+}
+
+/// Receiver of signed usage statements, see [RelayAccounting::add_hook].
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+pub trait SettlementHook {
+    async fn on_statement(&self, statement: &SignedUsageStatement);
+}
+
+#[cfg(not(feature = "wasm"))]
+type HookFn = Arc<dyn SettlementHook + Send + Sync>;
+
+#[cfg(feature = "wasm")]
+type HookFn = Arc<dyn SettlementHook>;
+
+/// Usage of an origin in current period and minute, and its delayed messages.
+#[derive(Debug, Clone, Copy)]
+struct OriginUsage {
+    usage: RelayUsage,
+    /// Start of current minute, and bytes relayed in it.
+    window: (u128, usize),
+    queued: usize,
+}
+
+impl OriginUsage {
+    fn new(origin: Did, now: u128) -> Self {
+        Self {
+            usage: RelayUsage {
+                origin,
+                messages: 0,
+                bytes: 0,
+            },
+            window: (now, 0),
+            queued: 0,
+        }
+    }
+
+    fn is_idle(&self, now: u128) -> bool {
+        self.usage.messages == 0
+            && self.queued == 0
+            && now.saturating_sub(self.window.0) >= BUDGET_WINDOW_MS
+    }
+}
+
+/// Usage of current period by origin, with the budget, policy and hooks of it, see module
+/// doc.
+pub struct RelayAccounting {
+    period_ms: u128,
+    /// Bytes relayed for each origin per minute, 0 is unlimited.
+    budget: AtomicUsize,
+    /// Start of current period, and usage in it.
+    usage: Mutex<(u128, BTreeMap<Did, OriginUsage>)>,
+    policy: Mutex<Option<Arc<dyn RelayPolicy>>>,
+    hooks: Mutex<Vec<HookFn>>,
+}
+
+impl Default for RelayAccounting {
+    fn default() -> Self {
+        Self::new(DEFAULT_STATEMENT_PERIOD_MS)
+    }
+}
+
+impl RelayAccounting {
+    /// Settle usage every `period_ms`.
+    pub fn new(period_ms: u128) -> Self {
+        Self {
+            period_ms,
+            budget: AtomicUsize::new(DEFAULT_RELAY_BUDGET),
+            usage: Mutex::new((utils::get_epoch_ms(), BTreeMap::new())),
+            policy: Mutex::new(None),
+            hooks: Mutex::new(vec![]),
+        }
+    }
+
+    /// Relay at most `budget` bytes per minute for each origin, 0 is unlimited.
+    pub fn set_budget(&self, budget: usize) {
+        self.budget.store(budget, Ordering::Relaxed);
+    }
+
+    /// Decide messages to be relayed by `policy`, all are allowed without one.
+    pub fn set_policy(&self, policy: Option<Arc<dyn RelayPolicy>>) {
+        *self.policy.lock().unwrap() = policy;
+    }
+
+    /// Hand signed statements to `hook` from now on.
+    pub fn add_hook(&self, hook: HookFn) {
+        self.hooks.lock().unwrap().push(hook);
+    }
+
+    /// Admit a message of `bytes` to be relayed for `origin`, by its budget and policy, and
+    /// charge it unless it's denied. A throttled message is queued, and should be
+    /// [RelayAccounting::dequeue]d once it's relayed.
+    pub fn admit(&self, origin: Did, bytes: usize) -> RelayDecision {
+        self.admit_at(origin, bytes, utils::get_epoch_ms())
+    }
+
+    fn admit_at(&self, origin: Did, bytes: usize, now: u128) -> RelayDecision {
+        let policy = self.policy.lock().unwrap().clone();
+        let budget = self.budget.load(Ordering::Relaxed);
+        let mut usage = self.usage.lock().unwrap();
+        let usage = &mut usage.1;
+        if !usage.contains_key(&origin) && usage.len() >= MAX_ORIGINS {
+            usage.retain(|_, u| !u.is_idle(now));
+            if usage.len() >= MAX_ORIGINS {
+                tracing::warn!(origin = ?origin, "too many origins relayed, deny");
+                return RelayDecision::Deny;
+            }
+        }
+        let entry = usage
+            .entry(origin)
+            .or_insert_with(|| OriginUsage::new(origin, now));
+        if now.saturating_sub(entry.window.0) >= BUDGET_WINDOW_MS {
+            entry.window = (now, 0);
+        }
+        if budget > 0 && entry.window.1 + bytes > budget {
+            tracing::warn!(origin = ?origin, "relay budget used up, deny");
+            return RelayDecision::Deny;
+        }
+        let decision = match policy {
+            Some(p) => p.decide(&entry.usage, bytes),
+            None => RelayDecision::Allow,
+        };
+        match decision {
+            RelayDecision::Deny => return decision,
+            RelayDecision::Throttle(_) if entry.queued >= MAX_QUEUED => {
+                tracing::warn!(origin = ?origin, "too many relayed messages delayed, deny");
+                return RelayDecision::Deny;
+            }
+            RelayDecision::Throttle(_) => entry.queued += 1,
+            RelayDecision::Allow => {}
+        }
+        entry.window.1 += bytes;
+        entry.usage.messages += 1;
+        entry.usage.bytes += bytes as u64;
+        decision
+    }
+
+    /// A throttled message of `origin` is relayed or dropped.
+    pub fn dequeue(&self, origin: Did) {
+        if let Some(u) = self.usage.lock().unwrap().1.get_mut(&origin) {
+            u.queued = u.queued.saturating_sub(1);
+        }
+    }
+
+    /// Usage of `origin` in current period.
+    pub fn usage(&self, origin: Did) -> RelayUsage {
+        self.usage.lock().unwrap().1.get(&origin).map_or(
+            RelayUsage {
+                origin,
+                messages: 0,
+                bytes: 0,
+            },
+            |u| u.usage,
+        )
+    }
+
+    /// Usage of all origins in current period.
+    pub fn usages(&self) -> Vec<RelayUsage> {
+        self.usage
+            .lock()
+            .unwrap()
+            .1
+            .values()
+            .filter(|u| u.usage.messages > 0)
+            .map(|u| u.usage)
+            .collect()
+    }
+
+    /// Close current period at `now` if it's over, and state its usage if any.
+    fn close_period(&self, relay: Did, now: u128) -> Option<UsageStatement> {
+        let mut usage = self.usage.lock().unwrap();
+        if now.saturating_sub(usage.0) < self.period_ms {
+            return None;
+        }
+        let since_ms = std::mem::replace(&mut usage.0, now);
+        let stated = usage
+            .1
+            .values_mut()
+            .filter(|u| u.usage.messages > 0)
+            .map(|u| {
+                let stated = u.usage;
+                u.usage.messages = 0;
+                u.usage.bytes = 0;
+                stated
+            })
+            .collect::<Vec<_>>();
+        // windows and queues of busy origins are kept
+        usage.1.retain(|_, u| !u.is_idle(now));
+        (!stated.is_empty()).then(|| UsageStatement {
+            relay,
+            since_ms,
+            until_ms: now,
+            usage: stated,
+        })
+    }
+
+    /// Sign usage of current period if it's over, and hand it to hooks, called by
+    /// stabilization.
+    pub async fn settle(
+        &self,
+        session_manager: &SessionManager,
+    ) -> Result<Option<SignedUsageStatement>> {
+        let relay = session_manager.authorizer()?.into();
+        let statement = match self.close_period(relay, utils::get_epoch_ms()) {
+            Some(s) => SignedUsageStatement::new(session_manager, s)?,
+            None => return Ok(None),
+        };
+        tracing::info!(
+            origins = statement.statement.usage.len(),
+            "usage of relayed messages settled"
+        );
+        let hooks = self.hooks.lock().unwrap().clone();
+        for hook in hooks {
+            hook.on_statement(&statement).await;
+        }
+        Ok(Some(statement))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    #[test]
+    fn test_quota_policy() {
+        let origin: Did = SecretKey::random().address().into();
+        let accounting = RelayAccounting::new(60_000);
+        accounting.set_policy(Some(Arc::new(QuotaPolicy {
+            max_bytes: 1000,
+            max_messages: 0,
+            throttle_ms: 50,
+        })));
+        assert_eq!(accounting.admit(origin, 500), RelayDecision::Allow);
+        assert_eq!(accounting.admit(origin, 501), RelayDecision::Deny);
+        assert_eq!(accounting.admit(origin, 300), RelayDecision::Throttle(50));
+        // quota is of each origin
+        let other: Did = SecretKey::random().address().into();
+        assert_eq!(accounting.admit(other, 500), RelayDecision::Allow);
+        // denied messages are not charged, throttled ones are
+        assert_eq!(accounting.usage(origin), RelayUsage {
+            origin,
+            messages: 2,
+            bytes: 800
+        });
+    }
+
+    #[test]
+    fn test_relay_budget() {
+        let origin: Did = SecretKey::random().address().into();
+        let accounting = RelayAccounting::new(60_000);
+        accounting.set_budget(100);
+        assert_eq!(accounting.admit_at(origin, 60, 0), RelayDecision::Allow);
+        assert_eq!(accounting.admit_at(origin, 60, 10), RelayDecision::Deny);
+        assert_eq!(accounting.admit_at(origin, 40, 20), RelayDecision::Allow);
+        // budget is of a minute
+        assert_eq!(
+            accounting.admit_at(origin, 60, BUDGET_WINDOW_MS),
+            RelayDecision::Allow
+        );
+        accounting.set_budget(0);
+        assert_eq!(
+            accounting.admit_at(origin, 1 << 30, BUDGET_WINDOW_MS),
+            RelayDecision::Allow
+        );
+    }
+
+    struct Throttle;
+
+    impl RelayPolicy for Throttle {
+        fn decide(&self, _: &RelayUsage, _: usize) -> RelayDecision {
+            RelayDecision::Throttle(50)
+        }
+    }
+
+    #[test]
+    fn test_relay_queue_bounded() {
+        let origin: Did = SecretKey::random().address().into();
+        let accounting = RelayAccounting::new(60_000);
+        accounting.set_policy(Some(Arc::new(Throttle)));
+        let decisions = (0..2 * MAX_QUEUED)
+            .map(|_| accounting.admit(origin, 1))
+            .collect::<Vec<_>>();
+        let queued = decisions
+            .iter()
+            .filter(|d| matches!(d, RelayDecision::Throttle(_)))
+            .count();
+        assert_eq!(queued, MAX_QUEUED);
+        assert_eq!(decisions.last(), Some(&RelayDecision::Deny));
+        accounting.dequeue(origin);
+        assert_eq!(accounting.admit(origin, 0), RelayDecision::Throttle(50));
+    }
+
+    #[tokio::test]
+    async fn test_settle_usage() {
+        let session = SessionManager::new_with_seckey(&SecretKey::random()).unwrap();
+        let origin: Did = SecretKey::random().address().into();
+        let accounting = RelayAccounting::new(0);
+        assert!(accounting.settle(&session).await.unwrap().is_none());
+        accounting.admit(origin, 10);
+        accounting.admit(origin, 20);
+        let signed = accounting.settle(&session).await.unwrap().unwrap();
+        assert!(signed.verify());
+        assert_eq!(signed.statement.usage, vec![RelayUsage {
+            origin,
+            messages: 2,
+            bytes: 30
+        }]);
+        // usage is reset for next period
+        assert!(accounting.usages().is_empty());
+
+        let mut forged = signed;
+        forged.statement.usage[0].bytes = 3000;
+        assert!(!forged.verify());
+    }
+}
@@ -0,0 +1,180 @@
+//! Addresses of nodes and accounts, 20 bytes as web3 addresses.
+//!
+//! With `web3` feature, which is default, [Address] is the one of web3 crate. Without it, a
+//! minimal local [H160] with the same text and serialized forms takes its place, so embedded and
+//! wasm users can build without the web3 stack, and still talk to nodes built with it.
+
+#[cfg(feature = "web3")]
+pub use web3::types::Address;
+#[cfg(feature = "web3")]
+pub use web3::types::H160;
+
+#[cfg(not(feature = "web3"))]
+pub use self::local::H160;
+#[cfg(not(feature = "web3"))]
+pub type Address = H160;
+
+/// Keccak-256 of `bytes`, as web3 uses for addresses and signed messages.
+pub fn keccak256(bytes: &[u8]) -> [u8; 32] {
+    use tiny_keccak::Hasher;
+    let mut output = [0u8; 32];
+    let mut hasher = tiny_keccak::Keccak::v256();
+    hasher.update(bytes);
+    hasher.finalize(&mut output);
+    output
+}
+
+#[cfg(not(feature = "web3"))]
+mod local {
+    use std::fmt;
+    use std::str::FromStr;
+
+    use serde::de;
+    use serde::Deserialize;
+    use serde::Deserializer;
+    use serde::Serialize;
+    use serde::Serializer;
+
+    /// 20 bytes hash, written as `0x` with 40 lowercase hex digits.
+    #[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct H160(pub [u8; 20]);
+
+    impl H160 {
+        pub const fn len_bytes() -> usize {
+            20
+        }
+
+        pub fn zero() -> Self {
+            Self::default()
+        }
+
+        pub fn is_zero(&self) -> bool {
+            self.0 == [0u8; 20]
+        }
+
+        /// Panics if length of `src` is not 20, as the one of web3 does.
+        pub fn from_slice(src: &[u8]) -> Self {
+            let mut ret = Self::zero();
+            ret.0.copy_from_slice(src);
+            ret
+        }
+
+        pub fn as_bytes(&self) -> &[u8] {
+            &self.0
+        }
+
+        pub fn as_fixed_bytes(&self) -> &[u8; 20] {
+            &self.0
+        }
+
+        pub fn to_fixed_bytes(self) -> [u8; 20] {
+            self.0
+        }
+    }
+
+    impl From<[u8; 20]> for H160 {
+        fn from(bytes: [u8; 20]) -> Self {
+            Self(bytes)
+        }
+    }
+
+    impl From<H160> for [u8; 20] {
+        fn from(h: H160) -> Self {
+            h.0
+        }
+    }
+
+    impl AsRef<[u8]> for H160 {
+        fn as_ref(&self) -> &[u8] {
+            &self.0
+        }
+    }
+
+    impl FromStr for H160 {
+        type Err = hex::FromHexError;
+
+        /// Hex digits of either case, with or without `0x`.
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let s = s
+                .strip_prefix("0x")
+                .or_else(|| s.strip_prefix("0X"))
+                .unwrap_or(s);
+            let mut ret = Self::zero();
+            hex::decode_to_slice(s, &mut ret.0)?;
+            Ok(ret)
+        }
+    }
+
+    impl fmt::LowerHex for H160 {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            if f.alternate() {
+                write!(f, "0x")?;
+            }
+            for b in self.0.iter() {
+                write!(f, "{:02x}", b)?;
+            }
+            Ok(())
+        }
+    }
+
+    impl fmt::Debug for H160 {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{:#x}", self)
+        }
+    }
+
+    /// Abbreviated as `0x1234…cdef`.
+    impl fmt::Display for H160 {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "0x{}…{}",
+                hex::encode(&self.0[..2]),
+                hex::encode(&self.0[18..])
+            )
+        }
+    }
+
+    impl Serialize for H160 {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer {
+            serializer.serialize_str(&format!("{:#x}", self))
+        }
+    }
+
+    impl<'de> Deserialize<'de> for H160 {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: Deserializer<'de> {
+            let s = String::deserialize(deserializer)?;
+            H160::from_str(&s).map_err(de::Error::custom)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_address_forms() {
+        assert_eq!(
+            hex::encode(keccak256(b"")),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+        let addr = Address::from_str("0x11E807fcc88dD319270493fB2e822e388Fe36ab0").unwrap();
+        let text = "0x11e807fcc88dd319270493fb2e822e388fe36ab0";
+        assert_eq!(format!("{:?}", addr), text);
+        assert_eq!(addr.to_string(), "0x11e8…6ab0");
+        assert_eq!(Address::from_str(&text[2..]).unwrap(), addr);
+        assert_eq!(Address::from_slice(addr.as_bytes()), addr);
+        assert!(Address::from_str("0x11e8").is_err());
+
+        let json = serde_json::to_string(&addr).unwrap();
+        assert_eq!(json, format!("\"{}\"", text));
+        assert_eq!(serde_json::from_str::<Address>(&json).unwrap(), addr);
+        let bytes = bincode::serialize(&addr).unwrap();
+        assert_eq!(bincode::deserialize::<Address>(&bytes).unwrap(), addr);
+    }
+}
@@ -0,0 +1,144 @@
+//! Admission of virtual nodes stored on this node by others.
+//!
+//! A node may not want to host every content published to it, a [StoreAdmission] set by
+//! [MessageHandler::with_store_admission](crate::message::MessageHandler::with_store_admission)
+//! is asked for each virtual node of [StoreVNode](crate::message::StoreVNode) which would be
+//! stored locally. Rejected ones are not stored, and reported to publisher by
+//! [StoreVNodeDenied](crate::message::StoreVNodeDenied), so it stops retrying them.
+//!
+//! Virtual nodes handed over by predecessor with
+//! [SyncVNodeWithSuccessor](crate::message::SyncVNodeWithSuccessor) are asked too, with the
+//! predecessor as publisher, and rejected ones are dropped. Publisher is always signer of the
+//! message, never taken from its relay path. Virtual nodes stored by this node itself are not
+//! asked.
+//!
+//! Offers of connecting this node relayed over DHT can be refused too, by an [OfferAdmissionFn]
+//! set by [with_offer_admission](crate::message::MessageHandler::with_offer_admission), which
+//! is asked with the peer relaying each offer to this node.
+use async_trait::async_trait;
+
+use crate::dht::vnode::VNodeType;
+use crate::dht::vnode::VirtualNode;
+use crate::dht::Did;
+
+/// Decides if a virtual node published by another node is stored here.
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+pub trait StoreAdmission {
+    /// Ok if `vnode` published by `publisher` can be stored, or reason of rejecting it,
+    /// which is sent to publisher. DHT is not locked while it's asked.
+    async fn admit(&self, vnode: &VirtualNode, publisher: Did) -> Result<(), String>;
+}
+
+#[cfg(not(feature = "wasm"))]
+pub type StoreAdmissionFn = Box<dyn StoreAdmission + Send + Sync>;
+
+#[cfg(feature = "wasm")]
+pub type StoreAdmissionFn = Box<dyn StoreAdmission>;
+
+/// Decides if an offer relayed by the peer is answered.
+#[cfg(not(feature = "wasm"))]
+pub type OfferAdmissionFn = Box<dyn Fn(Did) -> bool + Send + Sync>;
+
+#[cfg(feature = "wasm")]
+pub type OfferAdmissionFn = Box<dyn Fn(Did) -> bool>;
+
+/// Rejects virtual nodes by kind, size or publisher. Default policy admits everything.
+#[derive(Debug, Clone, Default)]
+pub struct AdmissionPolicy {
+    /// Only kinds stored, if it's set.
+    pub allow_kinds: Option<Vec<VNodeType>>,
+    /// Kinds never stored.
+    pub deny_kinds: Vec<VNodeType>,
+    /// Largest size stored, in bytes of encoded data, see [VirtualNode::size].
+    pub max_size: Option<usize>,
+    /// Publishers never stored for.
+    pub deny_publishers: Vec<Did>,
+}
+
+/// Kinds of virtual nodes keeping ring and identities working, all others are of applications.
+pub const RING_KINDS: &[VNodeType] = &[
+    VNodeType::SubRing,
+    VNodeType::Manifest,
+    VNodeType::Pubkey,
+    VNodeType::Rotation,
+];
+
+impl AdmissionPolicy {
+    /// Stores only [RING_KINDS], for seeds which are public entry points of a network, and
+    /// should not host its content, including kinds added later.
+    pub fn seed() -> Self {
+        Self {
+            allow_kinds: Some(RING_KINDS.to_vec()),
+            ..Default::default()
+        }
+    }
+
+    fn check(&self, vnode: &VirtualNode, publisher: Did) -> Result<(), String> {
+        let allowed = self
+            .allow_kinds
+            .as_ref()
+            .map_or(true, |kinds| kinds.contains(&vnode.kind));
+        if !allowed || self.deny_kinds.contains(&vnode.kind) {
+            return Err(format!("{:?} is not stored", vnode.kind));
+        }
+        if let Some(max) = self.max_size.filter(|max| vnode.size() > *max) {
+            return Err(format!("larger than {} bytes", max));
+        }
+        if self.deny_publishers.contains(&publisher) {
+            return Err("publisher is denied".to_owned());
+        }
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl StoreAdmission for AdmissionPolicy {
+    async fn admit(&self, vnode: &VirtualNode, publisher: Did) -> Result<(), String> {
+        self.check(vnode, publisher)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    #[test]
+    fn test_admission_policy() {
+        let publisher: Did = SecretKey::random().address().into();
+        let vnode: VirtualNode = "hello".to_owned().try_into().unwrap();
+        assert!(AdmissionPolicy::default().check(&vnode, publisher).is_ok());
+
+        let policy = AdmissionPolicy {
+            deny_kinds: vec![VNodeType::Inbox],
+            max_size: Some(vnode.size()),
+            ..Default::default()
+        };
+        assert!(policy.check(&vnode, publisher).is_ok());
+
+        let large: VirtualNode = "hello world".to_owned().try_into().unwrap();
+        assert!(policy.check(&large, publisher).is_err());
+        let mut inbox = vnode.clone();
+        inbox.kind = VNodeType::Inbox;
+        assert!(policy.check(&inbox, publisher).is_err());
+
+        let policy = AdmissionPolicy {
+            deny_publishers: vec![publisher],
+            ..Default::default()
+        };
+        assert!(policy.check(&vnode, publisher).is_err());
+
+        let seed = AdmissionPolicy::seed();
+        assert!(seed.check(&vnode, publisher).is_err());
+        let mut manifest = vnode.clone();
+        manifest.kind = VNodeType::Manifest;
+        assert!(seed.check(&manifest, publisher).is_ok());
+        for kind in [VNodeType::Topic, VNodeType::Erasure, VNodeType::Presence] {
+            let mut app = vnode.clone();
+            app.kind = kind;
+            assert!(seed.check(&app, publisher).is_err());
+        }
+    }
+}
@@ -0,0 +1,280 @@
+//! Audit of virtual nodes stored by others, a lightweight proof of storage.
+//!
+//! Stabilization picks a virtual node placed on another node every round, see
+//! [crate::placement], and sends [ChallengeVNode](crate::message::ChallengeVNode) with a
+//! random nonce and [digest] of one data item of it to the node storing it, which answers
+//! [ChallengeVNodeReport](crate::message::ChallengeVNodeReport) with [proof] of that item and
+//! the nonce. Publisher checks it against its own copy of the item, so a node claiming to store
+//! data but dropped it is detected. A single item is proved rather than all data, since the
+//! node storing a virtual node merges data of other publishers into it.
+//!
+//! Results are kept by [StorageAudit] as [AuditRecord] of each node, whose
+//! [AuditRecord::reputation] applications use to tell nodes to trust with their data.
+//! Challenges not answered in [CHALLENGE_TIMEOUT_MS] count as failures, and virtual nodes whose
+//! challenge failed are stored again, see [StorageAudit::take_failed].
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use dashmap::DashMap;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::dht::Did;
+use crate::message::Encoded;
+use crate::utils;
+
+/// Challenge fails if it's not answered in this long, in milliseconds.
+pub const CHALLENGE_TIMEOUT_MS: u128 = 10 * 1000;
+/// Challenges waiting for answer at most, new ones are not sent if it's full.
+pub const MAX_PENDING_CHALLENGES: usize = 1024;
+
+/// Hex of `sha256(data || nonce)`, data is encoded data items in order, and nonce is big
+/// endian.
+pub fn proof(data: &[Encoded], nonce: u64) -> String {
+    let mut hasher = Sha256::new();
+    for d in data {
+        hasher.update(d.as_bytes());
+    }
+    hasher.update(nonce.to_be_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Hex of `sha256(item)`, names a data item of a virtual node in a challenge.
+pub fn digest(item: &Encoded) -> String {
+    hex::encode(Sha256::digest(item.as_bytes()))
+}
+
+/// [proof] of the item of `data` named by `digest`, None if there is no such item.
+pub fn prove_item(data: &[Encoded], digest: &str, nonce: u64) -> Option<String> {
+    data.iter()
+        .find(|d| self::digest(d) == digest)
+        .map(|d| proof(std::slice::from_ref(d), nonce))
+}
+
+/// Results of challenges of a node.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AuditRecord {
+    pub passed: u32,
+    /// Wrong proofs, missing data and unanswered challenges.
+    pub failed: u32,
+    /// When the last failure happened, in milliseconds since epoch.
+    pub last_failure_ms: Option<u128>,
+}
+
+impl AuditRecord {
+    /// Share of challenges passed, counting one passed and one failed beforehand, so a node
+    /// never challenged has 0.5 and a few results don't make it 0 or 1 at once.
+    pub fn reputation(&self) -> f64 {
+        (self.passed as f64 + 1.0) / (self.passed as f64 + self.failed as f64 + 2.0)
+    }
+}
+
+#[derive(Debug)]
+struct Challenge {
+    stored_by: Did,
+    vnode: Did,
+    expected: String,
+    sent_ms: u128,
+}
+
+/// Pending challenges and records of nodes, see module doc.
+#[derive(Debug, Default)]
+pub struct StorageAudit {
+    pending: Mutex<HashMap<u64, Challenge>>,
+    records: DashMap<Did, AuditRecord>,
+    /// Virtual nodes whose challenge failed, not stored again yet.
+    failed: Mutex<Vec<Did>>,
+}
+
+impl StorageAudit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a challenge of `item` of virtual node `vnode` stored by `stored_by`, returns its
+    /// nonce, or None if too many challenges are pending.
+    pub fn challenge(&self, stored_by: Did, vnode: Did, item: &Encoded) -> Option<u64> {
+        self.challenge_at(
+            stored_by,
+            vnode,
+            item,
+            rand::random(),
+            utils::get_epoch_ms(),
+        )
+    }
+
+    fn challenge_at(
+        &self,
+        stored_by: Did,
+        vnode: Did,
+        item: &Encoded,
+        nonce: u64,
+        now: u128,
+    ) -> Option<u64> {
+        self.expire_at(now);
+        let mut pending = self.pending.lock().ok()?;
+        if pending.len() >= MAX_PENDING_CHALLENGES {
+            return None;
+        }
+        pending.insert(nonce, Challenge {
+            stored_by,
+            vnode,
+            expected: proof(std::slice::from_ref(item), nonce),
+            sent_ms: now,
+        });
+        Some(nonce)
+    }
+
+    /// Check answer of challenge `nonce` by `stored_by`, `proof` is None if it doesn't have
+    /// the item. Returns if it's passed, or None if there is no such challenge pending.
+    pub fn answer(&self, stored_by: Did, nonce: u64, proof: Option<&str>) -> Option<bool> {
+        self.answer_at(stored_by, nonce, proof, utils::get_epoch_ms())
+    }
+
+    fn answer_at(
+        &self,
+        stored_by: Did,
+        nonce: u64,
+        proof: Option<&str>,
+        now: u128,
+    ) -> Option<bool> {
+        let challenge = {
+            let mut pending = self.pending.lock().ok()?;
+            // answer of another node can't settle the challenge
+            if pending.get(&nonce)?.stored_by != stored_by {
+                return None;
+            }
+            pending.remove(&nonce)?
+        };
+        let passed = proof == Some(challenge.expected.as_str());
+        self.record_result(&challenge, passed, now);
+        Some(passed)
+    }
+
+    fn record_result(&self, challenge: &Challenge, passed: bool, now: u128) {
+        let mut r = self.records.entry(challenge.stored_by).or_default();
+        if passed {
+            r.passed = r.passed.saturating_add(1);
+            return;
+        }
+        r.failed = r.failed.saturating_add(1);
+        r.last_failure_ms = Some(now);
+        drop(r);
+        if let Ok(mut failed) = self.failed.lock() {
+            if failed.len() < MAX_PENDING_CHALLENGES && !failed.contains(&challenge.vnode) {
+                failed.push(challenge.vnode);
+            }
+        }
+    }
+
+    /// Virtual nodes whose challenge failed since last call, to store again.
+    pub fn take_failed(&self) -> Vec<Did> {
+        self.expire_at(utils::get_epoch_ms());
+        self.failed
+            .lock()
+            .map(|mut failed| std::mem::take(&mut *failed))
+            .unwrap_or_default()
+    }
+
+    /// Challenges timed out are failed.
+    fn expire_at(&self, now: u128) {
+        let expired = match self.pending.lock() {
+            Ok(mut pending) => {
+                let expired = pending
+                    .iter()
+                    .filter(|(_, c)| now.saturating_sub(c.sent_ms) >= CHALLENGE_TIMEOUT_MS)
+                    .map(|(nonce, _)| *nonce)
+                    .collect::<Vec<_>>();
+                expired
+                    .into_iter()
+                    .filter_map(|nonce| pending.remove(&nonce))
+                    .collect::<Vec<_>>()
+            }
+            Err(_) => return,
+        };
+        for c in expired {
+            tracing::debug!(node = ?c.stored_by, "storage challenge timed out");
+            self.record_result(&c, false, now);
+        }
+    }
+
+    /// Record of `did`, None if it's never challenged.
+    pub fn record(&self, did: Did) -> Option<AuditRecord> {
+        self.expire_at(utils::get_epoch_ms());
+        self.records.get(&did).map(|r| *r)
+    }
+
+    /// [AuditRecord::reputation] of `did`, 0.5 if it's never challenged.
+    pub fn reputation(&self, did: Did) -> f64 {
+        self.record(did).unwrap_or_default().reputation()
+    }
+
+    /// Records of all challenged nodes.
+    pub fn records(&self) -> Vec<(Did, AuditRecord)> {
+        self.expire_at(utils::get_epoch_ms());
+        self.records
+            .iter()
+            .map(|kv| (*kv.key(), *kv.value()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht::vnode::VirtualNode;
+    use crate::ecc::SecretKey;
+
+    #[test]
+    fn test_storage_audit() {
+        let audit = StorageAudit::new();
+        let node: Did = SecretKey::random().address().into();
+        let other: Did = SecretKey::random().address().into();
+        let vnode: VirtualNode = "hello".to_owned().try_into().unwrap();
+        let item = &vnode.data[0];
+        // data of another publisher merged into it by the node storing it
+        let other_vnode: VirtualNode = "world".to_owned().try_into().unwrap();
+        let merged = vec![other_vnode.data[0].clone(), item.clone()];
+        assert_eq!(
+            prove_item(&merged, &digest(item), 5),
+            prove_item(&vnode.data, &digest(item), 5)
+        );
+        assert_eq!(prove_item(&other_vnode.data, &digest(item), 5), None);
+
+        let id = vnode.did();
+        let nonce = audit.challenge_at(node, id, item, 1, 1_000).unwrap();
+        let good = prove_item(&merged, &digest(item), nonce).unwrap();
+        assert_ne!(good, proof(&merged, nonce));
+        // answer of another node, or of unknown challenge, is ignored
+        assert_eq!(audit.answer_at(other, nonce, Some(&good), 1_100), None);
+        assert_eq!(audit.answer_at(node, 2, Some(&good), 1_100), None);
+        assert_eq!(audit.answer_at(node, nonce, Some(&good), 1_100), Some(true));
+        assert_eq!(audit.answer_at(node, nonce, Some(&good), 1_100), None);
+
+        // data dropped
+        audit.challenge_at(node, id, item, 2, 2_000).unwrap();
+        assert_eq!(audit.answer_at(node, 2, None, 2_100), Some(false));
+
+        // not answered in time
+        audit.challenge_at(node, id, item, 3, 3_000).unwrap();
+        audit.expire_at(3_000 + CHALLENGE_TIMEOUT_MS);
+        assert_eq!(audit.answer_at(node, 3, Some("late"), 20_000), None);
+
+        assert_eq!(
+            audit.record(node),
+            Some(AuditRecord {
+                passed: 1,
+                failed: 2,
+                last_failure_ms: Some(3_000 + CHALLENGE_TIMEOUT_MS),
+            })
+        );
+        assert_eq!(audit.record(other), None);
+        assert_eq!(audit.reputation(node), 0.4);
+        assert_eq!(audit.reputation(other), 0.5);
+        // failed twice, stored again once
+        assert_eq!(audit.take_failed(), vec![id]);
+        assert!(audit.take_failed().is_empty());
+    }
+}
@@ -0,0 +1,150 @@
+//! Packet capture of swarm, for debugging routing problems of deployed networks.
+//! Every inbound and outbound [MessagePayload] is recorded into a fixed size ring buffer,
+//! the oldest records are dropped when it's full.
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::address::Address;
+use crate::dht::Did;
+use crate::message::MessagePayload;
+use crate::utils;
+
+/// Direction of a captured payload.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// Summary of a payload seen by swarm.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CapturedPayload {
+    pub direction: Direction,
+    /// Remote peer which sent or receives the payload.
+    pub peer: Address,
+    pub tx_id: String,
+    /// Variant name of message, eg: `ConnectNodeSend`.
+    pub message_type: String,
+    /// Size of encoded payload, in bytes.
+    pub size: usize,
+    pub path: Vec<Did>,
+    pub next_hop: Option<Did>,
+    pub destination: Did,
+    /// When payload is captured, in milliseconds since epoch.
+    pub ts_ms: u128,
+    /// Time since payload is signed by sender, in milliseconds.
+    pub age_ms: u128,
+}
+
+/// Ring buffer of [CapturedPayload].
+pub struct PacketCapture {
+    capacity: usize,
+    records: Mutex<VecDeque<CapturedPayload>>,
+}
+
+/// Variant name of an externally tagged enum, eg: `{"JoinDHT": {..}}` gives `JoinDHT`.
+fn message_type<T: Serialize>(data: &T) -> String {
+    match serde_json::to_value(data) {
+        Ok(serde_json::Value::Object(m)) if m.len() == 1 => m.keys().next().cloned(),
+        Ok(serde_json::Value::String(s)) => Some(s),
+        _ => None,
+    }
+    .unwrap_or_else(|| "Unknown".to_owned())
+}
+
+impl PacketCapture {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Record a payload of `size` bytes, sent to or received from `peer`.
+    pub fn record<T: Serialize>(
+        &self,
+        direction: Direction,
+        peer: Address,
+        payload: &MessagePayload<T>,
+        size: usize,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        let ts_ms = utils::get_epoch_ms();
+        let record = CapturedPayload {
+            direction,
+            peer,
+            tx_id: payload.tx_id.inner(),
+            message_type: message_type(&payload.data),
+            size,
+            path: payload.relay.path.clone(),
+            next_hop: payload.relay.next_hop,
+            destination: payload.relay.destination,
+            ts_ms,
+            age_ms: ts_ms.saturating_sub(payload.verification.ts_ms),
+        };
+        if let Ok(mut records) = self.records.lock() {
+            while records.len() >= self.capacity {
+                records.pop_front();
+            }
+            records.push_back(record);
+        }
+    }
+
+    /// All records, oldest first, and clear the buffer if `clear` is set.
+    pub fn records(&self, clear: bool) -> Vec<CapturedPayload> {
+        match self.records.lock() {
+            Ok(mut records) if clear => records.drain(..).collect(),
+            Ok(records) => records.iter().cloned().collect(),
+            Err(_) => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+    use crate::message::Message;
+    use crate::message::MessageRelay;
+    use crate::message::OriginVerificationGen;
+    use crate::message::RelayMethod;
+    use crate::session::SessionManager;
+
+    fn new_payload() -> MessagePayload<Message> {
+        let key = SecretKey::random();
+        let session = SessionManager::new_with_seckey(&key).unwrap();
+        let did: Did = key.address().into();
+        MessagePayload::new(
+            Message::LeaveDHT(crate::message::LeaveDHT { id: did }),
+            &session,
+            OriginVerificationGen::Origin,
+            MessageRelay::new(RelayMethod::SEND, vec![did], None, None, did),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_capture_ring_buffer() {
+        let capture = PacketCapture::new(2);
+        let payload = new_payload();
+        for size in 1..=3 {
+            capture.record(Direction::Outbound, payload.addr, &payload, size);
+        }
+        let records = capture.records(false);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].size, 2);
+        assert_eq!(records[1].message_type, "LeaveDHT");
+        assert_eq!(capture.records(true).len(), 2);
+        assert!(capture.records(false).is_empty());
+    }
+}
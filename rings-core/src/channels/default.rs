@@ -25,6 +25,15 @@ impl<T: Send> Channel<T> for AcChannel<T> {
         }
     }
 
+    /// Senders wait for room when it's full.
+    fn with_capacity(capacity: usize) -> Self {
+        let (tx, rx) = ac::bounded(capacity.max(1));
+        Self {
+            sender: tx,
+            receiver: rx,
+        }
+    }
+
     fn sender(&self) -> Self::Sender {
         self.sender.clone()
     }
@@ -4,13 +4,28 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use futures::channel::mpsc;
 use futures::lock::Mutex;
+use futures::SinkExt;
 
 use crate::err::Error;
 use crate::err::Result;
 use crate::types::channel::Channel;
 
-type Sender<T> = Arc<Mutex<mpsc::Sender<T>>>;
-type Receiver<T> = Arc<Mutex<mpsc::Receiver<T>>>;
+type Sender<T> = Arc<Mutex<Tx<T>>>;
+type Receiver<T> = Arc<Mutex<Rx<T>>>;
+
+/// Sender of an unbounded channel, or of a bounded one.
+#[derive(Debug)]
+pub enum Tx<T> {
+    Unbounded(mpsc::UnboundedSender<T>),
+    Bounded(mpsc::Sender<T>),
+}
+
+/// Receiver of [Tx].
+#[derive(Debug)]
+pub enum Rx<T> {
+    Unbounded(mpsc::UnboundedReceiver<T>),
+    Bounded(mpsc::Receiver<T>),
+}
 
 #[derive(Debug)]
 pub struct CbChannel<T> {
@@ -24,10 +39,19 @@ impl<T: Send> Channel<T> for CbChannel<T> {
     type Receiver = Receiver<T>;
 
     fn new() -> Self {
-        let (tx, rx) = mpsc::channel(64);
+        let (tx, rx) = mpsc::unbounded();
+        Self {
+            sender: Arc::new(Mutex::new(Tx::Unbounded(tx))),
+            receiver: Arc::new(Mutex::new(Rx::Unbounded(rx))),
+        }
+    }
+
+    /// Senders wait for room when it's full.
+    fn with_capacity(capacity: usize) -> Self {
+        let (tx, rx) = mpsc::channel(capacity.max(1));
         Self {
-            sender: Arc::new(Mutex::new(tx)),
-            receiver: Arc::new(Mutex::new(rx)),
+            sender: Arc::new(Mutex::new(Tx::Bounded(tx))),
+            receiver: Arc::new(Mutex::new(Rx::Bounded(rx))),
         }
     }
 
@@ -41,15 +65,23 @@ impl<T: Send> Channel<T> for CbChannel<T> {
 
     async fn send(sender: &Self::Sender, msg: T) -> Result<()> {
         let mut sender = sender.lock().await;
-        match sender.try_send(msg) {
-            Ok(()) => Ok(()),
-            Err(_) => Err(Error::ChannelSendMessageFailed),
+        let sent = match &mut *sender {
+            Tx::Unbounded(tx) => tx.unbounded_send(msg).is_ok(),
+            Tx::Bounded(tx) => tx.send(msg).await.is_ok(),
+        };
+        match sent {
+            true => Ok(()),
+            false => Err(Error::ChannelSendMessageFailed),
         }
     }
 
     async fn recv(receiver: &Self::Receiver) -> Result<Option<T>> {
         let mut receiver = receiver.lock().await;
-        match receiver.try_next() {
+        let next = match &mut *receiver {
+            Rx::Unbounded(rx) => rx.try_next(),
+            Rx::Bounded(rx) => rx.try_next(),
+        };
+        match next {
             Err(_) => Err(Error::ChannelRecvMessageFailed),
             Ok(Some(x)) => Ok(Some(x)),
             // when channel is closed and no messages left in the queue
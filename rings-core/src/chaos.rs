@@ -0,0 +1,143 @@
+//! Fault injection of swarm, for testing resilience of handlers and stabilization on live
+//! test networks. Only compiled with feature `chaos`.
+//!
+//! Faults are applied to outbound payloads by [crate::swarm::Swarm], a payload may be dropped,
+//! delayed, sent with a corrupted relay, or the transport it's sent on may be killed. Faults
+//! are set at runtime by [FaultInjector::set], all of them are disabled by default.
+use std::sync::Mutex;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::dht::Did;
+use crate::ecc::SecretKey;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::MessageRelay;
+
+/// Probabilities of faults, in range `0.0..=1.0`, and delay of payloads.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct FaultConfig {
+    /// Drop payloads silently.
+    pub drop_ratio: f64,
+    /// Delay every payload before sending, in milliseconds.
+    pub delay_ms: u64,
+    /// Send payloads with a random destination in relay.
+    pub corrupt_ratio: f64,
+    /// Close transport instead of sending payload.
+    pub kill_ratio: f64,
+}
+
+impl FaultConfig {
+    pub fn validate(&self) -> Result<()> {
+        for (name, ratio) in [
+            ("drop_ratio", self.drop_ratio),
+            ("corrupt_ratio", self.corrupt_ratio),
+            ("kill_ratio", self.kill_ratio),
+        ] {
+            if !(0.0..=1.0).contains(&ratio) {
+                return Err(Error::InvalidFaultConfig(format!(
+                    "{} should be in 0.0..=1.0, got {}",
+                    name, ratio
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fault applied to an outbound payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    Drop,
+    Corrupt,
+    Kill,
+}
+
+/// Faults of swarm, see [FaultConfig].
+#[derive(Debug, Default)]
+pub struct FaultInjector {
+    config: Mutex<FaultConfig>,
+}
+
+impl FaultInjector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace faults, all of them are disabled by [FaultConfig::default].
+    pub fn set(&self, config: FaultConfig) -> Result<()> {
+        config.validate()?;
+        tracing::warn!(config = ?config, "fault injection changed");
+        if let Ok(mut c) = self.config.lock() {
+            *c = config;
+        }
+        Ok(())
+    }
+
+    pub fn config(&self) -> FaultConfig {
+        self.config.lock().map(|c| c.clone()).unwrap_or_default()
+    }
+
+    /// Delay of payloads, in milliseconds.
+    pub fn delay_ms(&self) -> u64 {
+        self.config.lock().map(|c| c.delay_ms).unwrap_or(0)
+    }
+
+    /// Pick a fault for next payload, with random number `r` in `0.0..1.0`.
+    fn pick_with(&self, r: f64) -> Option<Fault> {
+        let c = self.config();
+        if r < c.kill_ratio {
+            Some(Fault::Kill)
+        } else if r < c.kill_ratio + c.drop_ratio {
+            Some(Fault::Drop)
+        } else if r < c.kill_ratio + c.drop_ratio + c.corrupt_ratio {
+            Some(Fault::Corrupt)
+        } else {
+            None
+        }
+    }
+
+    /// Pick a fault for next payload, None if it's sent as is.
+    pub fn pick(&self) -> Option<Fault> {
+        self.pick_with(rand::random::<f64>())
+    }
+
+    /// Send relay to a random destination.
+    pub fn corrupt(relay: &mut MessageRelay) {
+        relay.destination = Did::from(SecretKey::random().address());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_fault() {
+        let faults = FaultInjector::new();
+        assert_eq!(faults.pick_with(0.0), None);
+        faults
+            .set(FaultConfig {
+                drop_ratio: 0.2,
+                corrupt_ratio: 0.2,
+                kill_ratio: 0.1,
+                delay_ms: 10,
+            })
+            .unwrap();
+        assert_eq!(faults.pick_with(0.05), Some(Fault::Kill));
+        assert_eq!(faults.pick_with(0.2), Some(Fault::Drop));
+        assert_eq!(faults.pick_with(0.4), Some(Fault::Corrupt));
+        assert_eq!(faults.pick_with(0.6), None);
+        assert_eq!(faults.delay_ms(), 10);
+
+        assert!(faults
+            .set(FaultConfig {
+                drop_ratio: 1.5,
+                ..Default::default()
+            })
+            .is_err());
+        assert_eq!(faults.config().drop_ratio, 0.2);
+    }
+}
@@ -0,0 +1,173 @@
+//! Estimation of clock offsets of peers, so skewed clocks don't expire their payloads.
+//!
+//! Payloads are signed with `ts_ms` of their senders, and expire `ttl_ms` after it by clock
+//! of receiver. A peer whose clock is behind by more than `ttl_ms` looks like sending only
+//! expired payloads. [ClockSync] estimates how far clock of every peer is ahead from
+//! timestamps of its verified payloads, and expiry is evaluated by clock of signer. Offsets
+//! beyond the tolerated skew are clamped, so enough skew still expires payloads.
+//!
+//! A sample `ts_ms - now` is offset of peer minus delay of payload, the largest of recent
+//! samples has the least delay, and is taken as the offset.
+use std::collections::VecDeque;
+
+use dashmap::DashMap;
+
+use crate::dht::Did;
+#[cfg(not(feature = "wasm"))]
+use crate::err::Error;
+#[cfg(not(feature = "wasm"))]
+use crate::err::Result;
+
+/// Clock skew of peers tolerated by default, in milliseconds.
+pub const DEFAULT_MAX_CLOCK_SKEW_MS: u64 = 5 * 60 * 1000;
+/// Recent samples kept for each peer.
+const SAMPLES: usize = 16;
+
+/// Clock offsets of peers, see module doc.
+#[derive(Debug)]
+pub struct ClockSync {
+    max_skew_ms: u64,
+    samples: DashMap<Did, VecDeque<i128>>,
+}
+
+impl Default for ClockSync {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_CLOCK_SKEW_MS)
+    }
+}
+
+impl ClockSync {
+    /// Tolerate offsets up to `max_skew_ms`, 0 to evaluate expiry by local clock only.
+    pub fn new(max_skew_ms: u64) -> Self {
+        Self {
+            max_skew_ms,
+            samples: DashMap::new(),
+        }
+    }
+
+    pub fn max_skew_ms(&self) -> u64 {
+        self.max_skew_ms
+    }
+
+    /// `peer` signed a payload at `ts_ms`, which is verified at local time `now`.
+    pub fn observe(&self, peer: Did, ts_ms: u128, now: u128) {
+        if self.max_skew_ms == 0 {
+            return;
+        }
+        let sample = ts_ms as i128 - now as i128;
+        if sample.unsigned_abs() > self.max_skew_ms as u128 {
+            tracing::debug!(peer = ?peer, offset_ms = sample, "clock skew of peer over tolerance");
+        }
+        let mut samples = self.samples.entry(peer).or_default();
+        if samples.len() >= SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+
+    /// How far clock of `peer` is ahead of local one in milliseconds, negative if it's
+    /// behind, clamped to tolerated skew. 0 for peers never observed.
+    pub fn offset(&self, peer: Did) -> i128 {
+        self.samples
+            .get(&peer)
+            .map_or(0, |s| self.estimate(s.value()))
+    }
+
+    /// Offsets of all observed peers, see [ClockSync::offset].
+    pub fn offsets(&self) -> Vec<(Did, i128)> {
+        self.samples
+            .iter()
+            .map(|e| (*e.key(), self.estimate(e.value())))
+            .collect()
+    }
+
+    fn estimate(&self, samples: &VecDeque<i128>) -> i128 {
+        let max = self.max_skew_ms as i128;
+        samples.iter().max().map_or(0, |o| (*o).clamp(-max, max))
+    }
+
+    pub fn forget(&self, peer: Did) {
+        self.samples.remove(&peer);
+    }
+}
+
+/// Seconds from 1900, epoch of NTP, to 1970.
+#[cfg(not(feature = "wasm"))]
+const NTP_UNIX_OFFSET_SECS: u64 = 2_208_988_800;
+
+/// How far local clock is ahead of SNTP `server`, like `pool.ntp.org:123`, in milliseconds.
+/// It's blocking, for a check at startup.
+#[cfg(not(feature = "wasm"))]
+pub fn sntp_offset(server: &str, timeout_ms: u64) -> Result<i128> {
+    use std::net::ToSocketAddrs;
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    let ntp_err = |e: std::io::Error| Error::ClockCheck(e.to_string());
+    let server = server
+        .to_socket_addrs()
+        .map_err(ntp_err)?
+        .next()
+        .ok_or_else(|| Error::ClockCheck(format!("no address of {}", server)))?;
+    let bind = if server.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(bind).map_err(ntp_err)?;
+    socket
+        .set_read_timeout(Some(Duration::from_millis(timeout_ms)))
+        .map_err(ntp_err)?;
+    // version 4, mode client
+    let mut request = [0u8; 48];
+    request[0] = 0x23;
+    let sent = crate::utils::get_epoch_ms();
+    socket.send_to(&request, server).map_err(ntp_err)?;
+    let mut response = [0u8; 48];
+    let len = socket.recv(&mut response).map_err(ntp_err)?;
+    let received = crate::utils::get_epoch_ms();
+    if len < 48 {
+        return Err(Error::ClockCheck("short SNTP response".to_owned()));
+    }
+    // transmit timestamp of server, taken as the middle of round trip
+    let secs = u32::from_be_bytes([response[40], response[41], response[42], response[43]]);
+    let frac = u32::from_be_bytes([response[44], response[45], response[46], response[47]]);
+    let unix_secs = (secs as u64)
+        .checked_sub(NTP_UNIX_OFFSET_SECS)
+        .ok_or_else(|| Error::ClockCheck("invalid SNTP timestamp".to_owned()))?;
+    let server_ms = unix_secs as i128 * 1000 + ((frac as u64 * 1000) >> 32) as i128;
+    let local_ms = (sent + received) as i128 / 2;
+    Ok(local_ms - server_ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    #[test]
+    fn test_clock_sync() {
+        let clock = ClockSync::new(10_000);
+        let peer: Did = SecretKey::random().address().into();
+        assert_eq!(clock.offset(peer), 0);
+
+        // peer is 3s behind, payloads take 100ms to 300ms
+        clock.observe(peer, 10_000, 13_300);
+        clock.observe(peer, 20_000, 23_100);
+        clock.observe(peer, 30_000, 33_200);
+        assert_eq!(clock.offset(peer), -3_100);
+
+        // skew over tolerance is clamped
+        let far: Did = SecretKey::random().address().into();
+        clock.observe(far, 100_000, 50_000);
+        assert_eq!(clock.offset(far), 10_000);
+        assert_eq!(clock.offsets().len(), 2);
+
+        clock.forget(far);
+        assert_eq!(clock.offset(far), 0);
+
+        let strict = ClockSync::new(0);
+        strict.observe(peer, 10_000, 13_300);
+        assert_eq!(strict.offset(peer), 0);
+    }
+}
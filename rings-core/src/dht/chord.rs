@@ -1,4 +1,5 @@
 #![warn(missing_docs)]
+use std::collections::HashSet;
 use std::sync::Arc;
 
 use num_bigint::BigUint;
@@ -6,6 +7,9 @@ use serde::Deserialize;
 use serde::Serialize;
 
 use super::did::BiasId;
+use super::routing::ChordPolicy;
+use super::routing::RouteCache;
+use super::routing::RoutingPolicy;
 use super::successor::Successor;
 use super::types::Chord;
 use super::types::ChordStablize;
@@ -88,6 +92,25 @@ impl PeerRingAction {
     }
 }
 
+/// Serializable state of [PeerRing], exported for diagnostics and test reproduction.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerRingSnapshot {
+    /// Id of exported ring
+    pub id: Did,
+    /// Successor list
+    pub successors: Vec<Did>,
+    /// Predecessor
+    pub predecessor: Option<Did>,
+    /// Finger table, with empty entries
+    pub finger: Vec<Option<Did>>,
+    /// Next index of finger to fix
+    pub fix_finger_index: u8,
+    /// Relay capable nodes
+    pub relays: Vec<Did>,
+    /// Addresses of stored virtual nodes, data is not exported
+    pub storage_keys: Vec<Did>,
+}
+
 /// Implementation of PeerRing
 #[derive(Clone, Debug)]
 pub struct PeerRing {
@@ -106,6 +129,12 @@ pub struct PeerRing {
     pub storage: Arc<MemStorage<Did, VirtualNode>>,
     /// LocalCache
     pub cache: Arc<MemStorage<Did, VirtualNode>>,
+    /// Connected nodes which advertised themselves as relay capable
+    pub relays: HashSet<Did>,
+    /// Policy of picking next hop, [ChordPolicy] by default
+    pub routing: Arc<dyn RoutingPolicy>,
+    /// Routes to nodes observed recently, consulted by [PeerRing::find_route]
+    pub routes: Option<Arc<RouteCache>>,
 }
 
 impl PeerRing {
@@ -125,6 +154,9 @@ impl PeerRing {
             fix_finger_index: 0,
             storage: Arc::new(MemStorage::<Did, VirtualNode>::new()),
             cache: Arc::new(MemStorage::<Did, VirtualNode>::new()),
+            relays: HashSet::new(),
+            routing: Arc::new(ChordPolicy),
+            routes: None,
         }
     }
 
@@ -139,6 +171,48 @@ impl PeerRing {
             cache: Arc::new(MemStorage::<Did, VirtualNode>::new()),
             id,
             fix_finger_index: 0,
+            relays: HashSet::new(),
+            routing: Arc::new(ChordPolicy),
+            routes: None,
+        }
+    }
+
+    /// Use another routing policy, see [crate::dht::routing].
+    pub fn with_routing_policy(mut self, routing: Arc<dyn RoutingPolicy>) -> Self {
+        self.routing = routing;
+        self
+    }
+
+    /// Consult `routes` before finger table, to reach nodes contacted recently.
+    pub fn with_route_cache(mut self, routes: Arc<RouteCache>) -> Self {
+        self.routes = Some(routes);
+        self
+    }
+
+    /// Identifier which finger at `index` succeeds, `(n + 2^index) mod 2^160`.
+    pub fn finger_target(&self, index: u8) -> Did {
+        let did: BigUint = (BigUint::from(self.id) + BigUint::from(2u16).pow(index.into()))
+            % BigUint::from(2u16).pow(160);
+        did.into()
+    }
+
+    /// Next hop to node `id` by a route observed recently, see [RouteCache].
+    pub fn cached_route(&self, id: Did) -> Option<Did> {
+        self.routes
+            .as_ref()
+            .and_then(|r| r.get(id))
+            .filter(|n| *n != self.id)
+    }
+
+    /// Find next hop to node `id` itself, rather than successor of `id`, by a cached route if
+    /// there is one, otherwise same as [Chord::find_successor].
+    pub fn find_route(&self, id: Did) -> Result<PeerRingAction> {
+        match self.cached_route(id) {
+            Some(next) => Ok(PeerRingAction::RemoteAction(
+                next,
+                RemoteAction::FindSuccessor(id),
+            )),
+            None => self.find_successor(id),
         }
     }
 
@@ -153,6 +227,7 @@ impl PeerRing {
     pub fn remove(&mut self, id: Did) {
         self.finger.remove(id);
         self.successor.remove(id);
+        self.relays.remove(&id);
         if self.successor.is_none() {
             if let Some(x) = self.first() {
                 self.successor.update(x);
@@ -169,6 +244,107 @@ impl PeerRing {
     pub fn number_of_fingers(&self) -> usize {
         self.finger.len()
     }
+
+    /// Mark node as relay capable or not
+    pub fn set_relay(&mut self, id: Did, relay: bool) {
+        if relay && id != self.id {
+            self.relays.insert(id);
+        } else {
+            self.relays.remove(&id);
+        }
+    }
+
+    /// Pick a relay capable node in (self, target] but not in `path`, the closest one to
+    /// target wins. Forwarding to it always makes progress on the ring.
+    pub fn closest_relay(&self, target: Did, path: &[Did]) -> Option<Did> {
+        let bias = self.bias(target);
+        self.relays
+            .iter()
+            .filter(|r| self.bias(**r) <= bias && !path.contains(r))
+            .max_by_key(|r| self.bias(**r))
+            .copied()
+    }
+
+    /// Next hop to `target` of `next` found on DHT path, or a relay capable node not in
+    /// `path` instead if it's strictly closer to target on the ring.
+    pub fn prefer_relay(&self, target: Did, next: Did, path: &[Did]) -> Did {
+        match self.closest_relay(target, path) {
+            Some(relay) if target - relay < target - next => relay,
+            _ => next,
+        }
+    }
+
+    /// Export state of ring.
+    pub fn snapshot(&self) -> PeerRingSnapshot {
+        let mut relays = self.relays.iter().copied().collect::<Vec<_>>();
+        relays.sort();
+        let mut storage_keys = self.storage.keys();
+        storage_keys.sort();
+        PeerRingSnapshot {
+            id: self.id,
+            successors: self.successor.list(),
+            predecessor: self.predecessor,
+            finger: self.finger.list().clone(),
+            fix_finger_index: self.fix_finger_index,
+            relays,
+            storage_keys,
+        }
+    }
+
+    /// Load state exported by [PeerRing::snapshot], storage is not touched.
+    /// Snapshot of the same id is restored as is, otherwise its nodes are joined
+    /// to this ring, so the tables are rebuilt relatively to this id.
+    pub fn restore(&mut self, snapshot: &PeerRingSnapshot) {
+        self.finger = FingerTable::new(self.id, self.finger.list().len());
+        self.successor.clear();
+        self.relays.clear();
+        if snapshot.id == self.id {
+            for (i, f) in snapshot.finger.iter().enumerate() {
+                if let Some(did) = f {
+                    self.finger.set(i, did);
+                }
+            }
+            for s in &snapshot.successors {
+                self.successor.update(*s);
+            }
+            self.predecessor = snapshot.predecessor;
+            self.fix_finger_index = snapshot.fix_finger_index;
+        } else {
+            let nodes = snapshot
+                .finger
+                .iter()
+                .flatten()
+                .chain(snapshot.successors.iter())
+                .chain(std::iter::once(&snapshot.id))
+                .copied()
+                .collect::<HashSet<_>>();
+            for did in nodes {
+                self.join(did);
+            }
+            self.predecessor = None;
+            self.fix_finger_index = 0;
+        }
+        for r in &snapshot.relays {
+            self.set_relay(*r, true);
+        }
+    }
+
+    /// Fingers and successors in (self, target), the closest one to target first.
+    pub fn route_candidates(&self, target: Did) -> Vec<Did> {
+        let bias = self.bias(target);
+        let mut candidates = self
+            .finger
+            .list()
+            .iter()
+            .flatten()
+            .copied()
+            .chain(self.successor.list())
+            .filter(|c| *c != self.id && self.bias(*c) < bias)
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|c| std::cmp::Reverse(self.bias(*c)));
+        candidates.dedup();
+        candidates
+    }
 }
 
 impl Chord<PeerRingAction> for PeerRing {
@@ -200,7 +376,7 @@ impl Chord<PeerRingAction> for PeerRing {
         } else {
             // n = closest preceding node(id);
             // return n.find_successor(id);
-            match self.finger.closest(id) {
+            match self.routing.next_hop(self, id) {
                 Ok(n) => Ok(PeerRingAction::RemoteAction(
                     n,
                     RemoteAction::FindSuccessor(id),
@@ -244,10 +420,7 @@ impl ChordStablize<PeerRingAction> for PeerRing {
         if self.fix_finger_index >= 159 {
             self.fix_finger_index = 0;
         }
-        let did: BigUint = (BigUint::from(self.id)
-            + BigUint::from(2u16).pow(self.fix_finger_index.into()))
-            % BigUint::from(2u16).pow(160);
-        match self.find_successor(did.into()) {
+        match self.find_successor(self.finger_target(self.fix_finger_index)) {
             Ok(res) => match res {
                 PeerRingAction::Some(v) => {
                     self.finger.set(self.fix_finger_index as usize, &v);
@@ -324,6 +497,7 @@ impl ChordStorage<PeerRingAction> for PeerRing {
                     Ok(PeerRingAction::None)
                 }
                 None => {
+                    peer.check()?;
                     let _ = self.storage.set(&vid, peer);
                     Ok(PeerRingAction::None)
                 }
@@ -556,4 +730,84 @@ mod tests {
             did1
         );
     }
+
+    #[test]
+    fn test_closest_relay() {
+        let id = Did::from_str("0x1000000000000000000000000000000000000000").unwrap();
+        let r1 = Did::from_str("0x2000000000000000000000000000000000000000").unwrap();
+        let r2 = Did::from_str("0x3000000000000000000000000000000000000000").unwrap();
+        let target = Did::from_str("0x2800000000000000000000000000000000000000").unwrap();
+        let far = Did::from_str("0x0800000000000000000000000000000000000000").unwrap();
+
+        let mut node = PeerRing::new(id);
+        assert_eq!(node.closest_relay(target, &[]), None);
+        node.set_relay(r1, true);
+        node.set_relay(r2, true);
+        node.set_relay(id, true);
+        assert_eq!(node.closest_relay(target, &[]), Some(r1));
+        // far is behind self, so both relays can be used, r2 is closer
+        assert_eq!(node.closest_relay(far, &[]), Some(r2));
+        // nodes on path are never picked
+        assert_eq!(node.closest_relay(far, &[r2]), Some(r1));
+
+        // relay is taken only if it's strictly closer than next hop on DHT path
+        let behind = Did::from_str("0x1800000000000000000000000000000000000000").unwrap();
+        assert_eq!(node.prefer_relay(target, behind, &[]), r1);
+        assert_eq!(node.prefer_relay(target, r1, &[]), r1);
+        assert_eq!(node.prefer_relay(target, target, &[]), target);
+        assert_eq!(node.prefer_relay(target, behind, &[r1]), behind);
+
+        node.remove(r1);
+        assert_eq!(node.closest_relay(target, &[]), None);
+    }
+
+    #[test]
+    fn test_finger_target() {
+        let node =
+            PeerRing::new(Did::from_str("0x1000000000000000000000000000000000000000").unwrap());
+        assert_eq!(
+            node.finger_target(0),
+            Did::from_str("0x1000000000000000000000000000000000000001").unwrap()
+        );
+        assert_eq!(
+            node.finger_target(159),
+            Did::from_str("0x9000000000000000000000000000000000000000").unwrap()
+        );
+        // wraps around the ring
+        let node =
+            PeerRing::new(Did::from_str("0xf000000000000000000000000000000000000000").unwrap());
+        assert_eq!(
+            node.finger_target(159),
+            Did::from_str("0x7000000000000000000000000000000000000000").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let ids = (0..5)
+            .map(|_| SecretKey::random().address().into())
+            .collect::<Vec<Did>>();
+        let mut node = PeerRing::new(ids[0]);
+        for id in &ids[1..] {
+            node.join(*id);
+        }
+        node.set_relay(ids[1], true);
+        let snapshot = node.snapshot();
+
+        let mut same = PeerRing::new(ids[0]);
+        same.restore(&snapshot);
+        assert_eq!(same.snapshot(), snapshot);
+
+        // other id rebuilds tables with exported nodes
+        let other_id: Did = SecretKey::random().address().into();
+        let mut other = PeerRing::new(other_id);
+        other.restore(&snapshot);
+        let mut expected = PeerRing::new(other_id);
+        for id in &ids {
+            expected.join(*id);
+        }
+        assert_eq!(other.finger.list(), expected.finger.list());
+        assert_eq!(other.successor.list(), expected.successor.list());
+        assert!(other.relays.contains(&ids[1]));
+    }
 }
@@ -1,5 +1,11 @@
+//! DID of nodes and resources, the id on ring.
+//!
+//! DIDs are written as `did:rings:0x<40 hex digits>`, web3 addresses like `0x...`, and bare
+//! 40 hex digits are accepted as DIDs too. Serialized DIDs are `0x...` as addresses, for
+//! compatibility with nodes before DID method.
 use std::cmp::Eq;
 use std::cmp::PartialEq;
+use std::fmt;
 use std::ops::Add;
 use std::ops::Deref;
 use std::ops::Neg;
@@ -7,16 +13,21 @@ use std::ops::Sub;
 use std::str::FromStr;
 
 use num_bigint::BigUint;
+use serde::de;
 use serde::Deserialize;
+use serde::Deserializer;
 use serde::Serialize;
-use web3::types::H160;
 
+use crate::address::H160;
 /// Did is a finate Ring R(P) where P = 2^160
 use crate::ecc::HashStr;
 use crate::err::Error;
 use crate::err::Result;
 
-#[derive(Copy, Clone, Eq, Ord, PartialEq, PartialOrd, Debug, Serialize, Deserialize, Hash)]
+/// Prefix of DIDs of rings, see module doc.
+pub const DID_PREFIX: &str = "did:rings:";
+
+#[derive(Copy, Clone, Eq, Ord, PartialEq, PartialOrd, Debug, Serialize, Hash)]
 pub struct Did(H160);
 
 // Bias Did is a special Did which set origin Did's idendity to bias
@@ -158,7 +169,42 @@ impl From<H160> for Did {
 impl FromStr for Did {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self> {
-        Ok(Self(H160::from_str(s).map_err(|_| Error::BadCHexInCache)?))
+        let invalid = |reason: &str| Error::InvalidDid(s.to_owned(), reason.to_owned());
+        let trimmed = s.trim();
+        let hex = match trimmed.strip_prefix(DID_PREFIX) {
+            Some(rest) => rest,
+            None if trimmed.starts_with("did:") => {
+                return Err(invalid("only `did:rings:` method is supported"))
+            }
+            None => trimmed,
+        };
+        let hex = hex
+            .strip_prefix("0x")
+            .or_else(|| hex.strip_prefix("0X"))
+            .unwrap_or(hex);
+        if hex.len() != 40 {
+            return Err(invalid(&format!("expect 40 hex digits, got {}", hex.len())));
+        }
+        H160::from_str(hex)
+            .map(Self)
+            .map_err(|_| invalid("not a hex string"))
+    }
+}
+
+impl fmt::Display for Did {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{:?}", DID_PREFIX, self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Did {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where D: Deserializer<'de> {
+        if !deserializer.is_human_readable() {
+            return H160::deserialize(deserializer).map(Self);
+        }
+        let s = String::deserialize(deserializer)?;
+        Did::from_str(&s).map_err(de::Error::custom)
     }
 }
 
@@ -200,6 +246,31 @@ mod tests {
         assert!(c > b && b > a);
     }
 
+    #[test]
+    fn test_did_format() {
+        let hex = "0x11e807fcc88dd319270493fb2e822e388fe36ab0";
+        let did = Did::from_str(hex).unwrap();
+        assert_eq!(did.to_string(), format!("did:rings:{}", hex));
+        assert_eq!(Did::from_str(&did.to_string()).unwrap(), did);
+        assert_eq!(Did::from_str(&hex[2..]).unwrap(), did);
+        assert_eq!(Did::from_str(&hex.to_uppercase()[2..]).unwrap(), did);
+
+        for bad in [
+            "did:key:z6Mk",
+            "0x11e8",
+            "0xzze807fcc88dd319270493fb2e822e388fe36ab0",
+        ] {
+            assert!(matches!(Did::from_str(bad), Err(Error::InvalidDid(_, _))));
+        }
+
+        // serialized as address, both forms are accepted
+        assert_eq!(serde_json::to_string(&did).unwrap(), format!("\"{}\"", hex));
+        let parsed: Did = serde_json::from_str(&format!("\"{}\"", did)).unwrap();
+        assert_eq!(parsed, did);
+        let bin = bincode::serialize(&did).unwrap();
+        assert_eq!(bincode::deserialize::<Did>(&bin).unwrap(), did);
+    }
+
     #[test]
     fn test_finate_ring_neg() {
         let zero = Did::from_str("0x0000000000000000000000000000000000000000").unwrap();
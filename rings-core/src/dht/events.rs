@@ -0,0 +1,143 @@
+//! Events of ring topology, for applications to react to, like re-publishing presence.
+//!
+//! Message handler and stabilization observe the ring after they change it, even if they fail,
+//! and its differences from [RingNeighbours] observed last time are emitted as [DhtEvent]s, see
+//! [crate::swarm::Swarm::observe_ring]. Joining and leaving of nodes are emitted as they are
+//! handled. Events are sent to transport event channel of swarm through one [DhtEventQueue], in
+//! order of emitting, and delivered to [crate::swarm::PayloadListener::on_dht_event]. Nothing is
+//! compared or emitted while no listener is registered.
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::dht::Did;
+use crate::dht::PeerRing;
+
+/// Change of ring topology observed by this node.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DhtEvent {
+    /// Successor list is changed to this one.
+    SuccessorChanged(Vec<Did>),
+    /// Predecessor is changed to this one.
+    PredecessorChanged(Option<Did>),
+    /// Nodes are put to or gone from finger table.
+    FingerUpdated { added: Vec<Did>, removed: Vec<Did> },
+    /// Node joined DHT through this node.
+    NodeJoined(Did),
+    /// Node left DHT, or its transport failed.
+    NodeLeft(Did),
+}
+
+/// Neighbours of a ring at a moment, compared to tell [DhtEvent]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RingNeighbours {
+    successors: Vec<Did>,
+    predecessor: Option<Did>,
+    fingers: BTreeSet<Did>,
+}
+
+impl RingNeighbours {
+    pub fn of(ring: &PeerRing) -> Self {
+        Self {
+            successors: ring.successor.list(),
+            predecessor: ring.predecessor,
+            fingers: ring.finger.list().iter().flatten().copied().collect(),
+        }
+    }
+
+    /// Events of changing from this to `after`.
+    pub fn changes(&self, after: &Self) -> Vec<DhtEvent> {
+        let mut events = vec![];
+        if self.successors != after.successors {
+            events.push(DhtEvent::SuccessorChanged(after.successors.clone()));
+        }
+        if self.predecessor != after.predecessor {
+            events.push(DhtEvent::PredecessorChanged(after.predecessor));
+        }
+        if self.fingers != after.fingers {
+            events.push(DhtEvent::FingerUpdated {
+                added: after.fingers.difference(&self.fingers).copied().collect(),
+                removed: self.fingers.difference(&after.fingers).copied().collect(),
+            });
+        }
+        events
+    }
+}
+
+/// Events waiting to be sent to transport event channel, in order of emitting. At most one
+/// task sends them, so they can't overtake each other.
+#[derive(Debug, Default)]
+pub struct DhtEventQueue {
+    events: Mutex<VecDeque<DhtEvent>>,
+    sending: AtomicBool,
+}
+
+impl DhtEventQueue {
+    /// Queue `event`, returns true if no task is sending, so caller should start one.
+    pub fn push(&self, event: DhtEvent) -> bool {
+        match self.events.lock() {
+            Ok(mut events) => {
+                events.push_back(event);
+                !self.sending.swap(true, Ordering::AcqRel)
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Next event to send, the sending task is done if it's None.
+    pub fn pop(&self) -> Option<DhtEvent> {
+        let mut events = self.events.lock().ok()?;
+        let event = events.pop_front();
+        if event.is_none() {
+            self.sending.store(false, Ordering::Release);
+        }
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht::Chord;
+    use crate::ecc::SecretKey;
+
+    #[test]
+    fn test_ring_neighbours_changes() {
+        let id: Did = SecretKey::random().address().into();
+        let other: Did = SecretKey::random().address().into();
+        let mut ring = PeerRing::new(id);
+        let before = RingNeighbours::of(&ring);
+        assert!(before.changes(&before).is_empty());
+
+        ring.join(other);
+        ring.predecessor = Some(other);
+        let after = RingNeighbours::of(&ring);
+        assert_eq!(before.changes(&after), vec![
+            DhtEvent::SuccessorChanged(vec![other]),
+            DhtEvent::PredecessorChanged(Some(other)),
+            DhtEvent::FingerUpdated {
+                added: vec![other],
+                removed: vec![]
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_event_queue_order() {
+        let id: Did = SecretKey::random().address().into();
+        let queue = DhtEventQueue::default();
+        assert!(queue.push(DhtEvent::NodeJoined(id)));
+        // a task is sending already
+        assert!(!queue.push(DhtEvent::NodeLeft(id)));
+        assert_eq!(queue.pop(), Some(DhtEvent::NodeJoined(id)));
+        assert_eq!(queue.pop(), Some(DhtEvent::NodeLeft(id)));
+        assert_eq!(queue.pop(), None);
+        // the task is done, next event starts another
+        assert!(queue.push(DhtEvent::NodeJoined(id)));
+    }
+}
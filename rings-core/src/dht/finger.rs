@@ -72,36 +72,34 @@ impl FingerTable {
         }
     }
 
-    /// Join FingerTable
-    pub fn join(&mut self, id: Did) {
+    /// Indexes of fingers `id` would take, for being closer to their start than the current ones
+    fn positions(&self, id: Did) -> Vec<usize> {
         let bid: BiasId = id.bias(&self.id);
+        (0..self.size)
+            .filter(|k| {
+                // (n + 2^k) % 2^m >= n
+                // pos >= id
+                // from n to n + 2^160
+                let pos = Did::from(BigUint::from(2u16).pow(*k as u32));
+                // pos less than id
+                // if id is more close to self.id than an existed value v
+                bid.pos() >= pos && self.finger[*k].map_or(true, |v| bid < v.bias(&self.id))
+            })
+            .collect()
+    }
 
-        for k in 0u32..self.size as u32 {
-            // (n + 2^k) % 2^m >= n
-            // pos >= id
-            // from n to n + 2^160
-            let pos = Did::from(BigUint::from(2u16).pow(k));
-            // pos less than id
-            if bid.pos() >= pos {
-                // if pos <= id - self.id {
-                match self.finger[k as usize] {
-                    Some(v) => {
-                        // for a existed value v
-                        // if id is more close to self.id than v
-                        if bid < v.bias(&self.id) {
-                            // if id < v || id > -v {
-                            self.finger[k as usize] = Some(id);
-                            // if id is more close to successor
-                        }
-                    }
-                    None => {
-                        self.finger[k as usize] = Some(id);
-                    }
-                }
-            }
+    /// Join FingerTable
+    pub fn join(&mut self, id: Did) {
+        for k in self.positions(id) {
+            self.finger[k] = Some(id);
         }
     }
 
+    /// Check [FingerTable::join] of `id` would take some finger
+    pub fn would_join(&self, id: Did) -> bool {
+        id != self.id && !self.positions(id).is_empty()
+    }
+
     /// Check finger is contains some node
     pub fn contains(&self, v: &Option<Did>) -> bool {
         self.finger.contains(v)
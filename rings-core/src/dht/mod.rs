@@ -4,22 +4,37 @@
 
 mod did;
 pub use did::Did;
+pub use did::DID_PREFIX;
 mod chord;
+/// Events of ring topology
+pub mod events;
 /// Finger table for Rings
 pub mod finger;
 mod successor;
 mod types;
 pub use chord::PeerRing;
 pub use chord::PeerRingAction;
+pub use chord::PeerRingSnapshot;
 pub use chord::RemoteAction as PeerRingRemoteAction;
+pub use events::DhtEvent;
 pub use finger::FingerTable;
+/// Policies of picking next hop
+pub mod routing;
+pub use routing::RoutingPolicy;
 pub use types::Chord;
 pub use types::ChordStablize;
 pub use types::ChordStorage;
 pub use types::SubRingManager;
 mod stabilization;
+pub use stabilization::RepairReport;
 pub use stabilization::Stabilization;
+pub use stabilization::StabilizationHandle;
+pub use stabilization::StabilizationStatus;
 pub use stabilization::TStabilize;
+pub use stabilization::CONGESTED_OUTBOX;
+pub use stabilization::KEEPALIVE_INTERVAL;
+pub use stabilization::MAX_STABILIZE_INTERVAL;
+pub use stabilization::MIN_STABILIZE_INTERVAL;
 /// Implement SubRing with VNode
 pub mod subring;
 /// VNode is a special node that only has virtual address
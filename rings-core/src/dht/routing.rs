@@ -0,0 +1,497 @@
+#![warn(missing_docs)]
+//! Routing policies, which pick the next hop among fingers and successors when forwarding
+//! a message toward a target.
+//!
+//! [ChordPolicy] is the plain Chord behavior, the closest preceding node always wins.
+//! [LatencyAwarePolicy] looks at a few of the closest candidates, and prefers the one with
+//! lowest recorded RTT and without recent failures, see [RouteStats].
+//! [TagPreferencePolicy] prefers candidates tagged by operator, like `region=eu`, see
+//! [PeerTags].
+//!
+//! Besides policies, [RouteCache] keeps routes to nodes observed recently, by paths of
+//! payloads from them, so repeated traffic to a node skips walking fingers.
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::Did;
+use super::PeerRing;
+use crate::err::Result;
+use crate::message::MessageRelay;
+use crate::message::RelayMethod;
+use crate::tags::PeerTags;
+use crate::utils;
+
+/// RTT assumed for peers without any record, in milliseconds.
+pub const DEFAULT_RTT_MS: u64 = 200;
+/// How long an observed route is used, in milliseconds.
+pub const DEFAULT_ROUTE_TTL_MS: u128 = 60 * 1000;
+/// Routes cached at most.
+pub const DEFAULT_ROUTE_CACHE_SIZE: usize = 1024;
+
+/// Recorded quality of a directly connected peer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerMetrics {
+    /// Smoothed RTT in milliseconds.
+    pub rtt_ms: Option<u64>,
+    /// Failures since last success.
+    pub failures: u32,
+    /// When the last failure happened, in milliseconds since epoch.
+    pub last_failure_ms: Option<u128>,
+}
+
+impl PeerMetrics {
+    /// Check if peer failed within `cooldown_ms` before `now_ms`.
+    pub fn failed_recently(&self, now_ms: u128, cooldown_ms: u128) -> bool {
+        match self.last_failure_ms {
+            Some(ts) if self.failures > 0 => now_ms.saturating_sub(ts) < cooldown_ms,
+            _ => false,
+        }
+    }
+}
+
+/// RTT and failure history of peers, shared by swarm which records it,
+/// and routing policies which read it.
+#[derive(Debug, Default)]
+pub struct RouteStats {
+    peers: DashMap<Did, PeerMetrics>,
+}
+
+impl RouteStats {
+    /// Create an empty record.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a RTT sample, smoothed as `srtt = 7/8 * srtt + 1/8 * sample`, like TCP does.
+    pub fn record_rtt(&self, peer: Did, rtt_ms: u64) {
+        let mut m = self.peers.entry(peer).or_default();
+        m.rtt_ms = Some(match m.rtt_ms {
+            Some(srtt) => (srtt * 7 + rtt_ms) / 8,
+            None => rtt_ms,
+        });
+    }
+
+    /// Record a failed delivery to peer.
+    pub fn record_failure(&self, peer: Did) {
+        let mut m = self.peers.entry(peer).or_default();
+        m.failures = m.failures.saturating_add(1);
+        m.last_failure_ms = Some(utils::get_epoch_ms());
+    }
+
+    /// Record a successful delivery to peer, which resets its failures.
+    pub fn record_success(&self, peer: Did) {
+        if let Some(mut m) = self.peers.get_mut(&peer) {
+            m.failures = 0;
+        }
+    }
+
+    /// Get metrics of a peer.
+    pub fn get(&self, peer: &Did) -> Option<PeerMetrics> {
+        self.peers.get(peer).map(|m| *m)
+    }
+
+    /// Forget a peer.
+    pub fn remove(&self, peer: &Did) {
+        self.peers.remove(peer);
+    }
+
+    /// List metrics of all recorded peers.
+    pub fn items(&self) -> Vec<(Did, PeerMetrics)> {
+        self.peers
+            .iter()
+            .map(|kv| (*kv.key(), *kv.value()))
+            .collect()
+    }
+}
+
+/// Strategy of chosing next hop toward a target.
+pub trait RoutingPolicy: std::fmt::Debug + Send + Sync {
+    /// Pick next hop to reach `target`, or id of `ring` itself if no node precedes target.
+    fn next_hop(&self, ring: &PeerRing, target: Did) -> Result<Did>;
+}
+
+/// Default Chord routing, forwards to the closest preceding finger.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChordPolicy;
+
+impl RoutingPolicy for ChordPolicy {
+    fn next_hop(&self, ring: &PeerRing, target: Did) -> Result<Did> {
+        ring.finger.closest(target)
+    }
+}
+
+/// Pick the fastest healthy one among `window` closest preceding candidates.
+/// Peers failed within `failure_cooldown_ms` are skipped, unless all candidates failed.
+#[derive(Debug, Clone)]
+pub struct LatencyAwarePolicy {
+    stats: Arc<RouteStats>,
+    /// How many closest candidates are compared.
+    pub window: usize,
+    /// How long a failed peer is avoided, in milliseconds.
+    pub failure_cooldown_ms: u128,
+}
+
+impl LatencyAwarePolicy {
+    /// Create policy reading given stats, with a window of 3 and cooldown of 30 seconds.
+    pub fn new(stats: Arc<RouteStats>) -> Self {
+        Self {
+            stats,
+            window: 3,
+            failure_cooldown_ms: 30 * 1000,
+        }
+    }
+}
+
+impl RoutingPolicy for LatencyAwarePolicy {
+    fn next_hop(&self, ring: &PeerRing, target: Did) -> Result<Did> {
+        let candidates = ring.route_candidates(target);
+        if candidates.is_empty() {
+            return ring.finger.closest(target);
+        }
+        let now = utils::get_epoch_ms();
+        let window = &candidates[..self.window.clamp(1, candidates.len())];
+        // candidates are sorted closest first, min_by_key keeps the first of equals
+        let best = window
+            .iter()
+            .map(|c| (*c, self.stats.get(c).unwrap_or_default()))
+            .filter(|(_, m)| !m.failed_recently(now, self.failure_cooldown_ms))
+            .min_by_key(|(_, m)| m.rtt_ms.unwrap_or(DEFAULT_RTT_MS))
+            .map(|(c, _)| c);
+        Ok(best.unwrap_or(candidates[0]))
+    }
+}
+
+/// Pick the closest one tagged `key=value` among `window` closest preceding candidates,
+/// otherwise fall back to `inner` policy.
+#[derive(Debug, Clone)]
+pub struct TagPreferencePolicy {
+    tags: Arc<PeerTags>,
+    inner: Arc<dyn RoutingPolicy>,
+    /// Tag key of preferred peers.
+    pub key: String,
+    /// Tag value of preferred peers.
+    pub value: String,
+    /// How many closest candidates are compared.
+    pub window: usize,
+}
+
+impl TagPreferencePolicy {
+    /// Create policy preferring peers tagged `key=value`, with a window of 3.
+    pub fn new(tags: Arc<PeerTags>, key: &str, value: &str, inner: Arc<dyn RoutingPolicy>) -> Self {
+        Self {
+            tags,
+            inner,
+            key: key.to_owned(),
+            value: value.to_owned(),
+            window: 3,
+        }
+    }
+}
+
+impl RoutingPolicy for TagPreferencePolicy {
+    fn next_hop(&self, ring: &PeerRing, target: Did) -> Result<Did> {
+        let candidates = ring.route_candidates(target);
+        let window = &candidates[..self.window.min(candidates.len())];
+        match window
+            .iter()
+            .find(|c| self.tags.matches(**c, &self.key, &self.value))
+        {
+            Some(c) => Ok(*c),
+            None => self.inner.next_hop(ring, target),
+        }
+    }
+}
+
+/// A route to a node, observed from a payload which travelled from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedRoute {
+    /// Connected peer which delivered the payload.
+    pub next_hop: Did,
+    /// Hops to the node through `next_hop`.
+    pub hops: usize,
+    /// When it's observed, in milliseconds since epoch.
+    pub seen_ms: u128,
+}
+
+/// Routes to nodes observed recently, shared by swarm which records paths of inbound
+/// payloads, and [PeerRing] which consults it before finger table, see
+/// [PeerRing::find_route]. Routes expire after a TTL, or once their next hop is gone.
+#[derive(Debug)]
+pub struct RouteCache {
+    ttl_ms: u128,
+    capacity: usize,
+    routes: DashMap<Did, CachedRoute>,
+}
+
+impl Default for RouteCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_ROUTE_TTL_MS, DEFAULT_ROUTE_CACHE_SIZE)
+    }
+}
+
+impl RouteCache {
+    /// Keep at most `capacity` routes, each one for `ttl_ms` since it's observed.
+    pub fn new(ttl_ms: u128, capacity: usize) -> Self {
+        Self {
+            ttl_ms,
+            capacity,
+            routes: DashMap::new(),
+        }
+    }
+
+    /// Record `sender`, the peer delivered a payload to node `local`, and `origin` who signed
+    /// the payload, reached through `sender` in hops counted on `relay` path. Both of them
+    /// should be verified by signatures of payload. Other nodes on path are never recorded,
+    /// since path is not signed and anyone relaying could make them up.
+    pub fn observe(&self, local: Did, sender: Did, origin: Did, relay: &MessageRelay) {
+        self.observe_at(local, sender, origin, relay, utils::get_epoch_ms())
+    }
+
+    fn observe_at(&self, local: Did, sender: Did, origin: Did, relay: &MessageRelay, now_ms: u128) {
+        let path = &relay.path;
+        // sends pushed origin before sender, reports travelled back from origin to sender
+        let hops = match relay.method {
+            RelayMethod::SEND if path.first() == Some(&origin) => {
+                path.iter().rposition(|n| *n == sender).map(|i| i + 1)
+            }
+            RelayMethod::REPORT if path.last() == Some(&origin) => path
+                .len()
+                .checked_sub(relay.path_end_cursor + 1)
+                .filter(|i| path[*i] == sender)
+                .map(|i| path.len() - i),
+            _ => None,
+        };
+        if sender != local {
+            self.insert(sender, sender, 1, now_ms);
+        }
+        if let Some(hops) = hops.filter(|_| origin != local) {
+            self.insert(origin, sender, hops, now_ms);
+        }
+    }
+
+    fn insert(&self, node: Did, next_hop: Did, hops: usize, now_ms: u128) {
+        if !self.routes.contains_key(&node) && self.routes.len() >= self.capacity {
+            self.routes
+                .retain(|_, r| now_ms.saturating_sub(r.seen_ms) < self.ttl_ms);
+            if self.routes.len() >= self.capacity {
+                return;
+            }
+        }
+        let route = CachedRoute {
+            next_hop,
+            hops,
+            seen_ms: now_ms,
+        };
+        let mut entry = self.routes.entry(node).or_insert(route);
+        // a shorter or the same route replaces, a longer one only replaces expired
+        if hops <= entry.hops
+            || next_hop == entry.next_hop
+            || now_ms.saturating_sub(entry.seen_ms) >= self.ttl_ms
+        {
+            *entry = route;
+        }
+    }
+
+    /// Next hop to `node`, if a route to it is observed within TTL.
+    pub fn get(&self, node: Did) -> Option<Did> {
+        self.get_at(node, utils::get_epoch_ms())
+    }
+
+    fn get_at(&self, node: Did, now_ms: u128) -> Option<Did> {
+        let route = *self.routes.get(&node)?;
+        if now_ms.saturating_sub(route.seen_ms) >= self.ttl_ms {
+            self.routes.remove(&node);
+            return None;
+        }
+        Some(route.next_hop)
+    }
+
+    /// Forget routes through `next_hop`, once it's disconnected.
+    pub fn forget_via(&self, next_hop: Did) {
+        self.routes
+            .retain(|n, r| r.next_hop != next_hop && *n != next_hop);
+    }
+
+    /// List cached routes, expired ones included until they are looked up.
+    pub fn items(&self) -> Vec<(Did, CachedRoute)> {
+        self.routes
+            .iter()
+            .map(|kv| (*kv.key(), *kv.value()))
+            .collect()
+    }
+
+    /// Count of cached routes.
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    /// Check if no route is cached.
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+}
+
+/// Which routing policy a node uses.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RoutingStrategy {
+    /// See [ChordPolicy].
+    Chord,
+    /// See [LatencyAwarePolicy].
+    Latency,
+}
+
+impl Default for RoutingStrategy {
+    fn default() -> Self {
+        Self::Chord
+    }
+}
+
+impl RoutingStrategy {
+    /// Build the policy, `stats` is usually [crate::swarm::Swarm::route_stats].
+    pub fn build(self, stats: Arc<RouteStats>) -> Arc<dyn RoutingPolicy> {
+        match self {
+            Self::Chord => Arc::new(ChordPolicy),
+            Self::Latency => Arc::new(LatencyAwarePolicy::new(stats)),
+        }
+    }
+}
+
+impl std::str::FromStr for RoutingStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "chord" => Ok(Self::Chord),
+            "latency" => Ok(Self::Latency),
+            _ => Err(format!("unknown routing strategy: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for RoutingStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Chord => write!(f, "chord"),
+            Self::Latency => write!(f, "latency"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::dht::Chord;
+
+    fn did(s: &str) -> Did {
+        Did::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_latency_aware_policy() {
+        let a = did("0x1000000000000000000000000000000000000000");
+        let b = did("0x2000000000000000000000000000000000000000");
+        let c = did("0x3000000000000000000000000000000000000000");
+        let target = did("0x4000000000000000000000000000000000000000");
+        let mut ring = PeerRing::new(did("0x0000000000000000000000000000000000000001"));
+        for n in [a, b, c] {
+            ring.join(n);
+        }
+        assert_eq!(ring.route_candidates(target), vec![c, b, a]);
+        assert_eq!(ChordPolicy.next_hop(&ring, target).unwrap(), c);
+
+        let stats = Arc::new(RouteStats::new());
+        let policy = LatencyAwarePolicy::new(stats.clone());
+        // without any record, behaves like chord
+        assert_eq!(policy.next_hop(&ring, target).unwrap(), c);
+
+        stats.record_rtt(a, 300);
+        stats.record_rtt(b, 50);
+        stats.record_rtt(c, 400);
+        assert_eq!(policy.next_hop(&ring, target).unwrap(), b);
+
+        stats.record_failure(b);
+        assert_eq!(policy.next_hop(&ring, target).unwrap(), a);
+        stats.record_success(b);
+        assert_eq!(policy.next_hop(&ring, target).unwrap(), b);
+    }
+
+    #[test]
+    fn test_tag_preference_policy() {
+        let a = did("0x1000000000000000000000000000000000000000");
+        let b = did("0x2000000000000000000000000000000000000000");
+        let c = did("0x3000000000000000000000000000000000000000");
+        let target = did("0x4000000000000000000000000000000000000000");
+        let mut ring = PeerRing::new(did("0x0000000000000000000000000000000000000001"));
+        for n in [a, b, c] {
+            ring.join(n);
+        }
+        let tags = Arc::new(PeerTags::new());
+        let policy = TagPreferencePolicy::new(tags.clone(), "region", "eu", Arc::new(ChordPolicy));
+        assert_eq!(policy.next_hop(&ring, target).unwrap(), c);
+
+        tags.set(a, "region", "eu").unwrap();
+        assert_eq!(policy.next_hop(&ring, target).unwrap(), a);
+        tags.set(b, "region", "eu").unwrap();
+        assert_eq!(policy.next_hop(&ring, target).unwrap(), b);
+        tags.set(b, "region", "us").unwrap();
+        assert_eq!(policy.next_hop(&ring, target).unwrap(), a);
+    }
+
+    #[test]
+    fn test_route_cache() {
+        let local = did("0x0000000000000000000000000000000000000001");
+        let origin = did("0x1000000000000000000000000000000000000000");
+        let hop = did("0x2000000000000000000000000000000000000000");
+        let other = did("0x3000000000000000000000000000000000000000");
+        let cache = RouteCache::new(1000, 16);
+        let relay = MessageRelay::new(RelayMethod::SEND, vec![origin, hop], None, None, local);
+        cache.observe_at(local, hop, origin, &relay, 0);
+        assert_eq!(cache.get_at(origin, 10), Some(hop));
+        assert_eq!(cache.get_at(hop, 10), Some(hop));
+
+        // unsigned nodes on path are never learned
+        let forged = MessageRelay::new(RelayMethod::SEND, vec![other, hop], None, None, local);
+        cache.observe_at(local, hop, origin, &forged, 0);
+        assert_eq!(cache.get_at(other, 10), None);
+
+        // shorter route replaces
+        let direct = MessageRelay::new(RelayMethod::SEND, vec![origin], None, None, local);
+        cache.observe_at(local, origin, origin, &direct, 20);
+        assert_eq!(cache.get_at(origin, 30), Some(origin));
+        // longer one replaces only expired
+        let longer = MessageRelay::new(RelayMethod::SEND, vec![origin, other], None, None, local);
+        cache.observe_at(local, other, origin, &longer, 40);
+        assert_eq!(cache.get_at(origin, 50), Some(origin));
+        cache.observe_at(local, other, origin, &longer, 1100);
+        assert_eq!(cache.get_at(origin, 1110), Some(other));
+        assert_eq!(cache.get_at(origin, 3000), None);
+
+        // report of origin travelled back by hop
+        let report = MessageRelay::new(
+            RelayMethod::REPORT,
+            vec![local, hop, origin],
+            Some(1),
+            Some(local),
+            local,
+        );
+        cache.observe_at(local, hop, origin, &report, 3000);
+        assert_eq!(cache.get_at(origin, 3010), Some(hop));
+        cache.forget_via(hop);
+        assert_eq!(cache.get_at(origin, 3010), None);
+    }
+
+    #[test]
+    fn test_record_rtt_smoothed() {
+        let stats = RouteStats::new();
+        let peer = did("0x1000000000000000000000000000000000000000");
+        stats.record_rtt(peer, 80);
+        stats.record_rtt(peer, 160);
+        assert_eq!(stats.get(&peer).unwrap().rtt_ms, Some(90));
+    }
+}
@@ -1,24 +1,156 @@
+//! Stabilization of DHT, run periodically.
+//!
+//! Interval of rounds adapts to the ring: it drops to the shortest interval when churn is
+//! detected, that is successors changed or notifying them failed, and doubles after every quiet
+//! round up to the longest interval. A round is skipped while outbox of swarm is congested, so
+//! stabilization doesn't make a busy link worse.
+//!
+//! [Stabilization::spawn] runs rounds in a background task, which is paused, resumed, triggered,
+//! tuned and stopped by the returned [StabilizationHandle]. Rounds are timed by [crate::timer],
+//! so browsers run them by themselves too. The task also pings peers connected directly every
+//! [KEEPALIVE_INTERVAL], so idle connections are not dropped by NATs and browsers, even while
+//! rounds are paused.
+//!
+//! [Stabilization::repair] runs a full round of anti-entropy at once, instead of fixing one
+//! finger a round, which is useful after a network partition heals.
+use std::collections::BTreeMap;
+use std::ops::Deref;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
+use futures::channel::mpsc;
+use futures::future;
+use futures::future::Either;
 use futures::lock::Mutex;
+use futures::StreamExt;
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+use serde::Serialize;
 
+use crate::dht::vnode::VirtualNode;
 use crate::dht::ChordStablize;
+use crate::dht::ChordStorage;
+use crate::dht::Did;
 use crate::dht::PeerRing;
 use crate::dht::PeerRingAction;
 use crate::dht::PeerRingRemoteAction;
+use crate::err::Error;
 use crate::err::Result;
+use crate::manifest::ManifestRecord;
+use crate::manifest::DEFAULT_MANIFEST_TTL_MS;
+use crate::message::challenge_stored;
 use crate::message::FindSuccessorSend;
 use crate::message::Message;
 use crate::message::NotifyPredecessorSend;
 use crate::message::PayloadSender;
+use crate::message::PeerExchange;
+use crate::message::PeerSampleSend;
+use crate::message::Ping;
+use crate::message::SearchVNode;
+use crate::message::StoreVNode;
+use crate::presence::PresenceRecord;
+use crate::presence::DEFAULT_PRESENCE_TTL_MS;
+use crate::pubkey::PubkeyRecord;
+use crate::pubkey::DEFAULT_PUBKEY_TTL_MS;
+use crate::service::ServiceRecord;
+use crate::service::DEFAULT_SERVICE_TTL_MS;
+use crate::swarm::DrainState;
 use crate::swarm::Swarm;
+use crate::swarm::TransportManager;
+use crate::timer;
+use crate::timer::Interval;
+use crate::timer::TaskHandle;
+use crate::types::ice_transport::IceTransport;
+use crate::utils;
+
+/// Shortest interval between rounds, used while ring is churning, in seconds.
+pub const MIN_STABILIZE_INTERVAL: usize = 1;
+/// Longest interval between rounds, used while ring is quiet, in seconds.
+pub const MAX_STABILIZE_INTERVAL: usize = 60;
+/// Rounds are skipped while more payloads than this are being sent.
+pub const CONGESTED_OUTBOX: usize = 64;
+/// Interval of pinging peers connected directly.
+pub const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// State of stabilization, see [Stabilization::status].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StabilizationStatus {
+    /// Task is spawned by [Stabilization::spawn] or [TStabilize::wait].
+    pub running: bool,
+    /// Rounds are paused, except triggered ones.
+    pub paused: bool,
+    /// Seconds to wait before next round.
+    pub interval: usize,
+    pub min_interval: usize,
+    pub max_interval: usize,
+    /// When last round finished, in milliseconds since epoch.
+    pub last_round_ms: Option<u128>,
+}
+
+/// Changes made by [Stabilization::repair]. Answers of remote nodes to lookups and
+/// notifications arrive later, they are applied as usual but not reported here.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepairReport {
+    /// Successors and fingers dropped since they are not connected any more.
+    pub dropped: Vec<Did>,
+    /// Successors notified, their answers verify successor list.
+    pub notified: Vec<Did>,
+    /// Fingers resolved locally and changed.
+    pub fixed_fingers: usize,
+    /// Fingers looked up remotely again.
+    pub finger_lookups: usize,
+    /// Virtual nodes handed over to nodes which are responsible for them now, they are
+    /// removed here once [StoreVNodeReport](crate::message::StoreVNodeReport) arrives.
+    pub moved_vnodes: Vec<Did>,
+    /// Successor list after repair.
+    pub successors: Vec<Did>,
+}
 
 #[derive(Clone)]
 pub struct Stabilization {
     chord: Arc<Mutex<PeerRing>>,
     swarm: Arc<Swarm>,
-    timeout: usize,
+    min_interval: Arc<AtomicUsize>,
+    max_interval: Arc<AtomicUsize>,
+    /// Interval before next round, in seconds.
+    interval: Arc<AtomicUsize>,
+    /// Successors seen by last round.
+    successors: Arc<Mutex<Vec<Did>>>,
+    running: Arc<AtomicBool>,
+    paused: Arc<AtomicBool>,
+    last_round_ms: Arc<AtomicU64>,
+    trigger_tx: mpsc::UnboundedSender<()>,
+    /// Taken by the task when it starts.
+    trigger_rx: Arc<Mutex<Option<mpsc::UnboundedReceiver<()>>>>,
+}
+
+/// Handle of task spawned by [Stabilization::spawn], controls the task like [Stabilization]
+/// does. Dropping the handle doesn't stop the task.
+#[derive(Clone)]
+pub struct StabilizationHandle {
+    stabilization: Arc<Stabilization>,
+    task: TaskHandle,
+}
+
+impl Deref for StabilizationHandle {
+    type Target = Stabilization;
+
+    fn deref(&self) -> &Self::Target {
+        &self.stabilization
+    }
+}
+
+impl StabilizationHandle {
+    /// Stop the task, it can't be spawned again.
+    pub fn stop(&self) {
+        self.task.abort();
+        self.stabilization.running.store(false, Ordering::Relaxed);
+    }
 }
 
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
@@ -28,16 +160,161 @@ pub trait TStabilize {
 }
 
 impl Stabilization {
-    pub fn new(chord: Arc<Mutex<PeerRing>>, swarm: Arc<Swarm>, timeout: usize) -> Self {
+    /// Rounds run every [MIN_STABILIZE_INTERVAL] to [MAX_STABILIZE_INTERVAL] seconds, starting
+    /// with the shortest, a new node is joining the ring.
+    pub fn new(chord: Arc<Mutex<PeerRing>>, swarm: Arc<Swarm>) -> Self {
+        let (trigger_tx, trigger_rx) = mpsc::unbounded();
         Self {
             chord,
             swarm,
-            timeout,
+            min_interval: Arc::new(AtomicUsize::new(MIN_STABILIZE_INTERVAL)),
+            max_interval: Arc::new(AtomicUsize::new(MAX_STABILIZE_INTERVAL)),
+            interval: Arc::new(AtomicUsize::new(MIN_STABILIZE_INTERVAL)),
+            successors: Arc::new(Mutex::new(vec![])),
+            running: Arc::new(AtomicBool::new(false)),
+            paused: Arc::new(AtomicBool::new(false)),
+            last_round_ms: Arc::new(AtomicU64::new(0)),
+            trigger_tx,
+            trigger_rx: Arc::new(Mutex::new(Some(trigger_rx))),
         }
     }
 
+    /// Run rounds every `min` to `max` seconds.
+    pub fn with_interval(self, min: usize, max: usize) -> Self {
+        self.set_interval(min, max);
+        self
+    }
+
+    /// Run rounds every `min` to `max` seconds from now on, next round waits `min` seconds.
+    pub fn set_interval(&self, min: usize, max: usize) {
+        let min = min.max(1);
+        self.min_interval.store(min, Ordering::Relaxed);
+        self.max_interval.store(max.max(min), Ordering::Relaxed);
+        self.interval.store(min, Ordering::Relaxed);
+    }
+
+    /// Seconds to wait before next round.
     pub fn get_timeout(&self) -> usize {
-        self.timeout
+        self.interval.load(Ordering::Relaxed)
+    }
+
+    /// Stop running rounds by interval, triggered rounds still run.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    /// Run a round now, without waiting for interval.
+    pub fn trigger(&self) -> Result<()> {
+        self.trigger_tx
+            .unbounded_send(())
+            .map_err(|_| Error::StabilizationStopped)
+    }
+
+    pub fn status(&self) -> StabilizationStatus {
+        let last_round_ms = self.last_round_ms.load(Ordering::Relaxed);
+        StabilizationStatus {
+            running: self.running.load(Ordering::Relaxed),
+            paused: self.paused.load(Ordering::Relaxed),
+            interval: self.get_timeout(),
+            min_interval: self.min_interval.load(Ordering::Relaxed),
+            max_interval: self.max_interval.load(Ordering::Relaxed),
+            last_round_ms: (last_round_ms > 0).then(|| last_round_ms as u128),
+        }
+    }
+
+    /// Run stabilization in a background task.
+    pub fn spawn(self: Arc<Self>) -> StabilizationHandle {
+        let caller = self.clone();
+        let task = timer::spawn(async move { caller.run().await });
+        StabilizationHandle {
+            stabilization: self,
+            task,
+        }
+    }
+
+    /// Run rounds until the task is stopped, by interval unless paused, or when triggered.
+    async fn run(&self) {
+        let mut trigger = match self.trigger_rx.lock().await.take() {
+            Some(rx) => rx,
+            None => {
+                tracing::warn!("stabilization is already running");
+                return;
+            }
+        };
+        self.running.store(true, Ordering::Relaxed);
+        let mut rounds = Interval::new(self.round_period());
+        let mut keepalive = Interval::new(KEEPALIVE_INTERVAL);
+        loop {
+            let ticks = future::select(Box::pin(rounds.tick()), Box::pin(keepalive.tick()));
+            let triggered = match future::select(ticks, trigger.next()).await {
+                Either::Left((Either::Right(_), _)) => {
+                    self.keepalive().await;
+                    continue;
+                }
+                Either::Left((Either::Left(_), _)) => false,
+                Either::Right((triggered, _)) => triggered.is_some(),
+            };
+            if !triggered && self.paused.load(Ordering::Relaxed) {
+                continue;
+            }
+            if let Err(e) = self.stabilize().await {
+                tracing::error!("failed to stabilize {:?}", e);
+            }
+            self.last_round_ms
+                .store(utils::get_epoch_ms() as u64, Ordering::Relaxed);
+            // interval adapts to churn by rounds, see module doc
+            if rounds.period() != self.round_period() {
+                rounds.set_period(self.round_period());
+            }
+        }
+    }
+
+    fn round_period(&self) -> Duration {
+        Duration::from_secs(self.get_timeout() as u64)
+    }
+
+    /// Ping peers connected directly, see [KEEPALIVE_INTERVAL].
+    async fn keepalive(&self) {
+        for (address, transport) in self.swarm.get_transports() {
+            if !transport.is_connected().await {
+                continue;
+            }
+            if let Err(e) = self
+                .swarm
+                .send_direct_message(Message::Ping(Ping), address.into())
+                .await
+            {
+                tracing::debug!(peer = ?address, "failed to ping: {}", e);
+            }
+        }
+    }
+
+    /// Shorten interval to the shortest on churn, otherwise double it up to the longest.
+    fn adapt(&self, churn: bool) {
+        let next = if churn {
+            self.min_interval.load(Ordering::Relaxed)
+        } else {
+            (self.get_timeout() * 2).min(self.max_interval.load(Ordering::Relaxed))
+        };
+        if next != self.get_timeout() {
+            tracing::debug!(churn, interval = next, "stabilization interval changed");
+        }
+        self.interval.store(next, Ordering::Relaxed);
+    }
+
+    /// Successors changed since last round.
+    async fn successors_changed(&self) -> bool {
+        let current = self.chord.lock().await.successor.list();
+        let mut last = self.successors.lock().await;
+        if *last == current {
+            return false;
+        }
+        *last = current;
+        true
     }
 
     async fn notify_predecessor(&self) -> Result<()> {
@@ -60,7 +337,7 @@ impl Stabilization {
         match chord.fix_fingers() {
             Ok(action) => match action {
                 PeerRingAction::None => {
-                    // log::debug!("wait to next round");
+                    // tracing::debug!("wait to next round");
                     Ok(())
                 }
                 PeerRingAction::RemoteAction(
@@ -70,26 +347,350 @@ impl Stabilization {
                     let msg = Message::FindSuccessorSend(FindSuccessorSend {
                         id: current,
                         for_fix: true,
+                        finger: None,
                     });
                     self.swarm
                         .send_message(msg.clone(), next, self.swarm.address().into())
                         .await
                 }
                 _ => {
-                    log::error!("Invalid PeerRing Action");
+                    tracing::error!("Invalid PeerRing Action");
                     unreachable!();
                 }
             },
             Err(e) => {
-                log::error!("{:?}", e);
+                tracing::error!("{:?}", e);
                 Err(e)
             }
         }
     }
 
+    /// Pull own inbox from its holder, inbox stored locally is delivered when it arrives.
+    async fn check_inbox(&self) -> Result<()> {
+        let chord = self.chord.lock().await;
+        let id = VirtualNode::inbox_address(chord.id)?;
+        if let PeerRingAction::RemoteAction(next, _) = chord.lookup(&id)? {
+            self.swarm
+                .send_direct_message(Message::SearchVNode(SearchVNode { id }), next)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Publish a fresh presence record of this node, via its successor.
+    async fn publish_presence(&self) -> Result<()> {
+        let via = self.chord.lock().await.successor.min();
+        let record =
+            PresenceRecord::new(self.swarm.session_manager(), via, DEFAULT_PRESENCE_TTL_MS)?;
+        self.store_vnode(record.to_vnode()?).await
+    }
+
+    /// Refresh records of services registered in [crate::service::ServiceRegistry].
+    async fn publish_services(&self) -> Result<()> {
+        for name in self.swarm.services().list() {
+            let record =
+                ServiceRecord::new(self.swarm.session_manager(), &name, DEFAULT_SERVICE_TTL_MS)?;
+            self.store_vnode(record.to_vnode()?).await?;
+        }
+        Ok(())
+    }
+
+    /// Publish a fresh manifest of this node.
+    async fn publish_manifest(&self) -> Result<()> {
+        let record = ManifestRecord::new(
+            self.swarm.session_manager(),
+            self.swarm.manifest(),
+            DEFAULT_MANIFEST_TTL_MS,
+        )?;
+        self.store_vnode(record.to_vnode()?).await
+    }
+
+    /// Publish a fresh record of encryption key of this node, so messages are encrypted to it
+    /// before any direct contact.
+    async fn publish_pubkey(&self) -> Result<()> {
+        let record = PubkeyRecord::new(
+            self.swarm.session_manager(),
+            &self.swarm.encryption_key()?,
+            DEFAULT_PUBKEY_TTL_MS,
+        )?;
+        self.store_vnode(record.to_vnode()?).await
+    }
+
+    /// Sign usage of messages relayed for others, once its period is over.
+    async fn settle_relay_usage(&self) -> Result<()> {
+        self.swarm
+            .relay_accounting()
+            .settle(self.swarm.session_manager())
+            .await
+            .map(|_| ())
+    }
+
+    /// Store `vnode` locally, or on its successor, and track its placement.
+    async fn store_vnode(&self, vnode: VirtualNode) -> Result<()> {
+        let id = vnode.did();
+        let (action, local, successors) = {
+            let chord = self.chord.lock().await;
+            (chord.store(vnode)?, chord.id, chord.successor.list())
+        };
+        match action {
+            PeerRingAction::None => {
+                self.swarm
+                    .placements()
+                    .stored_locally(local, id, &successors);
+                Ok(())
+            }
+            PeerRingAction::RemoteAction(target, PeerRingRemoteAction::FindAndStore(vnode)) => {
+                self.swarm.placements().track(vnode.clone(), target);
+                self.swarm
+                    .send_direct_message(
+                        Message::StoreVNode(StoreVNode { data: vec![vnode] }),
+                        target,
+                    )
+                    .await
+            }
+            act => Err(Error::PeerRingUnexpectedAction(act)),
+        }
+    }
+
+    /// Store again virtual nodes not reported stored, or whose owner left, see
+    /// [crate::placement].
+    async fn retry_stores(&self) -> Result<()> {
+        for vnode in self.swarm.placements().due() {
+            tracing::debug!(vnode = ?vnode.did(), "store vnode again");
+            self.store_vnode(vnode).await?;
+        }
+        Ok(())
+    }
+
+    /// Store again virtual nodes whose challenge failed, and challenge the node storing a
+    /// random one placed on others, see [crate::audit].
+    async fn audit_placements(&self) -> Result<()> {
+        let placements = self.swarm.placements();
+        placements.store_again(&self.swarm.audits().take_failed());
+        let local: Did = self.swarm.address().into();
+        let placed = placements
+            .placed()
+            .into_iter()
+            .filter(|(_, stored_by)| *stored_by != local)
+            .collect::<Vec<_>>();
+        match placed.choose(&mut rand::thread_rng()) {
+            Some((vnode, stored_by)) => {
+                challenge_stored(&self.swarm, &self.chord, *stored_by, vnode).await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Exchange a sample of peers with a random connected peer, see [crate::gossip].
+    async fn gossip_peers(&self) -> Result<()> {
+        let peer: Did = match self.swarm.get_addresses().choose(&mut rand::thread_rng()) {
+            Some(p) => (*p).into(),
+            None => return Ok(()),
+        };
+        let msg = Message::PeerSampleSend(PeerSampleSend {
+            peers: self.swarm.peer_samples(peer),
+        });
+        self.swarm.send_direct_message(msg, peer).await
+    }
+
+    /// Share connected peers with a random connected peer, see [crate::pex].
+    async fn exchange_peers(&self) -> Result<()> {
+        let peer: Did = match self.swarm.get_addresses().choose(&mut rand::thread_rng()) {
+            Some(p) => (*p).into(),
+            None => return Ok(()),
+        };
+        let peers = self.swarm.pex_peers(peer).await;
+        if peers.is_empty() {
+            return Ok(());
+        }
+        let msg = Message::PeerExchange(PeerExchange { peers });
+        self.swarm.send_direct_message(msg, peer).await
+    }
+
+    /// Close connections over max connections, ring neighbours are kept, see [crate::pex].
+    async fn prune_connections(&self) {
+        let neighbours = {
+            let chord = self.chord.lock().await;
+            let mut neighbours = chord.successor.list();
+            neighbours.extend(chord.predecessor);
+            neighbours.extend(chord.finger.list().iter().flatten());
+            neighbours
+        };
+        self.swarm
+            .prune_connections(|did| neighbours.contains(&did))
+            .await;
+    }
+
+    /// Update tracked DIDs with records fetched in last round, and fetch them again.
+    async fn refresh_presence(&self) -> Result<()> {
+        let tracker = self.swarm.presence();
+        let chord = self.chord.lock().await;
+        for (did, _) in tracker.tracked() {
+            let id = VirtualNode::presence_address(did)?;
+            let (local, next) = match chord.lookup(&id)? {
+                PeerRingAction::SomeVNode(v) => (Some(v), None),
+                PeerRingAction::RemoteAction(next, _) => (chord.fetch_cache(&id), Some(next)),
+                _ => (None, None),
+            };
+            let record = local.and_then(|v| PresenceRecord::from_vnode(&v).ok());
+            tracker.update(did, record);
+            if let Some(next) = next {
+                self.swarm
+                    .send_direct_message(Message::SearchVNode(SearchVNode { id }), next)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Verify successors and fingers, drop those not connected, notify every successor, look
+    /// up every finger again, and hand over virtual nodes stored here whose successor is
+    /// another node now. Lookups are sent at once, their answers are joined to finger table.
+    /// Handovers are tracked as stores, see [crate::placement], so a virtual node is kept
+    /// until the node taking it over reports it's stored.
+    pub async fn repair(&self) -> Result<RepairReport> {
+        let mut report = RepairReport::default();
+        let mut msgs = vec![];
+        let mut moved: BTreeMap<Did, Vec<VirtualNode>> = BTreeMap::new();
+        {
+            let mut chord = self.chord.lock().await;
+            let mut known = chord.successor.list();
+            known.extend(chord.finger.list().iter().flatten());
+            known.sort();
+            known.dedup();
+            for did in known {
+                if self.swarm.get_transport(&did.into()).is_none() {
+                    chord.remove(did);
+                    report.dropped.push(did);
+                }
+            }
+
+            for s in chord.successor.list() {
+                msgs.push((
+                    Message::NotifyPredecessorSend(NotifyPredecessorSend { id: chord.id }),
+                    s,
+                ));
+                report.notified.push(s);
+            }
+
+            // entries of finger table change at few indices only, look up at each of them
+            let size = chord.finger.len();
+            for k in (0..size).rev() {
+                if k + 1 < size && chord.finger.get(k) == chord.finger.get(k + 1) {
+                    continue;
+                }
+                let target = chord.finger_target(k as u8);
+                match chord.find_successor(target)? {
+                    PeerRingAction::Some(v) if v == chord.id => {}
+                    PeerRingAction::Some(v) => {
+                        if *chord.finger.get(k) != Some(v) {
+                            chord.finger.set(k, &v);
+                            report.fixed_fingers += 1;
+                        }
+                    }
+                    PeerRingAction::RemoteAction(next, _) => {
+                        let msg = Message::FindSuccessorSend(FindSuccessorSend {
+                            id: target,
+                            for_fix: true,
+                            finger: Some(k as u8),
+                        });
+                        msgs.push((msg, next));
+                        report.finger_lookups += 1;
+                    }
+                    act => return Err(Error::PeerRingUnexpectedAction(act)),
+                }
+            }
+
+            for vid in chord.storage.keys() {
+                if let PeerRingAction::RemoteAction(next, _) = chord.find_successor(vid)? {
+                    if let Some(v) = chord.storage.get(&vid) {
+                        moved.entry(next).or_default().push(v);
+                    }
+                }
+            }
+            report.successors = chord.successor.list();
+        }
+
+        let origin: Did = self.swarm.address().into();
+        let sent = future::join_all(
+            msgs.into_iter()
+                .map(|(msg, next)| self.swarm.send_message(msg, next, origin)),
+        )
+        .await;
+        for e in sent.into_iter().filter_map(|r| r.err()) {
+            tracing::warn!("failed to send repair message: {}", e);
+        }
+        for (next, data) in moved {
+            let ids = data.iter().map(|v| v.did()).collect::<Vec<_>>();
+            for v in data.iter() {
+                self.swarm.placements().track(v.clone(), next);
+            }
+            let msg = Message::StoreVNode(StoreVNode { data });
+            match self.swarm.send_direct_message(msg, next).await {
+                Ok(()) => report.moved_vnodes.extend(ids),
+                Err(e) => tracing::warn!(next = ?next, "failed to hand over vnodes: {}", e),
+            }
+        }
+        tracing::info!(report = ?report, "dht repaired");
+        Ok(report)
+    }
+
     pub async fn stabilize(&self) -> Result<()> {
-        self.notify_predecessor().await?;
-        self.fix_fingers().await?;
+        if self.swarm.drain_state() != DrainState::Serving {
+            return Ok(());
+        }
+        let outbox = self.swarm.outbox_len();
+        if outbox > CONGESTED_OUTBOX {
+            tracing::debug!(outbox, "outbox is congested, skip stabilization");
+            return Ok(());
+        }
+        let notified = self.notify_predecessor().await;
+        let changed = self.successors_changed().await;
+        self.adapt(changed || notified.is_err());
+        let fixed = match notified {
+            Ok(()) => self.fix_fingers().await,
+            Err(e) => Err(e),
+        };
+        // changes of ring are told to listeners, even of a failing round, see
+        // [crate::dht::events]
+        if self.swarm.has_listeners() {
+            self.swarm.observe_ring(&*self.chord.lock().await);
+        }
+        fixed?;
+        if let Err(e) = self.check_inbox().await {
+            tracing::warn!("failed to check inbox: {}", e);
+        }
+        if let Err(e) = self.publish_presence().await {
+            tracing::warn!("failed to publish presence: {}", e);
+        }
+        if let Err(e) = self.refresh_presence().await {
+            tracing::warn!("failed to refresh presence: {}", e);
+        }
+        if let Err(e) = self.publish_services().await {
+            tracing::warn!("failed to publish services: {}", e);
+        }
+        if let Err(e) = self.publish_manifest().await {
+            tracing::warn!("failed to publish manifest: {}", e);
+        }
+        if let Err(e) = self.publish_pubkey().await {
+            tracing::warn!("failed to publish pubkey: {}", e);
+        }
+        if let Err(e) = self.audit_placements().await {
+            tracing::warn!("failed to audit placements: {}", e);
+        }
+        if let Err(e) = self.retry_stores().await {
+            tracing::warn!("failed to store vnodes again: {}", e);
+        }
+        if let Err(e) = self.gossip_peers().await {
+            tracing::warn!("failed to gossip peers: {}", e);
+        }
+        if let Err(e) = self.exchange_peers().await {
+            tracing::warn!("failed to exchange peers: {}", e);
+        }
+        self.prune_connections().await;
+        if let Err(e) = self.settle_relay_usage().await {
+            tracing::warn!("failed to settle relay usage: {}", e);
+        }
         Ok(())
     }
 }
@@ -97,13 +698,8 @@ impl Stabilization {
 #[cfg(not(feature = "wasm"))]
 mod stabilizer {
     use std::sync::Arc;
-    use std::time::Duration;
 
     use async_trait::async_trait;
-    use futures::future::FutureExt;
-    use futures::pin_mut;
-    use futures::select;
-    use futures_timer::Delay;
 
     use super::Stabilization;
     use super::TStabilize;
@@ -111,20 +707,7 @@ mod stabilizer {
     #[async_trait]
     impl TStabilize for Stabilization {
         async fn wait(self: Arc<Self>) {
-            loop {
-                let timeout = Delay::new(Duration::from_secs(self.timeout as u64)).fuse();
-                pin_mut!(timeout);
-                select! {
-                    _ = timeout => {
-                        match self.stabilize().await {
-                            Ok(()) => {},
-                            Err(e) => {
-                                log::error!("failed to stabilize {:?}", e);
-                            }
-                        };
-                    }
-                }
-            }
+            self.run().await
         }
     }
 }
@@ -134,23 +717,14 @@ mod stabilizer {
     use std::sync::Arc;
 
     use async_trait::async_trait;
-    use wasm_bindgen_futures::spawn_local;
 
     use super::Stabilization;
     use super::TStabilize;
-    use crate::poll;
 
     #[async_trait(?Send)]
     impl TStabilize for Stabilization {
         async fn wait(self: Arc<Self>) {
-            let caller = Arc::clone(&self);
-            let func = move || {
-                let caller = caller.clone();
-                spawn_local(Box::pin(async move {
-                    caller.stabilize().await.unwrap();
-                }))
-            };
-            poll!(func, 25000);
+            self.spawn();
         }
     }
 }
@@ -51,10 +51,27 @@ impl Successor {
         self.successors.truncate(self.max.into());
     }
 
+    /// `successor` would be kept by [Successor::update].
+    pub fn would_take(&self, successor: Did) -> bool {
+        if self.successors.contains(&successor) || successor == self.id {
+            return false;
+        }
+        self.successors.len() < self.capacity() || successor - self.id < self.max() - self.id
+    }
+
+    /// Successors kept at most.
+    pub fn capacity(&self) -> usize {
+        self.max.into()
+    }
+
     pub fn list(&self) -> Vec<Did> {
         self.successors.clone()
     }
 
+    pub fn clear(&mut self) {
+        self.successors.clear()
+    }
+
     pub fn remove(&mut self, id: Did) {
         self.successors.retain(|v| *v == id);
     }
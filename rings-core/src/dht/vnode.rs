@@ -11,9 +11,17 @@ use crate::dht::Did;
 use crate::ecc::HashStr;
 use crate::err::Error;
 use crate::err::Result;
+use crate::group::GroupKeyRecord;
+use crate::group::GroupRecord;
+use crate::manifest::ManifestRecord;
 use crate::message::Encoded;
 use crate::message::Encoder;
 use crate::message::MessagePayload;
+use crate::presence::PresenceRecord;
+use crate::pubkey::PubkeyRecord;
+use crate::rotation::RotationRecord;
+use crate::service::ServiceRecord;
+use crate::topic::TopicRecord;
 
 /// VNode Types
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -24,6 +32,26 @@ pub enum VNodeType {
     SubRing,
     /// RelayMessage: A Relayed but unreach message, which is stored on it's successor
     RelayMessage,
+    /// Inbox: Messages to an offline node, pulled by it when it's online
+    Inbox,
+    /// Presence: Signed and TTL-limited record of an online node, see [crate::presence]
+    Presence,
+    /// Group: Membership of a group signed by its admin, see [crate::group]
+    Group,
+    /// GroupKey: Key of a group wrapped to each member, signed by its admin, see [crate::group]
+    GroupKey,
+    /// Service: Signed records of providers of a service, see [crate::service]
+    Service,
+    /// Manifest: Self-signed description of a node, see [crate::manifest]
+    Manifest,
+    /// Erasure: How a large value is erasure coded into shards, see [crate::erasure]
+    Erasure,
+    /// Topic: Append-only log of signed records of publishers, see [crate::topic]
+    Topic,
+    /// Pubkey: Self-signed public key of a DID, see [crate::pubkey]
+    Pubkey,
+    /// Rotation: Identity rotation signed by both old and new DIDs, see [crate::rotation]
+    Rotation,
 }
 
 /// A Virtual Node is a Node that dont have real network address.
@@ -46,6 +74,69 @@ impl VirtualNode {
     pub fn did(&self) -> Did {
         self.address
     }
+
+    /// Address of inbox of `did`, which is sha1 of `inbox:{did}`.
+    pub fn inbox_address(did: Did) -> Result<Did> {
+        let address: HashStr = format!("inbox:{:?}", *did).into();
+        Did::try_from(address)
+    }
+
+    /// Address of presence record of `did`, which is sha1 of `presence:{did}`.
+    pub fn presence_address(did: Did) -> Result<Did> {
+        let address: HashStr = format!("presence:{:?}", *did).into();
+        Did::try_from(address)
+    }
+
+    /// Address of topic `name`, which is sha1 of `topic:{name}`.
+    pub fn topic_address(name: &str) -> Result<Did> {
+        let address: HashStr = format!("topic:{}", name).into();
+        Did::try_from(address)
+    }
+
+    /// Address of membership of group `name`, which is sha1 of `group:{name}`.
+    pub fn group_address(name: &str) -> Result<Did> {
+        let address: HashStr = format!("group:{}", name).into();
+        Did::try_from(address)
+    }
+
+    /// Address of key of group `name`, which is sha1 of `group_key:{name}`.
+    pub fn group_key_address(name: &str) -> Result<Did> {
+        let address: HashStr = format!("group_key:{}", name).into();
+        Did::try_from(address)
+    }
+
+    /// Address of providers of service `name`, which is sha1 of `service:{name}`.
+    pub fn service_address(name: &str) -> Result<Did> {
+        let address: HashStr = format!("service:{}", name).into();
+        Did::try_from(address)
+    }
+
+    /// Address of manifest of `did`, which is sha1 of `manifest:{did}`.
+    pub fn manifest_address(did: Did) -> Result<Did> {
+        let address: HashStr = format!("manifest:{:?}", *did).into();
+        Did::try_from(address)
+    }
+
+    /// Address of public key of `did`, which is sha1 of `pubkey:{did}`.
+    pub fn pubkey_address(did: Did) -> Result<Did> {
+        let address: HashStr = format!("pubkey:{:?}", *did).into();
+        Did::try_from(address)
+    }
+
+    /// Address of rotation of `did`, which is sha1 of `rotation:{did}`.
+    pub fn rotation_address(did: Did) -> Result<Did> {
+        let address: HashStr = format!("rotation:{:?}", *did).into();
+        Did::try_from(address)
+    }
+
+    /// Inbox of `recipient` with encoded messages.
+    pub fn inbox(recipient: Did, data: Vec<Encoded>) -> Result<Self> {
+        Ok(Self {
+            address: Self::inbox_address(recipient)?,
+            data,
+            kind: VNodeType::Inbox,
+        })
+    }
 }
 
 impl<T> TryFrom<MessagePayload<T>> for VirtualNode
@@ -84,12 +175,29 @@ impl TryFrom<String> for VirtualNode {
 }
 
 impl VirtualNode {
+    /// Size of encoded data, in bytes.
+    pub fn size(&self) -> usize {
+        self.data.iter().map(|d| d.len()).sum()
+    }
+
+    /// Check a record proving itself before it's first stored, later ones are checked on
+    /// merge by [VirtualNode::concat].
+    pub fn check(&self) -> Result<()> {
+        match self.kind {
+            VNodeType::Manifest => ManifestRecord::check_vnode(self).map(|_| ()),
+            VNodeType::Pubkey => PubkeyRecord::check_vnode(self).map(|_| ()),
+            VNodeType::Rotation => RotationRecord::check_vnode(self).map(|_| ()),
+            VNodeType::Topic => TopicRecord::check_vnode(self).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
     /// concat data of a virtual Node
     /// We do not needs to check the type of VNode because two VNode with same address but
     /// has different Type is incapable
     pub fn concat(a: &Self, b: &Self) -> Result<Self> {
         match &a.kind {
-            VNodeType::RelayMessage => {
+            VNodeType::RelayMessage | VNodeType::Inbox => {
                 if a.address != b.address {
                     Err(Error::AddressNotEqual)
                 } else {
@@ -100,7 +208,15 @@ impl VirtualNode {
                     })
                 }
             }
-            VNodeType::Data => Ok(a.clone()),
+            VNodeType::Data | VNodeType::Erasure => Ok(a.clone()),
+            VNodeType::Presence => PresenceRecord::merge(a, b),
+            VNodeType::Group => GroupRecord::merge(a, b),
+            VNodeType::GroupKey => GroupKeyRecord::merge(a, b),
+            VNodeType::Service => ServiceRecord::merge(a, b),
+            VNodeType::Manifest => ManifestRecord::merge(a, b),
+            VNodeType::Topic => TopicRecord::merge(a, b),
+            VNodeType::Pubkey => PubkeyRecord::merge(a, b),
+            VNodeType::Rotation => RotationRecord::merge(a, b),
             VNodeType::SubRing => {
                 // if subring exists, just join creator to new subring
                 let decoded_a: String = a.data[0].decode()?;
@@ -11,9 +11,9 @@ use serde::Deserialize;
 use serde::Serialize;
 use sha1::Digest;
 use sha1::Sha1;
-use web3::signing::keccak256;
-use web3::types::Address;
 
+use crate::address::keccak256;
+use crate::address::Address;
 use crate::err::Error;
 use crate::err::Result;
 pub mod elgamal;
@@ -1,6 +1,5 @@
 //! Signer for default ECDSA and EIP712
-use web3::signing::keccak256;
-
+use crate::address::keccak256;
 use crate::ecc::Address;
 use crate::ecc::PublicKey;
 use crate::ecc::SecretKey;
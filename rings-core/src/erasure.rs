@@ -0,0 +1,386 @@
+//! Erasure coded storage of large values.
+//!
+//! A value is split into `data_shards` shards, and `parity_shards` more are computed by
+//! Reed-Solomon code over GF(2^8) of `reed-solomon-erasure`, so any `data_shards` of all
+//! shards can reconstruct the value. Manifest keeps digest of every shard, a shard failing it
+//! is dropped before reconstructing, so a corrupted shard costs one of parity shards, like a
+//! lost one. Shards are stored as [VirtualNode]s at addresses derived from
+//! address of [ErasureManifest], see [ErasureManifest::shard_address], which are spread on the
+//! ring like any hash, so they are most likely stored by distinct nodes.
+//!
+//! Compared to storing copies of a value, it survives losing `parity_shards` nodes, with
+//! `(data_shards + parity_shards) / data_shards` times of storage.
+//!
+//! Manifest is stored at address of sha1 of `erasure:{digest}`, where digest is sha256 of
+//! value, so it's content addressed like [VNodeType::Data]. Use
+//! [MessageHandler::store_erasure] to store a value, and [MessageHandler::fetch_erasure] to
+//! fetch and reconstruct it.
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::dht::vnode::VNodeType;
+use crate::dht::vnode::VirtualNode;
+use crate::dht::Did;
+use crate::ecc::HashStr;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::Decoder;
+use crate::message::Encoder;
+use crate::message::MessageHandler;
+use crate::message::TChordStorage;
+
+/// Data shards of a value, suggested.
+pub const DEFAULT_DATA_SHARDS: usize = 4;
+/// Parity shards of a value, suggested.
+pub const DEFAULT_PARITY_SHARDS: usize = 2;
+/// Shards of a value at most, data and parity shards.
+pub const MAX_SHARDS: usize = 64;
+
+fn check_params(data_shards: usize, parity_shards: usize) -> Result<()> {
+    if data_shards == 0 || data_shards + parity_shards > MAX_SHARDS {
+        return Err(Error::InvalidErasureShards(data_shards, parity_shards));
+    }
+    Ok(())
+}
+
+/// Codec of `parity_shards` over `data_shards`, None without parity shards.
+fn codec(data_shards: usize, parity_shards: usize) -> Result<Option<ReedSolomon>> {
+    check_params(data_shards, parity_shards)?;
+    if parity_shards == 0 {
+        return Ok(None);
+    }
+    ReedSolomon::new(data_shards, parity_shards)
+        .map(Some)
+        .map_err(|_| Error::InvalidErasureShards(data_shards, parity_shards))
+}
+
+/// Split `value` into `data_shards` shards of the same size, padded with zeros, followed by
+/// `parity_shards` parity shards.
+pub fn encode(value: &[u8], data_shards: usize, parity_shards: usize) -> Result<Vec<Vec<u8>>> {
+    let codec = codec(data_shards, parity_shards)?;
+    let shard_len = ((value.len() + data_shards - 1) / data_shards).max(1);
+    let mut shards = (0..data_shards + parity_shards)
+        .map(|i| {
+            let mut shard = value
+                .iter()
+                .skip(i * shard_len)
+                .take(shard_len)
+                .copied()
+                .collect::<Vec<_>>();
+            shard.resize(shard_len, 0);
+            shard
+        })
+        .collect::<Vec<_>>();
+    if let Some(codec) = codec {
+        codec
+            .encode(&mut shards)
+            .map_err(|_| Error::InvalidErasureShard)?;
+    }
+    Ok(shards)
+}
+
+/// Reconstruct value of `len` bytes from `shards` by their index, None for missing ones.
+/// Any `data_shards` of them are enough, all present ones should be valid.
+pub fn reconstruct(
+    shards: &[Option<Vec<u8>>],
+    data_shards: usize,
+    parity_shards: usize,
+    len: usize,
+) -> Result<Vec<u8>> {
+    let codec = codec(data_shards, parity_shards)?;
+    let mut shards = shards
+        .iter()
+        .cloned()
+        .chain(std::iter::repeat(None))
+        .take(data_shards + parity_shards)
+        .collect::<Vec<_>>();
+    let present = shards.iter().filter(|s| s.is_some()).count();
+    if present < data_shards {
+        return Err(Error::ErasureShardsMissing(present, data_shards));
+    }
+    if let Some(codec) = codec {
+        codec
+            .reconstruct_data(&mut shards)
+            .map_err(|_| Error::InvalidErasureShard)?;
+    }
+    let mut value = shards
+        .into_iter()
+        .take(data_shards)
+        .collect::<Option<Vec<_>>>()
+        .ok_or(Error::InvalidErasureShard)?
+        .concat();
+    if value.len() < len {
+        return Err(Error::InvalidErasureShard);
+    }
+    value.truncate(len);
+    Ok(value)
+}
+
+/// Describes how a value is erasure coded, stored as [VNodeType::Erasure].
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ErasureManifest {
+    /// Length of value.
+    pub len: usize,
+    pub data_shards: usize,
+    pub parity_shards: usize,
+    /// Hex of sha256 of value.
+    pub digest: String,
+    /// Hex of sha256 of every shard, by index.
+    pub shard_digests: Vec<String>,
+}
+
+impl ErasureManifest {
+    /// Erasure code `value`, returns its manifest and shards to store.
+    pub fn split(
+        value: &[u8],
+        data_shards: usize,
+        parity_shards: usize,
+    ) -> Result<(Self, Vec<VirtualNode>)> {
+        let shards = encode(value, data_shards, parity_shards)?;
+        let manifest = Self {
+            len: value.len(),
+            data_shards,
+            parity_shards,
+            digest: hex::encode(Sha256::digest(value)),
+            shard_digests: shards
+                .iter()
+                .map(|s| hex::encode(Sha256::digest(s)))
+                .collect(),
+        };
+        let address = manifest.address()?;
+        let shards = shards
+            .into_iter()
+            .enumerate()
+            .map(|(i, shard)| {
+                Ok(VirtualNode {
+                    address: Self::shard_address(address, i)?,
+                    data: vec![shard.encode()?],
+                    kind: VNodeType::Data,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok((manifest, shards))
+    }
+
+    /// Address of manifest, which is sha1 of `erasure:{digest}`.
+    pub fn address(&self) -> Result<Did> {
+        let address: HashStr = format!("erasure:{}", self.digest).into();
+        Did::try_from(address)
+    }
+
+    /// Address of shard `index` of manifest at `address`, which is sha1 of
+    /// `erasure:{address}:{index}`.
+    pub fn shard_address(address: Did, index: usize) -> Result<Did> {
+        let address: HashStr = format!("erasure:{:?}:{}", *address, index).into();
+        Did::try_from(address)
+    }
+
+    /// Addresses of all shards, data shards first.
+    pub fn shard_addresses(&self) -> Result<Vec<Did>> {
+        let address = self.address()?;
+        (0..self.data_shards + self.parity_shards)
+            .map(|i| Self::shard_address(address, i))
+            .collect()
+    }
+
+    pub fn to_vnode(&self) -> Result<VirtualNode> {
+        let data = serde_json::to_string(self)
+            .map_err(Error::Serialize)?
+            .encode()?;
+        Ok(VirtualNode {
+            address: self.address()?,
+            data: vec![data],
+            kind: VNodeType::Erasure,
+        })
+    }
+
+    pub fn from_vnode(vnode: &VirtualNode) -> Result<Self> {
+        if vnode.kind != VNodeType::Erasure {
+            return Err(Error::InvalidVNodeType);
+        }
+        let encoded = vnode.data.first().ok_or(Error::InvalidVNodeType)?;
+        let s = String::from_encoded(encoded)?;
+        let manifest: Self = serde_json::from_str(&s).map_err(Error::Deserialize)?;
+        // manifest of another value may be replayed at this address
+        if manifest.address()? != vnode.address
+            || manifest.shard_digests.len() != manifest.data_shards + manifest.parity_shards
+        {
+            return Err(Error::InvalidVNodeType);
+        }
+        Ok(manifest)
+    }
+
+    /// Decode shard `index`, fails if it's of another address or doesn't match its digest.
+    pub fn decode_shard(&self, index: usize, vnode: &VirtualNode) -> Result<Vec<u8>> {
+        let digest = self
+            .shard_digests
+            .get(index)
+            .ok_or(Error::InvalidErasureShard)?;
+        if vnode.address != Self::shard_address(self.address()?, index)? {
+            return Err(Error::InvalidErasureShard);
+        }
+        let encoded = vnode.data.first().ok_or(Error::InvalidErasureShard)?;
+        let shard = Vec::<u8>::from_encoded(encoded)?;
+        if hex::encode(Sha256::digest(&shard)) != *digest {
+            return Err(Error::InvalidErasureShard);
+        }
+        Ok(shard)
+    }
+
+    /// Shards of `shards` by their index, None for missing ones and ones failing
+    /// [Self::decode_shard].
+    pub fn valid_shards(&self, shards: &[Option<VirtualNode>]) -> Vec<Option<Vec<u8>>> {
+        shards
+            .iter()
+            .enumerate()
+            .map(|(i, v)| v.as_ref().and_then(|v| self.decode_shard(i, v).ok()))
+            .collect()
+    }
+
+    /// Reconstruct value from `shards` by their index, None for missing ones, and check its
+    /// digest. Any `data_shards` valid ones of them are enough, see [Self::valid_shards].
+    pub fn join(&self, shards: &[Option<VirtualNode>]) -> Result<Vec<u8>> {
+        let shards = self.valid_shards(shards);
+        let value = reconstruct(&shards, self.data_shards, self.parity_shards, self.len)?;
+        if hex::encode(Sha256::digest(&value)) != self.digest {
+            return Err(Error::InvalidErasureShard);
+        }
+        Ok(value)
+    }
+}
+
+impl MessageHandler {
+    /// Store `value` erasure coded, see module doc. Returns its manifest, whose address is the
+    /// key to fetch it.
+    pub async fn store_erasure(
+        &self,
+        value: &[u8],
+        data_shards: usize,
+        parity_shards: usize,
+    ) -> Result<ErasureManifest> {
+        let (manifest, shards) = ErasureManifest::split(value, data_shards, parity_shards)?;
+        for shard in shards {
+            self.store(shard).await?;
+        }
+        self.store(manifest.to_vnode()?).await?;
+        Ok(manifest)
+    }
+
+    /// Fetch value erasure coded at `key` to cache, reconstruct it once enough shards are
+    /// fetched. Returns None if it's not ready, call it again later, missing shards are
+    /// fetched again.
+    pub async fn fetch_erasure(&self, key: &Did) -> Result<Option<Vec<u8>>> {
+        let manifest = match self.check_cache(key).await {
+            Some(v) => ErasureManifest::from_vnode(&v)?,
+            None => {
+                self.fetch(key).await?;
+                return Ok(None);
+            }
+        };
+        let mut shards = vec![];
+        for address in manifest.shard_addresses()? {
+            shards.push(self.check_cache(&address).await);
+        }
+        let valid = manifest.valid_shards(&shards);
+        if valid.iter().filter(|s| s.is_some()).count() >= manifest.data_shards {
+            return manifest.join(&shards).map(Some);
+        }
+        for (address, _) in manifest
+            .shard_addresses()?
+            .into_iter()
+            .zip(valid.iter())
+            .filter(|(_, s)| s.is_none())
+        {
+            self.fetch(&address).await?;
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconstruct() {
+        let value = (0..1000u32).map(|i| (i * 7) as u8).collect::<Vec<_>>();
+        let shards = encode(&value, 4, 2).unwrap();
+        assert_eq!(shards.len(), 6);
+        assert_eq!(shards[0], value[..250].to_vec());
+
+        // any 4 of 6 shards
+        for lost in [(0, 1), (2, 5), (4, 5), (0, 3)] {
+            let partial = shards
+                .iter()
+                .enumerate()
+                .map(|(i, s)| Some(s.clone()).filter(|_| i != lost.0 && i != lost.1))
+                .collect::<Vec<_>>();
+            assert_eq!(reconstruct(&partial, 4, 2, value.len()).unwrap(), value);
+        }
+        let partial = shards
+            .iter()
+            .enumerate()
+            .map(|(i, s)| Some(s.clone()).filter(|_| i > 2))
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            reconstruct(&partial, 4, 2, value.len()),
+            Err(Error::ErasureShardsMissing(3, 4))
+        ));
+        assert_eq!(
+            reconstruct(
+                &shards[..4].iter().cloned().map(Some).collect::<Vec<_>>(),
+                4,
+                0,
+                1000
+            )
+            .unwrap(),
+            value
+        );
+        assert!(encode(&value, 0, 2).is_err());
+        assert!(encode(&value, 60, 10).is_err());
+    }
+
+    fn shards_of(manifest: &ErasureManifest, value: &[u8]) -> Vec<Option<VirtualNode>> {
+        let (m, shards) =
+            ErasureManifest::split(value, manifest.data_shards, manifest.parity_shards).unwrap();
+        assert_eq!(&m, manifest);
+        shards.into_iter().map(Some).collect()
+    }
+
+    #[test]
+    fn test_erasure_manifest() {
+        let value = b"Across the Great Wall we can reach every corner in the world.".to_vec();
+        let (manifest, shards) = ErasureManifest::split(&value, 3, 2).unwrap();
+        assert_eq!(shards.len(), 5);
+        let vnode = manifest.to_vnode().unwrap();
+        assert_eq!(ErasureManifest::from_vnode(&vnode).unwrap(), manifest);
+
+        let mut partial = shards.into_iter().map(Some).collect::<Vec<_>>();
+        partial[0] = None;
+        partial[3] = None;
+        assert_eq!(manifest.join(&partial).unwrap(), value);
+
+        // shard of another address is ignored
+        let mut moved = partial.clone();
+        moved[1].as_mut().unwrap().address = manifest.address().unwrap();
+        assert!(manifest.join(&moved).is_err());
+
+        // a corrupted shard is dropped, any other 3 valid shards reconstruct it
+        let mut corrupted = shards_of(&manifest, &value);
+        corrupted[1].as_mut().unwrap().data = vec![vec![0u8; 21].encode().unwrap()];
+        corrupted[4] = None;
+        assert!(manifest
+            .decode_shard(1, corrupted[1].as_ref().unwrap())
+            .is_err());
+        assert_eq!(manifest.valid_shards(&corrupted)[1], None);
+        assert_eq!(manifest.join(&corrupted).unwrap(), value);
+        corrupted[0] = None;
+        assert!(matches!(
+            manifest.join(&corrupted),
+            Err(Error::ErasureShardsMissing(2, 3))
+        ));
+    }
+}
@@ -24,6 +24,9 @@ pub enum Error {
     #[error("Invalid rustc hexadecimal id in directory cache")]
     BadCHexInCache,
 
+    #[error("Invalid DID {0:?}, {1}")]
+    InvalidDid(String, String),
+
     #[error("URL parse error")]
     URLParse(#[from] url::ParseError),
 
@@ -54,6 +57,15 @@ pub enum Error {
     #[error("Gzip decode error.")]
     GzipDecode,
 
+    #[error("Compression error: {0}")]
+    CompressionError(String),
+
+    #[error("Decompressed data exceeds limit of {0} bytes")]
+    DecompressedTooLarge(usize),
+
+    #[error("Codec {0} is not supported by this build")]
+    UnsupportedCodec(String),
+
     #[error("Failed on promise, state not successed")]
     PromiseStateFailed,
 
@@ -114,8 +126,11 @@ pub enum Error {
     #[error("Cannot seek address in swarm table")]
     SwarmMissAddressInTable,
 
+    #[error("Too many payloads waiting to be sent to {0}")]
+    OutboxFull(Address),
+
     #[error("Cannot get transport from address: {0}")]
-    SwarmMissTransport(web3::types::Address),
+    SwarmMissTransport(crate::address::Address),
 
     #[error("Load message failed with message: {0}")]
     SwarmLoadMessageRecvFailed(String),
@@ -132,6 +147,9 @@ pub enum Error {
     #[error("failed to close previous when registering, {0}")]
     SwarmToClosePrevTransport(String),
 
+    #[error("Swarm is draining, new connections and messages are refused")]
+    SwarmDraining,
+
     #[error("call lock() failed")]
     SessionTryLockFailed,
 
@@ -248,6 +266,9 @@ pub enum Error {
     #[error("Only SEND message can reset destination")]
     ResetDestinationNeedSend,
 
+    #[error("Only REPORT message can detour")]
+    DetourNeedReport,
+
     #[cfg(feature = "wasm")]
     #[error("IndexedDB error, {0}")]
     IDBError(rexie::Error),
@@ -261,6 +282,182 @@ pub enum Error {
 
     #[error("entry not found")]
     EntryNotFound,
+
+    #[error("Invalid history cursor: {0}")]
+    InvalidHistoryCursor(String),
+
+    #[error("Invalid chunk {0} of file")]
+    InvalidFileChunk(usize),
+
+    #[error("Manifest should describe the node signing it")]
+    InvalidManifest,
+
+    #[error("Public key of {0} not found")]
+    PubkeyNotFound(String),
+
+    #[error("Pubkey record should be signed by its DID and key, and stored at its address")]
+    InvalidPubkeyRecord,
+
+    #[error("Messages stored in inbox should be encrypted to recipient")]
+    InboxNotEncrypted,
+
+    #[error("Delegation chain of session is longer than {0} links")]
+    DelegationTooLong(usize),
+
+    #[error("Rotation should be signed by both old and new identities")]
+    InvalidRotation,
+
+    #[error("Topic record is not signed by its publisher, or not of the topic")]
+    InvalidTopicRecord,
+
+    #[error("Peer sample is not led by its sender, or too large")]
+    InvalidPeerSample,
+
+    #[error("Too many relayed messages to {0} are not acknowledged")]
+    RelayWindowFull(String),
+
+    #[error("Relaying for {0} is denied by its budget or relay policy")]
+    RelayDenied(String),
+
+    #[error("Relayed message exceeds max hops")]
+    RelayHopsExceeded,
+
+    #[error("Routed message is expired")]
+    RoutedMessageExpired,
+
+    #[error("Only application messages can be relayed")]
+    RelayedMessageNotAllowed,
+
+    #[error("Origin of relayed message is not its signer")]
+    RelayedOriginMismatch,
+
+    #[error("Acknowledgement of relayed message {0} is not from its destination")]
+    InvalidRelayedAck(u64),
+
+    #[error("Receipt of message {0} is not from its destination")]
+    InvalidDeliveryReceipt(String),
+
+    #[error("Too many messages wait for receipts")]
+    TooManyPendingReceipts,
+
+    #[error("Stabilization task is stopped")]
+    StabilizationStopped,
+
+    #[error("Too many messages wait for dialing peer {0}")]
+    DialQueueFull(String),
+
+    #[error("Timeout of dialing peer {0}")]
+    DialTimeout(String),
+
+    #[error("Failed to dial peer {0}")]
+    DialFailed(String),
+
+    #[error("Timeout of connecting peer {0}, no answer through alternate path either")]
+    ConnectTimeout(String),
+
+    #[error("Invalid fault config: {0}")]
+    InvalidFaultConfig(String),
+
+    #[error("Topology report is not signed by the node it describes")]
+    InvalidTopologyReport,
+
+    #[error("Topology report answers no query sent")]
+    UnsolicitedTopologyReport,
+
+    #[error("Store report is not signed by the node storing or rejecting virtual nodes")]
+    InvalidStoreReport,
+
+    #[error("Challenge report is not signed by the node challenged")]
+    InvalidChallengeReport,
+
+    #[error("Invalid erasure coding of {0} data shards and {1} parity shards")]
+    InvalidErasureShards(usize, usize),
+
+    #[error("Erasure coded value needs {1} shards, only {0} are found")]
+    ErasureShardsMissing(usize, usize),
+
+    #[error("Invalid shard of erasure coded value")]
+    InvalidErasureShard,
+
+    #[error("Too many storage challenges pending")]
+    TooManyChallenges,
+
+    #[error("Group record should be signed by its admin, and stored at address of its name")]
+    InvalidGroupRecord,
+
+    #[error("Group key is not shared with {0}")]
+    GroupKeyNotShared(String),
+
+    #[error("Invalid group key, or message not sealed by it")]
+    InvalidGroupKey,
+
+    #[error("Invalid tag of peer: {0}")]
+    InvalidPeerTag(String),
+
+    #[error("Peer {0} is denied by ACL")]
+    PeerDenied(String),
+
+    #[error("Offer relayed by {0} is refused")]
+    OfferRefused(String),
+
+    #[error("Public key of peer {0} differs from the one of first contact")]
+    PeerKeyMismatch(String),
+
+    #[error("Identity {0} is already on the ring by another device")]
+    DuplicateIdentity(String),
+
+    #[error("Network id mismatch, remote: {0}, local: {1}")]
+    NetworkIdMismatch(String, String),
+
+    #[error("Protocol version incompatible, remote: {0}, local: {1}")]
+    ProtocolVersionIncompatible(String, String),
+
+    #[error("Failed to encrypt persisted state")]
+    EncryptAtRest,
+
+    #[error("Failed to decrypt persisted state, wrong key or corrupted data")]
+    DecryptAtRest,
+
+    #[error("Failed to load salt of storage password: {0}")]
+    StorageSalt(String),
+
+    #[error("Failed to check clock: {0}")]
+    ClockCheck(String),
+
+    #[error("Payload {0} is older than replay window")]
+    StalePayload(String),
+
+    #[error("Payload {0} is replayed")]
+    ReplayedPayload(String),
+
+    #[error("Transport is not connected in {0}ms")]
+    TransportConnectTimeout(u64),
+
+    #[error("Waiting for transport is cancelled")]
+    TransportConnectCancelled,
+
+    #[error("Handshake info of {0} bytes exceeds limit of {1} bytes")]
+    HandshakeInfoTooLarge(usize, usize),
+
+    #[error("Handshake info is not signed by {0}, the node it claims to be")]
+    HandshakeAddressMismatch(String),
+
+    #[error("Invalid SDP of handshake info: {0}")]
+    InvalidSdp(String),
+
+    #[error("Invalid candidates of handshake info: {0}")]
+    InvalidCandidates(String),
+
+    #[error("Failed to join verification of payload, {0}")]
+    VerifyPoolJoin(String),
+
+    #[cfg(feature = "sim")]
+    #[error("Simulation invariant violated, {0}")]
+    SimInvariantViolated(String),
+
+    #[cfg(feature = "sim")]
+    #[error("Failed to write simulation report, {0}")]
+    SimReport(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,216 @@
+//! Files shared via DHT.
+//!
+//! A file is split into chunks, each chunk is stored as an immutable [VNodeType::Data]
+//! virtual node, addressed by hash of its content. A [FileManifest] listing the chunks is
+//! stored the same way, and its address is the id of file. Since every chunk is verified by
+//! its address, a transfer can resume from the chunks already fetched.
+//!
+//! [FileTransfers] keeps progress of transfers, and pushes [TransferProgress] to subscribers.
+use std::sync::Mutex;
+
+use dashmap::DashMap;
+use futures::channel::mpsc;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::dht::vnode::VNodeType;
+use crate::dht::vnode::VirtualNode;
+use crate::dht::Did;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::Decoder;
+use crate::message::Encoded;
+use crate::message::Encoder;
+
+/// Size of chunks, small enough to be sent in one payload.
+pub const DEFAULT_CHUNK_SIZE: usize = 16 * 1024;
+
+/// Name, size and chunks of a file.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileManifest {
+    pub name: String,
+    /// Size in bytes.
+    pub size: u64,
+    pub chunk_size: usize,
+    /// Addresses of chunks in order.
+    pub chunks: Vec<Did>,
+}
+
+impl FileManifest {
+    /// Split `data` into chunks of `chunk_size`, returns manifest and chunks to store.
+    pub fn split(name: &str, data: &[u8], chunk_size: usize) -> Result<(Self, Vec<VirtualNode>)> {
+        let chunk_size = chunk_size.max(1);
+        let vnodes = data
+            .chunks(chunk_size)
+            .map(|c| VirtualNode::try_from(c.encode()?))
+            .collect::<Result<Vec<_>>>()?;
+        let manifest = Self {
+            name: name.to_owned(),
+            size: data.len() as u64,
+            chunk_size,
+            chunks: vnodes.iter().map(|v| v.address).collect(),
+        };
+        Ok((manifest, vnodes))
+    }
+
+    pub fn to_vnode(&self) -> Result<VirtualNode> {
+        serde_json::to_string(self)
+            .map_err(Error::Serialize)?
+            .try_into()
+    }
+
+    /// Decode manifest of file `id`, fails if the vnode is not the one addressed by `id`.
+    pub fn from_vnode(id: &Did, vnode: &VirtualNode) -> Result<Self> {
+        let encoded = verified_data(id, vnode)?;
+        let s = String::from_encoded(&encoded)?;
+        serde_json::from_str(&s).map_err(Error::Deserialize)
+    }
+
+    /// Id of file, which is address of manifest.
+    pub fn id(&self) -> Result<Did> {
+        Ok(self.to_vnode()?.address)
+    }
+
+    /// Decode chunk `index`, fails if content doesn't match its address.
+    pub fn decode_chunk(&self, index: usize, vnode: &VirtualNode) -> Result<Vec<u8>> {
+        let id = self
+            .chunks
+            .get(index)
+            .ok_or(Error::InvalidFileChunk(index))?;
+        let encoded = verified_data(id, vnode).map_err(|_| Error::InvalidFileChunk(index))?;
+        Vec::from_encoded(&encoded)
+    }
+}
+
+/// Data of a content addressed vnode, checked against its expected address.
+fn verified_data(id: &Did, vnode: &VirtualNode) -> Result<Encoded> {
+    if vnode.kind != VNodeType::Data {
+        return Err(Error::InvalidVNodeType);
+    }
+    let encoded = vnode.data.first().ok_or(Error::InvalidVNodeType)?;
+    if VirtualNode::try_from(encoded.clone())?.address != *id {
+        return Err(Error::AddressNotEqual);
+    }
+    Ok(encoded.clone())
+}
+
+/// Progress of fetching a file.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct TransferProgress {
+    pub file_id: Did,
+    pub name: String,
+    pub total_chunks: usize,
+    pub done_chunks: usize,
+    pub total_bytes: u64,
+    pub done_bytes: u64,
+}
+
+impl TransferProgress {
+    pub fn new(file_id: Did, manifest: &FileManifest) -> Self {
+        Self {
+            file_id,
+            name: manifest.name.clone(),
+            total_chunks: manifest.chunks.len(),
+            done_chunks: 0,
+            total_bytes: manifest.size,
+            done_bytes: 0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.done_chunks == self.total_chunks
+    }
+}
+
+/// Progress of transfers, and subscribers of their updates.
+#[derive(Default)]
+pub struct FileTransfers {
+    transfers: DashMap<Did, TransferProgress>,
+    subscribers: Mutex<Vec<mpsc::UnboundedSender<TransferProgress>>>,
+}
+
+impl FileTransfers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Receive updates of all transfers, drop the receiver to unsubscribe.
+    pub fn subscribe(&self) -> mpsc::UnboundedReceiver<TransferProgress> {
+        let (tx, rx) = mpsc::unbounded();
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(tx);
+        }
+        rx
+    }
+
+    /// Record progress of a transfer and notify subscribers.
+    pub fn update(&self, progress: TransferProgress) {
+        tracing::debug!(
+            file_id = ?progress.file_id,
+            done = progress.done_chunks,
+            total = progress.total_chunks,
+            "file transfer progress"
+        );
+        self.transfers.insert(progress.file_id, progress.clone());
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.retain(|s| s.unbounded_send(progress.clone()).is_ok());
+        }
+    }
+
+    pub fn get(&self, file_id: &Did) -> Option<TransferProgress> {
+        self.transfers.get(file_id).map(|p| p.clone())
+    }
+
+    /// Progress of all transfers since node started.
+    pub fn list(&self) -> Vec<TransferProgress> {
+        self.transfers.iter().map(|kv| kv.value().clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_and_decode() {
+        let data = (0..100u8).collect::<Vec<_>>();
+        let (manifest, chunks) = FileManifest::split("a.bin", &data, 30).unwrap();
+        assert_eq!(manifest.size, 100);
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(manifest.chunks.len(), 4);
+
+        let decoded = chunks
+            .iter()
+            .enumerate()
+            .flat_map(|(i, c)| manifest.decode_chunk(i, c).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(decoded, data);
+        // chunk in wrong place
+        assert!(manifest.decode_chunk(0, &chunks[1]).is_err());
+        assert!(manifest.decode_chunk(4, &chunks[0]).is_err());
+
+        let id = manifest.id().unwrap();
+        let vnode = manifest.to_vnode().unwrap();
+        assert_eq!(vnode.address, id);
+        assert_eq!(FileManifest::from_vnode(&id, &vnode).unwrap(), manifest);
+        assert!(FileManifest::from_vnode(&manifest.chunks[0], &vnode).is_err());
+    }
+
+    #[test]
+    fn test_file_transfers() {
+        let (manifest, _) = FileManifest::split("a.bin", &[1, 2, 3], 2).unwrap();
+        let id = manifest.id().unwrap();
+        let transfers = FileTransfers::new();
+        let mut rx = transfers.subscribe();
+
+        let mut progress = TransferProgress::new(id, &manifest);
+        transfers.update(progress.clone());
+        progress.done_chunks = 2;
+        progress.done_bytes = 3;
+        transfers.update(progress.clone());
+
+        assert_eq!(rx.try_next().unwrap().unwrap().done_chunks, 0);
+        assert!(rx.try_next().unwrap().unwrap().is_finished());
+        assert_eq!(transfers.get(&id), Some(progress));
+    }
+}
@@ -0,0 +1,228 @@
+//! Peer sampling by gossip, alongside Chord maintenance.
+//!
+//! Every round of stabilization a node sends a small random sample of peers it knows, led by
+//! itself, to a random connected peer, which answers with a sample of its own. Both merge what
+//! they receive into their [PeerView], a partial view of the network bounded in size, entries
+//! seen alive earliest are evicted first. Unlike fingers and successors, the view has peers
+//! from anywhere on the ring, it seeds bootstrap and crawlers.
+//!
+//! Only the entry of a sender about itself is first-hand, so a sample is taken only if it's
+//! led by its signer, see [verify_samples]. Peers it reports are hearsay, they are never
+//! taken as seen later than the sender itself, so a sender can't push real peers out of the
+//! view with entries fresher than any of them.
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::dht::Did;
+use crate::err::Error;
+use crate::err::Result;
+use crate::manifest::NodeManifest;
+use crate::utils;
+
+/// Peers kept in view by default.
+pub const DEFAULT_VIEW_SIZE: usize = 64;
+/// Peers sent in one sample.
+pub const DEFAULT_SAMPLE_SIZE: usize = 8;
+/// Peers not seen alive for this long are dropped from view, in milliseconds.
+pub const DEFAULT_SAMPLE_TTL_MS: u128 = 10 * 60 * 1000;
+
+/// A peer in a sample, with its liveness and capabilities.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PeerSample {
+    pub did: Did,
+    /// Latest time peer is seen alive, in milliseconds since epoch.
+    pub seen_ms: u128,
+    /// Peer advertised itself as relay capable.
+    pub relay: bool,
+    /// Optional components enabled on peer, see [NodeManifest::features].
+    pub features: Vec<String>,
+}
+
+impl PeerSample {
+    /// Sample of the node described by `manifest`, which is alive now.
+    pub fn of(manifest: &NodeManifest) -> Self {
+        Self {
+            did: manifest.did,
+            seen_ms: utils::get_epoch_ms(),
+            relay: manifest.relay,
+            features: manifest.features.clone(),
+        }
+    }
+}
+
+/// Check `samples` signed by `sender`, they should be led by the sender itself, at most
+/// [DEFAULT_SAMPLE_SIZE] of them. Reported peers are taken as seen no later than the sender.
+pub fn verify_samples(sender: Did, mut samples: Vec<PeerSample>) -> Result<Vec<PeerSample>> {
+    if samples.len() > DEFAULT_SAMPLE_SIZE {
+        return Err(Error::InvalidPeerSample);
+    }
+    let seen_ms = match samples.first() {
+        Some(s) if s.did == sender => s.seen_ms,
+        _ => return Err(Error::InvalidPeerSample),
+    };
+    for s in samples.iter_mut().skip(1) {
+        if s.did == sender {
+            return Err(Error::InvalidPeerSample);
+        }
+        s.seen_ms = s.seen_ms.min(seen_ms);
+    }
+    Ok(samples)
+}
+
+/// Partial view of the network, built by gossip.
+#[derive(Debug)]
+pub struct PeerView {
+    capacity: usize,
+    ttl_ms: u128,
+    peers: Mutex<BTreeMap<Did, PeerSample>>,
+}
+
+impl Default for PeerView {
+    fn default() -> Self {
+        Self::new(DEFAULT_VIEW_SIZE, DEFAULT_SAMPLE_TTL_MS)
+    }
+}
+
+impl PeerView {
+    /// Keep at most `capacity` peers, each for `ttl_ms` after it's seen alive.
+    pub fn new(capacity: usize, ttl_ms: u128) -> Self {
+        Self {
+            capacity,
+            ttl_ms,
+            peers: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Merge `samples` received by `local`, returns count of peers new to the view.
+    pub fn merge(&self, local: Did, samples: Vec<PeerSample>) -> usize {
+        self.merge_at(local, samples, utils::get_epoch_ms())
+    }
+
+    fn merge_at(&self, local: Did, samples: Vec<PeerSample>, now: u128) -> usize {
+        let mut peers = match self.peers.lock() {
+            Ok(p) => p,
+            Err(_) => return 0,
+        };
+        let mut added = 0;
+        // samples from future are not trusted to be fresher than now
+        for mut s in samples.into_iter().filter(|s| s.did != local) {
+            s.seen_ms = s.seen_ms.min(now);
+            match peers.get(&s.did) {
+                Some(old) if old.seen_ms >= s.seen_ms => {}
+                Some(_) => {
+                    peers.insert(s.did, s);
+                }
+                None => {
+                    peers.insert(s.did, s);
+                    added += 1;
+                }
+            }
+        }
+        peers.retain(|_, s| now.saturating_sub(s.seen_ms) < self.ttl_ms);
+        while peers.len() > self.capacity {
+            let oldest = peers.values().min_by_key(|s| s.seen_ms).map(|s| s.did);
+            match oldest {
+                Some(did) => peers.remove(&did),
+                None => break,
+            };
+        }
+        added
+    }
+
+    pub fn remove(&self, did: Did) -> Option<PeerSample> {
+        self.peers.lock().ok().and_then(|mut p| p.remove(&did))
+    }
+
+    /// At most `n` random peers of view.
+    pub fn sample(&self, n: usize) -> Vec<PeerSample> {
+        let peers = self.peers();
+        peers
+            .choose_multiple(&mut rand::thread_rng(), n)
+            .cloned()
+            .collect()
+    }
+
+    /// All peers of view, ordered by DID.
+    pub fn peers(&self) -> Vec<PeerSample> {
+        self.peers
+            .lock()
+            .map(|p| p.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.lock().map_or(0, |p| p.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    fn peer(seen_ms: u128, relay: bool) -> PeerSample {
+        PeerSample {
+            did: SecretKey::random().address().into(),
+            seen_ms,
+            relay,
+            features: vec![],
+        }
+    }
+
+    #[test]
+    fn test_peer_view() {
+        let view = PeerView::new(2, 1000);
+        let local: Did = SecretKey::random().address().into();
+        let a = peer(100, false);
+        let b = peer(200, true);
+        let mut me = peer(300, true);
+        me.did = local;
+        assert_eq!(view.merge_at(local, vec![a.clone(), b.clone(), me], 300), 2);
+        assert_eq!(view.len(), 2);
+
+        // fresher sample replaces, older one is ignored
+        let mut a2 = a.clone();
+        a2.seen_ms = 250;
+        assert_eq!(view.merge_at(local, vec![a2.clone()], 300), 0);
+        let mut a3 = a.clone();
+        a3.seen_ms = 50;
+        view.merge_at(local, vec![a3], 300);
+        assert!(view.peers().contains(&a2));
+
+        // full view evicts the peer seen earliest
+        let c = peer(280, false);
+        assert_eq!(view.merge_at(local, vec![c.clone()], 300), 1);
+        assert_eq!(view.len(), 2);
+        assert!(!view.peers().contains(&b));
+
+        // expired peers are dropped
+        view.merge_at(local, vec![], 1260);
+        assert_eq!(view.peers(), vec![c]);
+        assert_eq!(view.sample(8).len(), 1);
+    }
+
+    #[test]
+    fn test_verify_samples() {
+        let sender = peer(500, true);
+        let other = peer(900, false);
+        let verified = verify_samples(sender.did, vec![sender.clone(), other.clone()]).unwrap();
+        // hearsay is no fresher than the sender
+        assert_eq!(verified[1].seen_ms, 500);
+
+        // not led by the signer
+        assert!(verify_samples(sender.did, vec![other.clone(), sender.clone()]).is_err());
+        assert!(verify_samples(sender.did, vec![]).is_err());
+        // too many
+        let mut many = vec![sender.clone()];
+        many.extend((0..DEFAULT_SAMPLE_SIZE).map(|_| peer(100, false)));
+        assert!(verify_samples(sender.did, many).is_err());
+    }
+}
@@ -0,0 +1,553 @@
+//! Groups for group messaging.
+//!
+//! A group is a [SubRing] named after it, with a membership list managed by its admin.
+//! The list is stored as a [GroupRecord] at [VirtualNode::group_address], signed by admin,
+//! and only a newer record of the same admin replaces it. Messages to group are sent to each
+//! member as custom message carrying a [GroupMessage].
+//!
+//! Admin may establish a [GroupKey], which is wrapped to session key of each member by ElGamal
+//! and stored as a [GroupKeyRecord] at [VirtualNode::group_key_address]. Members unwrap it into
+//! their [GroupKeyring], and seal messages to group with it, so nodes relaying or storing them
+//! without being members see nothing but ciphertext. A new key is established by admin when
+//! membership changes, messages sealed by earlier keys stay readable by keys kept in keyring.
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use rand::RngCore;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::dht::subring::SubRing;
+use crate::dht::vnode::VNodeType;
+use crate::dht::vnode::VirtualNode;
+use crate::dht::Did;
+use crate::ecc::elgamal;
+use crate::ecc::CurveEle;
+use crate::ecc::PublicKey;
+use crate::ecc::SecretKey;
+use crate::err::Error;
+use crate::err::Result;
+use crate::record::RecordData;
+use crate::record::RecordSigner;
+use crate::record::SignedRecord;
+use crate::session::SessionManager;
+use crate::storage::StorageCipher;
+use crate::utils;
+
+/// Membership of a group.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    pub name: String,
+    /// Did of the subring of group.
+    pub subring: Did,
+    pub admin: Did,
+    /// Members of group, including admin.
+    pub members: Vec<Did>,
+}
+
+impl Group {
+    /// Create a group administrated by `admin`, who is always a member.
+    pub fn new(name: &str, admin: Did, members: &[Did]) -> Result<Self> {
+        let mut all = vec![admin];
+        for m in members {
+            if !all.contains(m) {
+                all.push(*m);
+            }
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            subring: SubRing::new(name, &admin)?.did,
+            admin,
+            members: all,
+        })
+    }
+
+    pub fn is_member(&self, did: &Did) -> bool {
+        self.members.contains(did)
+    }
+
+    /// Subring of group, with all members joined.
+    pub fn to_subring(&self) -> Result<SubRing> {
+        let mut subring = SubRing::new(&self.name, &self.admin)?;
+        subring.admin = Some(self.admin);
+        for m in self.members.iter() {
+            subring.finger.join(*m);
+        }
+        Ok(subring)
+    }
+}
+
+/// Group signed by its admin, membership never expires but is replaced by a newer record.
+pub type GroupRecord = SignedRecord<Group>;
+
+impl RecordData for Group {
+    const KIND: VNodeType = VNodeType::Group;
+
+    fn address(&self) -> Result<Did> {
+        VirtualNode::group_address(&self.name)
+    }
+
+    fn signers(&self) -> Vec<RecordSigner> {
+        vec![RecordSigner::Session(self.admin)]
+    }
+
+    fn invalid() -> Error {
+        Error::InvalidGroupRecord
+    }
+
+    /// Only a newer record of the same admin, so others can't take over the group.
+    fn replaces(stored: &GroupRecord, incoming: &GroupRecord) -> bool {
+        stored.data.admin == incoming.data.admin && incoming.ts_ms > stored.ts_ms
+    }
+}
+
+impl GroupRecord {
+    /// Sign `group`, session of `session_manager` should belong to its admin.
+    pub fn new(session_manager: &SessionManager, group: Group) -> Result<Self> {
+        Self::sign(group, usize::MAX, Some(session_manager), &[])
+    }
+
+    /// When membership is updated, in milliseconds since epoch.
+    pub fn updated_ms(&self) -> u128 {
+        self.ts_ms
+    }
+}
+
+/// Epochs of key kept for each group by [GroupKeyring].
+pub const KEPT_KEY_EPOCHS: usize = 4;
+
+/// Symmetric key shared by members of a group.
+#[derive(Clone)]
+pub struct GroupKey {
+    /// When key is generated, in milliseconds since epoch.
+    pub epoch: u128,
+    key: [u8; 32],
+}
+
+impl std::fmt::Debug for GroupKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GroupKey")
+            .field("epoch", &self.epoch)
+            .finish()
+    }
+}
+
+impl GroupKey {
+    pub fn random() -> Self {
+        let mut key = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+        Self {
+            epoch: utils::get_epoch_ms(),
+            key,
+        }
+    }
+
+    /// Encrypt `plain` sent to `group`, bound to name of it, see [StorageCipher::seal].
+    pub fn seal(&self, group: &str, plain: &[u8]) -> Result<Vec<u8>> {
+        StorageCipher::new(self.key)
+            .seal(group.as_bytes(), plain)
+            .map_err(|_| Error::InvalidGroupKey)
+    }
+
+    /// Decrypt what [GroupKey::seal] returned for the same `group`.
+    pub fn open(&self, group: &str, sealed: &[u8]) -> Result<Vec<u8>> {
+        StorageCipher::new(self.key)
+            .open(group.as_bytes(), sealed)
+            .map_err(|_| Error::InvalidGroupKey)
+    }
+}
+
+/// A [GroupKey] wrapped to session key of each member.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct GroupKeyShares {
+    /// Name of group.
+    pub group: String,
+    pub admin: Did,
+    /// Epoch of key, see [GroupKey::epoch].
+    pub epoch: u128,
+    /// Key encrypted to session public key of each member.
+    pub shares: Vec<(Did, Vec<(CurveEle, CurveEle)>)>,
+}
+
+/// Key shares signed by admin of group, replaced by a newer record of the same admin.
+pub type GroupKeyRecord = SignedRecord<GroupKeyShares>;
+
+impl RecordData for GroupKeyShares {
+    const KIND: VNodeType = VNodeType::GroupKey;
+
+    fn address(&self) -> Result<Did> {
+        VirtualNode::group_key_address(&self.group)
+    }
+
+    fn signers(&self) -> Vec<RecordSigner> {
+        vec![RecordSigner::Session(self.admin)]
+    }
+
+    fn invalid() -> Error {
+        Error::InvalidGroupKey
+    }
+
+    /// Only a later epoch of the same admin, like [Group::replaces].
+    fn replaces(stored: &GroupKeyRecord, incoming: &GroupKeyRecord) -> bool {
+        stored.data.admin == incoming.data.admin && incoming.data.epoch > stored.data.epoch
+    }
+}
+
+impl GroupKeyRecord {
+    /// Wrap `key` to session `members` of `group`, session of `session_manager` should belong
+    /// to admin of group. Those not in membership of group are skipped.
+    pub fn new(
+        session_manager: &SessionManager,
+        group: &Group,
+        key: &GroupKey,
+        members: &[(Did, PublicKey)],
+    ) -> Result<Self> {
+        let secret = hex::encode(key.key);
+        let shares = members
+            .iter()
+            .filter(|(did, _)| group.is_member(did))
+            .map(|(did, pubkey)| Ok((*did, elgamal::encrypt(&secret, pubkey)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let shares = GroupKeyShares {
+            group: group.name.clone(),
+            admin: group.admin,
+            epoch: key.epoch,
+            shares,
+        };
+        Self::sign(shares, usize::MAX, Some(session_manager), &[])
+    }
+
+    /// Members the key is shared with.
+    pub fn members(&self) -> Vec<Did> {
+        self.data.shares.iter().map(|(did, _)| *did).collect()
+    }
+
+    /// Unwrap key shared with `member`, by secret key of its session.
+    pub fn unwrap_key(&self, member: Did, session_key: &SecretKey) -> Result<GroupKey> {
+        let share = self
+            .data
+            .shares
+            .iter()
+            .find(|(did, _)| *did == member)
+            .map(|(_, share)| share)
+            .ok_or_else(|| Error::GroupKeyNotShared(format!("{:?}", *member)))?;
+        let secret = elgamal::decrypt(share, session_key).map_err(|_| Error::InvalidGroupKey)?;
+        let key = hex::decode(secret)
+            .ok()
+            .and_then(|k| <[u8; 32]>::try_from(k).ok())
+            .ok_or(Error::InvalidGroupKey)?;
+        Ok(GroupKey {
+            epoch: self.data.epoch,
+            key,
+        })
+    }
+}
+
+/// Keys of groups this node is a member of, latest [KEPT_KEY_EPOCHS] epochs for each. On
+/// native, keys can be persisted in sled with [GroupKeyring::open], sealed by a
+/// [StorageCipher], so messages to groups stay readable after restart.
+#[derive(Debug, Default)]
+pub struct GroupKeyring {
+    keys: Mutex<HashMap<String, BTreeMap<u128, GroupKey>>>,
+    #[cfg(not(feature = "wasm"))]
+    db: Option<(sled::Tree, StorageCipher)>,
+}
+
+/// Key of `epoch` of `group` in sled, name of group followed by epoch in big endian.
+#[cfg(not(feature = "wasm"))]
+fn db_key(group: &str, epoch: u128) -> Vec<u8> {
+    let mut k = group.as_bytes().to_vec();
+    k.extend_from_slice(&epoch.to_be_bytes());
+    k
+}
+
+impl GroupKeyring {
+    /// Keys kept in memory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open or create persisted keys at `path`, sealed by `cipher`. Fails if a key can't be
+    /// opened by `cipher`, like after password of node is changed.
+    #[cfg(not(feature = "wasm"))]
+    pub fn open<P: AsRef<std::path::Path>>(path: P, cipher: StorageCipher) -> Result<Self> {
+        let db = sled::open(path)
+            .and_then(|db| db.open_tree("group_keys"))
+            .map_err(Error::SledError)?;
+        let mut keys: HashMap<String, BTreeMap<u128, GroupKey>> = HashMap::new();
+        for kv in db.iter() {
+            let (k, v) = kv.map_err(Error::SledError)?;
+            let (group, epoch) = match k.len().checked_sub(16) {
+                Some(at) => k.split_at(at),
+                None => return Err(Error::InvalidGroupKey),
+            };
+            let group = String::from_utf8(group.to_vec()).map_err(|_| Error::InvalidGroupKey)?;
+            let epoch = u128::from_be_bytes(epoch.try_into().map_err(|_| Error::InvalidGroupKey)?);
+            let key =
+                <[u8; 32]>::try_from(cipher.open(&k, &v)?).map_err(|_| Error::InvalidGroupKey)?;
+            keys.entry(group)
+                .or_default()
+                .insert(epoch, GroupKey { epoch, key });
+        }
+        Ok(Self {
+            keys: Mutex::new(keys),
+            db: Some((db, cipher)),
+        })
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    fn persist(&self, group: &str, key: &GroupKey, evicted: &[u128]) -> Result<()> {
+        let (db, cipher) = match &self.db {
+            Some(db) => db,
+            None => return Ok(()),
+        };
+        let k = db_key(group, key.epoch);
+        db.insert(k.as_slice(), cipher.seal(&k, &key.key)?)
+            .map_err(Error::SledError)?;
+        for epoch in evicted {
+            db.remove(db_key(group, *epoch)).map_err(Error::SledError)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "wasm")]
+    fn persist(&self, _group: &str, _key: &GroupKey, _evicted: &[u128]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Keep `key` of `group`, keys of older epochs beyond [KEPT_KEY_EPOCHS] are dropped.
+    pub fn insert(&self, group: &str, key: GroupKey) -> Result<()> {
+        let mut evicted = vec![];
+        {
+            let mut keys = self.keys.lock().map_err(|_| Error::InvalidGroupKey)?;
+            let epochs = keys.entry(group.to_owned()).or_default();
+            epochs.insert(key.epoch, key.clone());
+            while epochs.len() > KEPT_KEY_EPOCHS {
+                let oldest = epochs.keys().next().cloned();
+                match oldest {
+                    Some(e) => {
+                        epochs.remove(&e);
+                        evicted.push(e);
+                    }
+                    None => break,
+                };
+            }
+        }
+        self.persist(group, &key, &evicted)
+    }
+
+    /// Latest key of `group`.
+    pub fn latest(&self, group: &str) -> Option<GroupKey> {
+        let keys = self.keys.lock().ok()?;
+        keys.get(group)?.values().last().cloned()
+    }
+
+    pub fn get(&self, group: &str, epoch: u128) -> Option<GroupKey> {
+        let keys = self.keys.lock().ok()?;
+        keys.get(group)?.get(&epoch).cloned()
+    }
+
+    /// Data of `msg`, decrypted if it's sealed.
+    pub fn open_message(&self, msg: &GroupMessage) -> Result<Vec<u8>> {
+        let epoch = match msg.epoch {
+            Some(e) => e,
+            None => return Ok(msg.data.clone()),
+        };
+        let key = self.get(&msg.group, epoch).ok_or(Error::InvalidGroupKey)?;
+        msg.open(&key)
+    }
+}
+
+/// Content of a custom message sent to group.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct GroupMessage {
+    /// Name of group.
+    pub group: String,
+    pub data: Vec<u8>,
+    /// Epoch of [GroupKey] sealing `data`, None if it's plain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epoch: Option<u128>,
+}
+
+impl GroupMessage {
+    /// Message of `data` to `group`, sealed by `key`.
+    pub fn sealed(group: &str, key: &GroupKey, data: &[u8]) -> Result<Self> {
+        Ok(Self {
+            group: group.to_owned(),
+            data: key.seal(group, data)?,
+            epoch: Some(key.epoch),
+        })
+    }
+
+    /// Decrypt data of message sealed by `key`.
+    pub fn open(&self, key: &GroupKey) -> Result<Vec<u8>> {
+        match self.epoch {
+            Some(e) if e == key.epoch => key.open(&self.group, &self.data),
+            Some(_) => Err(Error::InvalidGroupKey),
+            None => Ok(self.data.clone()),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        serde_json::to_vec(self).map_err(Error::Serialize)
+    }
+
+    /// Decode a received custom message, fails if it's not sent to a group.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        serde_json::from_slice(data).map_err(Error::Deserialize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    #[test]
+    fn test_group_record_merge() {
+        let admin = SecretKey::random();
+        let session = SessionManager::new_with_seckey(&admin).unwrap();
+        let member: Did = SecretKey::random().address().into();
+        let group = Group::new("rings", admin.address().into(), &[member, member]).unwrap();
+        assert_eq!(group.members, vec![admin.address().into(), member]);
+        assert_eq!(group.subring, SubRing::new("rings", &member).unwrap().did);
+
+        let record = GroupRecord::new(&session, group.clone()).unwrap();
+        assert!(record.verify());
+        let vnode = record.to_vnode().unwrap();
+        assert_eq!(GroupRecord::from_vnode(&vnode).unwrap(), record);
+
+        // others can't take over the group
+        let other = SecretKey::random();
+        let other_session = SessionManager::new_with_seckey(&other).unwrap();
+        let taken = Group::new("rings", other.address().into(), &[]).unwrap();
+        let taken = GroupRecord::new(&other_session, taken)
+            .unwrap()
+            .to_vnode()
+            .unwrap();
+        assert_eq!(GroupRecord::merge(&vnode, &taken).unwrap(), vnode);
+        let mut forged = record.clone();
+        forged.data.members.push(other.address().into());
+        let forged = forged.to_vnode().unwrap();
+        assert!(GroupRecord::merge(&vnode, &forged).is_err());
+
+        // admin updates membership
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let updated = Group::new("rings", admin.address().into(), &[]).unwrap();
+        let updated = GroupRecord::new(&session, updated)
+            .unwrap()
+            .to_vnode()
+            .unwrap();
+        assert_eq!(GroupRecord::merge(&vnode, &updated).unwrap(), updated);
+        assert_eq!(GroupRecord::merge(&updated, &vnode).unwrap(), updated);
+    }
+
+    #[test]
+    fn test_group_message() {
+        let msg = GroupMessage {
+            group: "rings".to_owned(),
+            data: "hello".as_bytes().to_vec(),
+            epoch: None,
+        };
+        assert_eq!(
+            GroupMessage::from_bytes(&msg.to_bytes().unwrap()).unwrap(),
+            msg
+        );
+        assert!(GroupMessage::from_bytes("hello".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_group_key() {
+        let admin = SecretKey::random();
+        let session = SessionManager::new_with_seckey(&admin).unwrap();
+        let member = SecretKey::random();
+        let member_session = SessionManager::new_with_seckey(&member).unwrap();
+        let outsider = SecretKey::random();
+        let group =
+            Group::new("rings", admin.address().into(), &[member.address().into()]).unwrap();
+
+        let session_key = |s: &SessionManager| s.session_key().unwrap();
+        let key = GroupKey::random();
+        let record = GroupKeyRecord::new(&session, &group, &key, &[
+            (admin.address().into(), session_key(&session).pubkey()),
+            (
+                member.address().into(),
+                session_key(&member_session).pubkey(),
+            ),
+            (outsider.address().into(), outsider.pubkey()),
+        ])
+        .unwrap();
+        assert!(record.verify());
+        assert_eq!(record.members(), group.members);
+        let vnode = record.to_vnode().unwrap();
+        assert_eq!(GroupKeyRecord::from_vnode(&vnode).unwrap(), record);
+
+        // member unwraps key and opens sealed message, others can't
+        let unwrapped = record
+            .unwrap_key(member.address().into(), &session_key(&member_session))
+            .unwrap();
+        let msg = GroupMessage::sealed("rings", &key, b"hello").unwrap();
+        assert_ne!(msg.data, b"hello");
+        assert_eq!(msg.open(&unwrapped).unwrap(), b"hello");
+        assert!(record
+            .unwrap_key(outsider.address().into(), &outsider)
+            .is_err());
+        assert!(msg.open(&GroupKey::random()).is_err());
+
+        let keyring = GroupKeyring::new();
+        assert!(keyring.open_message(&msg).is_err());
+        keyring.insert("rings", unwrapped).unwrap();
+        assert_eq!(keyring.open_message(&msg).unwrap(), b"hello");
+        assert_eq!(keyring.latest("rings").unwrap().epoch, key.epoch);
+
+        // sealed to its group, can't be passed off as a message to another one
+        let moved = GroupMessage {
+            group: "other".to_owned(),
+            ..msg.clone()
+        };
+        assert!(moved.open(&key).is_err());
+
+        // others can't replace key of group
+        let other_session = SessionManager::new_with_seckey(&outsider).unwrap();
+        let taken = Group::new("rings", outsider.address().into(), &[]).unwrap();
+        let taken = GroupKeyRecord::new(&other_session, &taken, &GroupKey::random(), &[])
+            .unwrap()
+            .to_vnode()
+            .unwrap();
+        assert_eq!(GroupKeyRecord::merge(&vnode, &taken).unwrap(), vnode);
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    #[test]
+    fn test_group_keyring_persisted() {
+        let path = format!("temp/group-keys-{}", uuid::Uuid::new_v4());
+        let cipher = StorageCipher::from_secret_key(&SecretKey::random());
+        let keys = (0..KEPT_KEY_EPOCHS as u128 + 1)
+            .map(|epoch| GroupKey {
+                epoch,
+                ..GroupKey::random()
+            })
+            .collect::<Vec<_>>();
+        {
+            let keyring = GroupKeyring::open(&path, cipher.clone()).unwrap();
+            for key in keys.iter() {
+                keyring.insert("rings", key.clone()).unwrap();
+            }
+        }
+        let msg = GroupMessage::sealed("rings", &keys[1], b"hello").unwrap();
+        let keyring = GroupKeyring::open(&path, cipher).unwrap();
+        assert_eq!(keyring.open_message(&msg).unwrap(), b"hello");
+        assert_eq!(
+            keyring.latest("rings").unwrap().epoch,
+            KEPT_KEY_EPOCHS as u128
+        );
+        // oldest epoch is dropped from disk too
+        assert!(keyring.get("rings", 0).is_none());
+        drop(keyring);
+
+        let other = StorageCipher::from_secret_key(&SecretKey::random());
+        assert!(GroupKeyring::open(&path, other).is_err());
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -0,0 +1,558 @@
+//! History of received custom messages, persisted in sled.
+//! Lightweight clients which connect to a node via HTTP can fetch messages they missed,
+//! see [MessageHistory::list].
+//!
+//! Messages are keyed by received time, sender and tx_id, so they are listed in received
+//! order, and indexed by sender and tx_id. Sender is signer of the message, and tx_id is
+//! chosen by sender, so a record is unique by both, and no sender can shadow records of
+//! others by reusing their tx_ids. With [MessageHistory::with_cipher], records are encrypted
+//! at rest, while keys and indexes stay in plain for ordering and lookup.
+//!
+//! A record and its indexes are written in one transaction. With
+//! [MessageHistory::with_retention], records older than a max age, and the oldest ones over a
+//! max count, are pruned when history is opened and every [PRUNE_INTERVAL] records inserted.
+use std::path::Path;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use serde::Deserialize;
+use serde::Serialize;
+use sled::transaction::ConflictableTransactionResult;
+use sled::transaction::TransactionError;
+use sled::Transactional;
+
+use crate::address::H160;
+use crate::dht::Did;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::CustomMessage;
+use crate::message::MaybeEncrypted;
+use crate::message::Message;
+use crate::message::MessagePayload;
+use crate::storage::StorageCipher;
+use crate::utils;
+
+/// Limit of records in one page, if filter not set it.
+pub const DEFAULT_PAGE_LIMIT: usize = 100;
+/// Default max age of records, 30 days in milliseconds.
+pub const DEFAULT_MAX_AGE_MS: u64 = 30 * 24 * 3600 * 1000;
+/// Default max count of records.
+pub const DEFAULT_MAX_RECORDS: usize = 100_000;
+/// Prune records every this many records inserted.
+pub const PRUNE_INTERVAL: usize = 100;
+
+/// A received custom message.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MessageRecord {
+    pub tx_id: String,
+    /// Origin of message, who signed it.
+    pub sender: Did,
+    pub destination: Did,
+    /// When message is received, in milliseconds since epoch.
+    pub ts_ms: u128,
+    /// Whether message was encrypted, `data` is always decrypted.
+    pub encrypted: bool,
+    pub data: Vec<u8>,
+}
+
+/// Conditions of [MessageHistory::list], unset fields match all.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct HistoryFilter {
+    pub sender: Option<Did>,
+    pub tx_id: Option<String>,
+    /// Received at or after, in milliseconds since epoch.
+    pub since_ms: Option<u128>,
+    /// Received before, in milliseconds since epoch.
+    pub until_ms: Option<u128>,
+    pub limit: Option<usize>,
+}
+
+/// One page of records, pass `next_cursor` to [MessageHistory::list] to get the next page.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct HistoryPage {
+    pub messages: Vec<MessageRecord>,
+    /// None if there is no more record.
+    pub next_cursor: Option<String>,
+}
+
+/// Persisted custom messages, with indexes by sender and tx_id.
+pub struct MessageHistory {
+    messages: sled::Tree,
+    by_sender: sled::Tree,
+    by_tx_id: sled::Tree,
+    cipher: Option<StorageCipher>,
+    /// 0 is unlimited.
+    max_age_ms: u64,
+    /// 0 is unlimited.
+    max_records: usize,
+    inserts: AtomicUsize,
+}
+
+/// Key of record, received time in big endian followed by sender and tx_id, so keys are
+/// sorted by time.
+fn record_key(ts_ms: u128, sender: &Did, tx_id: &str) -> Vec<u8> {
+    let mut key = ts_ms.to_be_bytes().to_vec();
+    key.extend_from_slice(sender.as_bytes());
+    key.extend_from_slice(tx_id.as_bytes());
+    key
+}
+
+/// Received time, sender and tx_id of record key.
+fn split_key(key: &[u8]) -> Option<(u128, Did, &[u8])> {
+    let ts_ms = u128::from_be_bytes(key.get(..16)?.try_into().ok()?);
+    let sender = Did::from(H160::from_slice(key.get(16..36)?));
+    Some((ts_ms, sender, &key[36..]))
+}
+
+/// Error of a transaction, they are never aborted.
+fn tx_error(e: TransactionError) -> Error {
+    match e {
+        TransactionError::Storage(e) => Error::SledError(e),
+        TransactionError::Abort(()) => {
+            Error::SledError(sled::Error::ReportableBug("transaction aborted".to_owned()))
+        }
+    }
+}
+
+/// Key in index of tx_id, tx_id followed by a separator and sender.
+fn tx_id_key(tx_id: &[u8], sender: &Did) -> Vec<u8> {
+    let mut key = tx_id_prefix(tx_id);
+    key.extend_from_slice(sender.as_bytes());
+    key
+}
+
+fn tx_id_prefix(tx_id: &[u8]) -> Vec<u8> {
+    let mut key = tx_id.to_vec();
+    key.push(0);
+    key
+}
+
+fn sender_key(sender: &Did, key: &[u8]) -> Vec<u8> {
+    let mut k = sender.as_bytes().to_vec();
+    k.extend_from_slice(key);
+    k
+}
+
+/// Cursor is `{ts_ms}:{sender}:{tx_id}` of the last returned record, sender in hex.
+fn encode_cursor(record: &MessageRecord) -> String {
+    format!(
+        "{}:{}:{}",
+        record.ts_ms,
+        hex::encode(record.sender.as_bytes()),
+        record.tx_id
+    )
+}
+
+fn decode_cursor(cursor: &str) -> Result<Vec<u8>> {
+    let invalid = || Error::InvalidHistoryCursor(cursor.to_owned());
+    let mut parts = cursor.splitn(3, ':');
+    let (ts_ms, sender, tx_id) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(ts_ms), Some(sender), Some(tx_id)) => (ts_ms, sender, tx_id),
+        _ => return Err(invalid()),
+    };
+    let ts_ms = ts_ms.parse::<u128>().map_err(|_| invalid())?;
+    let sender = hex::decode(sender).map_err(|_| invalid())?;
+    if sender.len() != 20 {
+        return Err(invalid());
+    }
+    let sender = Did::from(H160::from_slice(&sender));
+    Ok(record_key(ts_ms, &sender, tx_id))
+}
+
+/// Smallest key greater than `key`.
+fn successor_key(key: &[u8]) -> Vec<u8> {
+    let mut k = key.to_vec();
+    k.push(0);
+    k
+}
+
+impl MessageHistory {
+    /// Open or create history at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let db = sled::open(path).map_err(Error::SledError)?;
+        Ok(Self {
+            messages: db.open_tree("messages").map_err(Error::SledError)?,
+            by_sender: db.open_tree("by_sender").map_err(Error::SledError)?,
+            by_tx_id: db.open_tree("by_tx_id").map_err(Error::SledError)?,
+            cipher: None,
+            max_age_ms: 0,
+            max_records: 0,
+            inserts: AtomicUsize::new(0),
+        })
+    }
+
+    /// Keep records at most `max_age_ms` old, and at most `max_records` of them, 0 is
+    /// unlimited. History is pruned at once.
+    pub fn with_retention(mut self, max_age_ms: u64, max_records: usize) -> Result<Self> {
+        self.max_age_ms = max_age_ms;
+        self.max_records = max_records;
+        self.prune()?;
+        Ok(self)
+    }
+
+    /// Encrypt records at rest with `cipher`.
+    pub fn with_cipher(mut self, cipher: StorageCipher) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    /// Record a custom message received by node, `data` is the decrypted message.
+    pub fn record(
+        &self,
+        payload: &MessagePayload<Message>,
+        data: &CustomMessage,
+    ) -> Result<MessageRecord> {
+        let encrypted = matches!(
+            payload.data,
+            Message::CustomMessage(MaybeEncrypted::Encrypted(_))
+        );
+        let record = MessageRecord {
+            tx_id: payload.tx_id.inner(),
+            sender: payload.origin_verification.session.auth.authorizer.into(),
+            destination: payload.relay.destination,
+            ts_ms: utils::get_epoch_ms(),
+            encrypted,
+            data: data.0.clone(),
+        };
+        self.insert(&record)?;
+        Ok(record)
+    }
+
+    /// Insert a record, a record with existing tx_id from the same sender is ignored.
+    pub fn insert(&self, record: &MessageRecord) -> Result<()> {
+        let key = record_key(record.ts_ms, &record.sender, &record.tx_id);
+        let value = bincode::serialize(record).map_err(Error::BincodeSerialize)?;
+        let value = match &self.cipher {
+            Some(c) => c.seal(&key, &value)?,
+            None => value,
+        };
+        let tx_key = tx_id_key(record.tx_id.as_bytes(), &record.sender);
+        let inserted = (&self.messages, &self.by_sender, &self.by_tx_id)
+            .transaction(
+                |(messages, by_sender, by_tx_id)| -> ConflictableTransactionResult<bool> {
+                    if by_tx_id.get(tx_key.as_slice())?.is_some() {
+                        return Ok(false);
+                    }
+                    by_tx_id.insert(tx_key.as_slice(), key.as_slice())?;
+                    messages.insert(key.as_slice(), value.as_slice())?;
+                    by_sender.insert(sender_key(&record.sender, &key), key.as_slice())?;
+                    Ok(true)
+                },
+            )
+            .map_err(tx_error)?;
+        if inserted && (self.inserts.fetch_add(1, Ordering::Relaxed) + 1) % PRUNE_INTERVAL == 0 {
+            self.prune()?;
+        }
+        Ok(())
+    }
+
+    /// Remove records over retention, oldest first, returns count of them.
+    pub fn prune(&self) -> Result<usize> {
+        let expired = match self.max_age_ms {
+            0 => 0,
+            age => utils::get_epoch_ms().saturating_sub(age as u128),
+        };
+        let mut over = match self.max_records {
+            0 => 0,
+            max => self.messages.len().saturating_sub(max),
+        };
+        let mut removed = 0;
+        for key in self.messages.iter().keys() {
+            let key = key.map_err(Error::SledError)?;
+            let (ts_ms, sender, tx_id) = match split_key(&key) {
+                Some(parts) => parts,
+                None => continue,
+            };
+            if over == 0 && ts_ms >= expired {
+                break;
+            }
+            over = over.saturating_sub(1);
+            (&self.messages, &self.by_sender, &self.by_tx_id)
+                .transaction(
+                    |(messages, by_sender, by_tx_id)| -> ConflictableTransactionResult<()> {
+                        messages.remove(key.as_ref())?;
+                        by_sender.remove(sender_key(&sender, &key))?;
+                        by_tx_id.remove(tx_id_key(tx_id, &sender))?;
+                        Ok(())
+                    },
+                )
+                .map_err(tx_error)?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    /// Count of records.
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Whether history is empty.
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<MessageRecord>> {
+        let v = match self.messages.get(key).map_err(Error::SledError)? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        let v = match &self.cipher {
+            Some(c) => c.open(key, v.as_ref())?,
+            None => v.to_vec(),
+        };
+        Ok(Some(
+            bincode::deserialize(&v).map_err(Error::BincodeDeserialize)?,
+        ))
+    }
+
+    /// List records matching `filter` in received order, starting after `cursor`.
+    pub fn list(&self, filter: &HistoryFilter, cursor: Option<&str>) -> Result<HistoryPage> {
+        let limit = filter.limit.unwrap_or(DEFAULT_PAGE_LIMIT).max(1);
+
+        if let Some(tx_id) = &filter.tx_id {
+            let keys: Vec<sled::IVec> = match &filter.sender {
+                Some(sender) => self
+                    .by_tx_id
+                    .get(tx_id_key(tx_id.as_bytes(), sender))
+                    .map_err(Error::SledError)?
+                    .into_iter()
+                    .collect(),
+                None => self
+                    .by_tx_id
+                    .scan_prefix(tx_id_prefix(tx_id.as_bytes()))
+                    .values()
+                    .take(limit)
+                    .collect::<sled::Result<_>>()
+                    .map_err(Error::SledError)?,
+            };
+            let mut messages = vec![];
+            for key in keys {
+                if let Some(record) = self.get(&key)? {
+                    if record.tx_id == *tx_id && self.matches(filter, &record) {
+                        messages.push(record);
+                    }
+                }
+            }
+            messages.sort_by_key(|r| r.ts_ms);
+            return Ok(HistoryPage {
+                messages,
+                next_cursor: None,
+            });
+        }
+
+        let start = match cursor {
+            Some(c) => successor_key(&decode_cursor(c)?),
+            None => filter.since_ms.unwrap_or(0).to_be_bytes().to_vec(),
+        };
+        let keys: Box<dyn Iterator<Item = sled::Result<Vec<u8>>>> = match &filter.sender {
+            Some(sender) => {
+                let prefix = sender.as_bytes().to_vec();
+                let start = sender_key(sender, &start);
+                Box::new(
+                    self.by_sender
+                        .range(start..)
+                        .take_while(move |kv| match kv {
+                            Ok((k, _)) => k.starts_with(&prefix),
+                            Err(_) => true,
+                        })
+                        .map(|kv| kv.map(|(_, v)| v.to_vec())),
+                )
+            }
+            None => Box::new(
+                self.messages
+                    .range(start..)
+                    .map(|kv| kv.map(|(k, _)| k.to_vec())),
+            ),
+        };
+
+        let mut messages = vec![];
+        let mut more = false;
+        for key in keys {
+            let key = key.map_err(Error::SledError)?;
+            let record = match self.get(&key)? {
+                Some(r) => r,
+                None => continue,
+            };
+            if matches!(filter.until_ms, Some(until) if record.ts_ms >= until) {
+                break;
+            }
+            if !self.matches(filter, &record) {
+                continue;
+            }
+            if messages.len() == limit {
+                more = true;
+                break;
+            }
+            messages.push(record);
+        }
+        let next_cursor = match messages.last() {
+            Some(r) if more => Some(encode_cursor(r)),
+            _ => None,
+        };
+        Ok(HistoryPage {
+            messages,
+            next_cursor,
+        })
+    }
+
+    fn matches(&self, filter: &HistoryFilter, record: &MessageRecord) -> bool {
+        filter.sender.map_or(true, |s| s == record.sender)
+            && filter.since_ms.map_or(true, |s| record.ts_ms >= s)
+            && filter.until_ms.map_or(true, |u| record.ts_ms < u)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    fn new_record(sender: Did, ts_ms: u128, n: u8) -> MessageRecord {
+        MessageRecord {
+            tx_id: format!("tx{}", n),
+            sender,
+            destination: SecretKey::random().address().into(),
+            ts_ms,
+            encrypted: false,
+            data: vec![n],
+        }
+    }
+
+    #[test]
+    fn test_history_list() {
+        let path = format!("temp/history-{}", uuid::Uuid::new_v4());
+        let history = MessageHistory::open(&path).unwrap();
+        let alice: Did = SecretKey::random().address().into();
+        let bob: Did = SecretKey::random().address().into();
+        for n in 0..5u8 {
+            let sender = if n % 2 == 0 { alice } else { bob };
+            history
+                .insert(&new_record(sender, 1000 + n as u128, n))
+                .unwrap();
+        }
+        // duplicated tx_id is ignored
+        history.insert(&new_record(alice, 2000, 0)).unwrap();
+        assert_eq!(history.len(), 5);
+        // but it doesn't shadow the same tx_id of another sender
+        let mallory: Did = SecretKey::random().address().into();
+        history.insert(&new_record(mallory, 999, 3)).unwrap();
+        assert_eq!(history.len(), 6);
+
+        let filter = HistoryFilter {
+            since_ms: Some(1000),
+            limit: Some(2),
+            ..Default::default()
+        };
+        let page = history.list(&filter, None).unwrap();
+        assert_eq!(
+            page.messages.iter().map(|r| r.data[0]).collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+        let page = history.list(&filter, page.next_cursor.as_deref()).unwrap();
+        assert_eq!(
+            page.messages.iter().map(|r| r.data[0]).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        let page = history.list(&filter, page.next_cursor.as_deref()).unwrap();
+        assert_eq!(
+            page.messages.iter().map(|r| r.data[0]).collect::<Vec<_>>(),
+            vec![4]
+        );
+        assert_eq!(page.next_cursor, None);
+
+        let filter = HistoryFilter {
+            sender: Some(alice),
+            since_ms: Some(1001),
+            ..Default::default()
+        };
+        let page = history.list(&filter, None).unwrap();
+        assert_eq!(
+            page.messages.iter().map(|r| r.data[0]).collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+
+        let filter = HistoryFilter {
+            tx_id: Some("tx3".to_owned()),
+            ..Default::default()
+        };
+        let page = history.list(&filter, None).unwrap();
+        assert_eq!(page.messages.len(), 2);
+        assert_eq!(page.messages[0].sender, mallory);
+        assert_eq!(page.messages[1].sender, bob);
+
+        let filter = HistoryFilter {
+            sender: Some(bob),
+            tx_id: Some("tx3".to_owned()),
+            ..Default::default()
+        };
+        let page = history.list(&filter, None).unwrap();
+        assert_eq!(page.messages.len(), 1);
+        assert_eq!(page.messages[0].data, vec![3]);
+
+        drop(history);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_history_retention() {
+        let path = format!("temp/history-{}", uuid::Uuid::new_v4());
+        let history = MessageHistory::open(&path).unwrap();
+        let alice: Did = SecretKey::random().address().into();
+        let now = utils::get_epoch_ms();
+        // expired one
+        history.insert(&new_record(alice, now - 2000, 0)).unwrap();
+        for n in 1..5u8 {
+            history
+                .insert(&new_record(alice, now + n as u128, n))
+                .unwrap();
+        }
+        let history = history.with_retention(1000, 3).unwrap();
+        let page = history.list(&HistoryFilter::default(), None).unwrap();
+        assert_eq!(
+            page.messages.iter().map(|r| r.data[0]).collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+        // indexes are pruned with records
+        let filter = HistoryFilter {
+            tx_id: Some("tx1".to_owned()),
+            ..Default::default()
+        };
+        assert!(history.list(&filter, None).unwrap().messages.is_empty());
+        assert!(history
+            .by_sender
+            .get(sender_key(&alice, &record_key(now + 1, &alice, "tx1")))
+            .unwrap()
+            .is_none());
+        assert_eq!(history.by_tx_id.len(), 3);
+        assert_eq!(history.prune().unwrap(), 0);
+
+        drop(history);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_history_cipher() {
+        let path = format!("temp/history-{}", uuid::Uuid::new_v4());
+        let key = SecretKey::random();
+        let history = MessageHistory::open(&path)
+            .unwrap()
+            .with_cipher(StorageCipher::from_secret_key(&key));
+        let record = new_record(key.address().into(), 1000, 1);
+        history.insert(&record).unwrap();
+        let page = history.list(&HistoryFilter::default(), None).unwrap();
+        assert_eq!(page.messages, vec![record]);
+        drop(history);
+
+        let history = MessageHistory::open(&path)
+            .unwrap()
+            .with_cipher(StorageCipher::from_secret_key(&SecretKey::random()));
+        assert!(matches!(
+            history.list(&HistoryFilter::default(), None),
+            Err(Error::DecryptAtRest)
+        ));
+        drop(history);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -0,0 +1,242 @@
+//! Keys of peers, trusted on first use.
+//!
+//! A DID is derived from key of its authorizer, so checking that key tells nothing new. What is
+//! pinned instead is the key the authorizer signed for, [Session::authorized_key]: the session
+//! key of a node signing its own session, or the first delegate of a delegated one. The first
+//! time a transport of a peer is registered, [crate::swarm::Swarm] records it from session of the
+//! handshake, and checks it on every reconnect.
+//!
+//! So a peer is expected to keep its session, or its delegate, across reconnects. Another
+//! session of the same DID, like a restart with a new session key or a second device, is
+//! reported by [Error::PeerKeyMismatch], and it's logged or refused by [TofuPolicy]; a change
+//! made on purpose is accepted by [KnownPeers::forget]. On native, keys can be persisted in sled
+//! with [KnownPeers::open], so a thief of a session is caught across restarts too.
+//!
+//! [Session::authorized_key]: crate::session::Session::authorized_key
+use dashmap::DashMap;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::address::Address;
+use crate::dht::Did;
+use crate::err::Error;
+use crate::err::Result;
+use crate::utils;
+
+/// What to do when key of a peer differs from the one of first contact.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TofuPolicy {
+    /// Log a warning and go on.
+    Warn,
+    /// Close transport of the peer.
+    Refuse,
+}
+
+impl Default for TofuPolicy {
+    fn default() -> Self {
+        Self::Warn
+    }
+}
+
+impl std::str::FromStr for TofuPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(Self::Warn),
+            "refuse" => Ok(Self::Refuse),
+            _ => Err(format!("unknown tofu policy: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for TofuPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Warn => write!(f, "warn"),
+            Self::Refuse => write!(f, "refuse"),
+        }
+    }
+}
+
+/// A peer seen, with key of first contact.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct KnownPeer {
+    /// Address of key authorized by the DID, in hex.
+    pub key: String,
+    pub first_seen_ms: u128,
+    pub last_seen_ms: u128,
+    /// Contacts with another key.
+    pub mismatches: u64,
+}
+
+/// Keys of peers, by DID.
+#[derive(Debug, Default)]
+pub struct KnownPeers {
+    peers: DashMap<Did, KnownPeer>,
+    #[cfg(not(feature = "wasm"))]
+    db: Option<sled::Tree>,
+}
+
+fn encode(key: &Address) -> String {
+    hex::encode(key.as_bytes())
+}
+
+impl KnownPeers {
+    /// Keys kept in memory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open or create persisted keys at `path`. Records of authorizer keys, kept by older
+    /// versions, are dropped.
+    #[cfg(not(feature = "wasm"))]
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        let db = sled::open(path)
+            .and_then(|db| db.open_tree("known_peers"))
+            .map_err(Error::SledError)?;
+        let peers = DashMap::new();
+        for kv in db.iter() {
+            let (k, v) = kv.map_err(Error::SledError)?;
+            if k.len() != 20 {
+                return Err(Error::InvalidDid(
+                    hex::encode(&k),
+                    "not 20 bytes".to_owned(),
+                ));
+            }
+            let did: Did = crate::address::H160::from_slice(&k).into();
+            match serde_json::from_slice(&v) {
+                Ok(peer) => {
+                    peers.insert(did, peer);
+                }
+                Err(_) => {
+                    db.remove(&k).map_err(Error::SledError)?;
+                }
+            }
+        }
+        Ok(Self {
+            peers,
+            db: Some(db),
+        })
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    fn persist(&self, did: Did) -> Result<()> {
+        let db = match &self.db {
+            Some(db) => db,
+            None => return Ok(()),
+        };
+        if let Some(peer) = self.peers.get(&did) {
+            let v = serde_json::to_vec(&*peer).map_err(Error::Serialize)?;
+            db.insert(did.as_bytes(), v).map_err(Error::SledError)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "wasm")]
+    fn persist(&self, _did: Did) -> Result<()> {
+        Ok(())
+    }
+
+    /// Record `key` authorized by `did` on first contact, or check it against the recorded one.
+    pub fn observe(&self, did: Did, key: &Address) -> Result<()> {
+        self.observe_at(did, key, utils::get_epoch_ms())
+    }
+
+    fn observe_at(&self, did: Did, key: &Address, now: u128) -> Result<()> {
+        let key = encode(key);
+        let matched = {
+            let mut peer = self.peers.entry(did).or_insert_with(|| KnownPeer {
+                key: key.clone(),
+                first_seen_ms: now,
+                last_seen_ms: now,
+                mismatches: 0,
+            });
+            if peer.key == key {
+                peer.last_seen_ms = now;
+                true
+            } else {
+                peer.mismatches += 1;
+                false
+            }
+        };
+        self.persist(did)?;
+        match matched {
+            true => Ok(()),
+            false => Err(Error::PeerKeyMismatch(format!("{:?}", did))),
+        }
+    }
+
+    /// Record of `did`.
+    pub fn get(&self, did: Did) -> Option<KnownPeer> {
+        self.peers.get(&did).map(|p| p.clone())
+    }
+
+    /// Forget `did`, like after it renewed its session on purpose. Returns false if it's unknown.
+    pub fn forget(&self, did: Did) -> Result<bool> {
+        let removed = self.peers.remove(&did).is_some();
+        #[cfg(not(feature = "wasm"))]
+        if let Some(db) = &self.db {
+            db.remove(did.as_bytes()).map_err(Error::SledError)?;
+        }
+        Ok(removed)
+    }
+
+    /// All known peers.
+    pub fn items(&self) -> Vec<(Did, KnownPeer)> {
+        self.peers
+            .iter()
+            .map(|kv| (*kv.key(), kv.value().clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+    use crate::session::SessionManager;
+
+    #[test]
+    fn test_known_peers() {
+        let peers = KnownPeers::new();
+        let key = SecretKey::random();
+        let did: Did = key.address().into();
+        let session = SessionManager::new_with_seckey(&key).unwrap();
+        let first = session.session().unwrap().authorized_key();
+        peers.observe_at(did, &first, 10).unwrap();
+        peers.observe_at(did, &first, 20).unwrap();
+        let peer = peers.get(did).unwrap();
+        assert_eq!(peer.first_seen_ms, 10);
+        assert_eq!(peer.last_seen_ms, 20);
+        assert_eq!(peer.key, encode(&first));
+
+        // sessions delegated by the first one keep its key
+        let delegated = session.delegate(None).unwrap();
+        assert_eq!(delegated.session().unwrap().authorized_key(), first);
+        peers
+            .observe_at(did, &delegated.session().unwrap().authorized_key(), 25)
+            .unwrap();
+
+        // another session of the same DID is reported, and first one is kept
+        let other = SessionManager::new_with_seckey(&key).unwrap();
+        let second = other.session().unwrap().authorized_key();
+        assert_eq!(other.authorizer().unwrap(), key.address());
+        assert!(matches!(
+            peers.observe_at(did, &second, 30),
+            Err(Error::PeerKeyMismatch(_))
+        ));
+        let peer = peers.get(did).unwrap();
+        assert_eq!(peer.mismatches, 1);
+        assert_eq!(peer.last_seen_ms, 25);
+        assert_eq!(peer.key, encode(&first));
+
+        assert!(peers.forget(did).unwrap());
+        assert!(!peers.forget(did).unwrap());
+        peers.observe_at(did, &second, 40).unwrap();
+        assert_eq!(peers.items().len(), 1);
+        assert!("refuse".parse::<TofuPolicy>().is_ok());
+        assert!("deny".parse::<TofuPolicy>().is_err());
+    }
+}
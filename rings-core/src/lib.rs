@@ -63,19 +63,56 @@
 #![feature(async_closure)]
 #![feature(box_syntax)]
 #![feature(generators)]
+pub mod accounting;
+pub mod address;
+pub mod admission;
+pub mod audit;
+pub mod capture;
 pub mod channels;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod clock;
 pub mod dht;
 pub mod ecc;
+pub mod erasure;
 pub mod err;
+pub mod file;
+pub mod gossip;
+pub mod group;
+#[cfg(not(feature = "wasm"))]
+pub mod history;
+pub mod known_peers;
 pub mod macros;
+pub mod manifest;
 pub mod message;
+pub mod migration;
+pub mod outbox;
+pub mod overload;
+pub mod pex;
+pub mod placement;
 pub mod prelude;
+pub mod presence;
+pub mod pubkey;
+pub mod record;
+pub mod replay;
+pub mod rotation;
+pub mod service;
 pub mod session;
+#[cfg(feature = "sim")]
+pub mod sim;
 pub mod storage;
 pub mod swarm;
+pub mod tags;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+pub mod timer;
+pub mod topic;
+pub mod traffic;
 pub mod transports;
 pub mod types;
 pub mod utils;
+pub mod verify_pool;
+pub mod version;
 
 pub use async_trait::async_trait;
 pub use futures;
@@ -0,0 +1,126 @@
+//! Self-signed manifests of nodes, published to DHT by stabilization.
+//!
+//! Every node stores a signed [ManifestRecord] at [VirtualNode::manifest_address] of itself,
+//! which describes protocol versions, features and public endpoints of the node. Anyone can
+//! fetch it and check it's signed by the DID it describes, see [SignedRecord::verify].
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::dht::vnode::VNodeType;
+use crate::dht::vnode::VirtualNode;
+use crate::dht::Did;
+use crate::err::Error;
+use crate::err::Result;
+use crate::record::RecordData;
+use crate::record::RecordSigner;
+use crate::record::SignedRecord;
+use crate::session::SessionManager;
+use crate::types::ice_transport::HandshakeMeta;
+
+/// How long a manifest is valid, refreshed by every round of stabilization.
+pub const DEFAULT_MANIFEST_TTL_MS: usize = 10 * 60 * 1000;
+
+/// Description of a node.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct NodeManifest {
+    pub did: Did,
+    /// Version of rings-core.
+    pub version: String,
+    pub network_id: String,
+    pub protocol_version: u16,
+    pub min_protocol_version: u16,
+    /// Node has public address and is willing to relay messages for others.
+    pub relay: bool,
+    /// Optional components enabled on node, named by the node itself.
+    pub features: Vec<String>,
+    /// Public endpoints of node, like url of its http service.
+    pub endpoints: Vec<String>,
+}
+
+impl NodeManifest {
+    pub fn new(did: Did, meta: &HandshakeMeta, features: &[String], endpoints: &[String]) -> Self {
+        Self {
+            did,
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            network_id: meta.network_id.clone(),
+            protocol_version: meta.protocol_version,
+            min_protocol_version: meta.min_protocol_version,
+            relay: meta.relay,
+            features: features.to_vec(),
+            endpoints: endpoints.to_vec(),
+        }
+    }
+}
+
+/// Manifest signed by session of `did`.
+pub type ManifestRecord = SignedRecord<NodeManifest>;
+
+impl RecordData for NodeManifest {
+    const KIND: VNodeType = VNodeType::Manifest;
+
+    fn address(&self) -> Result<Did> {
+        VirtualNode::manifest_address(self.did)
+    }
+
+    fn signers(&self) -> Vec<RecordSigner> {
+        vec![RecordSigner::Session(self.did)]
+    }
+
+    fn invalid() -> Error {
+        Error::InvalidManifest
+    }
+}
+
+impl ManifestRecord {
+    pub fn new(
+        session_manager: &SessionManager,
+        manifest: NodeManifest,
+        ttl_ms: usize,
+    ) -> Result<Self> {
+        Self::sign(manifest, ttl_ms, Some(session_manager), &[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    #[test]
+    fn test_manifest_record() {
+        let key = SecretKey::random();
+        let session = SessionManager::new_with_seckey(&key).unwrap();
+        let did: Did = key.address().into();
+        let manifest = NodeManifest::new(did, &HandshakeMeta::default(), &["relay".to_owned()], &[
+            "https://node.example.com".to_owned(),
+        ]);
+        let record =
+            ManifestRecord::new(&session, manifest.clone(), DEFAULT_MANIFEST_TTL_MS).unwrap();
+        assert!(record.is_valid());
+
+        let vnode = record.to_vnode().unwrap();
+        assert_eq!(vnode.address, VirtualNode::manifest_address(did).unwrap());
+        assert_eq!(ManifestRecord::from_vnode(&vnode).unwrap(), record);
+
+        // manifest of others can't be signed
+        let other: Did = SecretKey::random().address().into();
+        let mut forged = manifest.clone();
+        forged.did = other;
+        assert!(ManifestRecord::new(&session, forged, DEFAULT_MANIFEST_TTL_MS).is_err());
+
+        // tampered manifest fails verification, and can't overwrite stored one
+        let mut tampered = record.clone();
+        tampered.data.endpoints = vec!["https://evil.example.com".to_owned()];
+        assert!(!tampered.verify());
+        let tampered_vnode = tampered.to_vnode().unwrap();
+        assert!(ManifestRecord::merge(&vnode, &tampered_vnode).is_err());
+
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let newer = ManifestRecord::new(&session, manifest, DEFAULT_MANIFEST_TTL_MS)
+            .unwrap()
+            .to_vnode()
+            .unwrap();
+        assert_eq!(ManifestRecord::merge(&vnode, &newer).unwrap(), newer);
+        assert_eq!(ManifestRecord::merge(&newer, &vnode).unwrap(), newer);
+    }
+}
@@ -0,0 +1,322 @@
+//! Compression of encoded payloads, negotiated per transport.
+//!
+//! Nodes announce codecs they accept in [crate::types::ice_transport::HandshakeMeta], in order
+//! of preference, and payloads to a peer are compressed by the first local codec the peer
+//! accepts. Peers built before negotiation accept gzip only. Payloads smaller than a threshold
+//! are sent as plain JSON. Decoding detects codec by magic bytes, so any codec can be decoded
+//! no matter what's negotiated.
+//!
+//! Backends are chosen at build time: gzip by `gzip` feature, zstd by `zstd` feature, or by
+//! `zstd-wasm` for wasm. Without gzip a node can't talk to peers before negotiation, but its
+//! wasm artifact is smaller.
+use std::fmt;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+use std::io::Read;
+#[cfg(feature = "gzip")]
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+#[cfg(feature = "gzip")]
+use flate2::read::GzDecoder;
+#[cfg(feature = "gzip")]
+use flate2::write::GzEncoder;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::err::Error;
+use crate::err::Result;
+
+/// Level of gzip, lower than max level 9 which costs much more CPU for little gain.
+pub const DEFAULT_GZIP_LEVEL: u32 = 6;
+/// Level of zstd.
+#[cfg(feature = "zstd")]
+pub const DEFAULT_ZSTD_LEVEL: i32 = 3;
+/// Payloads smaller than this are not compressed by default, in bytes.
+pub const DEFAULT_COMPRESS_THRESHOLD: usize = 1024;
+/// Payloads are refused if they decompress to more than this, in bytes.
+pub const MAX_DECOMPRESSED_SIZE: usize = 16 * 1024 * 1024;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+#[cfg(feature = "zstd")]
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression of payloads.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Codec {
+    /// Codecs supported by this build, in order of preference.
+    pub fn supported() -> Vec<Codec> {
+        let mut codecs = vec![];
+        #[cfg(feature = "zstd")]
+        codecs.push(Codec::Zstd);
+        #[cfg(feature = "gzip")]
+        codecs.push(Codec::Gzip);
+        codecs.push(Codec::None);
+        codecs
+    }
+
+    /// Codec of payloads to peers advertising no codecs, built before negotiation. It's gzip,
+    /// which they accept, or plain JSON if gzip is not built. Handshake info is always plain,
+    /// see [Encoder](super::Encoder) of [MessagePayload](super::MessagePayload).
+    pub fn fallback() -> Codec {
+        if cfg!(feature = "gzip") {
+            Codec::Gzip
+        } else {
+            Codec::None
+        }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        Self::supported().contains(self)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Codec::None => "none",
+            Codec::Gzip => "gzip",
+            Codec::Zstd => "zstd",
+        }
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            Codec::None => 0,
+            Codec::Gzip => 1,
+            Codec::Zstd => 2,
+        }
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Codec {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Codec::None),
+            "gzip" => Ok(Codec::Gzip),
+            "zstd" => Ok(Codec::Zstd),
+            _ => Err(format!("unknown codec `{}`, expect none, gzip or zstd", s)),
+        }
+    }
+}
+
+/// Pick first of `local` codecs which `remote` accepts, `remote` is empty if built before
+/// negotiation, which accepts gzip only. If there is none, it's [Codec::fallback] when `local`
+/// allows it, otherwise [Codec::None], a codec disabled locally is never used.
+pub fn negotiate(local: &[Codec], remote: &[Codec]) -> Codec {
+    let remote = if remote.is_empty() {
+        &[Codec::Gzip][..]
+    } else {
+        remote
+    };
+    local
+        .iter()
+        .find(|c| remote.contains(c))
+        .copied()
+        .unwrap_or_else(|| match Codec::fallback() {
+            c if local.contains(&c) => c,
+            _ => Codec::None,
+        })
+}
+
+/// Compress `data` with `codec`, plain data is returned if it's shorter than `threshold`.
+pub fn compress(codec: Codec, data: &[u8], threshold: usize) -> Result<Vec<u8>> {
+    if data.len() < threshold {
+        return Ok(data.to_vec());
+    }
+    match codec {
+        Codec::None => Ok(data.to_vec()),
+        #[cfg(feature = "gzip")]
+        Codec::Gzip => gzip(data, DEFAULT_GZIP_LEVEL),
+        #[cfg(not(feature = "gzip"))]
+        Codec::Gzip => Err(Error::UnsupportedCodec(codec.to_string())),
+        #[cfg(feature = "zstd")]
+        Codec::Zstd => zstd::stream::encode_all(data, DEFAULT_ZSTD_LEVEL)
+            .map_err(|e| Error::CompressionError(e.to_string())),
+        #[cfg(not(feature = "zstd"))]
+        Codec::Zstd => Err(Error::UnsupportedCodec(codec.to_string())),
+    }
+}
+
+#[cfg(feature = "gzip")]
+pub fn gzip(data: &[u8], level: u32) -> Result<Vec<u8>> {
+    let mut ec = GzEncoder::new(Vec::new(), flate2::Compression::new(level));
+    ec.write_all(data).map_err(|_| Error::GzipEncode)?;
+    ec.finish().map_err(|_| Error::GzipEncode)
+}
+
+/// Read all of `decoder` but no more than `limit` bytes.
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+fn read_within<R: Read>(decoder: R, limit: usize) -> std::io::Result<Vec<u8>> {
+    let mut out = vec![];
+    // one byte more tells it's over limit
+    decoder.take(limit as u64 + 1).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+#[cfg(feature = "gzip")]
+fn gunzip(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    read_within(GzDecoder::new(data), limit).map_err(|_| Error::GzipDecode)
+}
+
+/// Codec of `data`, detected by its magic bytes.
+pub fn detect(data: &[u8]) -> Codec {
+    #[cfg(feature = "zstd")]
+    if data.starts_with(&ZSTD_MAGIC) {
+        return Codec::Zstd;
+    }
+    if data.starts_with(&GZIP_MAGIC) {
+        return Codec::Gzip;
+    }
+    Codec::None
+}
+
+/// Decompress `data` compressed by any codec, up to [MAX_DECOMPRESSED_SIZE].
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    decompress_within(data, MAX_DECOMPRESSED_SIZE)
+}
+
+/// Decompress `data` compressed by any codec, it's refused if it's more than `limit` bytes
+/// decompressed, which is checked while decoding.
+pub fn decompress_within(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    let out = match detect(data) {
+        Codec::None => data.to_vec(),
+        #[cfg(feature = "gzip")]
+        Codec::Gzip => gunzip(data, limit)?,
+        #[cfg(not(feature = "gzip"))]
+        Codec::Gzip => return Err(Error::UnsupportedCodec(Codec::Gzip.to_string())),
+        #[cfg(feature = "zstd")]
+        Codec::Zstd => zstd::stream::read::Decoder::new(data)
+            .and_then(|d| read_within(d, limit))
+            .map_err(|e| Error::CompressionError(e.to_string()))?,
+        #[cfg(not(feature = "zstd"))]
+        Codec::Zstd => return Err(Error::UnsupportedCodec(Codec::Zstd.to_string())),
+    };
+    if out.len() > limit {
+        return Err(Error::DecompressedTooLarge(limit));
+    }
+    Ok(out)
+}
+
+/// Effective compression of one codec.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct CodecStats {
+    pub codec: Codec,
+    pub payloads: u64,
+    /// Bytes before compression.
+    pub raw_bytes: u64,
+    /// Bytes sent.
+    pub encoded_bytes: u64,
+    /// `encoded_bytes / raw_bytes`, lower is better.
+    pub ratio: f64,
+}
+
+/// Bytes compressed by each codec, recorded by swarm on sending.
+#[derive(Debug, Default)]
+pub struct CompressionStats {
+    /// Payloads, raw bytes and encoded bytes, indexed by codec.
+    counters: [[AtomicU64; 3]; 3],
+}
+
+impl CompressionStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, codec: Codec, raw: usize, encoded: usize) {
+        let c = &self.counters[codec.index()];
+        c[0].fetch_add(1, Ordering::Relaxed);
+        c[1].fetch_add(raw as u64, Ordering::Relaxed);
+        c[2].fetch_add(encoded as u64, Ordering::Relaxed);
+    }
+
+    /// Stats of codecs which are used.
+    pub fn stats(&self) -> Vec<CodecStats> {
+        [Codec::None, Codec::Gzip, Codec::Zstd]
+            .into_iter()
+            .filter_map(|codec| {
+                let c = &self.counters[codec.index()];
+                let payloads = c[0].load(Ordering::Relaxed);
+                let raw_bytes = c[1].load(Ordering::Relaxed);
+                let encoded_bytes = c[2].load(Ordering::Relaxed);
+                (payloads > 0).then(|| CodecStats {
+                    codec,
+                    payloads,
+                    raw_bytes,
+                    encoded_bytes,
+                    ratio: if raw_bytes == 0 {
+                        1.0
+                    } else {
+                        encoded_bytes as f64 / raw_bytes as f64
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        let local = [Codec::Zstd, Codec::Gzip, Codec::None];
+        assert_eq!(negotiate(&local, &[Codec::Gzip, Codec::Zstd]), Codec::Zstd);
+        assert_eq!(negotiate(&local, &[Codec::None]), Codec::None);
+        // peers built before negotiation
+        assert_eq!(negotiate(&local, &[]), Codec::fallback());
+        assert_eq!(negotiate(&[Codec::Gzip], &[Codec::Zstd]), Codec::fallback());
+        assert_eq!(negotiate(&[Codec::None], &[Codec::Zstd]), Codec::None);
+        assert_eq!(negotiate(&[Codec::None], &[]), Codec::None);
+        assert_eq!(Codec::Gzip.is_supported(), cfg!(feature = "gzip"));
+    }
+
+    #[test]
+    fn test_compress_roundtrip() {
+        let data = "hello rings ".repeat(200).into_bytes();
+        for codec in Codec::supported() {
+            let compressed = compress(codec, &data, DEFAULT_COMPRESS_THRESHOLD).unwrap();
+            assert_eq!(detect(&compressed), codec);
+            assert_eq!(decompress(&compressed).unwrap(), data);
+        }
+        // small payloads are not compressed
+        let small = b"{\"a\":1}";
+        assert_eq!(compress(Codec::Gzip, small, 16).unwrap(), small.to_vec());
+        #[cfg(not(feature = "gzip"))]
+        assert!(compress(Codec::Gzip, &data, 16).is_err());
+
+        // output is bounded while decoding
+        for codec in Codec::supported() {
+            let bomb = compress(codec, &vec![b' '; 1024 * 1024], 0).unwrap();
+            assert!(matches!(
+                decompress_within(&bomb, 64 * 1024),
+                Err(Error::DecompressedTooLarge(_))
+            ));
+            assert_eq!(
+                decompress_within(&bomb, 1024 * 1024).unwrap().len(),
+                1024 * 1024
+            );
+        }
+
+        let stats = CompressionStats::new();
+        stats.record(Codec::Gzip, 100, 25);
+        let s = stats.stats();
+        assert_eq!(s.len(), 1);
+        assert_eq!(s[0].ratio, 0.25);
+    }
+}
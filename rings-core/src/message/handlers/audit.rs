@@ -0,0 +1,126 @@
+#![warn(missing_docs)]
+//! Proof of storage challenges, see [crate::audit].
+//!
+//! [ChallengeVNode] travels along DHT path to the node storing a virtual node, which answers
+//! [ChallengeVNodeReport] with proof of the item challenged, or without proof if it doesn't
+//! have it. Answers are checked by [StorageAudit](crate::audit::StorageAudit) of publisher.
+//! Stabilization challenges a placed virtual node every round by [challenge_stored].
+use async_trait::async_trait;
+use futures::lock::Mutex;
+use rand::seq::SliceRandom;
+
+use super::topology::next_hop;
+use crate::audit;
+use crate::dht::vnode::VirtualNode;
+use crate::dht::Did;
+use crate::dht::PeerRing;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::types::ChallengeVNode;
+use crate::message::types::ChallengeVNodeReport;
+use crate::message::types::Message;
+use crate::message::HandleMsg;
+use crate::message::MessageHandler;
+use crate::message::MessagePayload;
+use crate::message::PayloadSender;
+use crate::swarm::Swarm;
+use crate::swarm::TransportManager;
+
+/// Challenge `stored_by` to prove it stores a random data item of `vnode`.
+pub(crate) async fn challenge_stored(
+    swarm: &Swarm,
+    dht: &Mutex<PeerRing>,
+    stored_by: Did,
+    vnode: &VirtualNode,
+) -> Result<()> {
+    let item = match vnode.data.choose(&mut rand::thread_rng()) {
+        Some(item) => item,
+        None => return Ok(()),
+    };
+    let nonce = swarm
+        .audits()
+        .challenge(stored_by, vnode.did(), item)
+        .ok_or(Error::TooManyChallenges)?;
+    let connected = swarm.get_transport(&stored_by.into()).is_some();
+    let next = next_hop(&*dht.lock().await, connected, stored_by)?;
+    let msg = ChallengeVNode {
+        id: vnode.did(),
+        nonce,
+        item: audit::digest(item),
+    };
+    swarm
+        .send_message(Message::ChallengeVNode(msg), next, stored_by)
+        .await
+}
+
+impl MessageHandler {
+    /// Challenge `stored_by` to prove it stores `vnode`, by a data item of `vnode`. Result is
+    /// recorded in [Swarm::audits](crate::swarm::Swarm::audits) when it answers or times out.
+    pub async fn challenge_vnode(&self, stored_by: Did, vnode: &VirtualNode) -> Result<()> {
+        challenge_stored(&self.swarm, &self.dht, stored_by, vnode).await
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<ChallengeVNode> for MessageHandler {
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &ChallengeVNode) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+        let dht = self.dht.lock().await;
+        let id = dht.id;
+        if relay.destination != id {
+            let connected = self.swarm.get_transport(&relay.destination).is_some();
+            let next = next_hop(&dht, connected, relay.destination)?;
+            drop(dht);
+            relay.relay(id, Some(next))?;
+            return self.transpond_payload(ctx, relay).await;
+        }
+        let proof = dht
+            .storage
+            .get(&msg.id)
+            .and_then(|v| audit::prove_item(&v.data, &msg.item, msg.nonce));
+        drop(dht);
+        let report = ChallengeVNodeReport {
+            stored_by: id,
+            id: msg.id,
+            nonce: msg.nonce,
+            proof,
+        };
+        relay.relay(id, None)?;
+        self.send_report_message(Message::ChallengeVNodeReport(report), relay)
+            .await
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<ChallengeVNodeReport> for MessageHandler {
+    async fn handle(
+        &self,
+        ctx: &MessagePayload<Message>,
+        msg: &ChallengeVNodeReport,
+    ) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+        let id = self.dht.lock().await.id;
+        relay.relay(id, None)?;
+        if relay.next_hop.is_some() {
+            return self.transpond_payload(ctx, relay).await;
+        }
+        // only the node challenged can answer
+        if Did::from(ctx.origin_verification.session.auth.authorizer) != msg.stored_by {
+            return Err(Error::InvalidChallengeReport);
+        }
+        match self
+            .swarm
+            .audits()
+            .answer(msg.stored_by, msg.nonce, msg.proof.as_deref())
+        {
+            Some(true) => tracing::debug!(node = ?msg.stored_by, vnode = ?msg.id, "storage proved"),
+            Some(false) => {
+                tracing::warn!(node = ?msg.stored_by, vnode = ?msg.id, "storage challenge failed")
+            }
+            None => tracing::debug!(node = ?msg.stored_by, "ignore unknown storage challenge"),
+        }
+        Ok(())
+    }
+}
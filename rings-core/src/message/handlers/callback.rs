@@ -0,0 +1,284 @@
+#![warn(missing_docs)]
+//! Registry of [MessageCallback]s, so independent modules of an application share a node.
+//!
+//! Every callback is registered by name with a [CallbackFilter], and is invoked for handled
+//! messages the filter matches, in order of registration. Registering a name again replaces
+//! its callback. [MessageHandler::set_callback] is a callback named [DEFAULT_CALLBACK]
+//! without filter.
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
+
+use super::CallbackFn;
+use crate::dht::Did;
+use crate::message::Message;
+use crate::message::MessageHandler;
+use crate::message::MessageKind;
+use crate::message::MessagePayload;
+
+/// Name of callback set by [MessageHandler::set_callback].
+pub const DEFAULT_CALLBACK: &str = "default";
+
+/// Messages a callback is invoked for. Default filter matches every message.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CallbackFilter {
+    /// Only messages originated from this node.
+    pub sender: Option<Did>,
+    /// Only messages of these kinds, see [Message::kind], any kind if empty.
+    pub kinds: Vec<MessageKind>,
+}
+
+impl CallbackFilter {
+    /// Only messages originated from `sender`.
+    pub fn sender(mut self, sender: Did) -> Self {
+        self.sender = Some(sender);
+        self
+    }
+
+    /// Messages of `kind` too, see [Message::kind].
+    pub fn kind(mut self, kind: MessageKind) -> Self {
+        self.kinds.push(kind);
+        self
+    }
+
+    /// Whether callback should be invoked for `payload`.
+    pub fn matches(&self, payload: &MessagePayload<Message>) -> bool {
+        self.sender.map_or(true, |s| s == payload.relay.origin())
+            && (self.kinds.is_empty() || self.kinds.contains(&payload.data.kind()))
+    }
+}
+
+struct Registered {
+    id: u64,
+    name: String,
+    filter: CallbackFilter,
+    callback: Arc<CallbackFn>,
+}
+
+/// Callbacks registered on a [MessageHandler], see module doc.
+#[derive(Default)]
+pub struct CallbackRegistry {
+    next_id: AtomicU64,
+    entries: Mutex<Vec<Registered>>,
+}
+
+/// Handle of a registered callback, removes it with [CallbackHandle::remove]. The callback
+/// stays registered if handle is dropped.
+#[derive(Clone)]
+pub struct CallbackHandle {
+    id: u64,
+    name: String,
+    registry: Weak<CallbackRegistry>,
+}
+
+impl std::fmt::Debug for CallbackHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CallbackHandle")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl CallbackHandle {
+    /// Name callback is registered by.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Remove the callback, false if it's removed or replaced already.
+    pub fn remove(&self) -> bool {
+        match self.registry.upgrade() {
+            Some(registry) => registry.remove_id(self.id),
+            None => false,
+        }
+    }
+}
+
+impl CallbackRegistry {
+    /// An empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `callback` for messages matching `filter` by `name`, replacing the one
+    /// registered by the same name.
+    pub fn register(
+        self: &Arc<Self>,
+        name: &str,
+        filter: CallbackFilter,
+        callback: CallbackFn,
+    ) -> CallbackHandle {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let entry = Registered {
+            id,
+            name: name.to_owned(),
+            filter,
+            callback: Arc::new(callback),
+        };
+        if let Ok(mut entries) = self.entries.lock() {
+            match entries.iter_mut().find(|e| e.name == name) {
+                Some(e) => *e = entry,
+                None => entries.push(entry),
+            }
+        }
+        CallbackHandle {
+            id,
+            name: name.to_owned(),
+            registry: Arc::downgrade(self),
+        }
+    }
+
+    /// A name never returned by this registry before, for callbacks registered on behalf of
+    /// applications, like subscriptions.
+    pub(super) fn unique_name(&self, prefix: &str) -> String {
+        format!("{}{}", prefix, self.next_id.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Remove callback registered by `name`, false if there is none.
+    pub fn unregister(&self, name: &str) -> bool {
+        self.remove_where(|e| e.name == name)
+    }
+
+    fn remove_id(&self, id: u64) -> bool {
+        self.remove_where(|e| e.id == id)
+    }
+
+    fn remove_where(&self, f: impl Fn(&Registered) -> bool) -> bool {
+        match self.entries.lock() {
+            Ok(mut entries) => {
+                let len = entries.len();
+                entries.retain(|e| !f(e));
+                entries.len() < len
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Names of registered callbacks, in order they are invoked.
+    pub fn names(&self) -> Vec<String> {
+        self.entries
+            .lock()
+            .map(|entries| entries.iter().map(|e| e.name.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Callbacks to invoke for `payload`. Lock is released before they are invoked, so a
+    /// callback may register or remove callbacks.
+    fn matching(&self, payload: &MessagePayload<Message>) -> Vec<Arc<CallbackFn>> {
+        self.entries
+            .lock()
+            .map(|entries| {
+                entries
+                    .iter()
+                    .filter(|e| e.filter.matches(payload))
+                    .map(|e| e.callback.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl MessageHandler {
+    /// Register `callback` for messages matching `filter` by `name`, see [CallbackRegistry].
+    pub fn register_callback(
+        &self,
+        name: &str,
+        filter: CallbackFilter,
+        callback: CallbackFn,
+    ) -> CallbackHandle {
+        self.callbacks.register(name, filter, callback)
+    }
+
+    /// Remove callback registered by `name`, false if there is none.
+    pub fn unregister_callback(&self, name: &str) -> bool {
+        self.callbacks.unregister(name)
+    }
+
+    /// Callbacks registered on handler.
+    pub fn callbacks(&self) -> Arc<CallbackRegistry> {
+        self.callbacks.clone()
+    }
+
+    pub(super) async fn invoke_callbacks(&self, payload: &MessagePayload<Message>) {
+        for cb in self.callbacks.matching(payload) {
+            match &payload.data {
+                Message::CustomMessage(msg) => cb.custom_message(self, payload, msg).await,
+                _ => cb.builtin_message(self, payload).await,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::ecc::SecretKey;
+    use crate::message::CustomMessage;
+    use crate::message::MaybeEncrypted;
+    use crate::message::MessageCallback;
+    use crate::session::SessionManager;
+
+    struct Noop;
+
+    #[async_trait]
+    impl MessageCallback for Noop {
+        async fn custom_message(
+            &self,
+            _handler: &MessageHandler,
+            _ctx: &MessagePayload<Message>,
+            _msg: &MaybeEncrypted<CustomMessage>,
+        ) {
+        }
+
+        async fn builtin_message(&self, _handler: &MessageHandler, _ctx: &MessagePayload<Message>) {
+        }
+    }
+
+    #[test]
+    fn test_callback_registry() {
+        let key = SecretKey::random();
+        let session = SessionManager::new_with_seckey(&key).unwrap();
+        let sender: Did = key.address().into();
+        let payload = MessagePayload::new_direct(
+            Message::custom("hello".as_bytes(), &None).unwrap(),
+            &session,
+            SecretKey::random().address().into(),
+        )
+        .unwrap();
+
+        assert!(CallbackFilter::default().matches(&payload));
+        assert!(CallbackFilter::default()
+            .sender(sender)
+            .kind(MessageKind::CustomMessage)
+            .matches(&payload));
+        assert!(!CallbackFilter::default()
+            .kind(MessageKind::StreamFrame)
+            .matches(&payload));
+        let other: Did = SecretKey::random().address().into();
+        assert!(!CallbackFilter::default().sender(other).matches(&payload));
+
+        let registry = Arc::new(CallbackRegistry::new());
+        let chat = registry.register("chat", CallbackFilter::default(), Box::new(Noop));
+        registry.register(
+            "stream",
+            CallbackFilter::default().kind(MessageKind::StreamFrame),
+            Box::new(Noop),
+        );
+        assert_eq!(registry.names(), vec!["chat", "stream"]);
+        assert_eq!(registry.matching(&payload).len(), 1);
+
+        // replaced callback isn't removed by handle of the old one
+        let replaced = registry.register("chat", CallbackFilter::default(), Box::new(Noop));
+        assert!(!chat.remove());
+        assert_eq!(registry.names(), vec!["chat", "stream"]);
+        assert!(replaced.remove());
+        assert!(!replaced.remove());
+        assert!(registry.unregister("stream"));
+        assert!(registry.names().is_empty());
+    }
+}
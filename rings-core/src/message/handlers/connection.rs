@@ -1,9 +1,17 @@
 use std::str::FromStr;
+use std::time::Duration;
 
 use async_trait::async_trait;
+use serde::Deserialize;
+use serde::Serialize;
 
+use crate::dht::vnode::VNodeType;
+use crate::dht::vnode::VirtualNode;
 use crate::dht::Chord;
 use crate::dht::ChordStorage;
+use crate::dht::DhtEvent;
+use crate::dht::Did;
+use crate::dht::PeerRing;
 use crate::dht::PeerRingAction;
 use crate::dht::PeerRingRemoteAction;
 use crate::err::Error;
@@ -13,8 +21,10 @@ use crate::message::types::ConnectNodeReport;
 use crate::message::types::ConnectNodeSend;
 use crate::message::types::FindSuccessorReport;
 use crate::message::types::FindSuccessorSend;
+use crate::message::types::FoundVNode;
 use crate::message::types::JoinDHT;
 use crate::message::types::Message;
+use crate::message::types::ReconnectPeer;
 use crate::message::types::SyncVNodeWithSuccessor;
 use crate::message::HandleMsg;
 use crate::message::LeaveDHT;
@@ -23,14 +33,89 @@ use crate::message::MessagePayload;
 use crate::message::PayloadSender;
 use crate::prelude::RTCSdpType;
 use crate::swarm::TransportManager;
+use crate::timer;
 use crate::types::ice_transport::IceTrickleScheme;
 
+/// Which peers of [FindSuccessorReport] are connected at once, see
+/// [MessageHandler::with_topology_policy].
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TopologyPolicy {
+    /// Connect every peer reported, which is the default.
+    #[default]
+    Eager,
+    /// Connect peers reported only if they would be taken as successor or finger. Others are
+    /// left to be connected lazily, by [lazy dial](MessageHandler::with_lazy_dial) once
+    /// messages are sent to them, which is turned on with it.
+    Structured,
+}
+
+impl FromStr for TopologyPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "eager" => Ok(Self::Eager),
+            "structured" => Ok(Self::Structured),
+            _ => Err(format!("unknown topology policy: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for TopologyPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Eager => write!(f, "eager"),
+            Self::Structured => write!(f, "structured"),
+        }
+    }
+}
+
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
 #[cfg_attr(not(feature = "wasm"), async_trait)]
 impl HandleMsg<LeaveDHT> for MessageHandler {
     async fn handle(&self, _ctx: &MessagePayload<Message>, msg: &LeaveDHT) -> Result<()> {
         let mut dht = self.dht.lock().await;
         dht.remove(msg.id);
+        self.swarm.placements().owner_left(msg.id);
+        self.swarm.emit_dht_event(DhtEvent::NodeLeft(msg.id));
+        Ok(())
+    }
+}
+
+impl MessageHandler {
+    /// Dial `peer` migrating again, in rest of its migration window. The lower DID of both
+    /// dials at once and the other after half of the window, so they don't race with offers.
+    async fn reconnect(&self, peer: Did) {
+        let address = peer.into();
+        let own: Did = self.swarm.address().into();
+        if own > peer {
+            let half = self.swarm.migration_remaining_ms(&address).unwrap_or(0) / 2;
+            timer::sleep(Duration::from_millis(half as u64)).await;
+        }
+        let remaining = match self.swarm.migration_remaining_ms(&address) {
+            Some(ms) if ms > 0 && self.swarm.get_transport(&address).is_none() => ms,
+            _ => return,
+        };
+        tracing::info!(peer = ?peer, "dial migrating peer again");
+        if let Err(e) = self.connect_with_timeout(&address, remaining).await {
+            tracing::info!(peer = ?peer, "failed to dial migrating peer: {}", e);
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<ReconnectPeer> for MessageHandler {
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &ReconnectPeer) -> Result<()> {
+        let own: Did = self.swarm.address().into();
+        if Did::from(ctx.origin_verification.session.auth.authorizer) != own {
+            tracing::warn!(peer = ?msg.id, "drop reconnect raised by others");
+            return Ok(());
+        }
+        let handler = self.clone();
+        let peer = msg.id;
+        timer::spawn(async move { handler.reconnect(peer).await });
         Ok(())
     }
 }
@@ -43,7 +128,29 @@ impl HandleMsg<JoinDHT> for MessageHandler {
         // finger table just have no other node(beside next), it will be a `create` op
         // otherwise, it will be a `send` op
         let mut dht = self.dht.lock().await;
-        match dht.join(msg.id) {
+        dht.set_relay(msg.id, msg.relay);
+        self.swarm.emit_dht_event(DhtEvent::NodeJoined(msg.id));
+        // hand over inbox of joined node, which holds messages sent while it's offline,
+        // it's kept until joined node acknowledges delivered messages
+        let inbox = VirtualNode::inbox_address(msg.id)?;
+        let inbox = dht
+            .storage
+            .get(&inbox)
+            .filter(|v| v.kind == VNodeType::Inbox);
+        // node joining a ring knows no successor yet
+        let cold = dht.successor.is_none();
+        let action = dht.join(msg.id);
+        let fingers = match cold {
+            true => self.finger_targets(&dht),
+            false => vec![],
+        };
+        // messages are sent after lock of DHT is released
+        drop(dht);
+        if let Some(v) = inbox {
+            self.send_direct_message(Message::FoundVNode(FoundVNode { data: vec![v] }), msg.id)
+                .await?;
+        }
+        match action {
             PeerRingAction::None => Ok(()),
             PeerRingAction::RemoteAction(next, PeerRingRemoteAction::FindSuccessor(id)) => {
                 // if there is only two nodes A, B, it may cause recursion
@@ -52,19 +159,56 @@ impl HandleMsg<JoinDHT> for MessageHandler {
                 // A.find_successor(B)
                 if next != ctx.addr.into() {
                     self.send_direct_message(
-                        Message::FindSuccessorSend(FindSuccessorSend { id, for_fix: false }),
+                        Message::FindSuccessorSend(FindSuccessorSend {
+                            id,
+                            for_fix: false,
+                            finger: None,
+                        }),
                         next,
                     )
-                    .await
-                } else {
-                    Ok(())
+                    .await?;
                 }
+                self.lookup_fingers(fingers, msg.id).await;
+                Ok(())
             }
             _ => unreachable!(),
         }
     }
 }
 
+impl MessageHandler {
+    /// Indices and targets of farthest fingers, at most `join_parallelism - 1` of them, which
+    /// are looked up besides successor by [MessageHandler::lookup_fingers].
+    fn finger_targets(&self, dht: &PeerRing) -> Vec<(u8, Did)> {
+        let size = dht.finger.len().min(u8::MAX as usize + 1);
+        (1..self.join_parallelism.min(size))
+            .map(|i| (size - i) as u8)
+            .map(|index| (index, dht.finger_target(index)))
+            .collect()
+    }
+
+    /// Look up `fingers` through `via` at the same time, instead of waiting for stabilization
+    /// to fix them one by one, so a node joining a large ring connects several of them
+    /// concurrently. Each report is set to the finger it's looked up for.
+    async fn lookup_fingers(&self, fingers: Vec<(u8, Did)>, via: Did) {
+        let lookups = fingers.into_iter().map(|(index, id)| {
+            self.send_direct_message(
+                Message::FindSuccessorSend(FindSuccessorSend {
+                    id,
+                    for_fix: true,
+                    finger: Some(index),
+                }),
+                via,
+            )
+        });
+        for r in futures::future::join_all(lookups).await {
+            if let Err(e) = r {
+                tracing::warn!(via = ?via, "failed to look up finger: {}", e);
+            }
+        }
+    }
+}
+
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
 #[cfg_attr(not(feature = "wasm"), async_trait)]
 impl HandleMsg<ConnectNodeSend> for MessageHandler {
@@ -77,10 +221,13 @@ impl HandleMsg<ConnectNodeSend> for MessageHandler {
                 relay.relay(dht.id, Some(relay.destination))?;
                 return self.transpond_payload(ctx, relay).await;
             } else {
-                let next_node = match dht.find_successor(relay.destination)? {
-                    PeerRingAction::Some(node) => Some(node),
-                    PeerRingAction::RemoteAction(node, _) => Some(node),
-                    _ => None,
+                // prefer relay capable nodes closer than DHT path
+                let (dest, path) = (relay.destination, &relay.path);
+                let next_node = match dht.find_successor(dest)? {
+                    PeerRingAction::Some(node) | PeerRingAction::RemoteAction(node, _) => {
+                        Some(dht.prefer_relay(dest, node, path))
+                    }
+                    _ => dht.closest_relay(dest, path),
                 }
                 .ok_or(Error::MessageHandlerMissNextNode)?;
                 relay.relay(dht.id, Some(next_node))?;
@@ -91,6 +238,15 @@ impl HandleMsg<ConnectNodeSend> for MessageHandler {
         relay.relay(dht.id, None)?;
         match self.swarm.get_transport(&relay.sender()) {
             None => {
+                let hop = Did::from(ctx.addr);
+                if !self
+                    .offer_admission
+                    .as_ref()
+                    .map_or(true, |admit| admit(hop))
+                {
+                    tracing::info!(peer = ?hop, "refuse offer relayed over dht");
+                    return Err(Error::OfferRefused(hop.to_string()));
+                }
                 let trans = self.swarm.new_transport().await?;
                 let sender_id = relay.sender();
                 trans
@@ -180,6 +336,11 @@ impl HandleMsg<FindSuccessorSend> for MessageHandler {
                     Message::FindSuccessorReport(FindSuccessorReport {
                         id,
                         for_fix: msg.for_fix,
+                        finger: msg.finger,
+                        successors: match msg.for_fix {
+                            true => vec![],
+                            false => dht.successor.list(),
+                        },
                     }),
                     relay,
                 )
@@ -195,6 +356,17 @@ impl HandleMsg<FindSuccessorSend> for MessageHandler {
     }
 }
 
+/// `successors` can be successor list of `responder` reporting `id`: it's led by `id`, and
+/// each one follows the one before on ring, going around from `responder` at most once.
+fn is_successor_list(responder: Did, id: Did, successors: &[Did]) -> bool {
+    successors.is_empty()
+        || (successors[0] == id
+            && !successors.contains(&responder)
+            && successors
+                .windows(2)
+                .all(|w| w[0] - responder < w[1] - responder))
+}
+
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
 #[cfg_attr(not(feature = "wasm"), async_trait)]
 impl HandleMsg<FindSuccessorReport> for MessageHandler {
@@ -208,25 +380,78 @@ impl HandleMsg<FindSuccessorReport> for MessageHandler {
         } else {
             if self.swarm.get_transport(&msg.id).is_none() && msg.id != self.swarm.address().into()
             {
-                self.connect(&msg.id.into()).await?;
+                let wanted = match self.topology_policy {
+                    TopologyPolicy::Eager => true,
+                    TopologyPolicy::Structured if msg.for_fix => dht.finger.would_join(msg.id),
+                    TopologyPolicy::Structured => dht.successor.would_take(msg.id),
+                };
+                drop(dht);
+                if wanted {
+                    self.connect(&msg.id.into()).await?;
+                } else {
+                    tracing::debug!(peer = ?msg.id, "reported peer is left to lazy dial");
+                }
                 return Ok(());
             }
             if msg.for_fix {
-                let fix_finger_index = dht.fix_finger_index;
-                dht.finger.set(fix_finger_index as usize, &msg.id);
+                // lookups of fingers may be in flight at the same time, see
+                // [MessageHandler::lookup_fingers], each report names its finger
+                let index = msg.finger.unwrap_or(dht.fix_finger_index);
+                dht.finger.set(index as usize, &msg.id);
             } else {
                 dht.successor.update(msg.id);
-                if let Ok(PeerRingAction::RemoteAction(
-                    next,
-                    PeerRingRemoteAction::SyncVNodeWithSuccessor(data),
-                )) = dht.sync_with_successor(msg.id)
-                {
+                let sync = match dht.sync_with_successor(msg.id) {
+                    Ok(PeerRingAction::RemoteAction(
+                        next,
+                        PeerRingRemoteAction::SyncVNodeWithSuccessor(data),
+                    )) => Some((next, data)),
+                    _ => None,
+                };
+                // successors of responder follow the reported one, connected ones are taken
+                // at once, others join successor list once they are connected, which a
+                // concurrent join starts for at most `join_parallelism - 1` of them
+                let responder = Did::from(ctx.origin_verification.session.auth.authorizer);
+                let successors = match is_successor_list(responder, msg.id, &msg.successors) {
+                    true => &msg.successors[..],
+                    false => {
+                        tracing::warn!(peer = ?responder, "drop successors not in order of ring");
+                        &[]
+                    }
+                };
+                let mut unconnected = vec![];
+                for s in successors.iter().take(dht.successor.capacity()) {
+                    if *s == dht.id || *s == msg.id {
+                        continue;
+                    }
+                    match self.swarm.get_transport(&(*s).into()) {
+                        Some(_) => dht.successor.update(*s),
+                        None if self.topology_policy == TopologyPolicy::Eager
+                            || dht.successor.would_take(*s) =>
+                        {
+                            unconnected.push(*s)
+                        }
+                        None => {}
+                    }
+                }
+                drop(dht);
+                if let Some((next, data)) = sync {
                     self.send_direct_message(
                         Message::SyncVNodeWithSuccessor(SyncVNodeWithSuccessor { data }),
                         next,
                     )
                     .await?;
                 }
+                for s in unconnected
+                    .into_iter()
+                    .take(self.join_parallelism.saturating_sub(1))
+                {
+                    let handler = self.clone();
+                    timer::spawn(async move {
+                        if let Err(e) = handler.connect(&s.into()).await {
+                            tracing::warn!(peer = ?s, "failed to dial gossiped successor: {}", e);
+                        }
+                    });
+                }
             }
             Ok(())
         }
@@ -240,19 +465,22 @@ mod test {
     use std::sync::Arc;
 
     use futures::lock::Mutex;
+    use num_bigint::BigUint;
     use tokio::time::sleep;
     use tokio::time::Duration;
-    use web3::types::Address;
 
     use super::*;
+    use crate::address::Address;
     use crate::dht::Did;
     use crate::dht::PeerRing;
     use crate::ecc::SecretKey;
+    use crate::message::handlers::test::create_connected_pair;
     use crate::message::MessageHandler;
     use crate::prelude::RTCSdpType;
     use crate::session::SessionManager;
     use crate::swarm::Swarm;
     use crate::swarm::TransportManager;
+    use crate::test_utils::KeyFixtures;
     use crate::types::ice_transport::IceTrickleScheme;
 
     // ndoe1.key < node2.key < node3.key
@@ -407,7 +635,7 @@ mod test {
         assert_eq!(ev_3.relay.path, vec![did3, did2]);
         assert!(matches!(
             ev_3.data,
-            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false}) if id == did3
+            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false, ..}) if id == did3
         ));
         // dht3 won't set did3 as successor
         assert!(!dht3.lock().await.successor.list().contains(&did3));
@@ -420,7 +648,7 @@ mod test {
         // node3 is only aware of node2, so it respond node2
         assert!(matches!(
             ev_2.data,
-            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false}) if id == did2
+            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false, ..}) if id == did2
         ));
         // dht2 won't set did2 as successor
         assert!(!dht2.lock().await.successor.list().contains(&did2));
@@ -452,7 +680,7 @@ mod test {
         assert_eq!(ev_2.relay.path, vec![did3, did1]);
         assert!(matches!(
             ev_2.data,
-            Message::FindSuccessorSend(FindSuccessorSend{id, for_fix: false}) if id == did3
+            Message::FindSuccessorSend(FindSuccessorSend{id, for_fix: false, ..}) if id == did3
         ));
 
         // 3->1 FindSuccessorReport
@@ -462,7 +690,7 @@ mod test {
         assert_eq!(ev_1.relay.path, vec![did1, did3]);
         assert!(matches!(
             ev_1.data,
-            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false}) if id == did1
+            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false, ..}) if id == did1
         ));
         // dht1 won't set did1 as successor
         assert!(!dht1.lock().await.successor.list().contains(&did1));
@@ -478,7 +706,7 @@ mod test {
         assert_eq!(ev_3.relay.path, vec![did3, did1, did2]);
         assert!(matches!(
             ev_3.data,
-            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false}) if id == did3
+            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false, ..}) if id == did3
         ));
         // dht3 won't set did3 as successor
         assert!(!dht3.lock().await.successor.list().contains(&did3));
@@ -547,7 +775,7 @@ mod test {
         assert_eq!(ev_1.relay.path, vec![did3, did2]);
         assert!(matches!(
             ev_1.data,
-            Message::FindSuccessorSend(FindSuccessorSend{id, for_fix: false}) if id == did3
+            Message::FindSuccessorSend(FindSuccessorSend{id, for_fix: false, ..}) if id == did3
         ));
 
         // 3->2 FindSuccessorReport
@@ -558,7 +786,7 @@ mod test {
         // node3 is only aware of node2, so it respond node2
         assert!(matches!(
             ev_2.data,
-            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false}) if id == did2
+            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false, ..}) if id == did2
         ));
         // dht2 won't set did2 as successor
         assert!(!dht2.lock().await.successor.list().contains(&did2));
@@ -572,7 +800,7 @@ mod test {
         // node1 is only aware of node2, so it respond node2
         assert!(matches!(
             ev_2.data,
-            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false}) if id == did2
+            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false, ..}) if id == did2
         ));
 
         // 1->2->3 FindSuccessorReport
@@ -583,7 +811,7 @@ mod test {
         assert_eq!(ev_3.relay.path_end_cursor, 1);
         assert!(matches!(
             ev_3.data,
-            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false}) if id == did2
+            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false, ..}) if id == did2
         ));
 
         println!("=== Check state before connect via DHT ===");
@@ -613,7 +841,7 @@ mod test {
         assert_eq!(ev_2.relay.path, vec![did1, did3]);
         assert!(matches!(
             ev_2.data,
-            Message::FindSuccessorSend(FindSuccessorSend{id, for_fix: false}) if id == did1
+            Message::FindSuccessorSend(FindSuccessorSend{id, for_fix: false, ..}) if id == did1
         ));
 
         // 1->3 FindSuccessorReport
@@ -623,7 +851,7 @@ mod test {
         assert_eq!(ev_3.relay.path, vec![did3, did1]);
         assert!(matches!(
             ev_3.data,
-            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false}) if id == did3
+            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false, ..}) if id == did3
         ));
         // dht3 won't set did3 as successor
         assert!(!node3.dht.lock().await.successor.list().contains(&did3));
@@ -639,7 +867,7 @@ mod test {
         assert_eq!(ev_1.relay.path, vec![did1, did3, did2]);
         assert!(matches!(
             ev_1.data,
-            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false}) if id == did1
+            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false, ..}) if id == did1
         ));
         // dht1 won't set did1 as successor
         assert!(!node1.dht.lock().await.successor.list().contains(&did1));
@@ -658,18 +886,13 @@ mod test {
     }
 
     fn gen_triple_ordered_keys() -> (SecretKey, SecretKey, SecretKey) {
-        let mut keys = Vec::from_iter(std::iter::repeat_with(SecretKey::random).take(3));
-        keys.sort_by(|a, b| {
-            if a.address() < b.address() {
-                std::cmp::Ordering::Less
-            } else {
-                std::cmp::Ordering::Greater
-            }
-        });
+        let keys = KeyFixtures::new(3).keys_spread(3);
         (keys[0], keys[1], keys[2])
     }
 
-    fn prepare_node(key: &SecretKey) -> (Did, Arc<Mutex<PeerRing>>, Arc<Swarm>, MessageHandler) {
+    pub fn prepare_node(
+        key: &SecretKey,
+    ) -> (Did, Arc<Mutex<PeerRing>>, Arc<Swarm>, MessageHandler) {
         let stun = "stun://stun.l.google.com:19302";
 
         let did = key.address().into();
@@ -684,7 +907,7 @@ mod test {
         (did, dht, swarm, node)
     }
 
-    async fn manually_establish_connection(swarm1: &Swarm, swarm2: &Swarm) -> Result<()> {
+    pub async fn manually_establish_connection(swarm1: &Swarm, swarm2: &Swarm) -> Result<()> {
         let sm1 = swarm1.session_manager();
         let sm2 = swarm2.session_manager();
 
@@ -752,7 +975,7 @@ mod test {
         assert_eq!(ev_1.relay.path, vec![did2]);
         assert!(matches!(
             ev_1.data,
-            Message::FindSuccessorSend(FindSuccessorSend{id, for_fix: false}) if id == did2
+            Message::FindSuccessorSend(FindSuccessorSend{id, for_fix: false, ..}) if id == did2
         ));
 
         // 2->1 FindSuccessorSend
@@ -761,7 +984,7 @@ mod test {
         assert_eq!(ev_2.relay.path, vec![did1]);
         assert!(matches!(
             ev_2.data,
-            Message::FindSuccessorSend(FindSuccessorSend{id, for_fix: false}) if id == did1
+            Message::FindSuccessorSend(FindSuccessorSend{id, for_fix: false, ..}) if id == did1
         ));
 
         Ok(())
@@ -785,7 +1008,7 @@ mod test {
         // node2 is only aware of node1, so it respond node1
         assert!(matches!(
             ev_1.data,
-            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false}) if id == did1
+            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false, ..}) if id == did1
         ));
         // dht1 won't set did1 as successor
         assert!(!dht1.lock().await.successor.list().contains(&did1));
@@ -798,7 +1021,7 @@ mod test {
         // node1 is only aware of node2, so it respond node2
         assert!(matches!(
             ev_2.data,
-            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false}) if id == did2
+            Message::FindSuccessorReport(FindSuccessorReport{id, for_fix: false, ..}) if id == did2
         ));
         // dht2 won't set did2 as successor
         assert!(!dht2.lock().await.successor.list().contains(&did2));
@@ -869,7 +1092,7 @@ mod test {
         assert_eq!(ev_1.relay.path, vec![did3]);
         assert!(matches!(
             ev_1.data,
-            Message::FindSuccessorSend(FindSuccessorSend{id, for_fix: false}) if id == did3
+            Message::FindSuccessorSend(FindSuccessorSend{id, for_fix: false, ..}) if id == did3
         ));
 
         // 1->3 FindSuccessorSend
@@ -878,7 +1101,7 @@ mod test {
         assert_eq!(ev_3.relay.path, vec![did1]);
         assert!(matches!(
             ev_3.data,
-            Message::FindSuccessorSend(FindSuccessorSend{id, for_fix: false}) if id == did1
+            Message::FindSuccessorSend(FindSuccessorSend{id, for_fix: false, ..}) if id == did1
         ));
 
         Ok(())
@@ -909,4 +1132,124 @@ mod test {
             assert!(swarm.get_transport(&addr).is_some());
         }
     }
+
+    #[tokio::test]
+    async fn test_topology_policy() -> Result<()> {
+        for (policy, pending) in [(TopologyPolicy::Eager, 1), (TopologyPolicy::Structured, 0)] {
+            let key1 = SecretKey::random();
+            let key2 = SecretKey::random();
+            let did1: Did = key1.address().into();
+            let did2: Did = key2.address().into();
+            let (node1, node2) = create_connected_pair(key1, key2).await?;
+            let node1 = node1.with_topology_policy(policy);
+
+            // successor list of node1 is full of nodes closer than the reported one
+            let one = || Did::from(BigUint::from(1u8));
+            let far = did1 - one();
+            {
+                let mut dht1 = node1.dht.lock().await;
+                dht1.successor.update(did2 + one());
+                dht1.successor.update(did2 + one() + one());
+                assert!(!dht1.successor.would_take(far));
+            }
+            node2
+                .send_direct_message(
+                    Message::FindSuccessorReport(FindSuccessorReport {
+                        id: far,
+                        for_fix: false,
+                        finger: None,
+                        successors: vec![],
+                    }),
+                    did1,
+                )
+                .await?;
+            assert!(node1.listen_once().await.is_some());
+            // far node is only connected eagerly, or dialed once messages are sent to it
+            assert_eq!(node1.swarm.pending_transports().await?.len(), pending);
+            assert_eq!(
+                node1.lazy_dial.is_some(),
+                policy == TopologyPolicy::Structured
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_successor_list_in_one_round_trip() -> Result<()> {
+        // responder < node1 < node2 < node3 < node4 on ring
+        let keys = KeyFixtures::new(5).keys_spread(5);
+        let responder = SessionManager::new_with_seckey(&keys[0]).unwrap();
+        let (did1, dht1, swarm1, node1) = prepare_node(&keys[1]);
+        let mut dids = vec![];
+        let mut swarms = vec![];
+        for key in &keys[2..] {
+            let (did, _, swarm, _) = prepare_node(key);
+            manually_establish_connection(&swarm1, &swarm).await?;
+            dids.push(did);
+            swarms.push(swarm);
+        }
+        assert!(is_successor_list(keys[0].address().into(), dids[0], &dids));
+
+        // successors out of order are dropped, only the reported one is taken
+        let report = |successors: Vec<Did>| FindSuccessorReport {
+            id: dids[0],
+            for_fix: false,
+            finger: None,
+            successors,
+        };
+        let disordered = report(vec![dids[0], dids[2], dids[1]]);
+        let payload = MessagePayload::new_direct(
+            Message::FindSuccessorReport(disordered.clone()),
+            &responder,
+            did1,
+        )?;
+        node1.handle(&payload, &disordered).await?;
+        assert_eq!(dht1.lock().await.successor.list(), vec![dids[0]]);
+
+        // one report of successor fills the whole successor list
+        let ordered = report(dids.clone());
+        let payload = MessagePayload::new_direct(
+            Message::FindSuccessorReport(ordered.clone()),
+            &responder,
+            did1,
+        )?;
+        node1.handle(&payload, &ordered).await?;
+        assert_eq!(dht1.lock().await.successor.list(), dids);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_finger_reports_in_flight() -> Result<()> {
+        let keys = KeyFixtures::new(6).keys_spread(4);
+        let responder = SessionManager::new_with_seckey(&keys[0]).unwrap();
+        let (did1, dht1, swarm1, node1) = prepare_node(&keys[1]);
+        let mut dids = vec![];
+        for key in &keys[2..] {
+            let (did, _, swarm, _) = prepare_node(key);
+            manually_establish_connection(&swarm1, &swarm).await?;
+            dids.push(did);
+        }
+
+        // reports of lookups in flight at the same time are set to fingers they are for,
+        // one from an older node is set to the finger being fixed
+        let fix_index = dht1.lock().await.fix_finger_index as usize;
+        for (id, finger) in [(dids[0], Some(150)), (dids[1], None)] {
+            let report = FindSuccessorReport {
+                id,
+                for_fix: true,
+                finger,
+                successors: vec![],
+            };
+            let payload = MessagePayload::new_direct(
+                Message::FindSuccessorReport(report.clone()),
+                &responder,
+                did1,
+            )?;
+            node1.handle(&payload, &report).await?;
+        }
+        let dht = dht1.lock().await;
+        assert_eq!(*dht.finger.get(150), Some(dids[0]));
+        assert_eq!(*dht.finger.get(fix_index), Some(dids[1]));
+        Ok(())
+    }
 }
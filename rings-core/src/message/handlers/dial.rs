@@ -0,0 +1,239 @@
+#![warn(missing_docs)]
+//! Lazy dial of peers, for application messages.
+//!
+//! With lazy dial on, see [MessageHandler::with_lazy_dial], a message to a peer which is
+//! neither connected nor marked unreachable doesn't fail. The first one dials the peer by
+//! [MessageHandler::connect], others are queued and sent once the peer is connected. At most
+//! `max_queued` messages wait for each peer. Every sender is told how its message went, a
+//! failed dial fails all of them, and a dial not done in [DIAL_TIMEOUT_MS] fails too.
+use std::sync::Arc;
+
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use futures::channel::oneshot;
+
+use crate::dht::Did;
+use crate::ecc::HashStr;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::handlers::relayed::send_app_payload;
+use crate::message::types::Message;
+use crate::message::MessageHandler;
+use crate::message::MessagePayload;
+use crate::utils;
+
+/// Messages queued for each peer by default.
+pub const DEFAULT_DIAL_QUEUE: usize = 64;
+/// Dial of a peer fails if it isn't connected in this time.
+pub const DIAL_TIMEOUT_MS: u128 = 10 * 1000;
+
+/// Message waiting for a peer, whether it asks for a receipt, and its sender told `tx_id` of
+/// it once it's sent.
+type Waiting = (Message, bool, oneshot::Sender<Result<HashStr>>);
+
+/// Messages waiting for a peer being dialed, with senders waiting for them.
+struct DialQueue {
+    started_ms: u128,
+    waiting: Vec<Waiting>,
+}
+
+/// Message queued by [LazyDial::enqueue].
+enum Enqueued {
+    /// First one, its sender dials the peer.
+    Dial(Message),
+    /// Queued behind the dial, resolves when it's sent or the dial fails.
+    Wait(oneshot::Receiver<Result<HashStr>>),
+}
+
+/// Messages waiting for peers being dialed.
+pub struct LazyDial {
+    max_queued: usize,
+    queues: DashMap<Did, DialQueue>,
+}
+
+impl Default for LazyDial {
+    fn default() -> Self {
+        Self::new(DEFAULT_DIAL_QUEUE)
+    }
+}
+
+impl LazyDial {
+    /// Queue at most `max_queued` messages for each peer.
+    pub fn new(max_queued: usize) -> Self {
+        Self {
+            max_queued,
+            queues: DashMap::new(),
+        }
+    }
+
+    /// Count of messages waiting for `peer`, with the one dialing it.
+    pub fn queued(&self, peer: Did) -> usize {
+        self.queues.get(&peer).map_or(0, |q| q.waiting.len() + 1)
+    }
+
+    /// Queue `msg` for `peer`, it's to be sent by caller with the dial if there is no dial yet.
+    /// A dial left by a cancelled caller is given up after twice of [DIAL_TIMEOUT_MS], its
+    /// waiting senders are failed.
+    fn enqueue(&self, peer: Did, msg: Message, receipt: bool) -> Result<Enqueued> {
+        self.enqueue_at(peer, msg, receipt, utils::get_epoch_ms())
+    }
+
+    fn enqueue_at(&self, peer: Did, msg: Message, receipt: bool, now: u128) -> Result<Enqueued> {
+        let new = DialQueue {
+            started_ms: now,
+            waiting: vec![],
+        };
+        let mut queue = match self.queues.entry(peer) {
+            Entry::Vacant(e) => {
+                e.insert(new);
+                return Ok(Enqueued::Dial(msg));
+            }
+            Entry::Occupied(e) => e.into_ref(),
+        };
+        if now.saturating_sub(queue.started_ms) > 2 * DIAL_TIMEOUT_MS {
+            *queue = new;
+            return Ok(Enqueued::Dial(msg));
+        }
+        if queue.waiting.len() + 1 >= self.max_queued {
+            return Err(Error::DialQueueFull(format!("{:?}", *peer)));
+        }
+        let (tx, rx) = oneshot::channel();
+        queue.waiting.push((msg, receipt, tx));
+        Ok(Enqueued::Wait(rx))
+    }
+
+    fn take(&self, peer: Did) -> Vec<Waiting> {
+        self.queues
+            .remove(&peer)
+            .map(|(_, q)| q.waiting)
+            .unwrap_or_default()
+    }
+}
+
+impl MessageHandler {
+    /// Queue application messages to peers not connected, and dial them, see [LazyDial].
+    /// It's disabled if `max_queued` is 0.
+    pub fn with_lazy_dial(mut self, max_queued: usize) -> Self {
+        self.lazy_dial = (max_queued > 0).then(|| Arc::new(LazyDial::new(max_queued)));
+        self
+    }
+
+    /// Send `msg` to `destination` once it's dialed, the first message dials it and sends the
+    /// queued ones. Fails if the dial fails. Asks for receipt of `msg` if `receipt` is true,
+    /// returns `tx_id` of it.
+    pub(crate) async fn dial_and_send(
+        &self,
+        dial: &LazyDial,
+        msg: Message,
+        destination: Did,
+        receipt: bool,
+    ) -> Result<HashStr> {
+        let msg = match dial.enqueue(destination, msg, receipt)? {
+            Enqueued::Dial(msg) => msg,
+            Enqueued::Wait(rx) => {
+                // dial is given up without telling, by a cancelled caller
+                return rx
+                    .await
+                    .unwrap_or_else(|_| Err(Error::DialFailed(format!("{:?}", *destination))));
+            }
+        };
+        tracing::debug!(peer = ?destination, "lazy dial peer");
+        let dialed = self.wait_dialed(destination).await;
+        let waiting = dial.take(destination);
+        if let Err(e) = dialed {
+            tracing::warn!(
+                peer = ?destination,
+                dropped = waiting.len() + 1,
+                "lazy dial failed: {}",
+                e
+            );
+            for (_, _, tx) in waiting {
+                let failed = Error::DialFailed(format!("{:?}: {}", *destination, e));
+                tx.send(Err(failed)).ok();
+            }
+            return Err(e);
+        }
+        let sent = self.send_dialed(msg, destination, receipt).await;
+        for (msg, receipt, tx) in waiting {
+            tx.send(self.send_dialed(msg, destination, receipt).await)
+                .ok();
+        }
+        sent
+    }
+
+    /// Send `msg` to `destination` just dialed.
+    async fn send_dialed(&self, msg: Message, destination: Did, receipt: bool) -> Result<HashStr> {
+        let payload = MessagePayload::new_direct(msg, self.swarm.session_manager(), destination)?;
+        send_app_payload(&self.swarm, payload, receipt.then(|| &*self.receipts)).await
+    }
+
+    /// Connect `destination`, waits until it's registered and connected.
+    #[cfg(not(feature = "wasm"))]
+    async fn wait_dialed(&self, destination: Did) -> Result<()> {
+        self.connect_with_timeout(&destination.into(), DIAL_TIMEOUT_MS)
+            .await
+            .map(|_| ())
+    }
+
+    /// Connect `destination`, waits until its data channel is open.
+    #[cfg(feature = "wasm")]
+    async fn wait_dialed(&self, destination: Did) -> Result<()> {
+        let dialed = async {
+            let transport = self.connect(&destination.into()).await?;
+            transport.wait_for_data_channel_open().await
+        };
+        let timeout = std::time::Duration::from_millis(DIAL_TIMEOUT_MS as u64);
+        crate::timer::timeout(timeout, dialed)
+            .await
+            .unwrap_or_else(|| Err(Error::DialTimeout(format!("{:?}", *destination))))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    #[test]
+    fn test_dial_queue() {
+        let dial = LazyDial::new(2);
+        let peer: Did = SecretKey::random().address().into();
+        let msg = || Message::custom(b"hello", &None).unwrap();
+        assert!(matches!(
+            dial.enqueue_at(peer, msg(), false, 100),
+            Ok(Enqueued::Dial(_))
+        ));
+        let mut waiting = match dial.enqueue_at(peer, msg(), false, 200).unwrap() {
+            Enqueued::Wait(rx) => rx,
+            Enqueued::Dial(_) => panic!("peer is dialed twice"),
+        };
+        assert!(dial.enqueue_at(peer, msg(), false, 200).is_err());
+        assert_eq!(dial.queued(peer), 2);
+
+        // waiting sender is told its message failed with the dial
+        for (_, _, tx) in dial.take(peer) {
+            tx.send(Err(Error::DialFailed("peer".to_owned()))).ok();
+        }
+        assert!(matches!(
+            waiting.try_recv(),
+            Ok(Some(Err(Error::DialFailed(_))))
+        ));
+        assert_eq!(dial.queued(peer), 0);
+
+        // a dial left behind is given up, its senders fail
+        assert!(matches!(
+            dial.enqueue_at(peer, msg(), false, 300),
+            Ok(Enqueued::Dial(_))
+        ));
+        let mut waiting = match dial.enqueue_at(peer, msg(), false, 400).unwrap() {
+            Enqueued::Wait(rx) => rx,
+            Enqueued::Dial(_) => panic!("peer is dialed twice"),
+        };
+        let later = 301 + 2 * DIAL_TIMEOUT_MS;
+        assert!(matches!(
+            dial.enqueue_at(peer, msg(), false, later),
+            Ok(Enqueued::Dial(_))
+        ));
+        assert!(waiting.try_recv().is_err());
+    }
+}
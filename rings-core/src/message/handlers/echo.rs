@@ -0,0 +1,122 @@
+#![warn(missing_docs)]
+//! Echo of custom messages back to their senders, so a peer can be benchmarked.
+//!
+//! With echo on, every custom message sent to this node is sent back to its origin with
+//! [ECHO_PREFIX] in front. Echoes are never echoed again, so two echoing nodes don't loop.
+//! Echoed messages are counted in [EchoStats].
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use crate::dht::Did;
+use crate::err::Result;
+use crate::message::CustomMessage;
+use crate::message::MaybeEncrypted;
+use crate::message::Message;
+use crate::message::MessageHandler;
+use crate::message::MessagePayload;
+
+/// Prefix of echoed messages.
+pub const ECHO_PREFIX: &[u8] = b"rings-echo:";
+
+/// Data of message `data` echoes, None if it isn't an echo.
+pub fn strip_echo(data: &[u8]) -> Option<&[u8]> {
+    data.strip_prefix(ECHO_PREFIX)
+}
+
+/// Messages echoed by node since it started.
+#[derive(Debug, Default)]
+pub struct EchoStats {
+    echoed: AtomicU64,
+    bytes: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl EchoStats {
+    /// Count of messages echoed.
+    pub fn echoed(&self) -> u64 {
+        self.echoed.load(Ordering::Relaxed)
+    }
+
+    /// Bytes of data echoed.
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Count of messages failed to echo.
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+}
+
+impl MessageHandler {
+    /// Echo custom messages back to their senders with [ECHO_PREFIX]. Off by default.
+    pub fn with_echo(mut self, echo: bool) -> Self {
+        self.echo = echo.then(|| Arc::new(EchoStats::default()));
+        self
+    }
+
+    /// Stats of echoed messages, None if echo is off.
+    pub fn echo_stats(&self) -> Option<Arc<EchoStats>> {
+        self.echo.clone()
+    }
+
+    /// Echo `msg` back to origin of `payload`, if echo is on and it's sent to this node.
+    /// A message encrypted to this node is echoed encrypted to session of origin.
+    pub(super) async fn echo(
+        &self,
+        payload: &MessagePayload<Message>,
+        msg: &MaybeEncrypted<CustomMessage>,
+    ) {
+        let stats = match &self.echo {
+            Some(s) if payload.relay.destination == Did::from(self.swarm.address()) => s,
+            _ => return,
+        };
+        match self.echo_back(payload, msg).await {
+            Ok(Some(len)) => {
+                stats.echoed.fetch_add(1, Ordering::Relaxed);
+                stats.bytes.fetch_add(len as u64, Ordering::Relaxed);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                stats.failed.fetch_add(1, Ordering::Relaxed);
+                tracing::debug!(tx_id = ?payload.tx_id, "failed to echo message: {}", e);
+            }
+        }
+    }
+
+    /// Returns length of data echoed, None for an echo, which isn't echoed again.
+    async fn echo_back(
+        &self,
+        payload: &MessagePayload<Message>,
+        msg: &MaybeEncrypted<CustomMessage>,
+    ) -> Result<Option<usize>> {
+        let (plain, encrypted) = self.open_msg(msg)?;
+        if strip_echo(&plain.0).is_some() {
+            return Ok(None);
+        }
+        let pubkey = if encrypted {
+            Some(payload.origin_session_pubkey()?)
+        } else {
+            None
+        };
+        let mut data = ECHO_PREFIX.to_vec();
+        data.extend_from_slice(&plain.0);
+        self.send_app_message(Message::custom(&data, &pubkey)?, payload.relay.origin())
+            .await?;
+        Ok(Some(plain.0.len()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_strip_echo() {
+        let mut data = ECHO_PREFIX.to_vec();
+        data.extend_from_slice(b"hello");
+        assert_eq!(strip_echo(&data), Some(&b"hello"[..]));
+        assert_eq!(strip_echo(b"hello"), None);
+    }
+}
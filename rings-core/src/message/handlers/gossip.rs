@@ -0,0 +1,81 @@
+#![warn(missing_docs)]
+//! Exchange of peer samples, see [crate::gossip].
+//!
+//! [PeerSampleSend] goes to a connected peer, which merges the sample into its view and answers
+//! [PeerSampleReport] with a sample of its own. On the answer, a node with few connected peers
+//! dials one of the peers it learned, relay capable ones first, to bootstrap its connections.
+use async_trait::async_trait;
+
+use crate::dht::Did;
+use crate::err::Result;
+use crate::gossip::verify_samples;
+use crate::message::types::Message;
+use crate::message::types::PeerSampleReport;
+use crate::message::types::PeerSampleSend;
+use crate::message::HandleMsg;
+use crate::message::MessageHandler;
+use crate::message::MessagePayload;
+use crate::message::PayloadSender;
+use crate::swarm::TransportManager;
+
+/// Peers learned by gossip are dialed while fewer peers than this are connected.
+pub const BOOTSTRAP_PEERS: usize = 4;
+
+impl MessageHandler {
+    /// Dial a peer learned by gossip, relay capable and recently seen ones first, if this
+    /// node has fewer than [BOOTSTRAP_PEERS] connected peers.
+    async fn bootstrap_from_view(&self) -> Result<()> {
+        if self.swarm.get_transport_numbers() >= BOOTSTRAP_PEERS {
+            return Ok(());
+        }
+        let local: Did = self.swarm.address().into();
+        let candidate = self
+            .swarm
+            .peer_view()
+            .peers()
+            .into_iter()
+            .filter(|s| s.did != local && self.swarm.get_transport(&s.did.into()).is_none())
+            .max_by_key(|s| (s.relay, s.seen_ms));
+        if let Some(s) = candidate {
+            tracing::debug!(peer = ?s.did, relay = s.relay, "bootstrap from peer view");
+            self.connect(&s.did.into()).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<PeerSampleSend> for MessageHandler {
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &PeerSampleSend) -> Result<()> {
+        let local: Did = self.swarm.address().into();
+        let sender: Did = ctx.addr.into();
+        let signer = Did::from(ctx.origin_verification.session.auth.authorizer);
+        let samples = verify_samples(signer, msg.peers.clone())?;
+        self.swarm.peer_view().merge(local, samples);
+        let mut relay = ctx.relay.clone();
+        relay.relay(local, None)?;
+        let report = PeerSampleReport {
+            peers: self.swarm.peer_samples(sender),
+        };
+        self.send_report_message(Message::PeerSampleReport(report), relay)
+            .await
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<PeerSampleReport> for MessageHandler {
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &PeerSampleReport) -> Result<()> {
+        let local: Did = self.swarm.address().into();
+        let mut relay = ctx.relay.clone();
+        relay.relay(local, None)?;
+        if relay.next_hop.is_some() {
+            return self.transpond_payload(ctx, relay).await;
+        }
+        let signer = Did::from(ctx.origin_verification.session.auth.authorizer);
+        let samples = verify_samples(signer, msg.peers.clone())?;
+        self.swarm.peer_view().merge(local, samples);
+        self.bootstrap_from_view().await
+    }
+}
@@ -0,0 +1,323 @@
+#![warn(missing_docs)]
+//! Store-and-forward of messages to offline nodes.
+//!
+//! A message to an offline node is encrypted to it and stored as an [VNodeType::Inbox] virtual
+//! node at [VirtualNode::inbox_address] of recipient, so the holder never reads it. The holder
+//! hands the inbox over when recipient joins it, and recipient pulls its inbox on stabilization.
+//! Messages stay in inbox until recipient acknowledges them by [InboxAck], so one may be
+//! delivered more than once if the ack is lost, never zero times.
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::storage::TChordStorage;
+use crate::dht::vnode::VNodeType;
+use crate::dht::vnode::VirtualNode;
+use crate::dht::Chord;
+use crate::dht::ChordStorage;
+use crate::dht::Did;
+use crate::dht::PeerRingAction;
+use crate::ecc::HashStr;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::types::InboxAck;
+use crate::message::types::MaybeEncrypted;
+use crate::message::types::Message;
+use crate::message::types::SearchVNode;
+use crate::message::Decoder;
+use crate::message::Encoded;
+use crate::message::Encoder;
+use crate::message::HandleMsg;
+use crate::message::MessageHandler;
+use crate::message::MessagePayload;
+use crate::message::PayloadSender;
+use crate::utils;
+
+/// How long a message waits in inbox by default, 7 days.
+pub const DEFAULT_INBOX_TTL_MS: u128 = 7 * 24 * 3600 * 1000;
+
+/// A message waiting in inbox.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct InboxEntry {
+    /// When the message is dropped, in milliseconds since epoch.
+    pub expires_ms: u128,
+    /// Payload signed by sender, with recipient as destination.
+    pub payload: MessagePayload<Message>,
+}
+
+impl InboxEntry {
+    /// Check if entry is expired.
+    pub fn is_expired(&self) -> bool {
+        utils::get_epoch_ms() > self.expires_ms
+    }
+
+    fn encode(&self) -> Result<Encoded> {
+        serde_json::to_string(self)
+            .map_err(Error::Serialize)?
+            .encode()
+    }
+
+    fn decode(encoded: &Encoded) -> Result<Self> {
+        let s = String::from_encoded(encoded)?;
+        serde_json::from_str(&s).map_err(Error::Deserialize)
+    }
+}
+
+/// TInbox should imply store-and-forward of messages to offline nodes
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+pub trait TInbox {
+    /// Store `payload` in inbox of its destination for `ttl_ms`, it should be a custom message
+    /// encrypted to destination.
+    async fn store_offline(&self, payload: MessagePayload<Message>, ttl_ms: u128) -> Result<()>;
+    /// Ask the holder of own inbox for pending messages.
+    async fn check_inbox(&self) -> Result<()>;
+    /// Handle messages of own inbox, expired or forged ones are dropped. Handled and dropped
+    /// ones are acknowledged to the holder.
+    async fn deliver_inbox(&self, inbox: VirtualNode) -> Result<()>;
+}
+
+impl MessageHandler {
+    /// Split own inbox out of `vnodes`, which should be delivered instead of stored.
+    pub(crate) fn take_own_inbox(
+        &self,
+        vnodes: Vec<VirtualNode>,
+    ) -> Result<(Vec<VirtualNode>, Vec<VirtualNode>)> {
+        let own = VirtualNode::inbox_address(self.swarm.address().into())?;
+        Ok(vnodes
+            .into_iter()
+            .partition(|v| v.kind == VNodeType::Inbox && v.address == own))
+    }
+
+    /// Remove messages `tx_ids` and expired ones from inbox `id` stored here, the inbox is
+    /// removed once it's empty.
+    async fn remove_acked(&self, id: &Did, tx_ids: &[HashStr]) {
+        let dht = self.dht.lock().await;
+        let inbox = match dht.storage.get(id) {
+            Some(v) if v.kind == VNodeType::Inbox => v,
+            _ => return,
+        };
+        let data = inbox
+            .data
+            .iter()
+            .filter(|e| {
+                InboxEntry::decode(e)
+                    .map(|entry| !entry.is_expired() && !tx_ids.contains(&entry.payload.tx_id))
+                    .unwrap_or(false)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        if data.is_empty() {
+            dht.storage.remove(id);
+        } else {
+            dht.storage.set(id, VirtualNode { data, ..inbox });
+        }
+    }
+
+    /// Acknowledge messages `tx_ids` of own inbox to its holder.
+    async fn ack_inbox(&self, tx_ids: Vec<HashStr>) -> Result<()> {
+        if tx_ids.is_empty() {
+            return Ok(());
+        }
+        let id = VirtualNode::inbox_address(self.swarm.address().into())?;
+        let action = {
+            let dht = self.dht.lock().await;
+            dht.find_successor(id)?
+        };
+        match action {
+            PeerRingAction::Some(_) => {
+                self.remove_acked(&id, &tx_ids).await;
+                Ok(())
+            }
+            PeerRingAction::RemoteAction(next, _) => {
+                self.send_direct_message(Message::InboxAck(InboxAck { id, tx_ids }), next)
+                    .await
+            }
+            act => Err(Error::PeerRingUnexpectedAction(act)),
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<InboxAck> for MessageHandler {
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &InboxAck) -> Result<()> {
+        // only recipient of inbox acknowledges its messages
+        let recipient = Did::from(ctx.origin_verification.session.auth.authorizer);
+        if VirtualNode::inbox_address(recipient)? != msg.id {
+            tracing::warn!(recipient = ?recipient, inbox = ?msg.id, "drop ack of others' inbox");
+            return Ok(());
+        }
+        let (id, action) = {
+            let dht = self.dht.lock().await;
+            (dht.id, dht.find_successor(msg.id)?)
+        };
+        match action {
+            PeerRingAction::Some(_) => {
+                self.remove_acked(&msg.id, &msg.tx_ids).await;
+                Ok(())
+            }
+            PeerRingAction::RemoteAction(next, _) => {
+                let mut relay = ctx.relay.clone();
+                relay.reset_destination(next)?;
+                relay.relay(id, Some(next))?;
+                self.transpond_payload(ctx, relay).await
+            }
+            act => Err(Error::PeerRingUnexpectedAction(act)),
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl TInbox for MessageHandler {
+    async fn store_offline(&self, payload: MessagePayload<Message>, ttl_ms: u128) -> Result<()> {
+        if !matches!(
+            payload.data,
+            Message::CustomMessage(MaybeEncrypted::Encrypted(_))
+        ) {
+            return Err(Error::InboxNotEncrypted);
+        }
+        let recipient = payload.relay.destination;
+        let entry = InboxEntry {
+            expires_ms: utils::get_epoch_ms() + ttl_ms,
+            payload,
+        };
+        let inbox = VirtualNode::inbox(recipient, vec![entry.encode()?])?;
+        tracing::debug!(recipient = ?recipient, inbox = ?inbox.address, "store message in inbox");
+        self.store(inbox).await
+    }
+
+    async fn check_inbox(&self) -> Result<()> {
+        let id = VirtualNode::inbox_address(self.swarm.address().into())?;
+        let action = {
+            let dht = self.dht.lock().await;
+            dht.lookup(&id)?
+        };
+        match action {
+            PeerRingAction::None => Ok(()),
+            PeerRingAction::SomeVNode(v) => self.deliver_inbox(v).await,
+            PeerRingAction::RemoteAction(next, _) => {
+                self.send_direct_message(Message::SearchVNode(SearchVNode { id }), next)
+                    .await
+            }
+            act => Err(Error::PeerRingUnexpectedAction(act)),
+        }
+    }
+
+    async fn deliver_inbox(&self, inbox: VirtualNode) -> Result<()> {
+        let own: Did = self.swarm.address().into();
+        let mut acked = vec![];
+        for encoded in inbox.data.iter() {
+            let entry = match InboxEntry::decode(encoded) {
+                Ok(e) => e,
+                Err(e) => {
+                    tracing::warn!("drop undecodable inbox entry: {}", e);
+                    continue;
+                }
+            };
+            let payload = &entry.payload;
+            if entry.is_expired()
+                || payload.relay.destination != own
+                || !payload
+                    .origin_verification
+                    .verify_payload(&payload.data, &payload.network_id)
+            {
+                tracing::warn!(tx_id = ?payload.tx_id, "drop expired or invalid inbox entry");
+                acked.push(payload.tx_id.clone());
+                continue;
+            }
+            match self.handle_payload(payload).await {
+                Ok(()) => acked.push(payload.tx_id.clone()),
+                Err(e) => {
+                    tracing::warn!(tx_id = ?payload.tx_id, "failed to handle inbox entry: {}", e)
+                }
+            }
+        }
+        self.ack_inbox(acked).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ecc::SecretKey;
+    use crate::session::SessionManager;
+
+    #[test]
+    fn test_inbox_entry() {
+        let key = SecretKey::random();
+        let session = SessionManager::new_with_seckey(&key).unwrap();
+        let recipient: Did = SecretKey::random().address().into();
+        let payload = MessagePayload::new_direct(
+            Message::custom("hello".as_bytes(), &None).unwrap(),
+            &session,
+            recipient,
+        )
+        .unwrap();
+        let entry = InboxEntry {
+            expires_ms: utils::get_epoch_ms() + DEFAULT_INBOX_TTL_MS,
+            payload,
+        };
+        let a = VirtualNode::inbox(recipient, vec![entry.encode().unwrap()]).unwrap();
+        let b = VirtualNode::inbox(recipient, vec![entry.encode().unwrap()]).unwrap();
+        assert_eq!(a.address, VirtualNode::inbox_address(recipient).unwrap());
+        assert_ne!(a.address, recipient);
+
+        let inbox = VirtualNode::concat(&a, &b).unwrap();
+        assert_eq!(inbox.data.len(), 2);
+        let decoded = InboxEntry::decode(&inbox.data[1]).unwrap();
+        assert_eq!(decoded, entry);
+        assert!(!decoded.is_expired());
+        assert!(decoded
+            .payload
+            .origin_verification
+            .verify(&decoded.payload.data));
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    #[tokio::test]
+    async fn test_inbox_kept_until_acked() {
+        use crate::message::handlers::connection::test::prepare_node;
+
+        let key = SecretKey::random();
+        let (_, dht, swarm, node) = prepare_node(&key);
+        let session = swarm.session_manager();
+        let recipient_key = SecretKey::random();
+        let recipient: Did = recipient_key.address().into();
+        let id = VirtualNode::inbox_address(recipient).unwrap();
+
+        let plain = MessagePayload::new_direct(
+            Message::custom("hello".as_bytes(), &None).unwrap(),
+            session,
+            recipient,
+        )
+        .unwrap();
+        assert!(matches!(
+            node.store_offline(plain, DEFAULT_INBOX_TTL_MS).await,
+            Err(Error::InboxNotEncrypted)
+        ));
+
+        let mut tx_ids = vec![];
+        for text in ["hello", "world"] {
+            let msg = Message::custom(text.as_bytes(), &Some(recipient_key.pubkey())).unwrap();
+            let payload = MessagePayload::new_direct(msg, session, recipient).unwrap();
+            tx_ids.push(payload.tx_id.clone());
+            node.store_offline(payload, DEFAULT_INBOX_TTL_MS)
+                .await
+                .unwrap();
+        }
+        assert_eq!(dht.lock().await.storage.get(&id).unwrap().data.len(), 2);
+
+        node.remove_acked(&id, &tx_ids[..1]).await;
+        let inbox = dht.lock().await.storage.get(&id).unwrap();
+        assert_eq!(inbox.data.len(), 1);
+        assert_eq!(
+            InboxEntry::decode(&inbox.data[0]).unwrap().payload.tx_id,
+            tx_ids[1]
+        );
+
+        node.remove_acked(&id, &tx_ids[1..]).await;
+        assert!(dht.lock().await.storage.get(&id).is_none());
+    }
+}
@@ -1,36 +1,92 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_recursion::async_recursion;
 use async_trait::async_trait;
+use dashmap::DashMap;
 use futures::lock::Mutex;
-use web3::types::Address;
 
+use self::callback::CallbackFilter;
+use self::callback::CallbackRegistry;
+use self::callback::DEFAULT_CALLBACK;
+use self::connection::TopologyPolicy;
+use self::dial::LazyDial;
+use self::dial::DEFAULT_DIAL_QUEUE;
+use self::echo::EchoStats;
+use self::receipt::Receipts;
+use self::stream::StreamManager;
 use super::CustomMessage;
+use super::LeaveDHT;
 use super::MaybeEncrypted;
 use super::Message;
 use super::MessagePayload;
 use super::OriginVerificationGen;
 use super::PayloadSender;
+use super::RelayMethod;
+use super::SyncVNodeWithSuccessor;
+use super::TopologyReport;
+use crate::address::Address;
+use crate::admission::OfferAdmissionFn;
+use crate::admission::StoreAdmissionFn;
 use crate::dht::Chord;
+use crate::dht::DhtEvent;
+use crate::dht::Did;
 use crate::dht::PeerRing;
 use crate::dht::PeerRingAction;
 use crate::err::Error;
 use crate::err::Result;
+#[cfg(not(feature = "wasm"))]
+use crate::group::GroupMessage;
+#[cfg(not(feature = "wasm"))]
+use crate::history::MessageHistory;
+use crate::overload::OverloadGuard;
 use crate::prelude::RTCSdpType;
 use crate::prelude::Transport;
 use crate::session::SessionManager;
+use crate::swarm::DrainState;
 use crate::swarm::Swarm;
 use crate::swarm::TransportManager;
+use crate::timer;
+use crate::types::ice_transport::IceTransport;
 use crate::types::ice_transport::IceTrickleScheme;
+use crate::utils;
 
+/// Proof of storage challenges
+pub mod audit;
+/// Registry of message callbacks
+pub mod callback;
 /// Operator and Handler for Connection
 pub mod connection;
+/// Lazy dial of peers not connected
+pub mod dial;
+/// Echo of custom messages for benchmarking
+pub mod echo;
+/// Exchange of peer samples by gossip
+pub mod gossip;
+/// Operator and Handler for offline Inbox
+pub mod inbox;
+/// Shedding of application messages under overload
+pub mod overload;
+/// Exchange of connected peers
+pub mod pex;
+/// Delivery receipts with latency of each hop
+pub mod receipt;
+/// Application traffic relayed along DHT path
+pub mod relayed;
+/// Rotation of identity
+pub mod rotation;
 /// Operator and handler for DHT stablization
 pub mod stablization;
 /// Operator and Handler for Storage
 pub mod storage;
+/// Byte streams between peers
+pub mod stream;
 /// Operator and Handler for SubRing
 pub mod subring;
+/// Handled messages as streams
+pub mod subscription;
+/// Ring neighbours of remote nodes
+pub mod topology;
 
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
 #[cfg_attr(not(feature = "wasm"), async_trait)]
@@ -54,7 +110,30 @@ type CallbackFn = Box<dyn MessageCallback>;
 pub struct MessageHandler {
     dht: Arc<Mutex<PeerRing>>,
     swarm: Arc<Swarm>,
-    callback: Arc<Mutex<Option<CallbackFn>>>,
+    callbacks: Arc<CallbackRegistry>,
+    streams: Arc<StreamManager>,
+    /// Reports of [topology] queries, with time they are received.
+    topology: Arc<DashMap<Did, (u128, TopologyReport)>>,
+    /// [topology] queries waiting for reports, with time they are sent.
+    topology_queries: Arc<DashMap<Did, u128>>,
+    /// Messages waiting for [receipt]s, and receipts delivered.
+    receipts: Arc<Receipts>,
+    /// Queue of messages to peers being dialed, None if lazy dial is off.
+    lazy_dial: Option<Arc<LazyDial>>,
+    /// Finger lookups sent at the same time on joining a ring, see [connection].
+    join_parallelism: usize,
+    /// Which peers reported on finding successors are connected, see [connection].
+    topology_policy: TopologyPolicy,
+    /// Sheds application messages under overload, None if shedding is off.
+    overload: Option<Arc<OverloadGuard>>,
+    /// Stats of custom messages echoed, None if echo is off.
+    echo: Option<Arc<EchoStats>>,
+    /// Asked before storing virtual nodes of others, None if everything is stored.
+    admission: Option<Arc<StoreAdmissionFn>>,
+    /// Asked before answering offers relayed over DHT, None if every one is answered.
+    offer_admission: Option<Arc<OfferAdmissionFn>>,
+    #[cfg(not(feature = "wasm"))]
+    history: Option<Arc<MessageHistory>>,
 }
 
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
@@ -63,30 +142,123 @@ pub trait HandleMsg<T> {
     async fn handle(&self, ctx: &MessagePayload<Message>, msg: &T) -> Result<()>;
 }
 
+/// Wait for answer of connect request at most this long, in milliseconds.
+pub const DEFAULT_CONNECT_TIMEOUT_MS: u128 = 15 * 1000;
+/// Finger lookups sent at the same time on joining a ring, suggested for nodes.
+pub const DEFAULT_JOIN_PARALLELISM: usize = 4;
+
+/// Next hop of connect request to `target`, other than `avoid`. Prefers a route observed
+/// recently, then relay capable nodes closer than DHT path, then DHT path.
+fn connect_next_hop(dht: &PeerRing, target: Did, avoid: Option<Did>) -> Result<Did> {
+    // target not connected is kept in DHT while it's migrating, it never relays to itself
+    let usable = |n: &Did| Some(*n) != avoid && *n != target;
+    let skip = avoid.into_iter().chain([target]).collect::<Vec<_>>();
+    if let Some(node) = dht.cached_route(target).filter(usable) {
+        return Ok(node);
+    }
+    match dht.find_successor(target)? {
+        PeerRingAction::Some(node) | PeerRingAction::RemoteAction(node, _) if usable(&node) => {
+            Some(dht.prefer_relay(target, node, &skip))
+        }
+        _ => dht.closest_relay(target, &skip).or_else(|| {
+            dht.route_candidates(target)
+                .into_iter()
+                .chain(dht.successor.list())
+                .find(|n| usable(n) && *n != dht.id)
+        }),
+    }
+    .ok_or(Error::NoNextHop)
+}
+
 impl MessageHandler {
     pub fn new_with_callback(
         dht: Arc<Mutex<PeerRing>>,
         swarm: Arc<Swarm>,
         callback: CallbackFn,
     ) -> Self {
-        Self {
-            dht,
-            swarm,
-            callback: Arc::new(Mutex::new(Some(callback))),
-        }
+        let handler = Self::new(dht, swarm);
+        handler
+            .callbacks
+            .register(DEFAULT_CALLBACK, CallbackFilter::default(), callback);
+        handler
     }
 
     pub fn new(dht: Arc<Mutex<PeerRing>>, swarm: Arc<Swarm>) -> Self {
+        // routed application messages are forwarded by swarm, before decoding their bodies
+        swarm.set_raw_router(relayed::RawRouter::new(&swarm, dht.clone()));
         Self {
             dht,
             swarm,
-            callback: Arc::new(Mutex::new(None)),
+            callbacks: Arc::new(CallbackRegistry::new()),
+            streams: Arc::new(StreamManager::new()),
+            topology: Arc::new(DashMap::new()),
+            topology_queries: Arc::new(DashMap::new()),
+            receipts: Arc::new(Receipts::new()),
+            lazy_dial: None,
+            join_parallelism: 1,
+            topology_policy: TopologyPolicy::default(),
+            overload: None,
+            echo: None,
+            admission: None,
+            offer_admission: None,
+            #[cfg(not(feature = "wasm"))]
+            history: None,
+        }
+    }
+
+    /// Look up at most `parallelism` fingers at the same time on joining a ring, their
+    /// successors are connected concurrently. Only successor is looked up if it's 0 or 1,
+    /// which is the default.
+    pub fn with_join_parallelism(mut self, parallelism: usize) -> Self {
+        self.join_parallelism = parallelism;
+        self
+    }
+
+    /// Connect peers reported on finding successors by `policy`, every one of them is connected
+    /// by default, see [TopologyPolicy]. [TopologyPolicy::Structured] turns on lazy dial with
+    /// [DEFAULT_DIAL_QUEUE] messages for each peer, if it's off.
+    pub fn with_topology_policy(mut self, policy: TopologyPolicy) -> Self {
+        self.topology_policy = policy;
+        if policy == TopologyPolicy::Structured && self.lazy_dial.is_none() {
+            self.lazy_dial = Some(Arc::new(LazyDial::new(DEFAULT_DIAL_QUEUE)));
         }
+        self
+    }
+
+    /// Ask `admission` before storing virtual nodes published by others, see [crate::admission].
+    pub fn with_store_admission(mut self, admission: StoreAdmissionFn) -> Self {
+        self.admission = Some(Arc::new(admission));
+        self
+    }
+
+    /// Ask `admission` with the peer relaying each offer of connecting this node over DHT,
+    /// refused ones are dropped, see [crate::admission].
+    pub fn with_offer_admission(mut self, admission: OfferAdmissionFn) -> Self {
+        self.offer_admission = Some(Arc::new(admission));
+        self
+    }
+
+    /// Persist custom messages sent to this node, see [MessageHistory].
+    #[cfg(not(feature = "wasm"))]
+    pub fn with_history(mut self, history: Arc<MessageHistory>) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    pub fn history(&self) -> Option<Arc<MessageHistory>> {
+        self.history.clone()
+    }
+
+    pub fn dht(&self) -> Arc<Mutex<PeerRing>> {
+        self.dht.clone()
     }
 
+    /// Replace callback named [DEFAULT_CALLBACK], which is invoked for every message. Use
+    /// [Self::register_callback] to have more of them.
     pub async fn set_callback(&self, f: CallbackFn) {
-        let mut cb = self.callback.lock().await;
-        *cb = Some(f)
+        self.callbacks
+            .register(DEFAULT_CALLBACK, CallbackFilter::default(), f);
     }
 
     // disconnect a node if a node is in DHT
@@ -96,11 +268,63 @@ impl MessageHandler {
         self.swarm.remove_transport(&address);
     }
 
+    /// Leave the ring gracefully, for upgrading without losing data:
+    /// 1. refuse new connections and messages,
+    /// 2. hand over stored virtual nodes to successor,
+    /// 3. notify peers with `LeaveDHT` and close transports one by one.
+    pub async fn drain(&self) -> Result<()> {
+        self.swarm.set_drain_state(DrainState::Draining);
+        let (id, successor, vnodes) = {
+            let dht = self.dht.lock().await;
+            (dht.id, dht.successor.min(), dht.storage.values())
+        };
+        if successor != id && !vnodes.is_empty() {
+            tracing::info!(successor = ?successor, "hand over {} vnodes", vnodes.len());
+            self.send_direct_message(
+                Message::SyncVNodeWithSuccessor(SyncVNodeWithSuccessor { data: vnodes }),
+                successor,
+            )
+            .await?;
+        }
+        for (address, transport) in self.swarm.get_transports() {
+            if let Err(e) = self
+                .send_direct_message(Message::LeaveDHT(LeaveDHT { id }), address.into())
+                .await
+            {
+                tracing::warn!(peer = ?address, "failed to notify leaving: {}", e);
+            }
+            if let Err(e) = transport.close().await {
+                tracing::warn!(peer = ?address, "failed to close transport: {}", e);
+            }
+            self.disconnect(address).await;
+        }
+        self.swarm.set_drain_state(DrainState::Drained);
+        Ok(())
+    }
+
     pub async fn connect(&self, address: &Address) -> Result<Arc<Transport>> {
         if let Some(t) = self.swarm.get_transport(address) {
             return Ok(t);
         }
+        self.connect_via(address, None).await.map(|(t, _)| t)
+    }
+
+    /// Send connect request to `address` through a next hop other than `avoid`, returns the
+    /// pending transport and the next hop. Pending transport is dropped if it's not sent.
+    async fn connect_via(
+        &self,
+        address: &Address,
+        avoid: Option<Did>,
+    ) -> Result<(Arc<Transport>, Did)> {
+        let target_id = address.to_owned().into();
+        let next_hop = connect_next_hop(&*self.dht.lock().await, target_id, avoid)?;
+        let transport = self.connect_through(address, next_hop).await?;
+        Ok((transport, next_hop))
+    }
 
+    /// Send connect request to `address` through `next_hop`, returns the pending transport.
+    /// Pending transport is dropped if it's not sent.
+    async fn connect_through(&self, address: &Address, next_hop: Did) -> Result<Arc<Transport>> {
         let target_id = address.to_owned().into();
         let transport = self.swarm.new_transport().await?;
         let handshake_info = transport
@@ -112,45 +336,182 @@ impl MessageHandler {
             transport_uuid: transport.id.to_string(),
             handshake_info: handshake_info.to_string(),
         });
-
-        let next_hop = {
-            let dht = self.dht.lock().await;
-            match dht.find_successor(target_id)? {
-                PeerRingAction::Some(node) => Some(node),
-                PeerRingAction::RemoteAction(node, _) => Some(node),
-                _ => None,
-            }
+        tracing::debug!(peer = ?target_id, next_hop = ?next_hop, "send connect request");
+        if let Err(e) = self.send_message(connect_msg, next_hop, target_id).await {
+            self.drop_pending(&transport).await;
+            return Err(e);
         }
-        .ok_or(Error::NoNextHop)?;
-        log::debug!("next_hop: {:?}", next_hop);
-        self.send_message(connect_msg, next_hop, target_id).await?;
         Ok(transport)
     }
 
-    async fn invoke_callback(&self, payload: &MessagePayload<Message>) -> Result<()> {
-        let mut callback = self.callback.lock().await;
-        if let Some(ref mut cb) = *callback {
-            let data = payload.data.clone();
-            match data {
-                Message::CustomMessage(msg) => cb.custom_message(self, payload, &msg).await,
-                _ => cb.builtin_message(self, payload).await,
+    /// Close a pending transport and forget it.
+    async fn drop_pending(&self, transport: &Arc<Transport>) {
+        if let Err(e) = self.swarm.pop_pending_transport(transport.id) {
+            tracing::debug!("failed to pop pending transport: {}", e);
+        }
+        if let Err(e) = transport.close().await {
+            tracing::debug!("failed to close pending transport: {}", e);
+        }
+    }
+
+    /// Connect `address`, and wait until its transport is registered and connected.
+    /// If remote doesn't answer in half of `timeout_ms`, the pending transport is dropped, and
+    /// it's retried once through another next hop in the rest, then [Error::ConnectTimeout] is
+    /// returned.
+    pub async fn connect_with_timeout(
+        &self,
+        address: &Address,
+        timeout_ms: u128,
+    ) -> Result<Arc<Transport>> {
+        let deadline = utils::get_epoch_ms() + timeout_ms;
+        let mut avoid = None;
+        for attempt in 0..2u128 {
+            let (transport, next_hop) = match self.swarm.get_transport(address) {
+                Some(t) => (t, address.to_owned().into()),
+                None => self.connect_via(address, avoid).await?,
             };
+            // first attempt waits half of the time left, and the retry waits the rest
+            let now = utils::get_epoch_ms();
+            let until = now + deadline.saturating_sub(now) / (2 - attempt);
+            while utils::get_epoch_ms() < until {
+                // remote may connect at the same time, any registered transport is fine
+                if let Some(t) = self.swarm.get_transport(address) {
+                    if t.is_connected().await {
+                        return Ok(t);
+                    }
+                }
+                timer::sleep(Duration::from_millis(50)).await;
+            }
+            tracing::warn!(peer = ?address, next_hop = ?next_hop, "connect timeout");
+            match self.swarm.get_transport(address) {
+                Some(t) if Arc::ptr_eq(&t, &transport) => {
+                    self.swarm.remove_transport(address);
+                    transport.close().await.ok();
+                }
+                _ => self.drop_pending(&transport).await,
+            }
+            avoid = Some(next_hop);
         }
-        Ok(())
+        Err(Error::ConnectTimeout(format!("{:?}", address)))
     }
 
     pub fn decrypt_msg(&self, msg: &MaybeEncrypted<CustomMessage>) -> Result<CustomMessage> {
-        let key = self.swarm.session_manager().session_key()?;
-        let (decrypt_msg, _) = msg.to_owned().decrypt(&key)?;
+        let (decrypt_msg, _) = self.open_msg(msg)?;
         Ok(decrypt_msg)
     }
 
+    /// Decrypt `msg` by published encryption key, or by key of session, which peers in direct
+    /// contact encrypt to. Returns whether it was encrypted.
+    pub(crate) fn open_msg(
+        &self,
+        msg: &MaybeEncrypted<CustomMessage>,
+    ) -> Result<(CustomMessage, bool)> {
+        let session_key = self.swarm.session_manager().session_key()?;
+        let encryption_key = self.swarm.encryption_key()?;
+        msg.to_owned()
+            .decrypt(&encryption_key)
+            .or_else(|_| msg.to_owned().decrypt(&session_key))
+    }
+
+    /// Message to a group sealed by a key in keyring is recorded as plain, others as they are.
+    #[cfg(not(feature = "wasm"))]
+    fn open_group_message(&self, msg: CustomMessage) -> CustomMessage {
+        let group_msg = match GroupMessage::from_bytes(&msg.0) {
+            Ok(m) if m.epoch.is_some() => m,
+            _ => return msg,
+        };
+        let opened = self
+            .swarm
+            .group_keys()
+            .open_message(&group_msg)
+            .and_then(|data| {
+                GroupMessage {
+                    data,
+                    epoch: None,
+                    ..group_msg
+                }
+                .to_bytes()
+            });
+        match opened {
+            Ok(data) => CustomMessage(data),
+            Err(e) => {
+                tracing::debug!("keep group message sealed: {}", e);
+                msg
+            }
+        }
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    fn record_history(
+        &self,
+        payload: &MessagePayload<Message>,
+        msg: &MaybeEncrypted<CustomMessage>,
+    ) {
+        let history = match &self.history {
+            Some(h) if payload.relay.destination == Did::from(self.swarm.address()) => h,
+            _ => return,
+        };
+        let result = self
+            .decrypt_msg(msg)
+            .map(|msg| self.open_group_message(msg))
+            .and_then(|msg| history.record(payload, &msg));
+        if let Err(e) = result {
+            tracing::warn!(tx_id = ?payload.tx_id, "failed to record message history: {}", e);
+        }
+    }
+
+    /// Route a report passing by around its previous hop, if it's gone, through a connected
+    /// node earlier on path, or by DHT toward its destination, see [crate::message::MessageRelay::detour].
+    /// Returns false if the report isn't detoured, then it's handled as usual.
+    async fn detour_report(&self, payload: &MessagePayload<Message>) -> Result<bool> {
+        if payload.relay.method != RelayMethod::REPORT {
+            return Ok(false);
+        }
+        let id: Did = self.swarm.address().into();
+        let mut relay = payload.relay.clone();
+        if relay.relay(id, None).is_err() {
+            return Ok(false);
+        }
+        let prev = match relay.next_hop {
+            Some(prev) if self.swarm.get_transport(&prev.into()).is_none() => prev,
+            _ => return Ok(false),
+        };
+        let destination = relay.destination;
+        // nodes of path closer to destination than previous hop, closest first
+        let earlier = relay
+            .path
+            .iter()
+            .take_while(|n| **n != prev)
+            .find(|n| self.swarm.get_transport(&(**n).into()).is_some())
+            .copied();
+        let next = match earlier {
+            Some(n) => n,
+            None => match self.dht.lock().await.find_route(destination)? {
+                PeerRingAction::Some(n) => n,
+                PeerRingAction::RemoteAction(n, _) => n,
+                _ => return Err(Error::MessageHandlerMissNextNode),
+            },
+        };
+        if next == prev || next == id {
+            return Err(Error::MessageHandlerMissNextNode);
+        }
+        tracing::debug!(tx_id = ?payload.tx_id, gone = ?prev, next_hop = ?next, "detour report");
+        relay.detour(id, next)?;
+        self.transpond_payload(payload, relay).await?;
+        Ok(true)
+    }
+
     #[cfg_attr(feature = "wasm", async_recursion(?Send))]
     #[cfg_attr(not(feature = "wasm"), async_recursion)]
     pub async fn handle_payload(&self, payload: &MessagePayload<Message>) -> Result<()> {
+        tracing::trace!(tx_id = ?payload.tx_id, peer = ?payload.addr, "handle payload");
+        if self.detour_report(payload).await? {
+            return Ok(());
+        }
         match &payload.data {
             Message::JoinDHT(ref msg) => self.handle(payload, msg).await,
             Message::LeaveDHT(ref msg) => self.handle(payload, msg).await,
+            Message::ReconnectPeer(ref msg) => self.handle(payload, msg).await,
             Message::ConnectNodeSend(ref msg) => self.handle(payload, msg).await,
             Message::ConnectNodeReport(ref msg) => self.handle(payload, msg).await,
             Message::AlreadyConnected(ref msg) => self.handle(payload, msg).await,
@@ -161,6 +522,25 @@ impl MessageHandler {
             Message::SearchVNode(ref msg) => self.handle(payload, msg).await,
             Message::FoundVNode(ref msg) => self.handle(payload, msg).await,
             Message::StoreVNode(ref msg) => self.handle(payload, msg).await,
+            Message::StoreVNodeReport(ref msg) => self.handle(payload, msg).await,
+            Message::StoreVNodeDenied(ref msg) => self.handle(payload, msg).await,
+            Message::InboxAck(ref msg) => self.handle(payload, msg).await,
+            Message::ChallengeVNode(ref msg) => self.handle(payload, msg).await,
+            Message::ChallengeVNodeReport(ref msg) => self.handle(payload, msg).await,
+            Message::SyncVNodeWithSuccessor(ref msg) => self.handle(payload, msg).await,
+            Message::StreamFrame(ref msg) => self.handle(payload, msg).await,
+            Message::RelayedData(ref msg) => self.handle(payload, msg).await,
+            Message::RelayedDataAck(ref msg) => self.handle(payload, msg).await,
+            Message::QueryTopology(ref msg) => self.handle(payload, msg).await,
+            Message::TopologyReport(ref msg) => self.handle(payload, msg).await,
+            Message::PeerSampleSend(ref msg) => self.handle(payload, msg).await,
+            Message::PeerSampleReport(ref msg) => self.handle(payload, msg).await,
+            Message::PeerExchange(ref msg) => self.handle(payload, msg).await,
+            Message::RotateIdentity(ref msg) => self.handle(payload, msg).await,
+            Message::DeliveryReceipt(ref msg) => self.handle(payload, msg).await,
+            Message::ServerBusy(ref msg) => self.handle(payload, msg).await,
+            // nothing to do, receiving it keeps connection alive
+            Message::Ping(_) => Ok(()),
             Message::MultiCall(ref msg) => {
                 for message in msg.messages.iter().cloned() {
                     let payload = MessagePayload::new(
@@ -168,33 +548,53 @@ impl MessageHandler {
                         self.swarm.session_manager(),
                         OriginVerificationGen::Stick(payload.origin_verification.clone()),
                         payload.relay.clone(),
-                    )?;
+                    )?
+                    .with_tx_id(payload.tx_id.clone());
                     self.handle_payload(&payload).await.unwrap_or(());
                 }
                 Ok(())
             }
-            Message::CustomMessage(_) => Ok(()),
+            #[cfg(not(feature = "wasm"))]
+            Message::CustomMessage(ref msg) => {
+                self.record_history(payload, msg);
+                self.echo(payload, msg).await;
+                Ok(())
+            }
+            #[cfg(feature = "wasm")]
+            Message::CustomMessage(ref msg) => {
+                self.echo(payload, msg).await;
+                Ok(())
+            }
             x => Err(Error::MessageHandlerUnsupportMessageType(format!(
                 "{:?}",
                 x
             ))),
         }?;
-        if let Err(e) = self.invoke_callback(payload).await {
-            log::warn!("invoke callback error: {}", e);
-        }
+        self.invoke_callbacks(payload).await;
 
         Ok(())
     }
 
     /// This method is required because web-sys components is not `Send`
     /// which means a listening loop cannot running concurrency.
+    /// Handle a received `payload`, and emit changes of ring it makes as [DhtEvent]s, see
+    /// [crate::dht::events]. Its receipt is returned if sender asked, see [receipt].
+    pub(crate) async fn handle_observed(&self, payload: &MessagePayload<Message>) -> Result<()> {
+        let result = self.handle_received(payload).await;
+        if self.swarm.has_listeners() {
+            self.swarm.observe_ring(&*self.dht.lock().await);
+        }
+        result?;
+        self.return_receipt(payload).await
+    }
+
     pub async fn listen_once(&self) -> Option<MessagePayload<Message>> {
         if let Some(payload) = self.swarm.poll_message().await {
-            if !payload.verify() {
-                log::error!("Cannot verify msg or it's expired: {:?}", payload);
+            if !self.swarm.verify_expiry(&payload) {
+                tracing::error!(tx_id = ?payload.tx_id, peer = ?payload.addr, "Cannot verify msg or it's expired: {:?}", payload);
             }
-            if let Err(e) = self.handle_payload(&payload).await {
-                log::error!("Error in handle_message: {}", e);
+            if let Err(e) = self.handle_observed(&payload).await {
+                tracing::error!(tx_id = ?payload.tx_id, peer = ?payload.addr, "Error in handle_message: {}", e);
             }
             Some(payload)
         } else {
@@ -236,12 +636,12 @@ mod listener {
             let payloads = self.swarm.iter_messages();
             pin_mut!(payloads);
             while let Some(payload) = payloads.next().await {
-                if !payload.verify() {
-                    log::error!("Cannot verify msg or it's expired: {:?}", payload);
+                if !self.swarm.verify_expiry(&payload) {
+                    tracing::error!(tx_id = ?payload.tx_id, peer = ?payload.addr, "Cannot verify msg or it's expired: {:?}", payload);
                     continue;
                 }
-                if let Err(e) = self.handle_payload(&payload).await {
-                    log::error!("Error in handle_message: {}", e);
+                if let Err(e) = self.handle_observed(&payload).await {
+                    tracing::error!(tx_id = ?payload.tx_id, peer = ?payload.addr, "Error in handle_message: {}", e);
                     continue;
                 }
             }
@@ -463,4 +863,68 @@ pub mod test {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_drain() -> Result<()> {
+        let key1 = SecretKey::random();
+        let key2 = SecretKey::random();
+        let (handler1, handler2) = create_connected_pair(key1, key2).await?;
+        let swarm1 = Arc::clone(&handler1.swarm);
+
+        handler1.drain().await?;
+        assert_eq!(swarm1.drain_state(), DrainState::Drained);
+        assert_eq!(swarm1.get_transport_numbers(), 0);
+        assert!(swarm1.new_transport().await.is_err());
+        assert!(handler1.dht.lock().await.successor.is_none());
+        assert_eq!(handler2.swarm.drain_state(), DrainState::Serving);
+        Ok(())
+    }
+
+    #[test]
+    fn test_connect_next_hop() {
+        let key = SecretKey::random();
+        let mut dht = PeerRing::new(key.address().into());
+        let target: Did = SecretKey::random().address().into();
+        assert!(matches!(
+            connect_next_hop(&dht, target, None),
+            Err(Error::NoNextHop)
+        ));
+        let hops: Vec<Did> = (0..2)
+            .map(|_| SecretKey::random().address().into())
+            .collect();
+        for hop in &hops {
+            dht.join(*hop);
+        }
+        let first = connect_next_hop(&dht, target, None).unwrap();
+        let second = connect_next_hop(&dht, target, Some(first)).unwrap();
+        assert!(hops.contains(&first) && hops.contains(&second));
+        assert_ne!(first, second);
+    }
+
+    #[tokio::test]
+    async fn test_connect_timeout_retry() -> Result<()> {
+        let harness = crate::test_utils::HandlerHarness::new()?;
+        let hops: Vec<Did> = (0..2)
+            .map(|_| SecretKey::random().address().into())
+            .collect();
+        for hop in &hops {
+            harness.dht().lock().await.join(*hop);
+        }
+        let target: Address = SecretKey::random().address();
+        let r = harness.handler().connect_with_timeout(&target, 400).await;
+        assert!(matches!(r, Err(Error::ConnectTimeout(_))));
+
+        // requests went through both hops, and no transport is left pending
+        let sent: Vec<Did> = harness
+            .take_sent()?
+            .into_iter()
+            .filter(|(_, p)| matches!(p.data, Message::ConnectNodeSend(_)))
+            .map(|(did, _)| did)
+            .collect();
+        assert_eq!(sent.len(), 2);
+        assert_ne!(sent[0], sent[1]);
+        assert!(harness.swarm().pending_transports().await?.is_empty());
+        assert!(harness.swarm().get_transport(&target).is_none());
+        Ok(())
+    }
 }
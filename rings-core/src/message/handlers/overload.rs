@@ -0,0 +1,99 @@
+#![warn(missing_docs)]
+//! Shedding of application messages under overload, see [crate::overload].
+//!
+//! Received payloads go through [MessageHandler::handle_received], which drops application
+//! messages while [OverloadGuard] reports overload, and answers their origin [ServerBusy] at
+//! most once a window. Everything else is handled as usual, so DHT maintenance keeps up under
+//! flood.
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::dht::Did;
+use crate::err::Result;
+use crate::message::types::Message;
+use crate::message::types::ServerBusy;
+use crate::message::HandleMsg;
+use crate::message::MessageHandler;
+use crate::message::MessagePayload;
+use crate::message::PayloadSender;
+use crate::message::RelayMethod;
+use crate::overload::OverloadGuard;
+use crate::overload::Timed;
+use crate::overload::BUDGET_WINDOW_MS;
+use crate::swarm::TransportManager;
+
+impl MessageHandler {
+    /// Shed application messages when more than `max_queue` payloads wait, or handlers take
+    /// more than `cpu_budget` percent of time. Nothing is shed if both are 0, which is the
+    /// default.
+    pub fn with_overload_guard(mut self, max_queue: usize, cpu_budget: u8) -> Self {
+        self.overload = (max_queue > 0 || cpu_budget > 0)
+            .then(|| Arc::new(OverloadGuard::new(max_queue, cpu_budget)));
+        self
+    }
+
+    /// Guard deciding what is shed, None if shedding is off.
+    pub fn overload_guard(&self) -> Option<Arc<OverloadGuard>> {
+        self.overload.clone()
+    }
+
+    /// Handle a payload received from transport, unless it's shed. Time handlers run is
+    /// counted against their budget, see [Timed].
+    pub(crate) async fn handle_received(&self, payload: &MessagePayload<Message>) -> Result<()> {
+        let guard = match &self.overload {
+            Some(g) => g,
+            None => return self.handle_payload(payload).await,
+        };
+        // reports are answers to this node, shedding them only wastes what it asked for
+        if payload.relay.method == RelayMethod::SEND
+            && guard.should_shed(&payload.data, self.swarm.backlog())
+        {
+            return self.reply_busy(guard, payload).await;
+        }
+        let (result, busy_us) = Timed::new(self.handle_payload(payload)).await;
+        guard.record(busy_us);
+        result
+    }
+
+    async fn reply_busy(
+        &self,
+        guard: &OverloadGuard,
+        payload: &MessagePayload<Message>,
+    ) -> Result<()> {
+        tracing::debug!(tx_id = ?payload.tx_id, peer = ?payload.addr, "shed message under overload");
+        let origin = Did::from(payload.origin_verification.session.auth.authorizer);
+        // already told in this window
+        if !guard.should_reply_busy(origin) {
+            return Ok(());
+        }
+        let mut relay = payload.relay.clone();
+        relay.relay(self.swarm.address().into(), None)?;
+        let busy = ServerBusy {
+            tx_id: payload.tx_id.clone(),
+            retry_after_ms: BUDGET_WINDOW_MS as u64,
+        };
+        self.send_report_message(Message::ServerBusy(busy), relay)
+            .await
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<ServerBusy> for MessageHandler {
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &ServerBusy) -> Result<()> {
+        let local: Did = self.swarm.address().into();
+        let mut relay = ctx.relay.clone();
+        relay.relay(local, None)?;
+        if relay.next_hop.is_some() {
+            return self.transpond_payload(ctx, relay).await;
+        }
+        tracing::warn!(
+            tx_id = ?msg.tx_id,
+            peer = ?Did::from(ctx.origin_verification.session.auth.authorizer),
+            retry_after_ms = msg.retry_after_ms,
+            "message shed by overloaded peer"
+        );
+        Ok(())
+    }
+}
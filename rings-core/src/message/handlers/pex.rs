@@ -0,0 +1,51 @@
+#![warn(missing_docs)]
+//! Exchange of connected peers, see [crate::pex].
+//!
+//! [PeerExchange] is only accepted from the connected peer which signed it, since peers in it
+//! are dialed through that peer. It isn't answered, the receiver shares its own peers when its
+//! stabilization picks the sender.
+use async_trait::async_trait;
+use futures::future::join_all;
+
+use crate::dht::Did;
+use crate::err::Result;
+use crate::message::types::Message;
+use crate::message::types::PeerExchange;
+use crate::message::HandleMsg;
+use crate::message::MessageHandler;
+use crate::message::MessagePayload;
+use crate::pex;
+use crate::swarm::TransportManager;
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<PeerExchange> for MessageHandler {
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &PeerExchange) -> Result<()> {
+        let sender: Did = ctx.addr.into();
+        if Did::from(ctx.origin_verification.session.auth.authorizer) != sender
+            || self.swarm.get_transport(&ctx.addr).is_none()
+        {
+            tracing::debug!(peer = ?sender, "ignore peer exchange not from a connected peer");
+            return Ok(());
+        }
+        let slots = self
+            .swarm
+            .max_connections()
+            .saturating_sub(self.swarm.get_transport_numbers());
+        let local: Did = self.swarm.address().into();
+        let dials = pex::choose_dials(
+            local,
+            &msg.peers,
+            |did| self.swarm.get_transport(&did.into()).is_some(),
+            slots,
+        );
+        join_all(dials.into_iter().map(|did| async move {
+            tracing::debug!(peer = ?did, via = ?sender, "connect peer learned by peer exchange");
+            if let Err(e) = self.connect_through(&did.into(), sender).await {
+                tracing::debug!(peer = ?did, "failed to connect peer of exchange: {}", e);
+            }
+        }))
+        .await;
+        Ok(())
+    }
+}
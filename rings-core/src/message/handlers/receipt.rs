@@ -0,0 +1,281 @@
+#![warn(missing_docs)]
+//! Delivery receipts with latency of each hop, for debugging slow routes.
+//!
+//! A message sent by [MessageHandler::send_with_receipt] is stamped by every node relaying
+//! it, see [crate::message::MessageRelay::stamps]. Once it's handled, destination returns a
+//! [DeliveryReceipt] of the stamps, which is handed to callbacks of sender like other
+//! messages, and kept for [RECEIPT_TIMEOUT_MS] to be looked up by `tx_id`, see
+//! [MessageHandler::delivery_receipt]. Latency of last hop includes handling by destination,
+//! and latencies are off by clock skews of nodes.
+//!
+//! A receipt refers to signed id of the message, see [crate::replay::payload_id], and is
+//! taken once, only if it's signed by destination of the message and arrives within
+//! [RECEIPT_TIMEOUT_MS]. Others are dropped before callbacks.
+use async_trait::async_trait;
+use dashmap::DashMap;
+
+use crate::dht::Did;
+use crate::ecc::HashStr;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::types::DeliveryReceipt;
+use crate::message::types::HopLatency;
+use crate::message::types::Message;
+use crate::message::HandleMsg;
+use crate::message::MessageHandler;
+use crate::message::MessagePayload;
+use crate::message::MessageRelay;
+use crate::message::PayloadSender;
+use crate::message::RelayMethod;
+use crate::replay;
+use crate::swarm::Swarm;
+use crate::utils;
+
+/// Receipts arriving later than this after their messages are dropped, in milliseconds.
+pub const RECEIPT_TIMEOUT_MS: u128 = 60 * 1000;
+/// Messages waiting for receipts at most.
+pub const MAX_PENDING_RECEIPTS: usize = 4096;
+
+/// Latency of each hop of `relay`, by its stamps. Every stamp names its node, so hops are
+/// attributed right whatever `path` says.
+fn hop_latencies(relay: &MessageRelay) -> Vec<HopLatency> {
+    let stamps = match &relay.stamps {
+        Some(s) => s,
+        None => return vec![],
+    };
+    stamps
+        .windows(2)
+        .map(|w| HopLatency {
+            node: w[1].node,
+            latency_ms: w[1].ts_ms.saturating_sub(w[0].ts_ms),
+        })
+        .collect()
+}
+
+/// Messages waiting for receipts, and receipts delivered, see module doc.
+#[derive(Default)]
+pub struct Receipts {
+    /// Destination, `tx_id` and sent time of messages waiting, by their signed ids.
+    pending: DashMap<HashStr, (Did, HashStr, u128)>,
+    /// Receipts taken, with time they arrived, by `tx_id` of their messages.
+    delivered: DashMap<HashStr, (DeliveryReceipt, u128)>,
+}
+
+impl Receipts {
+    /// No message is waiting.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count of messages waiting for receipts.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Receipt of message `tx_id`, if it arrived in last [RECEIPT_TIMEOUT_MS].
+    pub fn delivered(&self, tx_id: &HashStr) -> Option<DeliveryReceipt> {
+        let now = utils::get_epoch_ms();
+        self.delivered
+            .get(tx_id)
+            .filter(|e| now.saturating_sub(e.1) < RECEIPT_TIMEOUT_MS)
+            .map(|e| e.0.clone())
+    }
+
+    /// Ask destination of `payload` for a receipt, and send it by `swarm`. Returns `tx_id` of
+    /// the message.
+    pub(crate) async fn send(
+        &self,
+        swarm: &Swarm,
+        payload: MessagePayload<Message>,
+    ) -> Result<HashStr> {
+        let payload = payload.with_receipt();
+        let tx_id = payload.tx_id.clone();
+        let id = replay::payload_id(&payload.data, &payload.origin_verification)?;
+        self.expect(id.clone(), payload.relay.destination, tx_id.clone())?;
+        if let Err(e) = swarm.send_payload(payload).await {
+            self.pending.remove(&id);
+            return Err(e);
+        }
+        Ok(tx_id)
+    }
+
+    /// Wait for receipt of message `id` from `destination`, forgets ones timed out.
+    fn expect(&self, id: HashStr, destination: Did, tx_id: HashStr) -> Result<()> {
+        let now = utils::get_epoch_ms();
+        if self.pending.len() >= MAX_PENDING_RECEIPTS {
+            self.pending
+                .retain(|_, (_, _, ts)| now.saturating_sub(*ts) < RECEIPT_TIMEOUT_MS);
+        }
+        if self.pending.len() >= MAX_PENDING_RECEIPTS {
+            return Err(Error::TooManyPendingReceipts);
+        }
+        self.pending.insert(id, (destination, tx_id, now));
+        Ok(())
+    }
+
+    /// Take `receipt` signed by `signer`, false if it's not expected. It's kept by `tx_id` of
+    /// its message as sent, whatever `tx_id` the receipt names.
+    fn take(&self, receipt: &DeliveryReceipt, signer: Did) -> bool {
+        let now = utils::get_epoch_ms();
+        let tx_id = match self
+            .pending
+            .remove_if(&receipt.id, |_, (destination, _, ts)| {
+                *destination == signer && now.saturating_sub(*ts) < RECEIPT_TIMEOUT_MS
+            }) {
+            Some((_, (_, tx_id, _))) => tx_id,
+            None => return false,
+        };
+        if self.delivered.len() >= MAX_PENDING_RECEIPTS {
+            self.delivered
+                .retain(|_, (_, ts)| now.saturating_sub(*ts) < RECEIPT_TIMEOUT_MS);
+        }
+        if self.delivered.len() < MAX_PENDING_RECEIPTS {
+            let receipt = DeliveryReceipt {
+                tx_id: tx_id.clone(),
+                ..receipt.clone()
+            };
+            self.delivered.insert(tx_id, (receipt, now));
+        }
+        true
+    }
+}
+
+impl MessageHandler {
+    /// Send `msg` to `destination` like [MessageHandler::send_app_message], and ask for a
+    /// [DeliveryReceipt]. Returns `tx_id` of the message, which the receipt refers to.
+    pub async fn send_with_receipt(&self, msg: Message, destination: Did) -> Result<HashStr> {
+        self.send_app(msg, destination, true).await
+    }
+
+    /// Receipt of message `tx_id` sent by [MessageHandler::send_with_receipt], if it arrived in
+    /// last [RECEIPT_TIMEOUT_MS].
+    pub fn delivery_receipt(&self, tx_id: &HashStr) -> Option<DeliveryReceipt> {
+        self.receipts.delivered(tx_id)
+    }
+
+    /// Return receipt of `payload` handled, if it's to this node and its sender asked.
+    pub(crate) async fn return_receipt(&self, payload: &MessagePayload<Message>) -> Result<()> {
+        let id: Did = self.swarm.address().into();
+        let mut relay = payload.relay.clone();
+        if relay.method != RelayMethod::SEND || relay.destination != id || relay.stamps.is_none() {
+            return Ok(());
+        }
+        relay.relay(id, None)?;
+        let receipt = DeliveryReceipt {
+            tx_id: payload.tx_id.clone(),
+            id: replay::payload_id(&payload.data, &payload.origin_verification)?,
+            hops: hop_latencies(&relay),
+        };
+        self.send_report_message(Message::DeliveryReceipt(receipt), relay)
+            .await
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<DeliveryReceipt> for MessageHandler {
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &DeliveryReceipt) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+        relay.relay(self.swarm.address().into(), None)?;
+        if relay.next_hop.is_some() {
+            return self.transpond_payload(ctx, relay).await;
+        }
+        let signer = Did::from(ctx.origin_verification.session.auth.authorizer);
+        if !self.receipts.take(msg, signer) {
+            return Err(Error::InvalidDeliveryReceipt(msg.tx_id.inner()));
+        }
+        tracing::debug!(
+            tx_id = ?msg.tx_id,
+            hops = ?msg.hops,
+            "message delivered"
+        );
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ecc::SecretKey;
+    use crate::message::handlers::test::create_connected_pair;
+    use crate::message::HopStamp;
+
+    #[test]
+    fn test_hop_latencies() {
+        let nodes: Vec<Did> = (0..4)
+            .map(|_| SecretKey::random().address().into())
+            .collect();
+        let mut relay =
+            MessageRelay::new(RelayMethod::SEND, nodes[..2].to_vec(), None, None, nodes[2]);
+        assert!(hop_latencies(&relay).is_empty());
+        let stamp = |node: Did, ts_ms| HopStamp { node, ts_ms };
+        relay.stamps = Some(vec![stamp(nodes[0], 100), stamp(nodes[1], 130)]);
+        relay.path.push(nodes[2]);
+        relay.stamps.as_mut().unwrap().push(stamp(nodes[2], 180));
+        // a path changed on the way doesn't shift hops
+        relay.path.insert(1, nodes[3]);
+        assert_eq!(hop_latencies(&relay), vec![
+            HopLatency {
+                node: nodes[1],
+                latency_ms: 30
+            },
+            HopLatency {
+                node: nodes[2],
+                latency_ms: 50
+            },
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_delivery_receipt() -> Result<()> {
+        let key1 = SecretKey::random();
+        let key2 = SecretKey::random();
+        let did2: Did = key2.address().into();
+        let (node1, node2) = create_connected_pair(key1, key2).await?;
+
+        let tx_id = node1
+            .send_with_receipt(Message::custom(b"hello", &None)?, did2)
+            .await?;
+        assert!(node2.listen_once().await.is_some());
+        let payload = node1.listen_once().await.unwrap();
+        match payload.data {
+            Message::DeliveryReceipt(receipt) => {
+                assert_eq!(receipt.tx_id, tx_id);
+                assert_eq!(receipt.hops.len(), 1);
+                assert_eq!(receipt.hops[0].node, did2);
+            }
+            x => panic!("unexpected message {:?}", x),
+        }
+        assert_eq!(node1.receipts.pending(), 0);
+        assert_eq!(node1.delivery_receipt(&tx_id).unwrap().hops[0].node, did2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_receipt_bound_to_destination() -> Result<()> {
+        let key1 = SecretKey::random();
+        let key2 = SecretKey::random();
+        let did2: Did = key2.address().into();
+        let (node1, _node2) = create_connected_pair(key1, key2).await?;
+
+        let receipts = &node1.receipts;
+        let tx_id = HashStr::new("tx");
+        let receipt = |id: &str, tx_id: &str| DeliveryReceipt {
+            tx_id: HashStr::new(tx_id),
+            id: HashStr::new(id),
+            hops: vec![],
+        };
+        receipts.expect(HashStr::new("id"), did2, tx_id.clone())?;
+        // neither another signer nor another message takes the receipt
+        assert!(!receipts.take(&receipt("id", "tx"), SecretKey::random().address().into()));
+        assert!(!receipts.take(&receipt("other", "tx"), did2));
+        // it's kept by tx_id sent, not the one it names
+        assert!(receipts.take(&receipt("id", "forged"), did2));
+        assert_eq!(receipts.delivered(&tx_id).unwrap().tx_id, tx_id);
+        assert!(receipts.delivered(&HashStr::new("forged")).is_none());
+        // and it's taken only once
+        assert!(!receipts.take(&receipt("id", "tx"), did2));
+        Ok(())
+    }
+}
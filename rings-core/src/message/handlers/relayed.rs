@@ -0,0 +1,572 @@
+#![warn(missing_docs)]
+//! Application traffic relayed along DHT path, for peers which can't be connected by ICE.
+//!
+//! When ICE to a peer fails, the peer is marked unreachable in [RelayedLinks], and custom
+//! messages and stream frames to it are wrapped in [RelayedData], forwarded hop by hop along
+//! DHT path like `ConnectNodeSend`, until the peer is connected again. Destination reports
+//! [RelayedDataAck] back to origin, with signed id of the message, and an ack is taken only
+//! if it's signed by the destination, as a message is taken only if it's signed by the origin
+//! on its relay path. An origin has at most [RELAY_WINDOW] unacknowledged
+//! messages to each destination, and every node relays at most a budget of bytes per minute
+//! for each origin, so a busy pair can't exhaust nodes in between. Budget and policy of
+//! relaying are of [RelayAccounting](crate::accounting::RelayAccounting), charged to signer of
+//! the message.
+//!
+//! Messages to peers neither connected nor unreachable are routed instead: sent as they are
+//! with [RelayMethod::SEND] toward destination, and forwarded by every node to its next hop
+//! on DHT path, at most [RELAY_MAX_HOPS] hops and before signature of origin expires. They
+//! are charged to budget of origin like relayed ones.
+//!
+//! Nodes in between never decode bodies of either, swarm forwards them as received before
+//! decoding, only their headers are read.
+use std::collections::BTreeMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Weak;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use futures::lock::Mutex;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::accounting::RelayDecision;
+pub use crate::accounting::DEFAULT_RELAY_BUDGET;
+use crate::dht::Chord;
+use crate::dht::Did;
+use crate::dht::PeerRing;
+use crate::dht::PeerRingAction;
+use crate::ecc::HashStr;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::handlers::receipt::Receipts;
+use crate::message::types::Message;
+use crate::message::types::RelayedData;
+use crate::message::types::RelayedDataAck;
+use crate::message::HandleMsg;
+use crate::message::MessageHandler;
+use crate::message::MessagePayload;
+use crate::message::MessageRelay;
+use crate::message::OriginVerificationGen;
+use crate::message::PayloadSender;
+use crate::message::RawPayload;
+use crate::message::RelayMethod;
+use crate::replay;
+use crate::swarm::Swarm;
+use crate::swarm::TransportManager;
+use crate::timer;
+use crate::traffic;
+use crate::utils;
+
+/// Unacknowledged relayed messages to one destination at most.
+pub const RELAY_WINDOW: usize = 32;
+/// Relayed messages travelling more hops are dropped.
+pub const RELAY_MAX_HOPS: usize = 8;
+/// Unacknowledged messages are forgotten after this, so lost acks don't stall the window.
+const RELAY_ACK_TIMEOUT_MS: u128 = 10 * 1000;
+/// How long a sender waits for room in window, before [Error::RelayWindowFull].
+const RELAY_WAIT_MS: u128 = 5 * 1000;
+
+/// Unreachable peers, and relayed messages in flight.
+#[derive(Default)]
+pub struct RelayedLinks {
+    /// Peers failed ICE, with time they failed.
+    unreachable: DashMap<Did, u128>,
+    /// Sent time and signed id of unacknowledged messages, by destination and seq.
+    in_flight: DashMap<Did, BTreeMap<u64, (u128, Option<HashStr>)>>,
+    next_seq: AtomicU64,
+}
+
+impl RelayedLinks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// ICE to `peer` failed, relay messages to it from now on.
+    pub fn mark_unreachable(&self, peer: Did) {
+        tracing::info!(peer = ?peer, "peer unreachable, relay messages along DHT path");
+        self.unreachable.insert(peer, utils::get_epoch_ms());
+    }
+
+    /// `peer` is connected directly again.
+    pub fn mark_reachable(&self, peer: Did) {
+        self.unreachable.remove(&peer);
+        self.in_flight.remove(&peer);
+    }
+
+    /// Messages to `peer` are relayed.
+    pub fn is_unreachable(&self, peer: Did) -> bool {
+        self.unreachable.contains_key(&peer)
+    }
+
+    /// All peers which messages are relayed to.
+    pub fn unreachable(&self) -> Vec<Did> {
+        self.unreachable.iter().map(|e| *e.key()).collect()
+    }
+
+    /// Count of unacknowledged messages to `peer`.
+    pub fn in_flight(&self, peer: Did) -> usize {
+        self.in_flight.get(&peer).map_or(0, |m| m.len())
+    }
+
+    /// Take a seq for a message to `peer`, None if window is full.
+    fn try_acquire(&self, peer: Did) -> Option<u64> {
+        let now = utils::get_epoch_ms();
+        let mut sent = self.in_flight.entry(peer).or_default();
+        sent.retain(|_, (ts, _)| now.saturating_sub(*ts) < RELAY_ACK_TIMEOUT_MS);
+        if sent.len() >= RELAY_WINDOW {
+            return None;
+        }
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        sent.insert(seq, (now, None));
+        Some(seq)
+    }
+
+    /// Take a seq for a message to `peer`, waits for room in window.
+    async fn acquire(&self, peer: Did) -> Result<u64> {
+        let started = utils::get_epoch_ms();
+        while utils::get_epoch_ms().saturating_sub(started) < RELAY_WAIT_MS {
+            if let Some(seq) = self.try_acquire(peer) {
+                return Ok(seq);
+            }
+            timer::sleep(Duration::from_millis(50)).await;
+        }
+        self.try_acquire(peer)
+            .ok_or_else(|| Error::RelayWindowFull(format!("{:?}", *peer)))
+    }
+
+    /// Message `seq` to `peer` is sent with signed id `id`.
+    fn bind(&self, peer: Did, seq: u64, id: HashStr) {
+        if let Some(mut sent) = self.in_flight.get_mut(&peer) {
+            if let Some((_, bound)) = sent.get_mut(&seq) {
+                *bound = Some(id);
+            }
+        }
+    }
+
+    /// `peer` acknowledged message `seq` with signed id `id`, false if no such message was
+    /// sent to it.
+    fn ack(&self, peer: Did, seq: u64, id: &HashStr) -> bool {
+        let mut sent = match self.in_flight.get_mut(&peer) {
+            Some(s) => s,
+            None => return false,
+        };
+        if !matches!(sent.get(&seq), Some((_, Some(bound))) if bound == id) {
+            return false;
+        }
+        sent.remove(&seq);
+        true
+    }
+}
+
+/// Next hop to `destination` along DHT path, prefers a cached route, then relay capable
+/// nodes closer than DHT path, skips `path`.
+pub(crate) fn next_hop(dht: &PeerRing, destination: Did, path: &[Did]) -> Result<Did> {
+    if let Some(node) = dht.cached_route(destination).filter(|n| !path.contains(n)) {
+        return Ok(node);
+    }
+    match dht.find_successor(destination)? {
+        PeerRingAction::Some(node) | PeerRingAction::RemoteAction(node, _) => {
+            Some(dht.prefer_relay(destination, node, path))
+        }
+        _ => dht.closest_relay(destination, path),
+    }
+    .ok_or(Error::MessageHandlerMissNextNode)
+}
+
+/// Send `payload` of application message, asks for a [DeliveryReceipt] of it if `receipts`
+/// is given. Returns `tx_id` of the message.
+///
+/// [DeliveryReceipt]: crate::message::DeliveryReceipt
+pub(crate) async fn send_app_payload(
+    swarm: &Swarm,
+    payload: MessagePayload<Message>,
+    receipts: Option<&Receipts>,
+) -> Result<HashStr> {
+    if let Some(receipts) = receipts {
+        return receipts.send(swarm, payload).await;
+    }
+    let tx_id = payload.tx_id.clone();
+    swarm.send_payload(payload).await?;
+    Ok(tx_id)
+}
+
+/// Send application message to `destination`, directly if it's connected, routed along DHT
+/// path if it's not marked unreachable, otherwise relayed. See [send_app_payload] for
+/// `receipts`.
+pub(crate) async fn send_app_message(
+    swarm: &Swarm,
+    dht: &Mutex<PeerRing>,
+    msg: Message,
+    destination: Did,
+    receipts: Option<&Receipts>,
+) -> Result<HashStr> {
+    let links = swarm.relayed();
+    let session_manager = swarm.session_manager();
+    if swarm.get_transport(&destination.into()).is_some() {
+        let payload = MessagePayload::new_direct(msg, session_manager, destination)?;
+        return send_app_payload(swarm, payload, receipts).await;
+    }
+    if !links.is_unreachable(destination) {
+        let next = next_hop(&*dht.lock().await, destination, &[])?;
+        tracing::trace!(peer = ?destination, next_hop = ?next, "send routed message");
+        let payload = MessagePayload::new_send(msg, session_manager, next, destination)?;
+        return send_app_payload(swarm, payload, receipts).await;
+    }
+    let seq = links.acquire(destination).await?;
+    let next = next_hop(&*dht.lock().await, destination, &[])?;
+    tracing::trace!(peer = ?destination, next_hop = ?next, seq, "send relayed message");
+    let msg = Message::RelayedData(RelayedData {
+        seq,
+        message: Box::new(msg),
+    });
+    let payload = MessagePayload::new_send(msg, session_manager, next, destination)?;
+    links.bind(
+        destination,
+        seq,
+        replay::payload_id(&payload.data, &payload.origin_verification)?,
+    );
+    send_app_payload(swarm, payload, receipts).await
+}
+
+impl MessageHandler {
+    /// Send custom message or stream frame to `destination`, relayed along DHT path if ICE to
+    /// it failed, see [RelayedLinks]. With lazy dial on, a peer not connected is dialed first.
+    /// Messages to a rotated DID are sent to its new one.
+    pub async fn send_app_message(&self, msg: Message, destination: Did) -> Result<()> {
+        self.send_app(msg, destination, false).await.map(|_| ())
+    }
+
+    /// Send application message like [MessageHandler::send_app_message], asks for its
+    /// [receipt](super::receipt) if `receipt` is true. Returns `tx_id` of the message.
+    pub(crate) async fn send_app(
+        &self,
+        msg: Message,
+        destination: Did,
+        receipt: bool,
+    ) -> Result<HashStr> {
+        // peer may have rotated its identity, see [crate::rotation]
+        let destination = self.swarm.rotations().resolve(destination);
+        if let Some(dial) = &self.lazy_dial {
+            if self.swarm.get_transport(&destination.into()).is_none()
+                && !self.swarm.relayed().is_unreachable(destination)
+            {
+                return self.dial_and_send(dial, msg, destination, receipt).await;
+            }
+        }
+        let receipts = receipt.then(|| &*self.receipts);
+        send_app_message(&self.swarm, &self.dht, msg, destination, receipts).await
+    }
+}
+
+/// Forwards routed payloads passing by, without decoding their bodies. It's installed to
+/// swarm of a [MessageHandler], which calls it before decoding a payload received.
+#[derive(Clone)]
+pub(crate) struct RawRouter {
+    swarm: Weak<Swarm>,
+    dht: Arc<Mutex<PeerRing>>,
+}
+
+impl RawRouter {
+    pub(crate) fn new(swarm: &Arc<Swarm>, dht: Arc<Mutex<PeerRing>>) -> Self {
+        Self {
+            swarm: Arc::downgrade(swarm),
+            dht,
+        }
+    }
+
+    /// Forward application message or relayed data routed to others toward its destination,
+    /// returns false if it's to this node or of other kinds, then it's decoded and handled.
+    pub(crate) async fn forward(&self, payload: &RawPayload) -> Result<bool> {
+        let swarm = match self.swarm.upgrade() {
+            Some(swarm) => swarm,
+            None => return Ok(false),
+        };
+        let id: Did = swarm.address().into();
+        if payload.relay.method != RelayMethod::SEND
+            || payload.relay.destination == id
+            || !matches!(
+                traffic::message_type(payload.data.get().as_bytes()),
+                "CustomMessage" | "StreamFrame" | "RelayedData"
+            )
+        {
+            return Ok(false);
+        }
+        let origin = &payload.origin_verification;
+        if utils::get_epoch_ms() > origin.ts_ms + origin.ttl_ms as u128 {
+            return Err(Error::RoutedMessageExpired);
+        }
+        tracing::trace!(tx_id = ?payload.tx_id, "forward routed message");
+        let size = payload.data.get().len();
+        forward_toward(&swarm, &self.dht, payload, payload.relay.clone(), size).await?;
+        Ok(true)
+    }
+}
+
+/// Forward `ctx` of `size` bytes toward destination of `relay`, charged to its signer by
+/// [RelayAccounting](crate::accounting::RelayAccounting), never to a node of relay path
+/// which is not signed. Throttled ones are forwarded later, in background.
+async fn forward_toward<T>(
+    swarm: &Arc<Swarm>,
+    dht: &Arc<Mutex<PeerRing>>,
+    ctx: &MessagePayload<T>,
+    relay: MessageRelay,
+    size: usize,
+) -> Result<()>
+where
+    T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static + std::fmt::Debug,
+{
+    if relay.path.len() >= RELAY_MAX_HOPS {
+        return Err(Error::RelayHopsExceeded);
+    }
+    let origin = Did::from(ctx.origin_verification.session.auth.authorizer);
+    let accounting = swarm.relay_accounting();
+    match accounting.admit(origin, size) {
+        RelayDecision::Allow => forward_next(swarm, dht, ctx, relay).await,
+        RelayDecision::Throttle(ms) => {
+            tracing::debug!(origin = ?origin, ms, "delay relayed message");
+            let swarm = swarm.clone();
+            let dht = dht.clone();
+            let ctx = ctx.clone();
+            timer::spawn(async move {
+                timer::sleep(Duration::from_millis(ms)).await;
+                if let Err(e) = forward_next(&swarm, &dht, &ctx, relay).await {
+                    tracing::debug!(origin = ?origin, "failed to relay delayed message: {}", e);
+                }
+                accounting.dequeue(origin);
+            });
+            Ok(())
+        }
+        RelayDecision::Deny => {
+            tracing::warn!(origin = ?origin, "drop relayed message, denied");
+            Err(Error::RelayDenied(format!("{:?}", *origin)))
+        }
+    }
+}
+
+/// Forward `ctx` to next hop toward destination of `relay`, the destination itself if it's
+/// connected.
+async fn forward_next<T>(
+    swarm: &Swarm,
+    dht: &Mutex<PeerRing>,
+    ctx: &MessagePayload<T>,
+    mut relay: MessageRelay,
+) -> Result<()>
+where
+    T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static + std::fmt::Debug,
+{
+    let id: Did = swarm.address().into();
+    let next = if swarm.get_transport(&relay.destination).is_some() {
+        relay.destination
+    } else {
+        let mut path = relay.path.clone();
+        path.push(id);
+        next_hop(&*dht.lock().await, relay.destination, &path)?
+    };
+    tracing::trace!(tx_id = ?ctx.tx_id, next_hop = ?next, "forward message");
+    relay.relay(id, Some(next))?;
+    swarm.transpond_payload(ctx, relay).await
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<RelayedData> for MessageHandler {
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &RelayedData) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+        let id = self.dht.lock().await.id;
+        if relay.destination != id {
+            let size = serde_json::to_vec(&msg.message)
+                .map_err(Error::Serialize)?
+                .len();
+            return forward_toward(&self.swarm, &self.dht, ctx, relay, size).await;
+        }
+
+        if !matches!(
+            *msg.message,
+            Message::CustomMessage(_) | Message::StreamFrame(_)
+        ) {
+            return Err(Error::RelayedMessageNotAllowed);
+        }
+        // origin on relay path is not signed, it's taken only if it's the signer
+        if relay.origin() != Did::from(ctx.origin_verification.session.auth.authorizer) {
+            return Err(Error::RelayedOriginMismatch);
+        }
+        // handled as if it's sent by origin directly
+        let inner = MessagePayload::new(
+            (*msg.message).clone(),
+            self.swarm.session_manager(),
+            OriginVerificationGen::Stick(ctx.origin_verification.clone()),
+            ctx.relay.clone(),
+        )?
+        .with_tx_id(ctx.tx_id.clone());
+        self.handle_payload(&inner).await?;
+        relay.relay(id, None)?;
+        self.send_report_message(
+            Message::RelayedDataAck(RelayedDataAck {
+                seq: msg.seq,
+                id: replay::payload_id(&ctx.data, &ctx.origin_verification)?,
+                tx_id: Some(ctx.tx_id.clone()),
+            }),
+            relay,
+        )
+        .await
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<RelayedDataAck> for MessageHandler {
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &RelayedDataAck) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+        let id = self.dht.lock().await.id;
+        relay.relay(id, None)?;
+        if relay.next_hop.is_some() {
+            return self.transpond_payload(ctx, relay).await;
+        }
+        // only destination of the message can acknowledge it
+        let signer = Did::from(ctx.origin_verification.session.auth.authorizer);
+        if !self.swarm.relayed().ack(signer, msg.seq, &msg.id) {
+            return Err(Error::InvalidRelayedAck(msg.seq));
+        }
+        tracing::trace!(tx_id = ?msg.tx_id, seq = msg.seq, "relayed message acknowledged");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ecc::SecretKey;
+    use crate::session::SessionManager;
+
+    #[test]
+    fn test_window() {
+        let links = RelayedLinks::new();
+        let peer: Did = SecretKey::random().address().into();
+        assert!(!links.is_unreachable(peer));
+        links.mark_unreachable(peer);
+        assert!(links.is_unreachable(peer));
+
+        let seqs = (0..RELAY_WINDOW)
+            .map(|_| links.try_acquire(peer).unwrap())
+            .collect::<Vec<_>>();
+        assert!(links.try_acquire(peer).is_none());
+        let id = HashStr::new("id");
+        links.bind(peer, seqs[0], id.clone());
+        // acks of others, or of other messages, are not taken
+        let other: Did = SecretKey::random().address().into();
+        assert!(!links.ack(other, seqs[0], &id));
+        assert!(!links.ack(peer, seqs[0], &HashStr::new("forged")));
+        assert!(!links.ack(peer, seqs[1], &id));
+        assert!(links.ack(peer, seqs[0], &id));
+        assert_eq!(links.in_flight(peer), RELAY_WINDOW - 1);
+        assert!(links.try_acquire(peer).is_some());
+
+        links.mark_reachable(peer);
+        assert!(!links.is_unreachable(peer));
+        assert_eq!(links.in_flight(peer), 0);
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    #[tokio::test]
+    async fn test_route_app_message() -> Result<()> {
+        use crate::message::handlers::test::create_connected_pair;
+
+        let key1 = SecretKey::random();
+        let key2 = SecretKey::random();
+        let did1: Did = key1.address().into();
+        let (node1, node2) = create_connected_pair(key1, key2).await?;
+
+        // not connected peer is reached through successor, instead of failing
+        let far: Did = SecretKey::random().address().into();
+        node1
+            .send_app_message(Message::custom(b"hello", &None)?, far)
+            .await?;
+        // forwarded by node2 as received, never handed to its handler
+        assert!(node2.listen_once().await.is_none());
+        assert_eq!(node2.swarm.relay_accounting().usage(did1).messages, 1);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    #[tokio::test]
+    async fn test_route_through_intermediate() -> Result<()> {
+        use crate::message::handlers::connection::test::manually_establish_connection;
+        use crate::message::handlers::connection::test::prepare_node;
+
+        let key1 = SecretKey::random();
+        let key2 = SecretKey::random();
+        let key3 = SecretKey::random();
+        let (did1, _, swarm1, node1) = prepare_node(&key1);
+        let (did2, _, swarm2, node2) = prepare_node(&key2);
+        let (did3, _, swarm3, node3) = prepare_node(&key3);
+        // node1 - node2 - node3, node1 and node3 are not connected
+        manually_establish_connection(&swarm1, &swarm2).await?;
+        manually_establish_connection(&swarm2, &swarm3).await?;
+
+        swarm1
+            .send_message(Message::custom(b"hello", &None)?, did2, did3)
+            .await?;
+        let custom = |node: &MessageHandler| {
+            let node = node.clone();
+            async move {
+                loop {
+                    let payload = node.listen_once().await.unwrap();
+                    if matches!(payload.data, Message::CustomMessage(_)) {
+                        return payload;
+                    }
+                }
+            }
+        };
+        // node2 forwards it without decoding, charged to signer of the message
+        while swarm2.relay_accounting().usage(did1).messages == 0 {
+            node2.listen_once().await;
+        }
+
+        let delivered = custom(&node3).await;
+        assert_eq!(delivered.relay.destination, did3);
+        assert!(delivered.relay.path.contains(&did2));
+        assert_eq!(
+            Did::from(delivered.origin_verification.session.auth.authorizer),
+            did1
+        );
+        assert_eq!(swarm3.relay_accounting().usage(did1).messages, 0);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    #[tokio::test]
+    async fn test_relayed_origin_is_signer() -> Result<()> {
+        use crate::message::handlers::connection::test::prepare_node;
+
+        let key1 = SecretKey::random();
+        let key2 = SecretKey::random();
+        let (did2, _, _, node2) = prepare_node(&key2);
+        let session1 = SessionManager::new_with_seckey(&key1)?;
+        // signed by key1, claiming to be of another peer
+        let forged: Did = SecretKey::random().address().into();
+        let relayed = |origin: Did| {
+            let relay = MessageRelay::new(RelayMethod::SEND, vec![origin], None, None, did2);
+            MessagePayload::new(
+                Message::RelayedData(RelayedData {
+                    seq: 0,
+                    message: Box::new(Message::custom(b"hello", &None).unwrap()),
+                }),
+                &session1,
+                OriginVerificationGen::Origin,
+                relay,
+            )
+        };
+        assert!(matches!(
+            node2.handle_payload(&relayed(forged)?).await,
+            Err(Error::RelayedOriginMismatch)
+        ));
+        assert!(!matches!(
+            node2.handle_payload(&relayed(key1.address().into())?).await,
+            Err(Error::RelayedOriginMismatch)
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+#![warn(missing_docs)]
+//! Rotation of identity, see [crate::rotation].
+//!
+//! [RotateIdentity] is only accepted from the old DID of its record, so a node is only told
+//! of rotations by the peer rotating. Messages to the old DID are sent to the new one from then
+//! on, by [MessageHandler::send_app_message].
+use async_trait::async_trait;
+
+use crate::dht::DhtEvent;
+use crate::dht::Did;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::types::Message;
+use crate::message::types::RotateIdentity;
+use crate::message::HandleMsg;
+use crate::message::MessageHandler;
+use crate::message::MessagePayload;
+use crate::message::PayloadSender;
+use crate::message::TChordStorage;
+use crate::rotation::RotationRecord;
+use crate::swarm::TransportManager;
+
+impl MessageHandler {
+    /// Rotate identity of this node by `record`, signed by its key and the new one beforehand,
+    /// notify connected peers, and publish the record to DHT. The node should be restarted
+    /// with the new key then.
+    pub async fn rotate_identity(&self, record: &RotationRecord) -> Result<()> {
+        if !record.verify()
+            || record.data.old != Did::from(self.swarm.session_manager().authorizer()?)
+        {
+            return Err(Error::InvalidRotation);
+        }
+        let msg = Message::RotateIdentity(RotateIdentity {
+            record: record.clone(),
+        });
+        for peer in self.swarm.get_addresses() {
+            if let Err(e) = self.send_direct_message(msg.clone(), peer.into()).await {
+                tracing::debug!(peer = ?peer, "failed to notify rotation: {}", e);
+            }
+        }
+        self.store(record.to_vnode()?).await?;
+        tracing::info!(new = ?record.data.new, "identity rotated");
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<RotateIdentity> for MessageHandler {
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &RotateIdentity) -> Result<()> {
+        let rotation = msg.record.data;
+        if Did::from(ctx.origin_verification.session.auth.authorizer) != rotation.old {
+            tracing::debug!(old = ?rotation.old, "ignore rotation not from its old identity");
+            return Ok(());
+        }
+        if !self.swarm.rotations().insert(&msg.record) {
+            tracing::debug!(old = ?rotation.old, "ignore invalid or known rotation");
+            return Ok(());
+        }
+        tracing::info!(old = ?rotation.old, new = ?rotation.new, "peer rotated identity");
+        self.swarm.tags().migrate(rotation.old, rotation.new)?;
+        self.dht.lock().await.remove(rotation.old);
+        self.swarm.emit_dht_event(DhtEvent::NodeLeft(rotation.old));
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ecc::SecretKey;
+    use crate::message::handlers::test::create_connected_pair;
+
+    #[tokio::test]
+    async fn test_rotate_identity() -> Result<()> {
+        let key1 = SecretKey::random();
+        let key2 = SecretKey::random();
+        let did2: Did = key2.address().into();
+        let (node1, node2) = create_connected_pair(key1, key2).await?;
+        node1.swarm.tags().set(did2, "region", "eu")?;
+
+        let new = SecretKey::random();
+        let new_did: Did = new.address().into();
+        // only rotation of its own identity is published
+        let other = RotationRecord::new(&key1, &new)?;
+        assert!(node2.rotate_identity(&other).await.is_err());
+        let record = RotationRecord::new(&key2, &new)?;
+        node2.rotate_identity(&record).await?;
+        assert!(node1.listen_once().await.is_some());
+
+        assert_eq!(node1.swarm.rotations().resolve(did2), new_did);
+        assert!(node1.swarm.tags().matches(new_did, "region", "eu"));
+        assert!(!node1.dht.lock().await.successor.list().contains(&did2));
+        Ok(())
+    }
+}
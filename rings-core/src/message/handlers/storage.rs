@@ -1,21 +1,28 @@
 use async_trait::async_trait;
 
+use crate::dht::vnode::VNodeType;
 use crate::dht::vnode::VirtualNode;
+use crate::dht::Chord;
 use crate::dht::ChordStorage;
 use crate::dht::Did;
 use crate::dht::PeerRingAction;
 use crate::dht::PeerRingRemoteAction;
 use crate::err::Error;
 use crate::err::Result;
+use crate::message::handlers::inbox::TInbox;
+use crate::message::types::DeniedVNode;
 use crate::message::types::FoundVNode;
 use crate::message::types::Message;
 use crate::message::types::SearchVNode;
 use crate::message::types::StoreVNode;
+use crate::message::types::StoreVNodeDenied;
+use crate::message::types::StoreVNodeReport;
 use crate::message::types::SyncVNodeWithSuccessor;
 use crate::message::HandleMsg;
 use crate::message::MessageHandler;
 use crate::message::MessagePayload;
 use crate::message::PayloadSender;
+use crate::presence::PresenceRecord;
 
 /// TChordStorage should imply necessary method for DHT storage
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
@@ -58,12 +65,20 @@ impl TChordStorage for MessageHandler {
         }
     }
 
-    /// Store VirtualNode, TryInto<VirtualNode> is implementated for alot of types
+    /// Store VirtualNode, TryInto<VirtualNode> is implementated for alot of types.
+    /// Its placement is tracked, see [crate::placement].
     async fn store(&self, vnode: VirtualNode) -> Result<()> {
         let dht = self.dht.lock().await;
+        let id = vnode.did();
         match dht.store(vnode)? {
-            PeerRingAction::None => Ok(()),
+            PeerRingAction::None => {
+                self.swarm
+                    .placements()
+                    .stored_locally(dht.id, id, &dht.successor.list());
+                Ok(())
+            }
             PeerRingAction::RemoteAction(target, PeerRingRemoteAction::FindAndStore(vnode)) => {
+                self.swarm.placements().track(vnode.clone(), target);
                 self.send_direct_message(
                     Message::StoreVNode(StoreVNode { data: vec![vnode] }),
                     target,
@@ -118,37 +133,185 @@ impl HandleMsg<FoundVNode> for MessageHandler {
         if relay.next_hop.is_some() {
             self.transpond_payload(ctx, relay).await
         } else {
+            let (inbox, data) = self.take_own_inbox(msg.data.clone())?;
             // When query successor, store in local cache
-            for datum in msg.data.iter().cloned() {
+            for datum in data {
+                if datum.kind == VNodeType::Presence {
+                    if let Ok(record) = PresenceRecord::from_vnode(&datum) {
+                        self.swarm
+                            .presence()
+                            .update(record.presence.did, Some(record));
+                    }
+                }
                 dht.cache(datum);
             }
+            drop(dht);
+            for v in inbox {
+                self.deliver_inbox(v).await?;
+            }
             Ok(())
         }
     }
 }
 
+/// Outcome of [MessageHandler::admit_and_store].
+#[derive(Default)]
+struct Placed {
+    /// Ids of virtual nodes stored here.
+    stored: Vec<Did>,
+    /// Virtual nodes rejected by admission.
+    denied: Vec<DeniedVNode>,
+    /// Virtual nodes stored by others, with the next node to them.
+    forward: Vec<(Did, VirtualNode)>,
+}
+
+impl MessageHandler {
+    /// Store `vnodes` published by `publisher`, ones which would be stored here are asked
+    /// admission first. DHT is not locked while admission is asked.
+    async fn admit_and_store(&self, vnodes: Vec<VirtualNode>, publisher: Did) -> Result<Placed> {
+        let mut placed = Placed::default();
+        let vnodes = match &self.admission {
+            Some(admission) => {
+                let local = {
+                    let dht = self.dht.lock().await;
+                    vnodes
+                        .iter()
+                        .map(|v| matches!(dht.find_successor(v.did()), Ok(PeerRingAction::Some(_))))
+                        .collect::<Vec<_>>()
+                };
+                let mut admitted = vec![];
+                for (v, local) in vnodes.into_iter().zip(local) {
+                    // only ones stored here are asked, others are forwarded
+                    if local {
+                        if let Err(reason) = admission.admit(&v, publisher).await {
+                            tracing::info!(vnode = ?v.did(), publisher = ?publisher, "store denied: {}", reason);
+                            placed.denied.push(DeniedVNode {
+                                id: v.did(),
+                                reason,
+                            });
+                            continue;
+                        }
+                    }
+                    admitted.push(v);
+                }
+                admitted
+            }
+            None => vnodes,
+        };
+        let dht = self.dht.lock().await;
+        for v in vnodes {
+            let id = v.did();
+            match dht.store(v)? {
+                PeerRingAction::None => placed.stored.push(id),
+                PeerRingAction::RemoteAction(next, PeerRingRemoteAction::FindAndStore(v)) => {
+                    placed.forward.push((next, v))
+                }
+                act => return Err(Error::PeerRingUnexpectedAction(act)),
+            }
+        }
+        Ok(placed)
+    }
+}
+
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
 #[cfg_attr(not(feature = "wasm"), async_trait)]
 impl HandleMsg<StoreVNode> for MessageHandler {
     async fn handle(&self, ctx: &MessagePayload<Message>, msg: &StoreVNode) -> Result<()> {
+        let (inbox, virtual_peer) = self.take_own_inbox(msg.data.clone())?;
+        for v in inbox {
+            self.deliver_inbox(v).await?;
+        }
+        let publisher = Did::from(ctx.origin_verification.session.auth.authorizer);
+        let placed = self.admit_and_store(virtual_peer, publisher).await?;
+        let (id, replicas) = {
+            let dht = self.dht.lock().await;
+            (dht.id, dht.successor.list())
+        };
+        let mut nexts = placed.forward.iter().map(|(n, _)| *n).collect::<Vec<_>>();
+        nexts.sort();
+        nexts.dedup();
+        for next in nexts {
+            let mut relay = ctx.relay.clone();
+            relay.reset_destination(next)?;
+            relay.relay(id, Some(next))?;
+            self.transpond_payload(ctx, relay).await?;
+        }
+        if !placed.denied.is_empty() {
+            let mut relay = ctx.relay.clone();
+            relay.relay(id, None)?;
+            let denied = StoreVNodeDenied {
+                denied_by: id,
+                denied: placed.denied,
+            };
+            self.send_report_message(Message::StoreVNodeDenied(denied), relay)
+                .await?;
+        }
+        if placed.stored.is_empty() {
+            return Ok(());
+        }
+        // tell publisher where they are stored
+        let report = StoreVNodeReport {
+            stored_by: id,
+            ids: placed.stored,
+            replicas,
+        };
+        let mut relay = ctx.relay.clone();
+        relay.relay(id, None)?;
+        self.send_report_message(Message::StoreVNodeReport(report), relay)
+            .await
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<StoreVNodeReport> for MessageHandler {
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &StoreVNodeReport) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+        let id = self.dht.lock().await.id;
+        relay.relay(id, None)?;
+        if relay.next_hop.is_some() {
+            return self.transpond_payload(ctx, relay).await;
+        }
+        // only the node storing them can report, and placements accept only the node a
+        // store is sent to
+        if Did::from(ctx.origin_verification.session.auth.authorizer) != msg.stored_by {
+            return Err(Error::InvalidStoreReport);
+        }
+        let placed = self
+            .swarm
+            .placements()
+            .ack(msg.stored_by, &msg.ids, &msg.replicas);
+        tracing::debug!(stored_by = ?msg.stored_by, placed = placed.len(), "vnodes stored");
+        // ones handed over by repair are removed here once their owner has them
         let dht = self.dht.lock().await;
+        for id in placed {
+            if matches!(dht.find_successor(id)?, PeerRingAction::RemoteAction(..)) {
+                dht.storage.remove(&id);
+            }
+        }
+        Ok(())
+    }
+}
 
-        let virtual_peer = msg.data.clone();
-        for p in virtual_peer {
-            match dht.store(p) {
-                Ok(action) => match action {
-                    PeerRingAction::None => Ok(()),
-                    PeerRingAction::RemoteAction(next, _) => {
-                        let mut relay = ctx.relay.clone();
-                        relay.reset_destination(next)?;
-                        relay.relay(dht.id, Some(next))?;
-                        self.transpond_payload(ctx, relay).await
-                    }
-                    act => Err(Error::PeerRingUnexpectedAction(act)),
-                },
-                Err(e) => Err(e),
-            }?;
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<StoreVNodeDenied> for MessageHandler {
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &StoreVNodeDenied) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+        let id = self.dht.lock().await.id;
+        relay.relay(id, None)?;
+        if relay.next_hop.is_some() {
+            return self.transpond_payload(ctx, relay).await;
+        }
+        // only the node rejecting them can report
+        if Did::from(ctx.origin_verification.session.auth.authorizer) != msg.denied_by {
+            return Err(Error::InvalidStoreReport);
+        }
+        for d in msg.denied.iter() {
+            tracing::warn!(vnode = ?d.id, denied_by = ?msg.denied_by, "store denied: {}", d.reason);
         }
+        let ids = msg.denied.iter().map(|d| d.id).collect::<Vec<_>>();
+        self.swarm.placements().denied(&ids);
         Ok(())
     }
 }
@@ -159,28 +322,22 @@ impl HandleMsg<SyncVNodeWithSuccessor> for MessageHandler {
     // received remote sync vnode request
     async fn handle(
         &self,
-        _ctx: &MessagePayload<Message>,
+        ctx: &MessagePayload<Message>,
         msg: &SyncVNodeWithSuccessor,
     ) -> Result<()> {
-        let dht = self.dht.lock().await;
-
-        for data in msg.data.iter().cloned() {
-            // only simply store here
-            match dht.store(data) {
-                Ok(PeerRingAction::None) => Ok(()),
-                Ok(PeerRingAction::RemoteAction(
-                    next,
-                    PeerRingRemoteAction::FindAndStore(peer),
-                )) => {
-                    self.send_direct_message(
-                        Message::StoreVNode(StoreVNode { data: vec![peer] }),
-                        next,
-                    )
-                    .await
-                }
-                Ok(_) => unreachable!(),
-                Err(e) => Err(e),
-            }?;
+        let (inbox, vnodes) = self.take_own_inbox(msg.data.clone())?;
+        for v in inbox {
+            self.deliver_inbox(v).await?;
+        }
+        // handed over by predecessor, which is asked for as publisher
+        let publisher = Did::from(ctx.origin_verification.session.auth.authorizer);
+        let placed = self.admit_and_store(vnodes, publisher).await?;
+        for d in placed.denied.iter() {
+            tracing::info!(vnode = ?d.id, from = ?publisher, "handed over vnode denied: {}", d.reason);
+        }
+        for (next, v) in placed.forward {
+            self.send_direct_message(Message::StoreVNode(StoreVNode { data: vec![v] }), next)
+                .await?;
         }
         Ok(())
     }
@@ -201,25 +358,15 @@ mod test {
     use crate::session::SessionManager;
     use crate::swarm::Swarm;
     use crate::swarm::TransportManager;
+    use crate::test_utils::KeyFixtures;
     use crate::types::ice_transport::IceTrickleScheme;
 
     #[tokio::test]
     async fn test_store_vnode() -> Result<()> {
         let stun = "stun://stun.l.google.com:19302";
 
-        let mut key1 = SecretKey::random();
-        let mut key2 = SecretKey::random();
-
-        let mut v = vec![key1, key2];
-
-        v.sort_by(|a, b| {
-            if a.address() < b.address() {
-                std::cmp::Ordering::Less
-            } else {
-                std::cmp::Ordering::Greater
-            }
-        });
-        (key1, key2) = (v[0], v[1]);
+        let keys = KeyFixtures::new(2).keys_spread(2);
+        let (key1, key2) = (keys[0], keys[1]);
 
         println!(
             "test with key1: {:?}, key2: {:?}",
@@ -347,6 +494,18 @@ mod test {
             } else {
                 panic!();
             }
+            // node2 reports where it's stored
+            let ev = node1.listen_once().await.unwrap();
+            if let Message::StoreVNodeReport(x) = ev.data {
+                assert_eq!(x.ids, vec![vid]);
+                assert_eq!(x.stored_by, did2);
+            } else {
+                panic!();
+            }
+            assert_eq!(
+                swarm1.placements().placement(vid).map(|p| p.stored_by),
+                Some(did2)
+            );
         } else {
             node2.store(vnode.clone()).await.unwrap();
             // if vnode in range [node2, node1]
@@ -357,6 +516,17 @@ mod test {
             } else {
                 panic!();
             }
+            let ev = node2.listen_once().await.unwrap();
+            if let Message::StoreVNodeReport(x) = ev.data {
+                assert_eq!(x.ids, vec![vid]);
+                assert_eq!(x.stored_by, did1);
+            } else {
+                panic!();
+            }
+            assert_eq!(
+                swarm2.placements().placement(vid).map(|p| p.stored_by),
+                Some(did1)
+            );
         }
         assert!(node1.check_cache(&vid).await.is_none());
         assert!(node2.check_cache(&vid).await.is_none());
@@ -408,4 +578,39 @@ mod test {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_admit_and_store() -> Result<()> {
+        use crate::admission::AdmissionPolicy;
+
+        let key = SecretKey::random();
+        let did: Did = key.address().into();
+        let session = SessionManager::new_with_seckey(&key).unwrap();
+        let swarm = Arc::new(Swarm::new(
+            "stun://stun.l.google.com:19302",
+            key.address(),
+            session,
+        ));
+        let small: VirtualNode = "hello".to_owned().try_into()?;
+        let large: VirtualNode = "hello world, too large".to_owned().try_into()?;
+        let dht = Arc::new(Mutex::new(PeerRing::new(did)));
+        let node = MessageHandler::new(dht.clone(), swarm).with_store_admission(Box::new(
+            AdmissionPolicy {
+                max_size: Some(small.size()),
+                ..Default::default()
+            },
+        ));
+        let publisher: Did = SecretKey::random().address().into();
+
+        // a lone node stores everything itself, so every vnode is asked
+        let placed = node
+            .admit_and_store(vec![small.clone(), large.clone()], publisher)
+            .await?;
+        assert_eq!(placed.stored, vec![small.did()]);
+        assert_eq!(placed.denied.len(), 1);
+        assert_eq!(placed.denied[0].id, large.did());
+        assert!(placed.forward.is_empty());
+        assert!(dht.lock().await.storage.get(&large.did()).is_none());
+        Ok(())
+    }
 }
@@ -0,0 +1,460 @@
+#![warn(missing_docs)]
+//! Byte streams between two peers, for applications tunneling protocols like SSH or HTTP.
+//!
+//! A [Stream] implements [AsyncRead] and [AsyncWrite]. Bytes are carried by [StreamFrame]
+//! messages, multiplexed with stream ids and ordered by sequence numbers, over the same data
+//! channel as other messages of the peer. Open a stream with [MessageHandler::open_stream],
+//! and accept streams opened by others with [MessageHandler::accept_stream].
+//!
+//! Buffers are bounded. Frames wait for the reader of their stream, and so does handling of
+//! messages of the peer, which pushes back on the writer through the data channel; a stream
+//! whose reader takes no frame in [STREAM_STALL_MS] is reset. A stream dropped without being
+//! closed tells the other end by a close frame.
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use futures::channel::mpsc;
+use futures::future::Either;
+use futures::io::AsyncRead;
+use futures::io::AsyncWrite;
+use futures::lock::Mutex;
+use futures::ready;
+use futures::Future;
+use futures::SinkExt;
+use futures::StreamExt;
+
+use super::relayed::send_app_message;
+use crate::dht::Did;
+use crate::dht::PeerRing;
+use crate::err::Result;
+use crate::message::types::Message;
+use crate::message::types::StreamFrame;
+use crate::message::types::StreamFrameKind;
+use crate::message::HandleMsg;
+use crate::message::MessageHandler;
+use crate::message::MessagePayload;
+use crate::swarm::Swarm;
+use crate::timer;
+
+/// Bytes of one frame at most, larger writes are split.
+pub const MAX_FRAME_SIZE: usize = 16 * 1024;
+/// Frames of a stream buffered for its reader at most, and arrived ahead of order.
+pub const STREAM_BUFFER: usize = 64;
+/// Streams opened by others waiting to be accepted at most, more are closed at once.
+pub const MAX_PENDING_STREAMS: usize = 64;
+/// A stream whose reader takes no frame in this long is reset, in milliseconds.
+pub const STREAM_STALL_MS: u64 = 5000;
+
+#[cfg(not(feature = "wasm"))]
+type SendFuture = futures::future::BoxFuture<'static, Result<()>>;
+#[cfg(feature = "wasm")]
+type SendFuture = futures::future::LocalBoxFuture<'static, Result<()>>;
+
+/// Received data of a stream, None if remote closed it.
+type Chunk = Option<Vec<u8>>;
+
+struct StreamState {
+    tx: mpsc::Sender<Chunk>,
+    next_seq: u64,
+    /// Frames arrived before their predecessors.
+    pending: BTreeMap<u64, StreamFrameKind>,
+}
+
+/// What a frame leads to, see [StreamManager::dispatch].
+enum Dispatched {
+    None,
+    /// Receiver of a stream opened by the frame.
+    Opened(mpsc::Receiver<Chunk>),
+    /// Chunks in order for reader of a stream, the last one is None if it's closed.
+    Ready(mpsc::Sender<Chunk>, Vec<Chunk>),
+}
+
+/// Open streams of a node, and streams opened by others but not accepted yet.
+pub struct StreamManager {
+    streams: DashMap<(Did, u64), StreamState>,
+    incoming_tx: mpsc::Sender<Stream>,
+    incoming_rx: Mutex<mpsc::Receiver<Stream>>,
+}
+
+impl Default for StreamManager {
+    fn default() -> Self {
+        let (incoming_tx, incoming_rx) = mpsc::channel(MAX_PENDING_STREAMS);
+        Self {
+            streams: DashMap::new(),
+            incoming_tx,
+            incoming_rx: Mutex::new(incoming_rx),
+        }
+    }
+}
+
+impl StreamManager {
+    /// Create a manager without any stream.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn register(&self, peer: Did, id: u64) -> mpsc::Receiver<Chunk> {
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        self.streams.insert((peer, id), StreamState {
+            tx,
+            next_seq: 1,
+            pending: BTreeMap::new(),
+        });
+        rx
+    }
+
+    /// Order a frame from `peer` among ones of its stream. A stream whose frames arrive too
+    /// far ahead of order is reset.
+    fn dispatch(&self, peer: Did, frame: &StreamFrame) -> Dispatched {
+        let key = (peer, frame.stream_id);
+        if frame.kind == StreamFrameKind::Open {
+            if self.streams.contains_key(&key) {
+                return Dispatched::None;
+            }
+            return Dispatched::Opened(self.register(peer, frame.stream_id));
+        }
+        let mut state = match self.streams.get_mut(&key) {
+            Some(state) => state,
+            None => {
+                tracing::debug!(peer = ?peer, id = frame.stream_id, "frame of unknown stream");
+                return Dispatched::None;
+            }
+        };
+        if frame.seq >= state.next_seq + STREAM_BUFFER as u64 {
+            drop(state);
+            tracing::warn!(peer = ?peer, id = frame.stream_id, "stream overrun, reset it");
+            self.streams.remove(&key);
+            return Dispatched::None;
+        }
+        if frame.seq >= state.next_seq {
+            state.pending.insert(frame.seq, frame.kind.clone());
+        }
+        let mut ready = vec![];
+        loop {
+            let seq = state.next_seq;
+            let kind = match state.pending.remove(&seq) {
+                Some(k) => k,
+                None => break,
+            };
+            state.next_seq += 1;
+            match kind {
+                StreamFrameKind::Data(data) => ready.push(Some(data)),
+                _ => {
+                    ready.push(None);
+                    break;
+                }
+            }
+        }
+        let tx = state.tx.clone();
+        drop(state);
+        if ready.last() == Some(&None) {
+            self.streams.remove(&key);
+        }
+        Dispatched::Ready(tx, ready)
+    }
+
+    /// Pass chunks to reader of a stream, waits while its buffer is full, and resets it if
+    /// the reader takes nothing in [STREAM_STALL_MS].
+    async fn deliver(&self, peer: Did, id: u64, mut tx: mpsc::Sender<Chunk>, ready: Vec<Chunk>) {
+        for chunk in ready {
+            let send = tx.send(chunk);
+            let stall = timer::sleep(Duration::from_millis(STREAM_STALL_MS));
+            futures::pin_mut!(send, stall);
+            match futures::future::select(send, stall).await {
+                Either::Left((Ok(()), _)) => {}
+                // reader is dropped already
+                Either::Left((Err(_), _)) => return,
+                Either::Right(_) => {
+                    tracing::warn!(peer = ?peer, id, "reader of stream stalled, reset it");
+                    self.streams.remove(&(peer, id));
+                    return;
+                }
+            }
+        }
+    }
+
+    fn remove(&self, peer: Did, id: u64) {
+        self.streams.remove(&(peer, id));
+    }
+
+    /// Count of open streams.
+    pub fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    /// Whether there is no open stream.
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+}
+
+/// A byte stream to a peer, closed when dropped.
+pub struct Stream {
+    peer: Did,
+    id: u64,
+    swarm: Arc<Swarm>,
+    dht: Arc<Mutex<PeerRing>>,
+    manager: Arc<StreamManager>,
+    rx: mpsc::Receiver<Chunk>,
+    read_buf: Vec<u8>,
+    read_pos: usize,
+    eof: bool,
+    next_seq: u64,
+    sending: Option<SendFuture>,
+    closed: bool,
+}
+
+fn io_error(e: impl ToString) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+}
+
+impl Stream {
+    /// Peer of the other end.
+    pub fn peer(&self) -> Did {
+        self.peer
+    }
+
+    /// Id of stream, shared by both ends.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn frame(&mut self, kind: StreamFrameKind) -> Message {
+        let frame = StreamFrame {
+            stream_id: self.id,
+            seq: self.next_seq,
+            kind,
+        };
+        self.next_seq += 1;
+        Message::StreamFrame(frame)
+    }
+
+    fn start_send(&mut self, kind: StreamFrameKind) {
+        let msg = self.frame(kind);
+        let swarm = self.swarm.clone();
+        let dht = self.dht.clone();
+        let peer = self.peer;
+        self.sending = Some(Box::pin(async move {
+            send_app_message(&swarm, &dht, msg, peer, None)
+                .await
+                .map(|_| ())
+        }));
+    }
+
+    fn poll_sending(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        if let Some(f) = self.sending.as_mut() {
+            let r = ready!(f.as_mut().poll(cx));
+            self.sending = None;
+            r.map_err(io_error)?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if this.read_pos < this.read_buf.len() {
+                let n = buf.len().min(this.read_buf.len() - this.read_pos);
+                buf[..n].copy_from_slice(&this.read_buf[this.read_pos..this.read_pos + n]);
+                this.read_pos += n;
+                return Poll::Ready(Ok(n));
+            }
+            if this.eof {
+                return Poll::Ready(Ok(0));
+            }
+            match ready!(this.rx.poll_next_unpin(cx)) {
+                Some(Some(data)) => {
+                    this.read_buf = data;
+                    this.read_pos = 0;
+                }
+                Some(None) => this.eof = true,
+                // ended without a close frame
+                None => return Poll::Ready(Err(std::io::ErrorKind::ConnectionReset.into())),
+            }
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        if this.closed {
+            return Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()));
+        }
+        ready!(this.poll_sending(cx))?;
+        let n = buf.len().min(MAX_FRAME_SIZE);
+        this.start_send(StreamFrameKind::Data(buf[..n].to_vec()));
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.get_mut().poll_sending(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        if !this.closed {
+            ready!(this.poll_sending(cx))?;
+            this.start_send(StreamFrameKind::Close);
+            this.closed = true;
+        }
+        this.poll_sending(cx)
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        self.manager.remove(self.peer, self.id);
+        if self.closed {
+            return;
+        }
+        // tell the other end, frames sending now are ordered by seq there
+        let msg = self.frame(StreamFrameKind::Close);
+        let swarm = self.swarm.clone();
+        let dht = self.dht.clone();
+        let peer = self.peer;
+        timer::spawn(async move {
+            if let Err(e) = send_app_message(&swarm, &dht, msg, peer, None).await {
+                tracing::debug!(peer = ?peer, "failed to close stream: {}", e);
+            }
+        });
+    }
+}
+
+impl MessageHandler {
+    fn new_stream(&self, peer: Did, id: u64, rx: mpsc::Receiver<Chunk>) -> Stream {
+        Stream {
+            peer,
+            id,
+            swarm: self.swarm.clone(),
+            dht: self.dht.clone(),
+            manager: self.streams.clone(),
+            rx,
+            read_buf: vec![],
+            read_pos: 0,
+            eof: false,
+            next_seq: 0,
+            sending: None,
+            closed: false,
+        }
+    }
+
+    /// Open a stream to `peer`.
+    pub async fn open_stream(&self, peer: Did) -> Result<Stream> {
+        let id = rand::random::<u64>();
+        let rx = self.streams.register(peer, id);
+        let mut stream = self.new_stream(peer, id, rx);
+        let msg = stream.frame(StreamFrameKind::Open);
+        self.send_app_message(msg, peer).await?;
+        Ok(stream)
+    }
+
+    /// Wait for a stream opened by others, at most [MAX_PENDING_STREAMS] wait in queue until
+    /// accepted.
+    pub async fn accept_stream(&self) -> Option<Stream> {
+        self.streams.incoming_rx.lock().await.next().await
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<StreamFrame> for MessageHandler {
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &StreamFrame) -> Result<()> {
+        if ctx.relay.destination != self.swarm.address().into() {
+            return Ok(());
+        }
+        // frames are of streams with their signer, never with an unsigned origin of relay path
+        let peer = Did::from(ctx.origin_verification.session.auth.authorizer);
+        match self.streams.dispatch(peer, msg) {
+            Dispatched::Opened(rx) => {
+                let mut stream = self.new_stream(peer, msg.stream_id, rx);
+                // the open frame takes seq 0 of the other direction too
+                stream.next_seq = 1;
+                tracing::debug!(peer = ?peer, id = msg.stream_id, "stream opened by peer");
+                // closed by drop if too many are waiting
+                if let Err(e) = self.streams.incoming_tx.clone().try_send(stream) {
+                    tracing::warn!(peer = ?peer, id = msg.stream_id, "stream not accepted: {}", e);
+                }
+            }
+            Dispatched::Ready(tx, ready) => {
+                self.streams.deliver(peer, msg.stream_id, tx, ready).await
+            }
+            Dispatched::None => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    fn frame(seq: u64, kind: StreamFrameKind) -> StreamFrame {
+        StreamFrame {
+            stream_id: 7,
+            seq,
+            kind,
+        }
+    }
+
+    fn ready(d: Dispatched) -> Vec<Chunk> {
+        match d {
+            Dispatched::Ready(_, ready) => ready,
+            _ => panic!("frame is not of an open stream"),
+        }
+    }
+
+    #[test]
+    fn test_dispatch_in_order() {
+        let manager = StreamManager::new();
+        let peer: Did = SecretKey::random().address().into();
+        assert!(matches!(
+            manager.dispatch(peer, &frame(0, StreamFrameKind::Open)),
+            Dispatched::Opened(_)
+        ));
+        assert!(matches!(
+            manager.dispatch(peer, &frame(0, StreamFrameKind::Open)),
+            Dispatched::None
+        ));
+
+        let two = manager.dispatch(peer, &frame(2, StreamFrameKind::Data(vec![2])));
+        assert!(ready(two).is_empty());
+        let close = manager.dispatch(peer, &frame(3, StreamFrameKind::Close));
+        assert!(ready(close).is_empty());
+        let one = manager.dispatch(peer, &frame(1, StreamFrameKind::Data(vec![1])));
+        assert_eq!(ready(one), vec![Some(vec![1]), Some(vec![2]), None]);
+        assert!(manager.is_empty());
+        // closed already
+        assert!(matches!(
+            manager.dispatch(peer, &frame(1, StreamFrameKind::Data(vec![1]))),
+            Dispatched::None
+        ));
+    }
+
+    #[test]
+    fn test_dispatch_overrun() {
+        let manager = StreamManager::new();
+        let peer: Did = SecretKey::random().address().into();
+        manager.dispatch(peer, &frame(0, StreamFrameKind::Open));
+        let far = frame(1 + STREAM_BUFFER as u64, StreamFrameKind::Data(vec![0]));
+        assert!(matches!(manager.dispatch(peer, &far), Dispatched::None));
+        assert!(manager.is_empty());
+    }
+}
@@ -0,0 +1,230 @@
+#![warn(missing_docs)]
+//! Subscriptions to handled messages as streams, an alternative to [MessageCallback].
+//!
+//! A subscription is a callback in [CallbackRegistry](super::callback::CallbackRegistry)
+//! forwarding messages to a channel, so they can be consumed in a `select!` loop. Drop the
+//! [Subscription] to unsubscribe, the callback is removed at once.
+//!
+//! Channel of a subscription holds [SUBSCRIPTION_CAPACITY] messages. Handler never waits for a
+//! slow subscriber, messages are dropped while its channel is full.
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+
+use async_trait::async_trait;
+use futures::channel::mpsc;
+use futures::Stream;
+
+use super::callback::CallbackFilter;
+use super::callback::CallbackHandle;
+use crate::message::CustomMessage;
+use crate::message::MaybeEncrypted;
+use crate::message::Message;
+use crate::message::MessageCallback;
+use crate::message::MessageHandler;
+use crate::message::MessageKind;
+use crate::message::MessagePayload;
+
+/// Prefix of names subscriptions are registered by.
+pub const SUBSCRIPTION_PREFIX: &str = "subscription:";
+/// Messages buffered for a subscriber at most.
+pub const SUBSCRIPTION_CAPACITY: usize = 256;
+
+/// Stream of messages of a subscription, unsubscribes when it's dropped.
+pub struct Subscription<T> {
+    rx: mpsc::Receiver<T>,
+    handle: CallbackHandle,
+}
+
+impl<T> Subscription<T> {
+    /// Name the subscription is registered by.
+    pub fn name(&self) -> &str {
+        self.handle.name()
+    }
+}
+
+impl<T> Stream for Subscription<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        Pin::new(&mut self.rx).poll_next(cx)
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        self.handle.remove();
+    }
+}
+
+struct Subscriber<T> {
+    name: String,
+    tx: Mutex<mpsc::Sender<T>>,
+}
+
+impl<T> Subscriber<T> {
+    fn new(name: &str, tx: mpsc::Sender<T>) -> Self {
+        Self {
+            name: name.to_owned(),
+            tx: Mutex::new(tx),
+        }
+    }
+
+    /// Send `item` unless channel is full, subscription is removed if it's gone.
+    fn send(&self, handler: &MessageHandler, item: T) {
+        let sent = match self.tx.lock() {
+            Ok(mut tx) => tx.try_send(item),
+            Err(_) => return,
+        };
+        match sent {
+            Ok(()) => {}
+            Err(e) if e.is_full() => {
+                tracing::warn!(subscription = %self.name, "drop message, subscriber is slow")
+            }
+            Err(_) => {
+                handler.unregister_callback(&self.name);
+            }
+        }
+    }
+}
+
+type PayloadSubscriber = Subscriber<MessagePayload<Message>>;
+type CustomSubscriber = Subscriber<(MessagePayload<Message>, CustomMessage)>;
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl MessageCallback for PayloadSubscriber {
+    async fn custom_message(
+        &self,
+        handler: &MessageHandler,
+        ctx: &MessagePayload<Message>,
+        _msg: &MaybeEncrypted<CustomMessage>,
+    ) {
+        self.send(handler, ctx.clone())
+    }
+
+    async fn builtin_message(&self, handler: &MessageHandler, ctx: &MessagePayload<Message>) {
+        self.send(handler, ctx.clone())
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl MessageCallback for CustomSubscriber {
+    async fn custom_message(
+        &self,
+        handler: &MessageHandler,
+        ctx: &MessagePayload<Message>,
+        msg: &MaybeEncrypted<CustomMessage>,
+    ) {
+        let msg = match handler.decrypt_msg(msg) {
+            Ok(msg) => msg,
+            Err(e) => {
+                tracing::debug!(tx_id = ?ctx.tx_id, "skip undecryptable message: {}", e);
+                return;
+            }
+        };
+        self.send(handler, (ctx.clone(), msg))
+    }
+
+    async fn builtin_message(&self, _handler: &MessageHandler, _ctx: &MessagePayload<Message>) {}
+}
+
+impl MessageHandler {
+    /// Stream of every message handled from now on, drop it to unsubscribe.
+    pub fn subscribe(&self) -> Subscription<MessagePayload<Message>> {
+        let (tx, rx) = mpsc::channel(SUBSCRIPTION_CAPACITY);
+        let name = self.callbacks.unique_name(SUBSCRIPTION_PREFIX);
+        let subscriber = PayloadSubscriber::new(&name, tx);
+        let handle = self.register_callback(&name, CallbackFilter::default(), Box::new(subscriber));
+        Subscription { rx, handle }
+    }
+
+    /// Stream of custom messages matching `filter` handled from now on, decrypted by session
+    /// key of node. Kinds of `filter` are ignored. Drop it to unsubscribe.
+    pub fn subscribe_custom(
+        &self,
+        filter: CallbackFilter,
+    ) -> Subscription<(MessagePayload<Message>, CustomMessage)> {
+        let (tx, rx) = mpsc::channel(SUBSCRIPTION_CAPACITY);
+        let name = self.callbacks.unique_name(SUBSCRIPTION_PREFIX);
+        let filter = CallbackFilter {
+            kinds: vec![],
+            ..filter
+        }
+        .kind(MessageKind::CustomMessage);
+        let subscriber = CustomSubscriber::new(&name, tx);
+        let handle = self.register_callback(&name, filter, Box::new(subscriber));
+        Subscription { rx, handle }
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use futures::lock::Mutex;
+    use futures::FutureExt;
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::dht::Did;
+    use crate::dht::PeerRing;
+    use crate::ecc::SecretKey;
+    use crate::session::SessionManager;
+    use crate::swarm::Swarm;
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let key = SecretKey::random();
+        let session = SessionManager::new_with_seckey(&key).unwrap();
+        let swarm = Arc::new(Swarm::new(
+            "stun://stun.l.google.com:19302",
+            key.address(),
+            session.clone(),
+        ));
+        let dht = PeerRing::new(key.address().into());
+        let handler = MessageHandler::new(Arc::new(Mutex::new(dht)), swarm);
+
+        let mut all = handler.subscribe();
+        let other: Did = SecretKey::random().address().into();
+        let mut from_other = handler.subscribe_custom(CallbackFilter::default().sender(other));
+        let mut custom = handler.subscribe_custom(CallbackFilter::default());
+        assert_eq!(handler.callbacks().names().len(), 3);
+
+        let payload = MessagePayload::new_direct(
+            Message::custom("hello".as_bytes(), &None).unwrap(),
+            &session,
+            key.address().into(),
+        )
+        .unwrap();
+        handler.invoke_callbacks(&payload).await;
+        assert_eq!(all.next().await.unwrap().tx_id, payload.tx_id);
+        let (ctx, msg) = custom.next().await.unwrap();
+        assert_eq!(ctx.tx_id, payload.tx_id);
+        assert_eq!(msg.0, b"hello");
+
+        // dropped subscriptions are removed at once
+        drop(all);
+        drop(custom);
+        assert_eq!(handler.callbacks().names(), vec![from_other
+            .name()
+            .to_owned()]);
+        handler.invoke_callbacks(&payload).await;
+        assert!(from_other.next().now_or_never().is_none());
+
+        // messages are dropped while subscriber is slow
+        let mut slow = handler.subscribe();
+        for _ in 0..SUBSCRIPTION_CAPACITY + 8 {
+            handler.invoke_callbacks(&payload).await;
+        }
+        let mut received = 0;
+        while let Some(Some(_)) = slow.next().now_or_never() {
+            received += 1;
+        }
+        // and a slot of the sender
+        assert_eq!(received, SUBSCRIPTION_CAPACITY + 1);
+    }
+}
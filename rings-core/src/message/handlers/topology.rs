@@ -0,0 +1,109 @@
+#![warn(missing_docs)]
+//! Queries of ring neighbours of remote nodes, used by crawlers walking the ring.
+//!
+//! [QueryTopology] travels along DHT path to a node, which answers [TopologyReport] with its
+//! successors and predecessor. Reports are kept by [MessageHandler], see
+//! [MessageHandler::topology_report], only if they answer a query sent in last
+//! [TOPOLOGY_QUERY_TTL_MS], others are refused.
+use async_trait::async_trait;
+
+use crate::dht::Did;
+use crate::dht::PeerRing;
+use crate::dht::PeerRingAction;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::types::Message;
+use crate::message::types::QueryTopology;
+use crate::message::types::TopologyReport;
+use crate::message::HandleMsg;
+use crate::message::MessageHandler;
+use crate::message::MessagePayload;
+use crate::message::PayloadSender;
+use crate::swarm::TransportManager;
+use crate::utils;
+
+/// Reports answering queries sent longer than this ago are refused, in milliseconds.
+pub const TOPOLOGY_QUERY_TTL_MS: u128 = 60_000;
+
+/// Next hop to `destination`, the node itself if it's connected.
+pub(super) fn next_hop(dht: &PeerRing, connected: bool, destination: Did) -> Result<Did> {
+    if connected {
+        return Ok(destination);
+    }
+    match dht.find_route(destination)? {
+        PeerRingAction::Some(node) => Ok(node),
+        PeerRingAction::RemoteAction(node, _) => Ok(node),
+        _ => Err(Error::MessageHandlerMissNextNode),
+    }
+}
+
+impl MessageHandler {
+    /// Ask `did` for its successors and predecessor, the answer is kept as
+    /// [MessageHandler::topology_report] of `did`.
+    pub async fn query_topology(&self, did: Did) -> Result<()> {
+        let connected = self.swarm.get_transport(&did.into()).is_some();
+        let next = next_hop(&*self.dht.lock().await, connected, did)?;
+        let now = utils::get_epoch_ms();
+        self.topology_queries
+            .retain(|_, sent| now.saturating_sub(*sent) < TOPOLOGY_QUERY_TTL_MS);
+        self.topology_queries.insert(did, now);
+        self.send_message(Message::QueryTopology(QueryTopology {}), next, did)
+            .await
+    }
+
+    /// Latest report of `did`, with time it's received in milliseconds since epoch.
+    pub fn topology_report(&self, did: Did) -> Option<(u128, TopologyReport)> {
+        self.topology.get(&did).map(|r| r.clone())
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<QueryTopology> for MessageHandler {
+    async fn handle(&self, ctx: &MessagePayload<Message>, _msg: &QueryTopology) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+        let dht = self.dht.lock().await;
+        let id = dht.id;
+        if relay.destination != id {
+            let connected = self.swarm.get_transport(&relay.destination).is_some();
+            let next = next_hop(&dht, connected, relay.destination)?;
+            drop(dht);
+            relay.relay(id, Some(next))?;
+            return self.transpond_payload(ctx, relay).await;
+        }
+        let report = TopologyReport {
+            id,
+            successors: dht.successor.list(),
+            predecessor: dht.predecessor,
+        };
+        drop(dht);
+        relay.relay(id, None)?;
+        self.send_report_message(Message::TopologyReport(report), relay)
+            .await
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<TopologyReport> for MessageHandler {
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &TopologyReport) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+        let id = self.dht.lock().await.id;
+        relay.relay(id, None)?;
+        if relay.next_hop.is_some() {
+            return self.transpond_payload(ctx, relay).await;
+        }
+        // only the node itself can report its neighbours
+        if Did::from(ctx.origin_verification.session.auth.authorizer) != msg.id {
+            return Err(Error::InvalidTopologyReport);
+        }
+        let now = utils::get_epoch_ms();
+        match self.topology_queries.remove(&msg.id) {
+            Some((_, sent)) if now.saturating_sub(sent) < TOPOLOGY_QUERY_TTL_MS => {}
+            _ => return Err(Error::UnsolicitedTopologyReport),
+        }
+        self.topology
+            .insert(msg.id, (utils::get_epoch_ms(), msg.clone()));
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 //! Message and MessageHandler
 
+pub mod codec;
 mod encoder;
 pub use encoder::Decoder;
 pub use encoder::Encoded;
@@ -9,15 +10,51 @@ mod payload;
 pub use payload::MessagePayload;
 pub use payload::OriginVerificationGen;
 pub use payload::PayloadSender;
+pub use payload::RawBody;
+pub use payload::RawPayload;
+pub use payload::DEFAULT_NETWORK_ID;
 
 mod types;
 pub use types::*;
 
+pub mod tx_id;
+pub use tx_id::TxIdGenerator;
+
 mod handlers;
+pub(crate) use handlers::audit::challenge_stored;
+pub use handlers::callback::CallbackFilter;
+pub use handlers::callback::CallbackHandle;
+pub use handlers::callback::CallbackRegistry;
+pub use handlers::callback::DEFAULT_CALLBACK;
+pub use handlers::connection::TopologyPolicy;
+pub use handlers::dial::LazyDial;
+pub use handlers::dial::DEFAULT_DIAL_QUEUE;
+pub use handlers::echo::strip_echo;
+pub use handlers::echo::EchoStats;
+pub use handlers::echo::ECHO_PREFIX;
+pub use handlers::inbox::InboxEntry;
+pub use handlers::inbox::TInbox;
+pub use handlers::inbox::DEFAULT_INBOX_TTL_MS;
+pub(crate) use handlers::relayed::RawRouter;
+pub use handlers::relayed::RelayedLinks;
+pub use handlers::relayed::DEFAULT_RELAY_BUDGET;
+pub use handlers::relayed::RELAY_MAX_HOPS;
+pub use handlers::relayed::RELAY_WINDOW;
+pub use handlers::storage::TChordStorage;
+pub use handlers::stream::Stream;
+pub use handlers::stream::StreamManager;
+pub use handlers::stream::MAX_FRAME_SIZE;
+pub use handlers::subscription::Subscription;
+pub use handlers::subscription::SUBSCRIPTION_CAPACITY;
+pub use handlers::subscription::SUBSCRIPTION_PREFIX;
 pub use handlers::HandleMsg;
 pub use handlers::MessageCallback;
 pub use handlers::MessageHandler;
+pub use handlers::DEFAULT_CONNECT_TIMEOUT_MS;
+pub use handlers::DEFAULT_JOIN_PARALLELISM;
 
 mod protocols;
+pub use protocols::HopStamp;
 pub use protocols::MessageRelay;
+pub use protocols::MessageVerification;
 pub use protocols::RelayMethod;
@@ -1,20 +1,19 @@
-use std::io::Write;
-
 use async_trait::async_trait;
-use flate2::write::GzDecoder;
-use flate2::write::GzEncoder;
-use flate2::Compression;
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
-use web3::types::Address;
+use serde_json::value::RawValue;
 
+use super::codec;
+use super::codec::Codec;
 use super::encoder::Decoder;
 use super::encoder::Encoded;
 use super::encoder::Encoder;
+use super::protocols::HopStamp;
 use super::protocols::MessageRelay;
 use super::protocols::MessageVerification;
 use super::protocols::RelayMethod;
+use crate::address::Address;
 use crate::dht::Did;
 use crate::ecc::HashStr;
 use crate::ecc::PublicKey;
@@ -22,8 +21,25 @@ use crate::err::Error;
 use crate::err::Result;
 use crate::session::SessionManager;
 use crate::utils;
+use crate::version::PROTOCOL_VERSION;
 
 const DEFAULT_TTL_MS: usize = 60 * 1000;
+/// Network of payloads which not specify one, e.g. sent by old nodes.
+pub const DEFAULT_NETWORK_ID: &str = "rings";
+
+fn default_network_id() -> String {
+    DEFAULT_NETWORK_ID.to_owned()
+}
+
+/// Body of payload kept as raw JSON, it's decoded only when it's needed.
+pub type RawBody = Box<RawValue>;
+
+/// Payload with header decoded and body kept raw.
+///
+/// Relays decode routing fields only, i.e. `relay`, `verification` and `network_id`, to
+/// forward a payload. Signatures are made over JSON of body, so a raw body is verified and
+/// re-signed without decoding, even if relays can't decode it.
+pub type RawPayload = MessagePayload<RawBody>;
 
 pub enum OriginVerificationGen {
     Origin,
@@ -38,6 +54,13 @@ pub struct MessagePayload<T> {
     pub verification: MessageVerification,
     pub origin_verification: MessageVerification,
     pub relay: MessageRelay,
+    /// Network of sender, nodes drop payloads from other networks.
+    #[serde(default = "default_network_id")]
+    pub network_id: String,
+    /// Protocol version payload is sent in, negotiated by sender with this hop, see
+    /// `HandshakeMeta::payload_version`. 0 if sender is built before versioning.
+    #[serde(default)]
+    pub protocol_version: u16,
 }
 
 impl<T> MessagePayload<T>
@@ -51,8 +74,9 @@ where T: Serialize + DeserializeOwned
     ) -> Result<Self> {
         let ts_ms = utils::get_epoch_ms();
         let ttl_ms = DEFAULT_TTL_MS;
-        let msg = &MessageVerification::pack_msg(&data, ts_ms, ttl_ms)?;
-        let tx_id = msg.into();
+        let network_id = session_manager.network_id();
+        let msg = &MessageVerification::pack_payload_msg(&data, &network_id, ts_ms, ttl_ms)?;
+        let tx_id = session_manager.next_tx_id();
         let addr = session_manager.authorizer()?;
         let verification = MessageVerification {
             session: session_manager.session()?,
@@ -73,9 +97,27 @@ where T: Serialize + DeserializeOwned
             verification,
             origin_verification,
             relay,
+            network_id,
+            protocol_version: PROTOCOL_VERSION,
         })
     }
 
+    /// Keep `tx_id` of a message being relayed, see [super::tx_id].
+    pub fn with_tx_id(mut self, tx_id: HashStr) -> Self {
+        self.tx_id = tx_id;
+        self
+    }
+
+    /// Ask every hop to stamp the message, and destination to return a receipt of it, see
+    /// [crate::message::DeliveryReceipt].
+    pub fn with_receipt(mut self) -> Self {
+        self.relay.stamps = Some(vec![HopStamp {
+            node: self.relay.sender(),
+            ts_ms: utils::get_epoch_ms(),
+        }]);
+        self
+    }
+
     pub fn new_send(
         data: T,
         session_manager: &SessionManager,
@@ -106,42 +148,73 @@ where T: Serialize + DeserializeOwned
     }
 
     pub fn is_expired(&self) -> bool {
-        let now = utils::get_epoch_ms();
-        now > self.verification.ts_ms + self.verification.ttl_ms as u128
-            && now > self.origin_verification.ts_ms + self.origin_verification.ttl_ms as u128
+        self.is_expired_by(|_| 0)
+    }
+
+    /// Expired by clocks of signers, `offset_ms` of a node is how far its clock is ahead of
+    /// local one, see [crate::clock::ClockSync].
+    pub fn is_expired_by<F>(&self, offset_ms: F) -> bool
+    where F: Fn(Did) -> i128 {
+        let now = utils::get_epoch_ms() as i128;
+        let expired = |v: &MessageVerification, signer: Did| {
+            now + offset_ms(signer) > v.ts_ms as i128 + v.ttl_ms as i128
+        };
+        expired(
+            &self.verification,
+            self.verification.session.auth.authorizer.into(),
+        ) && expired(
+            &self.origin_verification,
+            self.origin_verification.session.auth.authorizer.into(),
+        )
     }
 
     pub fn verify(&self) -> bool {
-        if self.is_expired() {
+        self.verify_by(|_| 0)
+    }
+
+    /// Verify signatures, and expiry by clocks of signers, see [Self::is_expired_by].
+    pub fn verify_by<F>(&self, offset_ms: F) -> bool
+    where F: Fn(Did) -> i128 {
+        if self.is_expired_by(offset_ms) {
             return false;
         }
 
-        self.verification.verify(&self.data) && self.origin_verification.verify(&self.data)
+        self.verification
+            .verify_payload(&self.data, &self.network_id)
+            && self
+                .origin_verification
+                .verify_payload(&self.data, &self.network_id)
     }
 
     pub fn origin_session_pubkey(&self) -> Result<PublicKey> {
-        self.origin_verification.session_pubkey(&self.data)
+        self.origin_verification
+            .payload_session_pubkey(&self.data, &self.network_id)
     }
 
+    #[cfg(feature = "gzip")]
     pub fn gzip(&self, level: u8) -> Result<Vec<u8>> {
-        let mut ec = GzEncoder::new(Vec::new(), Compression::new(level as u32));
         let json_str = serde_json::to_string(self).map_err(|_| Error::SerializeToString)?;
-        ec.write_all(json_str.as_bytes())
-            .map_err(|_| Error::GzipEncode)?;
-        ec.finish().map_err(|_| Error::GzipEncode)
+        codec::gzip(json_str.as_bytes(), level as u32)
     }
 
+    #[cfg(feature = "gzip")]
     pub fn from_gzipped(data: &[u8]) -> Result<Self>
     where T: DeserializeOwned {
-        let mut writer = Vec::new();
-        let mut decoder = GzDecoder::new(writer);
-        decoder.write_all(data).map_err(|_| Error::GzipDecode)?;
-        decoder.try_finish().map_err(|_| Error::GzipDecode)?;
-        writer = decoder.finish().map_err(|_| Error::GzipDecode)?;
-        let m = serde_json::from_slice(&writer).map_err(Error::Deserialize)?;
+        if codec::detect(data) != Codec::Gzip {
+            return Err(Error::GzipDecode);
+        }
+        let m = serde_json::from_slice(&codec::decompress(data)?).map_err(Error::Deserialize)?;
         Ok(m)
     }
 
+    /// Compress payload by `codec`, if its JSON is not shorter than `threshold`.
+    /// Returns size of JSON with compressed bytes.
+    pub fn compress(&self, codec: Codec, threshold: usize) -> Result<(usize, Vec<u8>)> {
+        let json = self.to_json_vec()?;
+        let compressed = codec::compress(codec, &json, threshold)?;
+        Ok((json.len(), compressed))
+    }
+
     pub fn from_json(data: &[u8]) -> Result<Self> {
         serde_json::from_slice(data).map_err(Error::Deserialize)
     }
@@ -150,19 +223,61 @@ where T: Serialize + DeserializeOwned
         serde_json::to_vec(self).map_err(Error::Serialize)
     }
 
+    /// Decode payload of any codec, see [codec::detect].
     pub fn from_auto(data: &[u8]) -> Result<Self> {
-        if let Ok(m) = Self::from_gzipped(data) {
-            return Ok(m);
-        }
-        Self::from_json(data)
+        Self::from_json(&codec::decompress(data)?)
+    }
+
+    /// Decode payload of any codec, refused if it's more than `limit` bytes decompressed.
+    pub fn from_auto_within(data: &[u8], limit: usize) -> Result<Self> {
+        Self::from_json(&codec::decompress_within(data, limit)?)
+    }
+}
+
+impl RawPayload {
+    /// Decode body of payload, at destination.
+    pub fn decode_body<T>(self) -> Result<MessagePayload<T>>
+    where T: DeserializeOwned {
+        let data = serde_json::from_str(self.data.get()).map_err(Error::Deserialize)?;
+        Ok(MessagePayload {
+            data,
+            tx_id: self.tx_id,
+            addr: self.addr,
+            verification: self.verification,
+            origin_verification: self.origin_verification,
+            relay: self.relay,
+            network_id: self.network_id,
+            protocol_version: self.protocol_version,
+        })
+    }
+}
+
+impl<T> MessagePayload<T>
+where T: Serialize
+{
+    /// Keep body as raw JSON, see [RawPayload].
+    pub fn into_raw(self) -> Result<RawPayload> {
+        let data = serde_json::value::to_raw_value(&self.data).map_err(Error::Serialize)?;
+        Ok(MessagePayload {
+            data,
+            tx_id: self.tx_id,
+            addr: self.addr,
+            verification: self.verification,
+            origin_verification: self.origin_verification,
+            relay: self.relay,
+            network_id: self.network_id,
+            protocol_version: self.protocol_version,
+        })
     }
 }
 
 impl<T> Encoder for MessagePayload<T>
 where T: Serialize + DeserializeOwned
 {
+    /// Plain JSON, as it may be sent to any peer, like handshake info before codecs are
+    /// negotiated, and every build decodes it whatever codecs it's built with.
     fn encode(&self) -> Result<Encoded> {
-        self.gzip(9)?.encode()
+        self.to_json_vec()?.encode()
     }
 }
 
@@ -224,12 +339,15 @@ where T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static
         payload: &MessagePayload<T>,
         relay: MessageRelay,
     ) -> Result<()> {
-        self.send_payload(MessagePayload::new(
-            payload.data.clone(),
-            self.session_manager(),
-            OriginVerificationGen::Stick(payload.origin_verification.clone()),
-            relay,
-        )?)
+        self.send_payload(
+            MessagePayload::new(
+                payload.data.clone(),
+                self.session_manager(),
+                OriginVerificationGen::Stick(payload.origin_verification.clone()),
+                relay,
+            )?
+            .with_tx_id(payload.tx_id.clone()),
+        )
         .await
     }
 }
@@ -260,6 +378,17 @@ pub mod test {
         MessagePayload::new_direct(test_data, &session, destination).unwrap()
     }
 
+    #[test]
+    fn test_expired_by_clock_of_signer() {
+        let mut payload = new_test_payload();
+        // signer is 2 minutes behind
+        payload.verification.ts_ms -= 120_000;
+        payload.origin_verification.ts_ms -= 120_000;
+        assert!(payload.is_expired());
+        assert!(!payload.is_expired_by(|_| -120_000));
+        assert!(payload.is_expired_by(|_| -30_000));
+    }
+
     #[test]
     fn new_then_verify() {
         let payload = new_test_payload();
@@ -284,6 +413,83 @@ pub mod test {
         assert!(relaied_payload.verify());
     }
 
+    #[test]
+    fn test_payload_network_id() {
+        let payload = new_test_payload();
+        assert_eq!(payload.network_id, DEFAULT_NETWORK_ID);
+
+        // payloads from nodes without network id fall into default network
+        let mut value = serde_json::to_value(&payload).unwrap();
+        value.as_object_mut().unwrap().remove("network_id");
+        let payload2: MessagePayload<TestData> = serde_json::from_value(value).unwrap();
+        assert_eq!(payload2.network_id, DEFAULT_NETWORK_ID);
+
+        // network is signed, payloads can't be moved to another one
+        let mut moved = payload.clone();
+        moved.network_id = "testnet".to_owned();
+        assert!(!moved.verify());
+
+        let key = SecretKey::random();
+        let session = SessionManager::new_with_seckey(&key).unwrap();
+        session.set_network_id("testnet");
+        let payload3 =
+            MessagePayload::new_direct(payload.data.clone(), &session, key.address().into())
+                .unwrap();
+        assert_eq!(payload3.network_id, "testnet");
+        assert!(payload3.verify());
+        let mut moved = payload3;
+        moved.network_id = DEFAULT_NETWORK_ID.to_owned();
+        assert!(!moved.verify());
+    }
+
+    #[test]
+    fn test_payload_protocol_version() {
+        let payload = new_test_payload();
+        assert_eq!(payload.protocol_version, PROTOCOL_VERSION);
+
+        // payloads from nodes before versioning are version 0
+        let mut value = serde_json::to_value(&payload).unwrap();
+        value.as_object_mut().unwrap().remove("protocol_version");
+        let payload2: MessagePayload<TestData> = serde_json::from_value(value).unwrap();
+        assert_eq!(payload2.protocol_version, 0);
+    }
+
+    #[test]
+    fn test_raw_payload() {
+        let payload = new_test_payload();
+        let raw: RawPayload = payload.encode().unwrap().decode().unwrap();
+        assert_eq!(raw.relay, payload.relay);
+        assert!(raw.verify());
+
+        // relay re-signs raw body without decoding it
+        let key2 = SecretKey::random();
+        let did2 = key2.address().into();
+        let session2 = SessionManager::new_with_seckey(&key2).unwrap();
+        let mut relay = raw.relay.clone();
+        relay.next_hop = Some(did2);
+        relay.relay(did2, None).unwrap();
+        let relayed = MessagePayload::new(
+            raw.data.clone(),
+            &session2,
+            OriginVerificationGen::Stick(raw.origin_verification.clone()),
+            relay,
+        )
+        .unwrap();
+        let relayed: MessagePayload<TestData> = relayed
+            .encode()
+            .unwrap()
+            .decode::<RawPayload>()
+            .unwrap()
+            .decode_body()
+            .unwrap();
+        assert!(relayed.verify());
+        assert_eq!(relayed.data, payload.data);
+
+        let raw = payload.clone().into_raw().unwrap();
+        assert_eq!(raw.decode_body::<TestData>().unwrap(), payload);
+    }
+
+    #[cfg(feature = "gzip")]
     #[test]
     fn test_message_relay_gzip() {
         let payload = new_test_payload();
@@ -295,8 +501,11 @@ pub mod test {
     #[test]
     fn test_message_relay_from_auto() {
         let payload = new_test_payload();
-        let gziped_encoded_payload = payload.encode().unwrap();
-        let payload2: MessagePayload<TestData> = gziped_encoded_payload.decode().unwrap();
+        // encoded payloads are plain, every build decodes them
+        let encoded_payload = payload.encode().unwrap();
+        let bytes: Vec<u8> = encoded_payload.decode().unwrap();
+        assert_eq!(codec::detect(&bytes), Codec::None);
+        let payload2: MessagePayload<TestData> = encoded_payload.decode().unwrap();
         assert_eq!(payload, payload2);
 
         let ungzip_encoded_payload = payload.to_json_vec().unwrap().encode().unwrap();
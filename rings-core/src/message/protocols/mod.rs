@@ -1,6 +1,7 @@
 mod relay;
 mod verify;
 
+pub use self::relay::HopStamp;
 pub use self::relay::MessageRelay;
 pub use self::relay::RelayMethod;
 pub use self::verify::MessageVerification;
@@ -7,6 +7,10 @@ use serde::Serialize;
 use crate::dht::Did;
 use crate::err::Error;
 use crate::err::Result;
+use crate::utils;
+
+/// Path of a REPORT can't grow longer than this by detours.
+pub const MAX_DETOUR_PATH: usize = 32;
 
 /// `MessageRelay` divides messages into two types by method: SEND and REPORT.
 /// And will enable different behaviors when handling SEND and REPORT messages.
@@ -18,6 +22,15 @@ pub enum RelayMethod {
     REPORT,
 }
 
+/// Time `node` handled a message, in ms since epoch by its clock.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HopStamp {
+    /// Node stamping the message.
+    pub node: Did,
+    /// Time it's stamped.
+    pub ts_ms: u128,
+}
+
 /// MessageRelay guide message passing on rings network by relay.
 ///
 /// All messages should be sent with `MessageRelay`.
@@ -49,6 +62,18 @@ pub struct MessageRelay {
     /// The destination of the message. It may be customized when sending. It cannot be changed when reporting.
     /// It may help the handler to find out `next_hop` in some situations.
     pub destination: Did,
+
+    /// Time each node handled the message, in order of handling. Only kept if a
+    /// [DeliveryReceipt](crate::message::DeliveryReceipt) is requested, then every hop stamps
+    /// it by `relay`. Stamps name their nodes, so they still match hops if `path` is changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stamps: Option<Vec<HopStamp>>,
+
+    /// Time a SEND is sent directly to its destination, in ms since epoch by clock of sender.
+    /// Its REPORT echoes it back, so sender measures the round trip by its own clock, see
+    /// [MessageRelay::round_trip_ms].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sent_ms: Option<u128>,
 }
 
 impl MessageRelay {
@@ -67,6 +92,8 @@ impl MessageRelay {
             path_end_cursor: path_end_cursor.unwrap_or(0),
             next_hop,
             destination,
+            stamps: None,
+            sent_ms: None,
         }
     }
 
@@ -89,6 +116,12 @@ impl MessageRelay {
             RelayMethod::SEND => {
                 self.path.push(current);
                 self.next_hop = next_hop;
+                if let Some(stamps) = &mut self.stamps {
+                    stamps.push(HopStamp {
+                        node: current,
+                        ts_ms: utils::get_epoch_ms(),
+                    });
+                }
                 Ok(())
             }
 
@@ -141,9 +174,28 @@ impl MessageRelay {
             path_end_cursor: 0,
             next_hop: self.path_prev(),
             destination: self.sender(),
+            stamps: None,
+            sent_ms: self.sent_ms,
         })
     }
 
+    /// Check if it's a SEND going directly from its origin to destination.
+    pub fn is_direct_send(&self) -> bool {
+        self.method == RelayMethod::SEND
+            && self.path.len() == 1
+            && self.next_hop == Some(self.destination)
+    }
+
+    /// Round trip of a direct SEND, if it's the REPORT of it coming directly back from `from`,
+    /// measured at `now_ms` by clock of origin.
+    pub fn round_trip_ms(&self, from: Did, now_ms: u128) -> Option<u64> {
+        if self.method != RelayMethod::REPORT || self.path.len() != 2 || self.path[1] != from {
+            return None;
+        }
+        let rtt = now_ms.checked_sub(self.sent_ms?)?;
+        u64::try_from(rtt).ok()
+    }
+
     /// A SEND message can change its destination.
     /// Call with REPORT method will get an error imeediately.
     pub fn reset_destination(&mut self, destination: Did) -> Result<()> {
@@ -155,6 +207,40 @@ impl MessageRelay {
         }
     }
 
+    /// Route a REPORT around a gone hop, through `next_hop` toward destination.
+    /// Should be called by `current` after [MessageRelay::relay].
+    ///
+    /// The rest of path, which is not retraced yet, is replaced by `next_hop`, so the nodes
+    /// after it retrace path as usual. `next_hop` can't be a node the report passed already,
+    /// and path can't grow longer than [MAX_DETOUR_PATH], so a report never loops.
+    pub fn detour(&mut self, current: Did, next_hop: Did) -> Result<()> {
+        if self.method != RelayMethod::REPORT {
+            return Err(Error::DetourNeedReport);
+        }
+        let pos = self
+            .path
+            .len()
+            .checked_sub(self.path_end_cursor + 1)
+            .filter(|i| self.path[*i] == current)
+            .ok_or(Error::CannotInferNextHop)?;
+        let passed = &self.path[pos..];
+        if passed.contains(&next_hop) {
+            return Err(Error::InfiniteRelayPath);
+        }
+        let mut path = vec![self.destination];
+        if next_hop != self.destination {
+            path.push(next_hop);
+        }
+        if path.len() + passed.len() > MAX_DETOUR_PATH {
+            return Err(Error::InfiniteRelayPath);
+        }
+        path.extend_from_slice(passed);
+        self.path_end_cursor = passed.len() - 1;
+        self.path = path;
+        self.next_hop = Some(next_hop);
+        Ok(())
+    }
+
     /// Check if path and destination is valid.
     /// It will be automatically called at relay started.
     pub fn validate(&self) -> Result<()> {
@@ -268,6 +354,8 @@ mod test {
             path_end_cursor: 0,
             next_hop: None,
             destination: next_hop3,
+            stamps: None,
+            sent_ms: None,
         };
 
         // node0 -> node1
@@ -309,6 +397,8 @@ mod test {
             path_end_cursor: 0,
             next_hop: None,
             destination: next_hop4,
+            stamps: None,
+            sent_ms: None,
         };
 
         // node0 -> node1 -> node2 -> node3 -> node4
@@ -357,6 +447,81 @@ mod test {
         report_relay.relay(origin_sender, None).unwrap();
     }
 
+    #[test]
+    fn test_detour_report() {
+        let origin_sender = SecretKey::random().address().into();
+        let next_hop1 = SecretKey::random().address().into();
+        let next_hop2 = SecretKey::random().address().into();
+        let next_hop3 = SecretKey::random().address().into();
+        let detour_node = SecretKey::random().address().into();
+
+        let mut send_relay = MessageRelay {
+            method: RelayMethod::SEND,
+            path: vec![origin_sender],
+            path_end_cursor: 0,
+            next_hop: None,
+            destination: next_hop3,
+            stamps: None,
+            sent_ms: None,
+        };
+        assert!(send_relay.detour(origin_sender, next_hop1).is_err());
+
+        // node0 -> node1 -> node2 -> node3
+        send_relay.relay(next_hop1, None).unwrap();
+        send_relay.relay(next_hop2, None).unwrap();
+        send_relay.relay(next_hop3, None).unwrap();
+
+        // node3 -> node2, and node1 is gone
+        let mut report_relay = send_relay.report().unwrap();
+        report_relay.relay(next_hop2, None).unwrap();
+        assert_eq!(report_relay.next_hop, Some(next_hop1));
+        // nodes passed can't be next hop
+        assert!(report_relay.clone().detour(next_hop2, next_hop3).is_err());
+
+        // node2 -> detour node -> node0
+        report_relay.detour(next_hop2, detour_node).unwrap();
+        assert_eq!(report_relay.path, vec![
+            origin_sender,
+            detour_node,
+            next_hop2,
+            next_hop3
+        ]);
+        report_relay.validate().unwrap();
+        report_relay.relay(detour_node, None).unwrap();
+        assert_eq!(report_relay.next_hop, Some(origin_sender));
+        report_relay.relay(origin_sender, None).unwrap();
+        assert_eq!(report_relay.next_hop, None);
+        assert_eq!(report_relay.sender(), next_hop3);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let origin = SecretKey::random().address().into();
+        let peer = SecretKey::random().address().into();
+        let mut relay = MessageRelay::new(RelayMethod::SEND, vec![origin], None, Some(peer), peer);
+        assert!(relay.is_direct_send());
+        relay.sent_ms = Some(1000);
+        relay.relay(peer, None).unwrap();
+        assert!(!relay.is_direct_send());
+        assert_eq!(relay.round_trip_ms(peer, 1500), None);
+
+        let report = relay.report().unwrap();
+        assert_eq!(report.round_trip_ms(peer, 1040), Some(40));
+        // by another peer, or echoing time in future
+        assert_eq!(report.round_trip_ms(origin, 1040), None);
+        assert_eq!(report.round_trip_ms(peer, 900), None);
+
+        // report of a relayed send goes through other hops
+        let mut relay = MessageRelay::new(RelayMethod::SEND, vec![origin], None, None, peer);
+        relay.sent_ms = Some(1000);
+        relay
+            .relay(SecretKey::random().address().into(), None)
+            .unwrap();
+        relay.relay(peer, None).unwrap();
+        let report = relay.report().unwrap();
+        assert_eq!(report.round_trip_ms(peer, 1040), None);
+    }
+
     #[test]
     fn test_path_prev() {
         let origin_sender = SecretKey::random().address().into();
@@ -369,6 +534,8 @@ mod test {
             path_end_cursor: 0,
             next_hop: None,
             destination: next_hop2,
+            stamps: None,
+            sent_ms: None,
         };
 
         assert!(relay.path_prev().is_none());
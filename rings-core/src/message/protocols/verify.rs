@@ -7,6 +7,7 @@ use crate::ecc::signers;
 use crate::ecc::PublicKey;
 use crate::err::Error;
 use crate::err::Result;
+use crate::message::DEFAULT_NETWORK_ID;
 use crate::session::Session;
 use crate::session::Signer;
 
@@ -21,11 +22,27 @@ pub struct MessageVerification {
 impl MessageVerification {
     pub fn verify<T>(&self, data: &T) -> bool
     where T: Serialize {
+        self.verify_msg(self.msg(data))
+    }
+
+    /// Verify signature over `data` of a payload in network `network_id`, see
+    /// [Self::pack_payload_msg].
+    pub fn verify_payload<T>(&self, data: &T, network_id: &str) -> bool
+    where T: Serialize {
+        self.verify_msg(Self::pack_payload_msg(
+            data,
+            network_id,
+            self.ts_ms,
+            self.ttl_ms,
+        ))
+    }
+
+    fn verify_msg(&self, msg: Result<String>) -> bool {
         if !self.session.verify() {
             return false;
         }
 
-        if let (Ok(addr), Ok(msg)) = (self.session.address(), self.msg(data)) {
+        if let (Ok(addr), Ok(msg)) = (self.session.address(), msg) {
             match self.session.auth.signer {
                 Signer::DEFAULT => signers::default::verify(&msg, &addr, &self.sig),
                 Signer::EIP712 => signers::eip712::verify(&msg, &addr, &self.sig),
@@ -37,10 +54,24 @@ impl MessageVerification {
 
     pub fn session_pubkey<T>(&self, data: &T) -> Result<PublicKey>
     where T: Serialize {
-        let msg = self.msg(data)?;
+        self.recover(&self.msg(data)?)
+    }
+
+    /// Session key signing `data` of a payload in network `network_id`.
+    pub fn payload_session_pubkey<T>(&self, data: &T, network_id: &str) -> Result<PublicKey>
+    where T: Serialize {
+        self.recover(&Self::pack_payload_msg(
+            data,
+            network_id,
+            self.ts_ms,
+            self.ttl_ms,
+        )?)
+    }
+
+    fn recover(&self, msg: &str) -> Result<PublicKey> {
         match self.session.auth.signer {
-            Signer::DEFAULT => signers::default::recover(&msg, &self.sig),
-            Signer::EIP712 => signers::eip712::recover(&msg, &self.sig),
+            Signer::DEFAULT => signers::default::recover(msg, &self.sig),
+            Signer::EIP712 => signers::eip712::recover(msg, &self.sig),
         }
     }
 
@@ -51,6 +82,25 @@ impl MessageVerification {
         Ok(msg)
     }
 
+    /// Message signed for `data` of a payload in network `network_id`, so payloads can't be
+    /// moved to another network. Payloads of default network are signed like before networks
+    /// were added, old nodes still verify them.
+    pub fn pack_payload_msg<T>(
+        data: &T,
+        network_id: &str,
+        ts_ms: u128,
+        ttl_ms: usize,
+    ) -> Result<String>
+    where
+        T: Serialize,
+    {
+        let mut msg = Self::pack_msg(data, ts_ms, ttl_ms)?;
+        if network_id != DEFAULT_NETWORK_ID {
+            write!(msg, "\n{}", network_id).map_err(|_| Error::SerializeToString)?;
+        }
+        Ok(msg)
+    }
+
     fn msg<T>(&self, data: &T) -> Result<String>
     where T: Serialize {
         Self::pack_msg(data, self.ts_ms, self.ttl_ms)
@@ -0,0 +1,101 @@
+#![warn(missing_docs)]
+//! Transaction ids of payloads.
+//!
+//! A `tx_id` is generated once when a message is sent, and kept unchanged by every relay, so
+//! a message is correlated across hops, in history, and in replies like
+//! [ServerBusy](crate::message::ServerBusy). Ids are ULIDs, 48 bits of milliseconds followed
+//! by 80 random bits in Crockford base32. Ids generated by a node are monotonic, an id
+//! generated within the same millisecond as the last one, or after clock went back,
+//! increments the last one, so they sort in sending order. Every node has its own generator,
+//! see [SessionManager::next_tx_id](crate::session::SessionManager::next_tx_id), so nodes
+//! sharing a process don't share state.
+//!
+//! Ids are not signed, nodes don't trust them to tell payloads apart, see [crate::replay].
+use std::sync::Mutex;
+
+use crate::ecc::HashStr;
+use crate::utils;
+
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const RANDOM_BITS: u32 = 80;
+const RANDOM_MASK: u128 = (1 << RANDOM_BITS) - 1;
+/// Length of encoded id.
+pub const TX_ID_LEN: usize = 26;
+
+/// Milliseconds since epoch when `tx_id` is generated, None if it isn't a ULID, like ids of
+/// nodes before ULIDs.
+pub fn tx_id_ts_ms(tx_id: &HashStr) -> Option<u128> {
+    decode(&tx_id.inner()).map(|v| v >> RANDOM_BITS)
+}
+
+/// Generator of monotonic ids, see module doc.
+#[derive(Debug, Default)]
+pub struct TxIdGenerator {
+    last: Mutex<u128>,
+}
+
+impl TxIdGenerator {
+    /// Next id, by current time.
+    pub fn next(&self) -> HashStr {
+        self.next_at(utils::get_epoch_ms(), rand::random::<u128>())
+    }
+
+    fn next_at(&self, now: u128, random: u128) -> HashStr {
+        let fresh = (now << RANDOM_BITS) | (random & RANDOM_MASK);
+        let value = match self.last.lock() {
+            Ok(mut last) => {
+                // overflow of random bits carries into milliseconds
+                *last = if *last >> RANDOM_BITS >= now {
+                    *last + 1
+                } else {
+                    fresh
+                };
+                *last
+            }
+            Err(_) => fresh,
+        };
+        HashStr::new(encode(value))
+    }
+}
+
+fn encode(value: u128) -> String {
+    (0..TX_ID_LEN)
+        .rev()
+        .map(|i| ALPHABET[((value >> (i * 5)) & 0x1f) as usize] as char)
+        .collect()
+}
+
+fn decode(s: &str) -> Option<u128> {
+    if s.len() != TX_ID_LEN {
+        return None;
+    }
+    s.bytes().try_fold(0u128, |acc, c| {
+        let digit = ALPHABET.iter().position(|a| *a == c.to_ascii_uppercase())?;
+        acc.checked_mul(32)?.checked_add(digit as u128)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tx_id_monotonic() {
+        let generator = TxIdGenerator::default();
+        let a = generator.next_at(1_000, 42);
+        // same millisecond, and clock goes back
+        let b = generator.next_at(1_000, 7);
+        let c = generator.next_at(999, 7);
+        let d = generator.next_at(1_001, 0);
+        assert!(a.inner() < b.inner());
+        assert!(b.inner() < c.inner());
+        assert!(c.inner() < d.inner());
+        assert_eq!(a.inner().len(), TX_ID_LEN);
+
+        assert_eq!(tx_id_ts_ms(&a), Some(1_000));
+        assert_eq!(tx_id_ts_ms(&c), Some(1_000));
+        assert_eq!(tx_id_ts_ms(&d), Some(1_001));
+        assert_eq!(decode(&a.inner()), Some((1_000 << RANDOM_BITS) | 42));
+        assert_eq!(tx_id_ts_ms(&"not a ulid".into()), None);
+    }
+}
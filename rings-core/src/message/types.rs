@@ -5,10 +5,14 @@ use serde::Serialize;
 use crate::dht::vnode::VirtualNode;
 use crate::dht::Did;
 use crate::ecc::elgamal;
+use crate::ecc::HashStr;
 use crate::ecc::PublicKey;
 use crate::ecc::SecretKey;
 use crate::err::Error;
 use crate::err::Result;
+use crate::gossip::PeerSample;
+use crate::pex::PexPeer;
+use crate::rotation::RotationRecord;
 
 #[derive(Debug, PartialEq, Eq, Deserialize, Serialize, Clone)]
 pub struct ConnectNodeSend {
@@ -29,12 +33,23 @@ pub struct ConnectNodeReport {
 pub struct FindSuccessorSend {
     pub id: Did,
     pub for_fix: bool,
+    /// Index of finger being looked up, echoed by report. None for the one of
+    /// `fix_finger_index`, and from older nodes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finger: Option<u8>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct FindSuccessorReport {
     pub id: Did,
     pub for_fix: bool,
+    /// Index of finger being looked up, echoed from [FindSuccessorSend].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finger: Option<u8>,
+    /// Successor list of responder, which follows `id` on ring, so a joining node fills its
+    /// own one in one round trip. Empty for lookups of fingers, and from older nodes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub successors: Vec<Did>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
@@ -50,6 +65,9 @@ pub struct NotifyPredecessorReport {
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct JoinDHT {
     pub id: Did,
+    /// Joined node advertised itself as relay capable.
+    #[serde(default)]
+    pub relay: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
@@ -57,6 +75,13 @@ pub struct LeaveDHT {
     pub id: Did,
 }
 
+/// Transport of `id` failed, and it's dialed again while it's migrating, see
+/// [crate::migration]. Raised by swarm of this node, ones of others are dropped.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ReconnectPeer {
+    pub id: Did,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct SearchVNode {
     pub id: Did,
@@ -72,6 +97,62 @@ pub struct StoreVNode {
     pub data: Vec<VirtualNode>,
 }
 
+/// Virtual nodes of [StoreVNode] stored by the node answering it, see [crate::placement].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct StoreVNodeReport {
+    /// DID of node storing them.
+    pub stored_by: Did,
+    pub ids: Vec<Did>,
+    /// Successors of the node, which take over the virtual nodes when it leaves.
+    pub replicas: Vec<Did>,
+}
+
+/// Ask the node storing virtual node `id` to prove it has a data item, see [crate::audit].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ChallengeVNode {
+    pub id: Did,
+    pub nonce: u64,
+    /// [crate::audit::digest] of the item to prove.
+    pub item: String,
+}
+
+/// Answer of [ChallengeVNode].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ChallengeVNodeReport {
+    /// DID of node challenged.
+    pub stored_by: Did,
+    pub id: Did,
+    pub nonce: u64,
+    /// [crate::audit::proof] of the item and nonce, None if it's not stored.
+    pub proof: Option<String>,
+}
+
+/// A virtual node rejected, with reason given by [crate::admission::StoreAdmission].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct DeniedVNode {
+    pub id: Did,
+    pub reason: String,
+}
+
+/// Virtual nodes of [StoreVNode] rejected by the node which would store them, see
+/// [crate::admission].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct StoreVNodeDenied {
+    /// DID of node rejecting them.
+    pub denied_by: Did,
+    pub denied: Vec<DeniedVNode>,
+}
+
+/// Messages of inbox `id` delivered to its recipient, which are removed by the holder, see
+/// [crate::message::TInbox].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct InboxAck {
+    /// Address of inbox.
+    pub id: Did,
+    /// `tx_id` of delivered messages.
+    pub tx_ids: Vec<HashStr>,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct MultiCall {
     pub messages: Vec<Message>,
@@ -90,6 +171,106 @@ pub struct JoinSubRing {
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct CustomMessage(pub Vec<u8>);
 
+/// Kind of [StreamFrame].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub enum StreamFrameKind {
+    Open,
+    Data(Vec<u8>),
+    Close,
+}
+
+/// A frame of byte stream, ordered by `seq` within stream `stream_id`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct StreamFrame {
+    pub stream_id: u64,
+    pub seq: u64,
+    pub kind: StreamFrameKind,
+}
+
+/// Application message relayed along DHT path, to a peer which can't be connected directly.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct RelayedData {
+    pub seq: u64,
+    pub message: Box<Message>,
+}
+
+/// Destination received [RelayedData] `seq`, reported back to origin.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct RelayedDataAck {
+    pub seq: u64,
+    /// Signed id of acknowledged message, see [crate::replay::payload_id].
+    pub id: HashStr,
+    /// `tx_id` of acknowledged message, for correlation by origin.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_id: Option<HashStr>,
+}
+
+/// Ask destination for its ring neighbours.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct QueryTopology {}
+
+/// Ring neighbours of node `id`, reported back to origin of [QueryTopology].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct TopologyReport {
+    pub id: Did,
+    pub successors: Vec<Did>,
+    pub predecessor: Option<Did>,
+}
+
+/// Random sample of peers known by sender, led by sender itself, see [crate::gossip].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PeerSampleSend {
+    pub peers: Vec<PeerSample>,
+}
+
+/// Sample of peers answering [PeerSampleSend].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PeerSampleReport {
+    pub peers: Vec<PeerSample>,
+}
+
+/// Connected peers of sender, see [crate::pex].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PeerExchange {
+    pub peers: Vec<PexPeer>,
+}
+
+/// Identity of sender is rotated, see [crate::rotation].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct RotateIdentity {
+    pub record: RotationRecord,
+}
+
+/// Latency of a hop of a message, from previous node on path to `node`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct HopLatency {
+    pub node: Did,
+    pub latency_ms: u128,
+}
+
+/// Message `tx_id` is delivered, with latency of each hop on its path, see
+/// [crate::message::MessageHandler::send_with_receipt].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct DeliveryReceipt {
+    pub tx_id: HashStr,
+    /// Signed id of delivered message, see [crate::replay::payload_id].
+    pub id: HashStr,
+    pub hops: Vec<HopLatency>,
+}
+
+/// Keeps an idle connection alive, sent to peers connected directly, see
+/// [KEEPALIVE_INTERVAL](crate::dht::KEEPALIVE_INTERVAL).
+#[derive(Debug, PartialEq, Eq, Deserialize, Serialize, Clone)]
+pub struct Ping;
+
+/// Message `tx_id` is shed by an overloaded node, see [crate::overload].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ServerBusy {
+    pub tx_id: HashStr,
+    /// Suggested wait before sending application messages again, in milliseconds.
+    pub retry_after_ms: u64,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 pub enum MaybeEncrypted<T> {
     Encrypted(Vec<(PublicKey, PublicKey)>),
@@ -101,6 +282,7 @@ pub enum Message {
     MultiCall(MultiCall),
     JoinDHT(JoinDHT),
     LeaveDHT(LeaveDHT),
+    ReconnectPeer(ReconnectPeer),
     ConnectNodeSend(ConnectNodeSend),
     AlreadyConnected(AlreadyConnected),
     ConnectNodeReport(ConnectNodeReport),
@@ -111,9 +293,65 @@ pub enum Message {
     SearchVNode(SearchVNode),
     FoundVNode(FoundVNode),
     StoreVNode(StoreVNode),
+    StoreVNodeReport(StoreVNodeReport),
+    StoreVNodeDenied(StoreVNodeDenied),
+    InboxAck(InboxAck),
+    ChallengeVNode(ChallengeVNode),
+    ChallengeVNodeReport(ChallengeVNodeReport),
     SyncVNodeWithSuccessor(SyncVNodeWithSuccessor),
     JoinSubRing(JoinSubRing),
     CustomMessage(MaybeEncrypted<CustomMessage>),
+    StreamFrame(StreamFrame),
+    RelayedData(RelayedData),
+    RelayedDataAck(RelayedDataAck),
+    QueryTopology(QueryTopology),
+    TopologyReport(TopologyReport),
+    PeerSampleSend(PeerSampleSend),
+    PeerSampleReport(PeerSampleReport),
+    PeerExchange(PeerExchange),
+    RotateIdentity(RotateIdentity),
+    DeliveryReceipt(DeliveryReceipt),
+    ServerBusy(ServerBusy),
+    Ping(Ping),
+}
+
+/// Kind of a [Message], one for each of its variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum MessageKind {
+    MultiCall,
+    JoinDHT,
+    LeaveDHT,
+    ReconnectPeer,
+    ConnectNodeSend,
+    AlreadyConnected,
+    ConnectNodeReport,
+    FindSuccessorSend,
+    FindSuccessorReport,
+    NotifyPredecessorSend,
+    NotifyPredecessorReport,
+    SearchVNode,
+    FoundVNode,
+    StoreVNode,
+    StoreVNodeReport,
+    StoreVNodeDenied,
+    InboxAck,
+    ChallengeVNode,
+    ChallengeVNodeReport,
+    SyncVNodeWithSuccessor,
+    JoinSubRing,
+    CustomMessage,
+    StreamFrame,
+    RelayedData,
+    RelayedDataAck,
+    QueryTopology,
+    TopologyReport,
+    PeerSampleSend,
+    PeerSampleReport,
+    PeerExchange,
+    RotateIdentity,
+    DeliveryReceipt,
+    ServerBusy,
+    Ping,
 }
 
 impl std::fmt::Display for Message {
@@ -128,6 +366,46 @@ impl Message {
         let msg = MaybeEncrypted::new(data, pubkey)?;
         Ok(Message::CustomMessage(msg))
     }
+
+    /// Kind of message, callbacks may be filtered by.
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            Message::MultiCall(_) => MessageKind::MultiCall,
+            Message::JoinDHT(_) => MessageKind::JoinDHT,
+            Message::LeaveDHT(_) => MessageKind::LeaveDHT,
+            Message::ReconnectPeer(_) => MessageKind::ReconnectPeer,
+            Message::ConnectNodeSend(_) => MessageKind::ConnectNodeSend,
+            Message::AlreadyConnected(_) => MessageKind::AlreadyConnected,
+            Message::ConnectNodeReport(_) => MessageKind::ConnectNodeReport,
+            Message::FindSuccessorSend(_) => MessageKind::FindSuccessorSend,
+            Message::FindSuccessorReport(_) => MessageKind::FindSuccessorReport,
+            Message::NotifyPredecessorSend(_) => MessageKind::NotifyPredecessorSend,
+            Message::NotifyPredecessorReport(_) => MessageKind::NotifyPredecessorReport,
+            Message::SearchVNode(_) => MessageKind::SearchVNode,
+            Message::FoundVNode(_) => MessageKind::FoundVNode,
+            Message::StoreVNode(_) => MessageKind::StoreVNode,
+            Message::StoreVNodeReport(_) => MessageKind::StoreVNodeReport,
+            Message::StoreVNodeDenied(_) => MessageKind::StoreVNodeDenied,
+            Message::InboxAck(_) => MessageKind::InboxAck,
+            Message::ChallengeVNode(_) => MessageKind::ChallengeVNode,
+            Message::ChallengeVNodeReport(_) => MessageKind::ChallengeVNodeReport,
+            Message::SyncVNodeWithSuccessor(_) => MessageKind::SyncVNodeWithSuccessor,
+            Message::JoinSubRing(_) => MessageKind::JoinSubRing,
+            Message::CustomMessage(_) => MessageKind::CustomMessage,
+            Message::StreamFrame(_) => MessageKind::StreamFrame,
+            Message::RelayedData(_) => MessageKind::RelayedData,
+            Message::RelayedDataAck(_) => MessageKind::RelayedDataAck,
+            Message::QueryTopology(_) => MessageKind::QueryTopology,
+            Message::TopologyReport(_) => MessageKind::TopologyReport,
+            Message::PeerSampleSend(_) => MessageKind::PeerSampleSend,
+            Message::PeerSampleReport(_) => MessageKind::PeerSampleReport,
+            Message::PeerExchange(_) => MessageKind::PeerExchange,
+            Message::RotateIdentity(_) => MessageKind::RotateIdentity,
+            Message::DeliveryReceipt(_) => MessageKind::DeliveryReceipt,
+            Message::ServerBusy(_) => MessageKind::ServerBusy,
+            Message::Ping(_) => MessageKind::Ping,
+        }
+    }
 }
 
 impl<T> MaybeEncrypted<T>
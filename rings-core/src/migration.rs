@@ -0,0 +1,109 @@
+//! Peers migrating across network changes.
+//!
+//! When a peer switches its network, like a laptop moving to another Wi-Fi, ICE of its
+//! transport fails. Instead of dropping the peer at once, swarm takes the failed transport
+//! away and keeps the peer in DHT for a migration window. Both ends dial again through DHT,
+//! the one of lower DID at once and the other after half of the window, so they don't race
+//! with offers, and the new transport is registered under the same address. Payloads sent to
+//! a migrating peer fail at once like to any peer not connected, they are never held up for
+//! the window. If no transport comes in the window, the peer leaves DHT as a failed one.
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::address::Address;
+use crate::utils;
+
+/// Migration window suggested for nodes, in ms, swarm has none by default.
+pub const DEFAULT_MIGRATION_WINDOW_MS: u64 = 15 * 1000;
+
+/// Deadlines of peers migrating, see module doc.
+#[derive(Debug, Default)]
+pub struct MigratingPeers(Mutex<HashMap<Address, u128>>);
+
+impl MigratingPeers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start migration of `peer`, which ends in `window_ms`. Returns false if it's migrating
+    /// already, the deadline is not extended then.
+    pub fn start(&self, peer: Address, window_ms: u64) -> bool {
+        let deadline = utils::get_epoch_ms() + window_ms as u128;
+        let mut peers = self.0.lock().unwrap();
+        if peers.contains_key(&peer) {
+            return false;
+        }
+        peers.insert(peer, deadline);
+        true
+    }
+
+    /// End migration of `peer`, when a new transport is connected or it's given up. Returns
+    /// false if it's not migrating.
+    pub fn finish(&self, peer: &Address) -> bool {
+        self.0.lock().unwrap().remove(peer).is_some()
+    }
+
+    /// Migration of `peer` is not finished, and not expired.
+    pub fn is_migrating(&self, peer: &Address) -> bool {
+        let now = utils::get_epoch_ms();
+        self.0
+            .lock()
+            .unwrap()
+            .get(peer)
+            .map_or(false, |deadline| *deadline > now)
+    }
+
+    /// Time left in migration window of `peer`, in ms, None if it's not migrating.
+    pub fn remaining_ms(&self, peer: &Address) -> Option<u128> {
+        let now = utils::get_epoch_ms();
+        self.0
+            .lock()
+            .unwrap()
+            .get(peer)
+            .map(|deadline| deadline.saturating_sub(now))
+    }
+
+    /// Migration of `peer` is not finished, but expired.
+    pub fn is_expired(&self, peer: &Address) -> bool {
+        let now = utils::get_epoch_ms();
+        self.0
+            .lock()
+            .unwrap()
+            .get(peer)
+            .map_or(false, |deadline| *deadline <= now)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    #[test]
+    fn test_migrating_peers() {
+        let peers = MigratingPeers::new();
+        let peer = SecretKey::random().address();
+        assert!(!peers.is_migrating(&peer));
+        assert!(peers.start(peer, 60_000));
+        assert!(!peers.start(peer, 0));
+        assert!(peers.is_migrating(&peer));
+        assert!(!peers.is_expired(&peer));
+        assert!(peers.remaining_ms(&peer).unwrap() > 0);
+        assert!(peers.finish(&peer));
+        assert!(!peers.finish(&peer));
+
+        assert!(peers.start(peer, 0));
+        assert!(!peers.is_migrating(&peer));
+        assert!(peers.is_expired(&peer));
+        assert_eq!(peers.remaining_ms(&peer), Some(0));
+        assert_eq!(peers.len(), 1);
+    }
+}
@@ -0,0 +1,204 @@
+//! Fair scheduling of outbound payloads of swarm.
+//!
+//! Every peer has an outbox of payloads waiting to be sent, and has one payload in flight at
+//! most, so payloads to the same peer are sent in order. Outboxes are independent of each
+//! other, a slow transport only delays payloads to its own peer, never traffic to others.
+//! At most [MAX_QUEUED_SENDS] payloads wait in an outbox, more to the same peer are refused
+//! with [Error::OutboxFull], so callers of a stalled peer are pushed back instead of piling up.
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use futures::channel::oneshot;
+
+use crate::address::Address;
+use crate::err::Error;
+use crate::err::Result;
+
+/// Payloads waiting in outbox of one peer at most.
+pub const MAX_QUEUED_SENDS: usize = 64;
+
+#[derive(Default)]
+struct Outbox {
+    /// Waiting senders, in order they are queued.
+    waiting: VecDeque<oneshot::Sender<()>>,
+    /// A payload is in flight.
+    sending: bool,
+}
+
+/// Scheduler over outboxes of peers.
+pub struct OutboxScheduler {
+    max_queued: usize,
+    outboxes: Mutex<BTreeMap<Address, Outbox>>,
+}
+
+/// Turn of sending a payload to a peer, next one is scheduled when it's dropped.
+pub struct SendTurn<'a> {
+    scheduler: &'a OutboxScheduler,
+    peer: Address,
+    /// Set while waiting for the turn.
+    waiting: Option<oneshot::Receiver<()>>,
+}
+
+impl Drop for SendTurn<'_> {
+    fn drop(&mut self) {
+        match self.waiting.take() {
+            Some(rx) => self.scheduler.cancel(self.peer, rx),
+            None => self.scheduler.finish(self.peer),
+        }
+    }
+}
+
+impl Default for OutboxScheduler {
+    fn default() -> Self {
+        Self::new(MAX_QUEUED_SENDS)
+    }
+}
+
+impl OutboxScheduler {
+    /// Scheduler with at most `max_queued` payloads waiting for each peer.
+    pub fn new(max_queued: usize) -> Self {
+        Self {
+            max_queued,
+            outboxes: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Payloads waiting or being sent.
+    pub fn len(&self) -> usize {
+        self.outboxes.lock().map_or(0, |o| {
+            o.values()
+                .map(|o| o.waiting.len() + o.sending as usize)
+                .sum()
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Payloads waiting or being sent to `peer`.
+    pub fn peer_len(&self, peer: Address) -> usize {
+        self.outboxes.lock().map_or(0, |o| {
+            o.get(&peer)
+                .map_or(0, |o| o.waiting.len() + o.sending as usize)
+        })
+    }
+
+    /// Wait for turn of sending a payload to `peer`, hold the turn until it's sent. Fails if
+    /// outbox of `peer` is full.
+    pub async fn turn(&self, peer: Address) -> Result<SendTurn<'_>> {
+        let rx = match self.outboxes.lock() {
+            Ok(mut outboxes) => {
+                let outbox = outboxes.entry(peer).or_default();
+                if !outbox.sending {
+                    outbox.sending = true;
+                    None
+                } else if outbox.waiting.len() >= self.max_queued {
+                    return Err(Error::OutboxFull(peer));
+                } else {
+                    let (tx, rx) = oneshot::channel();
+                    outbox.waiting.push_back(tx);
+                    Some(rx)
+                }
+            }
+            // nothing is scheduled once it's poisoned
+            Err(_) => None,
+        };
+        let mut turn = SendTurn {
+            scheduler: self,
+            peer,
+            waiting: rx,
+        };
+        if let Some(rx) = turn.waiting.as_mut() {
+            // sender is never dropped before it's given a turn
+            rx.await.ok();
+        }
+        turn.waiting = None;
+        Ok(turn)
+    }
+
+    /// Waiting for turn is cancelled, the turn is given back if it's granted already.
+    fn cancel(&self, peer: Address, mut rx: oneshot::Receiver<()>) {
+        // checked under lock, so the turn isn't granted right after it
+        let granted = match self.outboxes.lock() {
+            Ok(mut o) => {
+                let granted = matches!(rx.try_recv(), Ok(Some(())));
+                drop(rx);
+                // room of cancelled one is free at once
+                if let Some(outbox) = o.get_mut(&peer) {
+                    outbox.waiting.retain(|tx| !tx.is_canceled());
+                }
+                granted
+            }
+            Err(_) => false,
+        };
+        if granted {
+            self.finish(peer);
+        }
+    }
+
+    /// Payload to `peer` is sent, give the turn to the next one waiting.
+    fn finish(&self, peer: Address) {
+        let mut outboxes = match self.outboxes.lock() {
+            Ok(o) => o,
+            Err(_) => return,
+        };
+        let outbox = match outboxes.get_mut(&peer) {
+            Some(o) => o,
+            None => return,
+        };
+        // cancelled senders are skipped
+        while let Some(tx) = outbox.waiting.pop_front() {
+            if tx.send(()).is_ok() {
+                return;
+            }
+        }
+        outboxes.remove(&peer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    #[test]
+    fn test_outboxes() {
+        let scheduler = OutboxScheduler::new(1);
+        let slow = SecretKey::random().address();
+        let other = SecretKey::random().address();
+
+        let first = scheduler.turn(slow).now_or_never().unwrap().unwrap();
+        // the next one to the slow peer waits, the one after it is refused
+        let mut second = Box::pin(scheduler.turn(slow));
+        assert!(second.as_mut().now_or_never().is_none());
+        assert!(matches!(
+            scheduler.turn(slow).now_or_never(),
+            Some(Err(Error::OutboxFull(_)))
+        ));
+        assert_eq!(scheduler.peer_len(slow), 2);
+
+        // other peer never waits for the slow one
+        let turn = scheduler.turn(other).now_or_never().unwrap().unwrap();
+        assert_eq!(scheduler.len(), 3);
+        drop(turn);
+
+        drop(first);
+        let turn = second.as_mut().now_or_never().unwrap().unwrap();
+        drop(turn);
+        assert!(scheduler.is_empty());
+
+        // cancelled waiting doesn't hold the turn, nor room in outbox
+        let first = scheduler.turn(slow).now_or_never().unwrap().unwrap();
+        let mut waiting = Box::pin(scheduler.turn(slow));
+        assert!(waiting.as_mut().now_or_never().is_none());
+        drop(waiting);
+        let mut waiting = Box::pin(scheduler.turn(slow));
+        assert!(waiting.as_mut().now_or_never().is_none());
+        drop(first);
+        assert!(waiting.as_mut().now_or_never().unwrap().is_ok());
+    }
+}
@@ -0,0 +1,258 @@
+//! Load-shedding of application messages when a node is overloaded.
+//!
+//! Payloads are handled one after another, so a flood of application messages would delay
+//! DHT maintenance until the ring falls apart. [OverloadGuard] watches depth of the queue of
+//! received payloads and share of time spent in handlers. While either is over its limit,
+//! application messages are dropped before they are handled, and their senders are told with
+//! [ServerBusy](crate::message::ServerBusy), at most once a window each, so shedding doesn't
+//! turn into a flood of its own. Messages maintaining DHT are always handled.
+//!
+//! Time of handlers is what they spend running, measured by [Timed] over polls of them, so
+//! handlers waiting for peers or timers don't count as busy.
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+
+use crate::dht::Did;
+use crate::message::Message;
+use crate::utils;
+
+/// Payloads waiting to be handled before application messages are shed, by default.
+pub const DEFAULT_MAX_QUEUE: usize = 1024;
+/// Percentage of time spent in handlers before application messages are shed, by default.
+pub const DEFAULT_CPU_BUDGET: u8 = 80;
+/// Length of window in which time spent in handlers is measured, in milliseconds.
+pub const BUDGET_WINDOW_MS: u128 = 1000;
+/// Peers answered [ServerBusy](crate::message::ServerBusy) in a window at most, others are
+/// not answered.
+const MAX_BUSY_PEERS: usize = 1024;
+
+/// Application messages, shed first under overload.
+pub fn is_sheddable(msg: &Message) -> bool {
+    matches!(
+        msg,
+        Message::CustomMessage(_) | Message::StreamFrame(_) | Message::RelayedData(_)
+    )
+}
+
+#[derive(Debug, Default)]
+struct Window {
+    start_ms: u128,
+    busy_us: u128,
+    /// Busy time of the last complete window.
+    last_busy_us: u128,
+}
+
+/// Future of `inner`, resolves to its output with microseconds spent in polls of it. Time it
+/// waits between polls is not counted.
+pub struct Timed<F> {
+    inner: Pin<Box<F>>,
+    busy_us: u128,
+}
+
+impl<F> Timed<F> {
+    /// Measure polls of `inner`.
+    pub fn new(inner: F) -> Self {
+        Self {
+            inner: Box::pin(inner),
+            busy_us: 0,
+        }
+    }
+}
+
+impl<F: Future> Future for Timed<F> {
+    type Output = (F::Output, u128);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let start = utils::get_epoch_us();
+        let poll = self.inner.as_mut().poll(cx);
+        self.busy_us += utils::get_epoch_us().saturating_sub(start);
+        let busy_us = self.busy_us;
+        poll.map(|output| (output, busy_us))
+    }
+}
+
+/// Decides whether application messages should be shed, see module doc.
+#[derive(Debug)]
+pub struct OverloadGuard {
+    max_queue: usize,
+    cpu_budget: u8,
+    window: Mutex<Window>,
+    shed: AtomicU64,
+    /// Peers answered busy, with time of the last answer.
+    busy_replies: Mutex<HashMap<Did, u128>>,
+}
+
+impl Default for OverloadGuard {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_QUEUE, DEFAULT_CPU_BUDGET)
+    }
+}
+
+impl OverloadGuard {
+    /// Shed when more than `max_queue` payloads wait, or handlers take more than `cpu_budget`
+    /// percent of time, 0 disables either check.
+    pub fn new(max_queue: usize, cpu_budget: u8) -> Self {
+        Self {
+            max_queue,
+            cpu_budget: cpu_budget.min(100),
+            window: Mutex::new(Window::default()),
+            shed: AtomicU64::new(0),
+            busy_replies: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Handlers of a payload ran for `busy_us` microseconds, see [Timed].
+    pub fn record(&self, busy_us: u128) {
+        self.record_at(busy_us, utils::get_epoch_ms())
+    }
+
+    fn record_at(&self, busy_us: u128, now: u128) {
+        if let Ok(mut w) = self.window.lock() {
+            w.roll(now);
+            w.busy_us += busy_us;
+        }
+    }
+
+    /// Node is overloaded with `queued` payloads waiting.
+    pub fn is_overloaded(&self, queued: usize) -> bool {
+        self.is_overloaded_at(queued, utils::get_epoch_ms())
+    }
+
+    fn is_overloaded_at(&self, queued: usize, now: u128) -> bool {
+        if self.max_queue > 0 && queued > self.max_queue {
+            return true;
+        }
+        if self.cpu_budget == 0 {
+            return false;
+        }
+        let busy = match self.window.lock() {
+            Ok(mut w) => {
+                w.roll(now);
+                w.busy_us.max(w.last_busy_us)
+            }
+            Err(_) => return false,
+        };
+        busy * 100 > BUDGET_WINDOW_MS * 1000 * self.cpu_budget as u128
+    }
+
+    /// Whether `msg` should be shed with `queued` payloads waiting, shed ones are counted.
+    pub fn should_shed(&self, msg: &Message, queued: usize) -> bool {
+        let shed = is_sheddable(msg) && self.is_overloaded(queued);
+        if shed {
+            self.shed.fetch_add(1, Ordering::Relaxed);
+        }
+        shed
+    }
+
+    /// Whether `peer` should be told a message of it is shed, true once a window for each.
+    pub fn should_reply_busy(&self, peer: Did) -> bool {
+        self.should_reply_busy_at(peer, utils::get_epoch_ms())
+    }
+
+    fn should_reply_busy_at(&self, peer: Did, now: u128) -> bool {
+        let mut replies = match self.busy_replies.lock() {
+            Ok(r) => r,
+            Err(_) => return false,
+        };
+        if matches!(replies.get(&peer), Some(t) if now.saturating_sub(*t) < BUDGET_WINDOW_MS) {
+            return false;
+        }
+        if replies.len() >= MAX_BUSY_PEERS {
+            replies.retain(|_, t| now.saturating_sub(*t) < BUDGET_WINDOW_MS);
+            if replies.len() >= MAX_BUSY_PEERS {
+                return false;
+            }
+        }
+        replies.insert(peer, now);
+        true
+    }
+
+    /// Count of messages shed so far.
+    pub fn shed_count(&self) -> u64 {
+        self.shed.load(Ordering::Relaxed)
+    }
+}
+
+impl Window {
+    fn roll(&mut self, now: u128) {
+        let elapsed = now.saturating_sub(self.start_ms);
+        if elapsed < BUDGET_WINDOW_MS {
+            return;
+        }
+        // a window passed without any handling means handlers are idle
+        self.last_busy_us = if elapsed < 2 * BUDGET_WINDOW_MS {
+            self.busy_us
+        } else {
+            0
+        };
+        self.busy_us = 0;
+        self.start_ms = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::HashStr;
+    use crate::ecc::SecretKey;
+    use crate::message::RelayedDataAck;
+
+    #[test]
+    fn test_overload_guard() {
+        let guard = OverloadGuard::new(10, 50);
+        let custom = Message::custom(b"hello", &None).unwrap();
+        let ack = Message::RelayedDataAck(RelayedDataAck {
+            seq: 1,
+            id: HashStr::new("id"),
+            tx_id: None,
+        });
+
+        assert!(!guard.is_overloaded_at(10, 0));
+        assert!(guard.is_overloaded_at(11, 0));
+        assert!(guard.should_shed(&custom, 11));
+        assert!(!guard.should_shed(&ack, 11));
+        assert_eq!(guard.shed_count(), 1);
+
+        // busy time over budget is overload, for the window and the next one
+        guard.record_at(400_000, 100);
+        assert!(!guard.is_overloaded_at(0, 500));
+        guard.record_at(200_000, 600);
+        assert!(guard.is_overloaded_at(0, 700));
+        assert!(guard.is_overloaded_at(0, 1500));
+        assert!(!guard.is_overloaded_at(0, 2600));
+
+        let disabled = OverloadGuard::new(0, 0);
+        disabled.record_at(10_000_000, 100);
+        assert!(!disabled.is_overloaded_at(usize::MAX, 200));
+    }
+
+    #[test]
+    fn test_busy_replies() {
+        let guard = OverloadGuard::default();
+        let peer: Did = SecretKey::random().address().into();
+        assert!(guard.should_reply_busy_at(peer, 100));
+        assert!(!guard.should_reply_busy_at(peer, 500));
+        assert!(guard.should_reply_busy_at(SecretKey::random().address().into(), 500));
+        assert!(guard.should_reply_busy_at(peer, 100 + BUDGET_WINDOW_MS));
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    #[tokio::test]
+    async fn test_timed() {
+        let timed = Timed::new(async {
+            // waiting for a timer is not counted, running is
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            1
+        });
+        let (output, busy_us) = timed.await;
+        assert_eq!(output, 1);
+        assert!((5000..50_000).contains(&busy_us));
+    }
+}
@@ -0,0 +1,145 @@
+//! Peer exchange, connected peers share their connected peers.
+//!
+//! Every round of stabilization a node sends [PeerExchange](crate::message::PeerExchange) to a
+//! random connected peer, with at most [DEFAULT_PEX_PEERS] of its other connected peers and
+//! hints of how to reach them. Unlike samples of [crate::gossip], which may come from anywhere,
+//! peers exchanged are connected to sender right now, so receiver dials them through sender,
+//! which forwards the offer on its direct link.
+//!
+//! Receiver dials at most [DIALS_PER_EXCHANGE] of them at once, relay capable and nearer ones
+//! first, while it has fewer connected peers than max connections of its swarm, see
+//! [SwarmBuilder::with_max_connections](crate::swarm::SwarmBuilder::with_max_connections). So a
+//! mesh forms faster and with more redundant links than by ring maintenance alone.
+//!
+//! Max connections caps every connection a node keeps, not only ones of peer exchange. Ring
+//! neighbours, that is successors, predecessor and fingers, are always kept, and each round of
+//! stabilization closes other connections over the cap, slowest ones first, see
+//! [choose_prunes]. Nearness is RTT measured by ICE of the transport, which clocks of peers
+//! don't skew.
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::dht::Did;
+
+/// Peers sent in one exchange.
+pub const DEFAULT_PEX_PEERS: usize = 16;
+/// Connections kept at most, ring neighbours are kept even over it. Peers of exchanges are
+/// only dialed while fewer are connected.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 32;
+/// Peers dialed on receiving one exchange.
+pub const DIALS_PER_EXCHANGE: usize = 2;
+
+/// A connected peer of sender, with hints of its reachability.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PexPeer {
+    pub did: Did,
+    /// Peer advertised itself as relay capable in handshake, so it has a public address.
+    pub relay: bool,
+    /// Smoothed RTT between sender and peer, in milliseconds, if it's measured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtt_ms: Option<u64>,
+}
+
+/// At most `limit` random peers of `peers` to share with `receiver`, which is left out.
+pub fn share(mut peers: Vec<PexPeer>, receiver: Did, limit: usize) -> Vec<PexPeer> {
+    peers.retain(|p| p.did != receiver);
+    peers.shuffle(&mut rand::thread_rng());
+    peers.truncate(limit);
+    peers
+}
+
+/// Peers of `peers` to dial by `local`, at most `slots` of them, relay capable ones first,
+/// then ones nearer to sender. Peers `connected` already are skipped.
+pub fn choose_dials<F>(local: Did, peers: &[PexPeer], connected: F, slots: usize) -> Vec<Did>
+where F: Fn(Did) -> bool {
+    let mut candidates = peers
+        .iter()
+        .filter(|p| p.did != local && !connected(p.did))
+        .collect::<Vec<_>>();
+    candidates.sort_by_key(|p| (!p.relay, p.rtt_ms.unwrap_or(u64::MAX)));
+    candidates
+        .into_iter()
+        .take(slots.min(DIALS_PER_EXCHANGE))
+        .map(|p| p.did)
+        .collect()
+}
+
+/// Connected `peers` with their RTT to close so at most `max` are left, except ones to
+/// `keep`. Slowest ones are closed first, and ones not measured before them.
+pub fn choose_prunes<F>(peers: &[(Did, Option<u64>)], keep: F, max: usize) -> Vec<Did>
+where F: Fn(Did) -> bool {
+    let surplus = peers.len().saturating_sub(max);
+    let mut candidates = peers
+        .iter()
+        .filter(|(did, _)| !keep(*did))
+        .collect::<Vec<_>>();
+    candidates.sort_by_key(|(_, rtt_ms)| std::cmp::Reverse(rtt_ms.unwrap_or(u64::MAX)));
+    candidates
+        .into_iter()
+        .take(surplus)
+        .map(|(did, _)| *did)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    fn peer(relay: bool, rtt_ms: Option<u64>) -> PexPeer {
+        PexPeer {
+            did: SecretKey::random().address().into(),
+            relay,
+            rtt_ms,
+        }
+    }
+
+    #[test]
+    fn test_choose_dials() {
+        let local: Did = SecretKey::random().address().into();
+        let far = peer(false, Some(300));
+        let near = peer(false, Some(20));
+        let unknown = peer(false, None);
+        let relay = peer(true, Some(500));
+        let connected = peer(true, Some(1));
+        let mut me = peer(true, Some(1));
+        me.did = local;
+        let peers = vec![
+            far.clone(),
+            unknown.clone(),
+            near.clone(),
+            relay.clone(),
+            connected.clone(),
+            me,
+        ];
+        let is_connected = |did| did == connected.did;
+
+        assert_eq!(choose_dials(local, &peers, is_connected, 8), vec![
+            relay.did, near.did
+        ]);
+        assert_eq!(choose_dials(local, &peers, is_connected, 1), vec![
+            relay.did
+        ]);
+        assert!(choose_dials(local, &peers, is_connected, 0).is_empty());
+
+        let shared = share(peers, far.did, 3);
+        assert_eq!(shared.len(), 3);
+        assert!(!shared.contains(&far));
+    }
+
+    #[test]
+    fn test_choose_prunes() {
+        let [neighbour, slow, fast, unknown] = [(); 4].map(|_| peer(false, None).did);
+        let peers = vec![
+            (neighbour, Some(900)),
+            (slow, Some(300)),
+            (fast, Some(20)),
+            (unknown, None),
+        ];
+        let keep = |did| did == neighbour;
+        assert_eq!(choose_prunes(&peers, keep, 2), vec![unknown, slow]);
+        assert_eq!(choose_prunes(&peers, keep, 0), vec![unknown, slow, fast]);
+        assert!(choose_prunes(&peers, keep, 4).is_empty());
+    }
+}
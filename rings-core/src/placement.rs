@@ -0,0 +1,277 @@
+//! Placement of virtual nodes stored by this node on DHT.
+//!
+//! A node storing a virtual node answers [StoreVNodeReport](crate::message::StoreVNodeReport)
+//! to publisher, with its DID and its successors, which take over the virtual node when it
+//! leaves. Only reports of the node a store is sent to are accepted, others could be forged
+//! by any node; a store forwarded on is placed by a retry instead, which looks up the owner
+//! again. [StoreTracker] keeps virtual nodes published recently with their placements, so
+//! publisher can check where they are, and store them again when:
+//! - no report arrives within [ACK_TIMEOUT_MS], at most [MAX_ATTEMPTS] times,
+//! - or the node storing them leaves the ring, see [StoreTracker::owner_left],
+//! - or it fails a challenge of [crate::audit], see [StoreTracker::store_again].
+//!
+//! Virtual nodes rejected by [StoreVNodeDenied](crate::message::StoreVNodeDenied) are not
+//! stored again.
+//!
+//! Stores are retried by stabilization, which looks up the current owner again.
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::dht::vnode::VirtualNode;
+use crate::dht::Did;
+use crate::utils;
+
+/// Store is retried if it's not reported in this long, in milliseconds.
+pub const ACK_TIMEOUT_MS: u128 = 10 * 1000;
+/// Stores given up after this many attempts without report.
+pub const MAX_ATTEMPTS: u32 = 3;
+/// Virtual nodes tracked, the ones published earliest are forgotten first.
+pub const MAX_TRACKED: usize = 1024;
+
+/// Where a virtual node is stored, by report of the node storing it.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    pub stored_by: Did,
+    /// Successors of `stored_by` when it's stored.
+    pub replicas: Vec<Did>,
+    /// When report is received, in milliseconds since epoch.
+    pub acked_ms: u128,
+}
+
+#[derive(Debug)]
+struct Tracked {
+    vnode: VirtualNode,
+    /// Node the store is sent to, the only one whose report is accepted.
+    sent_to: Did,
+    sent_ms: u128,
+    attempts: u32,
+    placement: Option<Placement>,
+}
+
+/// Virtual nodes published by this node and their placements, see module doc.
+#[derive(Debug, Default)]
+pub struct StoreTracker {
+    tracked: Mutex<HashMap<Did, Tracked>>,
+}
+
+impl StoreTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `vnode` is sent to `sent_to` to be stored.
+    pub fn track(&self, vnode: VirtualNode, sent_to: Did) {
+        self.track_at(vnode, sent_to, utils::get_epoch_ms())
+    }
+
+    fn track_at(&self, vnode: VirtualNode, sent_to: Did, now: u128) {
+        let mut tracked = match self.tracked.lock() {
+            Ok(t) => t,
+            Err(_) => return,
+        };
+        let id = vnode.did();
+        // retry of the same data counts, new data starts over
+        let attempts = match tracked.get(&id) {
+            Some(t) if t.placement.is_none() && t.vnode == vnode => t.attempts + 1,
+            _ => 1,
+        };
+        tracked.insert(id, Tracked {
+            vnode,
+            sent_to,
+            sent_ms: now,
+            attempts,
+            placement: None,
+        });
+        while tracked.len() > MAX_TRACKED {
+            let oldest = tracked
+                .iter()
+                .min_by_key(|(_, t)| t.sent_ms)
+                .map(|(id, _)| *id);
+            match oldest {
+                Some(id) => tracked.remove(&id),
+                None => break,
+            };
+        }
+    }
+
+    /// Accept report of `ids` stored by `stored_by`, returns virtual nodes placed.
+    /// Virtual nodes not tracked, placed already, or not sent to `stored_by`, are ignored.
+    pub fn ack(&self, stored_by: Did, ids: &[Did], replicas: &[Did]) -> Vec<Did> {
+        self.ack_at(stored_by, ids, replicas, utils::get_epoch_ms())
+    }
+
+    /// Record virtual node `id` is stored by this node itself, as `local`.
+    pub fn stored_locally(&self, local: Did, id: Did, replicas: &[Did]) {
+        if let Ok(mut tracked) = self.tracked.lock() {
+            if let Some(t) = tracked.get_mut(&id) {
+                t.sent_to = local;
+            }
+        }
+        self.ack(local, &[id], replicas);
+    }
+
+    fn ack_at(&self, stored_by: Did, ids: &[Did], replicas: &[Did], now: u128) -> Vec<Did> {
+        let mut tracked = match self.tracked.lock() {
+            Ok(t) => t,
+            Err(_) => return vec![],
+        };
+        let mut placed = vec![];
+        for id in ids {
+            if let Some(t) = tracked
+                .get_mut(id)
+                .filter(|t| t.placement.is_none() && t.sent_to == stored_by)
+            {
+                t.placement = Some(Placement {
+                    stored_by,
+                    replicas: replicas.to_vec(),
+                    acked_ms: now,
+                });
+                placed.push(*id);
+            }
+        }
+        placed
+    }
+
+    /// Placement of virtual node `id`, None if it's not reported yet.
+    pub fn placement(&self, id: Did) -> Option<Placement> {
+        self.tracked
+            .lock()
+            .ok()?
+            .get(&id)
+            .and_then(|t| t.placement.clone())
+    }
+
+    /// Forget `ids` rejected by the node which would store them, they are not retried.
+    pub fn denied(&self, ids: &[Did]) {
+        if let Ok(mut tracked) = self.tracked.lock() {
+            for id in ids {
+                tracked.remove(id);
+            }
+        }
+    }
+
+    /// Virtual nodes stored by `did` are stored again on next retry, since it left the ring.
+    pub fn owner_left(&self, did: Did) {
+        let ids = match self.tracked.lock() {
+            Ok(tracked) => tracked
+                .iter()
+                .filter(|(_, t)| t.placement.as_ref().map(|p| p.stored_by) == Some(did))
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>(),
+            Err(_) => return,
+        };
+        self.store_again(&ids)
+    }
+
+    /// Placed virtual nodes `ids` are stored again on next retry, since their owner may have
+    /// dropped them.
+    pub fn store_again(&self, ids: &[Did]) {
+        if let Ok(mut tracked) = self.tracked.lock() {
+            for id in ids {
+                if let Some(t) = tracked.get_mut(id).filter(|t| t.placement.is_some()) {
+                    t.placement = None;
+                    t.sent_ms = 0;
+                    t.attempts = 0;
+                }
+            }
+        }
+    }
+
+    /// Virtual nodes placed, with node storing each of them.
+    pub fn placed(&self) -> Vec<(VirtualNode, Did)> {
+        match self.tracked.lock() {
+            Ok(tracked) => tracked
+                .values()
+                .filter_map(|t| Some((t.vnode.clone(), t.placement.as_ref()?.stored_by)))
+                .collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    /// Virtual nodes to store again, ones given up are forgotten.
+    pub fn due(&self) -> Vec<VirtualNode> {
+        self.due_at(utils::get_epoch_ms())
+    }
+
+    fn due_at(&self, now: u128) -> Vec<VirtualNode> {
+        let mut tracked = match self.tracked.lock() {
+            Ok(t) => t,
+            Err(_) => return vec![],
+        };
+        let overdue =
+            |t: &Tracked| t.placement.is_none() && now - t.sent_ms.min(now) >= ACK_TIMEOUT_MS;
+        tracked.retain(|id, t| {
+            let give_up = overdue(t) && t.attempts >= MAX_ATTEMPTS;
+            if give_up {
+                tracing::warn!(vnode = ?id, "no node reported storing vnode, give up");
+            }
+            !give_up
+        });
+        tracked
+            .values()
+            .filter(|t| overdue(t))
+            .map(|t| t.vnode.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    #[test]
+    fn test_store_tracker() {
+        let tracker = StoreTracker::new();
+        let vnode: VirtualNode = "hello".to_owned().try_into().unwrap();
+        let id = vnode.did();
+        let owner: Did = SecretKey::random().address().into();
+        let replica: Did = SecretKey::random().address().into();
+
+        tracker.track_at(vnode.clone(), owner, 1_000);
+        assert!(tracker.due_at(1_000 + ACK_TIMEOUT_MS - 1).is_empty());
+        assert_eq!(tracker.due_at(1_000 + ACK_TIMEOUT_MS), vec![vnode.clone()]);
+
+        // report of another vnode is ignored
+        assert!(tracker.ack_at(owner, &[owner], &[], 2_000).is_empty());
+        // report of a node the store is not sent to is ignored
+        assert!(tracker.ack_at(replica, &[id], &[], 2_000).is_empty());
+        assert_eq!(tracker.ack_at(owner, &[id], &[replica], 2_000), vec![id]);
+        assert_eq!(
+            tracker.placement(id),
+            Some(Placement {
+                stored_by: owner,
+                replicas: vec![replica],
+                acked_ms: 2_000,
+            })
+        );
+        assert!(tracker.due_at(100_000).is_empty());
+        assert_eq!(tracker.placed(), vec![(vnode.clone(), owner)]);
+
+        // challenge failed, store it again at once
+        tracker.store_again(&[id]);
+        assert!(tracker.placed().is_empty());
+        assert_eq!(tracker.due_at(ACK_TIMEOUT_MS), vec![vnode.clone()]);
+        tracker.ack_at(owner, &[id], &[replica], 3_000);
+
+        // owner left, store it again at once
+        tracker.owner_left(owner);
+        assert_eq!(tracker.placement(id), None);
+        assert_eq!(tracker.due_at(ACK_TIMEOUT_MS), vec![vnode.clone()]);
+
+        // given up after attempts without report
+        for i in 0..MAX_ATTEMPTS as u128 {
+            tracker.track_at(vnode.clone(), owner, 10_000 + i);
+        }
+        assert!(tracker.due_at(100_000).is_empty());
+        assert_eq!(tracker.placement(id), None);
+
+        // denied ones are not retried
+        tracker.track_at(vnode.clone(), owner, 1_000);
+        tracker.denied(&[id]);
+        assert!(tracker.due_at(100_000).is_empty());
+    }
+}
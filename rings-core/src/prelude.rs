@@ -11,8 +11,8 @@ pub use uuid;
 pub use wasm_bindgen;
 #[cfg(feature = "wasm")]
 pub use wasm_bindgen_futures;
+#[cfg(feature = "web3")]
 pub use web3;
-pub use web3::types::Address;
 #[cfg(feature = "wasm")]
 pub use web_sys;
 #[cfg(feature = "wasm")]
@@ -22,4 +22,5 @@ pub use webrtc;
 #[cfg(not(feature = "wasm"))]
 pub use webrtc::peer_connection::sdp::sdp_type::RTCSdpType;
 
+pub use crate::address::Address;
 pub use crate::transports::Transport;
@@ -0,0 +1,264 @@
+//! Presence of DIDs, published to DHT by stabilization.
+//!
+//! Every node stores a signed [PresenceRecord] at [VirtualNode::presence_address] of itself,
+//! which says "I'm online via node X" and expires after `ttl_ms`, so a node which stops
+//! refreshing disappears by itself. [PresenceTracker] keeps states of tracked DIDs, and pushes
+//! [PresenceEvent] to subscribers when a tracked DID appears or disappears.
+use std::sync::Mutex;
+
+use dashmap::DashMap;
+use futures::channel::mpsc;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::dht::vnode::VNodeType;
+use crate::dht::vnode::VirtualNode;
+use crate::dht::Did;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::Decoder;
+use crate::message::Encoder;
+use crate::message::MessageVerification;
+use crate::session::SessionManager;
+use crate::utils;
+
+/// How long a presence record is valid, refreshed by every round of stabilization.
+pub const DEFAULT_PRESENCE_TTL_MS: usize = 3 * 60 * 1000;
+
+/// Content of presence record.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Presence {
+    pub did: Did,
+    /// A node directly connected to `did`, which relays messages to it.
+    pub via: Did,
+}
+
+/// Presence signed by session of `did`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PresenceRecord {
+    pub presence: Presence,
+    pub verification: MessageVerification,
+}
+
+impl PresenceRecord {
+    pub fn new(session_manager: &SessionManager, via: Did, ttl_ms: usize) -> Result<Self> {
+        let presence = Presence {
+            did: session_manager.authorizer()?.into(),
+            via,
+        };
+        let ts_ms = utils::get_epoch_ms();
+        let msg = MessageVerification::pack_msg(&presence, ts_ms, ttl_ms)?;
+        let verification = MessageVerification {
+            session: session_manager.session()?,
+            sig: session_manager.sign(&msg)?,
+            ttl_ms,
+            ts_ms,
+        };
+        Ok(Self {
+            presence,
+            verification,
+        })
+    }
+
+    /// When record expires, in milliseconds since epoch.
+    pub fn expires_ms(&self) -> u128 {
+        self.verification.ts_ms + self.verification.ttl_ms as u128
+    }
+
+    pub fn is_expired(&self) -> bool {
+        utils::get_epoch_ms() > self.expires_ms()
+    }
+
+    /// Check signature, and record is signed by `did` itself.
+    pub fn verify(&self) -> bool {
+        Did::from(self.verification.session.auth.authorizer) == self.presence.did
+            && self.verification.verify(&self.presence)
+    }
+
+    /// Record is valid and not expired.
+    pub fn is_online(&self) -> bool {
+        !self.is_expired() && self.verify()
+    }
+
+    pub fn to_vnode(&self) -> Result<VirtualNode> {
+        let data = serde_json::to_string(self)
+            .map_err(Error::Serialize)?
+            .encode()?;
+        Ok(VirtualNode {
+            address: VirtualNode::presence_address(self.presence.did)?,
+            data: vec![data],
+            kind: VNodeType::Presence,
+        })
+    }
+
+    pub fn from_vnode(vnode: &VirtualNode) -> Result<Self> {
+        if vnode.kind != VNodeType::Presence {
+            return Err(Error::InvalidVNodeType);
+        }
+        let encoded = vnode.data.first().ok_or(Error::InvalidVNodeType)?;
+        let s = String::from_encoded(encoded)?;
+        serde_json::from_str(&s).map_err(Error::Deserialize)
+    }
+
+    /// Merge stored presence vnode `a` with incoming `b`, keeps `b` only if it's a valid
+    /// record newer than `a`, so a forged record can't hide a node.
+    pub(crate) fn merge(a: &VirtualNode, b: &VirtualNode) -> Result<VirtualNode> {
+        if a.address != b.address {
+            return Err(Error::AddressNotEqual);
+        }
+        let incoming = match Self::from_vnode(b) {
+            Ok(r) if r.verify() => r,
+            _ => return Ok(a.clone()),
+        };
+        match Self::from_vnode(a) {
+            Ok(r) if r.verify() && r.verification.ts_ms > incoming.verification.ts_ms => {
+                Ok(a.clone())
+            }
+            _ => Ok(b.clone()),
+        }
+    }
+}
+
+/// A tracked DID appeared or disappeared.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PresenceEvent {
+    pub did: Did,
+    pub online: bool,
+    /// Latest record, None if it's never found.
+    pub record: Option<PresenceRecord>,
+}
+
+/// Tracked DIDs and subscribers of their [PresenceEvent].
+#[derive(Default)]
+pub struct PresenceTracker {
+    /// Last known state of tracked DIDs.
+    tracked: DashMap<Did, bool>,
+    subscribers: Mutex<Vec<mpsc::UnboundedSender<PresenceEvent>>>,
+}
+
+impl PresenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Track a DID, assumed offline until its record is found.
+    pub fn track(&self, did: Did) {
+        self.tracked.entry(did).or_insert(false);
+    }
+
+    pub fn untrack(&self, did: &Did) {
+        self.tracked.remove(did);
+    }
+
+    /// Tracked DIDs with their last known state.
+    pub fn tracked(&self) -> Vec<(Did, bool)> {
+        self.tracked
+            .iter()
+            .map(|kv| (*kv.key(), *kv.value()))
+            .collect()
+    }
+
+    /// Receive events of all tracked DIDs, drop the receiver to unsubscribe.
+    pub fn subscribe(&self) -> mpsc::UnboundedReceiver<PresenceEvent> {
+        let (tx, rx) = mpsc::unbounded();
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(tx);
+        }
+        rx
+    }
+
+    /// Update state of a tracked DID with its latest record, and notify subscribers if it
+    /// changed. Untracked DIDs are ignored.
+    pub fn update(&self, did: Did, record: Option<PresenceRecord>) {
+        let online = record.as_ref().map_or(false, |r| r.is_online());
+        let changed = match self.tracked.get_mut(&did) {
+            Some(mut state) if *state != online => {
+                *state = online;
+                true
+            }
+            _ => false,
+        };
+        if !changed {
+            return;
+        }
+        tracing::info!(did = ?did, online, "presence changed");
+        let event = PresenceEvent {
+            did,
+            online,
+            record,
+        };
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.retain(|s| s.unbounded_send(event.clone()).is_ok());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    #[test]
+    fn test_presence_record() {
+        let key = SecretKey::random();
+        let session = SessionManager::new_with_seckey(&key).unwrap();
+        let via: Did = SecretKey::random().address().into();
+        let record = PresenceRecord::new(&session, via, DEFAULT_PRESENCE_TTL_MS).unwrap();
+        assert_eq!(record.presence.did, key.address().into());
+        assert!(record.is_online());
+
+        let vnode = record.to_vnode().unwrap();
+        assert_eq!(
+            vnode.address,
+            VirtualNode::presence_address(key.address().into()).unwrap()
+        );
+        assert_eq!(PresenceRecord::from_vnode(&vnode).unwrap(), record);
+
+        // claim presence of others
+        let mut forged = record.clone();
+        forged.presence.did = via;
+        assert!(!forged.verify());
+
+        // forged or older record can't overwrite stored one
+        let mut forged_vnode = forged.to_vnode().unwrap();
+        forged_vnode.address = vnode.address;
+        assert_eq!(PresenceRecord::merge(&vnode, &forged_vnode).unwrap(), vnode);
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let newer = PresenceRecord::new(&session, via, DEFAULT_PRESENCE_TTL_MS)
+            .unwrap()
+            .to_vnode()
+            .unwrap();
+        assert_eq!(PresenceRecord::merge(&newer, &vnode).unwrap(), newer);
+        assert_eq!(PresenceRecord::merge(&vnode, &newer).unwrap(), newer);
+
+        let expired = PresenceRecord::new(&session, via, 0).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert!(!expired.is_online());
+    }
+
+    #[test]
+    fn test_presence_tracker() {
+        let key = SecretKey::random();
+        let session = SessionManager::new_with_seckey(&key).unwrap();
+        let did: Did = key.address().into();
+        let record = PresenceRecord::new(&session, did, DEFAULT_PRESENCE_TTL_MS).unwrap();
+
+        let tracker = PresenceTracker::new();
+        let mut rx = tracker.subscribe();
+        // untracked
+        tracker.update(did, Some(record.clone()));
+        assert!(rx.try_next().is_err());
+
+        tracker.track(did);
+        tracker.update(did, Some(record.clone()));
+        let event = rx.try_next().unwrap().unwrap();
+        assert!(event.online);
+        // not changed
+        tracker.update(did, Some(record));
+        assert!(rx.try_next().is_err());
+
+        tracker.update(did, None);
+        assert!(!rx.try_next().unwrap().unwrap().online);
+        assert_eq!(tracker.tracked(), vec![(did, false)]);
+    }
+}
@@ -0,0 +1,176 @@
+//! Self-signed public keys of DIDs, published to DHT by stabilization.
+//!
+//! Every node stores a signed [PubkeyRecord] at [VirtualNode::pubkey_address] of itself, which
+//! tells its encryption key. Messages are encrypted to it before any direct contact, even while
+//! the DID is offline, see [Message::custom_to]. The record is signed by session of the DID and
+//! by the key it tells, so nobody else can make messages encrypted to their key, and it's only
+//! stored at the address of its own DID.
+//!
+//! Nodes publish a long-lived key, [derive_encryption_key] of their identity key, so messages
+//! sent while they are offline are still read after they restart. Without one, the key of
+//! session is published, see [crate::swarm::Swarm::encryption_key].
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::address::keccak256;
+use crate::dht::vnode::VNodeType;
+use crate::dht::vnode::VirtualNode;
+use crate::dht::Did;
+use crate::ecc::PublicKey;
+use crate::ecc::SecretKey;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::Message;
+use crate::record::RecordData;
+use crate::record::RecordSigner;
+use crate::record::SignedRecord;
+use crate::session::SessionManager;
+
+/// How long a pubkey record is valid, refreshed by every round of stabilization.
+pub const DEFAULT_PUBKEY_TTL_MS: usize = 60 * 60 * 1000;
+/// Domain of encryption key derived from identity key, keeps it apart from signing.
+const ENCRYPTION_KEY_DOMAIN: &[u8] = b"rings-encryption-v1";
+
+/// Long-lived encryption key derived from identity `key`, the same on every start.
+pub fn derive_encryption_key(key: &SecretKey) -> Result<SecretKey> {
+    let mut material = ENCRYPTION_KEY_DOMAIN.to_vec();
+    material.extend_from_slice(&key.serialize());
+    libsecp256k1::SecretKey::parse(&keccak256(&material))
+        .map(Into::into)
+        .map_err(|e| Error::Libsecp256k1SecretKeyParse(format!("{:?}", e)))
+}
+
+/// Encryption key of `did`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct DidPubkey {
+    pub did: Did,
+    pub pubkey: PublicKey,
+}
+
+/// Pubkey signed by session of `did`, and by the key itself, which proves it's held by `did`.
+pub type PubkeyRecord = SignedRecord<DidPubkey>;
+
+impl RecordData for DidPubkey {
+    const KIND: VNodeType = VNodeType::Pubkey;
+
+    fn address(&self) -> Result<Did> {
+        VirtualNode::pubkey_address(self.did)
+    }
+
+    fn signers(&self) -> Vec<RecordSigner> {
+        vec![
+            RecordSigner::Session(self.did),
+            RecordSigner::Key(self.pubkey.address().into()),
+        ]
+    }
+
+    fn invalid() -> Error {
+        Error::InvalidPubkeyRecord
+    }
+}
+
+impl PubkeyRecord {
+    /// Record of `encryption_key`, signed by session of `session_manager`.
+    pub fn new(
+        session_manager: &SessionManager,
+        encryption_key: &SecretKey,
+        ttl_ms: usize,
+    ) -> Result<Self> {
+        let pubkey = DidPubkey {
+            did: session_manager.authorizer()?.into(),
+            pubkey: encryption_key.pubkey(),
+        };
+        Self::sign(pubkey, ttl_ms, Some(session_manager), &[*encryption_key])
+    }
+}
+
+/// Finds public keys of DIDs to encrypt messages to, usually from their [PubkeyRecord]s.
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+pub trait PubkeyResolver {
+    /// Public key of `did`, or [Error::PubkeyNotFound].
+    async fn resolve_pubkey(&self, did: Did) -> Result<PublicKey>;
+}
+
+impl Message {
+    /// Custom message encrypted to `did`, with its key found by `resolver`.
+    pub async fn custom_to<R>(msg: &[u8], did: Did, resolver: &R) -> Result<Message>
+    where R: PubkeyResolver + ?Sized {
+        let pubkey = resolver.resolve_pubkey(did).await?;
+        Message::custom(msg, &Some(pubkey))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    struct RecordResolver(Vec<PubkeyRecord>);
+
+    #[async_trait]
+    impl PubkeyResolver for RecordResolver {
+        async fn resolve_pubkey(&self, did: Did) -> Result<PublicKey> {
+            self.0
+                .iter()
+                .find(|r| r.data.did == did && r.is_valid())
+                .map(|r| r.data.pubkey)
+                .ok_or_else(|| Error::PubkeyNotFound(format!("{:?}", *did)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pubkey_record() {
+        let key = SecretKey::random();
+        let session = SessionManager::new_with_seckey(&key).unwrap();
+        let did: Did = key.address().into();
+        let encryption_key = derive_encryption_key(&key).unwrap();
+        assert_eq!(derive_encryption_key(&key).unwrap(), encryption_key);
+        let record = PubkeyRecord::new(&session, &encryption_key, DEFAULT_PUBKEY_TTL_MS).unwrap();
+        assert!(record.is_valid());
+
+        let vnode = record.to_vnode().unwrap();
+        assert_eq!(vnode.address, VirtualNode::pubkey_address(did).unwrap());
+        assert_eq!(PubkeyRecord::from_vnode(&vnode).unwrap(), record);
+        assert!(vnode.check().is_ok());
+
+        // key of others can't be claimed
+        let mut forged = record.clone();
+        forged.data.pubkey = SecretKey::random().pubkey();
+        assert!(!forged.verify());
+        let forged_vnode = forged.to_vnode().unwrap();
+        assert!(PubkeyRecord::merge(&vnode, &forged_vnode).is_err());
+
+        // record of another DID can't be stored at address of did
+        let other = SecretKey::random();
+        let other_session = SessionManager::new_with_seckey(&other).unwrap();
+        let mut squatting = PubkeyRecord::new(&other_session, &other, DEFAULT_PUBKEY_TTL_MS)
+            .unwrap()
+            .to_vnode()
+            .unwrap();
+        squatting.address = vnode.address;
+        assert!(squatting.check().is_err());
+        assert!(PubkeyRecord::merge(&vnode, &squatting).is_err());
+
+        // expired records are refused
+        let expired = PubkeyRecord::new(&session, &encryption_key, 0).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert!(expired.to_vnode().unwrap().check().is_err());
+
+        // message encrypted to resolved key is read by holder of encryption key, in any session
+        let resolver = RecordResolver(vec![record]);
+        let msg = Message::custom_to(b"hello", did, &resolver).await.unwrap();
+        let (plain, encrypted) = match msg {
+            Message::CustomMessage(m) => m.decrypt(&encryption_key).unwrap(),
+            _ => panic!("Unexpected message type"),
+        };
+        assert_eq!(plain.0, b"hello".to_vec());
+        assert!(encrypted);
+
+        let unknown: Did = SecretKey::random().address().into();
+        assert!(Message::custom_to(b"hello", unknown, &resolver)
+            .await
+            .is_err());
+    }
+}
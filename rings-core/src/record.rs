@@ -0,0 +1,216 @@
+//! Records signed by their owners, stored in DHT at addresses of their own.
+//!
+//! A [SignedRecord] carries its data, like a [NodeManifest](crate::manifest::NodeManifest),
+//! with signatures of everyone [RecordData::signers] names over it. A signer signs by its
+//! session, or by its key when the record should outlive sessions. Records are stored as
+//! vnodes of [RecordData::KIND] at [RecordData::address], and a stored one is only replaced by
+//! a valid record it [allows](RecordData::replaces), so nobody else can overwrite it.
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::dht::vnode::VNodeType;
+use crate::dht::vnode::VirtualNode;
+use crate::dht::Did;
+use crate::ecc::signers;
+use crate::ecc::PublicKey;
+use crate::ecc::SecretKey;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::Decoder;
+use crate::message::Encoder;
+use crate::message::MessageVerification;
+use crate::session::Session;
+use crate::session::SessionManager;
+use crate::utils;
+
+/// Who signs a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordSigner {
+    /// Session authorized by the DID.
+    Session(Did),
+    /// Key of the DID itself.
+    Key(Did),
+}
+
+/// Signature of a record, by session if it's given, otherwise by key of signer.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RecordSig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<Session>,
+    pub sig: Vec<u8>,
+}
+
+/// Data of a [SignedRecord].
+pub trait RecordData: Serialize + DeserializeOwned + Clone {
+    /// Kind of vnode record is stored as.
+    const KIND: VNodeType;
+
+    /// Address record is stored at.
+    fn address(&self) -> Result<Did>;
+
+    /// Signers of record, in order of its signatures.
+    fn signers(&self) -> Vec<RecordSigner>;
+
+    /// Error of a record failing checks.
+    fn invalid() -> Error;
+
+    /// Checks of data besides signatures.
+    fn check(&self) -> bool {
+        true
+    }
+
+    /// Whether valid `incoming` record replaces valid `stored` one, the newer one by default.
+    fn replaces(stored: &SignedRecord<Self>, incoming: &SignedRecord<Self>) -> bool {
+        incoming.ts_ms > stored.ts_ms
+    }
+}
+
+/// Data signed by its signers, see module doc.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SignedRecord<T> {
+    pub data: T,
+    pub ts_ms: u128,
+    pub ttl_ms: usize,
+    pub sigs: Vec<RecordSig>,
+}
+
+impl<T: RecordData> SignedRecord<T> {
+    /// Sign `data` by session of `session_manager` and `keys`, each signer of data should be
+    /// one of them.
+    pub fn sign(
+        data: T,
+        ttl_ms: usize,
+        session_manager: Option<&SessionManager>,
+        keys: &[SecretKey],
+    ) -> Result<Self> {
+        let ts_ms = utils::get_epoch_ms();
+        let msg = MessageVerification::pack_msg(&data, ts_ms, ttl_ms)?;
+        let sigs = data
+            .signers()
+            .into_iter()
+            .map(|signer| match signer {
+                RecordSigner::Session(did) => match session_manager {
+                    Some(s) if Did::from(s.authorizer()?) == did => Ok(RecordSig {
+                        session: Some(s.session()?),
+                        sig: s.sign(&msg)?,
+                    }),
+                    _ => Err(T::invalid()),
+                },
+                RecordSigner::Key(did) => keys
+                    .iter()
+                    .find(|k| Did::from(k.address()) == did)
+                    .map(|k| RecordSig {
+                        session: None,
+                        sig: signers::default::sign_raw(*k, &msg).to_vec(),
+                    })
+                    .ok_or_else(T::invalid),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let record = Self {
+            data,
+            ts_ms,
+            ttl_ms,
+            sigs,
+        };
+        if !record.verify() {
+            return Err(T::invalid());
+        }
+        Ok(record)
+    }
+
+    fn msg(&self) -> Result<String> {
+        MessageVerification::pack_msg(&self.data, self.ts_ms, self.ttl_ms)
+    }
+
+    fn verification(&self, sig: &RecordSig) -> Option<MessageVerification> {
+        Some(MessageVerification {
+            session: sig.session.clone()?,
+            ttl_ms: self.ttl_ms,
+            ts_ms: self.ts_ms,
+            sig: sig.sig.clone(),
+        })
+    }
+
+    /// When record expires, in milliseconds since epoch.
+    pub fn expires_ms(&self) -> u128 {
+        self.ts_ms + self.ttl_ms as u128
+    }
+
+    /// Check data, and it's signed by all its signers.
+    pub fn verify(&self) -> bool {
+        let signers = self.data.signers();
+        let msg = match self.msg() {
+            Ok(msg) => msg,
+            Err(_) => return false,
+        };
+        self.data.check()
+            && !signers.is_empty()
+            && signers.len() == self.sigs.len()
+            && signers.iter().zip(self.sigs.iter()).all(|(signer, sig)| {
+                match (signer, self.verification(sig)) {
+                    (RecordSigner::Session(did), Some(v)) => {
+                        Did::from(v.session.auth.authorizer) == *did && v.verify(&self.data)
+                    }
+                    (RecordSigner::Key(did), None) => signers::default::verify(&msg, did, &sig.sig),
+                    _ => false,
+                }
+            })
+    }
+
+    /// Record is valid and not expired.
+    pub fn is_valid(&self) -> bool {
+        utils::get_epoch_ms() <= self.expires_ms() && self.verify()
+    }
+
+    /// Public key of the first session signing the record.
+    pub fn session_pubkey(&self) -> Result<PublicKey> {
+        self.sigs
+            .iter()
+            .find_map(|sig| self.verification(sig))
+            .ok_or_else(T::invalid)?
+            .session_pubkey(&self.data)
+    }
+
+    pub fn to_vnode(&self) -> Result<VirtualNode> {
+        let data = serde_json::to_string(self)
+            .map_err(Error::Serialize)?
+            .encode()?;
+        Ok(VirtualNode {
+            address: self.data.address()?,
+            data: vec![data],
+            kind: T::KIND,
+        })
+    }
+
+    pub fn from_vnode(vnode: &VirtualNode) -> Result<Self> {
+        if vnode.kind != T::KIND {
+            return Err(Error::InvalidVNodeType);
+        }
+        let encoded = vnode.data.first().ok_or(Error::InvalidVNodeType)?;
+        let s = String::from_encoded(encoded)?;
+        serde_json::from_str(&s).map_err(Error::Deserialize)
+    }
+
+    /// Record of `vnode`, if it's valid, not expired, and stored at its address.
+    pub fn check_vnode(vnode: &VirtualNode) -> Result<Self> {
+        let record = Self::from_vnode(vnode)?;
+        if !record.is_valid() || record.data.address()? != vnode.address {
+            return Err(T::invalid());
+        }
+        Ok(record)
+    }
+
+    /// Merge stored vnode `a` with incoming `b`, keeps `b` only if it's valid and
+    /// [replaces](RecordData::replaces) `a`. Invalid `b` is refused.
+    pub(crate) fn merge(a: &VirtualNode, b: &VirtualNode) -> Result<VirtualNode> {
+        if a.address != b.address {
+            return Err(Error::AddressNotEqual);
+        }
+        let incoming = Self::check_vnode(b)?;
+        match Self::check_vnode(a) {
+            Ok(stored) if !T::replaces(&stored, &incoming) => Ok(a.clone()),
+            _ => Ok(b.clone()),
+        }
+    }
+}
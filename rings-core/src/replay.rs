@@ -0,0 +1,316 @@
+//! Replay protection of payloads by a sliding window per sender.
+//!
+//! Signatures prove who signed a payload, but not that it's fresh, a payload captured on the
+//! wire can be sent again until it expires. [ReplayGuard] keeps timestamps and ids of payloads
+//! recently received from every sender, and rejects ones older than the window or already
+//! seen. Age is evaluated by clock of sender, see [crate::clock].
+//!
+//! Payloads are identified by hash of their signed content, see [payload_id], signatures are
+//! malleable so they are not used.
+//!
+//! Entries leave the window as it slides, and a sender flooding more than [MAX_ENTRIES] in a
+//! window raises its lower edge, so memory of a sender is bounded. Idle windows are pruned
+//! every [PRUNE_EVERY] checks. On native, entries can be persisted in sled for as long as they
+//! are in window with [ReplayGuard::open], so a restart doesn't open a replay window.
+use std::collections::BTreeSet;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use dashmap::DashMap;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::address::keccak256;
+use crate::dht::Did;
+use crate::ecc::HashStr;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::MessageVerification;
+
+/// Length of window by default, in milliseconds, as long as payloads live.
+pub const DEFAULT_REPLAY_WINDOW_MS: u64 = 60 * 1000;
+/// Payloads of a sender kept in its window.
+pub const MAX_ENTRIES: usize = 4096;
+/// Checks between prunings of idle windows.
+pub const PRUNE_EVERY: u64 = 1024;
+
+#[derive(Debug, Default)]
+struct Window {
+    /// Payloads signed before it are rejected, even if they're in the window.
+    floor_ms: u128,
+    seen: BTreeSet<(u128, String)>,
+}
+
+/// Rejects of [ReplayGuard].
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayStats {
+    /// Payloads older than the window.
+    pub stale: u64,
+    /// Payloads already seen.
+    pub duplicate: u64,
+}
+
+/// Id of a payload signed by `verification` over `data`, hash of what is signed.
+pub fn payload_id<T>(data: &T, verification: &MessageVerification) -> Result<HashStr>
+where T: Serialize {
+    let msg = MessageVerification::pack_msg(data, verification.ts_ms, verification.ttl_ms)?;
+    Ok(hex::encode(keccak256(msg.as_bytes())).into())
+}
+
+/// Windows of senders, see module doc.
+#[derive(Debug)]
+pub struct ReplayGuard {
+    window_ms: u64,
+    windows: DashMap<Did, Window>,
+    checks: AtomicU64,
+    stale: AtomicU64,
+    duplicate: AtomicU64,
+    #[cfg(not(feature = "wasm"))]
+    db: Option<sled::Tree>,
+}
+
+/// Key of an entry in sled, sender, then timestamp, then id.
+#[cfg(not(feature = "wasm"))]
+fn entry_key(sender: Did, ts_ms: u128, id: &str) -> Vec<u8> {
+    [sender.as_bytes(), &ts_ms.to_be_bytes()[..], id.as_bytes()].concat()
+}
+
+impl Default for ReplayGuard {
+    fn default() -> Self {
+        Self::new(DEFAULT_REPLAY_WINDOW_MS)
+    }
+}
+
+impl ReplayGuard {
+    /// Reject payloads signed more than `window_ms` ago, 0 to disable the guard.
+    pub fn new(window_ms: u64) -> Self {
+        Self {
+            window_ms,
+            windows: DashMap::new(),
+            checks: AtomicU64::new(0),
+            stale: AtomicU64::new(0),
+            duplicate: AtomicU64::new(0),
+            #[cfg(not(feature = "wasm"))]
+            db: None,
+        }
+    }
+
+    /// Guard with entries persisted at `path`, entries of last run still in window are
+    /// loaded.
+    #[cfg(not(feature = "wasm"))]
+    pub fn open<P: AsRef<std::path::Path>>(window_ms: u64, path: P) -> Result<Self> {
+        let db = sled::open(path)
+            .and_then(|db| db.open_tree("replay"))
+            .map_err(Error::SledError)?;
+        let guard = Self {
+            db: Some(db),
+            ..Self::new(window_ms)
+        };
+        let lower = crate::utils::get_epoch_ms().saturating_sub(window_ms as u128);
+        for kv in guard.db.iter().flat_map(|db| db.iter()) {
+            let (k, _) = kv.map_err(Error::SledError)?;
+            if k.len() < 36 {
+                continue;
+            }
+            let sender: Did = crate::address::H160::from_slice(&k[..20]).into();
+            let mut ts = [0u8; 16];
+            ts.copy_from_slice(&k[20..36]);
+            let ts_ms = u128::from_be_bytes(ts);
+            let id = String::from_utf8_lossy(&k[36..]).to_string();
+            if ts_ms >= lower {
+                guard
+                    .windows
+                    .entry(sender)
+                    .or_default()
+                    .seen
+                    .insert((ts_ms, id));
+            }
+        }
+        guard.prune(crate::utils::get_epoch_ms() as i128);
+        Ok(guard)
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    fn persist(&self, sender: Did, ts_ms: u128, id: &str, remove: bool) {
+        if let Some(db) = &self.db {
+            let key = entry_key(sender, ts_ms, id);
+            let r = match remove {
+                true => db.remove(key).map(|_| ()),
+                false => db.insert(key, &[]).map(|_| ()),
+            };
+            if let Err(e) = r {
+                tracing::warn!("failed to persist replay window: {}", e);
+            }
+        }
+    }
+
+    #[cfg(feature = "wasm")]
+    fn persist(&self, _sender: Did, _ts_ms: u128, _id: &str, _remove: bool) {}
+
+    pub fn window_ms(&self) -> u64 {
+        self.window_ms
+    }
+
+    /// Check payload `id` signed by `sender` at `ts_ms`, by local time `now` which is
+    /// adjusted to clock of sender. Accepted payloads are remembered.
+    pub fn check(&self, sender: Did, ts_ms: u128, id: &HashStr, now: i128) -> Result<()> {
+        if self.window_ms == 0 {
+            return Ok(());
+        }
+        if self.checks.fetch_add(1, Ordering::Relaxed) % PRUNE_EVERY == PRUNE_EVERY - 1 {
+            self.prune(now);
+        }
+        let lower = (now - self.window_ms as i128).max(0) as u128;
+        let mut window = self.windows.entry(sender).or_default();
+        if ts_ms < lower.max(window.floor_ms) {
+            self.stale.fetch_add(1, Ordering::Relaxed);
+            return Err(Error::StalePayload(id.inner()));
+        }
+        window.slide(lower);
+        if !window.seen.insert((ts_ms, id.inner())) {
+            self.duplicate.fetch_add(1, Ordering::Relaxed);
+            return Err(Error::ReplayedPayload(id.inner()));
+        }
+        self.persist(sender, ts_ms, &id.inner(), false);
+        if window.seen.len() > MAX_ENTRIES {
+            if let Some(oldest) = window.seen.iter().next().cloned() {
+                window.seen.remove(&oldest);
+                window.floor_ms = window.floor_ms.max(oldest.0 + 1);
+                self.persist(sender, oldest.0, &oldest.1, true);
+            }
+        }
+        Ok(())
+    }
+
+    /// Rejected payloads so far.
+    pub fn stats(&self) -> ReplayStats {
+        ReplayStats {
+            stale: self.stale.load(Ordering::Relaxed),
+            duplicate: self.duplicate.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Drop windows of senders without payloads since `now - window_ms`, and persisted
+    /// entries out of window.
+    pub fn prune(&self, now: i128) {
+        let lower = (now - self.window_ms as i128).max(0) as u128;
+        self.windows.retain(|_, w| {
+            w.slide(lower);
+            !w.seen.is_empty()
+        });
+        #[cfg(not(feature = "wasm"))]
+        if let Some(db) = &self.db {
+            let expired = db
+                .iter()
+                .keys()
+                .flatten()
+                .filter(|k| k.len() >= 36 && k[20..36] < lower.to_be_bytes()[..])
+                .collect::<Vec<_>>();
+            for k in expired {
+                let _ = db.remove(k);
+            }
+        }
+    }
+}
+
+impl Window {
+    fn slide(&mut self, lower: u128) {
+        self.seen = self.seen.split_off(&(lower, String::new()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    #[test]
+    fn test_replay_guard() {
+        let guard = ReplayGuard::new(10_000);
+        let peer: Did = SecretKey::random().address().into();
+        let tx: HashStr = "tx-a".into();
+
+        assert!(guard.check(peer, 95_000, &tx, 100_000).is_ok());
+        assert!(matches!(
+            guard.check(peer, 95_000, &tx, 100_500),
+            Err(Error::ReplayedPayload(_))
+        ));
+        // same timestamp, other payload
+        assert!(guard.check(peer, 95_000, &"tx-b".into(), 100_500).is_ok());
+        // another sender has its own window
+        let other: Did = SecretKey::random().address().into();
+        assert!(guard.check(other, 95_000, &tx, 100_500).is_ok());
+
+        // out of window
+        assert!(matches!(
+            guard.check(peer, 95_000, &tx, 106_000),
+            Err(Error::StalePayload(_))
+        ));
+        assert_eq!(guard.stats(), ReplayStats {
+            stale: 1,
+            duplicate: 1
+        });
+
+        guard.prune(200_000);
+        assert!(guard.windows.is_empty());
+
+        let disabled = ReplayGuard::new(0);
+        assert!(disabled.check(peer, 0, &tx, 100_000).is_ok());
+        assert!(disabled.check(peer, 0, &tx, 100_000).is_ok());
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    #[test]
+    fn test_replay_guard_persisted() {
+        let path = std::env::temp_dir().join(format!("rings-replay-{}", uuid::Uuid::new_v4()));
+        let peer: Did = SecretKey::random().address().into();
+        let tx: HashStr = "tx-a".into();
+        let now = crate::utils::get_epoch_ms();
+        {
+            let guard = ReplayGuard::open(10_000, &path).unwrap();
+            assert!(guard.check(peer, now, &tx, now as i128).is_ok());
+        }
+        let guard = ReplayGuard::open(10_000, &path).unwrap();
+        assert!(matches!(
+            guard.check(peer, now, &tx, now as i128),
+            Err(Error::ReplayedPayload(_))
+        ));
+        drop(guard);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_payload_id() {
+        let key = SecretKey::random();
+        let session = crate::session::SessionManager::new_with_seckey(&key).unwrap();
+        let payload = crate::message::MessagePayload::new_direct(
+            "data".to_owned(),
+            &session,
+            key.address().into(),
+        )
+        .unwrap();
+        let id = payload_id(&payload.data, &payload.verification).unwrap();
+        // another signature of the same content is the same payload
+        let mut malleated = payload.verification.clone();
+        malleated.sig[0] ^= 1;
+        assert_eq!(payload_id(&payload.data, &malleated).unwrap(), id);
+        let mut later = payload.verification;
+        later.ts_ms += 1;
+        assert_ne!(payload_id(&payload.data, &later).unwrap(), id);
+    }
+
+    #[test]
+    fn test_replay_guard_bounded() {
+        let guard = ReplayGuard::new(1_000_000);
+        let peer: Did = SecretKey::random().address().into();
+        for i in 0..=MAX_ENTRIES as u128 {
+            let tx: HashStr = format!("tx-{}", i).into();
+            assert!(guard.check(peer, 500_000 + i, &tx, 600_000).is_ok());
+        }
+        // oldest one is evicted, and can't be replayed
+        assert!(matches!(
+            guard.check(peer, 500_000, &"tx-0".into(), 600_000),
+            Err(Error::StalePayload(_))
+        ));
+    }
+}
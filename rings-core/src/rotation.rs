@@ -0,0 +1,224 @@
+//! Rotation of identity, from a DID to a new one, like after a key is compromised.
+//!
+//! A [RotationRecord] links the old DID to the new one, and is signed by keys of both DIDs,
+//! not by their sessions, so it stays valid after the sessions expire, and neither side can be
+//! claimed by others. The rotating node stores it at [VirtualNode::rotation_address] of the
+//! old DID, and sends it to connected peers in [RotateIdentity](crate::message::RotateIdentity),
+//! see [crate::message::MessageHandler::rotate_identity]. Peers not connected then look it up
+//! in DHT before sending to a DID out of reach, see [Rotations::needs_lookup].
+//! Swarms keep rotations they learned in [Rotations], messages to the old DID are sent to the
+//! new one, and tags of the old DID are moved to the new one. The first valid record stored
+//! for a DID is kept, so a stolen old key can't redirect it again.
+use dashmap::DashMap;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::dht::vnode::VNodeType;
+use crate::dht::vnode::VirtualNode;
+use crate::dht::Did;
+use crate::ecc::SecretKey;
+use crate::err::Error;
+use crate::err::Result;
+use crate::record::RecordData;
+use crate::record::RecordSigner;
+use crate::record::SignedRecord;
+use crate::utils;
+
+/// Rotations followed at most on resolving a DID, so a loop of records ends.
+const MAX_ROTATION_HOPS: usize = 8;
+/// A DID is looked up in DHT again only after this long.
+const LOOKUP_INTERVAL_MS: u128 = 10 * 60 * 1000;
+/// Lookups remembered at most, older ones are forgotten beyond it.
+const MAX_LOOKUPS: usize = 4096;
+
+/// Identity `old` is replaced by `new`.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rotation {
+    pub old: Did,
+    pub new: Did,
+}
+
+/// Rotation signed by keys of both DIDs, which never expires.
+pub type RotationRecord = SignedRecord<Rotation>;
+
+impl RecordData for Rotation {
+    const KIND: VNodeType = VNodeType::Rotation;
+
+    fn address(&self) -> Result<Did> {
+        VirtualNode::rotation_address(self.old)
+    }
+
+    fn signers(&self) -> Vec<RecordSigner> {
+        vec![RecordSigner::Key(self.old), RecordSigner::Key(self.new)]
+    }
+
+    fn invalid() -> Error {
+        Error::InvalidRotation
+    }
+
+    fn check(&self) -> bool {
+        self.old != self.new
+    }
+
+    /// A DID is only rotated once, stored rotation is kept.
+    fn replaces(_: &RotationRecord, _: &RotationRecord) -> bool {
+        false
+    }
+}
+
+impl RotationRecord {
+    /// Rotate from identity of `old` key to identity of `new` key.
+    pub fn new(old: &SecretKey, new: &SecretKey) -> Result<Self> {
+        let rotation = Rotation {
+            old: old.address().into(),
+            new: new.address().into(),
+        };
+        Self::sign(rotation, usize::MAX, None, &[*old, *new])
+    }
+}
+
+/// Rotations learned by swarm, see module doc.
+#[derive(Debug, Default)]
+pub struct Rotations {
+    rotated: DashMap<Did, Did>,
+    /// Time DIDs were last looked up in DHT.
+    lookups: DashMap<Did, u128>,
+}
+
+impl Rotations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Learn rotation of `record`, returns false if it's invalid, or old DID of it is rotated
+    /// already.
+    pub fn insert(&self, record: &RotationRecord) -> bool {
+        if !record.verify() || self.rotated.contains_key(&record.data.old) {
+            return false;
+        }
+        self.rotated.insert(record.data.old, record.data.new);
+        true
+    }
+
+    /// Latest identity of `did`, following its rotations.
+    pub fn resolve(&self, did: Did) -> Did {
+        let mut current = did;
+        for _ in 0..MAX_ROTATION_HOPS {
+            match self.rotated.get(&current) {
+                Some(new) => current = *new,
+                None => break,
+            }
+        }
+        current
+    }
+
+    /// DID `old` is rotated to.
+    pub fn get(&self, old: Did) -> Option<Did> {
+        self.rotated.get(&old).map(|v| *v)
+    }
+
+    /// All rotations as `(old, new)`.
+    pub fn list(&self) -> Vec<(Did, Did)> {
+        self.rotated
+            .iter()
+            .map(|kv| (*kv.key(), *kv.value()))
+            .collect()
+    }
+
+    /// Whether rotation of `did` should be looked up in DHT, it's true once per
+    /// [LOOKUP_INTERVAL_MS] for a DID not known to be rotated.
+    pub fn needs_lookup(&self, did: Did) -> bool {
+        self.needs_lookup_at(did, utils::get_epoch_ms())
+    }
+
+    fn needs_lookup_at(&self, did: Did, now: u128) -> bool {
+        if self.rotated.contains_key(&did) {
+            return false;
+        }
+        if let Some(ts) = self.lookups.get(&did) {
+            if now < *ts + LOOKUP_INTERVAL_MS {
+                return false;
+            }
+        }
+        if self.lookups.len() >= MAX_LOOKUPS {
+            self.lookups.retain(|_, ts| now < *ts + LOOKUP_INTERVAL_MS);
+        }
+        if self.lookups.len() >= MAX_LOOKUPS {
+            return false;
+        }
+        self.lookups.insert(did, now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Encoder;
+
+    #[test]
+    fn test_rotation_record() {
+        let old_key = SecretKey::random();
+        let new_key = SecretKey::random();
+        let record = RotationRecord::new(&old_key, &new_key).unwrap();
+        assert!(record.verify());
+        assert!(RotationRecord::new(&old_key, &old_key).is_err());
+
+        let vnode = record.to_vnode().unwrap();
+        assert_eq!(
+            vnode.address,
+            VirtualNode::rotation_address(old_key.address().into()).unwrap()
+        );
+        assert_eq!(RotationRecord::check_vnode(&vnode).unwrap(), record);
+
+        // new DID must sign it too
+        let thief = SecretKey::random();
+        let mut forged = RotationRecord::new(&old_key, &thief).unwrap();
+        forged.data.new = new_key.address().into();
+        assert!(!forged.verify());
+        let mut forged_vnode = forged.to_vnode().unwrap();
+        assert!(RotationRecord::check_vnode(&forged_vnode).is_err());
+
+        // stored rotation is kept, and an invalid one is refused even over an invalid one
+        let stolen = RotationRecord::new(&old_key, &thief).unwrap();
+        assert_eq!(
+            RotationRecord::merge(&vnode, &stolen.to_vnode().unwrap()).unwrap(),
+            vnode
+        );
+        assert!(RotationRecord::merge(&vnode, &forged_vnode).is_err());
+        forged_vnode.data = vec!["garbage".to_owned().encode().unwrap()];
+        assert!(RotationRecord::merge(&forged_vnode, &forged.to_vnode().unwrap()).is_err());
+        assert_eq!(RotationRecord::merge(&forged_vnode, &vnode).unwrap(), vnode);
+
+        // record of another DID is refused at address of old one
+        let other = RotationRecord::new(&thief, &new_key).unwrap();
+        let mut misplaced = other.to_vnode().unwrap();
+        misplaced.address = vnode.address;
+        assert!(RotationRecord::check_vnode(&misplaced).is_err());
+
+        let rotations = Rotations::new();
+        assert!(rotations.insert(&record));
+        assert!(!rotations.insert(&stolen));
+        assert!(!rotations.insert(&forged));
+        let newer = SecretKey::random();
+        assert!(rotations.insert(&RotationRecord::new(&new_key, &newer).unwrap()));
+        assert_eq!(
+            rotations.resolve(old_key.address().into()),
+            newer.address().into()
+        );
+    }
+
+    #[test]
+    fn test_rotation_lookups() {
+        let rotations = Rotations::new();
+        let old_key = SecretKey::random();
+        let did: Did = old_key.address().into();
+        assert!(rotations.needs_lookup_at(did, 10));
+        assert!(!rotations.needs_lookup_at(did, 20));
+        assert!(rotations.needs_lookup_at(did, 10 + LOOKUP_INTERVAL_MS));
+
+        let record = RotationRecord::new(&old_key, &SecretKey::random()).unwrap();
+        assert!(rotations.insert(&record));
+        assert!(!rotations.needs_lookup_at(did, 20 + 2 * LOOKUP_INTERVAL_MS));
+    }
+}
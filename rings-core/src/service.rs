@@ -0,0 +1,230 @@
+//! Services provided by nodes, registered in DHT by name.
+//!
+//! A provider stores a signed [ServiceRecord] at [VirtualNode::service_address] of the name,
+//! where records of all providers are kept together. Records expire after `ttl_ms`, and are
+//! refreshed by stabilization while the name is in [ServiceRegistry] of provider, so a
+//! provider which goes away disappears by itself.
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::dht::vnode::VNodeType;
+use crate::dht::vnode::VirtualNode;
+use crate::dht::Did;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::Decoder;
+use crate::message::Encoded;
+use crate::message::Encoder;
+use crate::message::MessageVerification;
+use crate::session::SessionManager;
+use crate::utils;
+
+/// How long a service record is valid, refreshed by every round of stabilization.
+pub const DEFAULT_SERVICE_TTL_MS: usize = 3 * 60 * 1000;
+
+/// Content of service record.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Service {
+    pub name: String,
+    pub provider: Did,
+}
+
+/// Service signed by session of its provider.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ServiceRecord {
+    pub service: Service,
+    pub verification: MessageVerification,
+}
+
+impl ServiceRecord {
+    pub fn new(session_manager: &SessionManager, name: &str, ttl_ms: usize) -> Result<Self> {
+        let service = Service {
+            name: name.to_owned(),
+            provider: session_manager.authorizer()?.into(),
+        };
+        let ts_ms = utils::get_epoch_ms();
+        let msg = MessageVerification::pack_msg(&service, ts_ms, ttl_ms)?;
+        let verification = MessageVerification {
+            session: session_manager.session()?,
+            sig: session_manager.sign(&msg)?,
+            ttl_ms,
+            ts_ms,
+        };
+        Ok(Self {
+            service,
+            verification,
+        })
+    }
+
+    /// When record expires, in milliseconds since epoch.
+    pub fn expires_ms(&self) -> u128 {
+        self.verification.ts_ms + self.verification.ttl_ms as u128
+    }
+
+    /// Check signature, and record is signed by provider itself.
+    pub fn verify(&self) -> bool {
+        Did::from(self.verification.session.auth.authorizer) == self.service.provider
+            && self.verification.verify(&self.service)
+    }
+
+    /// Record is valid and not expired.
+    pub fn is_alive(&self) -> bool {
+        utils::get_epoch_ms() <= self.expires_ms() && self.verify()
+    }
+
+    fn encode(&self) -> Result<Encoded> {
+        serde_json::to_string(self)
+            .map_err(Error::Serialize)?
+            .encode()
+    }
+
+    fn decode(encoded: &Encoded) -> Result<Self> {
+        let s = String::from_encoded(encoded)?;
+        serde_json::from_str(&s).map_err(Error::Deserialize)
+    }
+
+    pub fn to_vnode(&self) -> Result<VirtualNode> {
+        Ok(VirtualNode {
+            address: VirtualNode::service_address(&self.service.name)?,
+            data: vec![self.encode()?],
+            kind: VNodeType::Service,
+        })
+    }
+
+    /// Alive records of all providers in a service vnode.
+    pub fn from_vnode(vnode: &VirtualNode) -> Result<Vec<Self>> {
+        if vnode.kind != VNodeType::Service {
+            return Err(Error::InvalidVNodeType);
+        }
+        Ok(vnode
+            .data
+            .iter()
+            .filter_map(|d| Self::decode(d).ok())
+            .filter(|r| r.is_alive())
+            .collect())
+    }
+
+    /// Merge stored service vnode `a` with incoming `b`, keeps the newest alive record of
+    /// each provider, so a forged record can't replace others.
+    pub(crate) fn merge(a: &VirtualNode, b: &VirtualNode) -> Result<VirtualNode> {
+        if a.address != b.address {
+            return Err(Error::AddressNotEqual);
+        }
+        let mut records: Vec<Self> = vec![];
+        for r in Self::from_vnode(a)?
+            .into_iter()
+            .chain(Self::from_vnode(b)?.into_iter())
+        {
+            match records
+                .iter_mut()
+                .find(|x| x.service.provider == r.service.provider)
+            {
+                Some(x) if x.verification.ts_ms < r.verification.ts_ms => *x = r,
+                Some(_) => {}
+                None => records.push(r),
+            }
+        }
+        Ok(VirtualNode {
+            address: a.address,
+            data: records
+                .iter()
+                .map(|r| r.encode())
+                .collect::<Result<Vec<_>>>()?,
+            kind: VNodeType::Service,
+        })
+    }
+}
+
+/// Names of services provided by this node, published by stabilization.
+#[derive(Default)]
+pub struct ServiceRegistry {
+    names: Mutex<BTreeSet<String>>,
+}
+
+impl ServiceRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&self, name: &str) {
+        if let Ok(mut names) = self.names.lock() {
+            names.insert(name.to_owned());
+        }
+    }
+
+    /// Stop publishing `name`, returns false if it's not registered.
+    /// Published record remains until it expires.
+    pub fn unregister(&self, name: &str) -> bool {
+        self.names
+            .lock()
+            .map(|mut names| names.remove(name))
+            .unwrap_or(false)
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.names
+            .lock()
+            .map(|names| names.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    #[test]
+    fn test_service_record_merge() {
+        let a = SessionManager::new_with_seckey(&SecretKey::random()).unwrap();
+        let b = SessionManager::new_with_seckey(&SecretKey::random()).unwrap();
+        let ra = ServiceRecord::new(&a, "web", DEFAULT_SERVICE_TTL_MS).unwrap();
+        let rb = ServiceRecord::new(&b, "web", DEFAULT_SERVICE_TTL_MS).unwrap();
+        assert!(ra.is_alive());
+        assert_eq!(
+            ra.to_vnode().unwrap().address,
+            VirtualNode::service_address("web").unwrap()
+        );
+
+        let merged =
+            ServiceRecord::merge(&ra.to_vnode().unwrap(), &rb.to_vnode().unwrap()).unwrap();
+        assert_eq!(ServiceRecord::from_vnode(&merged).unwrap(), vec![
+            ra.clone(),
+            rb.clone()
+        ]);
+
+        // newer record replaces the older one of same provider
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let newer = ServiceRecord::new(&a, "web", DEFAULT_SERVICE_TTL_MS).unwrap();
+        let merged = ServiceRecord::merge(&merged, &newer.to_vnode().unwrap()).unwrap();
+        assert_eq!(ServiceRecord::from_vnode(&merged).unwrap(), vec![
+            newer.clone(),
+            rb.clone()
+        ]);
+
+        // forged and expired records are dropped
+        let mut forged = rb.clone();
+        forged.service.provider = newer.service.provider;
+        let expired = ServiceRecord::new(&b, "web", 0).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let merged =
+            ServiceRecord::merge(&forged.to_vnode().unwrap(), &expired.to_vnode().unwrap())
+                .unwrap();
+        assert!(ServiceRecord::from_vnode(&merged).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_service_registry() {
+        let registry = ServiceRegistry::new();
+        registry.register("web");
+        registry.register("ssh");
+        registry.register("web");
+        assert_eq!(registry.list(), vec!["ssh".to_owned(), "web".to_owned()]);
+        assert!(registry.unregister("ssh"));
+        assert!(!registry.unregister("ssh"));
+        assert_eq!(registry.list(), vec!["web".to_owned()]);
+    }
+}
@@ -4,22 +4,37 @@
 //! - `SessionManager::gen_unsign_info(addr, ..)`, it will returns the msg needs for sign, and a temporate private key
 //! - Then we can sign the auth message via some web3 provider like metamask or just with raw private key, and create the SessionManger with
 //! - SessionManager::new(sig, auth_info, temp_key)
+//!
+//! Authority of a session can be delegated further, like wallet key -> device key -> ephemeral
+//! browser key, so one identity runs on several devices at the same time, see
+//! [SessionManager::delegate]. Session of a delegate carries the chain of sessions it's derived
+//! from, each link signed by the key authorized by the previous one, and the whole chain is
+//! verified with every payload. Chains are at most [MAX_DELEGATIONS] links long.
+//!
+//! All devices of an identity share its DID, which is also its id on the ring, so only one of
+//! them can join a network at a time. Swarm refuses a transport of a DID connected already by
+//! another device, told apart by [Session::authorized_key], and of its own DID.
 
 use std::sync::Arc;
 use std::sync::RwLock;
 
 use serde::Deserialize;
 use serde::Serialize;
-use web3::types::Address;
 
+use crate::address::Address;
 use crate::ecc::signers;
+use crate::ecc::HashStr;
 use crate::ecc::PublicKey;
 use crate::ecc::SecretKey;
 use crate::err::Error;
 use crate::err::Result;
+use crate::message::TxIdGenerator;
+use crate::message::DEFAULT_NETWORK_ID;
 use crate::utils;
 
 const DEFAULT_TTL_MS: usize = 24 * 3600 * 1000;
+/// Links of a delegation chain at most, not counting the session itself.
+pub const MAX_DELEGATIONS: usize = 4;
 
 /// we support both EIP712 and raw ECDSA singing forrmat
 #[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone)]
@@ -47,6 +62,10 @@ pub struct AuthorizedInfo {
 pub struct Session {
     pub sig: Vec<u8>,
     pub auth: AuthorizedInfo,
+    /// Sessions this one is delegated by, from the one signed by `auth.authorizer`. Each link
+    /// authorizes `auth.addr` of it to sign the next one, and the last one signs this session.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub delegations: Vec<Session>,
 }
 
 #[derive(Debug, Clone)]
@@ -58,12 +77,18 @@ pub struct SessionWithKey {
 #[derive(Debug)]
 pub struct SessionManager {
     inner: Arc<RwLock<SessionWithKey>>,
+    /// Ids of payloads signed by this session, kept across renewals.
+    tx_ids: Arc<TxIdGenerator>,
+    /// Network of payloads signed by this session, see [SessionManager::set_network_id].
+    network_id: Arc<RwLock<String>>,
 }
 
 impl Clone for SessionManager {
     fn clone(&self) -> Self {
         Self {
             inner: Arc::clone(&self.inner),
+            tx_ids: Arc::clone(&self.tx_ids),
+            network_id: Arc::clone(&self.network_id),
         }
     }
 }
@@ -79,6 +104,7 @@ impl Session {
         Self {
             sig: sig.to_vec(),
             auth: auth_info.clone(),
+            delegations: vec![],
         }
     }
 
@@ -91,18 +117,33 @@ impl Session {
         }
     }
 
+    /// Check session is signed by its authorizer, or by the end of a valid delegation chain of
+    /// the authorizer.
     pub fn verify(&self) -> bool {
+        if self.delegations.len() > MAX_DELEGATIONS {
+            return false;
+        }
+        let mut signer = self.auth.authorizer;
+        for link in &self.delegations {
+            if link.auth.authorizer != signer
+                || !link.delegations.is_empty()
+                || !link.is_signed_by(&signer)
+            {
+                return false;
+            }
+            signer = link.auth.addr;
+        }
+        self.is_signed_by(&signer)
+    }
+
+    fn is_signed_by(&self, signer: &Address) -> bool {
         if self.is_expired() {
             return false;
         }
         if let Ok(auth_str) = self.auth.to_string() {
             match self.auth.signer {
-                Signer::DEFAULT => {
-                    signers::default::verify(&auth_str, &self.auth.authorizer, &self.sig)
-                }
-                Signer::EIP712 => {
-                    signers::eip712::verify(&auth_str, &self.auth.authorizer, &self.sig)
-                }
+                Signer::DEFAULT => signers::default::verify(&auth_str, signer, &self.sig),
+                Signer::EIP712 => signers::eip712::verify(&auth_str, signer, &self.sig),
             }
         } else {
             false
@@ -117,7 +158,20 @@ impl Session {
         }
     }
 
+    /// Key signed by authorizer itself, first delegate of chain or this session key. It is not
+    /// derived from the authorizer, so it tells apart sessions and devices of the same DID.
+    pub fn authorized_key(&self) -> Address {
+        self.delegations
+            .first()
+            .map(|link| link.auth.addr)
+            .unwrap_or(self.auth.addr)
+    }
+
     pub fn authorizer_pubkey(&self) -> Result<PublicKey> {
+        // only first link of a chain is signed by authorizer
+        if let Some(link) = self.delegations.first() {
+            return link.authorizer_pubkey();
+        }
         let auth = self.auth.to_string()?;
         match self.auth.signer {
             Signer::DEFAULT => signers::default::recover(&auth, &self.sig),
@@ -155,9 +209,77 @@ impl SessionManager {
 
         Self {
             inner: Arc::new(RwLock::new(inner)),
+            tx_ids: Arc::new(TxIdGenerator::default()),
+            network_id: Arc::new(RwLock::new(DEFAULT_NETWORK_ID.to_owned())),
         }
     }
 
+    /// Session of `auth_info`, which is signed by `session_key` of the last one of
+    /// `delegations`, the chain given by [SessionManager::delegate].
+    pub fn new_with_delegations(
+        sig: &[u8],
+        auth_info: &AuthorizedInfo,
+        session_key: &SecretKey,
+        delegations: Vec<Session>,
+    ) -> Result<Self> {
+        let mut session = Session::new(sig, auth_info);
+        session.delegations = delegations;
+        if session.delegations.len() > MAX_DELEGATIONS {
+            return Err(Error::DelegationTooLong(MAX_DELEGATIONS));
+        }
+        if !session.verify() {
+            return Err(Error::VerifySignatureFailed);
+        }
+        let inner = SessionWithKey {
+            session,
+            session_key: *session_key,
+        };
+        Ok(Self {
+            inner: Arc::new(RwLock::new(inner)),
+            tx_ids: Arc::new(TxIdGenerator::default()),
+            network_id: Arc::new(RwLock::new(DEFAULT_NETWORK_ID.to_owned())),
+        })
+    }
+
+    /// Delegate authority of this session to a new key, for another device or an ephemeral
+    /// session of browser. Session of the new key has the same authorizer, and is valid for
+    /// `ttl`, but no longer than any link of its chain.
+    ///
+    /// The delegate has the same DID, so it should join a network other devices of the
+    /// identity are not on, see module doc.
+    pub fn delegate(&self, ttl: Option<Ttl>) -> Result<Self> {
+        let session = self.session()?;
+        if session.delegations.len() >= MAX_DELEGATIONS {
+            return Err(Error::DelegationTooLong(MAX_DELEGATIONS));
+        }
+        let (auth, key) = Self::gen_unsign_info(session.auth.authorizer, ttl, None)?;
+        let sig = self.session_key()?.sign(&auth.to_string()?).to_vec();
+        let mut delegations = session.delegations.clone();
+        delegations.push(Session {
+            delegations: vec![],
+            ..session
+        });
+        Self::new_with_delegations(&sig, &auth, &key, delegations)
+    }
+
+    /// Next `tx_id` of payloads sent by this node, see [crate::message::tx_id].
+    pub fn next_tx_id(&self) -> HashStr {
+        self.tx_ids.next()
+    }
+
+    /// Network of payloads signed by this session, it's covered by their signatures.
+    pub fn network_id(&self) -> String {
+        self.network_id
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Sign payloads for network `network_id`, set by [crate::swarm::SwarmBuilder::with_network_id].
+    pub fn set_network_id(&self, network_id: &str) {
+        *self.network_id.write().unwrap_or_else(|e| e.into_inner()) = network_id.to_owned();
+    }
+
     /// generate Session with private key
     /// only use it for unittest
     pub fn new_with_seckey(key: &SecretKey) -> Result<Self> {
@@ -212,6 +334,7 @@ impl SessionManager {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::message::MessageVerification;
 
     #[test]
     pub fn test_session_verify() {
@@ -221,6 +344,48 @@ mod test {
         assert!(session.verify());
     }
 
+    #[test]
+    pub fn test_delegated_session() {
+        let wallet = SecretKey::random();
+        let sm = SessionManager::new_with_seckey(&wallet).unwrap();
+        let device = sm.delegate(None).unwrap();
+        let browser = device.delegate(Some(Ttl::Some(60_000))).unwrap();
+        let session = browser.session().unwrap();
+        assert!(session.verify());
+        assert_eq!(session.delegations.len(), 2);
+        assert_eq!(browser.authorizer().unwrap(), wallet.address());
+        assert_eq!(session.authorizer_pubkey().unwrap(), wallet.pubkey());
+
+        // payload signed by browser key verifies with the chain
+        let ts_ms = utils::get_epoch_ms();
+        let msg = MessageVerification::pack_msg(&"hello", ts_ms, 1000).unwrap();
+        let verification = MessageVerification {
+            session: session.clone(),
+            sig: browser.sign(&msg).unwrap(),
+            ttl_ms: 1000,
+            ts_ms,
+        };
+        assert!(verification.verify(&"hello"));
+
+        // a link of chain can't be replaced
+        let mut forged = session.clone();
+        forged.delegations[0] = SessionManager::new_with_seckey(&SecretKey::random())
+            .unwrap()
+            .session()
+            .unwrap();
+        assert!(!forged.verify());
+        let mut cut = session;
+        cut.delegations.remove(1);
+        assert!(!cut.verify());
+
+        // chain length is limited
+        let mut sm = browser;
+        while sm.session().unwrap().delegations.len() < MAX_DELEGATIONS {
+            sm = sm.delegate(None).unwrap();
+        }
+        assert!(sm.delegate(None).is_err());
+    }
+
     #[test]
     pub fn test_authorizer_pubkey() {
         let key = SecretKey::random();
@@ -0,0 +1,582 @@
+//! rings-sim, a network simulator for churn experiments.
+//!
+//! [Simulation] spins up nodes over a [MockHub] of its own in one process,
+//! joins, leaves and fails them on a [SimEvent] schedule, and measures the following after every round:
+//! - ring consistency: ratio of nodes whose successor is the next live node on the ring,
+//! - lookup success rate: ratio of random lookups routed by finger tables to the right successor,
+//! - data availability: ratio of seeded keys which are still stored on some live node.
+//!
+//! Rounds are collected into a [SimReport], which can be written as CSV or checked with [Invariants].
+//! Requires feature `sim`, which also enables the mock transport.
+//!
+//! ```no_run
+//! # async fn run() -> rings_core::err::Result<()> {
+//! use rings_core::sim::*;
+//!
+//! let report = Simulation::new(SimConfig::default())
+//!     .with_event(SimEvent::new(5, SimAction::Fail(2)))
+//!     .run()
+//!     .await?;
+//! report.write_csv("churn.csv")?;
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::lock::Mutex;
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand::SeedableRng;
+use tokio::task::JoinHandle;
+
+use crate::address::H160;
+use crate::dht::vnode::VirtualNode;
+use crate::dht::Chord;
+use crate::dht::Did;
+use crate::dht::PeerRing;
+use crate::dht::PeerRingAction;
+use crate::dht::Stabilization;
+use crate::ecc::SecretKey;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::MessageHandler;
+use crate::message::TChordStorage;
+use crate::prelude::RTCSdpType;
+use crate::session::SessionManager;
+use crate::swarm::Swarm;
+use crate::swarm::TransportManager;
+use crate::transports::mock::MockConfig;
+use crate::transports::mock::MockHub;
+use crate::types::ice_transport::IceTransport;
+use crate::types::ice_transport::IceTrickleScheme;
+use crate::types::message::MessageListener;
+
+const ICE_SERVER: &str = "stun://stun.l.google.com:19302";
+/// Lookups routed by more hops are failed.
+const MAX_HOPS: usize = 64;
+
+/// Parameters of a simulation.
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    /// Nodes joined before the first round.
+    pub nodes: usize,
+    /// Number of rounds, each round runs stabilization once on every live node.
+    pub rounds: usize,
+    /// Time to wait for messages after stabilization of each round.
+    pub settle: Duration,
+    /// Random lookups measured per round.
+    pub lookups: usize,
+    /// Keys stored into DHT before the first round.
+    pub keys: usize,
+    /// Seed of node keys, schedule targets and lookups.
+    pub seed: u64,
+    /// Latency and loss of links between nodes.
+    pub transport: MockConfig,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            nodes: 16,
+            rounds: 20,
+            settle: Duration::from_millis(100),
+            lookups: 50,
+            keys: 50,
+            seed: 0,
+            transport: MockConfig::default(),
+        }
+    }
+}
+
+/// What happens to nodes at a scheduled round.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimAction {
+    /// New nodes join via a random live node.
+    Join(usize),
+    /// Random live nodes leave gracefully, see [MessageHandler::drain].
+    Leave(usize),
+    /// Random live nodes crash, their transports are closed without notifying.
+    Fail(usize),
+}
+
+/// An action applied before stabilization of `round`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimEvent {
+    pub round: usize,
+    pub action: SimAction,
+}
+
+impl SimEvent {
+    pub fn new(round: usize, action: SimAction) -> Self {
+        Self { round, action }
+    }
+}
+
+/// Measurements of a round.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoundStats {
+    pub round: usize,
+    pub live_nodes: usize,
+    pub joined: usize,
+    pub left: usize,
+    pub failed: usize,
+    pub ring_consistency: f64,
+    pub lookup_success_rate: f64,
+    pub data_availability: f64,
+}
+
+/// Lower bounds of round measurements, checked by [SimReport::check].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invariants {
+    /// Rounds before it are warming up and not checked.
+    pub from_round: usize,
+    pub min_ring_consistency: f64,
+    pub min_lookup_success_rate: f64,
+    pub min_data_availability: f64,
+}
+
+impl Default for Invariants {
+    fn default() -> Self {
+        Self {
+            from_round: 0,
+            min_ring_consistency: 1.0,
+            min_lookup_success_rate: 1.0,
+            min_data_availability: 1.0,
+        }
+    }
+}
+
+/// Result of a simulation, one row per round.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimReport {
+    pub rounds: Vec<RoundStats>,
+}
+
+impl SimReport {
+    pub const CSV_HEADER: &'static str = "round,live_nodes,joined,left,failed,ring_consistency,lookup_success_rate,data_availability";
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("{}\n", Self::CSV_HEADER);
+        for r in &self.rounds {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{:.4},{:.4},{:.4}\n",
+                r.round,
+                r.live_nodes,
+                r.joined,
+                r.left,
+                r.failed,
+                r.ring_consistency,
+                r.lookup_success_rate,
+                r.data_availability
+            ));
+        }
+        csv
+    }
+
+    pub fn write_csv(&self, path: &str) -> Result<()> {
+        std::fs::write(path, self.to_csv()).map_err(|e| Error::SimReport(e.to_string()))
+    }
+
+    /// Check every round since `invariants.from_round`, and report the first violation.
+    pub fn check(&self, invariants: &Invariants) -> Result<()> {
+        for r in self
+            .rounds
+            .iter()
+            .filter(|r| r.round >= invariants.from_round)
+        {
+            for (name, value, min) in [
+                (
+                    "ring_consistency",
+                    r.ring_consistency,
+                    invariants.min_ring_consistency,
+                ),
+                (
+                    "lookup_success_rate",
+                    r.lookup_success_rate,
+                    invariants.min_lookup_success_rate,
+                ),
+                (
+                    "data_availability",
+                    r.data_availability,
+                    invariants.min_data_availability,
+                ),
+            ] {
+                if value < min {
+                    return Err(Error::SimInvariantViolated(format!(
+                        "round {}: {} is {:.4}, expected at least {:.4}",
+                        r.round, name, value, min
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct SimNode {
+    did: Did,
+    dht: Arc<Mutex<PeerRing>>,
+    swarm: Arc<Swarm>,
+    handler: Arc<MessageHandler>,
+    stabilization: Stabilization,
+    listener: JoinHandle<()>,
+}
+
+impl SimNode {
+    fn new(key: SecretKey, hub: &MockHub) -> Result<Self> {
+        let did: Did = key.address().into();
+        let session_manager = SessionManager::new_with_seckey(&key)?;
+        let dht = Arc::new(Mutex::new(PeerRing::new(did)));
+        let swarm = Arc::new(
+            Swarm::builder(key.address(), session_manager)
+                .with_ice_servers(ICE_SERVER)
+                .with_mock_hub(hub.clone())
+                .build()?,
+        );
+        let handler = Arc::new(MessageHandler::new(dht.clone(), swarm.clone()));
+        let stabilization = Stabilization::new(dht.clone(), swarm.clone()).with_interval(1, 1);
+        let listener = tokio::spawn(handler.clone().listen());
+        Ok(Self {
+            did,
+            dht,
+            swarm,
+            handler,
+            stabilization,
+            listener,
+        })
+    }
+
+    /// Connect to `other` directly, as if handshake info was exchanged out of band.
+    async fn connect(&self, other: &SimNode) -> Result<()> {
+        let offer_transport = self.swarm.new_transport().await?;
+        let offer = offer_transport
+            .get_handshake_info(self.swarm.session_manager(), RTCSdpType::Offer)
+            .await?;
+        let answer_transport = other.swarm.new_transport().await?;
+        let addr = answer_transport.register_remote_info(offer).await?;
+        other
+            .swarm
+            .register(&addr, answer_transport.clone())
+            .await?;
+        let answer = answer_transport
+            .get_handshake_info(other.swarm.session_manager(), RTCSdpType::Answer)
+            .await?;
+        // register before connected, so swarm knows transport when RegisterTransport arrives
+        self.swarm
+            .register(&other.swarm.address(), offer_transport.clone())
+            .await?;
+        offer_transport.register_remote_info(answer).await?;
+        offer_transport.wait_for_connected().await
+    }
+
+    async fn close_transports(&self) {
+        for (address, transport) in self.swarm.get_transports() {
+            if let Err(e) = transport.close().await {
+                tracing::warn!(peer = ?address, "failed to close transport: {}", e);
+            }
+        }
+    }
+
+    async fn leave(self) {
+        if let Err(e) = self.handler.drain().await {
+            tracing::warn!(node = ?self.did, "failed to drain: {}", e);
+        }
+        self.listener.abort();
+    }
+
+    async fn fail(self) {
+        self.listener.abort();
+        self.close_transports().await;
+    }
+}
+
+/// Simulator of a ring, see [module level doc](self).
+pub struct Simulation {
+    config: SimConfig,
+    hub: MockHub,
+    events: Vec<SimEvent>,
+    nodes: Vec<SimNode>,
+    keys: Vec<Did>,
+    rng: StdRng,
+}
+
+impl Simulation {
+    pub fn new(config: SimConfig) -> Self {
+        let rng = StdRng::seed_from_u64(config.seed);
+        Self {
+            config,
+            hub: MockHub::new(),
+            events: vec![],
+            nodes: vec![],
+            keys: vec![],
+            rng,
+        }
+    }
+
+    pub fn with_event(mut self, event: SimEvent) -> Self {
+        self.events.push(event);
+        self
+    }
+
+    pub fn with_events(mut self, events: impl IntoIterator<Item = SimEvent>) -> Self {
+        self.events.extend(events);
+        self
+    }
+
+    /// Run every round, then shut down all nodes.
+    pub async fn run(mut self) -> Result<SimReport> {
+        self.hub.configure(self.config.transport);
+        self.join(self.config.nodes).await?;
+        self.stabilize().await;
+        self.seed_keys().await;
+
+        let mut report = SimReport::default();
+        for round in 0..self.config.rounds {
+            let (mut joined, mut left, mut failed) = (0, 0, 0);
+            let actions = self
+                .events
+                .iter()
+                .filter(|e| e.round == round)
+                .map(|e| e.action)
+                .collect::<Vec<_>>();
+            for action in actions {
+                match action {
+                    SimAction::Join(n) => joined += self.join(n).await?,
+                    SimAction::Leave(n) => {
+                        for node in self.pick(n) {
+                            node.leave().await;
+                            left += 1;
+                        }
+                    }
+                    SimAction::Fail(n) => {
+                        for node in self.pick(n) {
+                            node.fail().await;
+                            failed += 1;
+                        }
+                    }
+                }
+            }
+            self.stabilize().await;
+            let stats = RoundStats {
+                round,
+                live_nodes: self.nodes.len(),
+                joined,
+                left,
+                failed,
+                ring_consistency: self.ring_consistency().await,
+                lookup_success_rate: self.lookup_success_rate().await,
+                data_availability: self.data_availability().await,
+            };
+            tracing::info!(?stats, "round finished");
+            report.rounds.push(stats);
+        }
+
+        for node in self.nodes.drain(..) {
+            node.fail().await;
+        }
+        Ok(report)
+    }
+
+    async fn join(&mut self, n: usize) -> Result<usize> {
+        for _ in 0..n {
+            let key = SecretKey::from(libsecp256k1::SecretKey::random(&mut self.rng));
+            let node = SimNode::new(key, &self.hub)?;
+            if !self.nodes.is_empty() {
+                let bootstrap = self.rng.gen_range(0..self.nodes.len());
+                node.connect(&self.nodes[bootstrap]).await?;
+            }
+            self.nodes.push(node);
+        }
+        tokio::time::sleep(self.config.settle).await;
+        Ok(n)
+    }
+
+    fn pick(&mut self, n: usize) -> Vec<SimNode> {
+        let mut picked = vec![];
+        for _ in 0..n.min(self.nodes.len()) {
+            let i = self.rng.gen_range(0..self.nodes.len());
+            picked.push(self.nodes.swap_remove(i));
+        }
+        picked
+    }
+
+    async fn stabilize(&self) {
+        for node in &self.nodes {
+            if let Err(e) = node.stabilization.stabilize().await {
+                tracing::debug!(node = ?node.did, "failed to stabilize: {}", e);
+            }
+        }
+        tokio::time::sleep(self.config.settle).await;
+    }
+
+    /// Store keys by messages of DHT protocol, each from a random live node, like any client.
+    async fn seed_keys(&mut self) {
+        for i in 0..self.config.keys {
+            let vnode = match VirtualNode::try_from(format!("rings-sim-{}-{}", self.config.seed, i))
+            {
+                Ok(v) => v,
+                Err(e) => {
+                    tracing::warn!("failed to create vnode: {}", e);
+                    continue;
+                }
+            };
+            self.keys.push(vnode.did());
+            let from = &self.nodes[self.rng.gen_range(0..self.nodes.len())];
+            if let Err(e) = from.handler.store(vnode).await {
+                tracing::warn!(node = ?from.did, "failed to store key: {}", e);
+            }
+        }
+        tokio::time::sleep(self.config.settle).await;
+    }
+
+    fn index_of(&self, did: Did) -> Option<usize> {
+        self.nodes.iter().position(|n| n.did == did)
+    }
+
+    /// The first live node clockwise from `id`, `id` itself included.
+    fn successor_of(&self, id: Did, exclude: Option<Did>) -> Option<Did> {
+        self.nodes
+            .iter()
+            .map(|n| n.did)
+            .filter(|d| Some(*d) != exclude)
+            .min_by_key(|d| *d - id)
+    }
+
+    async fn ring_consistency(&self) -> f64 {
+        let mut consistent = 0;
+        for node in &self.nodes {
+            let expected = self
+                .successor_of(node.did, Some(node.did))
+                .unwrap_or(node.did);
+            if node.dht.lock().await.successor.min() == expected {
+                consistent += 1;
+            }
+        }
+        ratio(consistent, self.nodes.len())
+    }
+
+    async fn lookup_success_rate(&mut self) -> f64 {
+        if self.nodes.is_empty() {
+            return 0.0;
+        }
+        let mut succeeded = 0;
+        for _ in 0..self.config.lookups {
+            let target = Did::from(H160::from(self.rng.gen::<[u8; 20]>()));
+            let mut current = self.rng.gen_range(0..self.nodes.len());
+            for _ in 0..MAX_HOPS {
+                let action = self.nodes[current].dht.lock().await.find_successor(target);
+                match action {
+                    Ok(PeerRingAction::Some(found)) => {
+                        if Some(found) == self.successor_of(target, None) {
+                            succeeded += 1;
+                        }
+                        break;
+                    }
+                    Ok(PeerRingAction::RemoteAction(next, _)) => match self.index_of(next) {
+                        Some(i) if i != current => current = i,
+                        _ => break,
+                    },
+                    _ => break,
+                }
+            }
+        }
+        ratio(succeeded, self.config.lookups)
+    }
+
+    async fn data_availability(&self) -> f64 {
+        let mut stored = HashSet::new();
+        for node in &self.nodes {
+            stored.extend(node.dht.lock().await.storage.keys());
+        }
+        let available = self.keys.iter().filter(|k| stored.contains(k)).count();
+        ratio(available, self.keys.len())
+    }
+}
+
+fn ratio(n: usize, total: usize) -> f64 {
+    if total == 0 {
+        1.0
+    } else {
+        n as f64 / total as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_simulation_schedule() -> Result<()> {
+        let config = SimConfig {
+            nodes: 6,
+            rounds: 10,
+            settle: Duration::from_millis(50),
+            lookups: 10,
+            keys: 10,
+            ..Default::default()
+        };
+        let report = Simulation::new(config)
+            .with_events([
+                SimEvent::new(1, SimAction::Join(2)),
+                SimEvent::new(2, SimAction::Leave(1)),
+                SimEvent::new(3, SimAction::Fail(1)),
+            ])
+            .run()
+            .await?;
+        let live = report
+            .rounds
+            .iter()
+            .map(|r| r.live_nodes)
+            .collect::<Vec<_>>();
+        assert_eq!(live, vec![6, 8, 7, 6, 6, 6, 6, 6, 6, 6]);
+        assert_eq!(report.rounds[2].left, 1);
+        assert_eq!(report.rounds[3].failed, 1);
+        // keys are stored by messages, all of them reach some node before churn
+        assert_eq!(report.rounds[0].data_availability, 1.0);
+
+        let csv = report.to_csv();
+        assert!(csv.starts_with(SimReport::CSV_HEADER));
+        assert_eq!(csv.lines().count(), 11);
+
+        // ring converges again after churn, keys of the failed node may be lost
+        report.check(&Invariants {
+            from_round: 7,
+            min_ring_consistency: 0.8,
+            min_lookup_success_rate: 0.9,
+            min_data_availability: 0.5,
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_report_check() {
+        let stats = |round, ring_consistency| RoundStats {
+            round,
+            live_nodes: 4,
+            joined: 0,
+            left: 0,
+            failed: 0,
+            ring_consistency,
+            lookup_success_rate: 1.0,
+            data_availability: 1.0,
+        };
+        let report = SimReport {
+            rounds: vec![stats(0, 0.5), stats(1, 1.0)],
+        };
+        assert!(report.check(&Invariants::default()).is_err());
+        assert!(report
+            .check(&Invariants {
+                from_round: 1,
+                ..Default::default()
+            })
+            .is_ok());
+        assert!(report
+            .check(&Invariants {
+                min_ring_consistency: 0.5,
+                ..Default::default()
+            })
+            .is_ok());
+    }
+}
@@ -0,0 +1,169 @@
+//! Encryption at rest of persisted state.
+//!
+//! Values written by [crate::storage::Storage] and [crate::history::MessageHistory] can be
+//! sealed with AES-256-GCM before they reach disk. The key is derived either from secret key
+//! of node, so nothing else has to be kept, or from a separate password, so storage survives
+//! rotation of node key. Every value has its own random nonce, stored in front of it, and is
+//! bound to the key it's stored under, so sealed values can't be swapped between keys.
+//!
+//! A password is salted with random bytes kept next to the storage, see [load_salt], so the
+//! same password never gives the same key on different storages.
+use aes_gcm::aead::Aead;
+use aes_gcm::aead::NewAead;
+use aes_gcm::aead::Payload;
+use aes_gcm::Aes256Gcm;
+use aes_gcm::Key;
+use aes_gcm::Nonce;
+use hmac::Hmac;
+use rand::RngCore;
+use sha2::Sha256;
+
+use crate::address::keccak256;
+use crate::ecc::SecretKey;
+use crate::err::Error;
+use crate::err::Result;
+
+/// Rounds of PBKDF2 deriving key from password.
+pub const PASSWORD_ROUNDS: u32 = 100_000;
+/// Domain of key derived from secret key of node, keeps it apart from signing.
+const NODE_KEY_DOMAIN: &[u8] = b"rings-storage-v1";
+const NONCE_LEN: usize = 12;
+/// Length of random salt of password.
+pub const SALT_LEN: usize = 16;
+
+/// Seals and opens persisted values with AES-256-GCM.
+#[derive(Clone)]
+pub struct StorageCipher {
+    cipher: Aes256Gcm,
+}
+
+impl std::fmt::Debug for StorageCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("StorageCipher")
+    }
+}
+
+impl StorageCipher {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self {
+            cipher: Aes256Gcm::new(Key::from_slice(&key)),
+        }
+    }
+
+    /// Key derived from secret key of node.
+    pub fn from_secret_key(key: &SecretKey) -> Self {
+        let mut material = NODE_KEY_DOMAIN.to_vec();
+        material.extend_from_slice(&key.serialize());
+        Self::new(keccak256(&material))
+    }
+
+    /// Key derived from `password`, `salt` keeps the same password from giving the same key
+    /// on different storages, it should be random, see [load_salt].
+    pub fn from_password(password: &str, salt: &[u8]) -> Self {
+        let mut key = [0u8; 32];
+        pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt, PASSWORD_ROUNDS, &mut key);
+        Self::new(key)
+    }
+
+    /// Encrypt `plain` stored under `key`, returns nonce followed by ciphertext.
+    pub fn seal(&self, key: &[u8], plain: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let payload = Payload {
+            msg: plain,
+            aad: key,
+        };
+        let sealed = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| Error::EncryptAtRest)?;
+        let mut data = nonce.to_vec();
+        data.extend(sealed);
+        Ok(data)
+    }
+
+    /// Decrypt what [StorageCipher::seal] returned for the same `key`.
+    pub fn open(&self, key: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return Err(Error::DecryptAtRest);
+        }
+        let (nonce, data) = sealed.split_at(NONCE_LEN);
+        let payload = Payload {
+            msg: data,
+            aad: key,
+        };
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| Error::DecryptAtRest)
+    }
+}
+
+/// Salt of password of storage at `path`, kept in file `<path>.salt`, created with random
+/// bytes if it's not there yet.
+#[cfg(not(feature = "wasm"))]
+pub fn load_salt(path: &str) -> Result<Vec<u8>> {
+    let salt_path = format!("{}.salt", path);
+    match std::fs::read(&salt_path) {
+        Ok(salt) if salt.len() == SALT_LEN => return Ok(salt),
+        Ok(_) => return Err(Error::StorageSalt(format!("{}: malformed", salt_path))),
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(Error::StorageSalt(format!("{}: {}", salt_path, e)))
+        }
+        Err(_) => {}
+    }
+    let mut salt = vec![0u8; SALT_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    std::fs::write(&salt_path, &salt)
+        .map_err(|e| Error::StorageSalt(format!("{}: {}", salt_path, e)))?;
+    Ok(salt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_storage_cipher() {
+        let key = SecretKey::random();
+        let cipher = StorageCipher::from_secret_key(&key);
+        let sealed = cipher.seal(b"k1", b"hello").unwrap();
+        assert_ne!(&sealed[NONCE_LEN..], b"hello");
+        assert_eq!(cipher.open(b"k1", &sealed).unwrap(), b"hello");
+        // nonce is random, sealing twice differs
+        assert_ne!(cipher.seal(b"k1", b"hello").unwrap(), sealed);
+        // bound to its key, can't be moved under another one
+        assert!(cipher.open(b"k2", &sealed).is_err());
+
+        let other = StorageCipher::from_secret_key(&SecretKey::random());
+        assert!(other.open(b"k1", &sealed).is_err());
+        assert!(cipher.open(b"k1", &sealed[..4]).is_err());
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    #[test]
+    fn test_password_salt() {
+        let path = format!("temp/cipher-{}", uuid::Uuid::new_v4());
+        let path = path.as_str();
+        std::fs::create_dir_all("temp").unwrap();
+        let salt = load_salt(path).unwrap();
+        assert_eq!(salt.len(), SALT_LEN);
+        // kept, so the same password opens storage again
+        assert_eq!(load_salt(path).unwrap(), salt);
+
+        let by_password = StorageCipher::from_password("secret", &salt);
+        let sealed = by_password.seal(b"k", b"hello").unwrap();
+        assert_eq!(
+            StorageCipher::from_password("secret", &salt)
+                .open(b"k", &sealed)
+                .unwrap(),
+            b"hello"
+        );
+        assert!(StorageCipher::from_password("guess", &salt)
+            .open(b"k", &sealed)
+            .is_err());
+        assert!(StorageCipher::from_password("secret", &[0u8; SALT_LEN])
+            .open(b"k", &sealed)
+            .is_err());
+        std::fs::remove_file(format!("{}.salt", path)).unwrap();
+    }
+}
@@ -0,0 +1,298 @@
+//! Persistence of virtual nodes stored in DHT, off handler paths.
+//!
+//! Handlers read and write virtual nodes of [crate::dht::PeerRing] in memory, while holding
+//! lock of DHT. With a persistent backend, every write of the memory storage is also recorded
+//! in a [Journal], and applied to the backend through the async storage traits by a
+//! [StorageTask] running in background. Handlers never wait for the backend, so a slow one
+//! can't stall message processing. Virtual nodes are loaded back from the backend by
+//! [StorageTask::open], page by page.
+//!
+//! Journal is compacted, only the latest write of a key waits to be applied, and it keeps
+//! writes of at most [MAX_JOURNAL_LEN] keys, the earliest ones are dropped when a backend
+//! falls that far behind. Memory is the source of truth, a dropped write only misses the
+//! backend until its key is written again.
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use futures::channel::mpsc;
+use futures::future::Either;
+use futures::Future;
+use futures::StreamExt;
+
+use super::MemStorage;
+use super::PersistenceStorageReadAndWrite;
+use super::PersistenceStorageRemove;
+use crate::dht::vnode::VirtualNode;
+use crate::dht::Did;
+use crate::err::Result;
+
+/// Keys with writes waiting in journal at most, see module doc.
+pub const MAX_JOURNAL_LEN: usize = 64 * 1024;
+/// Virtual nodes loaded from backend in one page, see [StorageTask::open].
+const LOAD_PAGE_SIZE: usize = 256;
+
+/// Write of [MemStorage], queued for persistent backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageOp<K, V> {
+    Put(K, V),
+    Remove(K),
+}
+
+impl<K: Copy, V> StorageOp<K, V> {
+    fn key(&self) -> K {
+        match self {
+            StorageOp::Put(k, _) => *k,
+            StorageOp::Remove(k) => *k,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct JournalState<K, V> {
+    /// Latest write of each key, with order it's recorded in.
+    ops: HashMap<K, (u64, StorageOp<K, V>)>,
+    next: u64,
+}
+
+/// Writes of [MemStorage] not applied to backend yet, see module doc.
+#[derive(Debug)]
+pub struct Journal<K, V> {
+    state: Mutex<JournalState<K, V>>,
+    /// Task is woken up already, writes are taken by it soon.
+    notified: AtomicBool,
+}
+
+impl<K, V> Journal<K, V>
+where
+    K: Copy + Eq + Hash,
+    V: Clone,
+{
+    fn new() -> Self {
+        Self {
+            state: Mutex::new(JournalState {
+                ops: HashMap::new(),
+                next: 0,
+            }),
+            notified: AtomicBool::new(false),
+        }
+    }
+
+    /// Record `op`, replacing earlier write of its key. Returns true if task should be woken.
+    fn record(&self, op: StorageOp<K, V>) -> bool {
+        if let Ok(mut state) = self.state.lock() {
+            let seq = state.next;
+            state.next += 1;
+            state.ops.insert(op.key(), (seq, op));
+            if state.ops.len() > MAX_JOURNAL_LEN {
+                let earliest = state
+                    .ops
+                    .iter()
+                    .min_by_key(|(_, (seq, _))| *seq)
+                    .map(|(k, _)| *k);
+                if let Some(k) = earliest {
+                    state.ops.remove(&k);
+                    tracing::warn!("storage journal is full, drop earliest write");
+                }
+            }
+        }
+        !self.notified.swap(true, Ordering::AcqRel)
+    }
+
+    /// Take all writes waiting, in order they are recorded.
+    fn take(&self) -> Vec<StorageOp<K, V>> {
+        self.notified.store(false, Ordering::Release);
+        let ops = match self.state.lock() {
+            Ok(mut state) => std::mem::take(&mut state.ops),
+            Err(_) => return vec![],
+        };
+        let mut ops = ops.into_values().collect::<Vec<_>>();
+        ops.sort_by_key(|(seq, _)| *seq);
+        ops.into_iter().map(|(_, op)| op).collect()
+    }
+
+    /// Count of keys with writes waiting.
+    pub fn len(&self) -> usize {
+        self.state.lock().map(|s| s.ops.len()).unwrap_or(0)
+    }
+
+    /// Whether no write is waiting.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Records writes of a [MemStorage] to its [Journal], and wakes up [StorageTask].
+#[derive(Debug, Clone)]
+pub struct JournalWriter<K, V> {
+    journal: Arc<Journal<K, V>>,
+    wake: mpsc::UnboundedSender<()>,
+}
+
+impl<K, V> JournalWriter<K, V>
+where
+    K: Copy + Eq + Hash,
+    V: Clone,
+{
+    pub(super) fn record(&self, op: StorageOp<K, V>) {
+        if self.journal.record(op) {
+            // memory is still the source of truth if storage task is stopped
+            self.wake.unbounded_send(()).ok();
+        }
+    }
+}
+
+/// Applies journaled writes of virtual nodes to a persistent backend.
+pub struct StorageTask<S> {
+    backend: S,
+    journal: Arc<Journal<Did, VirtualNode>>,
+    wake: mpsc::UnboundedReceiver<()>,
+}
+
+fn key_of(did: &Did) -> String {
+    format!("{:?}", **did)
+}
+
+impl<S> StorageTask<S>
+where S: PersistenceStorageReadAndWrite<String, VirtualNode> + PersistenceStorageRemove<String>
+{
+    /// Load virtual nodes stored in `backend`, returns memory storage of them, for
+    /// [crate::dht::PeerRing::new_with_storage], and the task persisting its writes.
+    pub async fn open(backend: S) -> Result<(MemStorage<Did, VirtualNode>, Self)> {
+        let storage = MemStorage::new();
+        let mut after: Option<String> = None;
+        loop {
+            let page: Vec<(String, VirtualNode)> =
+                backend.scan(after.as_ref(), LOAD_PAGE_SIZE).await?;
+            after = match page.last() {
+                Some((key, _)) => Some(key.clone()),
+                None => break,
+            };
+            for (key, vnode) in page {
+                match Did::from_str(&key) {
+                    Ok(did) => {
+                        storage.set(&did, vnode);
+                    }
+                    Err(_) => tracing::warn!(key = %key, "skip stored vnode of invalid key"),
+                }
+            }
+        }
+        tracing::info!("loaded {} stored vnodes", storage.len());
+        let journal = Arc::new(Journal::new());
+        let (wake_tx, wake) = mpsc::unbounded();
+        let writer = JournalWriter {
+            journal: journal.clone(),
+            wake: wake_tx,
+        };
+        Ok((storage.with_journal(writer), Self {
+            backend,
+            journal,
+            wake,
+        }))
+    }
+
+    /// Journal of writes waiting to be applied.
+    pub fn journal(&self) -> Arc<Journal<Did, VirtualNode>> {
+        self.journal.clone()
+    }
+
+    /// Apply journaled writes, until the memory storage is dropped.
+    pub async fn run(mut self) {
+        self.run_until(futures::future::pending()).await
+    }
+
+    /// Apply journaled writes, until `stop` resolves or the memory storage is dropped. Writes
+    /// journaled meanwhile are applied once it runs again.
+    pub async fn run_until<F>(&mut self, stop: F)
+    where F: Future<Output = ()> {
+        futures::pin_mut!(stop);
+        while let Either::Left((Some(()), _)) =
+            futures::future::select(self.wake.next(), &mut stop).await
+        {
+            self.apply().await;
+        }
+        // writes recorded before it's stopped or memory storage is dropped
+        self.apply().await;
+    }
+
+    async fn apply(&self) {
+        for op in self.journal.take() {
+            let result = match &op {
+                StorageOp::Put(did, vnode) => self.backend.put(&key_of(did), vnode).await,
+                StorageOp::Remove(did) => self.backend.remove(&key_of(did)).await,
+            };
+            if let Err(e) = result {
+                tracing::warn!("failed to persist vnode: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+    use crate::storage::PersistenceStorageOperation;
+    use crate::storage::Storage;
+
+    #[tokio::test]
+    async fn test_storage_task() {
+        let backend = Storage::new_with_cap_and_path(4096, "temp/journal_db")
+            .await
+            .unwrap();
+        backend.clear().await.unwrap();
+        let (storage, task) = StorageTask::open(backend).await.unwrap();
+        assert!(storage.is_empty());
+
+        let vnode: VirtualNode = "hello".to_owned().try_into().unwrap();
+        let did = vnode.did();
+        let other: Did = SecretKey::random().address().into();
+        storage.set(&did, vnode.clone());
+        storage.set(&other, vnode.clone());
+        storage.set(&other, vnode.clone());
+        storage.remove(&other);
+        // writes of a key are compacted
+        assert_eq!(task.journal().len(), 2);
+        // task stops after journaled writes are applied and memory storage is dropped
+        drop(storage);
+        task.run().await;
+
+        let backend = Storage::new_with_cap_and_path(4096, "temp/journal_db")
+            .await
+            .unwrap();
+        let (storage, mut task) = StorageTask::open(backend).await.unwrap();
+        assert_eq!(storage.get(&did), Some(vnode.clone()));
+        assert!(storage.get(&other).is_none());
+
+        // writes are applied when it's stopped, and ones journaled after when it runs again
+        storage.set(&other, vnode.clone());
+        task.run_until(async {}).await;
+        assert!(task.journal().is_empty());
+        storage.remove(&other);
+        assert_eq!(task.journal().len(), 1);
+        task.run_until(async {}).await;
+        assert!(task.journal().is_empty());
+    }
+
+    #[test]
+    fn test_journal_retention() {
+        let journal = Journal::<u64, u64>::new();
+        assert!(journal.record(StorageOp::Put(0, 0)));
+        // task is woken once until it takes writes
+        assert!(!journal.record(StorageOp::Put(1, 1)));
+        for k in 2..(MAX_JOURNAL_LEN as u64 + 1) {
+            journal.record(StorageOp::Put(k, k));
+        }
+        assert_eq!(journal.len(), MAX_JOURNAL_LEN);
+        let ops = journal.take();
+        // the earliest one is dropped
+        assert_eq!(ops.first(), Some(&StorageOp::Put(1, 1)));
+        assert!(journal.is_empty());
+        assert!(journal.record(StorageOp::Remove(1)));
+    }
+}
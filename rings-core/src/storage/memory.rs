@@ -2,6 +2,9 @@ use std::hash::Hash;
 
 use dashmap::DashMap;
 
+use super::journal::JournalWriter;
+use super::journal::StorageOp;
+
 #[derive(Clone, Debug, Default)]
 pub struct MemStorage<K, V>
 where
@@ -9,6 +12,8 @@ where
     V: Clone,
 {
     table: DashMap<K, V>,
+    /// Writes are also queued to it if it's set, see [super::journal].
+    journal: Option<JournalWriter<K, V>>,
 }
 
 impl<K, V> MemStorage<K, V>
@@ -19,6 +24,19 @@ where
     pub fn new() -> Self {
         Self {
             table: DashMap::default(),
+            journal: None,
+        }
+    }
+
+    /// Record writes to `journal`, to persist them by a [super::journal::StorageTask].
+    pub fn with_journal(mut self, journal: JournalWriter<K, V>) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    fn record(&self, op: StorageOp<K, V>) {
+        if let Some(journal) = &self.journal {
+            journal.record(op);
         }
     }
 
@@ -27,6 +45,9 @@ where
     }
 
     pub fn set(&self, addr: &K, value: V) -> Option<V> {
+        if self.journal.is_some() {
+            self.record(StorageOp::Put(*addr, value.clone()));
+        }
         self.table.insert(*addr, value)
     }
 
@@ -43,24 +64,26 @@ where
     }
 
     pub fn keys(&self) -> Vec<K> {
-        self.table.clone().into_iter().map(|(k, _v)| k).collect()
+        self.table.iter().map(|e| *e.key()).collect()
     }
 
     pub fn values(&self) -> Vec<V> {
-        self.table.clone().into_iter().map(|(_k, v)| v).collect()
+        self.table.iter().map(|e| e.value().clone()).collect()
     }
 
     pub fn items(&self) -> Vec<(K, V)> {
         self.table
-            .clone()
-            .into_iter()
-            .map(|(k, v)| (k, v))
+            .iter()
+            .map(|e| (*e.key(), e.value().clone()))
             .collect()
     }
 
     pub fn remove(&self, addr: &K) -> Option<(K, V)> {
         match self.get(addr) {
-            Some(_) => self.table.remove(addr),
+            Some(_) => {
+                self.record(StorageOp::Remove(*addr));
+                self.table.remove(addr)
+            }
             None => None,
         }
     }
@@ -68,9 +91,8 @@ where
 
 #[cfg(test)]
 mod tests {
-    use web3::types::Address;
-
     use super::*;
+    use crate::address::Address;
     use crate::ecc::SecretKey;
 
     #[test]
@@ -1,6 +1,13 @@
+pub mod cipher;
+pub mod journal;
 mod memory;
 pub mod persistence;
 
+pub use cipher::StorageCipher;
+pub use journal::Journal;
+pub use journal::JournalWriter;
+pub use journal::StorageOp;
+pub use journal::StorageTask;
 pub use memory::MemStorage;
 
 #[cfg(feature = "wasm")]
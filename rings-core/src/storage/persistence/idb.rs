@@ -144,6 +144,31 @@ where
             .collect::<Vec<(K, V)>>())
     }
 
+    async fn scan(&self, after: Option<&K>, limit: usize) -> Result<Vec<(K, V)>> {
+        let (_tx, store) = self.get_tx_store(TransactionMode::ReadOnly)?;
+        let range = match after {
+            Some(k) => Some(
+                rexie::KeyRange::lower_bound(&JsValue::from(k.to_string()), true)
+                    .map_err(Error::IDBError)?,
+            ),
+            None => None,
+        };
+        let limit = u32::try_from(limit).unwrap_or(u32::MAX);
+        let entries = store
+            .get_all(range.as_ref(), Some(limit), None, None)
+            .await
+            .map_err(Error::IDBError)?;
+        Ok(entries
+            .iter()
+            .filter_map(|(k, v)| {
+                Some((
+                    K::from(k.as_string()?),
+                    v.into_serde::<DataStruct<V>>().ok()?.data,
+                ))
+            })
+            .collect())
+    }
+
     async fn put(&self, key: &K, entry: &V) -> Result<()> {
         self.prune().await?;
         let (tx, store) = self.get_tx_store(TransactionMode::ReadWrite)?;
@@ -11,15 +11,32 @@ use super::PersistenceStorageReadAndWrite;
 use super::PersistenceStorageRemove;
 use crate::err::Error;
 use crate::err::Result;
+use crate::storage::StorageCipher;
 
 trait KvStorageBasic {
     fn get_db(&self) -> &sled::Db;
+    fn cipher(&self) -> Option<&StorageCipher>;
+
+    fn seal(&self, key: &[u8], data: Vec<u8>) -> Result<Vec<u8>> {
+        match self.cipher() {
+            Some(c) => c.seal(key, &data),
+            None => Ok(data),
+        }
+    }
+
+    fn open(&self, key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+        match self.cipher() {
+            Some(c) => c.open(key, data),
+            None => Ok(data.to_vec()),
+        }
+    }
 }
 
 /// StorageInstance struct
 pub struct KvStorage {
     db: sled::Db,
     cap: usize,
+    cipher: Option<StorageCipher>,
 }
 
 impl KvStorage {
@@ -34,7 +51,17 @@ impl KvStorage {
             .cache_capacity(cap as u64)
             .open()
             .map_err(Error::SledError)?;
-        Ok(Self { db, cap })
+        Ok(Self {
+            db,
+            cap,
+            cipher: None,
+        })
+    }
+
+    /// Encrypt values at rest with `cipher`, keys are kept in plain.
+    pub fn with_cipher(mut self, cipher: StorageCipher) -> Self {
+        self.cipher = Some(cipher);
+        self
     }
 
     /// New KvStorage with default path
@@ -49,12 +76,22 @@ impl KvStorage {
     pub async fn new() -> Result<Self> {
         Self::new_with_cap(200000000).await
     }
+
+    /// New KvStorage with default capacity 200 megabytes
+    pub async fn new_with_path<P>(path: P) -> Result<Self>
+    where P: AsRef<std::path::Path> {
+        Self::new_with_cap_and_path(200000000, path).await
+    }
 }
 
 impl KvStorageBasic for KvStorage {
     fn get_db(&self) -> &sled::Db {
         &self.db
     }
+
+    fn cipher(&self) -> Option<&StorageCipher> {
+        self.cipher.as_ref()
+    }
 }
 
 #[async_trait]
@@ -100,31 +137,69 @@ where
             .get(k)
             .map_err(Error::SledError)?
             .ok_or(Error::EntryNotFound)?;
-        bincode::deserialize(v.as_ref()).map_err(Error::BincodeDeserialize)
+        bincode::deserialize(&self.open(k, v.as_ref())?).map_err(Error::BincodeDeserialize)
     }
 
     /// Put `entry` in the cache under `key`.
     async fn put(&self, key: &K, value: &V) -> Result<()> {
         self.prune().await?;
-        let data = bincode::serialize(value).map_err(Error::BincodeSerialize)?;
+        let k = key.to_string();
+        let data = self.seal(
+            k.as_bytes(),
+            bincode::serialize(value).map_err(Error::BincodeSerialize)?,
+        )?;
         self.get_db()
-            .insert(key.to_string().as_bytes(), data)
+            .insert(k.as_bytes(), data)
             .map_err(Error::SledError)?;
         Ok(())
     }
 
     async fn get_all(&self) -> Result<Vec<(K, V)>> {
         let iter = self.get_db().iter();
-        Ok(iter
+        // values of a wrong key fail loudly, instead of being skipped like malformed ones
+        let entries = iter
             .flatten()
+            .map(|(k, v)| {
+                let v = self.open(k.as_ref(), v.as_ref())?;
+                Ok((k, v))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(entries
+            .into_iter()
             .flat_map(|(k, v)| {
                 Some((
                     K::from(std::str::from_utf8(k.as_ref()).ok()?.to_string()),
-                    bincode::deserialize(v.as_ref()).ok()?,
+                    bincode::deserialize(&v).ok()?,
                 ))
             })
             .collect_vec())
     }
+
+    async fn scan(&self, after: Option<&K>, limit: usize) -> Result<Vec<(K, V)>> {
+        let iter = match after {
+            Some(k) => self.get_db().range((
+                std::ops::Bound::Excluded(k.to_string().into_bytes()),
+                std::ops::Bound::Unbounded,
+            )),
+            None => self.get_db().iter(),
+        };
+        let mut entries = vec![];
+        for (k, v) in iter.flatten() {
+            if entries.len() >= limit {
+                break;
+            }
+            // values of a wrong key fail loudly, like in get_all
+            let v = self.open(k.as_ref(), v.as_ref())?;
+            let key = match std::str::from_utf8(k.as_ref()) {
+                Ok(k) => K::from(k.to_string()),
+                Err(_) => continue,
+            };
+            if let Ok(v) = bincode::deserialize(&v) {
+                entries.push((key, v));
+            }
+        }
+        Ok(entries)
+    }
 }
 
 #[async_trait]
@@ -190,6 +265,13 @@ mod test {
             all_entries.len()
         );
 
+        let page: Vec<(String, TestStorageStruct)> = storage.scan(None, 1).await.unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].0, key1);
+        let page: Vec<(String, TestStorageStruct)> = storage.scan(Some(&key1), 10).await.unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].0, key2);
+
         let keys = vec![key1, key2];
         let values = vec![data1.content, data2.content];
 
@@ -206,4 +288,33 @@ mod test {
         storage.get_db().flush_async().await.unwrap();
         drop(storage)
     }
+
+    #[tokio::test]
+    async fn test_kv_storage_cipher() {
+        let cipher = StorageCipher::new([7u8; 32]);
+        let storage = KvStorage::new_with_cap_and_path(4096, "temp/db_cipher")
+            .await
+            .unwrap()
+            .with_cipher(cipher.clone());
+        storage.clear().await.unwrap();
+        let key = "test".to_owned();
+        let data = TestStorageStruct {
+            content: "secret".to_string(),
+        };
+        storage.put(&key, &data).await.unwrap();
+        let raw = storage.get_db().get(key.as_bytes()).unwrap().unwrap();
+        assert_ne!(raw.as_ref(), bincode::serialize(&data).unwrap().as_slice());
+        let got: TestStorageStruct = storage.get(&key).await.unwrap();
+        assert_eq!(got.content, data.content);
+        drop(storage);
+
+        let storage = KvStorage::new_with_cap_and_path(4096, "temp/db_cipher")
+            .await
+            .unwrap()
+            .with_cipher(StorageCipher::new([8u8; 32]));
+        let got: Result<TestStorageStruct> = storage.get(&key).await;
+        assert!(matches!(got, Err(Error::DecryptAtRest)));
+        let all: Result<Vec<(String, TestStorageStruct)>> = storage.get_all().await;
+        assert!(all.is_err());
+    }
 }
@@ -21,6 +21,10 @@ pub trait PersistenceStorageReadAndWrite<K, V>: PersistenceStorageOperation {
     async fn put(&self, key: &K, entry: &V) -> Result<()>;
 
     async fn get_all(&self) -> Result<Vec<(K, V)>>;
+
+    /// Entries ordered by key, after `after` if it's given, at most `limit` of them, to read
+    /// storage page by page. Malformed entries are skipped.
+    async fn scan(&self, after: Option<&K>, limit: usize) -> Result<Vec<(K, V)>>;
 }
 
 /// Persistence Storage remove functions
@@ -0,0 +1,175 @@
+//! Key/value tags of peers, set by operator of node.
+//!
+//! Tags are local, they are never sent to others. They are used to manage fleets of relays,
+//! in ACL, a peer tagged `acl=deny` is refused by [crate::swarm::Swarm], and in routing, see
+//! [crate::dht::routing::TagPreferencePolicy]. On native, tags can be persisted in sled with
+//! [PeerTags::open].
+use std::collections::BTreeMap;
+
+use dashmap::DashMap;
+
+use crate::dht::Did;
+use crate::err::Error;
+use crate::err::Result;
+
+/// Tag key of ACL.
+pub const ACL_TAG: &str = "acl";
+/// Peers tagged `acl=deny` are refused.
+pub const ACL_DENY: &str = "deny";
+
+/// Tags of peers.
+#[derive(Debug, Default)]
+pub struct PeerTags {
+    tags: DashMap<Did, BTreeMap<String, String>>,
+    #[cfg(not(feature = "wasm"))]
+    db: Option<sled::Tree>,
+}
+
+impl PeerTags {
+    /// Tags kept in memory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open or create persisted tags at `path`.
+    #[cfg(not(feature = "wasm"))]
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        let db = sled::open(path)
+            .and_then(|db| db.open_tree("peer_tags"))
+            .map_err(Error::SledError)?;
+        let tags = DashMap::new();
+        for kv in db.iter() {
+            let (k, v) = kv.map_err(Error::SledError)?;
+            if k.len() != 20 {
+                return Err(Error::InvalidPeerTag(hex::encode(&k)));
+            }
+            let did: Did = crate::address::H160::from_slice(&k).into();
+            tags.insert(did, serde_json::from_slice(&v).map_err(Error::Deserialize)?);
+        }
+        Ok(Self { tags, db: Some(db) })
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    fn persist(&self, did: Did) -> Result<()> {
+        let db = match &self.db {
+            Some(db) => db,
+            None => return Ok(()),
+        };
+        match self.tags.get(&did) {
+            Some(tags) => {
+                let v = serde_json::to_vec(&*tags).map_err(Error::Serialize)?;
+                db.insert(did.as_bytes(), v).map_err(Error::SledError)?;
+            }
+            None => {
+                db.remove(did.as_bytes()).map_err(Error::SledError)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "wasm")]
+    fn persist(&self, _did: Did) -> Result<()> {
+        Ok(())
+    }
+
+    /// Set tag `key` of `did` to `value`.
+    pub fn set(&self, did: Did, key: &str, value: &str) -> Result<()> {
+        if key.is_empty() {
+            return Err(Error::InvalidPeerTag(key.to_owned()));
+        }
+        self.tags
+            .entry(did)
+            .or_default()
+            .insert(key.to_owned(), value.to_owned());
+        self.persist(did)
+    }
+
+    /// Remove tag `key` of `did`, returns false if it's not set.
+    pub fn remove(&self, did: Did, key: &str) -> Result<bool> {
+        let removed = match self.tags.get_mut(&did) {
+            Some(mut tags) => tags.remove(key).is_some(),
+            None => false,
+        };
+        self.tags.remove_if(&did, |_, tags| tags.is_empty());
+        if removed {
+            self.persist(did)?;
+        }
+        Ok(removed)
+    }
+
+    /// Move tags of `old` to `new`, like when identity of a peer is rotated, tags set to `new`
+    /// already are kept.
+    pub fn migrate(&self, old: Did, new: Did) -> Result<()> {
+        let tags = match self.tags.remove(&old) {
+            Some((_, tags)) => tags,
+            None => return Ok(()),
+        };
+        {
+            let mut entry = self.tags.entry(new).or_default();
+            for (k, v) in tags {
+                entry.entry(k).or_insert(v);
+            }
+        }
+        self.persist(old)?;
+        self.persist(new)
+    }
+
+    /// All tags of `did`.
+    pub fn get(&self, did: Did) -> BTreeMap<String, String> {
+        self.tags.get(&did).map(|t| t.clone()).unwrap_or_default()
+    }
+
+    /// Check if `did` is tagged `key=value`.
+    pub fn matches(&self, did: Did, key: &str, value: &str) -> bool {
+        self.tags
+            .get(&did)
+            .map_or(false, |t| t.get(key).map(|v| v.as_str()) == Some(value))
+    }
+
+    /// Peer is refused by ACL.
+    pub fn is_denied(&self, did: Did) -> bool {
+        self.matches(did, ACL_TAG, ACL_DENY)
+    }
+
+    /// All tagged peers.
+    pub fn items(&self) -> Vec<(Did, BTreeMap<String, String>)> {
+        self.tags
+            .iter()
+            .map(|kv| (*kv.key(), kv.value().clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    #[test]
+    fn test_peer_tags() {
+        let tags = PeerTags::new();
+        let did: Did = SecretKey::random().address().into();
+        tags.set(did, "region", "eu").unwrap();
+        assert!(tags.matches(did, "region", "eu"));
+        assert!(!tags.matches(did, "region", "us"));
+        assert!(!tags.is_denied(did));
+        tags.set(did, ACL_TAG, ACL_DENY).unwrap();
+        assert!(tags.is_denied(did));
+        assert_eq!(tags.get(did).len(), 2);
+
+        assert!(tags.remove(did, ACL_TAG).unwrap());
+        assert!(!tags.remove(did, ACL_TAG).unwrap());
+        assert!(tags.remove(did, "region").unwrap());
+        assert!(tags.items().is_empty());
+        assert!(tags.set(did, "", "x").is_err());
+
+        // denied peer is still denied after rotation
+        let new: Did = SecretKey::random().address().into();
+        tags.set(did, ACL_TAG, ACL_DENY).unwrap();
+        tags.set(new, "region", "us").unwrap();
+        tags.migrate(did, new).unwrap();
+        assert!(tags.is_denied(new));
+        assert!(tags.matches(new, "region", "us"));
+        assert!(tags.get(did).is_empty());
+    }
+}
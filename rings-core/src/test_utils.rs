@@ -0,0 +1,383 @@
+//! Utilities for unit tests, of this crate and of its users, needs feature `test-utils`.
+//!
+//! [KeyFixtures] makes keys whose DIDs are at chosen positions of ring, the same ones for the
+//! same seed, so topologies like wraparound or adjacent ids are made directly, instead of
+//! sorting random keys.
+//!
+//! [HandlerHarness] runs a handler of a node without transports: crafted payloads are queued by
+//! [HandlerHarness::inject] and handled in order by [HandlerHarness::step] or
+//! [HandlerHarness::run], passing the same checks as ones received by swarm, like ACL, network,
+//! protocol version and replay, and payloads sent by the handler
+//! are captured by [OutboundSink] instead of sent, to be checked by
+//! [HandlerHarness::take_sent].
+//!
+//! ```no_run
+//! # async fn run() -> rings_core::err::Result<()> {
+//! use rings_core::ecc::SecretKey;
+//! use rings_core::message::FindSuccessorSend;
+//! use rings_core::message::Message;
+//! use rings_core::test_utils::HandlerHarness;
+//!
+//! let harness = HandlerHarness::new()?;
+//! let peer = SecretKey::random();
+//! let msg = Message::FindSuccessorSend(FindSuccessorSend {
+//!     id: peer.address().into(),
+//!     for_fix: false,
+//!     finger: None,
+//! });
+//! harness.inject(harness.payload_from(&peer, msg)?);
+//! harness.run().await;
+//! let sent = harness.take_sent()?;
+//! assert!(matches!(sent[0].1.data, Message::FindSuccessorReport(_)));
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use futures::lock::Mutex as AsyncMutex;
+use num_bigint::BigUint;
+use rand::rngs::StdRng;
+use rand::RngCore;
+use rand::SeedableRng;
+
+use crate::address::Address;
+use crate::dht::Did;
+use crate::dht::PeerRing;
+use crate::ecc::SecretKey;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::Encoder;
+use crate::message::Message;
+use crate::message::MessageHandler;
+use crate::message::MessagePayload;
+use crate::session::SessionManager;
+use crate::swarm::Swarm;
+use crate::verify_pool;
+
+const ICE_SERVER: &str = "stun://stun.l.google.com:19302";
+/// Leading bits of DID matched to the wanted position by [KeyFixtures], so it's within 1/256
+/// of ring from it.
+pub const FIXTURE_PREFIX_BITS: u32 = 8;
+/// Leading bits shared by DIDs of [KeyFixtures::keys_adjacent], so they are within 1/4096 of
+/// ring from each other.
+pub const ADJACENT_PREFIX_BITS: u32 = 12;
+
+/// DID at `fraction` of ring, clockwise from zero, `fraction` is taken modulo 1.
+pub fn did_at(fraction: f64) -> Did {
+    let fraction = fraction.rem_euclid(1.0);
+    let scaled = (fraction * 2f64.powi(64)) as u64;
+    Did::from(BigUint::from(scaled) << 96u32)
+}
+
+fn prefix(did: Did, bits: u32) -> BigUint {
+    BigUint::from(did) >> (160 - bits.min(160))
+}
+
+/// Leading bits telling apart `n` positions spread evenly over ring, each in its own bucket.
+fn spread_bits(n: usize) -> u32 {
+    FIXTURE_PREFIX_BITS.max(usize::BITS - n.saturating_sub(1).leading_zeros())
+}
+
+/// Keys with DIDs at chosen positions of ring, deterministic by seed.
+pub struct KeyFixtures {
+    rng: StdRng,
+}
+
+impl KeyFixtures {
+    /// Fixtures of `seed`, the same seed makes the same keys.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Next key, at any position.
+    pub fn key(&mut self) -> SecretKey {
+        loop {
+            let mut bytes = [0u8; 32];
+            self.rng.fill_bytes(&mut bytes);
+            if let Ok(key) = libsecp256k1::SecretKey::parse(&bytes) {
+                return key.into();
+            }
+        }
+    }
+
+    /// Key whose DID has the same leading `bits` bits as `target`, it takes about `2^bits`
+    /// tries.
+    pub fn key_near(&mut self, target: Did, bits: u32) -> SecretKey {
+        let wanted = prefix(target, bits);
+        loop {
+            let key = self.key();
+            if prefix(key.address().into(), bits) == wanted {
+                return key;
+            }
+        }
+    }
+
+    /// Key whose DID is at `fraction` of ring, within [FIXTURE_PREFIX_BITS].
+    pub fn key_at(&mut self, fraction: f64) -> SecretKey {
+        self.key_near(did_at(fraction), FIXTURE_PREFIX_BITS)
+    }
+
+    /// Keys at each of `fractions`, in order of them.
+    pub fn keys_at(&mut self, fractions: &[f64]) -> Vec<SecretKey> {
+        fractions.iter().map(|f| self.key_at(*f)).collect()
+    }
+
+    /// `n` keys spread evenly over ring, in ascending order of DIDs. Each is matched to its
+    /// position by enough bits to keep neighbours apart, more than [FIXTURE_PREFIX_BITS] for
+    /// `n` over 256, so large `n` takes longer.
+    pub fn keys_spread(&mut self, n: usize) -> Vec<SecretKey> {
+        let bits = spread_bits(n);
+        (0..n)
+            .map(|i| self.key_near(did_at((i as f64 + 0.5) / n as f64), bits))
+            .collect()
+    }
+
+    /// `n` keys whose DIDs are next to each other, within [ADJACENT_PREFIX_BITS] of `fraction`
+    /// of ring, so no key at another position is between them, in ascending order of DIDs.
+    pub fn keys_adjacent(&mut self, fraction: f64, n: usize) -> Vec<SecretKey> {
+        let mut keys = (0..n)
+            .map(|_| self.key_near(did_at(fraction), ADJACENT_PREFIX_BITS))
+            .collect::<Vec<_>>();
+        keys.sort_by_key(|k| k.address());
+        keys
+    }
+}
+
+/// Payloads sent by swarm, captured instead of sent when it's set by
+/// [SwarmBuilder::with_outbound_sink](crate::swarm::SwarmBuilder::with_outbound_sink).
+#[derive(Debug, Default)]
+pub struct OutboundSink {
+    sent: Mutex<Vec<(Address, Vec<u8>)>>,
+}
+
+impl OutboundSink {
+    /// Capture encoded `payload` sent to `address`.
+    pub fn push(&self, address: Address, payload: Vec<u8>) {
+        if let Ok(mut sent) = self.sent.lock() {
+            sent.push((address, payload));
+        }
+    }
+
+    /// Take all captured payloads, in order of sending.
+    pub fn take(&self) -> Vec<(Address, Vec<u8>)> {
+        self.sent
+            .lock()
+            .map(|mut sent| std::mem::take(&mut *sent))
+            .unwrap_or_default()
+    }
+}
+
+/// A [MessageHandler] fed by injected payloads, with sent ones captured, see module doc.
+pub struct HandlerHarness {
+    swarm: Arc<Swarm>,
+    handler: Arc<MessageHandler>,
+    inbound: Mutex<VecDeque<MessagePayload<Message>>>,
+    outbound: Arc<OutboundSink>,
+}
+
+impl HandlerHarness {
+    /// Harness of a node with a random key.
+    pub fn new() -> Result<Self> {
+        Self::new_with_key(&SecretKey::random())
+    }
+
+    /// Harness of node of `key`.
+    pub fn new_with_key(key: &SecretKey) -> Result<Self> {
+        let session = SessionManager::new_with_seckey(key)?;
+        let outbound = Arc::new(OutboundSink::default());
+        let swarm = Arc::new(
+            Swarm::builder(key.address(), session)
+                .with_ice_servers(ICE_SERVER)
+                .with_outbound_sink(outbound.clone())
+                .build()?,
+        );
+        let dht = Arc::new(AsyncMutex::new(PeerRing::new(key.address().into())));
+        Ok(Self {
+            handler: Arc::new(MessageHandler::new(dht, swarm.clone())),
+            swarm,
+            inbound: Mutex::new(VecDeque::new()),
+            outbound,
+        })
+    }
+
+    /// DID of node.
+    pub fn did(&self) -> Did {
+        self.swarm.address().into()
+    }
+
+    pub fn handler(&self) -> Arc<MessageHandler> {
+        self.handler.clone()
+    }
+
+    pub fn swarm(&self) -> Arc<Swarm> {
+        self.swarm.clone()
+    }
+
+    pub fn dht(&self) -> Arc<AsyncMutex<PeerRing>> {
+        self.handler.dht()
+    }
+
+    /// Payload of `msg` sent directly to node by peer of `key`.
+    pub fn payload_from(&self, key: &SecretKey, msg: Message) -> Result<MessagePayload<Message>> {
+        let session = SessionManager::new_with_seckey(key)?;
+        MessagePayload::new_direct(msg, &session, self.did())
+    }
+
+    /// Queue `payload`, as if it's received by swarm.
+    pub fn inject(&self, payload: MessagePayload<Message>) {
+        if let Ok(mut inbound) = self.inbound.lock() {
+            inbound.push_back(payload);
+        }
+    }
+
+    /// Handle next queued payload, None if queue is empty. Payloads are checked by swarm like
+    /// received ones, and dropped with its error if they fail, like
+    /// [Error::VerifySignatureFailed]. Payloads forwarded by swarm are not handled.
+    pub async fn step(&self) -> Option<Result<()>> {
+        let payload = self.inbound.lock().ok()?.pop_front()?;
+        Some(self.receive(payload).await)
+    }
+
+    async fn receive(&self, payload: MessagePayload<Message>) -> Result<()> {
+        let from = payload.addr;
+        let inbound = verify_pool::prepare(from, payload.encode()?.as_bytes().to_vec())?;
+        match self.swarm.load_payload(inbound).await? {
+            Some(payload) => {
+                // like [MessageHandler::listen_once], by clocks sampled while loading
+                if !self.swarm.verify_expiry(&payload) {
+                    tracing::debug!(tx_id = ?payload.tx_id, "payload is expired");
+                }
+                self.handler.handle_observed(&payload).await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Handle queued payloads until queue is empty, including ones injected meanwhile, returns
+    /// result of each.
+    pub async fn run(&self) -> Vec<Result<()>> {
+        let mut results = vec![];
+        while let Some(result) = self.step().await {
+            results.push(result);
+        }
+        results
+    }
+
+    /// Take payloads sent by handler, with DIDs they are sent to, in order of sending.
+    pub fn take_sent(&self) -> Result<Vec<(Did, MessagePayload<Message>)>> {
+        self.outbound
+            .take()
+            .into_iter()
+            .map(|(address, data)| Ok((address.into(), MessagePayload::from_json(&data)?)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "wasm"))]
+    use crate::message::FindSuccessorSend;
+
+    #[test]
+    fn test_key_fixtures() {
+        assert_eq!(did_at(0.0), Did::from(BigUint::from(0u8)));
+        assert_eq!(did_at(0.5), Did::from(BigUint::from(1u8) << 159u32));
+        assert_eq!(did_at(1.25), did_at(0.25));
+
+        let keys = KeyFixtures::new(7).keys_at(&[0.999, 0.001, 0.5]);
+        let again = KeyFixtures::new(7).keys_at(&[0.999, 0.001, 0.5]);
+        assert_eq!(keys, again);
+        let dids = keys
+            .iter()
+            .map(|k| Did::from(k.address()))
+            .collect::<Vec<_>>();
+        // wraparound, 0.999 is before 0.001 clockwise
+        assert!(dids[0] > dids[2] && dids[2] > dids[1]);
+        for (did, f) in dids.iter().zip([0.999, 0.001, 0.5]) {
+            assert_eq!(
+                prefix(*did, FIXTURE_PREFIX_BITS),
+                prefix(did_at(f), FIXTURE_PREFIX_BITS)
+            );
+        }
+
+        let spread = KeyFixtures::new(7).keys_spread(4);
+        assert!(spread.windows(2).all(|w| w[0].address() < w[1].address()));
+        // buckets of neighbours are apart, with more bits than 8 for over 256 keys
+        for n in [1, 4, 129, 256, 257, 1000] {
+            let bits = spread_bits(n);
+            let buckets = (0..n)
+                .map(|i| prefix(did_at((i as f64 + 0.5) / n as f64), bits))
+                .collect::<Vec<_>>();
+            assert!(buckets.windows(2).all(|w| w[0] < w[1]), "n: {}", n);
+        }
+        assert_eq!(spread_bits(256), FIXTURE_PREFIX_BITS);
+        assert_eq!(spread_bits(257), FIXTURE_PREFIX_BITS + 1);
+
+        let mut fixtures = KeyFixtures::new(7);
+        let adjacent = fixtures.keys_adjacent(0.25, 3);
+        let others = fixtures.keys_at(&[0.2, 0.3]);
+        assert!(adjacent.windows(2).all(|w| w[0].address() < w[1].address()));
+        assert!(others[0].address() < adjacent[0].address());
+        assert!(adjacent[2].address() < others[1].address());
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    #[tokio::test]
+    async fn test_handler_harness() -> Result<()> {
+        let harness = HandlerHarness::new()?;
+        let peer = SecretKey::random();
+        let msg = Message::FindSuccessorSend(FindSuccessorSend {
+            id: peer.address().into(),
+            for_fix: true,
+            finger: None,
+        });
+        harness.inject(harness.payload_from(&peer, msg)?);
+
+        // payload changed after signed is dropped
+        let mut forged = harness.payload_from(
+            &peer,
+            Message::FindSuccessorSend(FindSuccessorSend {
+                id: peer.address().into(),
+                for_fix: true,
+                finger: None,
+            }),
+        )?;
+        forged.data = Message::FindSuccessorSend(FindSuccessorSend {
+            id: harness.did(),
+            for_fix: true,
+            finger: None,
+        });
+        harness.inject(forged);
+
+        // checks of swarm apply, a replayed payload is dropped
+        let replayed = harness.payload_from(
+            &peer,
+            Message::FindSuccessorSend(FindSuccessorSend {
+                id: peer.address().into(),
+                for_fix: false,
+                finger: None,
+            }),
+        )?;
+        harness.inject(replayed.clone());
+        harness.inject(replayed);
+
+        let results = harness.run().await;
+        assert_eq!(results.len(), 4);
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(Error::VerifySignatureFailed)));
+        assert!(results[2].is_ok());
+        assert!(matches!(results[3], Err(Error::ReplayedPayload(_))));
+
+        let sent = harness.take_sent()?;
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0].0, Did::from(peer.address()));
+        assert!(matches!(sent[0].1.data, Message::FindSuccessorReport(_)));
+        assert!(harness.take_sent()?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+//! Timers and background tasks working on native and in browsers.
+//!
+//! Native timers are driven by `futures_timer`, and tasks run on tokio with feature `tokio`,
+//! otherwise each on a thread of its own. In browsers, timers are
+//! `setTimeout` of the global scope, so they work in pages and workers, and tasks run on the
+//! event loop by `spawn_local`. Background loops like [Stabilization](crate::dht::Stabilization)
+//! are built on them, so they run by themselves on both platforms, without host page driving
+//! them.
+use std::future::Future;
+use std::time::Duration;
+
+use futures::future::AbortHandle;
+use futures::future::Abortable;
+use futures::future::Either;
+use futures::pin_mut;
+
+use crate::utils;
+
+/// Wait for `duration`.
+#[cfg(not(feature = "wasm"))]
+pub async fn sleep(duration: Duration) {
+    futures_timer::Delay::new(duration).await;
+}
+
+/// Wait for `duration`.
+#[cfg(feature = "wasm")]
+pub async fn sleep(duration: Duration) {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen::JsValue;
+
+    let ms = duration.as_millis().min(i32::MAX as u128) as i32;
+    let promise = js_sys::Promise::new(&mut |resolve, reject| {
+        let global = js_sys::global();
+        let scheduled = js_sys::Reflect::get(&global, &JsValue::from_str("setTimeout"))
+            .and_then(|f| f.dyn_into::<js_sys::Function>().map_err(JsValue::from))
+            .and_then(|f| f.call2(&global, &resolve, &JsValue::from(ms)));
+        if let Err(e) = scheduled {
+            reject.call1(&JsValue::NULL, &e).ok();
+        }
+    });
+    if wasm_bindgen_futures::JsFuture::from(promise).await.is_err() {
+        // firing at once would make loops waiting on it spin
+        tracing::error!("setTimeout is unavailable, timer never fires");
+        futures::future::pending::<()>().await;
+    }
+}
+
+/// Wait for `fut` at most `duration`, None if it's not done by then.
+pub async fn timeout<F: Future>(duration: Duration, fut: F) -> Option<F::Output> {
+    let delay = sleep(duration);
+    pin_mut!(fut);
+    pin_mut!(delay);
+    match futures::future::select(fut, delay).await {
+        Either::Left((output, _)) => Some(output),
+        Either::Right(_) => None,
+    }
+}
+
+/// Ticks every `period`, without drift. Ticks missed while consumer is busy are skipped.
+#[derive(Debug, Clone)]
+pub struct Interval {
+    period: Duration,
+    next_ms: u128,
+}
+
+impl Interval {
+    /// First tick is `period` later.
+    pub fn new(period: Duration) -> Self {
+        Self {
+            period,
+            next_ms: utils::get_epoch_ms() + period.as_millis(),
+        }
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Tick every `period` from now on, next tick is `period` later.
+    pub fn set_period(&mut self, period: Duration) {
+        *self = Self::new(period);
+    }
+
+    /// Wait for next tick.
+    pub async fn tick(&mut self) {
+        let now = utils::get_epoch_ms();
+        if self.next_ms > now {
+            sleep(Duration::from_millis((self.next_ms - now) as u64)).await;
+        }
+        self.next_ms = self.advance(utils::get_epoch_ms());
+    }
+
+    /// Next tick after `now`.
+    fn advance(&self, now: u128) -> u128 {
+        let period = self.period.as_millis().max(1);
+        let next = self.next_ms + period;
+        if next > now {
+            next
+        } else {
+            next + (now - next) / period * period + period
+        }
+    }
+}
+
+/// Handle of a task spawned by [spawn]. Dropping the handle doesn't stop the task.
+#[derive(Debug, Clone)]
+pub struct TaskHandle(AbortHandle);
+
+impl TaskHandle {
+    /// Stop the task at its next await point.
+    pub fn abort(&self) {
+        self.0.abort()
+    }
+}
+
+/// Run `task` in background on tokio.
+#[cfg(all(not(feature = "wasm"), feature = "tokio"))]
+pub fn spawn<F>(task: F) -> TaskHandle
+where F: std::future::Future<Output = ()> + Send + 'static {
+    let (handle, registration) = AbortHandle::new_pair();
+    tokio::spawn(async move {
+        Abortable::new(task, registration).await.ok();
+    });
+    TaskHandle(handle)
+}
+
+/// Run `task` in background on a thread of its own.
+#[cfg(all(not(feature = "wasm"), not(feature = "tokio")))]
+pub fn spawn<F>(task: F) -> TaskHandle
+where F: std::future::Future<Output = ()> + Send + 'static {
+    let (handle, registration) = AbortHandle::new_pair();
+    std::thread::spawn(move || {
+        futures::executor::block_on(Abortable::new(task, registration)).ok();
+    });
+    TaskHandle(handle)
+}
+
+/// Run `task` in background on event loop.
+#[cfg(feature = "wasm")]
+pub fn spawn<F>(task: F) -> TaskHandle
+where F: std::future::Future<Output = ()> + 'static {
+    let (handle, registration) = AbortHandle::new_pair();
+    wasm_bindgen_futures::spawn_local(async move {
+        Abortable::new(task, registration).await.ok();
+    });
+    TaskHandle(handle)
+}
+
+#[cfg(not(feature = "wasm"))]
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_interval_skips_missed_ticks() {
+        let interval = Interval {
+            period: Duration::from_millis(100),
+            next_ms: 1_000,
+        };
+        assert_eq!(interval.advance(1_050), 1_100);
+        // consumer was busy for 3 periods
+        assert_eq!(interval.advance(1_350), 1_400);
+        assert_eq!(interval.advance(1_100), 1_200);
+    }
+
+    #[tokio::test]
+    async fn test_spawn_and_abort() {
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let counter = ticks.clone();
+        let task = spawn(async move {
+            let mut interval = Interval::new(Duration::from_millis(20));
+            loop {
+                interval.tick().await;
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        sleep(Duration::from_millis(110)).await;
+        task.abort();
+        let stopped = ticks.load(Ordering::Relaxed);
+        assert!(stopped >= 3);
+        sleep(Duration::from_millis(60)).await;
+        assert_eq!(ticks.load(Ordering::Relaxed), stopped);
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        assert_eq!(
+            timeout(Duration::from_millis(50), async { 1 }).await,
+            Some(1)
+        );
+        let slow = sleep(Duration::from_millis(200));
+        assert!(timeout(Duration::from_millis(20), slow).await.is_none());
+    }
+}
@@ -0,0 +1,343 @@
+//! Topics, append-only logs named in DHT, as backend of pubsub and history of messages.
+//!
+//! Publishers append signed [TopicRecord]s to a topic, stored together at
+//! [VirtualNode::topic_address] of its name. Records of a publisher form a chain, each one has
+//! the next sequence number and digest of its previous record, so history can't be rewritten
+//! without being noticed.
+//!
+//! Two records of a publisher with the same sequence number is a fork, which happens when a
+//! publisher appends from two devices, or equivocates on purpose. Forked records are all kept
+//! as evidence, and [TopicLog] resolves them when it's read: the branch reaching the highest
+//! sequence number wins, earlier one on a tie, and the others are reported as [TopicFork]s.
+//!
+//! Records are verified once, when they arrive at the node storing the topic, and kept in
+//! order of arrival there, so the ones arrived earliest are dropped first when it's full.
+//! Timestamps are chosen by signers, they never decide which records are kept.
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::dht::vnode::VNodeType;
+use crate::dht::vnode::VirtualNode;
+use crate::dht::Did;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::Decoder;
+use crate::message::Encoded;
+use crate::message::Encoder;
+use crate::message::MessageVerification;
+use crate::session::SessionManager;
+use crate::utils;
+
+/// Records kept in a topic at most, the ones arrived earliest are dropped first.
+pub const MAX_TOPIC_RECORDS: usize = 4096;
+
+/// One append to a topic.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct TopicEntry {
+    pub topic: String,
+    pub publisher: Did,
+    /// Sequence number of entry among ones of publisher, starts from 0.
+    pub seq: u64,
+    /// Digest of previous record of publisher, None for the first one.
+    pub prev: Option<String>,
+    pub data: String,
+}
+
+/// Entry signed by its publisher, never expires.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct TopicRecord {
+    pub entry: TopicEntry,
+    pub verification: MessageVerification,
+}
+
+impl TopicRecord {
+    /// Sign an entry appended after `prev` of the publisher, see [TopicLog::next].
+    pub fn new(
+        session_manager: &SessionManager,
+        topic: &str,
+        (seq, prev): (u64, Option<String>),
+        data: &str,
+    ) -> Result<Self> {
+        let entry = TopicEntry {
+            topic: topic.to_owned(),
+            publisher: session_manager.authorizer()?.into(),
+            seq,
+            prev,
+            data: data.to_owned(),
+        };
+        let ts_ms = utils::get_epoch_ms();
+        let ttl_ms = usize::MAX;
+        let msg = MessageVerification::pack_msg(&entry, ts_ms, ttl_ms)?;
+        let verification = MessageVerification {
+            session: session_manager.session()?,
+            sig: session_manager.sign(&msg)?,
+            ttl_ms,
+            ts_ms,
+        };
+        Ok(Self {
+            entry,
+            verification,
+        })
+    }
+
+    /// Hex of sha256 of signed entry, referred by next record of publisher.
+    pub fn digest(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(serde_json::to_vec(&self.entry).unwrap_or_default());
+        hasher.update(&self.verification.sig);
+        hex::encode(hasher.finalize())
+    }
+
+    /// Check signature, and record is signed by its publisher.
+    pub fn verify(&self) -> bool {
+        Did::from(self.verification.session.auth.authorizer) == self.entry.publisher
+            && self.verification.verify(&self.entry)
+    }
+
+    fn encode(&self) -> Result<Encoded> {
+        serde_json::to_string(self)
+            .map_err(Error::Serialize)?
+            .encode()
+    }
+
+    fn decode(encoded: &Encoded) -> Result<Self> {
+        let s = String::from_encoded(encoded)?;
+        serde_json::from_str(&s).map_err(Error::Deserialize)
+    }
+
+    pub fn to_vnode(&self) -> Result<VirtualNode> {
+        Ok(VirtualNode {
+            address: VirtualNode::topic_address(&self.entry.topic)?,
+            data: vec![self.encode()?],
+            kind: VNodeType::Topic,
+        })
+    }
+
+    /// Valid records of a topic vnode, forked ones included.
+    pub fn from_vnode(vnode: &VirtualNode) -> Result<Vec<Self>> {
+        if vnode.kind != VNodeType::Topic {
+            return Err(Error::InvalidVNodeType);
+        }
+        Ok(vnode
+            .data
+            .iter()
+            .filter_map(|d| Self::decode(d).ok())
+            .filter(|r| {
+                VirtualNode::topic_address(&r.entry.topic).ok() == Some(vnode.address) && r.verify()
+            })
+            .collect())
+    }
+
+    /// Records of a topic vnode stored first, every one should be valid, see [VirtualNode::check].
+    pub fn check_vnode(vnode: &VirtualNode) -> Result<Vec<Self>> {
+        if vnode.kind != VNodeType::Topic {
+            return Err(Error::InvalidVNodeType);
+        }
+        if vnode.data.len() > MAX_TOPIC_RECORDS {
+            return Err(Error::InvalidTopicRecord);
+        }
+        let records = Self::from_vnode(vnode)?;
+        if records.len() != vnode.data.len() {
+            return Err(Error::InvalidTopicRecord);
+        }
+        Ok(records)
+    }
+
+    /// Merge stored topic vnode `a` with incoming `b`. Records of `a` are verified when they
+    /// are stored, only new valid records of `b` are verified and appended, and at most
+    /// [MAX_TOPIC_RECORDS] are kept, the ones arrived earliest are dropped.
+    pub(crate) fn merge(a: &VirtualNode, b: &VirtualNode) -> Result<VirtualNode> {
+        if a.address != b.address {
+            return Err(Error::AddressNotEqual);
+        }
+        if a.kind != VNodeType::Topic || b.kind != VNodeType::Topic {
+            return Err(Error::InvalidVNodeType);
+        }
+        let mut seen = a.data.iter().collect::<HashSet<_>>();
+        let mut data = a.data.clone();
+        for d in b.data.iter() {
+            if !seen.insert(d) {
+                continue;
+            }
+            let valid = Self::decode(d).ok().filter(|r| {
+                VirtualNode::topic_address(&r.entry.topic).ok() == Some(a.address) && r.verify()
+            });
+            if valid.is_some() {
+                data.push(d.clone());
+            }
+        }
+        let dropped = data.len().saturating_sub(MAX_TOPIC_RECORDS);
+        data.drain(..dropped);
+        Ok(VirtualNode {
+            address: a.address,
+            data,
+            kind: VNodeType::Topic,
+        })
+    }
+}
+
+/// Records of a publisher forked at `seq`, the ones not on winning branch are `dropped`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct TopicFork {
+    pub publisher: Did,
+    pub seq: u64,
+    pub kept: TopicRecord,
+    pub dropped: Vec<TopicRecord>,
+}
+
+/// History of a topic with forks resolved, see module doc.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct TopicLog {
+    /// Records on winning branch of each publisher, by time they are signed.
+    pub records: Vec<TopicRecord>,
+    pub forks: Vec<TopicFork>,
+}
+
+impl TopicLog {
+    pub fn from_vnode(vnode: &VirtualNode) -> Result<Self> {
+        Ok(Self::resolve(TopicRecord::from_vnode(vnode)?))
+    }
+
+    /// Resolve forks of `records`, see module doc.
+    pub fn resolve(records: Vec<TopicRecord>) -> Self {
+        // digest of each record is computed once
+        let mut by_publisher: BTreeMap<Did, Vec<(String, TopicRecord)>> = BTreeMap::new();
+        for r in records {
+            by_publisher
+                .entry(r.entry.publisher)
+                .or_default()
+                .push((r.digest(), r));
+        }
+        let mut log = Self::default();
+        for (publisher, mut records) in by_publisher {
+            // highest seq first, earlier one on a tie
+            records.sort_by(|(da, a), (db, b)| {
+                (b.entry.seq, a.verification.ts_ms, da).cmp(&(
+                    a.entry.seq,
+                    b.verification.ts_ms,
+                    db,
+                ))
+            });
+            let by_digest = records
+                .iter()
+                .map(|(d, r)| (d.as_str(), r))
+                .collect::<BTreeMap<_, _>>();
+            // walk back from head by digest of previous record
+            let mut chain = vec![];
+            let mut next = records.first().map(|(_, r)| r);
+            while let Some(r) = next {
+                next = r
+                    .entry
+                    .prev
+                    .as_deref()
+                    .and_then(|prev| by_digest.get(prev).copied())
+                    .filter(|p| p.entry.seq + 1 == r.entry.seq);
+                chain.push(r.clone());
+            }
+            let mut by_seq: BTreeMap<u64, Vec<&TopicRecord>> = BTreeMap::new();
+            for (_, r) in records.iter() {
+                by_seq.entry(r.entry.seq).or_default().push(r);
+            }
+            for kept in chain.iter() {
+                let dropped = by_seq
+                    .get(&kept.entry.seq)
+                    .into_iter()
+                    .flatten()
+                    .filter(|r| **r != kept)
+                    .map(|r| (*r).clone())
+                    .collect::<Vec<_>>();
+                if !dropped.is_empty() {
+                    tracing::warn!(publisher = ?publisher, seq = kept.entry.seq, "topic forked");
+                    log.forks.push(TopicFork {
+                        publisher,
+                        seq: kept.entry.seq,
+                        kept: kept.clone(),
+                        dropped,
+                    });
+                }
+            }
+            log.records.extend(chain);
+        }
+        log.records
+            .sort_by_key(|r| (r.verification.ts_ms, r.entry.publisher, r.entry.seq));
+        log.forks.sort_by_key(|f| (f.publisher, f.seq));
+        log
+    }
+
+    /// Sequence number and previous digest of next record of `publisher`.
+    pub fn next(&self, publisher: Did) -> (u64, Option<String>) {
+        self.records
+            .iter()
+            .filter(|r| r.entry.publisher == publisher)
+            .max_by_key(|r| r.entry.seq)
+            .map(|r| (r.entry.seq + 1, Some(r.digest())))
+            .unwrap_or((0, None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    #[test]
+    fn test_topic_log() {
+        let alice = SessionManager::new_with_seckey(&SecretKey::random()).unwrap();
+        let bob = SessionManager::new_with_seckey(&SecretKey::random()).unwrap();
+        let alice_did: Did = alice.authorizer().unwrap().into();
+
+        let a0 = TopicRecord::new(&alice, "news", (0, None), "a0").unwrap();
+        assert!(a0.verify());
+        let vnode = a0.to_vnode().unwrap();
+        assert_eq!(vnode.address, VirtualNode::topic_address("news").unwrap());
+        let log = TopicLog::from_vnode(&vnode).unwrap();
+        let a1 = TopicRecord::new(&alice, "news", log.next(alice_did), "a1").unwrap();
+        assert_eq!(a1.entry.prev, Some(a0.digest()));
+        let b0 = TopicRecord::new(&bob, "news", (0, None), "b0").unwrap();
+
+        // alice forks at seq 1, the branch reaching seq 2 wins
+        let a1_fork = TopicRecord::new(&alice, "news", (1, Some(a0.digest())), "a1'").unwrap();
+        let a2_fork = TopicRecord::new(&alice, "news", (2, Some(a1_fork.digest())), "a2'").unwrap();
+
+        let mut merged = vnode;
+        for r in [&a1, &b0, &a1_fork, &a2_fork, &a1] {
+            merged = TopicRecord::merge(&merged, &r.to_vnode().unwrap()).unwrap();
+        }
+        assert_eq!(merged.data.len(), 5);
+        // kept in order of arrival, not of timestamps chosen by signers
+        let arrived = [&a0, &a1, &b0, &a1_fork, &a2_fork]
+            .iter()
+            .map(|r| r.to_vnode().unwrap().data[0].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(merged.data, arrived);
+        let log = TopicLog::from_vnode(&merged).unwrap();
+        let data = log
+            .records
+            .iter()
+            .filter(|r| r.entry.publisher == alice_did)
+            .map(|r| r.entry.data.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(data, vec!["a0", "a1'", "a2'"]);
+        assert_eq!(log.records.len(), 4);
+        assert_eq!(log.forks.len(), 1);
+        assert_eq!(log.forks[0].seq, 1);
+        assert_eq!(log.forks[0].kept, a1_fork);
+        assert_eq!(log.forks[0].dropped, vec![a1]);
+        assert_eq!(log.next(alice_did), (3, Some(a2_fork.digest())));
+
+        // record of another topic is dropped
+        let other = TopicRecord::new(&bob, "other", (0, None), "x").unwrap();
+        let mut forged = other.to_vnode().unwrap();
+        forged.address = merged.address;
+        let merged = TopicRecord::merge(&merged, &forged).unwrap();
+        assert_eq!(merged.data.len(), 5);
+        // and refused when it's stored first
+        assert!(merged.check().is_ok());
+        assert!(forged.check().is_err());
+    }
+}
@@ -0,0 +1,174 @@
+//! Traffic of swarm with each peer, in messages and bytes by message type.
+//!
+//! Swarm counts every payload it sends to or receives from a connected peer, see
+//! [crate::swarm::Swarm::peer_traffic]. Message type is read from head of JSON of payload, so
+//! relayed payloads are counted without their bodies decoded.
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::capture::Direction;
+use crate::dht::Did;
+
+/// Message types counted for each peer at most, later ones are counted as [OTHER_TYPE].
+pub const MAX_MESSAGE_TYPES: usize = 64;
+/// Message type of payloads which can't be told, or over [MAX_MESSAGE_TYPES].
+pub const OTHER_TYPE: &str = "Other";
+/// Message type names longer than this are counted as [OTHER_TYPE].
+const MAX_TYPE_LEN: usize = 64;
+
+/// Messages and bytes of encoded payloads.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrafficCounter {
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+impl TrafficCounter {
+    fn add(&mut self, bytes: usize) {
+        self.messages += 1;
+        self.bytes += bytes as u64;
+    }
+}
+
+/// Traffic with a peer, since it's connected.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PeerTrafficStats {
+    pub did: Did,
+    pub sent: TrafficCounter,
+    pub received: TrafficCounter,
+    /// Sent by message type, like `JoinDHT`.
+    pub sent_by_type: BTreeMap<String, TrafficCounter>,
+    /// Received by message type, like `JoinDHT`.
+    pub received_by_type: BTreeMap<String, TrafficCounter>,
+}
+
+impl PeerTrafficStats {
+    fn new(did: Did) -> Self {
+        Self {
+            did,
+            sent: TrafficCounter::default(),
+            received: TrafficCounter::default(),
+            sent_by_type: BTreeMap::new(),
+            received_by_type: BTreeMap::new(),
+        }
+    }
+
+    fn record(&mut self, direction: Direction, message_type: &str, bytes: usize) {
+        let (total, by_type) = match direction {
+            Direction::Outbound => (&mut self.sent, &mut self.sent_by_type),
+            Direction::Inbound => (&mut self.received, &mut self.received_by_type),
+        };
+        total.add(bytes);
+        let known = message_type.len() <= MAX_TYPE_LEN
+            && (by_type.contains_key(message_type) || by_type.len() < MAX_MESSAGE_TYPES);
+        let key = if known { message_type } else { OTHER_TYPE };
+        by_type.entry(key.to_owned()).or_default().add(bytes);
+    }
+}
+
+/// Counters of traffic with each peer.
+#[derive(Debug, Default)]
+pub struct PeerTraffic(Mutex<BTreeMap<Did, PeerTrafficStats>>);
+
+impl PeerTraffic {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a payload of `bytes` sent to or received from `peer`.
+    pub fn record(&self, direction: Direction, peer: Did, message_type: &str, bytes: usize) {
+        if let Ok(mut peers) = self.0.lock() {
+            peers
+                .entry(peer)
+                .or_insert_with(|| PeerTrafficStats::new(peer))
+                .record(direction, message_type, bytes);
+        }
+    }
+
+    /// Drop counters of `peer`, once it's disconnected.
+    pub fn forget(&self, peer: Did) {
+        if let Ok(mut peers) = self.0.lock() {
+            peers.remove(&peer);
+        }
+    }
+
+    /// Traffic with `peer`, if any is counted.
+    pub fn peer(&self, peer: Did) -> Option<PeerTrafficStats> {
+        self.0.lock().ok()?.get(&peer).cloned()
+    }
+
+    /// Traffic with all peers, ordered by DID.
+    pub fn snapshot(&self) -> Vec<PeerTrafficStats> {
+        self.0
+            .lock()
+            .map(|peers| peers.values().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Variant name of a message in JSON, like `JoinDHT` of `{"JoinDHT":{..}}`, or of a payload
+/// starting with its data, like `{"data":{"JoinDHT":{..}},..}`.
+pub fn message_type(json: &[u8]) -> &str {
+    let rest = match json.strip_prefix(br#"{"data":"#) {
+        Some(data) => data,
+        None => json,
+    };
+    let rest = rest.strip_prefix(b"{").unwrap_or(rest);
+    rest.strip_prefix(b"\"")
+        .and_then(|name| {
+            let end = name.iter().position(|b| *b == b'"')?;
+            std::str::from_utf8(&name[..end]).ok()
+        })
+        .filter(|name| !name.is_empty())
+        .unwrap_or(OTHER_TYPE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    #[test]
+    fn test_message_type() {
+        assert_eq!(message_type(br#"{"JoinDHT":{"id":"0x11"}}"#), "JoinDHT");
+        assert_eq!(
+            message_type(br#"{"data":{"LeaveDHT":{"id":"0x11"}},"tx_id":"1"}"#),
+            "LeaveDHT"
+        );
+        assert_eq!(message_type(br#"{"data":"Ping","tx_id":"1"}"#), "Ping");
+        assert_eq!(message_type(b"[1,2]"), OTHER_TYPE);
+        assert_eq!(message_type(b""), OTHER_TYPE);
+    }
+
+    #[test]
+    fn test_peer_traffic() {
+        let traffic = PeerTraffic::new();
+        let peer: Did = SecretKey::random().address().into();
+        traffic.record(Direction::Outbound, peer, "JoinDHT", 100);
+        traffic.record(Direction::Outbound, peer, "JoinDHT", 50);
+        traffic.record(Direction::Inbound, peer, "LeaveDHT", 10);
+        let stats = traffic.peer(peer).unwrap();
+        assert_eq!(stats.sent, TrafficCounter {
+            messages: 2,
+            bytes: 150
+        });
+        assert_eq!(stats.sent_by_type["JoinDHT"].messages, 2);
+        assert_eq!(stats.received.bytes, 10);
+        assert_eq!(stats.received_by_type["LeaveDHT"].bytes, 10);
+
+        // types sent by a peer are bounded
+        for i in 0..MAX_MESSAGE_TYPES + 10 {
+            traffic.record(Direction::Inbound, peer, &format!("T{}", i), 1);
+        }
+        let stats = traffic.peer(peer).unwrap();
+        assert_eq!(stats.received_by_type.len(), MAX_MESSAGE_TYPES + 1);
+        assert_eq!(stats.received_by_type[OTHER_TYPE].messages, 11);
+
+        assert_eq!(traffic.snapshot().len(), 1);
+        traffic.forget(peer);
+        assert!(traffic.peer(peer).is_none());
+    }
+}
@@ -1,4 +1,6 @@
+use std::net::IpAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_lock::RwLock as AsyncRwLock;
 use async_trait::async_trait;
@@ -7,19 +9,25 @@ use futures::future::join_all;
 use futures::future::BoxFuture;
 use futures::lock::Mutex as FuturesMutex;
 use serde_json;
-use web3::types::Address;
+use webrtc::api::setting_engine::SettingEngine;
 use webrtc::api::APIBuilder;
 use webrtc::data_channel::data_channel_message::DataChannelMessage;
 use webrtc::data_channel::data_channel_state::RTCDataChannelState;
 use webrtc::data_channel::RTCDataChannel;
+use webrtc::ice::candidate::CandidateType;
+use webrtc::ice::network_type::NetworkType;
 use webrtc::ice_transport::ice_candidate::RTCIceCandidate;
 use webrtc::ice_transport::ice_connection_state::RTCIceConnectionState;
 use webrtc::peer_connection::configuration::RTCConfiguration;
 use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::policy::ice_transport_policy::RTCIceTransportPolicy;
 use webrtc::peer_connection::sdp::sdp_type::RTCSdpType;
 use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
 use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::stats::StatsReport;
+use webrtc::stats::StatsReportType;
 
+use crate::address::Address;
 use crate::channels::Channel as AcChannel;
 use crate::ecc::PublicKey;
 use crate::err::Error;
@@ -28,26 +36,58 @@ use crate::message::Encoded;
 use crate::message::Encoder;
 use crate::message::MessagePayload;
 use crate::session::SessionManager;
+use crate::transports::helper::check_sdp;
+use crate::transports::helper::CancelToken;
 use crate::transports::helper::Promise;
+use crate::transports::helper::TrafficCounters;
 use crate::transports::helper::TricklePayload;
+#[cfg(feature = "mock")]
+use crate::transports::mock::MockHub;
+#[cfg(feature = "mock")]
+use crate::transports::mock::MockTransport;
 use crate::types::channel::Channel;
 use crate::types::channel::Event;
+use crate::types::ice_transport::address_family;
+use crate::types::ice_transport::HandshakeMeta;
 use crate::types::ice_transport::IceCandidate;
 use crate::types::ice_transport::IceServer;
 use crate::types::ice_transport::IceTransport;
 use crate::types::ice_transport::IceTransportCallback;
+use crate::types::ice_transport::IceTransportPolicy;
 use crate::types::ice_transport::IceTrickleScheme;
+use crate::types::ice_transport::IpFamily;
+use crate::types::ice_transport::TransportStats;
 
 type EventSender = <AcChannel<Event> as Channel<Event>>::Sender;
 
+/// Return `$e` of the mock transport backing `$self`, if it's backed by one.
+macro_rules! or_mock {
+    ($self:ident, |$m:ident| $e:expr) => {
+        #[cfg(feature = "mock")]
+        if let Some($m) = &$self.mock {
+            return $e;
+        }
+    };
+}
+
+/// Transport over WebRTC, or over a [MockHub] if it's made by [DefaultTransport::new_mock],
+/// which has no peer connection then.
+///
+/// [MockHub]: crate::transports::mock::MockHub
 #[derive(Clone)]
 pub struct DefaultTransport {
     pub id: uuid::Uuid,
+    #[cfg(feature = "mock")]
+    mock: Option<MockTransport>,
     connection: Arc<FuturesMutex<Option<Arc<RTCPeerConnection>>>>,
     pending_candidates: Arc<FuturesMutex<Vec<RTCIceCandidate>>>,
     data_channel: Arc<FuturesMutex<Option<Arc<RTCDataChannel>>>>,
     event_sender: EventSender,
     public_key: Arc<AsyncRwLock<Option<PublicKey>>>,
+    remote_key: Arc<AsyncRwLock<Option<Address>>>,
+    local_meta: Arc<AsyncRwLock<HandshakeMeta>>,
+    remote_meta: Arc<AsyncRwLock<Option<HandshakeMeta>>>,
+    traffic: Arc<TrafficCounters>,
 }
 
 impl PartialEq for DefaultTransport {
@@ -74,22 +114,55 @@ impl IceTransport<Event, AcChannel<Event>> for DefaultTransport {
     fn new(event_sender: EventSender) -> Self {
         Self {
             id: uuid::Uuid::new_v4(),
+            #[cfg(feature = "mock")]
+            mock: None,
             connection: Arc::new(FuturesMutex::new(None)),
             pending_candidates: Arc::new(FuturesMutex::new(vec![])),
             data_channel: Arc::new(FuturesMutex::new(None)),
             public_key: Arc::new(AsyncRwLock::new(None)),
+            remote_key: Arc::new(AsyncRwLock::new(None)),
+            local_meta: Arc::new(AsyncRwLock::new(HandshakeMeta::default())),
+            remote_meta: Arc::new(AsyncRwLock::new(None)),
+            traffic: Arc::new(TrafficCounters::default()),
             event_sender,
         }
     }
 
     async fn start(&mut self, ice_server: &IceServer) -> Result<&Self> {
+        #[cfg(feature = "mock")]
+        if let Some(mock) = self.mock.as_mut() {
+            mock.start(ice_server).await?;
+            return Ok(self);
+        }
+        let (policy, family) = {
+            let meta = self.local_meta.read().await;
+            (meta.ice_transport_policy, meta.ip_family)
+        };
+        let ice_transport_policy = match policy {
+            IceTransportPolicy::Relay => RTCIceTransportPolicy::Relay,
+            _ => RTCIceTransportPolicy::All,
+        };
+        let mut setting_engine = SettingEngine::default();
+        if policy == IceTransportPolicy::NoHost {
+            // host candidates are gathered from no interface, srflx ones still are
+            setting_engine.set_interface_filter(Box::new(|_: &str| false));
+        }
+        // candidates of a disabled family are never gathered, nor used by agent
+        match family {
+            IpFamily::Ipv4 => setting_engine.set_network_types(vec![NetworkType::Udp4]),
+            IpFamily::Ipv6 => setting_engine.set_network_types(vec![NetworkType::Udp6]),
+            _ => (),
+        }
         let config = RTCConfiguration {
             ice_servers: vec![ice_server.clone().into()],
             ice_candidate_pool_size: 100,
+            ice_transport_policy,
             ..Default::default()
         };
 
-        let api = APIBuilder::new().build();
+        let api = APIBuilder::new()
+            .with_setting_engine(setting_engine)
+            .build();
         match api.new_peer_connection(config).await {
             Ok(c) => {
                 let mut conn = self.connection.lock().await;
@@ -104,6 +177,7 @@ impl IceTransport<Event, AcChannel<Event>> for DefaultTransport {
     }
 
     async fn close(&self) -> Result<()> {
+        or_mock!(self, |m| m.close().await);
         if let Some(pc) = self.get_peer_connection().await {
             pc.close()
                 .await
@@ -114,6 +188,7 @@ impl IceTransport<Event, AcChannel<Event>> for DefaultTransport {
     }
 
     async fn ice_connection_state(&self) -> Option<Self::IceConnectionState> {
+        or_mock!(self, |m| m.ice_connection_state().await);
         self.get_peer_connection()
             .await
             .map(|pc| pc.ice_connection_state())
@@ -126,8 +201,14 @@ impl IceTransport<Event, AcChannel<Event>> for DefaultTransport {
             .unwrap_or(false)
     }
 
-    async fn pubkey(&self) -> PublicKey {
-        self.public_key.read().await.unwrap()
+    async fn pubkey(&self) -> Option<PublicKey> {
+        or_mock!(self, |m| m.pubkey().await);
+        *self.public_key.read().await
+    }
+
+    async fn remote_key(&self) -> Option<Address> {
+        or_mock!(self, |m| m.remote_key().await);
+        *self.remote_key.read().await
     }
 
     async fn get_peer_connection(&self) -> Option<Arc<RTCPeerConnection>> {
@@ -188,6 +269,7 @@ impl IceTransport<Event, AcChannel<Event>> for DefaultTransport {
     }
 
     async fn send_message(&self, msg: &[u8]) -> Result<()> {
+        or_mock!(self, |m| m.send_message(msg).await);
         let size = msg.len();
         match self.get_data_channel().await {
             Some(cnn) => match cnn.send(&Bytes::from(msg.to_vec())).await {
@@ -195,6 +277,7 @@ impl IceTransport<Event, AcChannel<Event>> for DefaultTransport {
                     if !s == size {
                         Err(Error::RTCDataChannelMessageIncomplete(s, size))
                     } else {
+                        self.traffic.sent(size);
                         Ok(())
                     }
                 }
@@ -210,6 +293,17 @@ impl IceTransport<Event, AcChannel<Event>> for DefaultTransport {
         }
     }
 
+    async fn stats(&self) -> TransportStats {
+        or_mock!(self, |m| m.stats().await);
+        let mut stats = self.traffic.stats();
+        if let Some(pc) = self.get_peer_connection().await {
+            if let Some(pair) = SelectedPair::from_report(&pc.get_stats().await) {
+                pair.apply(&mut stats);
+            }
+        }
+        stats
+    }
+
     async fn add_ice_candidate(&self, candidate: IceCandidate) -> Result<()> {
         match self.get_peer_connection().await {
             Some(peer_connection) => peer_connection
@@ -243,6 +337,68 @@ impl IceTransport<Event, AcChannel<Event>> for DefaultTransport {
     }
 }
 
+/// Candidate pair nominated by ICE, read from stats of peer connection.
+#[derive(Debug, Clone, PartialEq)]
+struct SelectedPair {
+    /// Latest RTT of connectivity checks, in seconds, 0 if none is answered yet.
+    rtt_secs: f64,
+    /// Connectivity checks sent, and answered, the others are lost.
+    requests_sent: u64,
+    responses_received: u64,
+    local_type: CandidateType,
+    remote_type: CandidateType,
+    remote_ip: String,
+}
+
+impl SelectedPair {
+    fn from_report(report: &StatsReport) -> Option<Self> {
+        let pair = report.reports.values().find_map(|r| match r {
+            StatsReportType::CandidatePair(pair) if pair.nominated => Some(pair),
+            _ => None,
+        })?;
+        let candidate = |id: &str| match report.reports.get(id) {
+            Some(StatsReportType::LocalCandidate(c))
+            | Some(StatsReportType::RemoteCandidate(c)) => Some(c),
+            _ => None,
+        };
+        let local = candidate(&pair.local_candidate_id)?;
+        let remote = candidate(&pair.remote_candidate_id)?;
+        Some(Self {
+            rtt_secs: pair.current_round_trip_time,
+            requests_sent: pair.requests_sent,
+            responses_received: pair.responses_received,
+            local_type: local.candidate_type,
+            remote_type: remote.candidate_type,
+            remote_ip: remote.ip.clone(),
+        })
+    }
+
+    /// Fill RTT, loss, candidate types and address family of `stats`.
+    fn apply(&self, stats: &mut TransportStats) {
+        stats.rtt_ms = (self.rtt_secs > 0.0).then(|| (self.rtt_secs * 1000.0).round() as u64);
+        stats.packets_lost = Some(self.requests_sent.saturating_sub(self.responses_received));
+        stats.local_candidate_type = Some(candidate_type(self.local_type).to_owned());
+        stats.remote_candidate_type = Some(candidate_type(self.remote_type).to_owned());
+        stats.address_family = self
+            .remote_ip
+            .trim_matches(|c| c == '[' || c == ']')
+            .parse::<IpAddr>()
+            .ok()
+            .map(|ip| address_family(&ip).to_owned());
+    }
+}
+
+/// Name of candidate type, as the one of browsers.
+fn candidate_type(typ: CandidateType) -> &'static str {
+    match typ {
+        CandidateType::Host => "host",
+        CandidateType::ServerReflexive => "srflx",
+        CandidateType::PeerReflexive => "prflx",
+        CandidateType::Relay => "relay",
+        _ => "unknown",
+    }
+}
+
 impl DefaultTransport {
     pub async fn setup_channel(&mut self, name: &str) -> Result<()> {
         match self.get_peer_connection().await {
@@ -272,6 +428,7 @@ impl IceTransportCallback<Event, AcChannel<Event>> for DefaultTransport {
         Box<(dyn FnMut(RTCIceConnectionState) -> BoxFuture<'static, ()> + Sync + Send + 'static)>;
 
     async fn apply_callback(&self) -> Result<&Self> {
+        or_mock!(self, |m| m.apply_callback().await.map(|_| self));
         let on_ice_candidate_callback = self.on_ice_candidate().await;
         let on_data_channel_callback = self.on_data_channel().await;
         let on_ice_connection_state_change_callback = self.on_ice_connection_state_change().await;
@@ -331,12 +488,27 @@ impl IceTransportCallback<Event, AcChannel<Event>> for DefaultTransport {
     async fn on_ice_candidate(&self) -> Self::OnLocalCandidateHdlrFn {
         let peer_connection = self.get_peer_connection().await;
         let pending_candidates = Arc::clone(&self.pending_candidates);
+        let local_meta = Arc::clone(&self.local_meta);
 
         box move |c: Option<<Self as IceTransport<Event, AcChannel<Event>>>::Candidate>| {
             let peer_connection = peer_connection.clone();
             let pending_candidates = Arc::clone(&pending_candidates);
+            let local_meta = Arc::clone(&local_meta);
             Box::pin(async move {
                 if let Some(candidate) = c {
+                    let meta = local_meta.read().await.clone();
+                    let allowed = match candidate.to_json().await {
+                        Ok(c) => meta.allows_candidate(&c.candidate),
+                        Err(_) => false,
+                    };
+                    if !allowed {
+                        log::debug!(
+                            "drop candidate by ice transport policy {} and ip family {}",
+                            meta.ice_transport_policy,
+                            meta.ip_family
+                        );
+                        return;
+                    }
                     if let Some(peer_connection) = peer_connection {
                         let desc = peer_connection.remote_description().await;
                         if desc.is_none() {
@@ -351,16 +523,29 @@ impl IceTransportCallback<Event, AcChannel<Event>> for DefaultTransport {
 
     async fn on_data_channel(&self) -> Self::OnDataChannelHdlrFn {
         let event_sender = self.event_sender.clone();
+        let traffic = self.traffic.clone();
+        let public_key = Arc::clone(&self.public_key);
 
         box move |d: Arc<RTCDataChannel>| {
             let event_sender = event_sender.clone();
+            let traffic = traffic.clone();
+            let public_key = Arc::clone(&public_key);
             Box::pin(async move {
                 d.on_message(Box::new(move |msg: DataChannelMessage| {
                     log::debug!("Message from DataChannel: '{:?}'", msg);
+                    traffic.received(msg.data.len());
                     let event_sender = event_sender.clone();
+                    let public_key = Arc::clone(&public_key);
                     Box::pin(async move {
+                        let remote = match *public_key.read().await {
+                            Some(pk) => pk.address(),
+                            None => {
+                                log::warn!("drop message before remote info is registered");
+                                return;
+                            }
+                        };
                         if event_sender
-                            .send(Event::DataChannelMessage(msg.data.to_vec()))
+                            .send(Event::DataChannelMessage(remote, msg.data.to_vec()))
                             .await
                             .is_err()
                         {
@@ -387,8 +572,9 @@ impl IceTrickleScheme<Event, AcChannel<Event>> for DefaultTransport {
         session_manager: &SessionManager,
         kind: RTCSdpType,
     ) -> Result<Encoded> {
+        or_mock!(self, |m| m.get_handshake_info(session_manager, kind).await);
         log::trace!("prepareing handshake info {:?}", kind);
-        let sdp = match kind {
+        let mut sdp = match kind {
             RTCSdpType::Answer => self.get_answer().await?,
             RTCSdpType::Offer => self.get_offer().await?,
             kind => {
@@ -397,16 +583,24 @@ impl IceTrickleScheme<Event, AcChannel<Event>> for DefaultTransport {
                 sdp
             }
         };
-        let local_candidates_json = join_all(
+        let meta = self.local_meta.read().await.clone();
+        // candidates gathered into sdp are never filtered by callback
+        sdp.sdp = meta.filter_sdp(&sdp.sdp);
+        let mut local_candidates_json: Vec<IceCandidate> = join_all(
             self.get_pending_candidates()
                 .await
                 .iter()
                 .map(async move |c| c.clone().to_json().await.unwrap().into()),
         )
         .await;
+        local_candidates_json.sort_by_key(|c| meta.ip_family.rank(&c.candidate));
+        for c in local_candidates_json.iter_mut() {
+            c.candidate = meta.scrub_candidate(&c.candidate);
+        }
         let data = TricklePayload {
             sdp: serde_json::to_string(&sdp).unwrap(),
             candidates: local_candidates_json,
+            meta,
         };
         log::trace!("prepared hanshake info :{:?}", data);
         let resp = MessagePayload::new_direct(
@@ -414,44 +608,80 @@ impl IceTrickleScheme<Event, AcChannel<Event>> for DefaultTransport {
             session_manager,
             session_manager.authorizer()?.to_owned().into(), // This is a fake destination
         )?;
-        Ok(resp.gzip(9)?.encode()?)
+        Ok(resp.encode()?)
     }
 
     async fn register_remote_info(&self, data: Encoded) -> Result<Address> {
-        let data: MessagePayload<TricklePayload> = data.decode()?;
+        or_mock!(self, |m| m.register_remote_info(data).await);
+        let data = TricklePayload::decode_checked(&data)?;
         log::trace!("register remote info: {:?}", data);
-        match data.verify() {
-            true => {
-                let sdp = serde_json::from_str::<RTCSessionDescription>(&data.data.sdp)
-                    .map_err(Error::Deserialize)?;
-                log::trace!("setting remote sdp: {:?}", sdp);
-                self.set_remote_description(sdp).await?;
-                log::trace!("setting remote candidate");
-                for c in &data.data.candidates {
-                    log::trace!("add candiates: {:?}", c);
-                    self.add_ice_candidate(c.clone()).await?;
-                }
-                if let Ok(public_key) = data.origin_verification.session.authorizer_pubkey() {
-                    let mut pk = self.public_key.write().await;
-                    *pk = Some(public_key);
-                };
-                Ok(data.addr)
-            }
-            _ => {
-                log::error!("cannot verify message sig");
-                return Err(Error::VerifySignatureFailed);
-            }
+        let local_meta = self.local_meta.read().await.clone();
+        if data.data.meta.network_id != local_meta.network_id {
+            return Err(Error::NetworkIdMismatch(
+                data.data.meta.network_id.clone(),
+                local_meta.network_id,
+            ));
+        }
+        let remote_meta = local_meta.negotiate(&data.data.meta)?;
+        let sdp = serde_json::from_str::<RTCSessionDescription>(&data.data.sdp)
+            .map_err(Error::Deserialize)?;
+        check_sdp(&sdp.sdp)?;
+        log::trace!("setting remote sdp: {:?}", sdp);
+        self.set_remote_description(sdp).await?;
+        log::trace!("setting remote candidate");
+        for c in &data.data.candidates {
+            log::trace!("add candiates: {:?}", c);
+            self.add_ice_candidate(c.clone()).await?;
         }
+        if let Ok(public_key) = data.origin_verification.session.authorizer_pubkey() {
+            let mut pk = self.public_key.write().await;
+            *pk = Some(public_key);
+        };
+        *self.remote_key.write().await = Some(data.origin_verification.session.authorized_key());
+        let mut meta = self.remote_meta.write().await;
+        *meta = Some(remote_meta);
+        Ok(data.addr)
     }
 
     async fn wait_for_connected(&self) -> Result<()> {
         let promise = self.connect_success_promise().await?;
         promise.await
     }
+
+    async fn wait_for_connected_timeout(
+        &self,
+        timeout: Duration,
+        cancel: &CancelToken,
+    ) -> Result<()> {
+        let promise = self.connect_success_promise().await?;
+        promise.wait(timeout, cancel).await
+    }
+
+    async fn set_local_meta(&self, meta: HandshakeMeta) {
+        or_mock!(self, |m| m.set_local_meta(meta).await);
+        let mut m = self.local_meta.write().await;
+        *m = meta;
+    }
+
+    async fn remote_meta(&self) -> Option<HandshakeMeta> {
+        or_mock!(self, |m| m.remote_meta().await);
+        self.remote_meta.read().await.clone()
+    }
 }
 
 impl DefaultTransport {
+    /// Transport backed by a mock one in `hub`, see [crate::transports::mock].
+    #[cfg(feature = "mock")]
+    pub fn new_mock(hub: &MockHub, event_sender: EventSender) -> Self {
+        let mock = MockTransport::new_in(hub, event_sender.clone());
+        let mut transport = Self::new(event_sender);
+        transport.id = mock.id;
+        transport.mock = Some(mock);
+        transport
+    }
+
     pub async fn wait_for_data_channel_open(&self) -> Result<()> {
+        or_mock!(self, |m| m.wait_for_data_channel_open().await);
         match self.get_data_channel().await {
             Some(dc) => {
                 if dc.ready_state() == RTCDataChannelState::Open {
@@ -462,12 +692,7 @@ impl DefaultTransport {
                     dc.on_open(box move || {
                         let state = Arc::clone(&state);
                         Box::pin(async move {
-                            let mut s = state.lock().unwrap();
-                            if let Some(w) = s.waker.take() {
-                                s.completed = true;
-                                s.successed = Some(true);
-                                w.wake();
-                            }
+                            state.lock().unwrap().complete(true);
                         })
                     })
                     .await;
@@ -482,6 +707,7 @@ impl DefaultTransport {
     }
 
     pub async fn connect_success_promise(&self) -> Result<Promise> {
+        or_mock!(self, |m| m.connect_success_promise().await);
         match self.get_peer_connection().await {
             Some(peer_connection) => {
                 let promise = Promise::default();
@@ -493,20 +719,10 @@ impl DefaultTransport {
                         Box::pin(async move {
                             match st {
                                 RTCPeerConnectionState::Connected => {
-                                    let mut s = state.lock().unwrap();
-                                    if let Some(w) = s.waker.take() {
-                                        s.completed = true;
-                                        s.successed = Some(true);
-                                        w.wake();
-                                    }
+                                    state.lock().unwrap().complete(true);
                                 }
                                 RTCPeerConnectionState::Failed => {
-                                    let mut s = state.lock().unwrap();
-                                    if let Some(w) = s.waker.take() {
-                                        s.completed = true;
-                                        s.successed = Some(false);
-                                        w.wake();
-                                    }
+                                    state.lock().unwrap().complete(false);
                                 }
                                 _ => {
                                     log::trace!("Connect State changed to {:?}", st);
@@ -515,14 +731,13 @@ impl DefaultTransport {
                         })
                     })
                     .await;
-                let current_state = peer_connection.connection_state();
-                if RTCPeerConnectionState::Connected == current_state {
-                    let mut s = state_clone.lock().unwrap();
-                    if let Some(w) = s.waker.take() {
-                        s.completed = true;
-                        s.successed = Some(true);
-                        w.wake();
+                // state may change before the promise is polled
+                match peer_connection.connection_state() {
+                    RTCPeerConnectionState::Connected => state_clone.lock().unwrap().complete(true),
+                    RTCPeerConnectionState::Failed | RTCPeerConnectionState::Closed => {
+                        state_clone.lock().unwrap().complete(false)
                     }
+                    _ => {}
                 };
                 Ok(promise)
             }
@@ -627,6 +842,33 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_selected_pair_stats() {
+        let mut pair = SelectedPair {
+            rtt_secs: 0.0425,
+            requests_sent: 10,
+            responses_received: 8,
+            local_type: CandidateType::Relay,
+            remote_type: CandidateType::Host,
+            remote_ip: "fe80::1".to_owned(),
+        };
+        let mut stats = TransportStats::default();
+        pair.apply(&mut stats);
+        assert_eq!(stats.rtt_ms, Some(43));
+        assert_eq!(stats.packets_lost, Some(2));
+        assert_eq!(stats.local_candidate_type.as_deref(), Some("relay"));
+        assert_eq!(stats.remote_candidate_type.as_deref(), Some("host"));
+        assert_eq!(stats.address_family.as_deref(), Some("ipv6"));
+        assert!(stats.is_relayed());
+
+        // no check answered yet, RTT is unknown
+        pair.rtt_secs = 0.0;
+        pair.remote_ip = "10.0.0.1".to_owned();
+        pair.apply(&mut stats);
+        assert_eq!(stats.rtt_ms, None);
+        assert_eq!(stats.address_family.as_deref(), Some("ipv4"));
+    }
+
     #[tokio::test]
     async fn test_ice_connection_establish() -> Result<()> {
         let transport1 = prepare_transport().await?;
@@ -634,6 +876,11 @@ pub mod tests {
 
         establish_connection(&transport1, &transport2).await?;
 
+        // selected pair is read from stats of peer connection
+        let stats = transport1.stats().await;
+        assert_eq!(stats.local_candidate_type.as_deref(), Some("host"));
+        assert!(stats.address_family.is_some());
+
         Ok(())
     }
 }
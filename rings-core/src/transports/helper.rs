@@ -1,16 +1,32 @@
+use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::task::Context;
 use std::task::Poll;
+use std::task::Waker;
+use std::time::Duration;
 
+use futures::future;
+use futures::future::Either;
 use serde::Deserialize;
 use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
 
+use crate::address::Address;
 use crate::err::Error;
 use crate::err::Result;
+use crate::message::Encoded;
+use crate::message::MessagePayload;
+use crate::timer;
+use crate::types::ice_transport::HandshakeMeta;
 use crate::types::ice_transport::IceCandidate;
+use crate::types::ice_transport::TransportStats;
+use crate::utils;
 
 #[derive(Default)]
 pub struct State {
@@ -19,10 +35,206 @@ pub struct State {
     pub waker: Option<std::task::Waker>,
 }
 
+impl State {
+    /// Complete the promise, even if it's never polled yet.
+    pub fn complete(&mut self, success: bool) {
+        self.completed = true;
+        self.successed = Some(success);
+        if let Some(w) = self.waker.take() {
+            w.wake();
+        }
+    }
+}
+
+/// Handshake info larger than this is refused before it's decoded, in bytes of encoded form.
+pub const MAX_HANDSHAKE_INFO_SIZE: usize = 64 * 1024;
+/// Handshake info decompressed to more than this is refused, in bytes of JSON.
+pub const MAX_HANDSHAKE_INFO_DECODED_SIZE: usize = 256 * 1024;
+/// Handshake info with more candidates than this is refused.
+pub const MAX_HANDSHAKE_CANDIDATES: usize = 64;
+/// Longest candidate accepted, in bytes.
+const MAX_CANDIDATE_LEN: usize = 512;
+/// Nonce of HTTP handshake expires after it, see [HandshakeNonces].
+pub const HANDSHAKE_NONCE_TTL_MS: u128 = 60 * 1000;
+/// Nonces of HTTP handshake waiting for offers at most, no more are issued until some are
+/// used or expired, so issued ones are never flushed by others.
+const MAX_HANDSHAKE_NONCES: usize = 1024;
+/// Answers of offers are kept for retries of the same offer this long, see [AnsweredOffers].
+pub const ANSWERED_OFFER_TTL_MS: u128 = 60 * 1000;
+/// Answered offers kept at most, the earliest ones are dropped first.
+const MAX_ANSWERED_OFFERS: usize = 1024;
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct TricklePayload {
     pub sdp: String,
     pub candidates: Vec<IceCandidate>,
+    #[serde(default)]
+    pub meta: HandshakeMeta,
+}
+
+impl TricklePayload {
+    /// Decode handshake info from remote, and check its size, signatures and candidates
+    /// before anything of it is applied. SDP is checked by transports, see [check_sdp].
+    pub fn decode_checked(data: &Encoded) -> Result<MessagePayload<Self>> {
+        if data.len() > MAX_HANDSHAKE_INFO_SIZE {
+            return Err(Error::HandshakeInfoTooLarge(
+                data.len(),
+                MAX_HANDSHAKE_INFO_SIZE,
+            ));
+        }
+        let bytes: Vec<u8> = data.decode()?;
+        let payload =
+            MessagePayload::<Self>::from_auto_within(&bytes, MAX_HANDSHAKE_INFO_DECODED_SIZE)?;
+        if !payload.verify() {
+            return Err(Error::VerifySignatureFailed);
+        }
+        // address is not signed, so it should be the signer of both verifications
+        let addr = payload.addr;
+        if [&payload.verification, &payload.origin_verification]
+            .iter()
+            .any(|v| v.session.auth.authorizer != addr)
+        {
+            return Err(Error::HandshakeAddressMismatch(format!("{:?}", addr)));
+        }
+        let candidates = &payload.data.candidates;
+        if candidates.len() > MAX_HANDSHAKE_CANDIDATES {
+            return Err(Error::InvalidCandidates(format!(
+                "{} candidates, at most {}",
+                candidates.len(),
+                MAX_HANDSHAKE_CANDIDATES
+            )));
+        }
+        // empty one marks end of candidates
+        if let Some(c) = candidates.iter().find(|c| {
+            c.candidate.len() > MAX_CANDIDATE_LEN
+                || !(c.candidate.is_empty() || c.candidate.starts_with("candidate:"))
+        }) {
+            return Err(Error::InvalidCandidates(
+                c.candidate.chars().take(64).collect(),
+            ));
+        }
+        Ok(payload)
+    }
+}
+
+/// Nonces issued to peers handshaking over HTTP, signed in their offers, see
+/// [HandshakeMeta::nonce]. Each one is accepted once before it expires, and only by the node
+/// issued it, so a captured offer can't be replayed to this node or any other.
+#[derive(Debug, Default)]
+pub struct HandshakeNonces(Mutex<HashMap<String, u128>>);
+
+impl HandshakeNonces {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Issue a new nonce, expires in [HANDSHAKE_NONCE_TTL_MS]. None if [MAX_HANDSHAKE_NONCES]
+    /// are waiting.
+    pub fn issue(&self) -> Option<String> {
+        let now = utils::get_epoch_ms();
+        let mut nonces = self.0.lock().unwrap();
+        nonces.retain(|_, expires| *expires > now);
+        if nonces.len() >= MAX_HANDSHAKE_NONCES {
+            return None;
+        }
+        let nonce = hex::encode(rand::random::<[u8; 16]>());
+        nonces.insert(nonce.clone(), now + HANDSHAKE_NONCE_TTL_MS);
+        Some(nonce)
+    }
+
+    /// Accept `nonce` if it's issued here and not expired, it's never accepted again.
+    pub fn consume(&self, nonce: &str) -> bool {
+        let now = utils::get_epoch_ms();
+        self.0
+            .lock()
+            .unwrap()
+            .remove(nonce)
+            .map_or(false, |expires| expires > now)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct AnsweredOffer {
+    digest: String,
+    transport: uuid::Uuid,
+    answer: Encoded,
+    expires: u128,
+}
+
+/// Last offer answered of each peer, with the transport allocated for it and the answer, so
+/// a retried offer is answered again with the same transport instead of a new one.
+#[derive(Debug, Default)]
+pub struct AnsweredOffers(Mutex<HashMap<Address, AnsweredOffer>>);
+
+impl AnsweredOffers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn digest(offer: &Encoded) -> String {
+        hex::encode(Sha256::digest(offer.as_bytes()))
+    }
+
+    /// Transport and answer of `offer` of `peer`, if it's answered in
+    /// [ANSWERED_OFFER_TTL_MS], and is the last one of that peer.
+    pub fn get(&self, peer: &Address, offer: &Encoded) -> Option<(uuid::Uuid, Encoded)> {
+        let now = utils::get_epoch_ms();
+        let digest = Self::digest(offer);
+        self.0
+            .lock()
+            .unwrap()
+            .get(peer)
+            .filter(|a| a.expires > now && a.digest == digest)
+            .map(|a| (a.transport, a.answer.clone()))
+    }
+
+    /// Keep `answer` of `offer` of `peer`, which replaces the previous one of that peer.
+    pub fn insert(&self, peer: &Address, offer: &Encoded, transport: uuid::Uuid, answer: &Encoded) {
+        let now = utils::get_epoch_ms();
+        let mut offers = self.0.lock().unwrap();
+        offers.retain(|_, a| a.expires > now);
+        if offers.len() >= MAX_ANSWERED_OFFERS && !offers.contains_key(peer) {
+            if let Some(earliest) = offers
+                .iter()
+                .min_by_key(|(_, a)| a.expires)
+                .map(|(p, _)| *p)
+            {
+                offers.remove(&earliest);
+            }
+        }
+        offers.insert(*peer, AnsweredOffer {
+            digest: Self::digest(offer),
+            transport,
+            answer: answer.clone(),
+            expires: now + ANSWERED_OFFER_TTL_MS,
+        });
+    }
+}
+
+/// Check `sdp` of remote looks like a session description, lines of `<type>=<value>` starting
+/// with version, with at least one media.
+pub fn check_sdp(sdp: &str) -> Result<()> {
+    let invalid = |reason: &str| Err(Error::InvalidSdp(reason.to_owned()));
+    if !sdp.starts_with("v=0") {
+        return invalid("should start with v=0");
+    }
+    let mut media = 0;
+    for line in sdp.lines().map(|l| l.trim_end_matches('\r')) {
+        if line.is_empty() {
+            continue;
+        }
+        let bytes = line.as_bytes();
+        if bytes.len() < 2 || !bytes[0].is_ascii_lowercase() || bytes[1] != b'=' {
+            return invalid("malformed line");
+        }
+        if bytes[0] == b'm' {
+            media += 1;
+        }
+    }
+    if media == 0 {
+        return invalid("no media");
+    }
+    Ok(())
 }
 
 #[derive(Default)]
@@ -49,3 +261,297 @@ impl Future for Promise {
         }
     }
 }
+
+impl Promise {
+    /// Wait for the promise, see [timeout_or_cancel].
+    pub async fn wait(self, timeout: Duration, cancel: &CancelToken) -> Result<()> {
+        timeout_or_cancel(self, timeout, cancel).await
+    }
+}
+
+#[derive(Debug, Default)]
+struct CancelState {
+    cancelled: bool,
+    /// Id of next waiting future.
+    next: u64,
+    /// Wakers of waiting futures, each is removed once its future is dropped.
+    wakers: HashMap<u64, Waker>,
+}
+
+/// Cancels waiting for transports sharing the token, like connects of a draining swarm.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<Mutex<CancelState>>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel all waiting, and waiting later, on the token.
+    pub fn cancel(&self) {
+        let mut s = self.0.lock().unwrap();
+        s.cancelled = true;
+        for (_, w) in s.wakers.drain() {
+            w.wake();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.lock().unwrap().cancelled
+    }
+
+    /// Resolves once the token is cancelled.
+    pub fn cancelled(&self) -> Cancelled {
+        let id = {
+            let mut s = self.0.lock().unwrap();
+            s.next += 1;
+            s.next
+        };
+        Cancelled {
+            token: self.clone(),
+            id,
+        }
+    }
+
+    /// Futures waiting for the token.
+    pub fn waiting(&self) -> usize {
+        self.0.lock().unwrap().wakers.len()
+    }
+}
+
+/// Future of [CancelToken::cancelled], its waker is removed from token once it's dropped.
+#[derive(Debug)]
+pub struct Cancelled {
+    token: CancelToken,
+    id: u64,
+}
+
+impl Future for Cancelled {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut s = self.token.0.lock().unwrap();
+        if s.cancelled {
+            Poll::Ready(())
+        } else {
+            match s.wakers.get_mut(&self.id) {
+                Some(w) if w.will_wake(cx.waker()) => {}
+                Some(w) => *w = cx.waker().clone(),
+                None => {
+                    s.wakers.insert(self.id, cx.waker().clone());
+                }
+            }
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Cancelled {
+    fn drop(&mut self) {
+        if let Ok(mut s) = self.token.0.lock() {
+            s.wakers.remove(&self.id);
+        }
+    }
+}
+
+/// Wait for `fut`, fails with [Error::TransportConnectTimeout] after `timeout`, or with
+/// [Error::TransportConnectCancelled] once `cancel` is cancelled.
+pub async fn timeout_or_cancel<T, F>(fut: F, timeout: Duration, cancel: &CancelToken) -> Result<T>
+where F: Future<Output = Result<T>> {
+    let fut = Box::pin(fut);
+    let stop = future::select(Box::pin(timer::sleep(timeout)), cancel.cancelled());
+    match future::select(fut, stop).await {
+        Either::Left((r, _)) => r,
+        Either::Right((Either::Left(_), _)) => {
+            Err(Error::TransportConnectTimeout(timeout.as_millis() as u64))
+        }
+        Either::Right((Either::Right(_), _)) => Err(Error::TransportConnectCancelled),
+    }
+}
+
+/// Bytes and frames counted by a transport itself, when WebRTC stats don't have them.
+#[derive(Debug, Default)]
+pub struct TrafficCounters {
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    frames_lost: AtomicU64,
+}
+
+impl TrafficCounters {
+    pub fn sent(&self, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn received(&self, bytes: usize) {
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn lost(&self) {
+        self.frames_lost.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Stats with counted bytes, `packets_lost` is left None.
+    pub fn stats(&self) -> TransportStats {
+        TransportStats {
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            ..Default::default()
+        }
+    }
+
+    pub fn frames_lost(&self) -> u64 {
+        self.frames_lost.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ecc::SecretKey;
+    use crate::message::Encoder;
+    use crate::session::SessionManager;
+
+    #[tokio::test]
+    async fn test_promise_timeout_and_cancel() {
+        let cancel = CancelToken::new();
+        let promise = Promise::default();
+        promise.state().lock().unwrap().complete(true);
+        assert!(promise.wait(Duration::from_secs(1), &cancel).await.is_ok());
+
+        let r = Promise::default()
+            .wait(Duration::from_millis(50), &cancel)
+            .await;
+        assert!(matches!(r, Err(Error::TransportConnectTimeout(50))));
+        // finished waits leave nothing in token
+        assert_eq!(cancel.waiting(), 0);
+
+        let waiting = cancel.clone();
+        let task = tokio::spawn(async move {
+            Promise::default()
+                .wait(Duration::from_secs(10), &waiting)
+                .await
+        });
+        timer::sleep(Duration::from_millis(20)).await;
+        assert_eq!(cancel.waiting(), 1);
+        cancel.cancel();
+        assert!(matches!(
+            task.await.unwrap(),
+            Err(Error::TransportConnectCancelled)
+        ));
+        assert!(cancel.is_cancelled());
+    }
+
+    #[test]
+    fn test_decode_checked_handshake_info() {
+        let sm = SessionManager::new_with_seckey(&SecretKey::random()).unwrap();
+        let candidate = |c: &str| IceCandidate {
+            candidate: c.to_owned(),
+            sdp_mid: None,
+            sdp_m_line_index: None,
+            username_fragment: None,
+        };
+        let payload = |candidates: Vec<IceCandidate>| {
+            let data = TricklePayload {
+                sdp: "sdp".to_owned(),
+                candidates,
+                meta: HandshakeMeta::default(),
+            };
+            MessagePayload::new_direct(data, &sm, sm.authorizer().unwrap().into()).unwrap()
+        };
+
+        let ok = payload(vec![candidate(
+            "candidate:1 1 udp 1 10.0.0.1 5000 typ host",
+        )]);
+        let decoded = TricklePayload::decode_checked(&ok.encode().unwrap()).unwrap();
+        assert_eq!(decoded.addr, ok.addr);
+
+        // address isn't covered by signature, forging it is refused
+        let mut forged = payload(vec![]);
+        forged.addr = SecretKey::random().address();
+        assert!(matches!(
+            TricklePayload::decode_checked(&forged.encode().unwrap()),
+            Err(Error::HandshakeAddressMismatch(_))
+        ));
+
+        let bad = payload(vec![candidate("a=candidate:1")]);
+        assert!(matches!(
+            TricklePayload::decode_checked(&bad.encode().unwrap()),
+            Err(Error::InvalidCandidates(_))
+        ));
+        let many = payload(vec![candidate(""); MAX_HANDSHAKE_CANDIDATES + 1]);
+        assert!(matches!(
+            TricklePayload::decode_checked(&many.encode().unwrap()),
+            Err(Error::InvalidCandidates(_))
+        ));
+
+        let huge = Encoded::from_encoded_str(&"1".repeat(MAX_HANDSHAKE_INFO_SIZE + 1));
+        assert!(matches!(
+            TricklePayload::decode_checked(&huge),
+            Err(Error::HandshakeInfoTooLarge(..))
+        ));
+
+        // small when encoded, huge when decompressed
+        #[cfg(feature = "gzip")]
+        {
+            let raw = vec![b' '; MAX_HANDSHAKE_INFO_DECODED_SIZE * 4];
+            let bomb = crate::message::codec::gzip(&raw, 9).unwrap();
+            let encoded = bomb.encode().unwrap();
+            assert!(encoded.len() < MAX_HANDSHAKE_INFO_SIZE);
+            assert!(matches!(
+                TricklePayload::decode_checked(&encoded),
+                Err(Error::DecompressedTooLarge(MAX_HANDSHAKE_INFO_DECODED_SIZE))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_handshake_nonces() {
+        let nonces = HandshakeNonces::new();
+        let nonce = nonces.issue().unwrap();
+        assert_eq!(nonce.len(), 32);
+        assert!(!nonces.consume("00"));
+        assert!(nonces.consume(&nonce));
+        // replayed
+        assert!(!nonces.consume(&nonce));
+        // issued by another node
+        assert!(!HandshakeNonces::new().consume(&nonces.issue().unwrap()));
+
+        // issued ones are kept when it's full
+        let first = nonces.issue().unwrap();
+        for _ in 1..MAX_HANDSHAKE_NONCES {
+            nonces.issue().unwrap();
+        }
+        assert!(nonces.issue().is_none());
+        assert!(nonces.consume(&first));
+        assert!(nonces.issue().is_some());
+    }
+
+    #[test]
+    fn test_answered_offers() {
+        let offers = AnsweredOffers::new();
+        let peer = SecretKey::random().address();
+        let offer: Encoded = "offer".encode().unwrap();
+        let answer: Encoded = "answer".encode().unwrap();
+        let id = uuid::Uuid::new_v4();
+        assert!(offers.get(&peer, &offer).is_none());
+        offers.insert(&peer, &offer, id, &answer);
+        assert_eq!(offers.get(&peer, &offer), Some((id, answer.clone())));
+        // another offer of the peer replaces it
+        let offer2: Encoded = "offer2".encode().unwrap();
+        offers.insert(&peer, &offer2, uuid::Uuid::new_v4(), &answer);
+        assert!(offers.get(&peer, &offer).is_none());
+        assert!(offers
+            .get(&SecretKey::random().address(), &offer2)
+            .is_none());
+    }
+
+    #[test]
+    fn test_check_sdp() {
+        let sdp = "v=0\r\no=- 1 2 IN IP4 127.0.0.1\r\ns=-\r\nm=application 9 UDP/DTLS/SCTP webrtc-datachannel\r\n";
+        assert!(check_sdp(sdp).is_ok());
+        assert!(check_sdp("").is_err());
+        assert!(check_sdp("v=0\r\ns=-\r\n").is_err());
+        assert!(check_sdp("v=0\r\nm=application\r\n<script>\r\n").is_err());
+    }
+}
@@ -0,0 +1,14 @@
+//! In-memory transport for tests and simulation.
+//!
+//! [MockTransport] implements transport traits without STUN or WebRTC. Frames are delivered
+//! over in-process channels of a [MockHub], with configurable latency and loss rate, so
+//! hundreds of nodes can run in one process. Build rings-core with feature `mock`, and give a
+//! swarm a hub by [SwarmBuilder::with_mock_hub], to make its transports mock ones. Swarms
+//! without a hub still use WebRTC.
+//!
+//! [SwarmBuilder::with_mock_hub]: crate::swarm::SwarmBuilder::with_mock_hub
+pub mod transport;
+
+pub use transport::MockConfig;
+pub use transport::MockHub;
+pub use transport::MockTransport;
@@ -0,0 +1,592 @@
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures_timer::Delay;
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand::SeedableRng;
+use webrtc::ice_transport::ice_connection_state::RTCIceConnectionState;
+use webrtc::peer_connection::sdp::sdp_type::RTCSdpType;
+
+use crate::address::Address;
+use crate::channels::Channel as AcChannel;
+use crate::ecc::PublicKey;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::Decoder;
+use crate::message::Encoded;
+use crate::message::Encoder;
+use crate::message::MessagePayload;
+use crate::session::SessionManager;
+use crate::transports::helper::CancelToken;
+use crate::transports::helper::Promise;
+use crate::transports::helper::State;
+use crate::transports::helper::TrafficCounters;
+use crate::transports::helper::TricklePayload;
+use crate::types::channel::Channel;
+use crate::types::channel::Event;
+use crate::types::ice_transport::HandshakeMeta;
+use crate::types::ice_transport::IceCandidate;
+use crate::types::ice_transport::IceServer;
+use crate::types::ice_transport::IceTransport;
+use crate::types::ice_transport::IceTransportCallback;
+use crate::types::ice_transport::IceTrickleScheme;
+use crate::types::ice_transport::TransportStats;
+
+type EventSender = <AcChannel<Event> as Channel<Event>>::Sender;
+
+/// Behavior of links between mock transports.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MockConfig {
+    /// Delay of every frame.
+    pub latency: Duration,
+    /// Probability of dropping a frame, from 0.0 to 1.0.
+    pub loss_rate: f64,
+    /// Seed of random generator which decides frame loss, same seed gives same losses.
+    pub seed: u64,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        Self {
+            latency: Duration::ZERO,
+            loss_rate: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+/// Network of mock transports, only transports of the same hub reach each other. Every test
+/// or simulation makes its own, so they share no state.
+#[derive(Clone, Default)]
+pub struct MockHub {
+    /// Started transports by id, a transport stays here until it's closed.
+    transports: Arc<Mutex<HashMap<uuid::Uuid, MockTransport>>>,
+    /// Config of transports created afterwards.
+    config: Arc<RwLock<MockConfig>>,
+}
+
+impl MockHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set config of transports created afterwards, transports created before are not affected.
+    pub fn configure(&self, config: MockConfig) {
+        if let Ok(mut c) = self.config.write() {
+            *c = config;
+        }
+    }
+
+    /// Config of transports created now.
+    pub fn config(&self) -> MockConfig {
+        self.config.read().map(|c| *c).unwrap_or_default()
+    }
+
+    fn get(&self, id: &uuid::Uuid) -> Option<MockTransport> {
+        self.transports.lock().ok()?.get(id).cloned()
+    }
+
+    fn insert(&self, transport: MockTransport) {
+        if let Ok(mut transports) = self.transports.lock() {
+            transports.insert(transport.id, transport);
+        }
+    }
+
+    fn remove(&self, id: &uuid::Uuid) {
+        if let Ok(mut transports) = self.transports.lock() {
+            transports.remove(id);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MockTransport {
+    pub id: uuid::Uuid,
+    hub: MockHub,
+    event_sender: EventSender,
+    config: Arc<RwLock<MockConfig>>,
+    rng: Arc<Mutex<StdRng>>,
+    public_key: Arc<RwLock<Option<PublicKey>>>,
+    remote_key: Arc<RwLock<Option<Address>>>,
+    local_meta: Arc<RwLock<HandshakeMeta>>,
+    remote_meta: Arc<RwLock<Option<HandshakeMeta>>>,
+    remote_id: Arc<RwLock<Option<uuid::Uuid>>>,
+    connected: Arc<AtomicBool>,
+    closed: Arc<AtomicBool>,
+    promises: Arc<Mutex<Vec<Arc<Mutex<State>>>>>,
+    traffic: Arc<TrafficCounters>,
+}
+
+impl PartialEq for MockTransport {
+    fn eq(&self, other: &Self) -> bool {
+        self.id.eq(&other.id)
+    }
+}
+
+impl Drop for MockTransport {
+    fn drop(&mut self) {
+        tracing::trace!("transport dropped: {}", self.id);
+    }
+}
+
+impl MockTransport {
+    /// Transport in `hub`, with config of the hub. [IceTransport::new] makes a transport in a
+    /// hub of its own, which reaches no other one.
+    pub fn new_in(hub: &MockHub, event_sender: EventSender) -> Self {
+        let config = hub.config();
+        Self {
+            id: uuid::Uuid::new_v4(),
+            hub: hub.clone(),
+            event_sender,
+            rng: Arc::new(Mutex::new(StdRng::seed_from_u64(config.seed))),
+            config: Arc::new(RwLock::new(config)),
+            public_key: Arc::new(RwLock::new(None)),
+            remote_key: Arc::new(RwLock::new(None)),
+            local_meta: Arc::new(RwLock::new(HandshakeMeta::default())),
+            remote_meta: Arc::new(RwLock::new(None)),
+            remote_id: Arc::new(RwLock::new(None)),
+            connected: Arc::new(AtomicBool::new(false)),
+            closed: Arc::new(AtomicBool::new(false)),
+            promises: Arc::new(Mutex::new(vec![])),
+            traffic: Arc::new(TrafficCounters::default()),
+        }
+    }
+
+    /// Change config of this transport only.
+    pub fn set_config(&self, config: MockConfig) {
+        if let Ok(mut c) = self.config.write() {
+            *c = config;
+        }
+        if let Ok(mut rng) = self.rng.lock() {
+            *rng = StdRng::seed_from_u64(config.seed);
+        }
+    }
+
+    fn config(&self) -> MockConfig {
+        self.config.read().map(|c| *c).unwrap_or_default()
+    }
+
+    fn remote_id(&self) -> Option<uuid::Uuid> {
+        self.remote_id.read().ok().and_then(|r| *r)
+    }
+
+    pub(crate) fn remote_address(&self) -> Option<Address> {
+        self.public_key
+            .read()
+            .ok()
+            .and_then(|pk| pk.map(|k| k.address()))
+    }
+
+    fn resolve_promises(&self, success: bool) {
+        if let Ok(mut promises) = self.promises.lock() {
+            for state in promises.drain(..) {
+                state.lock().unwrap().complete(success);
+            }
+        }
+    }
+
+    /// Both ends are connected when each of them registered the other one.
+    async fn try_connect(&self) {
+        let remote = match self.remote_id().and_then(|id| self.hub.get(&id)) {
+            Some(r) if r.remote_id() == Some(self.id) => r,
+            _ => return,
+        };
+        for t in [self, &remote] {
+            if t.connected.swap(true, Ordering::SeqCst) {
+                continue;
+            }
+            t.resolve_promises(true);
+            if let Some(address) = t.remote_address() {
+                if t.event_sender
+                    .send(Event::RegisterTransport(address))
+                    .await
+                    .is_err()
+                {
+                    tracing::error!("Failed when send RegisterTransport");
+                }
+            }
+        }
+    }
+
+    pub async fn wait_for_data_channel_open(&self) -> Result<()> {
+        self.connect_success_promise().await?.await
+    }
+
+    pub async fn connect_success_promise(&self) -> Result<Promise> {
+        let promise = Promise::default();
+        if self.connected.load(Ordering::SeqCst) {
+            promise.0.lock().unwrap().complete(true);
+        } else if self.closed.load(Ordering::SeqCst) {
+            return Err(Error::RTCDataChannelStateNotOpen);
+        } else {
+            self.promises.lock().unwrap().push(promise.state());
+        }
+        Ok(promise)
+    }
+}
+
+#[async_trait]
+impl IceTransport<Event, AcChannel<Event>> for MockTransport {
+    type Connection = ();
+    type Candidate = IceCandidate;
+    type Sdp = String;
+    type DataChannel = ();
+    type IceConnectionState = RTCIceConnectionState;
+    type Msg = Vec<u8>;
+
+    fn new(event_sender: EventSender) -> Self {
+        Self::new_in(&MockHub::new(), event_sender)
+    }
+
+    async fn start(&mut self, _ice_server: &IceServer) -> Result<&Self> {
+        self.hub.insert(self.clone());
+        Ok(self)
+    }
+
+    async fn close(&self) -> Result<()> {
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        let was_connected = self.connected.swap(false, Ordering::SeqCst);
+        self.resolve_promises(false);
+        self.hub.remove(&self.id);
+        let remote = self.remote_id().and_then(|id| self.hub.get(&id));
+        // remote sees the connection failed, as ice state of WebRTC does
+        if let (true, Some(remote)) = (was_connected, remote) {
+            remote.connected.store(false, Ordering::SeqCst);
+            if let Some(address) = remote.remote_address() {
+                let _ = remote
+                    .event_sender
+                    .send(Event::ConnectFailed(address))
+                    .await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn ice_connection_state(&self) -> Option<Self::IceConnectionState> {
+        Some(if self.closed.load(Ordering::SeqCst) {
+            RTCIceConnectionState::Closed
+        } else if self.connected.load(Ordering::SeqCst) {
+            RTCIceConnectionState::Connected
+        } else {
+            RTCIceConnectionState::New
+        })
+    }
+
+    async fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    async fn pubkey(&self) -> Option<PublicKey> {
+        self.public_key.read().ok().and_then(|pk| *pk)
+    }
+
+    async fn remote_key(&self) -> Option<Address> {
+        self.remote_key.read().ok().and_then(|k| *k)
+    }
+
+    async fn get_peer_connection(&self) -> Option<Arc<()>> {
+        None
+    }
+
+    async fn get_pending_candidates(&self) -> Vec<IceCandidate> {
+        vec![]
+    }
+
+    async fn get_answer(&self) -> Result<String> {
+        Ok(self.id.to_string())
+    }
+
+    async fn get_offer(&self) -> Result<String> {
+        Ok(self.id.to_string())
+    }
+
+    async fn get_answer_str(&self) -> Result<String> {
+        self.get_answer().await
+    }
+
+    async fn get_offer_str(&self) -> Result<String> {
+        self.get_offer().await
+    }
+
+    async fn get_data_channel(&self) -> Option<Arc<()>> {
+        None
+    }
+
+    async fn send_message(&self, msg: &[u8]) -> Result<()> {
+        if !self.connected.load(Ordering::SeqCst) {
+            return Err(Error::RTCDataChannelNotReady);
+        }
+        let remote = self
+            .remote_id()
+            .and_then(|id| self.hub.get(&id))
+            .ok_or(Error::RTCDataChannelStateNotOpen)?;
+        let config = self.config();
+        let lost =
+            config.loss_rate > 0.0 && self.rng.lock().unwrap().gen_bool(config.loss_rate.min(1.0));
+        if lost {
+            tracing::trace!(transport = %self.id, "drop frame of {} bytes", msg.len());
+            self.traffic.lost();
+            return Ok(());
+        }
+        if !config.latency.is_zero() {
+            Delay::new(config.latency).await;
+        }
+        let from = remote
+            .remote_address()
+            .ok_or(Error::RTCDataChannelStateNotOpen)?;
+        remote
+            .event_sender
+            .send(Event::DataChannelMessage(from, msg.to_vec()))
+            .await
+            .map_err(|_| Error::RTCDataChannelStateNotOpen)?;
+        self.traffic.sent(msg.len());
+        remote.traffic.received(msg.len());
+        Ok(())
+    }
+
+    /// Links of mock transports are direct, and RTT is twice of configured latency.
+    async fn stats(&self) -> TransportStats {
+        let connected = self.connected.load(Ordering::SeqCst);
+        let latency_ms = self.config().latency.as_millis() as u64;
+        TransportStats {
+            rtt_ms: connected.then(|| latency_ms * 2),
+            packets_lost: Some(self.traffic.frames_lost()),
+            local_candidate_type: connected.then(|| "host".to_owned()),
+            remote_candidate_type: connected.then(|| "host".to_owned()),
+            ..self.traffic.stats()
+        }
+    }
+
+    async fn set_local_description<T>(&self, _desc: T) -> Result<()>
+    where T: Into<String> + Send {
+        Ok(())
+    }
+
+    async fn add_ice_candidate(&self, _candidate: IceCandidate) -> Result<()> {
+        Ok(())
+    }
+
+    async fn set_remote_description<T>(&self, desc: T) -> Result<()>
+    where T: Into<String> + Send {
+        let id = uuid::Uuid::parse_str(&desc.into()).map_err(|_| Error::InvalidTransportUuid)?;
+        if let Ok(mut r) = self.remote_id.write() {
+            *r = Some(id);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IceTransportCallback<Event, AcChannel<Event>> for MockTransport {
+    type OnLocalCandidateHdlrFn = ();
+    type OnDataChannelHdlrFn = ();
+    type OnIceConnectionStateChangeHdlrFn = ();
+
+    async fn apply_callback(&self) -> Result<&Self> {
+        Ok(self)
+    }
+
+    async fn on_ice_connection_state_change(&self) -> Self::OnIceConnectionStateChangeHdlrFn {}
+
+    async fn on_ice_candidate(&self) -> Self::OnLocalCandidateHdlrFn {}
+
+    async fn on_data_channel(&self) -> Self::OnDataChannelHdlrFn {}
+}
+
+#[async_trait]
+impl IceTrickleScheme<Event, AcChannel<Event>> for MockTransport {
+    type SdpType = RTCSdpType;
+
+    async fn get_handshake_info(
+        &self,
+        session_manager: &SessionManager,
+        _kind: RTCSdpType,
+    ) -> Result<Encoded> {
+        let data = TricklePayload {
+            sdp: self.id.to_string(),
+            candidates: vec![],
+            meta: self.local_meta.read().unwrap().clone(),
+        };
+        let resp = MessagePayload::new_direct(
+            data,
+            session_manager,
+            session_manager.authorizer()?.to_owned().into(), // This is a fake destination
+        )?;
+        Ok(resp.encode()?)
+    }
+
+    async fn register_remote_info(&self, data: Encoded) -> Result<Address> {
+        let data = TricklePayload::decode_checked(&data)?;
+        let local_meta = self.local_meta.read().unwrap().clone();
+        if data.data.meta.network_id != local_meta.network_id {
+            return Err(Error::NetworkIdMismatch(
+                data.data.meta.network_id.clone(),
+                local_meta.network_id,
+            ));
+        }
+        let remote_meta = local_meta.negotiate(&data.data.meta)?;
+        if let (Ok(public_key), Ok(mut pk)) = (
+            data.origin_verification.session.authorizer_pubkey(),
+            self.public_key.write(),
+        ) {
+            *pk = Some(public_key);
+        };
+        if let Ok(mut key) = self.remote_key.write() {
+            *key = Some(data.origin_verification.session.authorized_key());
+        }
+        if let Ok(mut meta) = self.remote_meta.write() {
+            *meta = Some(remote_meta);
+        }
+        self.set_remote_description(data.data.sdp.clone()).await?;
+        self.try_connect().await;
+        Ok(data.addr)
+    }
+
+    async fn wait_for_connected(&self) -> Result<()> {
+        let promise = self.connect_success_promise().await?;
+        promise.await
+    }
+
+    async fn wait_for_connected_timeout(
+        &self,
+        timeout: Duration,
+        cancel: &CancelToken,
+    ) -> Result<()> {
+        let promise = self.connect_success_promise().await?;
+        promise.wait(timeout, cancel).await
+    }
+
+    async fn set_local_meta(&self, meta: HandshakeMeta) {
+        if let Ok(mut m) = self.local_meta.write() {
+            *m = meta;
+        }
+    }
+
+    async fn remote_meta(&self) -> Option<HandshakeMeta> {
+        self.remote_meta.read().ok().and_then(|m| m.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    async fn prepare_transport(hub: &MockHub) -> (MockTransport, AcChannel<Event>) {
+        let ch = AcChannel::new();
+        let mut trans = MockTransport::new_in(hub, ch.sender());
+        let stun = IceServer::from_str("stun://stun.l.google.com:19302").unwrap();
+        trans.start(&stun).await.unwrap();
+        (trans, ch)
+    }
+
+    async fn establish_connection(
+        transport1: &MockTransport,
+        transport2: &MockTransport,
+    ) -> Result<()> {
+        let sm1 = SessionManager::new_with_seckey(&SecretKey::random())?;
+        let sm2 = SessionManager::new_with_seckey(&SecretKey::random())?;
+        let offer = transport1
+            .get_handshake_info(&sm1, RTCSdpType::Offer)
+            .await?;
+        transport2.register_remote_info(offer).await?;
+        assert!(!transport2.is_connected().await);
+        let answer = transport2
+            .get_handshake_info(&sm2, RTCSdpType::Answer)
+            .await?;
+        transport1.register_remote_info(answer).await?;
+        transport1.wait_for_connected().await?;
+        transport2.wait_for_connected().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_transport_connect_and_send() -> Result<()> {
+        let hub = MockHub::new();
+        let (t1, ch1) = prepare_transport(&hub).await;
+        let (t2, ch2) = prepare_transport(&hub).await;
+        establish_connection(&t1, &t2).await?;
+        assert_eq!(
+            t1.ice_connection_state().await,
+            Some(RTCIceConnectionState::Connected)
+        );
+        assert!(matches!(
+            AcChannel::recv(&ch1.receiver()).await?,
+            Some(Event::RegisterTransport(_))
+        ));
+        assert!(matches!(
+            AcChannel::recv(&ch2.receiver()).await?,
+            Some(Event::RegisterTransport(_))
+        ));
+
+        let t1_address = t2.remote_address().unwrap();
+        t1.send_message(b"hello").await?;
+        assert_eq!(
+            AcChannel::recv(&ch2.receiver()).await?,
+            Some(Event::DataChannelMessage(t1_address, b"hello".to_vec()))
+        );
+
+        t1.close().await?;
+        assert!(t1.send_message(b"hello").await.is_err());
+        assert!(matches!(
+            AcChannel::recv(&ch2.receiver()).await?,
+            Some(Event::ConnectFailed(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_transport_loss() -> Result<()> {
+        let hub = MockHub::new();
+        let (t1, _ch1) = prepare_transport(&hub).await;
+        let (t2, ch2) = prepare_transport(&hub).await;
+        establish_connection(&t1, &t2).await?;
+        t1.set_config(MockConfig {
+            loss_rate: 1.0,
+            ..Default::default()
+        });
+        t1.send_message(b"lost").await?;
+        t2.send_message(b"back").await?;
+        t1.set_config(MockConfig::default());
+        let t1_address = t2.remote_address().unwrap();
+        t1.send_message(b"kept").await?;
+        // registration event first, then only the frame which is not dropped
+        AcChannel::recv(&ch2.receiver()).await?;
+        assert_eq!(
+            AcChannel::recv(&ch2.receiver()).await?,
+            Some(Event::DataChannelMessage(t1_address, b"kept".to_vec()))
+        );
+        let stats = t1.stats().await;
+        assert_eq!(stats.bytes_sent, 4);
+        assert_eq!(stats.bytes_received, 4);
+        assert_eq!(stats.packets_lost, Some(1));
+        assert!(!stats.is_relayed());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_hubs_apart() -> Result<()> {
+        let (t1, _ch1) = prepare_transport(&MockHub::new()).await;
+        let (t2, _ch2) = prepare_transport(&MockHub::new()).await;
+        let sm1 = SessionManager::new_with_seckey(&SecretKey::random())?;
+        let sm2 = SessionManager::new_with_seckey(&SecretKey::random())?;
+        let offer = t1.get_handshake_info(&sm1, RTCSdpType::Offer).await?;
+        t2.register_remote_info(offer).await?;
+        let answer = t2.get_handshake_info(&sm2, RTCSdpType::Answer).await?;
+        t1.register_remote_info(answer).await?;
+        assert!(!t1.is_connected().await);
+        assert!(!t2.is_connected().await);
+        Ok(())
+    }
+}
@@ -1,5 +1,7 @@
 #[cfg(not(feature = "wasm"))]
 pub mod default;
+#[cfg(all(not(feature = "wasm"), feature = "mock"))]
+pub mod mock;
 #[cfg(feature = "wasm")]
 pub mod wasm;
 
@@ -1,12 +1,17 @@
+use std::net::IpAddr;
+
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json;
+use wasm_bindgen::JsCast;
 use wasm_bindgen::JsValue;
 use web_sys::RtcSdpType;
 use web_sys::RtcSessionDescription;
 
 use crate::err::Error;
 use crate::err::Result;
+use crate::types::ice_transport::address_family;
+use crate::types::ice_transport::TransportStats;
 
 #[derive(Clone, Deserialize, Serialize, Debug)]
 #[serde(rename_all = "lowercase")]
@@ -84,3 +89,65 @@ impl From<RtcSessionDescriptionWrapper> for web_sys::RtcSessionDescription {
         RtcSessionDescription::new_with_description_init_dict(&sdp).unwrap()
     }
 }
+
+/// Read [TransportStats] of selected candidate pair from `RTCStatsReport` of `getStats`.
+pub fn stats_from_report(report: &JsValue) -> TransportStats {
+    let get = |v: &JsValue, key: &str| {
+        js_sys::Reflect::get(v, &JsValue::from_str(key))
+            .ok()
+            .filter(|v| !v.is_undefined())
+    };
+    let mut entries: Vec<JsValue> = vec![];
+    let map: &js_sys::Map = report.unchecked_ref();
+    map.for_each(&mut |value, _| entries.push(value));
+    let find = |id: &JsValue| {
+        entries
+            .iter()
+            .find(|e| get(e, "id").as_ref() == Some(id))
+            .cloned()
+    };
+    let is_type =
+        |e: &JsValue, t: &str| get(e, "type").and_then(|v| v.as_string()).as_deref() == Some(t);
+
+    // chrome refers selected pair from transport, firefox marks the pair as selected
+    let pair = entries
+        .iter()
+        .filter(|e| is_type(e, "transport"))
+        .find_map(|t| get(t, "selectedCandidatePairId"))
+        .and_then(|id| find(&id))
+        .or_else(|| {
+            entries
+                .iter()
+                .find(|e| {
+                    is_type(e, "candidate-pair")
+                        && get(e, "selected").and_then(|v| v.as_bool()) == Some(true)
+                })
+                .cloned()
+        });
+    let pair = match pair {
+        Some(pair) => pair,
+        None => return TransportStats::default(),
+    };
+    let number = |key: &str| get(&pair, key).and_then(|v| v.as_f64());
+    let candidate = |key: &str, field: &str| {
+        get(&pair, key)
+            .and_then(|id| find(&id))
+            .and_then(|c| get(&c, field))
+            .and_then(|v| v.as_string())
+    };
+    // `ip` is the older name of `address`
+    let remote_ip = candidate("remoteCandidateId", "address")
+        .or_else(|| candidate("remoteCandidateId", "ip"))
+        .and_then(|ip| ip.parse::<IpAddr>().ok());
+    TransportStats {
+        // in seconds
+        rtt_ms: number("currentRoundTripTime").map(|rtt| (rtt * 1000.0) as u64),
+        bytes_sent: number("bytesSent").unwrap_or_default() as u64,
+        bytes_received: number("bytesReceived").unwrap_or_default() as u64,
+        // data channels have no RTP stats of loss
+        packets_lost: None,
+        local_candidate_type: candidate("localCandidateId", "candidateType"),
+        remote_candidate_type: candidate("remoteCandidateId", "candidateType"),
+        address_family: remote_ip.map(|ip| address_family(&ip).to_owned()),
+    }
+}
@@ -1,17 +1,15 @@
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::RwLock;
+use std::time::Duration;
 
 use async_trait::async_trait;
-use futures::channel::mpsc;
-use futures::lock::Mutex as FuturesMutex;
 use js_sys::Uint8Array;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use wasm_bindgen::JsValue;
 use wasm_bindgen_futures::spawn_local;
 use wasm_bindgen_futures::JsFuture;
-use web3::types::Address;
 use web_sys::MessageEvent;
 use web_sys::RtcConfiguration;
 use web_sys::RtcDataChannel;
@@ -27,7 +25,9 @@ use web_sys::RtcSdpType;
 use web_sys::RtcSessionDescription;
 use web_sys::RtcSessionDescriptionInit;
 
+use super::helper::stats_from_report;
 use super::helper::RtcSessionDescriptionWrapper;
+use crate::address::Address;
 use crate::channels::Channel as CbChannel;
 use crate::ecc::PublicKey;
 use crate::err::Error;
@@ -36,17 +36,22 @@ use crate::message::Encoded;
 use crate::message::Encoder;
 use crate::message::MessagePayload;
 use crate::session::SessionManager;
+use crate::transports::helper::check_sdp;
+use crate::transports::helper::CancelToken;
 use crate::transports::helper::Promise;
 use crate::transports::helper::TricklePayload;
 use crate::types::channel::Channel;
 use crate::types::channel::Event;
+use crate::types::ice_transport::HandshakeMeta;
 use crate::types::ice_transport::IceCandidate;
 use crate::types::ice_transport::IceServer;
 use crate::types::ice_transport::IceTransport;
 use crate::types::ice_transport::IceTransportCallback;
+use crate::types::ice_transport::IceTransportPolicy;
 use crate::types::ice_transport::IceTrickleScheme;
+use crate::types::ice_transport::TransportStats;
 
-type EventSender = Arc<FuturesMutex<mpsc::Sender<Event>>>;
+type EventSender = <CbChannel<Event> as Channel<Event>>::Sender;
 
 #[derive(Clone)]
 pub struct WasmTransport {
@@ -56,6 +61,9 @@ pub struct WasmTransport {
     channel: Option<Arc<RtcDataChannel>>,
     event_sender: EventSender,
     public_key: Arc<RwLock<Option<PublicKey>>>,
+    remote_key: Arc<RwLock<Option<Address>>>,
+    local_meta: Arc<RwLock<HandshakeMeta>>,
+    remote_meta: Arc<RwLock<Option<HandshakeMeta>>>,
 }
 
 impl PartialEq for WasmTransport {
@@ -80,6 +88,9 @@ impl IceTransport<Event, CbChannel<Event>> for WasmTransport {
             pending_candidates: Arc::new(Mutex::new(vec![])),
             channel: None,
             public_key: Arc::new(RwLock::new(None)),
+            remote_key: Arc::new(RwLock::new(None)),
+            local_meta: Arc::new(RwLock::new(HandshakeMeta::default())),
+            remote_meta: Arc::new(RwLock::new(None)),
             event_sender,
         }
     }
@@ -98,6 +109,17 @@ impl IceTransport<Event, CbChannel<Event>> for WasmTransport {
             r.is_ok(),
             "setting properties should never fail on our dictionary objects"
         );
+        if self.local_meta.read().unwrap().ice_transport_policy == IceTransportPolicy::Relay {
+            let r = js_sys::Reflect::set(
+                &config,
+                &JsValue::from("iceTransportPolicy"),
+                &JsValue::from("relay"),
+            );
+            debug_assert!(
+                r.is_ok(),
+                "setting properties should never fail on our dictionary objects"
+            );
+        }
 
         self.connection = RtcPeerConnection::new_with_configuration(&config)
             .ok()
@@ -114,8 +136,12 @@ impl IceTransport<Event, CbChannel<Event>> for WasmTransport {
         Ok(())
     }
 
-    async fn pubkey(&self) -> PublicKey {
-        self.public_key.read().unwrap().unwrap()
+    async fn pubkey(&self) -> Option<PublicKey> {
+        *self.public_key.read().unwrap()
+    }
+
+    async fn remote_key(&self) -> Option<Address> {
+        *self.remote_key.read().unwrap()
     }
 
     async fn ice_connection_state(&self) -> Option<Self::IceConnectionState> {
@@ -208,6 +234,20 @@ impl IceTransport<Event, CbChannel<Event>> for WasmTransport {
         }
     }
 
+    async fn stats(&self) -> TransportStats {
+        let pc = match self.get_peer_connection().await {
+            Some(pc) => pc,
+            None => return TransportStats::default(),
+        };
+        match JsFuture::from(pc.get_stats()).await {
+            Ok(report) => stats_from_report(&report),
+            Err(e) => {
+                log::warn!("failed to get stats of transport {}: {:?}", self.id, e);
+                TransportStats::default()
+            }
+        }
+    }
+
     async fn set_local_description<T>(&self, desc: T) -> Result<()>
     where T: Into<Self::Sdp> {
         match &self.get_peer_connection().await {
@@ -367,12 +407,22 @@ impl IceTransportCallback<Event, CbChannel<Event>> for WasmTransport {
     async fn on_ice_candidate(&self) -> Self::OnLocalCandidateHdlrFn {
         let peer_connection = self.get_peer_connection().await;
         let pending_candidates = Arc::clone(&self.pending_candidates);
+        let local_meta = Arc::clone(&self.local_meta);
         log::debug!("binding ice candidate callback");
         box move |ev: RtcPeerConnectionIceEvent| {
             log::info!("ice_Candidate {:?}", ev.candidate());
             let mut candidates = pending_candidates.lock().unwrap();
             let peer_connection = peer_connection.clone();
+            let meta = local_meta.read().unwrap().clone();
             if let Some(candidate) = ev.candidate() {
+                if !meta.allows_candidate(&candidate.candidate()) {
+                    log::debug!(
+                        "drop candidate by ice transport policy {} and ip family {}",
+                        meta.ice_transport_policy,
+                        meta.ip_family
+                    );
+                    return;
+                }
                 if peer_connection.is_some() {
                     candidates.push(candidate);
                     println!("Candidates Number: {:?}", candidates.len());
@@ -383,15 +433,18 @@ impl IceTransportCallback<Event, CbChannel<Event>> for WasmTransport {
 
     async fn on_data_channel(&self) -> Self::OnDataChannelHdlrFn {
         let event_sender = self.event_sender.clone();
+        let public_key = Arc::clone(&self.public_key);
 
         box move |ev: RtcDataChannelEvent| {
             log::debug!("channel open");
             let event_sender = Arc::clone(&event_sender);
+            let public_key = Arc::clone(&public_key);
             let ch = ev.channel();
             let on_message_cb = Closure::wrap(
                 (box move |ev: MessageEvent| {
                     let data = ev.data();
                     let event_sender = Arc::clone(&event_sender);
+                    let public_key = Arc::clone(&public_key);
                     spawn_local(async move {
                         let msg = if data.has_type::<web_sys::Blob>() {
                             let data: web_sys::Blob = data.clone().into();
@@ -411,9 +464,17 @@ impl IceTransportCallback<Event, CbChannel<Event>> for WasmTransport {
                         if msg.is_empty() {
                             return;
                         }
+                        let remote = match *public_key.read().unwrap() {
+                            Some(pk) => pk.address(),
+                            None => {
+                                log::warn!("drop message before remote info is registered");
+                                return;
+                            }
+                        };
                         let event_sender = Arc::clone(&event_sender);
                         if let Err(e) =
-                            CbChannel::send(&event_sender, Event::DataChannelMessage(msg)).await
+                            CbChannel::send(&event_sender, Event::DataChannelMessage(remote, msg))
+                                .await
                         {
                             log::error!("Failed on handle msg, {:?}", e);
                         }
@@ -446,16 +507,24 @@ impl IceTrickleScheme<Event, CbChannel<Event>> for WasmTransport {
                 return Err(Error::RTCSdpTypeNotMatch);
             }
         };
-        let local_candidates_json: Vec<IceCandidate> = self
+        let meta = self.local_meta.read().unwrap().clone();
+        let mut local_candidates_json: Vec<IceCandidate> = self
             .get_pending_candidates()
             .await
             .iter()
             .map(|c| c.clone().to_json().into_serde::<IceCandidate>().unwrap())
             .collect();
+        local_candidates_json.sort_by_key(|c| meta.ip_family.rank(&c.candidate));
+        for c in local_candidates_json.iter_mut() {
+            c.candidate = meta.scrub_candidate(&c.candidate);
+        }
+        // candidates gathered into sdp are never filtered by callback
+        let mut sdp = RtcSessionDescriptionWrapper::from(sdp);
+        sdp.sdp = meta.filter_sdp(&sdp.sdp);
         let data = TricklePayload {
-            sdp: serde_json::to_string(&RtcSessionDescriptionWrapper::from(sdp))
-                .map_err(Error::Deserialize)?,
+            sdp: serde_json::to_string(&sdp).map_err(Error::Deserialize)?,
             candidates: local_candidates_json,
+            meta,
         };
         log::debug!("prepared handshake info :{:?}", data);
         let resp = MessagePayload::new_direct(
@@ -463,32 +532,42 @@ impl IceTrickleScheme<Event, CbChannel<Event>> for WasmTransport {
             session_manager,
             session_manager.authorizer()?.to_owned().into(), // This is a fake destination
         )?;
-        Ok(resp.gzip(9)?.encode()?)
+        Ok(resp.encode()?)
     }
 
     async fn register_remote_info(&self, data: Encoded) -> Result<Address> {
-        let data: MessagePayload<TricklePayload> = data.decode()?;
+        let data = TricklePayload::decode_checked(&data)?;
         log::debug!("register remote info: {:?}", &data);
-
-        match data.verify() {
-            true => {
-                if let Ok(public_key) = data.origin_verification.session.authorizer_pubkey() {
-                    let mut pk = self.public_key.write().unwrap();
-                    *pk = Some(public_key);
-                };
-                let sdp: RtcSessionDescriptionWrapper = data.data.sdp.try_into()?;
-                self.set_remote_description(sdp.to_owned()).await?;
-                for c in &data.data.candidates {
-                    log::debug!("add remote candiates: {:?}", c);
-                    self.add_ice_candidate(c.clone()).await?;
-                }
-                Ok(data.addr)
-            }
-            _ => {
-                log::error!("cannot verify message sig");
-                return Err(Error::VerifySignatureFailed);
-            }
+        let local_meta = self
+            .local_meta
+            .read()
+            .map(|m| m.clone())
+            .unwrap_or_default();
+        if data.data.meta.network_id != local_meta.network_id {
+            return Err(Error::NetworkIdMismatch(
+                data.data.meta.network_id.clone(),
+                local_meta.network_id,
+            ));
+        }
+        let remote_meta = local_meta.negotiate(&data.data.meta)?;
+        let sdp: RtcSessionDescriptionWrapper = data.data.sdp.try_into()?;
+        check_sdp(&sdp.sdp)?;
+        if let Ok(public_key) = data.origin_verification.session.authorizer_pubkey() {
+            let mut pk = self.public_key.write().unwrap();
+            *pk = Some(public_key);
+        };
+        if let Ok(mut key) = self.remote_key.write() {
+            *key = Some(data.origin_verification.session.authorized_key());
+        }
+        if let Ok(mut meta) = self.remote_meta.write() {
+            *meta = Some(remote_meta);
         }
+        self.set_remote_description(sdp.to_owned()).await?;
+        for c in &data.data.candidates {
+            log::debug!("add remote candiates: {:?}", c);
+            self.add_ice_candidate(c.clone()).await?;
+        }
+        Ok(data.addr)
     }
 
     async fn wait_for_connected(&self) -> Result<()> {
@@ -496,6 +575,25 @@ impl IceTrickleScheme<Event, CbChannel<Event>> for WasmTransport {
         let promise = self.connect_success_promise().await?;
         promise.await
     }
+
+    async fn wait_for_connected_timeout(
+        &self,
+        timeout: Duration,
+        cancel: &CancelToken,
+    ) -> Result<()> {
+        let promise = self.connect_success_promise().await?;
+        promise.wait(timeout, cancel).await
+    }
+
+    async fn set_local_meta(&self, meta: HandshakeMeta) {
+        if let Ok(mut m) = self.local_meta.write() {
+            *m = meta;
+        }
+    }
+
+    async fn remote_meta(&self) -> Option<HandshakeMeta> {
+        self.remote_meta.read().ok().and_then(|m| m.clone())
+    }
 }
 
 impl WasmTransport {
@@ -517,12 +615,7 @@ impl WasmTransport {
                     match dc_cloned.ready_state() {
                         RtcDataChannelState::Open => {
                             let state = Arc::clone(&state);
-                            let mut s = state.lock().unwrap();
-                            if let Some(w) = s.waker.take() {
-                                w.wake();
-                                s.completed = true;
-                                s.successed = Some(true);
-                            }
+                            state.lock().unwrap().complete(true);
                         }
                         x => {
                             log::debug!("datachannel status: {:?}", x)
@@ -553,12 +646,7 @@ impl WasmTransport {
                     Closure::wrap(Box::new(move || match conn_clone.ice_gathering_state() {
                         RtcIceGatheringState::Complete => {
                             let state = Arc::clone(&state);
-                            let mut s = state.lock().unwrap();
-                            if let Some(w) = s.waker.take() {
-                                w.wake();
-                                s.completed = true;
-                                s.successed = Some(true);
-                            }
+                            state.lock().unwrap().complete(true);
                         }
                         x => {
                             log::trace!("gather status: {:?}", x)
@@ -577,22 +665,13 @@ impl WasmTransport {
             Some(conn) => {
                 let promise = Promise::default();
                 let state = Arc::clone(&promise.state());
+                let state_clone = Arc::clone(&state);
                 let callback = Closure::wrap(Box::new(move |st: RtcIceConnectionState| match st {
                     RtcIceConnectionState::Connected => {
-                        let mut s = state.lock().unwrap();
-                        if let Some(w) = s.waker.take() {
-                            w.wake();
-                            s.completed = true;
-                            s.successed = Some(true);
-                        }
+                        state.lock().unwrap().complete(true);
                     }
                     RtcIceConnectionState::Failed => {
-                        let mut s = state.lock().unwrap();
-                        if let Some(w) = s.waker.take() {
-                            w.wake();
-                            s.completed = true;
-                            s.successed = Some(false);
-                        }
+                        state.lock().unwrap().complete(false);
                     }
                     _ => {
                         log::trace!("Connect State changed to {:?}", st);
@@ -601,6 +680,12 @@ impl WasmTransport {
                     as Box<dyn FnMut(RtcIceConnectionState)>);
                 conn.set_oniceconnectionstatechange(Some(callback.as_ref().unchecked_ref()));
                 callback.forget();
+                // state may change before the promise is polled
+                match conn.ice_connection_state() {
+                    RtcIceConnectionState::Connected => state_clone.lock().unwrap().complete(true),
+                    RtcIceConnectionState::Failed => state_clone.lock().unwrap().complete(false),
+                    _ => {}
+                };
                 Ok(promise)
             }
             None => Err(Error::RTCPeerConnectionNotEstablish),
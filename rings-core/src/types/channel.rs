@@ -1,14 +1,20 @@
 use async_trait::async_trait;
 use serde::Serialize;
-use web3::types::Address;
 
+use crate::address::Address;
+use crate::dht::DhtEvent;
 use crate::err::Result;
 
 #[derive(Debug, PartialEq, Eq, Serialize, Clone)]
 pub enum Event {
     ConnectFailed(Address),
-    DataChannelMessage(Vec<u8>),
+    /// Message received, with remote address of transport it came by.
+    DataChannelMessage(Address, Vec<u8>),
     RegisterTransport(Address),
+    /// Migration window of peer is passed, see [crate::migration].
+    MigrationExpired(Address),
+    /// Change of ring topology, see [crate::dht::events].
+    Dht(DhtEvent),
 }
 
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
@@ -18,6 +24,8 @@ pub trait Channel<T: Send> {
     type Receiver;
 
     fn new() -> Self;
+    /// Channel buffering at most `capacity` messages, at least one.
+    fn with_capacity(capacity: usize) -> Self;
     fn sender(&self) -> Self::Sender;
     fn receiver(&self) -> Self::Receiver;
     async fn send(sender: &Self::Sender, msg: T) -> Result<()>;
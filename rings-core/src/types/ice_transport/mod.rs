@@ -1,17 +1,26 @@
 pub mod ice_server;
+use std::net::IpAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use serde::Deserialize;
 use serde::Serialize;
-use web3::types::Address;
 
 pub use self::ice_server::IceServer;
+use crate::address::Address;
 use crate::ecc::PublicKey;
+use crate::err::Error;
 use crate::err::Result;
+use crate::message::codec;
+use crate::message::codec::Codec;
 use crate::message::Encoded;
+use crate::message::DEFAULT_NETWORK_ID;
 use crate::session::SessionManager;
+use crate::transports::helper::CancelToken;
 use crate::types::channel::Channel;
+use crate::version;
+use crate::version::VersionPolicy;
 
 /// Struct From [webrtc-rs](https://docs.rs/webrtc/latest/webrtc/ice_transport/ice_candidate/struct.RTCIceCandidateInit.html)
 /// For [RFC Std](https://w3c.github.io/webrtc-pc/#dom-rtcicecandidate-tojson), ICE Candidate should be camelCase
@@ -30,6 +39,390 @@ pub struct IceCandidate {
     pub username_fragment: Option<String>,
 }
 
+/// Which local ICE candidates are gathered and sent to remote, for nodes which must not leak
+/// their LAN addresses to peers.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum IceTransportPolicy {
+    /// All candidates.
+    All,
+    /// Only candidates relayed by TURN servers, no connection can be made without one.
+    Relay,
+    /// All candidates but host ones, public addresses learned by STUN are still sent.
+    NoHost,
+}
+
+impl Default for IceTransportPolicy {
+    fn default() -> Self {
+        Self::All
+    }
+}
+
+impl std::str::FromStr for IceTransportPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Self::All),
+            "relay" => Ok(Self::Relay),
+            "no-host" => Ok(Self::NoHost),
+            _ => Err(format!("unknown ice transport policy: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for IceTransportPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::All => write!(f, "all"),
+            Self::Relay => write!(f, "relay"),
+            Self::NoHost => write!(f, "no-host"),
+        }
+    }
+}
+
+impl IceTransportPolicy {
+    /// Check if `candidate`, in form of SDP `candidate` attribute, may be sent to remote.
+    pub fn allows(&self, candidate: &str) -> bool {
+        let typ = candidate
+            .split_whitespace()
+            .skip_while(|s| *s != "typ")
+            .nth(1);
+        match self {
+            Self::All => true,
+            Self::Relay => typ == Some("relay"),
+            Self::NoHost => typ.map_or(false, |t| t != "host"),
+        }
+    }
+
+    /// Local addresses are hidden from remote, by every policy but `All`.
+    pub fn hides_local(&self) -> bool {
+        *self != Self::All
+    }
+
+    /// `candidate` as it's sent to remote. Server reflexive and relay candidates tell the
+    /// local address they are gathered from by `raddr` and `rport`, which is rewritten to
+    /// `0.0.0.0 0` if local addresses are hidden.
+    pub fn scrub(&self, candidate: &str) -> String {
+        if !self.hides_local() {
+            return candidate.to_owned();
+        }
+        let mut prev = "";
+        candidate
+            .split_inclusive(|c: char| c.is_ascii_whitespace())
+            .map(|s| {
+                let token = s.trim_end();
+                let scrubbed = match prev {
+                    "raddr" => Some("0.0.0.0"),
+                    "rport" => Some("0"),
+                    _ => None,
+                };
+                prev = token;
+                match scrubbed {
+                    Some(v) => format!("{}{}", v, &s[token.len()..]),
+                    None => s.to_owned(),
+                }
+            })
+            .collect()
+    }
+
+    /// SDP connection line `c=` with its address replaced by an unspecified one if local
+    /// addresses are hidden.
+    fn scrub_connection(&self, line: &str) -> String {
+        if !self.hides_local() {
+            return line.to_owned();
+        }
+        let end = &line[line.trim_end().len()..];
+        match line.trim_end().split_whitespace().nth(1) {
+            Some("IP6") => format!("c=IN IP6 ::{}", end),
+            _ => format!("c=IN IP4 0.0.0.0{}", end),
+        }
+    }
+}
+
+/// Address families of local ICE candidates gathered and sent to remote. Native transports
+/// gather nothing of a disabled family, browsers gather all and drop those before sending.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum IpFamily {
+    /// Both IPv4 and IPv6 candidates.
+    Dual,
+    /// Both, IPv4 candidates are sent first.
+    PreferIpv4,
+    /// Both, IPv6 candidates are sent first.
+    PreferIpv6,
+    /// IPv4 candidates only.
+    Ipv4,
+    /// IPv6 candidates only.
+    Ipv6,
+}
+
+impl Default for IpFamily {
+    fn default() -> Self {
+        Self::Dual
+    }
+}
+
+impl std::str::FromStr for IpFamily {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "dual" => Ok(Self::Dual),
+            "prefer-ipv4" => Ok(Self::PreferIpv4),
+            "prefer-ipv6" => Ok(Self::PreferIpv6),
+            "ipv4" => Ok(Self::Ipv4),
+            "ipv6" => Ok(Self::Ipv6),
+            _ => Err(format!("unknown ip family: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for IpFamily {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Dual => write!(f, "dual"),
+            Self::PreferIpv4 => write!(f, "prefer-ipv4"),
+            Self::PreferIpv6 => write!(f, "prefer-ipv6"),
+            Self::Ipv4 => write!(f, "ipv4"),
+            Self::Ipv6 => write!(f, "ipv6"),
+        }
+    }
+}
+
+impl IpFamily {
+    /// Check if `candidate`, in form of SDP `candidate` attribute, may be sent to remote.
+    /// Candidates of mDNS hostnames are always allowed, their family is unknown.
+    pub fn allows(&self, candidate: &str) -> bool {
+        match candidate_ip(candidate) {
+            Some(IpAddr::V4(_)) => *self != Self::Ipv6,
+            Some(IpAddr::V6(_)) => *self != Self::Ipv4,
+            None => true,
+        }
+    }
+
+    /// Sort key of `candidate`, candidates of preferred family go first.
+    pub fn rank(&self, candidate: &str) -> u8 {
+        match (self, candidate_ip(candidate)) {
+            (Self::PreferIpv4, Some(IpAddr::V6(_))) => 1,
+            (Self::PreferIpv6, Some(IpAddr::V4(_))) => 1,
+            _ => 0,
+        }
+    }
+}
+
+/// Connection address of `candidate`, None if it's a mDNS hostname.
+fn candidate_ip(candidate: &str) -> Option<IpAddr> {
+    candidate.split_whitespace().nth(4)?.parse().ok()
+}
+
+/// `ipv4` or `ipv6`, family of `ip`.
+pub fn address_family(ip: &IpAddr) -> &'static str {
+    match ip {
+        IpAddr::V4(_) => "ipv4",
+        IpAddr::V6(_) => "ipv6",
+    }
+}
+
+/// Metadata of node, exchanged with handshake info.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct HandshakeMeta {
+    /// Node has public address and is willing to relay messages for others.
+    #[serde(default)]
+    pub relay: bool,
+    /// Network of node, peers from other networks are refused.
+    #[serde(default = "default_network_id")]
+    pub network_id: String,
+    /// Highest protocol version supported, 0 if node is built before versioning.
+    #[serde(default)]
+    pub protocol_version: u16,
+    /// Lowest protocol version supported.
+    #[serde(default)]
+    pub min_protocol_version: u16,
+    /// How to handle peers without common protocol version, never sent to remote.
+    #[serde(skip)]
+    pub version_policy: VersionPolicy,
+    /// Protocol version agreed with remote, only set on metadata of remote.
+    #[serde(skip)]
+    pub negotiated_version: Option<u16>,
+    /// Codecs accepted for payloads, in order of preference, empty if node is built before
+    /// negotiation of compression.
+    #[serde(default)]
+    pub codecs: Vec<Codec>,
+    /// Payloads smaller than this are not compressed, never sent to remote.
+    #[serde(skip)]
+    pub compress_threshold: usize,
+    /// Codec of payloads sent to remote, only set on metadata of remote.
+    #[serde(skip)]
+    pub negotiated_codec: Option<Codec>,
+    /// Policy of local candidates gathered and sent to remote, the policy itself is kept
+    /// local.
+    #[serde(skip)]
+    pub ice_transport_policy: IceTransportPolicy,
+    /// Address families of local candidates, never sent to remote.
+    #[serde(skip)]
+    pub ip_family: IpFamily,
+    /// Nonce issued by remote for handshake over HTTP, signed with offer, see
+    /// [HandshakeNonces](crate::transports::helper::HandshakeNonces).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+}
+
+fn default_network_id() -> String {
+    DEFAULT_NETWORK_ID.to_owned()
+}
+
+impl Default for HandshakeMeta {
+    fn default() -> Self {
+        Self {
+            relay: false,
+            network_id: default_network_id(),
+            protocol_version: version::PROTOCOL_VERSION,
+            min_protocol_version: version::MIN_PROTOCOL_VERSION,
+            version_policy: VersionPolicy::default(),
+            negotiated_version: None,
+            codecs: Codec::supported(),
+            compress_threshold: codec::DEFAULT_COMPRESS_THRESHOLD,
+            negotiated_codec: None,
+            ice_transport_policy: IceTransportPolicy::default(),
+            ip_family: IpFamily::default(),
+            nonce: None,
+        }
+    }
+}
+
+impl HandshakeMeta {
+    /// Negotiate protocol version with remote, following local `version_policy`.
+    /// Return remote metadata with `negotiated_version` filled.
+    pub fn negotiate(&self, remote: &HandshakeMeta) -> Result<HandshakeMeta> {
+        let negotiated = version::negotiate(
+            self.min_protocol_version,
+            self.protocol_version,
+            remote.min_protocol_version,
+            remote.protocol_version,
+        );
+        if negotiated.is_none() {
+            let remote_range = format!(
+                "{}..={}",
+                remote.min_protocol_version, remote.protocol_version
+            );
+            let local_range = format!("{}..={}", self.min_protocol_version, self.protocol_version);
+            match self.version_policy {
+                VersionPolicy::Refuse => {
+                    return Err(Error::ProtocolVersionIncompatible(
+                        remote_range,
+                        local_range,
+                    ))
+                }
+                VersionPolicy::Warn => log::warn!(
+                    "no common protocol version, remote: {}, local: {}",
+                    remote_range,
+                    local_range
+                ),
+            }
+        }
+        Ok(HandshakeMeta {
+            negotiated_version: negotiated,
+            negotiated_codec: Some(codec::negotiate(&self.codecs, &remote.codecs)),
+            ..remote.clone()
+        })
+    }
+
+    /// Protocol version of payloads sent to remote, the negotiated one, or the highest local
+    /// one without a common version. Only meaningful on metadata of remote.
+    pub fn payload_version(&self) -> u16 {
+        self.negotiated_version.unwrap_or(version::PROTOCOL_VERSION)
+    }
+
+    /// Check if local `candidate` may be sent to remote, by `ice_transport_policy` and
+    /// `ip_family`.
+    pub fn allows_candidate(&self, candidate: &str) -> bool {
+        self.ice_transport_policy.allows(candidate) && self.ip_family.allows(candidate)
+    }
+
+    /// Local `candidate` as it's sent to remote, see [IceTransportPolicy::scrub].
+    pub fn scrub_candidate(&self, candidate: &str) -> String {
+        self.ice_transport_policy.scrub(candidate)
+    }
+
+    /// Drop local candidates not allowed from `sdp`, and hide local addresses of the others
+    /// and of connection lines, by `ice_transport_policy`.
+    pub fn filter_sdp(&self, sdp: &str) -> String {
+        sdp.split_inclusive('\n')
+            .filter(|l| !l.starts_with("a=candidate:") || self.allows_candidate(l))
+            .map(|l| {
+                if l.starts_with("a=candidate:") {
+                    self.scrub_candidate(l)
+                } else if l.starts_with("c=") {
+                    self.ice_transport_policy.scrub_connection(l)
+                } else {
+                    l.to_owned()
+                }
+            })
+            .collect()
+    }
+}
+
+/// Statistics of a transport, from WebRTC stats of its selected candidate pair.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransportStats {
+    /// Current RTT of candidate pair, in milliseconds.
+    pub rtt_ms: Option<u64>,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Frames lost on the link, None if transport can't tell.
+    pub packets_lost: Option<u64>,
+    /// Type of local candidate, `host`, `srflx`, `prflx` or `relay`.
+    pub local_candidate_type: Option<String>,
+    /// Type of remote candidate, `host`, `srflx`, `prflx` or `relay`.
+    pub remote_candidate_type: Option<String>,
+    /// Family of remote address, `ipv4` or `ipv6`.
+    #[serde(default)]
+    pub address_family: Option<String>,
+}
+
+impl TransportStats {
+    /// Link goes through a TURN server, on either side.
+    pub fn is_relayed(&self) -> bool {
+        [&self.local_candidate_type, &self.remote_candidate_type]
+            .iter()
+            .any(|t| t.as_deref() == Some("relay"))
+    }
+}
+
+/// Totals of [TransportStats] of all transports of a node.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransportSummary {
+    pub transports: usize,
+    /// Transports going through a TURN server.
+    pub relayed: usize,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Sum of frames lost known by transports.
+    pub packets_lost: u64,
+    /// Average RTT of transports reporting it, in milliseconds.
+    pub avg_rtt_ms: Option<u64>,
+}
+
+impl<'a> FromIterator<&'a TransportStats> for TransportSummary {
+    fn from_iter<I: IntoIterator<Item = &'a TransportStats>>(iter: I) -> Self {
+        let mut summary = Self::default();
+        let mut rtts = vec![];
+        for s in iter {
+            summary.transports += 1;
+            summary.relayed += s.is_relayed() as usize;
+            summary.bytes_sent += s.bytes_sent;
+            summary.bytes_received += s.bytes_received;
+            summary.packets_lost += s.packets_lost.unwrap_or_default();
+            rtts.extend(s.rtt_ms);
+        }
+        if !rtts.is_empty() {
+            summary.avg_rtt_ms = Some(rtts.iter().sum::<u64>() / rtts.len() as u64);
+        }
+        summary
+    }
+}
+
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
 #[cfg_attr(not(feature = "wasm"), async_trait)]
 pub trait IceTransport<E: Send, Ch: Channel<E>> {
@@ -45,7 +438,9 @@ pub trait IceTransport<E: Send, Ch: Channel<E>> {
     async fn close(&self) -> Result<()>;
     async fn ice_connection_state(&self) -> Option<Self::IceConnectionState>;
     async fn is_connected(&self) -> bool;
-    async fn pubkey(&self) -> PublicKey;
+    async fn pubkey(&self) -> Option<PublicKey>;
+    /// Key authorized by the remote DID, see [crate::session::Session::authorized_key].
+    async fn remote_key(&self) -> Option<Address>;
     async fn get_peer_connection(&self) -> Option<Arc<Self::Connection>>;
     async fn get_pending_candidates(&self) -> Vec<Self::Candidate>;
     async fn get_answer(&self) -> Result<Self::Sdp>;
@@ -54,6 +449,8 @@ pub trait IceTransport<E: Send, Ch: Channel<E>> {
     async fn get_offer_str(&self) -> Result<String>;
     async fn get_data_channel(&self) -> Option<Arc<Self::DataChannel>>;
     async fn send_message(&self, msg: &[u8]) -> Result<()>;
+    /// Statistics of link, like `getStats` of WebRTC.
+    async fn stats(&self) -> TransportStats;
     async fn set_local_description<T>(&self, desc: T) -> Result<()>
     where T: Into<Self::Sdp> + Send;
     async fn add_ice_candidate(&self, candidate: IceCandidate) -> Result<()>;
@@ -84,4 +481,142 @@ pub trait IceTrickleScheme<E: Send, Ch: Channel<E>>: IceTransport<E, Ch> {
     ) -> Result<Encoded>;
     async fn register_remote_info(&self, data: Encoded) -> Result<Address>;
     async fn wait_for_connected(&self) -> Result<()>;
+    /// Same as [wait_for_connected](Self::wait_for_connected), but fails after `timeout` or once
+    /// `cancel` is cancelled.
+    async fn wait_for_connected_timeout(
+        &self,
+        timeout: Duration,
+        cancel: &CancelToken,
+    ) -> Result<()>;
+    /// Set metadata sent to remote with handshake info.
+    async fn set_local_meta(&self, meta: HandshakeMeta);
+    /// Metadata received from remote, None if remote info is not registered yet.
+    async fn remote_meta(&self) -> Option<HandshakeMeta>;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_handshake_meta_negotiate() {
+        let local = HandshakeMeta::default();
+        let remote = local.negotiate(&HandshakeMeta::default()).unwrap();
+        assert_eq!(remote.negotiated_version, Some(version::PROTOCOL_VERSION));
+
+        // meta of nodes before versioning
+        let legacy: HandshakeMeta = serde_json::from_str("{}").unwrap();
+        let remote = local.negotiate(&legacy).unwrap();
+        assert_eq!(remote.negotiated_version, Some(0));
+        assert_eq!(remote.payload_version(), 0);
+        assert_eq!(remote.negotiated_codec, Some(Codec::fallback()));
+
+        let future = HandshakeMeta {
+            protocol_version: version::PROTOCOL_VERSION + 2,
+            min_protocol_version: version::PROTOCOL_VERSION + 1,
+            ..Default::default()
+        };
+        let remote = local.negotiate(&future).unwrap();
+        assert_eq!(remote.negotiated_version, None);
+        assert_eq!(remote.payload_version(), version::PROTOCOL_VERSION);
+        let strict = HandshakeMeta {
+            version_policy: VersionPolicy::Refuse,
+            ..Default::default()
+        };
+        assert!(strict.negotiate(&future).is_err());
+    }
+
+    #[test]
+    fn test_ice_transport_policy() {
+        let host = "candidate:1 1 udp 2130706431 192.168.1.2 50000 typ host";
+        let srflx =
+            "candidate:2 1 udp 1694498815 1.2.3.4 50000 typ srflx raddr 192.168.1.2 rport 50000";
+        let relay = "candidate:3 1 udp 16777215 5.6.7.8 3478 typ relay raddr 1.2.3.4 rport 50000";
+        let allowed = |p: IceTransportPolicy| {
+            [host, srflx, relay]
+                .iter()
+                .map(|c| p.allows(c))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(allowed(IceTransportPolicy::All), vec![true, true, true]);
+        assert_eq!(allowed(IceTransportPolicy::Relay), vec![false, false, true]);
+        assert_eq!(allowed(IceTransportPolicy::NoHost), vec![false, true, true]);
+        assert_eq!(
+            "no-host".parse::<IceTransportPolicy>().unwrap(),
+            IceTransportPolicy::NoHost
+        );
+
+        let meta = HandshakeMeta {
+            ice_transport_policy: IceTransportPolicy::NoHost,
+            ..Default::default()
+        };
+        // LAN address is never told to remote
+        let scrubbed = "candidate:2 1 udp 1694498815 1.2.3.4 50000 typ srflx raddr 0.0.0.0 rport 0";
+        assert_eq!(meta.scrub_candidate(srflx), scrubbed);
+        assert_eq!(IceTransportPolicy::All.scrub(srflx), srflx);
+        let sdp = format!(
+            "v=0\r\nc=IN IP4 192.168.1.2\r\na={}\r\na={}\r\na=end-of-candidates\r\n",
+            host, srflx
+        );
+        let filtered = meta.filter_sdp(&sdp);
+        assert_eq!(
+            filtered,
+            format!(
+                "v=0\r\nc=IN IP4 0.0.0.0\r\na={}\r\na=end-of-candidates\r\n",
+                scrubbed
+            )
+        );
+        assert!(!filtered.contains("192.168.1.2"));
+    }
+
+    #[test]
+    fn test_ip_family() {
+        let v4 = "candidate:1 1 udp 2130706431 192.168.1.2 50000 typ host";
+        let v6 = "candidate:2 1 udp 2130706431 fe80::1 50000 typ host";
+        let mdns = "candidate:3 1 udp 2130706431 4f1c2c1a-5d9e.local 50000 typ host";
+        assert!(IpFamily::Dual.allows(v4) && IpFamily::Dual.allows(v6));
+        assert!(IpFamily::Ipv4.allows(v4) && !IpFamily::Ipv4.allows(v6));
+        assert!(!IpFamily::Ipv6.allows(v4) && IpFamily::Ipv6.allows(v6));
+        assert!(IpFamily::Ipv4.allows(mdns) && IpFamily::Ipv6.allows(mdns));
+
+        let mut candidates = vec![v4, v6, mdns];
+        candidates.sort_by_key(|c| IpFamily::PreferIpv6.rank(c));
+        assert_eq!(candidates, vec![v6, mdns, v4]);
+        assert_eq!(
+            "prefer-ipv4".parse::<IpFamily>().unwrap(),
+            IpFamily::PreferIpv4
+        );
+    }
+
+    #[test]
+    fn test_transport_summary() {
+        let direct = TransportStats {
+            rtt_ms: Some(20),
+            bytes_sent: 100,
+            bytes_received: 50,
+            local_candidate_type: Some("host".to_owned()),
+            remote_candidate_type: Some("srflx".to_owned()),
+            ..Default::default()
+        };
+        let relayed = TransportStats {
+            rtt_ms: Some(80),
+            bytes_sent: 10,
+            packets_lost: Some(3),
+            local_candidate_type: Some("relay".to_owned()),
+            ..Default::default()
+        };
+        assert!(!direct.is_relayed());
+        assert!(relayed.is_relayed());
+        let summary: TransportSummary = [&direct, &relayed, &TransportStats::default()]
+            .into_iter()
+            .collect();
+        assert_eq!(summary, TransportSummary {
+            transports: 3,
+            relayed: 1,
+            bytes_sent: 110,
+            bytes_received: 50,
+            packets_lost: 3,
+            avg_rtt_ms: Some(50),
+        });
+    }
 }
@@ -3,3 +3,8 @@ use chrono::Utc;
 pub fn get_epoch_ms() -> u128 {
     Utc::now().timestamp_millis() as u128
 }
+
+/// Microseconds since epoch, to measure spans shorter than a millisecond.
+pub fn get_epoch_us() -> u128 {
+    (Utc::now().timestamp_nanos() / 1000) as u128
+}
@@ -0,0 +1,171 @@
+//! Decoding and verification of received payloads, off the receive loop.
+//!
+//! Every payload received is decompressed, its header is decoded, and its signatures are
+//! verified before anything else, by [prepare]. These are the costly steps of receiving, and
+//! they depend on nothing but the payload, so with workers [VerifyPool] runs them on blocking
+//! threads of tokio, several payloads at once, while [Swarm::iter_messages] still hands
+//! payloads to handler in order of receiving. Checks depending on state of swarm, like replay
+//! and expiry, are made after, in order.
+//!
+//! Browsers have no threads, payloads are always prepared inline there, and so they are
+//! without feature `tokio`.
+//!
+//! [Swarm::iter_messages]: crate::swarm::Swarm::iter_messages
+use serde::Serialize;
+
+use crate::address::Address;
+use crate::err::Result;
+use crate::message::codec::MAX_DECOMPRESSED_SIZE;
+use crate::message::Encoded;
+use crate::message::MessagePayload;
+use crate::message::RawPayload;
+use crate::types::channel::Event;
+
+/// Payload received, decoded with its signatures checked.
+#[derive(Debug)]
+pub struct Inbound {
+    /// Remote address of transport it came by.
+    pub from: Address,
+    pub payload: RawPayload,
+    /// Size of payload as received.
+    pub size: usize,
+    /// Both signatures of payload are valid.
+    pub verified: bool,
+}
+
+/// Event received, with its payload prepared if it's one.
+#[derive(Debug)]
+pub enum Received {
+    Payload(Inbound),
+    Event(Option<Event>),
+}
+
+/// Verify signatures of sender and origin of `payload` over its body, expiry is not checked.
+pub fn verify_signatures<T>(payload: &MessagePayload<T>) -> bool
+where T: Serialize {
+    payload
+        .verification
+        .verify_payload(&payload.data, &payload.network_id)
+        && payload
+            .origin_verification
+            .verify_payload(&payload.data, &payload.network_id)
+}
+
+/// Decode `msg` received from `from` and check its signatures. It's refused if it's more than
+/// [MAX_DECOMPRESSED_SIZE] decompressed, which is checked while decompressing.
+pub fn prepare(from: Address, msg: Vec<u8>) -> Result<Inbound> {
+    let size = msg.len();
+    let encoded: Encoded = msg.try_into()?;
+    let bytes: Vec<u8> = encoded.decode()?;
+    // body is decoded after checks of header, or never if it's relayed
+    let payload: RawPayload = MessagePayload::from_auto_within(&bytes, MAX_DECOMPRESSED_SIZE)?;
+    let verified = verify_signatures(&payload);
+    Ok(Inbound {
+        from,
+        payload,
+        size,
+        verified,
+    })
+}
+
+/// Runs [prepare] of at most `workers` payloads at once, on blocking threads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VerifyPool {
+    workers: usize,
+}
+
+impl VerifyPool {
+    /// Pool of `workers`, 0 to prepare payloads inline.
+    pub fn new(workers: usize) -> Self {
+        Self { workers }
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Payloads being prepared at once, at least 1.
+    pub fn concurrency(&self) -> usize {
+        self.workers.max(1)
+    }
+
+    /// Prepare payload of `ev`, other events are passed as they are.
+    pub async fn receive(self, ev: Result<Option<Event>>) -> Result<Received> {
+        match ev? {
+            Some(Event::DataChannelMessage(from, msg)) => {
+                Ok(Received::Payload(self.prepare(from, msg).await?))
+            }
+            ev => Ok(Received::Event(ev)),
+        }
+    }
+
+    #[cfg(all(not(feature = "wasm"), feature = "tokio"))]
+    async fn prepare(self, from: Address, msg: Vec<u8>) -> Result<Inbound> {
+        if self.workers == 0 {
+            return prepare(from, msg);
+        }
+        tokio::task::spawn_blocking(move || prepare(from, msg))
+            .await
+            .map_err(|e| crate::err::Error::VerifyPoolJoin(e.to_string()))?
+    }
+
+    #[cfg(any(feature = "wasm", not(feature = "tokio")))]
+    async fn prepare(self, from: Address, msg: Vec<u8>) -> Result<Inbound> {
+        prepare(from, msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+    use crate::message::Encoder;
+    use crate::message::Message;
+    use crate::session::SessionManager;
+
+    fn encoded(valid: bool) -> Vec<u8> {
+        let key = SecretKey::random();
+        let session = SessionManager::new_with_seckey(&key).unwrap();
+        let mut payload = MessagePayload::new_direct(
+            Message::LeaveDHT(crate::message::LeaveDHT {
+                id: key.address().into(),
+            }),
+            &session,
+            key.address().into(),
+        )
+        .unwrap();
+        if !valid {
+            payload.data = Message::LeaveDHT(crate::message::LeaveDHT {
+                id: SecretKey::random().address().into(),
+            });
+        }
+        payload.encode().unwrap().as_bytes().to_vec()
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    #[tokio::test]
+    async fn test_verify_pool() {
+        let from = SecretKey::random().address();
+        for pool in [VerifyPool::new(0), VerifyPool::new(4)] {
+            let ev = Ok(Some(Event::DataChannelMessage(from, encoded(true))));
+            match pool.receive(ev).await.unwrap() {
+                Received::Payload(inbound) => {
+                    assert!(inbound.verified);
+                    assert_eq!(inbound.from, from);
+                }
+                _ => panic!("payload is not prepared"),
+            }
+            let ev = Ok(Some(Event::DataChannelMessage(from, encoded(false))));
+            match pool.receive(ev).await.unwrap() {
+                Received::Payload(inbound) => assert!(!inbound.verified),
+                _ => panic!("payload is not prepared"),
+            }
+            let ev = Ok(Some(Event::DataChannelMessage(from, b"garbage".to_vec())));
+            assert!(pool.receive(ev).await.is_err());
+            assert!(matches!(
+                pool.receive(Ok(None)).await.unwrap(),
+                Received::Event(None)
+            ));
+        }
+    }
+}
@@ -0,0 +1,87 @@
+#![warn(missing_docs)]
+//! Protocol versions of wire format.
+//!
+//! Each node supports a range of protocol versions `[MIN_PROTOCOL_VERSION, PROTOCOL_VERSION]`.
+//! Both ends exchange their ranges in handshake info, and the highest common version is picked.
+//! Version `0` means the peer was built before versioning, so it never sends a version.
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Highest protocol version supported by this node, also stamped on every payload.
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// Lowest protocol version this node can talk with.
+pub const MIN_PROTOCOL_VERSION: u16 = 0;
+
+/// What to do when remote peer or payload has no common protocol version with us.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VersionPolicy {
+    /// Log a warning and go on.
+    Warn,
+    /// Refuse the peer or drop the payload.
+    Refuse,
+}
+
+impl Default for VersionPolicy {
+    fn default() -> Self {
+        Self::Warn
+    }
+}
+
+impl std::str::FromStr for VersionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(Self::Warn),
+            "refuse" => Ok(Self::Refuse),
+            _ => Err(format!("unknown version policy: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for VersionPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Warn => write!(f, "warn"),
+            Self::Refuse => write!(f, "refuse"),
+        }
+    }
+}
+
+/// Pick the highest version inside both `[local_min, local_max]` and `[remote_min, remote_max]`.
+pub fn negotiate(local_min: u16, local_max: u16, remote_min: u16, remote_max: u16) -> Option<u16> {
+    let low = local_min.max(remote_min);
+    let high = local_max.min(remote_max);
+    if low <= high {
+        Some(high)
+    } else {
+        None
+    }
+}
+
+/// Check if a payload stamped with `version` can be handled by this node.
+pub fn is_supported(version: u16) -> bool {
+    (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate(0, 1, 0, 1), Some(1));
+        assert_eq!(negotiate(0, 2, 0, 1), Some(1));
+        assert_eq!(negotiate(1, 3, 2, 5), Some(3));
+        assert_eq!(negotiate(2, 3, 0, 1), None);
+    }
+
+    #[test]
+    fn test_is_supported() {
+        assert!(is_supported(0));
+        assert!(is_supported(PROTOCOL_VERSION));
+        assert!(!is_supported(PROTOCOL_VERSION + 1));
+    }
+}
@@ -3,6 +3,7 @@ pub mod test {
     use std::str::FromStr;
     use std::sync::Arc;
 
+    use async_trait::async_trait;
     use futures::lock::Mutex;
     use rings_core::dht::vnode::VirtualNode;
     use rings_core::dht::Did;
@@ -13,8 +14,10 @@ pub mod test {
     use rings_core::message::Encoder;
     use rings_core::message::Message;
     use rings_core::message::MessageHandler;
+    use rings_core::message::MessagePayload;
     use rings_core::message::PayloadSender;
     use rings_core::session::SessionManager;
+    use rings_core::swarm::PayloadListener;
     use rings_core::swarm::Swarm;
     use rings_core::swarm::TransportManager;
     use rings_core::transports::Transport;
@@ -149,6 +152,46 @@ pub mod test {
         Ok(())
     }
 
+    struct CountingListener {
+        received: Mutex<Vec<Did>>,
+    }
+
+    #[async_trait]
+    impl PayloadListener for CountingListener {
+        async fn on_payload(&self, payload: &MessagePayload<Message>) {
+            self.received.lock().await.push(payload.addr.into());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_payload_listener() -> Result<()> {
+        let key1 = SecretKey::random();
+        let key2 = SecretKey::random();
+        let swarm1 = Arc::new(new_swarm(&key1));
+        let swarm2 = Arc::new(new_swarm(&key2));
+        let listener = Arc::new(CountingListener {
+            received: Mutex::new(vec![]),
+        });
+        let id = swarm1.register_listener(listener.clone());
+        let (_, _) = establish_connection(Arc::clone(&swarm1), Arc::clone(&swarm2)).await?;
+
+        // JoinDHT of registered transport
+        assert!(swarm1.poll_message().await.is_some());
+        assert_eq!(listener.received.lock().await.len(), 1);
+
+        assert!(swarm1.unregister_listener(id));
+        assert!(!swarm1.unregister_listener(id));
+        swarm2
+            .send_direct_message(
+                Message::custom("hello".as_bytes(), &None)?,
+                key1.address().into(),
+            )
+            .await?;
+        assert!(swarm1.poll_message().await.is_some());
+        assert_eq!(listener.received.lock().await.len(), 1);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_handle_connect_node() -> Result<()> {
         let mut key1 = SecretKey::random();
@@ -393,6 +436,7 @@ pub mod test {
                         Message::FindSuccessorSend(message::FindSuccessorSend {
                             id: swarm2.address().into(),
                             for_fix: false,
+                            finger: None,
                         }),
                         swarm1.address().into(),
                         swarm1.address().into(),
@@ -483,6 +527,7 @@ pub mod test {
                         Message::FindSuccessorSend(message::FindSuccessorSend {
                             id: swarm2.address().into(),
                             for_fix: false,
+                            finger: None,
                         }),
                         swarm1.address().into(),
                         swarm1.address().into(),
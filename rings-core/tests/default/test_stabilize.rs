@@ -120,7 +120,7 @@ pub mod test {
 
     async fn run_stabilize(chord: Arc<Mutex<PeerRing>>, swarm: Arc<Swarm>) {
         let mut result = Result::<()>::Ok(());
-        let stabilization = Stabilization::new(chord, swarm, 5usize);
+        let stabilization = Stabilization::new(chord, swarm).with_interval(5, 5);
         let timeout_in_secs = stabilization.get_timeout();
         println!("RUN Stabilization");
         while result.is_ok() {
@@ -179,7 +179,7 @@ pub mod test {
                 transport_1_to_2.wait_for_data_channel_open().await.unwrap();
                 assert!(dht1.lock().await.successor.list().contains(&key2.address().into()));
                 assert!(dht2.lock().await.successor.list().contains(&key1.address().into()));
-                let stabilization = Stabilization::new(Arc::clone(&dht1), Arc::clone(&swarm1), 5usize);
+                let stabilization = Stabilization::new(Arc::clone(&dht1), Arc::clone(&swarm1));
                 let _ = stabilization.stabilize().await;
                 sleep(Duration::from_millis(10000)).await;
                 assert_eq!(dht2.lock().await.predecessor, Some(key1.address().into()));
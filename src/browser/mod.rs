@@ -1,16 +1,18 @@
 //! rings-node browser support.
 #![allow(clippy::unused_unit)]
+pub mod resume;
 pub mod utils;
 
 use std::str::FromStr;
 use std::sync::Arc;
 
 use futures::lock::Mutex;
+use futures::StreamExt;
 use js_sys::Promise;
-use rings_core_wasm::dht::TStabilize;
 use serde::Deserialize;
 use serde::Serialize;
 
+use self::resume::ResumeState;
 use self::utils::from_rtc_ice_connection_state;
 use crate::prelude::js_sys;
 use crate::prelude::rings_core::async_trait;
@@ -24,7 +26,7 @@ use crate::prelude::rings_core::message::Message;
 use crate::prelude::rings_core::message::MessageCallback;
 use crate::prelude::rings_core::message::MessageHandler;
 use crate::prelude::rings_core::message::MessagePayload;
-use crate::prelude::rings_core::prelude::web3::types::Address;
+use crate::prelude::rings_core::prelude::Address;
 use crate::prelude::rings_core::session::AuthorizedInfo;
 use crate::prelude::rings_core::session::SessionManager;
 use crate::prelude::rings_core::session::Signer;
@@ -37,9 +39,9 @@ use crate::prelude::wasm_bindgen;
 use crate::prelude::wasm_bindgen::prelude::*;
 use crate::prelude::wasm_bindgen_futures;
 use crate::prelude::wasm_bindgen_futures::future_to_promise;
-use crate::prelude::web3::contract::tokens::Tokenizable;
 use crate::prelude::web_sys::RtcIceConnectionState;
 use crate::processor;
+use crate::processor::parse_did;
 use crate::processor::Processor;
 
 #[wasm_bindgen(start)]
@@ -126,10 +128,54 @@ impl UnsignedInfo {
 /// const sig = new Uint8Array(web3.utils.hexToBytes(signed));
 /// const client = new Client(unsignedInfo, sig, stunOrTurnUrl);
 /// ```
+/// After a refresh of page, client saved by [Client::persist] is made again by
+/// [Client::restore], see [resume].
 #[wasm_bindgen]
 #[derive(Clone)]
 pub struct Client {
     processor: Arc<Processor>,
+    stuns: String,
+    /// Urls of nodes connected by [Client::connect_peer_via_http].
+    bootstraps: Arc<Mutex<Vec<String>>>,
+}
+
+impl Client {
+    fn new_with_session(
+        address: Address,
+        session: SessionManager,
+        stuns: String,
+    ) -> Result<Client, JsError> {
+        let swarm = Arc::new(
+            Swarm::builder(address, session)
+                .with_ice_servers(&stuns)
+                .build()
+                .map_err(JsError::from)?,
+        );
+        let pr = PeerRing::new(swarm.address().into()).with_route_cache(swarm.route_cache());
+        let dht = Arc::new(Mutex::new(pr));
+        let msg_handler = Arc::new(MessageHandler::new(dht.clone(), swarm.clone()));
+        let stabilization = Arc::new(Stabilization::new(dht, swarm.clone()));
+        let processor = Arc::new(Processor::from((swarm, msg_handler, stabilization)));
+        Ok(Client {
+            processor,
+            stuns,
+            bootstraps: Arc::new(Mutex::new(vec![])),
+        })
+    }
+
+    async fn save_state(&self) -> Result<(), JsError> {
+        let swarm = &self.processor.swarm;
+        let bootstraps = self.bootstraps.lock().await.clone();
+        let peers = swarm
+            .get_addresses()
+            .iter()
+            .map(|a| format!("{:?}", a))
+            .collect();
+        ResumeState::new(swarm.session_manager(), &self.stuns, bootstraps, peers)?
+            .save()
+            .await?;
+        Ok(())
+    }
 }
 
 #[wasm_bindgen]
@@ -142,13 +188,110 @@ impl Client {
     ) -> Result<Client, JsError> {
         let random_key = unsigned_info.random_key;
         let session = SessionManager::new(&signed_data.to_vec(), &unsigned_info.auth, &random_key);
-        let swarm = Arc::new(Swarm::new(&stuns, unsigned_info.key_addr, session));
-        let pr = PeerRing::new(swarm.address().into());
-        let dht = Arc::new(Mutex::new(pr));
-        let msg_handler = Arc::new(MessageHandler::new(dht.clone(), swarm.clone()));
-        let stabilization = Arc::new(Stabilization::new(dht, swarm.clone(), 20));
-        let processor = Arc::new(Processor::from((swarm, msg_handler, stabilization)));
-        Ok(Client { processor })
+        Self::new_with_session(unsigned_info.key_addr, session, stuns)
+    }
+
+    /// Make client saved by [Client::persist] again, without signing by wallet. Resolves to
+    /// null if nothing is saved, or the saved session is expired.
+    /// ```typescript
+    /// const client = (await Client.restore()) ?? new Client(unsignedInfo, sig, stunOrTurnUrl);
+    /// ```
+    pub fn restore() -> Promise {
+        future_to_promise(async move {
+            let state = match ResumeState::load().await.map_err(JsError::from)? {
+                Some(state) => state,
+                None => return Ok(JsValue::null()),
+            };
+            let session = match state.session_manager() {
+                Ok(session) => session,
+                Err(e) => {
+                    log::warn!("drop saved state of client: {}", e);
+                    ResumeState::clear().await.map_err(JsError::from)?;
+                    return Ok(JsValue::null());
+                }
+            };
+            let address = state.session.auth.authorizer;
+            let client = Self::new_with_session(address, session, state.stuns)?;
+            *client.bootstraps.lock().await = state.bootstraps;
+            Ok(JsValue::from(client))
+        })
+    }
+
+    /// Save session, bootstrap nodes and connected peers of client, for [Client::restore] and
+    /// [Client::resume] after a refresh of page. Call it again once peers change.
+    pub fn persist(&self) -> Promise {
+        let this = self.clone();
+        future_to_promise(async move {
+            this.save_state().await?;
+            Ok(JsValue::null())
+        })
+    }
+
+    /// Remove state saved by [Client::persist], like on logout.
+    pub fn forget() -> Promise {
+        future_to_promise(async move {
+            ResumeState::clear().await.map_err(JsError::from)?;
+            Ok(JsValue::null())
+        })
+    }
+
+    /// Reconnect to peers saved by [Client::persist], resolves to addresses of peers connected.
+    /// Bootstrap nodes are connected over HTTP first, then other peers by offers relayed
+    /// through them, all of each at once. It should be called after [Client::start] or [Client::listen].
+    pub fn resume(&self) -> Promise {
+        let this = self.clone();
+        future_to_promise(async move {
+            let state = match ResumeState::load().await.map_err(JsError::from)? {
+                Some(state) => state,
+                None => return Ok(js_sys::Array::new().into()),
+            };
+            let p = this.processor.clone();
+            // each is reconnected at once, so one unreachable doesn't hold up the others
+            futures::future::join_all(state.bootstraps.iter().map(|url| {
+                let p = p.clone();
+                async move {
+                    match p.connect_peer_via_http(url).await {
+                        Ok(transport) => {
+                            if let Err(e) = transport.wait_for_data_channel_open().await {
+                                log::warn!("bootstrap {} not ready: {}", url, e);
+                            }
+                        }
+                        Err(e) => log::warn!("failed to reconnect bootstrap {}: {}", url, e),
+                    }
+                }
+            }))
+            .await;
+            let connected = p.swarm.get_addresses();
+            let peers = state.peers.iter().filter_map(|peer| match parse_did(peer) {
+                Ok(did) => Some((peer, Address::from(did))),
+                Err(e) => {
+                    log::warn!("skip saved peer {}: {}", peer, e);
+                    None
+                }
+            });
+            futures::future::join_all(
+                peers
+                    .filter(|(_, address)| !connected.contains(address))
+                    .map(|(peer, address)| {
+                        let p = p.clone();
+                        async move {
+                            if let Err(e) = p.connect_with_address(&address, true).await {
+                                log::warn!("failed to reconnect {}: {}", peer, e);
+                            }
+                        }
+                    }),
+            )
+            .await;
+            this.save_state().await?;
+            let mut js_array = js_sys::Array::new();
+            js_array.extend(
+                p.swarm
+                    .get_addresses()
+                    .into_iter()
+                    .map(|a| JsValue::from_str(&format!("{:?}", a))),
+            );
+            Ok(js_array.into())
+        })
     }
 
     /// start backgroud listener without custom callback
@@ -158,14 +301,8 @@ impl Client {
         future_to_promise(async move {
             let h = Arc::clone(&p.msg_handler);
             let s = Arc::clone(&p.stabilization);
-            futures::join!(
-                async {
-                    h.listen().await;
-                },
-                async {
-                    s.wait().await;
-                }
-            );
+            s.spawn();
+            h.listen().await;
             Ok(JsValue::null())
         })
     }
@@ -173,7 +310,7 @@ impl Client {
     /// get self web3 address
     #[wasm_bindgen(getter)]
     pub fn address(&self) -> String {
-        self.processor.address().into_token().to_string()
+        format!("{:x}", self.processor.address())
     }
 
     /// listen message callback.
@@ -201,14 +338,8 @@ impl Client {
             let h = Arc::clone(&p.msg_handler);
             let s = Arc::clone(&p.stabilization);
             h.set_callback(cb).await;
-            futures::join!(
-                async {
-                    h.listen().await;
-                },
-                async {
-                    s.wait().await;
-                }
-            );
+            s.spawn();
+            h.listen().await;
             Ok(JsValue::null())
         })
     }
@@ -217,11 +348,18 @@ impl Client {
     pub fn connect_peer_via_http(&self, remote_url: String) -> Promise {
         log::debug!("remote_url: {}", remote_url);
         let p = self.processor.clone();
+        let bootstraps = self.bootstraps.clone();
         future_to_promise(async move {
             let transport = p
                 .connect_peer_via_http(remote_url.as_str())
                 .await
                 .map_err(JsError::from)?;
+            {
+                let mut bootstraps = bootstraps.lock().await;
+                if !bootstraps.contains(&remote_url) {
+                    bootstraps.push(remote_url.clone());
+                }
+            }
             log::debug!("connect_peer_via_http transport_id: {:?}", transport.id);
             Ok(JsValue::from_str(transport.id.to_string().as_str()))
         })
@@ -231,8 +369,7 @@ impl Client {
     pub fn connect_with_address_without_wait(&self, address: String) -> Promise {
         let p = self.processor.clone();
         future_to_promise(async move {
-            let address =
-                Address::from_str(address.as_str()).map_err(|_| JsError::new("invalid address"))?;
+            let address: Address = parse_did(address.as_str()).map_err(JsError::from)?.into();
             let peer = p
                 .connect_with_address(&address, false)
                 .await
@@ -255,8 +392,7 @@ impl Client {
     pub fn connect_with_address(&self, address: String) -> Promise {
         let p = self.processor.clone();
         future_to_promise(async move {
-            let address =
-                Address::from_str(address.as_str()).map_err(|_| JsError::new("invalid address"))?;
+            let address: Address = parse_did(address.as_str()).map_err(JsError::from)?.into();
             let peer = p
                 .connect_with_address(&address, true)
                 .await
@@ -371,6 +507,57 @@ impl Client {
         })
     }
 
+    /// send custom message to peer, and ask it for a receipt, resolves `tx_id` of message.
+    /// Receipt is handed to `builtin_message` callback, or fetched by `delivery_receipt`.
+    pub fn send_message_with_receipt(
+        &self,
+        destination: String,
+        msg: js_sys::Uint8Array,
+    ) -> Promise {
+        let p = self.processor.clone();
+        future_to_promise(async move {
+            let tx_id = p
+                .send_message_with_receipt(destination.as_str(), &msg.to_vec())
+                .await
+                .map_err(JsError::from)?;
+            Ok(JsValue::from_str(&tx_id))
+        })
+    }
+
+    /// receipt of message `tx_id` sent with receipt, null until it arrives.
+    pub fn delivery_receipt(&self, tx_id: String) -> Result<JsValue, JsError> {
+        match self.processor.delivery_receipt(&tx_id) {
+            Some(receipt) => JsValue::from_serde(&receipt).map_err(JsError::from),
+            None => Ok(JsValue::null()),
+        }
+    }
+
+    /// track presence of `did`, its changes are passed to callbacks of `on_presence`
+    pub fn track_presence(&self, did: String) -> Result<(), JsError> {
+        self.processor.track_presence(&did).map_err(JsError::from)
+    }
+
+    /// stop tracking presence of `did`
+    pub fn untrack_presence(&self, did: String) -> Result<(), JsError> {
+        self.processor.untrack_presence(&did).map_err(JsError::from)
+    }
+
+    /// call `callback` with each presence change of tracked DIDs, like
+    /// `{ did, online, record }`, the promise never resolves
+    pub fn on_presence(&self, callback: js_sys::Function) -> Promise {
+        let mut events = self.processor.swarm.presence().subscribe();
+        future_to_promise(async move {
+            let this = JsValue::null();
+            while let Some(ev) = events.next().await {
+                let ev = JsValue::from_serde(&ev).map_err(JsError::from)?;
+                if let Err(e) = callback.call1(&this, &ev) {
+                    log::warn!("invoke on_presence error: {:?}", e);
+                }
+            }
+            Ok(JsValue::null())
+        })
+    }
+
     /// get peer by address
     pub fn get_peer(&self, address: String) -> Promise {
         let p = self.processor.clone();
@@ -516,7 +703,7 @@ impl Peer {
 impl From<(Option<RtcIceConnectionState>, processor::Peer)> for Peer {
     fn from((st, p): (Option<RtcIceConnectionState>, processor::Peer)) -> Self {
         Self {
-            address: p.address.to_string(),
+            address: format!("{:x}", *p.address),
             transport_id: p.transport.id.to_string(),
             state: st.map(from_rtc_ice_connection_state),
         }
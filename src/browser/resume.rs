@@ -0,0 +1,101 @@
+//! State of browser client kept across refreshes of page.
+//!
+//! [Client::persist](super::Client::persist) saves session of client, with its ICE servers,
+//! bootstrap nodes connected over HTTP and addresses of connected peers, to IndexedDB.
+//! [Client::restore](super::Client::restore) makes the same client again without signing by
+//! wallet, and [Client::resume](super::Client::resume) reconnects to bootstrap nodes first, then
+//! to other peers by offers relayed through them.
+//!
+//! Session key is never saved. A key delegated by the session for [RESUME_TTL_MS] is saved
+//! instead, see [SessionManager::delegate], so anyone reading IndexedDB of the page can sign as
+//! this client only until the delegation expires. Expired state is removed on restore.
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::prelude::rings_core::ecc::SecretKey;
+use crate::prelude::rings_core::err::Error;
+use crate::prelude::rings_core::err::Result;
+use crate::prelude::rings_core::session::Session;
+use crate::prelude::rings_core::session::SessionManager;
+use crate::prelude::rings_core::session::Ttl;
+use crate::prelude::rings_core::storage::PersistenceStorageReadAndWrite;
+use crate::prelude::rings_core::storage::PersistenceStorageRemove;
+use crate::prelude::rings_core::storage::Storage;
+
+/// Key of state in IndexedDB.
+const STATE_KEY: &str = "rings-client-state";
+/// Saved state is valid this long after it's saved, in milliseconds.
+pub const RESUME_TTL_MS: usize = 60 * 60 * 1000;
+
+/// State of client to resume.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ResumeState {
+    /// Session delegated by session of client.
+    pub session: Session,
+    /// Key of delegated session, in hex.
+    pub session_key: String,
+    pub stuns: String,
+    /// Urls of bootstrap nodes connected over HTTP.
+    pub bootstraps: Vec<String>,
+    /// Addresses of connected peers.
+    pub peers: Vec<String>,
+}
+
+impl ResumeState {
+    /// State of `session_manager`, with connected ones of it. Session of a restored client is
+    /// delegated already, it's saved again as is, so its chain doesn't grow on every refresh.
+    pub fn new(
+        session_manager: &SessionManager,
+        stuns: &str,
+        bootstraps: Vec<String>,
+        peers: Vec<String>,
+    ) -> Result<Self> {
+        let (session, key) = match session_manager.session()?.delegations.is_empty() {
+            true => {
+                let delegate = session_manager.delegate(Some(Ttl::Some(RESUME_TTL_MS)))?;
+                (delegate.session()?, delegate.session_key()?)
+            }
+            false => (session_manager.session()?, session_manager.session_key()?),
+        };
+        Ok(Self {
+            session,
+            session_key: key.to_string(),
+            stuns: stuns.to_owned(),
+            bootstraps,
+            peers,
+        })
+    }
+
+    /// Session manager of saved session, fails if the session is expired.
+    pub fn session_manager(&self) -> Result<SessionManager> {
+        let key = SecretKey::try_from(self.session_key.as_str())?;
+        SessionManager::new_with_delegations(
+            &self.session.sig,
+            &self.session.auth,
+            &key,
+            self.session.delegations.clone(),
+        )
+    }
+
+    /// Saved state, None if there is none.
+    pub async fn load() -> Result<Option<Self>> {
+        let storage = Storage::new().await?;
+        match storage.get(&STATE_KEY.to_owned()).await {
+            Ok(state) => Ok(Some(state)),
+            Err(Error::Deserialize(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Save state, replacing the saved one.
+    pub async fn save(&self) -> Result<()> {
+        let storage = Storage::new().await?;
+        storage.put(&STATE_KEY.to_owned(), self).await
+    }
+
+    /// Remove saved state.
+    pub async fn clear() -> Result<()> {
+        let storage = Storage::new().await?;
+        storage.remove(&STATE_KEY.to_owned()).await
+    }
+}
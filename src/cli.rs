@@ -1,12 +1,48 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
 use jsonrpc_core::Params;
 use jsonrpc_core::Value;
 //use jsonrpc_core_client::RawClient;
 use serde_json::json;
 
 use crate::jsonrpc::method::Method;
+use crate::jsonrpc::response::BenchmarkReport;
+use crate::jsonrpc::response::CrawlReport;
+use crate::jsonrpc::response::ErasureInfo;
+use crate::jsonrpc::response::ErasureValue;
+use crate::jsonrpc::response::FileInfo;
+use crate::jsonrpc::response::GroupInfo;
+use crate::jsonrpc::response::GroupKeyInfo;
+use crate::jsonrpc::response::GroupSendResult;
+use crate::jsonrpc::response::KnownPeerInfo;
+use crate::jsonrpc::response::LocalData;
+use crate::jsonrpc::response::LocalDataPage;
+use crate::jsonrpc::response::ManifestInfo;
+use crate::jsonrpc::response::NodeInfo;
 use crate::jsonrpc::response::Peer;
+use crate::jsonrpc::response::PeerPage;
+use crate::jsonrpc::response::PendingPage;
+use crate::jsonrpc::response::PresenceInfo;
+use crate::jsonrpc::response::ServiceProvider;
+use crate::jsonrpc::response::StateSnapshot;
+use crate::jsonrpc::response::TopicInfo;
+use crate::jsonrpc::response::TopicRecordInfo;
 use crate::jsonrpc::response::TransportAndIce;
+use crate::jsonrpc_client::HttpOptions;
+use crate::jsonrpc_client::RetryPolicy;
 use crate::jsonrpc_client::SimpleClient;
+use crate::prelude::rings_core::capture::CapturedPayload;
+#[cfg(feature = "chaos")]
+use crate::prelude::rings_core::chaos::FaultConfig;
+use crate::prelude::rings_core::dht::RepairReport;
+use crate::prelude::rings_core::dht::StabilizationStatus;
+use crate::prelude::rings_core::file::TransferProgress;
+use crate::prelude::rings_core::history::HistoryFilter;
+use crate::prelude::rings_core::history::HistoryPage;
+use crate::prelude::rings_core::rotation::RotationRecord;
+use crate::processor::PeerFilter;
+use crate::processor::StabilizationControl;
 
 #[derive(Clone)]
 pub struct Client {
@@ -21,10 +57,37 @@ type Output<T> = anyhow::Result<ClientOutput<T>>;
 
 impl Client {
     pub async fn new(endpoint_url: &str) -> anyhow::Result<Self> {
-        let client = SimpleClient::new_with_url(endpoint_url);
+        Self::new_with_options(endpoint_url, &HttpOptions::default()).await
+    }
+
+    /// Call node at `endpoint_url` by a http client of `options`.
+    pub async fn new_with_options(
+        endpoint_url: &str,
+        options: &HttpOptions,
+    ) -> anyhow::Result<Self> {
+        let client = SimpleClient::new_with_options(endpoint_url, options)
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
         Ok(Self { client })
     }
 
+    /// Fail calls not finished in `timeout`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.client = self.client.with_timeout(timeout);
+        self
+    }
+
+    /// Retry failed calls with `retry`, see [RetryPolicy].
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.client = self.client.with_retry(retry);
+        self
+    }
+
+    /// Call admin methods of node with `token`.
+    pub fn with_admin_token(mut self, token: &str) -> Self {
+        self.client = self.client.with_admin_token(token);
+        self
+    }
+
     pub async fn connect_peer_via_http(&mut self, http_url: &str) -> Output<String> {
         let resp = self
             .client
@@ -116,29 +179,61 @@ impl Client {
         )
     }
 
-    pub async fn list_peers(&mut self) -> Output<Vec<Peer>> {
+    pub async fn list_peers(
+        &mut self,
+        filter: &PeerFilter,
+        cursor: Option<&str>,
+    ) -> Output<PeerPage> {
         let resp = self
             .client
-            .call_method(Method::ListPeers.as_str(), Params::Array(vec![]))
+            .call_method(
+                Method::ListPeers.as_str(),
+                Params::Array(vec![json!(filter), json!(cursor)]),
+            )
             .await
             .map_err(|e| anyhow::anyhow!("{}", e))?;
 
-        let peers: Vec<Peer> =
-            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let page: PeerPage = serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
 
         let mut display = String::new();
         display.push_str("Successful\n");
-        display.push_str("Address, TransportId\n");
+        display.push_str("Address, TransportId, Link, Tags\n");
         display.push_str(
-            peers
+            page.peers
                 .iter()
-                .map(|peer| format!("{}, {}", peer.address, peer.transport_id))
+                .map(|peer| {
+                    let tags = peer
+                        .tags
+                        .iter()
+                        .map(|(k, v)| format!("{}={}", k, v))
+                        .collect::<Vec<_>>();
+                    let link = match &peer.stats {
+                        Some(s) => {
+                            let kind = if s.is_relayed() { "relayed" } else { "direct" };
+                            match &s.address_family {
+                                Some(family) => format!("{} {}", kind, family),
+                                None => kind.to_owned(),
+                            }
+                        }
+                        None => "-".to_owned(),
+                    };
+                    format!(
+                        "{}, {}, {}, {}",
+                        peer.address,
+                        peer.transport_id,
+                        link,
+                        tags.join(" ")
+                    )
+                })
                 .collect::<Vec<_>>()
                 .join("\n")
                 .as_str(),
         );
+        if let Some(cursor) = &page.next_cursor {
+            display.push_str(&format!("\nNext cursor: {}", cursor));
+        }
 
-        ClientOutput::ok(display, peers)
+        ClientOutput::ok(display, page)
     }
 
     pub async fn disconnect(&mut self, address: &str) -> Output<()> {
@@ -153,19 +248,287 @@ impl Client {
         ClientOutput::ok("Done.".into(), ())
     }
 
-    pub async fn list_pendings(&self) -> Output<()> {
+    pub async fn node_info(&self) -> Output<NodeInfo> {
         let resp = self
             .client
-            .call_method(Method::ListPendings.as_str(), Params::Array(vec![]))
+            .call_method(Method::NodeInfo.as_str(), Params::Array(vec![]))
             .await
             .map_err(|e| anyhow::anyhow!("{}", e))?;
-        let resp: Vec<String> =
+        let info: NodeInfo = serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let mut display = format!(
+            "address: {}\nversion: {}\nprotocol version: {}..={} ({})\nnetwork id: {}\nrelay: {}",
+            info.address,
+            info.version,
+            info.min_protocol_version,
+            info.protocol_version,
+            info.version_policy,
+            info.network_id,
+            info.relay
+        );
+        for s in info.compression.iter() {
+            display.push_str(&format!(
+                "\ncompression {}: {} payloads, ratio {:.2}",
+                s.codec, s.payloads, s.ratio
+            ));
+        }
+        let t = &info.transports;
+        display.push_str(&format!(
+            "\ntransports: {} ({} relayed), sent {} bytes, received {} bytes",
+            t.transports, t.relayed, t.bytes_sent, t.bytes_received
+        ));
+        if let Some(rtt) = t.avg_rtt_ms {
+            display.push_str(&format!(", avg rtt {}ms", rtt));
+        }
+        ClientOutput::ok(display, info)
+    }
+
+    pub async fn drain(&self) -> Output<()> {
+        self.client
+            .call_method(Method::Drain.as_str(), Params::Array(vec![]))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        ClientOutput::ok("Drained, node is stopping.".into(), ())
+    }
+
+    pub async fn rotate_identity(&self, record: &RotationRecord) -> Output<()> {
+        self.client
+            .call_method(
+                Method::RotateIdentity.as_str(),
+                Params::Array(vec![json!(record)]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        ClientOutput::ok(
+            format!(
+                "Identity rotated to {:?}, restart node with the new key.",
+                *record.data.new
+            ),
+            (),
+        )
+    }
+
+    /// Verify successors, fingers and stored data of DHT now.
+    pub async fn repair_dht(&self) -> Output<RepairReport> {
+        let resp = self
+            .client
+            .call_method(Method::RepairDht.as_str(), Params::Array(vec![]))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let report: RepairReport =
             serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
-        let mut display = String::new();
-        for item in resp.iter() {
-            display.push_str(item)
+        let display = format!(
+            "dropped: {}, notified: {}\nfingers fixed: {}, looked up: {}\nvnodes moved: {}\nsuccessors: {}",
+            report.dropped.len(),
+            report.notified.len(),
+            report.fixed_fingers,
+            report.finger_lookups,
+            report.moved_vnodes.len(),
+            report
+                .successors
+                .iter()
+                .map(|s| format!("{:?}", **s))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        ClientOutput::ok(display, report)
+    }
+
+    /// List data of DHT stored by node, one page after `cursor`.
+    pub async fn list_local_data(
+        &self,
+        cursor: Option<&str>,
+        limit: Option<usize>,
+    ) -> Output<LocalDataPage> {
+        let resp = self
+            .client
+            .call_method(
+                Method::ListLocalData.as_str(),
+                Params::Array(vec![json!(cursor), json!(limit)]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let page: LocalDataPage =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let mut display = page
+            .data
+            .iter()
+            .map(|d| format!("{} {:?} entries: {}", d.key, d.kind, d.data.len()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if let Some(cursor) = &page.next_cursor {
+            display.push_str(&format!("\nNext cursor: {}", cursor));
+        }
+        ClientOutput::ok(display, page)
+    }
+
+    /// Delete data of DHT `key` stored by node.
+    pub async fn delete_local_data(&self, key: &str) -> Output<LocalData> {
+        let resp = self
+            .client
+            .call_method(
+                Method::DeleteLocalData.as_str(),
+                Params::Array(vec![json!(key)]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let data: LocalData = serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        ClientOutput::ok(format!("Deleted {} {:?}.", data.key, data.kind), data)
+    }
+
+    /// Show state of stabilization, after applying `control` if it's set.
+    pub async fn stabilization(
+        &self,
+        control: Option<StabilizationControl>,
+    ) -> Output<StabilizationStatus> {
+        let resp = match control {
+            Some(control) => {
+                let params: Params = serde_json::from_value(json!(control))?;
+                self.client
+                    .call_method(Method::ControlStabilization.as_str(), params)
+                    .await
+            }
+            None => {
+                self.client
+                    .call_method(Method::StabilizationStatus.as_str(), Params::Array(vec![]))
+                    .await
+            }
+        }
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let status: StabilizationStatus =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let display = format!(
+            "running: {}, paused: {}\ninterval: {}s, range: {}s-{}s\nlast round: {}",
+            status.running,
+            status.paused,
+            status.interval,
+            status.min_interval,
+            status.max_interval,
+            status
+                .last_round_ms
+                .map(|ts| ts.to_string())
+                .unwrap_or_else(|| "never".to_owned())
+        );
+        ClientOutput::ok(display, status)
+    }
+
+    pub async fn captured_payloads(&self, clear: bool) -> Output<Vec<CapturedPayload>> {
+        let resp = self
+            .client
+            .call_method(
+                Method::CapturedPayloads.as_str(),
+                Params::Array(vec![json!(clear)]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let records: Vec<CapturedPayload> =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let display = records
+            .iter()
+            .map(|r| {
+                format!(
+                    "{} {:?} {:?} {} {} bytes, path: {:?}, next_hop: {:?}, destination: {:?}, age: {}ms",
+                    r.ts_ms,
+                    r.direction,
+                    r.peer,
+                    r.message_type,
+                    r.size,
+                    r.path,
+                    r.next_hop,
+                    r.destination,
+                    r.age_ms
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        ClientOutput::ok(display, records)
+    }
+
+    pub async fn export_state(&self) -> Output<StateSnapshot> {
+        let resp = self
+            .client
+            .call_method(Method::ExportState.as_str(), Params::Array(vec![]))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let snapshot: StateSnapshot =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let display = serde_json::to_string_pretty(&snapshot)?;
+        ClientOutput::ok(display, snapshot)
+    }
+
+    pub async fn import_state(&self, snapshot: &StateSnapshot) -> Output<()> {
+        self.client
+            .call_method(
+                Method::ImportState.as_str(),
+                Params::Array(vec![json!(snapshot)]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        ClientOutput::ok(
+            format!(
+                "Imported state of {}, {} successors, {} fingers",
+                snapshot.address,
+                snapshot.dht.successors.len(),
+                snapshot.dht.finger.iter().flatten().count()
+            ),
+            (),
+        )
+    }
+
+    pub async fn list_messages(
+        &self,
+        filter: &HistoryFilter,
+        cursor: Option<&str>,
+    ) -> Output<HistoryPage> {
+        let resp = self
+            .client
+            .call_method(
+                Method::ListMessages.as_str(),
+                Params::Array(vec![json!(filter), json!(cursor)]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let page: HistoryPage =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let mut display = page
+            .messages
+            .iter()
+            .map(|r| {
+                format!(
+                    "{} {} {:?} {}",
+                    r.ts_ms,
+                    r.tx_id,
+                    r.sender,
+                    String::from_utf8_lossy(&r.data)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        if let Some(cursor) = &page.next_cursor {
+            display.push_str(&format!("\nNext cursor: {}", cursor));
         }
-        ClientOutput::ok(display, ())
+        ClientOutput::ok(display, page)
+    }
+
+    pub async fn list_pendings(
+        &self,
+        filter: &PeerFilter,
+        cursor: Option<&str>,
+    ) -> Output<PendingPage> {
+        let resp = self
+            .client
+            .call_method(
+                Method::ListPendings.as_str(),
+                Params::Array(vec![json!(filter), json!(cursor)]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let page: PendingPage =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let mut display = page.transport_ids.join("\n");
+        if let Some(cursor) = &page.next_cursor {
+            display.push_str(&format!("\nNext cursor: {}", cursor));
+        }
+        ClientOutput::ok(display, page)
     }
 
     pub async fn close_pending_transport(&self, transport_id: &str) -> Output<()> {
@@ -179,10 +542,529 @@ impl Client {
         ClientOutput::ok("Done.".into(), ())
     }
 
-    pub async fn send_message(&self, address: &str, text: &str) -> Output<()> {
+    pub async fn query_presence(&self, did: &str) -> Output<PresenceInfo> {
+        let resp = self
+            .client
+            .call_method(
+                Method::QueryPresence.as_str(),
+                Params::Array(vec![json!(did)]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let info: PresenceInfo =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let display = match (&info.via, info.online) {
+            (Some(via), true) => format!("{} is online via {}", info.did, via),
+            _ => format!("{} is offline", info.did),
+        };
+        ClientOutput::ok(display, info)
+    }
+
+    /// Track presence of `did` if `track`, otherwise stop tracking it.
+    pub async fn track_presence(&self, did: &str, track: bool) -> Output<Vec<(String, bool)>> {
+        let method = if track {
+            Method::TrackPresence
+        } else {
+            Method::UntrackPresence
+        };
+        let resp = self
+            .client
+            .call_method(method.as_str(), Params::Array(vec![json!(did)]))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let tracked: Vec<(String, bool)> =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let display = tracked
+            .iter()
+            .map(|(did, online)| format!("{} {}", did, if *online { "online" } else { "offline" }))
+            .collect::<Vec<_>>()
+            .join("\n");
+        ClientOutput::ok(display, tracked)
+    }
+
+    pub async fn create_group(&self, name: &str, members: &[String]) -> Output<GroupInfo> {
+        let resp = self
+            .client
+            .call_method(
+                Method::CreateGroup.as_str(),
+                Params::Array(vec![json!(name), json!(members)]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let info: GroupInfo = serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        ClientOutput::ok(
+            format!(
+                "Group {} created, {} members",
+                info.name,
+                info.members.len()
+            ),
+            info,
+        )
+    }
+
+    pub async fn fetch_group(&self, name: &str) -> Output<GroupInfo> {
+        let resp = self
+            .client
+            .call_method(
+                Method::FetchGroup.as_str(),
+                Params::Array(vec![json!(name)]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let info: GroupInfo = serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let display = format!(
+            "Group {}, admin: {}, updated: {}\nMembers:\n{}",
+            info.name,
+            info.admin,
+            info.updated_ms,
+            info.members.join("\n")
+        );
+        ClientOutput::ok(display, info)
+    }
+
+    pub async fn rotate_group_key(&self, name: &str) -> Output<GroupKeyInfo> {
+        let resp = self
+            .client
+            .call_method(
+                Method::RotateGroupKey.as_str(),
+                Params::Array(vec![json!(name)]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let info: GroupKeyInfo =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let mut display = format!(
+            "Key {} of group {} shared with {} members.",
+            info.epoch,
+            info.name,
+            info.members.len()
+        );
+        if !info.skipped.is_empty() {
+            display.push_str(&format!(
+                "\nSkipped, no manifest:\n{}",
+                info.skipped.join("\n")
+            ));
+        }
+        ClientOutput::ok(display, info)
+    }
+
+    pub async fn fetch_group_key(&self, name: &str) -> Output<GroupKeyInfo> {
+        let resp = self
+            .client
+            .call_method(
+                Method::FetchGroupKey.as_str(),
+                Params::Array(vec![json!(name)]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let info: GroupKeyInfo =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let display = format!(
+            "Key {} of group {}, admin: {}",
+            info.epoch, info.name, info.admin
+        );
+        ClientOutput::ok(display, info)
+    }
+
+    pub async fn publish_topic(&self, name: &str, data: &str) -> Output<TopicRecordInfo> {
+        let resp = self
+            .client
+            .call_method(
+                Method::PublishTopic.as_str(),
+                Params::Array(vec![json!(name), json!(data)]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let info: TopicRecordInfo =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        ClientOutput::ok(
+            format!(
+                "Record {} published to {}, digest: {}",
+                info.seq, name, info.digest
+            ),
+            info,
+        )
+    }
+
+    pub async fn fetch_topic(&self, name: &str) -> Output<TopicInfo> {
+        let resp = self
+            .client
+            .call_method(
+                Method::FetchTopic.as_str(),
+                Params::Array(vec![json!(name)]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let info: TopicInfo = serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let mut display = format!("Topic {}, {} records", info.name, info.records.len());
+        for r in info.records.iter() {
+            display.push_str(&format!(
+                "\n{} #{} {}: {}",
+                r.ts_ms, r.seq, r.publisher, r.data
+            ));
+        }
+        for f in info.forks.iter() {
+            display.push_str(&format!(
+                "\nForked: {} at #{}, kept {}, dropped {}",
+                f.publisher,
+                f.seq,
+                f.kept,
+                f.dropped.join(", ")
+            ));
+        }
+        ClientOutput::ok(display, info)
+    }
+
+    pub async fn send_to_group(
+        &self,
+        group: &str,
+        text: &str,
+        offline_ttl: Option<u64>,
+    ) -> Output<GroupSendResult> {
+        let mut params = serde_json::Map::new();
+        params.insert("group".to_owned(), json!(group));
+        params.insert("text".to_owned(), json!(text));
+        if let Some(ttl) = offline_ttl {
+            params.insert("offline_ttl".to_owned(), json!(ttl));
+        }
+        let resp = self
+            .client
+            .call_method(Method::SendToGroup.as_str(), Params::Map(params))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let r: GroupSendResult =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let mut display = format!("Sent to {} members.", r.sent.len());
+        if !r.failed.is_empty() {
+            display.push_str(&format!("\nFailed:\n{}", r.failed.join("\n")));
+        }
+        ClientOutput::ok(display, r)
+    }
+
+    pub async fn send_file(&self, path: &str) -> Output<FileInfo> {
+        let resp = self
+            .client
+            .call_method(Method::SendFile.as_str(), Params::Array(vec![json!(path)]))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let info: FileInfo = serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        ClientOutput::ok(
+            format!(
+                "Stored {}, {} bytes in {} chunks, id: {}",
+                info.name, info.size, info.chunks, info.id
+            ),
+            info,
+        )
+    }
+
+    pub async fn fetch_file(&self, id: &str, output: Option<&str>) -> Output<TransferProgress> {
+        let resp = self
+            .client
+            .call_method(
+                Method::FetchFile.as_str(),
+                Params::Array(vec![json!(id), json!(output)]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let progress: TransferProgress =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        ClientOutput::ok(
+            format!("Fetched {}, {} bytes", progress.name, progress.done_bytes),
+            progress,
+        )
+    }
+
+    /// Store `value` erasure coded on DHT, shards default to those of
+    /// [rings_core::erasure] if they're None.
+    pub async fn store_erasure(
+        &self,
+        value: &[u8],
+        data_shards: Option<usize>,
+        parity_shards: Option<usize>,
+    ) -> Output<ErasureInfo> {
+        let resp = self
+            .client
+            .call_method(
+                Method::StoreErasure.as_str(),
+                Params::Array(vec![
+                    json!(base64::encode(value)),
+                    json!(data_shards),
+                    json!(parity_shards),
+                ]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let info: ErasureInfo =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        ClientOutput::ok(
+            format!(
+                "Stored {} bytes in {} + {} shards, id: {}",
+                info.len, info.data_shards, info.parity_shards, info.id
+            ),
+            info,
+        )
+    }
+
+    pub async fn fetch_erasure(&self, id: &str) -> Output<ErasureValue> {
+        let resp = self
+            .client
+            .call_method(
+                Method::FetchErasure.as_str(),
+                Params::Array(vec![json!(id)]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let value: ErasureValue =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let data = base64::decode(&value.value).map_err(|e| anyhow::anyhow!("{}", e))?;
+        ClientOutput::ok(String::from_utf8_lossy(&data).to_string(), value)
+    }
+
+    /// Provide service `name` if `register`, otherwise stop providing it.
+    pub async fn register_service(&self, name: &str, register: bool) -> Output<Vec<String>> {
+        let method = if register {
+            Method::RegisterService
+        } else {
+            Method::UnregisterService
+        };
+        let resp = self
+            .client
+            .call_method(method.as_str(), Params::Array(vec![json!(name)]))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let names: Vec<String> =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        ClientOutput::ok(names.join("\n"), names)
+    }
+
+    pub async fn resolve_service(&self, name: &str) -> Output<Vec<ServiceProvider>> {
+        let resp = self
+            .client
+            .call_method(
+                Method::ResolveService.as_str(),
+                Params::Array(vec![json!(name)]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let providers: Vec<ServiceProvider> =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let display = providers
+            .iter()
+            .map(|p| match p.rtt_ms {
+                Some(rtt) => format!("{} {}ms", p.did, rtt),
+                None if p.connected => format!("{} connected", p.did),
+                None => p.did.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        ClientOutput::ok(display, providers)
+    }
+
+    pub async fn tag_peer(
+        &self,
+        did: &str,
+        key: &str,
+        value: Option<&str>,
+    ) -> Output<BTreeMap<String, String>> {
+        let resp = self
+            .client
+            .call_method(
+                Method::TagPeer.as_str(),
+                Params::Array(vec![json!(did), json!(key), json!(value)]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let tags: BTreeMap<String, String> =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let display = tags
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("\n");
+        ClientOutput::ok(display, tags)
+    }
+
+    pub async fn list_known_peers(&self) -> Output<Vec<KnownPeerInfo>> {
+        let resp = self
+            .client
+            .call_method(Method::ListKnownPeers.as_str(), Params::Array(vec![]))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let peers: Vec<KnownPeerInfo> =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let mut display = String::new();
+        display.push_str("Address, Key, FirstSeen, LastSeen, Mismatches\n");
+        display.push_str(
+            peers
+                .iter()
+                .map(|p| {
+                    format!(
+                        "{}, {}, {}, {}, {}",
+                        p.did, p.key, p.first_seen_ms, p.last_seen_ms, p.mismatches
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+                .as_str(),
+        );
+        ClientOutput::ok(display, peers)
+    }
+
+    pub async fn whois(&self, did: &str) -> Output<ManifestInfo> {
+        let resp = self
+            .client
+            .call_method(Method::Whois.as_str(), Params::Array(vec![json!(did)]))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let info: ManifestInfo =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let mut display = format!(
+            "{}\nversion: {}, protocol: {}-{}, network: {}\nrelay: {}",
+            info.did,
+            info.version,
+            info.min_protocol_version,
+            info.protocol_version,
+            info.network_id,
+            info.relay
+        );
+        if !info.features.is_empty() {
+            display.push_str(&format!("\nfeatures: {}", info.features.join(", ")));
+        }
+        for endpoint in info.endpoints.iter() {
+            display.push_str(&format!("\nendpoint: {}", endpoint));
+        }
+        ClientOutput::ok(display, info)
+    }
+
+    /// Set faults injected by node, or show them if `config` is None.
+    #[cfg(feature = "chaos")]
+    pub async fn inject_faults(&self, config: Option<FaultConfig>) -> Output<FaultConfig> {
+        let params = match config {
+            Some(c) => serde_json::from_value(json!(c)).map_err(|e| anyhow::anyhow!("{}", e))?,
+            None => Params::None,
+        };
+        let resp = self
+            .client
+            .call_method(Method::InjectFaults.as_str(), params)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let c: FaultConfig = serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let display = format!(
+            "drop: {}, delay: {}ms, corrupt: {}, kill: {}",
+            c.drop_ratio, c.delay_ms, c.corrupt_ratio, c.kill_ratio
+        );
+        ClientOutput::ok(display, c)
+    }
+
+    /// Walk the ring from node, visiting `max_nodes` at most.
+    pub async fn crawl(
+        &self,
+        max_nodes: Option<usize>,
+        with_manifests: bool,
+    ) -> Output<CrawlReport> {
+        let resp = self
+            .client
+            .call_method(
+                Method::Crawl.as_str(),
+                Params::Array(vec![json!(max_nodes), json!(with_manifests)]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let report: CrawlReport =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let mut display = format!(
+            "estimated size: {}, visited: {}, complete: {}",
+            report.estimated_size,
+            report.nodes.len(),
+            report.complete
+        );
+        for node in report.nodes.iter() {
+            let state = match (node.alive, node.rtt_ms) {
+                (true, Some(rtt)) => format!("alive {}ms", rtt),
+                (true, None) => "alive".to_owned(),
+                (false, _) => "dead".to_owned(),
+            };
+            display.push_str(&format!("\n{}, {}", node.did, state));
+            if let Some(m) = node.manifest.as_ref() {
+                display.push_str(&format!(", version: {}", m.version));
+            }
+        }
+        ClientOutput::ok(display, report)
+    }
+
+    /// Benchmark echoes of `did`, which should run in echo mode, defaults of node are used
+    /// for unset params.
+    pub async fn benchmark(
+        &self,
+        did: &str,
+        size: Option<usize>,
+        count: Option<usize>,
+        concurrency: Option<usize>,
+    ) -> Output<BenchmarkReport> {
+        let resp = self
+            .client
+            .call_method(
+                Method::Benchmark.as_str(),
+                Params::Array(vec![
+                    json!(did),
+                    json!(size),
+                    json!(count),
+                    json!(concurrency),
+                ]),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let r: BenchmarkReport =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let display = format!(
+            "sent: {}, echoed: {}, failed: {}, in {}ms\nthroughput: {:.1} msg/s, {:.1} KiB/s\nlatency: min {:.2}ms, avg {:.2}ms, p50 {:.2}ms, p99 {:.2}ms, max {:.2}ms",
+            r.sent,
+            r.received,
+            r.failed,
+            r.elapsed_ms,
+            r.messages_per_sec,
+            r.bytes_per_sec / 1024.0,
+            r.latency_min_ms,
+            r.latency_avg_ms,
+            r.latency_p50_ms,
+            r.latency_p99_ms,
+            r.latency_max_ms
+        );
+        ClientOutput::ok(display, r)
+    }
+
+    /// Resolve ENS `name` to address, or address to its verified name if `reverse`.
+    pub async fn ens_resolve(&self, name: &str, reverse: bool) -> Output<String> {
+        let method = if reverse {
+            Method::EnsReverse
+        } else {
+            Method::EnsResolve
+        };
+        let resp = self
+            .client
+            .call_method(method.as_str(), Params::Array(vec![json!(name)]))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let r: String = serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        ClientOutput::ok(r.clone(), r)
+    }
+
+    pub async fn send_message(
+        &self,
+        address: &str,
+        text: &str,
+        offline_ttl: Option<u64>,
+        encrypt: bool,
+    ) -> Output<()> {
         let mut params = serde_json::Map::new();
         params.insert("destination".to_owned(), json!(address));
         params.insert("text".to_owned(), json!(text));
+        if let Some(ttl) = offline_ttl {
+            params.insert("offline_ttl".to_owned(), json!(ttl));
+        }
+        if encrypt {
+            params.insert("encrypt".to_owned(), json!(true));
+        }
         self.client
             .call_method(Method::SendTo.as_str(), Params::Map(params))
             .await
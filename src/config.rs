@@ -0,0 +1,1536 @@
+//! Layered configuration of rings-node.
+//!
+//! Values are resolved in the following order, later ones override earlier ones:
+//! 1. built-in defaults,
+//! 2. TOML config file (`rings.toml` by default),
+//! 3. `RINGS_*` environment variables,
+//! 4. command line flags.
+use std::fs;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::jsonrpc_client::HttpOptions;
+use crate::prelude::rings_core::accounting::QuotaPolicy;
+use crate::prelude::rings_core::accounting::DEFAULT_RELAY_BUDGET;
+use crate::prelude::rings_core::clock::DEFAULT_MAX_CLOCK_SKEW_MS;
+use crate::prelude::rings_core::dht::routing::RoutingStrategy;
+use crate::prelude::rings_core::dht::Did;
+use crate::prelude::rings_core::ecc::SecretKey;
+use crate::prelude::rings_core::history::DEFAULT_MAX_AGE_MS;
+use crate::prelude::rings_core::history::DEFAULT_MAX_RECORDS;
+use crate::prelude::rings_core::known_peers::TofuPolicy;
+use crate::prelude::rings_core::message::codec::Codec;
+use crate::prelude::rings_core::message::codec::DEFAULT_COMPRESS_THRESHOLD;
+use crate::prelude::rings_core::message::TopologyPolicy;
+use crate::prelude::rings_core::message::DEFAULT_JOIN_PARALLELISM;
+use crate::prelude::rings_core::message::DEFAULT_NETWORK_ID;
+use crate::prelude::rings_core::migration::DEFAULT_MIGRATION_WINDOW_MS;
+use crate::prelude::rings_core::overload::DEFAULT_CPU_BUDGET;
+use crate::prelude::rings_core::overload::DEFAULT_MAX_QUEUE;
+use crate::prelude::rings_core::pex::DEFAULT_MAX_CONNECTIONS;
+use crate::prelude::rings_core::prelude::url::Url;
+use crate::prelude::rings_core::replay::DEFAULT_REPLAY_WINDOW_MS;
+use crate::prelude::rings_core::storage::cipher::load_salt;
+use crate::prelude::rings_core::storage::StorageCipher;
+use crate::prelude::rings_core::types::ice_transport::IceServer;
+use crate::prelude::rings_core::types::ice_transport::IceTransportPolicy;
+use crate::prelude::rings_core::types::ice_transport::IpFamily;
+use crate::prelude::rings_core::version::VersionPolicy;
+
+/// Config file looked up in working directory when no path is given.
+pub const DEFAULT_CONFIG_PATH: &str = "rings.toml";
+/// Prefix of environment variables which override config file values.
+pub const ENV_PREFIX: &str = "RINGS_";
+/// Environment variables read without [ENV_PREFIX] too, as they were before config file.
+const LEGACY_ENV: &[&str] = &["HTTP_ADDR", "ICE_SERVERS", "ETH_ENDPOINT", "ETH_KEY"];
+/// Value of secrets in [Config::redacted].
+pub const REDACTED: &str = "<redacted>";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Listen address of jsonrpc server, `[::]:50000` serves both IPv6 and IPv4.
+    pub http_addr: String,
+    /// Also serve jsonrpc on this unix socket.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rpc_socket: Option<String>,
+    /// HTTP callers presenting this bearer token may use admin methods, like `sendFile`.
+    /// Callers of `rpc_socket` are always admin.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin_token: Option<String>,
+    /// `sendFile` and `fetchFile` are confined to this directory, refused if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub share_dir: Option<String>,
+    /// ICE servers, separated by `;`.
+    pub ice_servers: String,
+    /// Ethereum endpoint.
+    pub eth_endpoint: String,
+    /// Network to join, nodes of different networks never connect to each other.
+    pub network_id: String,
+    /// `warn` or `refuse` peers without common protocol version.
+    pub version_policy: VersionPolicy,
+    /// `dual`, `prefer-ipv4`, `prefer-ipv6`, `ipv4` or `ipv6`, address families of local ICE
+    /// candidates sent to peers.
+    pub ip_family: IpFamily,
+    /// `all`, `relay` or `no-host`, local ICE candidates gathered and sent to peers. `relay`
+    /// needs a TURN server in `ice_servers`.
+    pub ice_transport_policy: IceTransportPolicy,
+    /// Refuse offers without a nonce issued by `handshakeNonce`, which offers over HTTP of
+    /// older nodes and manual handshakes have not. Required by default, so a captured offer
+    /// is never replayed.
+    pub require_handshake_nonce: bool,
+    /// Peers are kept in DHT this long after ICE of their transports fails, waiting for them
+    /// to connect again from another network, in ms. 0 drops them at once.
+    pub migration_window_ms: u64,
+    /// Payloads received are decompressed and verified on this many blocking threads at once,
+    /// 0 does it on receive loop. Multi-core nodes relaying much traffic want about one per
+    /// core.
+    pub verify_workers: usize,
+    /// `chord` or `latency` aware choice of next hop.
+    pub routing: RoutingStrategy,
+    /// Prefer next hops tagged `key=value`, like `region=eu`, see `tagPeer`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefer_tag: Option<String>,
+    /// Hex encoded secret key, conflicts with `keystore`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eth_key: Option<String>,
+    /// Path of a file which contains hex encoded secret key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keystore: Option<String>,
+    /// Path of persistence storage, virtual nodes stored on this node are kept there.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage_path: Option<String>,
+    /// Encrypt persisted virtual nodes and message history, with a key derived from
+    /// `storage_password` if set, otherwise from secret key of node.
+    pub encrypt_at_rest: bool,
+    /// Password of encryption at rest, keeps storage readable after node key changes. It's
+    /// salted by random bytes kept in file `<path>.salt` next to each storage.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage_password: Option<String>,
+    /// Codecs accepted for payloads, in order of preference.
+    pub codecs: Vec<Codec>,
+    /// Payloads smaller than this are not compressed, in bytes.
+    pub compress_threshold: usize,
+    /// Record latest payloads for debugging, 0 to disable capture.
+    pub capture_size: usize,
+    /// Dial peers not connected on sending messages, queuing at most this many messages for
+    /// each of them, 0 to fail such sends.
+    pub lazy_dial_queue: usize,
+    /// Fingers looked up and connected at the same time on joining a ring, 1 to wait for
+    /// stabilization to fix them one by one.
+    pub join_parallelism: usize,
+    /// `eager` to connect every peer reported on finding successors, or `structured` to connect
+    /// only successors and fingers and dial others lazily, see [TopologyPolicy].
+    pub topology_policy: TopologyPolicy,
+    /// Shed application messages when more than this many received payloads wait, 0 to disable.
+    pub shed_queue: usize,
+    /// Shed application messages when handlers take more than this percent of time, 0 to
+    /// disable.
+    pub shed_cpu_budget: u8,
+    /// Clock skew of peers tolerated on evaluating expiry of their payloads, in milliseconds,
+    /// 0 to evaluate it by local clock only.
+    pub max_clock_skew_ms: u64,
+    /// Reject payloads signed longer than this ago by clocks of their senders, or already
+    /// received, in milliseconds, 0 to disable replay protection.
+    pub replay_window_ms: u64,
+    /// Persist payloads in replay window here, so they can't be replayed after a restart,
+    /// they are kept in memory if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replay_path: Option<String>,
+    /// Keep at most this many peers connected, ring neighbours are kept even over it. Peers
+    /// learned by peer exchange are only connected while fewer are.
+    pub max_connections: usize,
+    /// Relay at most this many bytes per minute for each peer which can't connect others, 0
+    /// is unlimited.
+    pub relay_budget: usize,
+    /// Refuse relaying for a peer more than this many bytes in an hour, 0 is unlimited.
+    pub relay_quota_bytes: u64,
+    /// Refuse relaying for a peer more than this many messages in an hour, 0 is unlimited.
+    pub relay_quota_messages: u64,
+    /// Delay messages of peers using 3/4 of their relay quota by this long, in milliseconds, 0
+    /// to relay them at once.
+    pub relay_throttle_ms: u64,
+    /// Check local clock against this SNTP server at startup, like `pool.ntp.org:123`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ntp_server: Option<String>,
+    /// Persist received custom messages here, for `listMessages`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history_path: Option<String>,
+    /// Prune persisted messages older than this, in milliseconds, 0 is unlimited.
+    pub history_max_age_ms: u64,
+    /// Keep at most this many persisted messages, the oldest are pruned, 0 is unlimited.
+    pub history_max_records: usize,
+    /// Persist tags of peers here, they are kept in memory if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags_path: Option<String>,
+    /// Persist session keys of peers seen here, they are kept in memory if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub known_peers_path: Option<String>,
+    /// Persist keys of groups this node is a member of here, sealed like storage, or by node
+    /// key if `encrypt_at_rest` is off. They are kept in memory if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_keys_path: Option<String>,
+    /// `warn` or `refuse` peers whose session key differs from the one of first contact, see
+    /// [crate::prelude::rings_core::known_peers].
+    pub tofu_policy: TofuPolicy,
+    /// Interval of stabilization, in seconds.
+    pub stabilize_timeout: usize,
+    /// Adapt interval of stabilization to churn if it's set, from `stabilize_timeout` while
+    /// ring is churning up to this while it's quiet, in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stabilize_max_interval: Option<usize>,
+    /// Listen address of SOCKS5 proxy, which tunnels connections through `socks5_exit`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub socks5_addr: Option<String>,
+    /// DID of exit peer of SOCKS5 proxy, like `did:rings:0x...` or `0x...`, it should be
+    /// connected directly.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub socks5_exit: Option<String>,
+    /// `username:password` clients of SOCKS5 proxy authenticate with, required by
+    /// `socks5_addr`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub socks5_auth: Option<String>,
+    /// DIDs of peers allowed to use this node as exit of their SOCKS5 proxy, empty to serve
+    /// nobody.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exit_peers: Vec<String>,
+    /// Let exit peers reach private, loopback and link-local addresses of this node's network,
+    /// they are refused by default.
+    pub exit_allow_private: bool,
+    /// Address of local HTTP service, exposed to peers via their `/peer/:did/*path` gateway.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_service: Option<String>,
+    /// Listen address of DNS stub, which resolves `<name>.rings` to ip of `http_addr`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dns_addr: Option<String>,
+    /// Ethereum RPC endpoint of ENS resolver, ENS names are accepted as DIDs if set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ens_endpoint: Option<String>,
+    /// Public endpoints announced in manifest of node, like url of its http service.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub public_endpoints: Vec<String>,
+    /// How this node finds peers to join the ring.
+    pub bootstrap: BootstrapConfig,
+    /// Run as seed, a public entry point of network.
+    pub seed: SeedConfig,
+    /// Push metrics to statsd or OTLP.
+    pub metrics: MetricsConfig,
+    /// Http client calling JSON-RPC of other nodes, like on connecting them by url.
+    pub http_client: HttpClientConfig,
+    /// Switches of optional components.
+    pub features: FeatureConfig,
+    /// Where this config was loaded from, used by error locations.
+    #[serde(skip)]
+    source: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeatureConfig {
+    /// Run stabilization of chord ring.
+    pub stabilization: bool,
+    /// Advertise this node as relay capable, only for nodes with public address.
+    pub relay: bool,
+    /// Echo custom messages back to their senders, for `benchmark` of other nodes.
+    pub echo: bool,
+    /// Advertise this node and connect to nodes of the same network on LAN by mDNS.
+    pub mdns: bool,
+}
+
+/// How a node finds seeds to join the ring, see [crate::service::run_bootstrap].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BootstrapMode {
+    /// Peers are connected manually.
+    Manual,
+    /// Seeds listed in config.
+    Static,
+    /// Seeds in TXT records of a DNS name, refreshed periodically.
+    Dns,
+    /// Seeds connected in previous sessions.
+    Cache,
+}
+
+impl FromStr for BootstrapMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "manual" => Ok(Self::Manual),
+            "static" => Ok(Self::Static),
+            "dns" => Ok(Self::Dns),
+            "cache" => Ok(Self::Cache),
+            _ => Err(format!("unknown bootstrap mode: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for BootstrapMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Manual => "manual",
+            Self::Static => "static",
+            Self::Dns => "dns",
+            Self::Cache => "cache",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BootstrapConfig {
+    /// `manual`, `static`, `dns` or `cache`.
+    pub mode: BootstrapMode,
+    /// URLs of jsonrpc servers of seed nodes, for `static` mode.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub seeds: Vec<String>,
+    /// Name of TXT records of seed URLs, for `dns` mode, like `_rings.example.org`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dns_name: Option<String>,
+    /// Nameserver asked for `dns_name`, like `1.1.1.1:53`, nameserver of system if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dns_server: Option<String>,
+    /// Resolve `dns_name` again every this many seconds.
+    pub refresh_secs: u64,
+    /// Save connected seeds here, they are dialed in `cache` mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_path: Option<String>,
+}
+
+impl Default for BootstrapConfig {
+    fn default() -> Self {
+        Self {
+            mode: BootstrapMode::Manual,
+            seeds: vec![],
+            dns_name: None,
+            dns_server: None,
+            refresh_secs: 300,
+            cache_path: None,
+        }
+    }
+}
+
+/// Seed mode, see [crate::service::HandshakeLimiter] and [crate::service::run_idle_sweeper].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SeedConfig {
+    /// Answer offers of anyone, close idle transports, limit handshakes of each client IP and
+    /// of each peer relaying them over DHT, and never store virtual nodes of applications.
+    pub enabled: bool,
+    /// Close transports which received nothing for this many seconds.
+    pub idle_timeout_secs: u64,
+    /// Offers answered for each client IP, or for each peer relaying them over DHT, in
+    /// `handshake_window_secs`.
+    pub max_handshakes_per_ip: u32,
+    /// Window of `max_handshakes_per_ip`, in seconds.
+    pub handshake_window_secs: u64,
+}
+
+impl Default for SeedConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            idle_timeout_secs: 60,
+            max_handshakes_per_ip: 10,
+            handshake_window_secs: 60,
+        }
+    }
+}
+
+/// Http client of node, see [HttpOptions].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpClientConfig {
+    /// Idle connections are closed after this many seconds.
+    pub pool_idle_timeout_secs: u64,
+    /// Idle connections kept for each node.
+    pub pool_max_idle_per_host: usize,
+    /// Interval of TCP keep-alive probes of connections, in seconds.
+    pub tcp_keepalive_secs: u64,
+    /// Fail calls not connected in this many seconds.
+    pub connect_timeout_secs: u64,
+}
+
+impl Default for HttpClientConfig {
+    fn default() -> Self {
+        let options = HttpOptions::default();
+        Self {
+            pool_idle_timeout_secs: options.pool_idle_timeout.as_secs(),
+            pool_max_idle_per_host: options.pool_max_idle_per_host,
+            tcp_keepalive_secs: options.tcp_keepalive.as_secs(),
+            connect_timeout_secs: options.connect_timeout.as_secs(),
+        }
+    }
+}
+
+impl From<&HttpClientConfig> for HttpOptions {
+    fn from(config: &HttpClientConfig) -> Self {
+        Self {
+            pool_idle_timeout: Duration::from_secs(config.pool_idle_timeout_secs),
+            pool_max_idle_per_host: config.pool_max_idle_per_host,
+            tcp_keepalive: Duration::from_secs(config.tcp_keepalive_secs),
+            connect_timeout: Duration::from_secs(config.connect_timeout_secs),
+        }
+    }
+}
+
+/// Protocol of pushing metrics, see [crate::service::run_metrics_push].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricsProtocol {
+    /// Gauges over UDP.
+    Statsd,
+    /// OTLP/HTTP with JSON encoding.
+    Otlp,
+}
+
+impl FromStr for MetricsProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "statsd" => Ok(Self::Statsd),
+            "otlp" => Ok(Self::Otlp),
+            _ => Err(format!("unknown metrics protocol: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for MetricsProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Statsd => "statsd",
+            Self::Otlp => "otlp",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// `statsd` or `otlp`.
+    pub protocol: MetricsProtocol,
+    /// `host:port` of statsd, or url of OTLP/HTTP metrics, like
+    /// `http://127.0.0.1:4318/v1/metrics`. Nothing is pushed if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    /// Push metrics every this many seconds.
+    pub interval_secs: u64,
+    /// Prefix of metric names, like `rings` of `rings.transports.count`.
+    pub prefix: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            protocol: MetricsProtocol::Statsd,
+            endpoint: None,
+            interval_secs: 60,
+            prefix: "rings".to_owned(),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            http_addr: "127.0.0.1:50000".to_owned(),
+            rpc_socket: None,
+            admin_token: None,
+            share_dir: None,
+            ice_servers: "stun://stun.l.google.com:19302".to_owned(),
+            eth_endpoint: "http://127.0.0.1:8545".to_owned(),
+            network_id: DEFAULT_NETWORK_ID.to_owned(),
+            version_policy: VersionPolicy::default(),
+            ice_transport_policy: IceTransportPolicy::default(),
+            ip_family: IpFamily::default(),
+            require_handshake_nonce: true,
+            migration_window_ms: DEFAULT_MIGRATION_WINDOW_MS,
+            verify_workers: 0,
+            routing: RoutingStrategy::default(),
+            prefer_tag: None,
+            eth_key: None,
+            keystore: None,
+            storage_path: None,
+            encrypt_at_rest: false,
+            storage_password: None,
+            codecs: Codec::supported(),
+            compress_threshold: DEFAULT_COMPRESS_THRESHOLD,
+            capture_size: 0,
+            lazy_dial_queue: 0,
+            join_parallelism: DEFAULT_JOIN_PARALLELISM,
+            topology_policy: TopologyPolicy::default(),
+            shed_queue: DEFAULT_MAX_QUEUE,
+            shed_cpu_budget: DEFAULT_CPU_BUDGET,
+            max_clock_skew_ms: DEFAULT_MAX_CLOCK_SKEW_MS,
+            replay_window_ms: DEFAULT_REPLAY_WINDOW_MS,
+            replay_path: None,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            relay_budget: DEFAULT_RELAY_BUDGET,
+            relay_quota_bytes: 0,
+            relay_quota_messages: 0,
+            relay_throttle_ms: 0,
+            ntp_server: None,
+            history_path: None,
+            history_max_age_ms: DEFAULT_MAX_AGE_MS,
+            history_max_records: DEFAULT_MAX_RECORDS,
+            tags_path: None,
+            known_peers_path: None,
+            group_keys_path: None,
+            tofu_policy: TofuPolicy::default(),
+            stabilize_timeout: 20,
+            stabilize_max_interval: None,
+            socks5_addr: None,
+            socks5_exit: None,
+            socks5_auth: None,
+            exit_peers: vec![],
+            exit_allow_private: false,
+            http_service: None,
+            dns_addr: None,
+            ens_endpoint: None,
+            public_endpoints: vec![],
+            bootstrap: BootstrapConfig::default(),
+            seed: SeedConfig::default(),
+            metrics: MetricsConfig::default(),
+            http_client: HttpClientConfig::default(),
+            features: FeatureConfig::default(),
+            source: None,
+        }
+    }
+}
+
+impl Default for FeatureConfig {
+    fn default() -> Self {
+        Self {
+            stabilization: true,
+            relay: false,
+            echo: false,
+            mdns: false,
+        }
+    }
+}
+
+impl FromStr for Config {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        toml::from_str(s).map_err(|e| {
+            let location = match e.line_col() {
+                Some((line, col)) => format!("line {}, column {}", line + 1, col + 1),
+                None => "<unknown>".to_owned(),
+            };
+            Error::InvalidConfig(location, e.to_string())
+        })
+    }
+}
+
+impl Config {
+    /// Load config from `path`, then apply environment overrides.
+    /// If `path` is None, [DEFAULT_CONFIG_PATH] is used when it exists.
+    pub fn load(path: Option<&str>) -> Result<Self> {
+        let path = match path {
+            Some(p) => Some(p),
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => Some(DEFAULT_CONFIG_PATH),
+            None => None,
+        };
+        let mut config = match path {
+            Some(p) => Self::from_file(p)?,
+            None => Self::default(),
+        };
+        config.apply_env()?;
+        Ok(config)
+    }
+
+    /// Read config from a TOML file, without environment overrides.
+    pub fn from_file(path: &str) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).map_err(|e| Error::ConfigFile(format!("{}: {}", path, e)))?;
+        let mut config = Self::from_str(&content).map_err(|e| match e {
+            Error::InvalidConfig(loc, msg) => {
+                Error::InvalidConfig(format!("{}, {}", path, loc), msg)
+            }
+            e => e,
+        })?;
+        config.source = Some(path.to_owned());
+        Ok(config)
+    }
+
+    /// Override fields with `RINGS_*` environment variables, eg: `RINGS_HTTP_ADDR`. Names of
+    /// [LEGACY_ENV] are read without prefix too, if prefixed ones are not set.
+    pub fn apply_env(&mut self) -> Result<()> {
+        self.apply_vars(|k| {
+            std::env::var(format!("{}{}", ENV_PREFIX, k))
+                .ok()
+                .or_else(|| {
+                    LEGACY_ENV
+                        .contains(&k)
+                        .then(|| std::env::var(k).ok())
+                        .flatten()
+                })
+        })
+    }
+
+    fn apply_vars<F>(&mut self, get: F) -> Result<()>
+    where F: Fn(&str) -> Option<String> {
+        let parse_err =
+            |k: &str, e: String| Error::InvalidConfig(format!("env {}{}", ENV_PREFIX, k), e);
+        if let Some(v) = get("HTTP_ADDR") {
+            self.http_addr = v;
+        }
+        if let Some(v) = get("RPC_SOCKET") {
+            self.rpc_socket = Some(v);
+        }
+        if let Some(v) = get("ADMIN_TOKEN") {
+            self.admin_token = Some(v);
+        }
+        if let Some(v) = get("SHARE_DIR") {
+            self.share_dir = Some(v);
+        }
+        if let Some(v) = get("ICE_SERVERS") {
+            self.ice_servers = v;
+        }
+        if let Some(v) = get("ETH_ENDPOINT") {
+            self.eth_endpoint = v;
+        }
+        if let Some(v) = get("NETWORK_ID") {
+            self.network_id = v;
+        }
+        if let Some(v) = get("VERSION_POLICY") {
+            self.version_policy = v
+                .parse()
+                .map_err(|e: String| parse_err("VERSION_POLICY", e))?;
+        }
+        if let Some(v) = get("ICE_TRANSPORT_POLICY") {
+            self.ice_transport_policy = v
+                .parse()
+                .map_err(|e: String| parse_err("ICE_TRANSPORT_POLICY", e))?;
+        }
+        if let Some(v) = get("IP_FAMILY") {
+            self.ip_family = v.parse().map_err(|e: String| parse_err("IP_FAMILY", e))?;
+        }
+        if let Some(v) = get("MIGRATION_WINDOW_MS") {
+            self.migration_window_ms = v.parse().map_err(|e: std::num::ParseIntError| {
+                parse_err("MIGRATION_WINDOW_MS", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("VERIFY_WORKERS") {
+            self.verify_workers = v
+                .parse()
+                .map_err(|e: std::num::ParseIntError| parse_err("VERIFY_WORKERS", e.to_string()))?;
+        }
+        if let Some(v) = get("REQUIRE_HANDSHAKE_NONCE") {
+            self.require_handshake_nonce = v.parse().map_err(|e: std::str::ParseBoolError| {
+                parse_err("REQUIRE_HANDSHAKE_NONCE", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("ROUTING") {
+            self.routing = v.parse().map_err(|e: String| parse_err("ROUTING", e))?;
+        }
+        if let Some(v) = get("PREFER_TAG") {
+            self.prefer_tag = Some(v);
+        }
+        // key of node given by config file is never replaced silently
+        if let Some(v) = get("ETH_KEY") {
+            if self.keystore.is_some() || self.eth_key.as_ref().map_or(false, |k| *k != v) {
+                return Err(parse_err(
+                    "ETH_KEY",
+                    "conflicts with key of config file".to_owned(),
+                ));
+            }
+            self.eth_key = Some(v);
+        }
+        if let Some(v) = get("KEYSTORE") {
+            if self.eth_key.is_some() || self.keystore.as_ref().map_or(false, |k| *k != v) {
+                return Err(parse_err(
+                    "KEYSTORE",
+                    "conflicts with key of config file".to_owned(),
+                ));
+            }
+            self.keystore = Some(v);
+        }
+        if let Some(v) = get("STORAGE_PATH") {
+            self.storage_path = Some(v);
+        }
+        if let Some(v) = get("ENCRYPT_AT_REST") {
+            self.encrypt_at_rest = v.parse().map_err(|e: std::str::ParseBoolError| {
+                parse_err("ENCRYPT_AT_REST", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("STORAGE_PASSWORD") {
+            self.storage_password = Some(v);
+        }
+        if let Some(v) = get("STABILIZE_TIMEOUT") {
+            self.stabilize_timeout = v.parse().map_err(|e: std::num::ParseIntError| {
+                parse_err("STABILIZE_TIMEOUT", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("STABILIZE_MAX_INTERVAL") {
+            self.stabilize_max_interval =
+                Some(v.parse().map_err(|e: std::num::ParseIntError| {
+                    parse_err("STABILIZE_MAX_INTERVAL", e.to_string())
+                })?);
+        }
+        if let Some(v) = get("CODECS") {
+            self.codecs = v
+                .split(',')
+                .map(|s| s.trim().parse())
+                .collect::<std::result::Result<_, String>>()
+                .map_err(|e| parse_err("CODECS", e))?;
+        }
+        if let Some(v) = get("COMPRESS_THRESHOLD") {
+            self.compress_threshold = v.parse().map_err(|e: std::num::ParseIntError| {
+                parse_err("COMPRESS_THRESHOLD", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("CAPTURE_SIZE") {
+            self.capture_size = v
+                .parse()
+                .map_err(|e: std::num::ParseIntError| parse_err("CAPTURE_SIZE", e.to_string()))?;
+        }
+        if let Some(v) = get("LAZY_DIAL_QUEUE") {
+            self.lazy_dial_queue = v.parse().map_err(|e: std::num::ParseIntError| {
+                parse_err("LAZY_DIAL_QUEUE", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("JOIN_PARALLELISM") {
+            self.join_parallelism = v.parse().map_err(|e: std::num::ParseIntError| {
+                parse_err("JOIN_PARALLELISM", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("TOPOLOGY_POLICY") {
+            self.topology_policy = v
+                .parse()
+                .map_err(|e: String| parse_err("TOPOLOGY_POLICY", e))?;
+        }
+        if let Some(v) = get("SHED_QUEUE") {
+            self.shed_queue = v
+                .parse()
+                .map_err(|e: std::num::ParseIntError| parse_err("SHED_QUEUE", e.to_string()))?;
+        }
+        if let Some(v) = get("SHED_CPU_BUDGET") {
+            self.shed_cpu_budget = v.parse().map_err(|e: std::num::ParseIntError| {
+                parse_err("SHED_CPU_BUDGET", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("MAX_CLOCK_SKEW_MS") {
+            self.max_clock_skew_ms = v.parse().map_err(|e: std::num::ParseIntError| {
+                parse_err("MAX_CLOCK_SKEW_MS", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("REPLAY_WINDOW_MS") {
+            self.replay_window_ms = v.parse().map_err(|e: std::num::ParseIntError| {
+                parse_err("REPLAY_WINDOW_MS", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("MAX_CONNECTIONS") {
+            self.max_connections = v.parse().map_err(|e: std::num::ParseIntError| {
+                parse_err("MAX_CONNECTIONS", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("RELAY_BUDGET") {
+            self.relay_budget = v
+                .parse()
+                .map_err(|e: std::num::ParseIntError| parse_err("RELAY_BUDGET", e.to_string()))?;
+        }
+        if let Some(v) = get("RELAY_QUOTA_BYTES") {
+            self.relay_quota_bytes = v.parse().map_err(|e: std::num::ParseIntError| {
+                parse_err("RELAY_QUOTA_BYTES", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("RELAY_QUOTA_MESSAGES") {
+            self.relay_quota_messages = v.parse().map_err(|e: std::num::ParseIntError| {
+                parse_err("RELAY_QUOTA_MESSAGES", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("RELAY_THROTTLE_MS") {
+            self.relay_throttle_ms = v.parse().map_err(|e: std::num::ParseIntError| {
+                parse_err("RELAY_THROTTLE_MS", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("NTP_SERVER") {
+            self.ntp_server = Some(v);
+        }
+        if let Some(v) = get("HISTORY_PATH") {
+            self.history_path = Some(v);
+        }
+        if let Some(v) = get("HISTORY_MAX_AGE_MS") {
+            self.history_max_age_ms = v.parse().map_err(|e: std::num::ParseIntError| {
+                parse_err("HISTORY_MAX_AGE_MS", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("HISTORY_MAX_RECORDS") {
+            self.history_max_records = v.parse().map_err(|e: std::num::ParseIntError| {
+                parse_err("HISTORY_MAX_RECORDS", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("TAGS_PATH") {
+            self.tags_path = Some(v);
+        }
+        if let Some(v) = get("KNOWN_PEERS_PATH") {
+            self.known_peers_path = Some(v);
+        }
+        if let Some(v) = get("GROUP_KEYS_PATH") {
+            self.group_keys_path = Some(v);
+        }
+        if let Some(v) = get("REPLAY_PATH") {
+            self.replay_path = Some(v);
+        }
+        if let Some(v) = get("TOFU_POLICY") {
+            self.tofu_policy = v.parse().map_err(|e: String| parse_err("TOFU_POLICY", e))?;
+        }
+        if let Some(v) = get("SOCKS5_ADDR") {
+            self.socks5_addr = Some(v);
+        }
+        if let Some(v) = get("SOCKS5_EXIT") {
+            self.socks5_exit = Some(v);
+        }
+        if let Some(v) = get("SOCKS5_AUTH") {
+            self.socks5_auth = Some(v);
+        }
+        if let Some(v) = get("EXIT_PEERS") {
+            self.exit_peers = v.split(',').map(|s| s.trim().to_owned()).collect();
+        }
+        if let Some(v) = get("EXIT_ALLOW_PRIVATE") {
+            self.exit_allow_private = v.parse().map_err(|e: std::str::ParseBoolError| {
+                parse_err("EXIT_ALLOW_PRIVATE", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("HTTP_SERVICE") {
+            self.http_service = Some(v);
+        }
+        if let Some(v) = get("DNS_ADDR") {
+            self.dns_addr = Some(v);
+        }
+        if let Some(v) = get("ENS_ENDPOINT") {
+            self.ens_endpoint = Some(v);
+        }
+        if let Some(v) = get("PUBLIC_ENDPOINTS") {
+            self.public_endpoints = v.split(',').map(|s| s.trim().to_owned()).collect();
+        }
+        if let Some(v) = get("BOOTSTRAP_MODE") {
+            self.bootstrap.mode = v
+                .parse()
+                .map_err(|e: String| parse_err("BOOTSTRAP_MODE", e))?;
+        }
+        if let Some(v) = get("BOOTSTRAP_SEEDS") {
+            self.bootstrap.seeds = v.split(',').map(|s| s.trim().to_owned()).collect();
+        }
+        if let Some(v) = get("BOOTSTRAP_DNS_NAME") {
+            self.bootstrap.dns_name = Some(v);
+        }
+        if let Some(v) = get("BOOTSTRAP_DNS_SERVER") {
+            self.bootstrap.dns_server = Some(v);
+        }
+        if let Some(v) = get("BOOTSTRAP_REFRESH_SECS") {
+            self.bootstrap.refresh_secs = v.parse().map_err(|e: std::num::ParseIntError| {
+                parse_err("BOOTSTRAP_REFRESH_SECS", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("BOOTSTRAP_CACHE_PATH") {
+            self.bootstrap.cache_path = Some(v);
+        }
+        if let Some(v) = get("SEED_ENABLED") {
+            self.seed.enabled = v
+                .parse()
+                .map_err(|e: std::str::ParseBoolError| parse_err("SEED_ENABLED", e.to_string()))?;
+        }
+        if let Some(v) = get("SEED_IDLE_TIMEOUT_SECS") {
+            self.seed.idle_timeout_secs = v.parse().map_err(|e: std::num::ParseIntError| {
+                parse_err("SEED_IDLE_TIMEOUT_SECS", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("SEED_MAX_HANDSHAKES_PER_IP") {
+            self.seed.max_handshakes_per_ip = v.parse().map_err(|e: std::num::ParseIntError| {
+                parse_err("SEED_MAX_HANDSHAKES_PER_IP", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("SEED_HANDSHAKE_WINDOW_SECS") {
+            self.seed.handshake_window_secs = v.parse().map_err(|e: std::num::ParseIntError| {
+                parse_err("SEED_HANDSHAKE_WINDOW_SECS", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("METRICS_PROTOCOL") {
+            self.metrics.protocol = v
+                .parse()
+                .map_err(|e: String| parse_err("METRICS_PROTOCOL", e))?;
+        }
+        if let Some(v) = get("METRICS_ENDPOINT") {
+            self.metrics.endpoint = Some(v);
+        }
+        if let Some(v) = get("METRICS_INTERVAL_SECS") {
+            self.metrics.interval_secs = v.parse().map_err(|e: std::num::ParseIntError| {
+                parse_err("METRICS_INTERVAL_SECS", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("METRICS_PREFIX") {
+            self.metrics.prefix = v;
+        }
+        if let Some(v) = get("HTTP_CLIENT_POOL_IDLE_TIMEOUT_SECS") {
+            self.http_client.pool_idle_timeout_secs =
+                v.parse().map_err(|e: std::num::ParseIntError| {
+                    parse_err("HTTP_CLIENT_POOL_IDLE_TIMEOUT_SECS", e.to_string())
+                })?;
+        }
+        if let Some(v) = get("HTTP_CLIENT_POOL_MAX_IDLE_PER_HOST") {
+            self.http_client.pool_max_idle_per_host =
+                v.parse().map_err(|e: std::num::ParseIntError| {
+                    parse_err("HTTP_CLIENT_POOL_MAX_IDLE_PER_HOST", e.to_string())
+                })?;
+        }
+        if let Some(v) = get("HTTP_CLIENT_TCP_KEEPALIVE_SECS") {
+            self.http_client.tcp_keepalive_secs =
+                v.parse().map_err(|e: std::num::ParseIntError| {
+                    parse_err("HTTP_CLIENT_TCP_KEEPALIVE_SECS", e.to_string())
+                })?;
+        }
+        if let Some(v) = get("HTTP_CLIENT_CONNECT_TIMEOUT_SECS") {
+            self.http_client.connect_timeout_secs =
+                v.parse().map_err(|e: std::num::ParseIntError| {
+                    parse_err("HTTP_CLIENT_CONNECT_TIMEOUT_SECS", e.to_string())
+                })?;
+        }
+        if let Some(v) = get("FEATURES_STABILIZATION") {
+            self.features.stabilization = v.parse().map_err(|e: std::str::ParseBoolError| {
+                parse_err("FEATURES_STABILIZATION", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("FEATURES_RELAY") {
+            self.features.relay = v.parse().map_err(|e: std::str::ParseBoolError| {
+                parse_err("FEATURES_RELAY", e.to_string())
+            })?;
+        }
+        if let Some(v) = get("FEATURES_ECHO") {
+            self.features.echo = v
+                .parse()
+                .map_err(|e: std::str::ParseBoolError| parse_err("FEATURES_ECHO", e.to_string()))?;
+        }
+        if let Some(v) = get("FEATURES_MDNS") {
+            self.features.mdns = v
+                .parse()
+                .map_err(|e: std::str::ParseBoolError| parse_err("FEATURES_MDNS", e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn location(&self, field: &str) -> String {
+        match &self.source {
+            Some(s) => format!("{}, field `{}`", s, field),
+            None => format!("field `{}`", field),
+        }
+    }
+
+    /// Check every field, and report the first invalid one with its location.
+    pub fn validate(&self) -> Result<()> {
+        SocketAddr::from_str(&self.http_addr)
+            .map_err(|e| Error::InvalidConfig(self.location("http_addr"), e.to_string()))?;
+        for s in self.ice_servers.split(';') {
+            IceServer::from_str(s).map_err(|e| {
+                Error::InvalidConfig(self.location("ice_servers"), format!("{}: {}", s, e))
+            })?;
+        }
+        Url::parse(&self.eth_endpoint)
+            .map_err(|e| Error::InvalidConfig(self.location("eth_endpoint"), e.to_string()))?;
+        if self.network_id.is_empty() {
+            return Err(Error::InvalidConfig(
+                self.location("network_id"),
+                "should not be empty".to_owned(),
+            ));
+        }
+        if let Some(tag) = &self.prefer_tag {
+            if self.prefer_tag().is_none() {
+                return Err(Error::InvalidConfig(
+                    self.location("prefer_tag"),
+                    format!("should be `key=value`: {}", tag),
+                ));
+            }
+        }
+        if let Some(c) = self.codecs.iter().find(|c| !c.is_supported()) {
+            return Err(Error::InvalidConfig(
+                self.location("codecs"),
+                format!("{} is not supported by this build", c),
+            ));
+        }
+        if self.eth_key.is_some() && self.keystore.is_some() {
+            return Err(Error::InvalidConfig(
+                self.location("keystore"),
+                "conflicts with `eth_key`".to_owned(),
+            ));
+        }
+        if self.storage_password.is_some() && !self.encrypt_at_rest {
+            return Err(Error::InvalidConfig(
+                self.location("storage_password"),
+                "unused, set `encrypt_at_rest` to encrypt storage".to_owned(),
+            ));
+        }
+        if self.stabilize_timeout == 0 {
+            return Err(Error::InvalidConfig(
+                self.location("stabilize_timeout"),
+                "should be greater than 0".to_owned(),
+            ));
+        }
+        if matches!(self.stabilize_max_interval, Some(max) if max < self.stabilize_timeout) {
+            return Err(Error::InvalidConfig(
+                self.location("stabilize_max_interval"),
+                "should be at least `stabilize_timeout`".to_owned(),
+            ));
+        }
+        if self.shed_cpu_budget > 100 {
+            return Err(Error::InvalidConfig(
+                self.location("shed_cpu_budget"),
+                "should be a percentage, at most 100".to_owned(),
+            ));
+        }
+        if let Some(addr) = &self.socks5_addr {
+            SocketAddr::from_str(addr)
+                .map_err(|e| Error::InvalidConfig(self.location("socks5_addr"), e.to_string()))?;
+        }
+        match (&self.socks5_addr, &self.socks5_exit) {
+            (Some(_), None) => {
+                return Err(Error::InvalidConfig(
+                    self.location("socks5_exit"),
+                    "missing, required by `socks5_addr`".to_owned(),
+                ))
+            }
+            (_, Some(_)) => {
+                self.socks5_exit()?;
+            }
+            _ => {}
+        }
+        match (&self.socks5_addr, &self.socks5_auth) {
+            (Some(_), None) => {
+                return Err(Error::InvalidConfig(
+                    self.location("socks5_auth"),
+                    "missing, required by `socks5_addr`".to_owned(),
+                ))
+            }
+            (_, Some(_)) => {
+                self.socks5_auth()?;
+            }
+            _ => {}
+        }
+        if let Some(addr) = &self.http_service {
+            SocketAddr::from_str(addr)
+                .map_err(|e| Error::InvalidConfig(self.location("http_service"), e.to_string()))?;
+        }
+        if let Some(addr) = &self.dns_addr {
+            SocketAddr::from_str(addr)
+                .map_err(|e| Error::InvalidConfig(self.location("dns_addr"), e.to_string()))?;
+            self.gateway_ips()?;
+        }
+        if let Some(endpoint) = &self.ens_endpoint {
+            Url::parse(endpoint)
+                .map_err(|e| Error::InvalidConfig(self.location("ens_endpoint"), e.to_string()))?;
+        }
+        self.exit_peers()?;
+        self.validate_bootstrap()?;
+        self.validate_seed()?;
+        self.validate_metrics()
+    }
+
+    fn validate_metrics(&self) -> Result<()> {
+        let m = &self.metrics;
+        let endpoint = match &m.endpoint {
+            Some(e) => e,
+            None => return Ok(()),
+        };
+        let invalid = match m.protocol {
+            MetricsProtocol::Statsd => match endpoint.rsplit_once(':') {
+                Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => None,
+                _ => Some(format!("should be `host:port` of statsd: {}", endpoint)),
+            },
+            MetricsProtocol::Otlp => Url::parse(endpoint).err().map(|e| e.to_string()),
+        };
+        if let Some(e) = invalid {
+            return Err(Error::InvalidConfig(self.location("metrics.endpoint"), e));
+        }
+        if m.interval_secs == 0 {
+            return Err(Error::InvalidConfig(
+                self.location("metrics.interval_secs"),
+                "should be greater than 0".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn validate_seed(&self) -> Result<()> {
+        let s = &self.seed;
+        if !s.enabled {
+            return Ok(());
+        }
+        let zero = [
+            ("idle_timeout_secs", s.idle_timeout_secs == 0),
+            ("max_handshakes_per_ip", s.max_handshakes_per_ip == 0),
+            ("handshake_window_secs", s.handshake_window_secs == 0),
+        ];
+        if let Some((field, _)) = zero.iter().find(|(_, z)| *z) {
+            return Err(Error::InvalidConfig(
+                self.location(&format!("seed.{}", field)),
+                "should be greater than 0".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn validate_bootstrap(&self) -> Result<()> {
+        let b = &self.bootstrap;
+        for s in b.seeds.iter() {
+            Url::parse(s).map_err(|e| {
+                Error::InvalidConfig(self.location("bootstrap.seeds"), format!("{}: {}", s, e))
+            })?;
+        }
+        if let Some(server) = &b.dns_server {
+            SocketAddr::from_str(server).map_err(|e| {
+                Error::InvalidConfig(self.location("bootstrap.dns_server"), e.to_string())
+            })?;
+        }
+        let missing = match b.mode {
+            BootstrapMode::Static if b.seeds.is_empty() => Some("seeds"),
+            BootstrapMode::Dns if b.dns_name.is_none() => Some("dns_name"),
+            BootstrapMode::Cache if b.cache_path.is_none() => Some("cache_path"),
+            _ => None,
+        };
+        if let Some(field) = missing {
+            return Err(Error::InvalidConfig(
+                self.location(&format!("bootstrap.{}", field)),
+                format!("missing, required by `{}` mode", b.mode),
+            ));
+        }
+        if b.mode == BootstrapMode::Dns && b.refresh_secs == 0 {
+            return Err(Error::InvalidConfig(
+                self.location("bootstrap.refresh_secs"),
+                "should be greater than 0".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Key and value of `prefer_tag`.
+    /// Shortest and longest intervals of stabilization, in seconds, see
+    /// `stabilize_max_interval`.
+    pub fn stabilize_interval(&self) -> (usize, usize) {
+        let max = self
+            .stabilize_max_interval
+            .unwrap_or(self.stabilize_timeout);
+        (self.stabilize_timeout, max)
+    }
+
+    pub fn prefer_tag(&self) -> Option<(&str, &str)> {
+        self.prefer_tag
+            .as_ref()?
+            .split_once('=')
+            .filter(|(k, _)| !k.is_empty())
+    }
+
+    /// Quota of peers relayed for, None if it's unlimited.
+    pub fn relay_policy(&self) -> Option<QuotaPolicy> {
+        let policy = QuotaPolicy {
+            max_bytes: self.relay_quota_bytes,
+            max_messages: self.relay_quota_messages,
+            throttle_ms: self.relay_throttle_ms,
+        };
+        (policy.max_bytes > 0 || policy.max_messages > 0).then(|| policy)
+    }
+
+    /// Exit peer of SOCKS5 proxy, if proxy is enabled. It's a DID in any form of [Did].
+    pub fn socks5_exit(&self) -> Result<Option<Did>> {
+        match (&self.socks5_addr, &self.socks5_exit) {
+            (Some(_), Some(exit)) => Did::from_str(exit)
+                .map(Some)
+                .map_err(|e| Error::InvalidConfig(self.location("socks5_exit"), e.to_string())),
+            _ => Ok(None),
+        }
+    }
+
+    /// Username and password of SOCKS5 proxy, split from `socks5_auth`. Both are at most 255
+    /// bytes, username is not empty.
+    pub fn socks5_auth(&self) -> Result<Option<(String, String)>> {
+        let auth = match &self.socks5_auth {
+            Some(auth) => auth,
+            None => return Ok(None),
+        };
+        match auth.split_once(':') {
+            Some((user, password))
+                if !user.is_empty() && user.len() <= 255 && password.len() <= 255 =>
+            {
+                Ok(Some((user.to_owned(), password.to_owned())))
+            }
+            _ => Err(Error::InvalidConfig(
+                self.location("socks5_auth"),
+                "should be `username:password`, each at most 255 bytes".to_owned(),
+            )),
+        }
+    }
+
+    /// Peers allowed to use this node as SOCKS5 exit, DIDs in any form of [Did].
+    pub fn exit_peers(&self) -> Result<Vec<Did>> {
+        self.exit_peers
+            .iter()
+            .map(|p| {
+                Did::from_str(p).map_err(|e| {
+                    Error::InvalidConfig(self.location("exit_peers"), format!("{}: {}", p, e))
+                })
+            })
+            .collect()
+    }
+
+    /// Optional components announced in manifest of node.
+    pub fn manifest_features(&self) -> Vec<String> {
+        [
+            ("stabilization", self.features.stabilization),
+            ("relay", self.features.relay),
+            ("echo", self.features.echo),
+            ("mdns", self.features.mdns),
+            ("seed", self.seed.enabled),
+            ("socks5-exit", !self.exit_peers.is_empty()),
+            ("http-service", self.http_service.is_some()),
+            ("dns", self.dns_addr.is_some()),
+            ("ens", self.ens_endpoint.is_some()),
+        ]
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| name.to_string())
+        .collect()
+    }
+
+    /// Addresses answered by DNS stub in `A` or `AAAA` records, ip of `http_addr`. Nobody
+    /// connects to an unspecified address, loopback is answered instead, of both families for
+    /// `[::]`, which accepts IPv4 clients too.
+    pub fn gateway_ips(&self) -> Result<Vec<IpAddr>> {
+        let ip = SocketAddr::from_str(&self.http_addr)
+            .map(|addr| addr.ip())
+            .map_err(|e| Error::InvalidConfig(self.location("http_addr"), e.to_string()))?;
+        Ok(match ip {
+            IpAddr::V4(v4) if v4.is_unspecified() => vec![Ipv4Addr::LOCALHOST.into()],
+            IpAddr::V6(v6) if v6.is_unspecified() => {
+                vec![Ipv6Addr::LOCALHOST.into(), Ipv4Addr::LOCALHOST.into()]
+            }
+            ip => vec![ip],
+        })
+    }
+
+    /// Get secret key from `eth_key` or `keystore`.
+    pub fn secret_key(&self) -> Result<SecretKey> {
+        let (field, key) = match (&self.eth_key, &self.keystore) {
+            (Some(k), _) => ("eth_key", k.to_owned()),
+            (None, Some(path)) => (
+                "keystore",
+                fs::read_to_string(path)
+                    .map_err(|e| Error::ConfigFile(format!("{}: {}", path, e)))?,
+            ),
+            (None, None) => {
+                return Err(Error::InvalidConfig(
+                    self.location("eth_key"),
+                    "missing, set `eth_key` or `keystore`".to_owned(),
+                ))
+            }
+        };
+        SecretKey::from_str(key.trim())
+            .map_err(|e| Error::InvalidConfig(self.location(field), e.to_string()))
+    }
+
+    /// Cipher of state persisted at `path` by node with secret key `key`, None if
+    /// `encrypt_at_rest` is off. A password is salted by salt of `path`, see [load_salt].
+    pub fn storage_cipher(&self, key: &SecretKey, path: &str) -> Result<Option<StorageCipher>> {
+        if !self.encrypt_at_rest {
+            return Ok(None);
+        }
+        Ok(Some(match &self.storage_password {
+            Some(password) => {
+                let salt = load_salt(path).map_err(Error::NodeBuild)?;
+                StorageCipher::from_password(password, &salt)
+            }
+            None => StorageCipher::from_secret_key(key),
+        }))
+    }
+
+    /// Copy of config with secrets replaced by [REDACTED], for `rings-node config show`.
+    pub fn redacted(&self) -> Self {
+        let redact = |v: &Option<String>| v.as_ref().map(|_| REDACTED.to_owned());
+        Self {
+            admin_token: redact(&self.admin_token),
+            eth_key: redact(&self.eth_key),
+            storage_password: redact(&self.storage_password),
+            socks5_auth: redact(&self.socks5_auth),
+            ..self.clone()
+        }
+    }
+
+    /// Dump config as TOML.
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string_pretty(self).map_err(|e| Error::ConfigFile(e.to_string()))
+    }
+
+    /// Write a config file with default values, used by `rings-node config init`.
+    pub fn init_file(path: &str, force: bool) -> Result<()> {
+        if !force && Path::new(path).exists() {
+            return Err(Error::ConfigFile(format!("{} already exists", path)));
+        }
+        let content = Self::default().to_toml()?;
+        fs::write(path, content).map_err(|e| Error::ConfigFile(format!("{}: {}", path, e)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_default_config_roundtrip() {
+        let config = Config::default();
+        let content = config.to_toml().unwrap();
+        assert_eq!(Config::from_str(&content).unwrap(), config);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_parse_error_location() {
+        let err = Config::from_str("http_addr = \"127.0.0.1:50000\"\nstabilize_timeout = \"x\"\n")
+            .unwrap_err();
+        match err {
+            Error::InvalidConfig(loc, _) => assert!(loc.starts_with("line 2"), "{}", loc),
+            e => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_validate_field_location() {
+        let config = Config {
+            http_addr: "not an addr".to_owned(),
+            ..Default::default()
+        };
+        match config.validate().unwrap_err() {
+            Error::InvalidConfig(loc, _) => assert_eq!(loc, "field `http_addr`"),
+            e => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_env_override() {
+        let mut config = Config::default();
+        config
+            .apply_vars(|k| match k {
+                "HTTP_ADDR" => Some("0.0.0.0:1234".to_owned()),
+                "FEATURES_STABILIZATION" => Some("false".to_owned()),
+                "TOFU_POLICY" => Some("refuse".to_owned()),
+                "VERIFY_WORKERS" => Some("4".to_owned()),
+                "TOPOLOGY_POLICY" => Some("structured".to_owned()),
+                "HTTP_CLIENT_CONNECT_TIMEOUT_SECS" => Some("3".to_owned()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(config.http_addr, "0.0.0.0:1234");
+        assert!(!config.features.stabilization);
+        assert_eq!(config.tofu_policy, TofuPolicy::Refuse);
+        assert_eq!(config.verify_workers, 4);
+        assert_eq!(config.topology_policy, TopologyPolicy::Structured);
+        assert_eq!(
+            HttpOptions::from(&config.http_client).connect_timeout,
+            Duration::from_secs(3)
+        );
+        assert!(config
+            .apply_vars(|k| (k == "STABILIZE_TIMEOUT").then(|| "abc".to_owned()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_key_conflict_and_redact() {
+        let key = SecretKey::random().to_string();
+        let mut config = Config {
+            eth_key: Some(key.clone()),
+            admin_token: Some("s3cr3t".to_owned()),
+            ..Default::default()
+        };
+        config
+            .apply_vars(|k| (k == "ETH_KEY").then(|| key.clone()))
+            .unwrap();
+        let other = SecretKey::random().to_string();
+        assert!(config
+            .apply_vars(|k| (k == "ETH_KEY").then(|| other.clone()))
+            .is_err());
+        assert!(config
+            .apply_vars(|k| (k == "KEYSTORE").then(|| "keystore.json".to_owned()))
+            .is_err());
+        assert_eq!(config.eth_key, Some(key.clone()));
+
+        let shown = config.redacted().to_toml().unwrap();
+        assert!(!shown.contains(&key));
+        assert!(!shown.contains("s3cr3t"));
+        assert_eq!(config.redacted().eth_key.as_deref(), Some(REDACTED));
+    }
+
+    #[test]
+    fn test_relay_config() {
+        let mut config = Config::default();
+        assert_eq!(config.relay_policy(), None);
+        config
+            .apply_vars(|k| match k {
+                "RELAY_QUOTA_BYTES" => Some("1000".to_owned()),
+                "RELAY_THROTTLE_MS" => Some("50".to_owned()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(
+            config.relay_policy(),
+            Some(QuotaPolicy {
+                max_bytes: 1000,
+                max_messages: 0,
+                throttle_ms: 50,
+            })
+        );
+    }
+
+    #[test]
+    fn test_socks5_config() {
+        let exit = SecretKey::random().address();
+        let mut config = Config {
+            socks5_addr: Some("127.0.0.1:1080".to_owned()),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        config.socks5_exit = Some(format!("{:?}", exit));
+        config
+            .apply_vars(|k| (k == "EXIT_PEERS").then(|| format!("{:?}, {}", exit, Did::from(exit))))
+            .unwrap();
+        // proxy is never open to anyone
+        assert!(config.validate().is_err());
+        config.socks5_auth = Some("nopassword".to_owned());
+        assert!(config.validate().is_err());
+        config.socks5_auth = Some("user:pass:word".to_owned());
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.socks5_auth().unwrap(),
+            Some(("user".to_owned(), "pass:word".to_owned()))
+        );
+        assert_eq!(config.socks5_exit().unwrap(), Some(exit.into()));
+        assert_eq!(config.exit_peers().unwrap(), vec![exit.into(), exit.into()]);
+        assert!(!config.exit_allow_private);
+    }
+
+    #[test]
+    fn test_dns_config() {
+        let mut config = Config {
+            dns_addr: Some("127.0.0.1:5353".to_owned()),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.gateway_ips().unwrap(), vec![IpAddr::from_str(
+            "127.0.0.1"
+        )
+        .unwrap()]);
+        assert_eq!(config.manifest_features(), vec![
+            "stabilization".to_owned(),
+            "dns".to_owned()
+        ]);
+        config.http_addr = "[::1]:50000".to_owned();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.gateway_ips().unwrap(), vec![
+            IpAddr::from_str("::1").unwrap()
+        ]);
+        config.http_addr = "[::]:50000".to_owned();
+        assert_eq!(config.gateway_ips().unwrap(), vec![
+            IpAddr::from_str("::1").unwrap(),
+            IpAddr::from_str("127.0.0.1").unwrap()
+        ]);
+        config.http_addr = "0.0.0.0:50000".to_owned();
+        assert_eq!(config.gateway_ips().unwrap(), vec![IpAddr::from_str(
+            "127.0.0.1"
+        )
+        .unwrap()]);
+    }
+
+    #[test]
+    fn test_bootstrap_config() {
+        let mut config = Config::from_str("[bootstrap]\nmode = \"dns\"\n").unwrap();
+        assert_eq!(config.bootstrap.mode, BootstrapMode::Dns);
+        match config.validate().unwrap_err() {
+            Error::InvalidConfig(loc, _) => assert_eq!(loc, "field `bootstrap.dns_name`"),
+            e => panic!("unexpected error {:?}", e),
+        }
+        config
+            .apply_vars(|k| match k {
+                "BOOTSTRAP_MODE" => Some("static".to_owned()),
+                "BOOTSTRAP_SEEDS" => Some("http://a:50000, http://b:50000".to_owned()),
+                _ => None,
+            })
+            .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.bootstrap.seeds.len(), 2);
+        assert!(config
+            .apply_vars(|k| (k == "BOOTSTRAP_MODE").then(|| "mdns".to_owned()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_stabilize_interval() {
+        let mut config = Config::from_str("stabilize_timeout = 10\n").unwrap();
+        assert_eq!(config.stabilize_interval(), (10, 10));
+        config
+            .apply_vars(|k| (k == "STABILIZE_MAX_INTERVAL").then(|| "5".to_owned()))
+            .unwrap();
+        match config.validate().unwrap_err() {
+            Error::InvalidConfig(loc, _) => assert_eq!(loc, "field `stabilize_max_interval`"),
+            e => panic!("unexpected error {:?}", e),
+        }
+        config.stabilize_max_interval = Some(60);
+        assert!(config.validate().is_ok());
+        assert_eq!(config.stabilize_interval(), (10, 60));
+    }
+
+    #[test]
+    fn test_seed_config() {
+        let mut config = Config::from_str("[seed]\nenabled = true\n").unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.seed.idle_timeout_secs, 60);
+        config
+            .apply_vars(|k| (k == "SEED_IDLE_TIMEOUT_SECS").then(|| "0".to_owned()))
+            .unwrap();
+        match config.validate().unwrap_err() {
+            Error::InvalidConfig(loc, _) => assert_eq!(loc, "field `seed.idle_timeout_secs`"),
+            e => panic!("unexpected error {:?}", e),
+        }
+        config.seed.idle_timeout_secs = 30;
+        assert!(config.require_handshake_nonce);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_metrics_config() {
+        let mut config =
+            Config::from_str("[metrics]\nprotocol = \"otlp\"\nendpoint = \"127.0.0.1:4318\"\n")
+                .unwrap();
+        match config.validate().unwrap_err() {
+            Error::InvalidConfig(loc, _) => assert_eq!(loc, "field `metrics.endpoint`"),
+            e => panic!("unexpected error {:?}", e),
+        }
+        config
+            .apply_vars(|k| (k == "METRICS_PROTOCOL").then(|| "statsd".to_owned()))
+            .unwrap();
+        assert!(config.validate().is_ok());
+        assert!(config
+            .apply_vars(|k| (k == "METRICS_PROTOCOL").then(|| "graphite".to_owned()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_secret_key() {
+        let key = SecretKey::random();
+        let config = Config {
+            eth_key: Some(key.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(config.secret_key().unwrap(), key);
+        assert!(Config::default().secret_key().is_err());
+    }
+}
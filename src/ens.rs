@@ -0,0 +1,90 @@
+//! Resolve ENS names to addresses, and back, via an ethereum RPC endpoint.
+//!
+//! DIDs are web3 addresses, so wherever JSON-RPC takes a DID, an ENS name like `alice.eth` is
+//! accepted too when the node is configured with `ens_endpoint`, see
+//! [crate::processor::Processor::resolve_did].
+use crate::error::Error;
+use crate::error::Result;
+use crate::ethereum::link_web3;
+use crate::ethereum::Transport;
+use crate::prelude::rings_core::prelude::web3::contract::ens::Ens;
+use crate::prelude::rings_core::prelude::Address;
+
+/// Name is an ENS name rather than a hex address, DID or IP address. It has at least two
+/// labels, none of them empty, and its top level label is not numeric.
+pub fn is_ens_name(name: &str) -> bool {
+    let labels: Vec<&str> = name.split('.').collect();
+    !name.starts_with("0x")
+        && labels.len() >= 2
+        && labels
+            .iter()
+            .all(|l| !l.is_empty() && !l.contains(|c: char| c.is_whitespace() || ":/@".contains(c)))
+        && !labels
+            .last()
+            .map_or(true, |tld| tld.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// ENS resolver on an ethereum RPC endpoint.
+pub struct EnsResolver {
+    ens: Ens<Transport>,
+}
+
+impl EnsResolver {
+    /// Connect to `endpoint`, http or websocket.
+    pub async fn new(endpoint: &str) -> Result<Self> {
+        let web3 = link_web3(endpoint)
+            .await
+            .map_err(|e| Error::EnsError(e.to_string()))?;
+        Ok(Self { ens: web3.ens() })
+    }
+
+    /// Address of ENS `name`.
+    pub async fn resolve(&self, name: &str) -> Result<Address> {
+        let address = self
+            .ens
+            .eth_address(name)
+            .await
+            .map_err(|e| Error::EnsError(e.to_string()))?;
+        if address.is_zero() {
+            return Err(Error::EnsError(format!("{} is not resolved", name)));
+        }
+        Ok(address)
+    }
+
+    /// Primary ENS name of `address`, only if the name resolves back to `address`,
+    /// as anyone can set a reverse record to any name.
+    pub async fn reverse(&self, address: Address) -> Result<String> {
+        let name = self
+            .ens
+            .canonical_name(address)
+            .await
+            .map_err(|e| Error::EnsError(e.to_string()))?;
+        if name.is_empty() || self.resolve(&name).await? != address {
+            return Err(Error::EnsError(format!(
+                "{:?} has no verified name",
+                address
+            )));
+        }
+        Ok(name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_ens_name() {
+        assert!(is_ens_name("alice.eth"));
+        assert!(is_ens_name("pay.alice.eth"));
+        assert!(!is_ens_name("alice"));
+        assert!(!is_ens_name("alice."));
+        assert!(!is_ens_name("0x11E807fcc88dD319270493fB2e822e388Fe36ab0"));
+        assert!(!is_ens_name("1.2.3.4"));
+        assert!(!is_ens_name("::1"));
+        assert!(!is_ens_name("alice..eth"));
+        assert!(!is_ens_name(".eth"));
+        assert!(!is_ens_name("did:rings:alice.eth"));
+        assert!(!is_ens_name("http://alice.eth"));
+    }
+}
@@ -44,6 +44,78 @@ pub enum Error {
     SendMessage(rings_core::err::Error),
     #[error("Build message body error: {0}")]
     MessagePayload(rings_core::err::Error),
+    #[error("Config file error: {0}")]
+    ConfigFile(String),
+    #[error("Invalid config at {0}: {1}")]
+    InvalidConfig(String, String),
+    #[error("Drain error: {0}")]
+    DrainError(rings_core::err::Error),
+    #[error("Packet capture is disabled")]
+    CaptureDisabled,
+    #[error("Message history is disabled")]
+    HistoryDisabled,
+    #[error("Message history error: {0}")]
+    HistoryError(rings_core::err::Error),
+    #[error("Presence error: {0}")]
+    PresenceError(rings_core::err::Error),
+    #[error("Group not found: {0}")]
+    GroupNotFound(String),
+    #[error("Group error: {0}")]
+    GroupError(rings_core::err::Error),
+    #[error("Not a member of group: {0}")]
+    NotGroupMember(String),
+    #[error("File transfer error: {0}")]
+    FileTransfer(String),
+    #[error("File not found: {0}")]
+    FileNotFound(String),
+    #[error("Service error: {0}")]
+    ServiceError(rings_core::err::Error),
+    #[error("ENS resolver is not configured.")]
+    EnsDisabled,
+    #[error("ENS error: {0}")]
+    EnsError(String),
+    #[error("Manifest of {0} not found")]
+    ManifestNotFound(String),
+    #[error("Manifest error: {0}")]
+    ManifestError(rings_core::err::Error),
+    #[error("Stabilization error: {0}")]
+    StabilizationError(rings_core::err::Error),
+    #[error("Invalid stabilization interval, min: {0}, max: {1}")]
+    InvalidStabilizationInterval(usize, usize),
+    #[error("Peer tag error: {0}")]
+    PeerTagError(rings_core::err::Error),
+    #[error("Fault injection error: {0}")]
+    FaultError(rings_core::err::Error),
+    #[error("Not admin of group: {0}")]
+    NotGroupAdmin(String),
+    #[error("Local data not found: {0}")]
+    LocalDataNotFound(String),
+    #[error("Build node error: {0}")]
+    NodeBuild(rings_core::err::Error),
+    #[error("Node conflicts with another one: {0}")]
+    NodeConflict(String),
+    #[error("{0}")]
+    InvalidDid(rings_core::err::Error),
+    #[error("Connect timeout, {0}")]
+    ConnectTimeout(rings_core::err::Error),
+    #[error("Handshake nonce is missing, unknown, expired or used")]
+    InvalidHandshakeNonce,
+    #[error("Known peers error: {0}")]
+    KnownPeersError(rings_core::err::Error),
+    #[error("Unauthorized, {0} requires admin token")]
+    Unauthorized(String),
+    #[error("Rotate identity error, {0}")]
+    RotateIdentity(rings_core::err::Error),
+    #[error("Too many handshake nonces are waiting for offers")]
+    HandshakeNoncesExhausted,
+    #[error("Group name is taken by another admin: {0}")]
+    GroupNameTaken(String),
+    #[error("Topic error: {0}")]
+    TopicError(rings_core::err::Error),
+    #[error("Erasure coding error: {0}")]
+    ErasureError(rings_core::err::Error),
+    #[error("Erasure coded value not found: {0}")]
+    ErasureNotFound(String),
 }
 
 impl Error {
@@ -69,6 +141,42 @@ impl Error {
             Error::ConnectError(_) => 17,
             Error::SendMessage(_) => 18,
             Error::MessagePayload(_) => 19,
+            Error::ConfigFile(_) => 20,
+            Error::InvalidConfig(_, _) => 21,
+            Error::DrainError(_) => 22,
+            Error::CaptureDisabled => 23,
+            Error::HistoryDisabled => 24,
+            Error::HistoryError(_) => 25,
+            Error::PresenceError(_) => 26,
+            Error::GroupNotFound(_) => 27,
+            Error::GroupError(_) => 28,
+            Error::NotGroupMember(_) => 29,
+            Error::FileTransfer(_) => 30,
+            Error::FileNotFound(_) => 31,
+            Error::ServiceError(_) => 32,
+            Error::EnsDisabled => 33,
+            Error::EnsError(_) => 34,
+            Error::ManifestNotFound(_) => 35,
+            Error::ManifestError(_) => 36,
+            Error::StabilizationError(_) => 37,
+            Error::InvalidStabilizationInterval(_, _) => 38,
+            Error::PeerTagError(_) => 39,
+            Error::FaultError(_) => 40,
+            Error::NotGroupAdmin(_) => 41,
+            Error::LocalDataNotFound(_) => 42,
+            Error::NodeBuild(_) => 43,
+            Error::NodeConflict(_) => 44,
+            Error::InvalidDid(_) => 45,
+            Error::ConnectTimeout(_) => 46,
+            Error::InvalidHandshakeNonce => 47,
+            Error::KnownPeersError(_) => 48,
+            Error::Unauthorized(_) => 49,
+            Error::RotateIdentity(_) => 50,
+            Error::HandshakeNoncesExhausted => 51,
+            Error::GroupNameTaken(_) => 52,
+            Error::TopicError(_) => 53,
+            Error::ErasureError(_) => 54,
+            Error::ErasureNotFound(_) => 55,
         };
         -32000 - code
     }
@@ -15,16 +15,101 @@ pub enum Method {
     CreateOffer,
     /// Answer offer for manually handshake
     AnswerOffer,
+    /// Issue a nonce to be signed in offer of handshake over HTTP
+    HandshakeNonce,
     /// Accept Answer for manually handshake
     AcceptAnswer,
     /// Send custom message to peer
     SendTo,
+    /// Fetch receipt of a message sent with receipt
+    DeliveryReceipt,
     /// Disconnect a peer
     Disconnect,
     /// List all pending connections
     ListPendings,
     /// Close pending connect
     ClosePendingTransport,
+    /// Report version and network of node
+    NodeInfo,
+    /// Leave the ring gracefully and stop the service
+    Drain,
+    /// Publish rotation of identity of node to a new DID
+    RotateIdentity,
+    /// List payloads recorded by packet capture
+    CapturedPayloads,
+    /// Report messages and bytes sent to and received from each peer
+    PeerTraffic,
+    /// Report messages and bytes relayed for each peer in current period
+    RelayUsage,
+    /// Export DHT and peers as a snapshot
+    ExportState,
+    /// Load DHT of a snapshot
+    ImportState,
+    /// List received custom messages
+    ListMessages,
+    /// Query presence of a DID
+    QueryPresence,
+    /// Track presence of a DID
+    TrackPresence,
+    /// Stop tracking presence of a DID
+    UntrackPresence,
+    /// Create a group, or update its members
+    CreateGroup,
+    /// Send message to members of a group
+    SendToGroup,
+    /// Fetch members of a group
+    FetchGroup,
+    /// Establish a new key of a group, shared with its members
+    RotateGroupKey,
+    /// Fetch key of a group shared with this node
+    FetchGroupKey,
+    /// Append a record to a topic
+    PublishTopic,
+    /// Fetch history of a topic, with forks resolved
+    FetchTopic,
+    /// Store a file on DHT
+    SendFile,
+    /// Fetch a file from DHT
+    FetchFile,
+    /// Store a value erasure coded on DHT
+    StoreErasure,
+    /// Fetch a value erasure coded on DHT
+    FetchErasure,
+    /// Provide a service by name
+    RegisterService,
+    /// Stop providing a service
+    UnregisterService,
+    /// Find providers of a service
+    ResolveService,
+    /// Resolve an ENS name to address
+    EnsResolve,
+    /// Look up verified ENS name of an address
+    EnsReverse,
+    /// Fetch verified manifest of a node
+    Whois,
+    /// Show state of stabilization
+    StabilizationStatus,
+    /// Pause, resume or trigger stabilization, or change its interval
+    ControlStabilization,
+    /// Verify successors, fingers and stored data of DHT at once
+    RepairDht,
+    /// List virtual nodes stored by this node, page by page
+    ListLocalData,
+    /// Delete a virtual node stored by this node
+    DeleteLocalData,
+    /// Set or remove a local tag of a peer
+    TagPeer,
+    /// List peers seen, with public keys of their first contact
+    ListKnownPeers,
+    /// Walk the ring, collecting neighbours and liveness of nodes
+    Crawl,
+    /// Measure throughput and latency of echoes of a peer in echo mode
+    Benchmark,
+    /// Set faults injected to outbound payloads, needs feature `chaos`
+    #[cfg(feature = "chaos")]
+    InjectFaults,
+    /// Describe all methods as an OpenRPC document
+    Discover,
 }
 
 impl Method {
@@ -36,11 +121,154 @@ impl Method {
             Method::ListPeers => "listPeers",
             Method::CreateOffer => "createOffer",
             Method::AnswerOffer => "answerOffer",
+            Method::HandshakeNonce => "handshakeNonce",
             Method::SendTo => "sendTo",
+            Method::DeliveryReceipt => "deliveryReceipt",
             Method::Disconnect => "disconnect",
             Method::AcceptAnswer => "acceptAnswer",
             Method::ListPendings => "listPendings",
             Method::ClosePendingTransport => "closePendingTransport",
+            Method::NodeInfo => "nodeInfo",
+            Method::Drain => "drain",
+            Method::RotateIdentity => "rotateIdentity",
+            Method::CapturedPayloads => "capturedPayloads",
+            Method::PeerTraffic => "peerTraffic",
+            Method::RelayUsage => "relayUsage",
+            Method::ExportState => "exportState",
+            Method::ImportState => "importState",
+            Method::ListMessages => "listMessages",
+            Method::QueryPresence => "queryPresence",
+            Method::TrackPresence => "trackPresence",
+            Method::UntrackPresence => "untrackPresence",
+            Method::CreateGroup => "createGroup",
+            Method::SendToGroup => "sendToGroup",
+            Method::FetchGroup => "fetchGroup",
+            Method::RotateGroupKey => "rotateGroupKey",
+            Method::FetchGroupKey => "fetchGroupKey",
+            Method::PublishTopic => "publishTopic",
+            Method::FetchTopic => "fetchTopic",
+            Method::SendFile => "sendFile",
+            Method::FetchFile => "fetchFile",
+            Method::StoreErasure => "storeErasure",
+            Method::FetchErasure => "fetchErasure",
+            Method::RegisterService => "registerService",
+            Method::UnregisterService => "unregisterService",
+            Method::ResolveService => "resolveService",
+            Method::EnsResolve => "ensResolve",
+            Method::EnsReverse => "ensReverse",
+            Method::Whois => "whois",
+            Method::StabilizationStatus => "stabilizationStatus",
+            Method::ControlStabilization => "controlStabilization",
+            Method::RepairDht => "repairDht",
+            Method::ListLocalData => "listLocalData",
+            Method::DeleteLocalData => "deleteLocalData",
+            Method::TagPeer => "tagPeer",
+            Method::ListKnownPeers => "listKnownPeers",
+            Method::Crawl => "crawl",
+            Method::Benchmark => "benchmark",
+            #[cfg(feature = "chaos")]
+            Method::InjectFaults => "injectFaults",
+            Method::Discover => "rpc.discover",
+        }
+    }
+
+    /// Calling it again with same params has no more effect, so it's safe to retry.
+    pub fn is_idempotent(&self) -> bool {
+        matches!(
+            self,
+            Method::ListPeers
+                | Method::DeliveryReceipt
+                | Method::Disconnect
+                | Method::ListPendings
+                | Method::NodeInfo
+                | Method::CapturedPayloads
+                | Method::PeerTraffic
+                | Method::RelayUsage
+                | Method::ExportState
+                | Method::ListMessages
+                | Method::QueryPresence
+                | Method::TrackPresence
+                | Method::UntrackPresence
+                | Method::FetchGroup
+                | Method::FetchGroupKey
+                | Method::FetchTopic
+                | Method::FetchErasure
+                | Method::ResolveService
+                | Method::EnsResolve
+                | Method::EnsReverse
+                | Method::Whois
+                | Method::StabilizationStatus
+                | Method::ListLocalData
+                | Method::DeleteLocalData
+                | Method::TagPeer
+                | Method::ListKnownPeers
+                | Method::Crawl
+                | Method::Discover
+        )
+    }
+
+    /// Return summary of method, in OpenRPC document of node
+    pub fn summary(&self) -> &str {
+        match self {
+            Method::ConnectPeerViaHttp => "Connect peer with remote jsonrpc server url",
+            Method::ConnectWithAddress => "Connect peer with remote peer's web3 address",
+            Method::ListPeers => "List all connected peers",
+            Method::CreateOffer => "Create offer for manually handshake",
+            Method::AnswerOffer => "Answer offer for manually handshake",
+            Method::HandshakeNonce => "Issue a nonce to be signed in offer of handshake over HTTP",
+            Method::AcceptAnswer => "Accept Answer for manually handshake",
+            Method::SendTo => "Send custom message to peer",
+            Method::DeliveryReceipt => "Fetch receipt of a message sent with receipt",
+            Method::Disconnect => "Disconnect a peer",
+            Method::ListPendings => "List all pending connections",
+            Method::ClosePendingTransport => "Close pending connect",
+            Method::NodeInfo => "Report version and network of node",
+            Method::Drain => "Leave the ring gracefully and stop the service",
+            Method::RotateIdentity => "Publish rotation of identity of node to a new DID",
+            Method::CapturedPayloads => "List payloads recorded by packet capture",
+            Method::PeerTraffic => "Report messages and bytes sent to and received from each peer",
+            Method::RelayUsage => {
+                "Report messages and bytes relayed for each peer in current period"
+            }
+            Method::ExportState => "Export DHT and peers as a snapshot",
+            Method::ImportState => "Load DHT of a snapshot",
+            Method::ListMessages => "List received custom messages",
+            Method::QueryPresence => "Query presence of a DID",
+            Method::TrackPresence => "Track presence of a DID",
+            Method::UntrackPresence => "Stop tracking presence of a DID",
+            Method::CreateGroup => "Create a group, or update its members",
+            Method::SendToGroup => "Send message to members of a group",
+            Method::FetchGroup => "Fetch members of a group",
+            Method::RotateGroupKey => "Establish a new key of a group, shared with its members",
+            Method::FetchGroupKey => "Fetch key of a group shared with this node",
+            Method::PublishTopic => "Append a record to a topic",
+            Method::FetchTopic => "Fetch history of a topic, with forks resolved",
+            Method::SendFile => "Store a file on DHT",
+            Method::FetchFile => "Fetch a file from DHT",
+            Method::StoreErasure => "Store a value erasure coded on DHT",
+            Method::FetchErasure => "Fetch a value erasure coded on DHT",
+            Method::RegisterService => "Provide a service by name",
+            Method::UnregisterService => "Stop providing a service",
+            Method::ResolveService => "Find providers of a service",
+            Method::EnsResolve => "Resolve an ENS name to address",
+            Method::EnsReverse => "Look up verified ENS name of an address",
+            Method::Whois => "Fetch verified manifest of a node",
+            Method::StabilizationStatus => "Show state of stabilization",
+            Method::ControlStabilization => {
+                "Pause, resume or trigger stabilization, or change its interval"
+            }
+            Method::RepairDht => "Verify successors, fingers and stored data of DHT at once",
+            Method::ListLocalData => "List virtual nodes stored by this node, page by page",
+            Method::DeleteLocalData => "Delete a virtual node stored by this node",
+            Method::TagPeer => "Set or remove a local tag of a peer",
+            Method::ListKnownPeers => "List peers seen, with session keys of their first contact",
+            Method::Crawl => "Walk the ring, collecting neighbours and liveness of nodes",
+            Method::Benchmark => "Measure throughput and latency of echoes of a peer in echo mode",
+            #[cfg(feature = "chaos")]
+            Method::InjectFaults => {
+                "Set faults injected to outbound payloads, needs feature `chaos`"
+            }
+            Method::Discover => "Describe all methods as an OpenRPC document",
         }
     }
 }
@@ -61,11 +289,54 @@ impl TryFrom<&str> for Method {
             "listPeers" => Self::ListPeers,
             "createOffer" => Self::CreateOffer,
             "answerOffer" => Self::AnswerOffer,
+            "handshakeNonce" => Self::HandshakeNonce,
             "sendTo" => Self::SendTo,
+            "deliveryReceipt" => Self::DeliveryReceipt,
             "disconnect" => Self::Disconnect,
             "acceptAnswer" => Self::AcceptAnswer,
             "listPendings" => Self::ListPendings,
             "closePendingTransport" => Self::ClosePendingTransport,
+            "nodeInfo" => Self::NodeInfo,
+            "drain" => Self::Drain,
+            "rotateIdentity" => Self::RotateIdentity,
+            "capturedPayloads" => Self::CapturedPayloads,
+            "peerTraffic" => Self::PeerTraffic,
+            "relayUsage" => Self::RelayUsage,
+            "exportState" => Self::ExportState,
+            "importState" => Self::ImportState,
+            "listMessages" => Self::ListMessages,
+            "queryPresence" => Self::QueryPresence,
+            "trackPresence" => Self::TrackPresence,
+            "untrackPresence" => Self::UntrackPresence,
+            "createGroup" => Self::CreateGroup,
+            "sendToGroup" => Self::SendToGroup,
+            "fetchGroup" => Self::FetchGroup,
+            "rotateGroupKey" => Self::RotateGroupKey,
+            "fetchGroupKey" => Self::FetchGroupKey,
+            "publishTopic" => Self::PublishTopic,
+            "fetchTopic" => Self::FetchTopic,
+            "sendFile" => Self::SendFile,
+            "fetchFile" => Self::FetchFile,
+            "storeErasure" => Self::StoreErasure,
+            "fetchErasure" => Self::FetchErasure,
+            "registerService" => Self::RegisterService,
+            "unregisterService" => Self::UnregisterService,
+            "resolveService" => Self::ResolveService,
+            "ensResolve" => Self::EnsResolve,
+            "ensReverse" => Self::EnsReverse,
+            "whois" => Self::Whois,
+            "stabilizationStatus" => Self::StabilizationStatus,
+            "controlStabilization" => Self::ControlStabilization,
+            "repairDht" => Self::RepairDht,
+            "listLocalData" => Self::ListLocalData,
+            "deleteLocalData" => Self::DeleteLocalData,
+            "tagPeer" => Self::TagPeer,
+            "listKnownPeers" => Self::ListKnownPeers,
+            "crawl" => Self::Crawl,
+            "benchmark" => Self::Benchmark,
+            #[cfg(feature = "chaos")]
+            "injectFaults" => Self::InjectFaults,
+            "rpc.discover" => Self::Discover,
             _ => return Err(Error::InvalidMethod),
         })
     }
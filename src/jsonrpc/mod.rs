@@ -1,6 +1,8 @@
 ///! jsonrpc-server of rings-node
 ///! [JSON-RPC]: https://www.jsonrpc.org/specification
 pub mod method;
+#[cfg(feature = "client")]
+pub mod openrpc;
 pub mod response;
 #[cfg(feature = "client")]
 mod server;
@@ -0,0 +1,1032 @@
+#![warn(missing_docs)]
+//! OpenRPC document of node, returned by `rpc.discover`, see <https://spec.open-rpc.org>.
+//!
+//! Methods are described by [typed requests](crate::jsonrpc_client::typed) and their
+//! responses, params by [RequestParams] and results by [JsonSchema] of response types, so
+//! client generators and API explorers don't need to read source of node.
+use std::collections::BTreeMap;
+
+use serde_json::json;
+use serde_json::Value;
+
+use super::response::BenchmarkReport;
+use super::response::CrawlReport;
+use super::response::CrawledNode;
+use super::response::EchoInfo;
+use super::response::ErasureInfo;
+use super::response::ErasureValue;
+use super::response::FileInfo;
+use super::response::GroupInfo;
+use super::response::GroupKeyInfo;
+use super::response::GroupSendResult;
+use super::response::KnownPeerInfo;
+use super::response::LocalData;
+use super::response::LocalDataPage;
+use super::response::ManifestInfo;
+use super::response::NodeInfo;
+use super::response::Peer;
+use super::response::PeerPage;
+use super::response::PendingPage;
+use super::response::PresenceInfo;
+use super::response::SendToResult;
+use super::response::ServiceProvider;
+use super::response::StateSnapshot;
+use super::response::TopicForkInfo;
+use super::response::TopicInfo;
+use super::response::TopicRecordInfo;
+use super::response::TransportAndIce;
+use crate::jsonrpc_client::typed::AcceptAnswerRequest;
+use crate::jsonrpc_client::typed::AnswerOfferRequest;
+use crate::jsonrpc_client::typed::BenchmarkRequest;
+use crate::jsonrpc_client::typed::CapturedPayloadsRequest;
+use crate::jsonrpc_client::typed::ClosePendingTransportRequest;
+use crate::jsonrpc_client::typed::ConnectPeerViaHttpRequest;
+use crate::jsonrpc_client::typed::ConnectWithAddressRequest;
+use crate::jsonrpc_client::typed::ControlStabilizationRequest;
+use crate::jsonrpc_client::typed::CrawlRequest;
+use crate::jsonrpc_client::typed::CreateGroupRequest;
+use crate::jsonrpc_client::typed::CreateOfferRequest;
+use crate::jsonrpc_client::typed::DeleteLocalDataRequest;
+use crate::jsonrpc_client::typed::DeliveryReceiptRequest;
+use crate::jsonrpc_client::typed::DisconnectRequest;
+use crate::jsonrpc_client::typed::DiscoverRequest;
+use crate::jsonrpc_client::typed::DrainRequest;
+use crate::jsonrpc_client::typed::EmptyResponse;
+use crate::jsonrpc_client::typed::EnsResolveRequest;
+use crate::jsonrpc_client::typed::EnsReverseRequest;
+use crate::jsonrpc_client::typed::ExportStateRequest;
+use crate::jsonrpc_client::typed::FetchErasureRequest;
+use crate::jsonrpc_client::typed::FetchFileRequest;
+use crate::jsonrpc_client::typed::FetchGroupKeyRequest;
+use crate::jsonrpc_client::typed::FetchGroupRequest;
+use crate::jsonrpc_client::typed::FetchTopicRequest;
+use crate::jsonrpc_client::typed::HandshakeNonceRequest;
+use crate::jsonrpc_client::typed::ImportStateRequest;
+#[cfg(feature = "chaos")]
+use crate::jsonrpc_client::typed::InjectFaultsRequest;
+use crate::jsonrpc_client::typed::ListKnownPeersRequest;
+use crate::jsonrpc_client::typed::ListLocalDataRequest;
+use crate::jsonrpc_client::typed::ListMessagesRequest;
+use crate::jsonrpc_client::typed::ListPeersPageRequest;
+use crate::jsonrpc_client::typed::ListPendingsPageRequest;
+use crate::jsonrpc_client::typed::NodeInfoRequest;
+use crate::jsonrpc_client::typed::PeerTrafficRequest;
+use crate::jsonrpc_client::typed::PublishTopicRequest;
+use crate::jsonrpc_client::typed::QueryPresenceRequest;
+use crate::jsonrpc_client::typed::RegisterServiceRequest;
+use crate::jsonrpc_client::typed::RelayUsageRequest;
+use crate::jsonrpc_client::typed::RepairDhtRequest;
+use crate::jsonrpc_client::typed::ResolveServiceRequest;
+use crate::jsonrpc_client::typed::RotateGroupKeyRequest;
+use crate::jsonrpc_client::typed::RotateIdentityRequest;
+use crate::jsonrpc_client::typed::SendFileRequest;
+use crate::jsonrpc_client::typed::SendToGroupRequest;
+use crate::jsonrpc_client::typed::SendToRequest;
+use crate::jsonrpc_client::typed::StabilizationStatusRequest;
+use crate::jsonrpc_client::typed::StoreErasureRequest;
+use crate::jsonrpc_client::typed::TagPeerRequest;
+use crate::jsonrpc_client::typed::TrackPresenceRequest;
+use crate::jsonrpc_client::typed::UnregisterServiceRequest;
+use crate::jsonrpc_client::typed::UntrackPresenceRequest;
+use crate::jsonrpc_client::typed::WhoisRequest;
+use crate::jsonrpc_client::RpcRequest;
+use crate::prelude::rings_core::accounting::RelayUsage;
+use crate::prelude::rings_core::capture::CapturedPayload;
+use crate::prelude::rings_core::capture::Direction;
+#[cfg(feature = "chaos")]
+use crate::prelude::rings_core::chaos::FaultConfig;
+use crate::prelude::rings_core::dht::vnode::VNodeType;
+use crate::prelude::rings_core::dht::Did;
+use crate::prelude::rings_core::dht::PeerRingSnapshot;
+use crate::prelude::rings_core::dht::RepairReport;
+use crate::prelude::rings_core::dht::StabilizationStatus;
+use crate::prelude::rings_core::ecc::HashStr;
+use crate::prelude::rings_core::file::TransferProgress;
+use crate::prelude::rings_core::history::HistoryFilter;
+use crate::prelude::rings_core::history::HistoryPage;
+use crate::prelude::rings_core::history::MessageRecord;
+use crate::prelude::rings_core::message::codec::Codec;
+use crate::prelude::rings_core::message::codec::CodecStats;
+use crate::prelude::rings_core::message::DeliveryReceipt;
+use crate::prelude::rings_core::message::Encoded;
+use crate::prelude::rings_core::message::HopLatency;
+use crate::prelude::rings_core::prelude::Address;
+use crate::prelude::rings_core::record::RecordSig;
+use crate::prelude::rings_core::replay::ReplayStats;
+use crate::prelude::rings_core::rotation::Rotation;
+use crate::prelude::rings_core::rotation::RotationRecord;
+use crate::prelude::rings_core::session::Session;
+use crate::prelude::rings_core::traffic::PeerTrafficStats;
+use crate::prelude::rings_core::traffic::TrafficCounter;
+use crate::prelude::rings_core::types::ice_transport::TransportStats;
+use crate::prelude::rings_core::types::ice_transport::TransportSummary;
+use crate::processor::PeerFilter;
+
+/// Version of OpenRPC spec the document follows.
+pub const OPENRPC_VERSION: &str = "1.2.6";
+
+/// JSON schema of a type, as it's serialized on wire.
+pub trait JsonSchema {
+    /// Schema of value.
+    fn schema() -> Value;
+    /// Value can be null, or omitted as a param or a field.
+    fn optional() -> bool {
+        false
+    }
+}
+
+/// Params of a typed request, as OpenRPC content descriptors.
+pub trait RequestParams: RpcRequest {
+    /// `by-position` for params in array, `by-name` for params in object.
+    const STRUCTURE: &'static str = "by-position";
+    /// Content descriptors of params, in order they are sent.
+    fn params() -> Vec<Value>;
+}
+
+macro_rules! impl_schema {
+    ($($ty:ty),+ => $schema:expr) => {
+        $(impl JsonSchema for $ty {
+            fn schema() -> Value {
+                $schema
+            }
+        })+
+    };
+}
+
+/// Object schema of a struct, generated from its fields and their types.
+///
+/// Fields are destructured from the struct and checked against their types, so a schema
+/// doesn't build once it drifts from its struct. Fields with `#[serde(default)]` are marked
+/// `#[default]`, or all of them when the struct is, and can be omitted.
+macro_rules! impl_object_schema {
+    ($(#[$struct_default:ident])? $ty:ident {
+        $($(#[$default:ident])? $field:ident: $field_ty:ty),* $(,)?
+    }) => {
+        impl JsonSchema for $ty {
+            fn schema() -> Value {
+                #[allow(dead_code)]
+                fn fields(value: $ty) {
+                    let $ty { $($field),* } = value;
+                    $(let _: $field_ty = $field;)*
+                }
+                let struct_default = is_default!(false; $($struct_default)?);
+                object(stringify!($ty), vec![$((
+                    stringify!($field),
+                    <$field_ty as JsonSchema>::schema(),
+                    <$field_ty as JsonSchema>::optional()
+                        || is_default!(struct_default; $($default)?),
+                )),*])
+            }
+        }
+    };
+}
+
+/// `true` for a field marked `#[default]`, otherwise whether its struct is.
+macro_rules! is_default {
+    ($struct_default:expr;) => {
+        $struct_default
+    };
+    ($struct_default:expr; default) => {
+        true
+    };
+}
+
+macro_rules! impl_params {
+    ($request:ident { $($rest:tt)* }) => {
+        impl_params!($request, "by-position" { $($rest)* });
+    };
+    ($request:ident, $structure:literal { $($field:ident: $field_ty:ty),* $(,)? }) => {
+        impl RequestParams for $request {
+            const STRUCTURE: &'static str = $structure;
+
+            fn params() -> Vec<Value> {
+                vec![$(param::<$field_ty>(stringify!($field))),*]
+            }
+        }
+    };
+}
+
+fn object(title: &str, fields: Vec<(&str, Value, bool)>) -> Value {
+    let required = fields
+        .iter()
+        .filter(|(_, _, optional)| !optional)
+        .map(|(name, _, _)| *name)
+        .collect::<Vec<_>>();
+    let properties = fields
+        .into_iter()
+        .map(|(name, schema, _)| (name.to_owned(), schema))
+        .collect::<serde_json::Map<_, _>>();
+    json!({
+        "title": title,
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+}
+
+fn param<T: JsonSchema>(name: &str) -> Value {
+    json!({
+        "name": name,
+        "required": !T::optional(),
+        "schema": T::schema(),
+    })
+}
+
+fn string_enum(title: &str, values: &[&str]) -> Value {
+    json!({ "title": title, "type": "string", "enum": values })
+}
+
+impl_schema!(String => json!({ "type": "string" }));
+impl_schema!(bool => json!({ "type": "boolean" }));
+impl_schema!(u8, u16, u64, u128, usize => json!({ "type": "integer", "minimum": 0 }));
+impl_schema!(f64 => json!({ "type": "number" }));
+impl_schema!(() => json!({ "type": "null" }));
+impl_schema!(Value => json!({}));
+impl_schema!(Did => json!({
+    "title": "Did",
+    "type": "string",
+    "description": "DID of node, `did:rings:0x...` or hex of address",
+    "pattern": "^(did:rings:)?(0[xX])?[0-9a-fA-F]{40}$",
+}));
+impl_schema!(HashStr => json!({ "type": "string" }));
+impl_schema!(Address => json!({
+    "title": "Address",
+    "type": "string",
+    "pattern": "^0x[0-9a-fA-F]{40}$",
+}));
+impl_schema!(Encoded => json!({
+    "title": "Encoded",
+    "type": "string",
+    "description": "base58 encoded data",
+}));
+impl_schema!(Direction => string_enum("Direction", &["inbound", "outbound"]));
+impl_schema!(Codec => string_enum("Codec", &["none", "gzip", "zstd"]));
+impl_schema!(VNodeType => string_enum("VNodeType", &[
+    "Data",
+    "SubRing",
+    "RelayMessage",
+    "Inbox",
+    "Presence",
+    "Group",
+    "GroupKey",
+    "Service",
+    "Manifest",
+    "Erasure",
+    "Topic",
+    "Pubkey",
+    "Rotation",
+]));
+impl_schema!(Session => json!({
+    "title": "Session",
+    "type": "object",
+    "description": "session authorized by signer, see rings-core",
+}));
+impl_schema!(EmptyResponse => object("EmptyResponse", vec![]));
+
+impl<T: JsonSchema> JsonSchema for Option<T> {
+    fn schema() -> Value {
+        json!({ "oneOf": [T::schema(), { "type": "null" }] })
+    }
+
+    fn optional() -> bool {
+        true
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Vec<T> {
+    fn schema() -> Value {
+        json!({ "type": "array", "items": T::schema() })
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for BTreeMap<String, T> {
+    fn schema() -> Value {
+        json!({ "type": "object", "additionalProperties": T::schema() })
+    }
+}
+
+impl<A: JsonSchema, B: JsonSchema> JsonSchema for (A, B) {
+    fn schema() -> Value {
+        json!({
+            "type": "array",
+            "items": [A::schema(), B::schema()],
+            "minItems": 2,
+            "maxItems": 2,
+        })
+    }
+}
+
+impl_object_schema!(Peer {
+    address: Did,
+    transport_id: String,
+    #[default]
+    tags: BTreeMap<String, String>,
+    stats: Option<TransportStats>,
+});
+impl_object_schema!(PeerPage {
+    peers: Vec<Peer>,
+    next_cursor: Option<String>,
+});
+impl_object_schema!(PendingPage {
+    transport_ids: Vec<String>,
+    next_cursor: Option<String>,
+});
+impl_object_schema!(#[default] PeerFilter {
+    connected: Option<bool>,
+    did_prefix: Option<String>,
+    tags: BTreeMap<String, String>,
+    limit: Option<usize>,
+});
+impl_object_schema!(LocalData {
+    key: String,
+    kind: VNodeType,
+    data: Vec<Encoded>,
+});
+impl_object_schema!(LocalDataPage {
+    data: Vec<LocalData>,
+    next_cursor: Option<String>,
+});
+impl_object_schema!(TransportAndIce {
+    transport_id: String,
+    ice: String,
+});
+impl_object_schema!(CodecStats {
+    codec: Codec,
+    payloads: u64,
+    raw_bytes: u64,
+    encoded_bytes: u64,
+    ratio: f64,
+});
+impl_object_schema!(ReplayStats {
+    stale: u64,
+    duplicate: u64,
+});
+impl_object_schema!(EchoInfo {
+    echoed: u64,
+    bytes: u64,
+    failed: u64,
+});
+impl_object_schema!(NodeInfo {
+    version: String,
+    protocol_version: u16,
+    min_protocol_version: u16,
+    version_policy: String,
+    address: String,
+    network_id: String,
+    relay: bool,
+    #[default]
+    compression: Vec<CodecStats>,
+    #[default]
+    replay: ReplayStats,
+    echo: Option<EchoInfo>,
+    #[default]
+    transports: TransportSummary,
+});
+impl_object_schema!(TransportStats {
+    rtt_ms: Option<u64>,
+    bytes_sent: u64,
+    bytes_received: u64,
+    packets_lost: Option<u64>,
+    local_candidate_type: Option<String>,
+    remote_candidate_type: Option<String>,
+    address_family: Option<String>,
+});
+impl_object_schema!(TransportSummary {
+    transports: usize,
+    relayed: usize,
+    bytes_sent: u64,
+    bytes_received: u64,
+    packets_lost: u64,
+    avg_rtt_ms: Option<u64>,
+});
+impl_object_schema!(BenchmarkReport {
+    sent: usize,
+    received: usize,
+    failed: usize,
+    size: usize,
+    elapsed_ms: u64,
+    messages_per_sec: f64,
+    bytes_per_sec: f64,
+    latency_min_ms: f64,
+    latency_avg_ms: f64,
+    latency_p50_ms: f64,
+    latency_p99_ms: f64,
+    latency_max_ms: f64,
+});
+impl_object_schema!(PeerRingSnapshot {
+    id: Did,
+    successors: Vec<Did>,
+    predecessor: Option<Did>,
+    finger: Vec<Option<Did>>,
+    fix_finger_index: u8,
+    relays: Vec<Did>,
+    storage_keys: Vec<Did>,
+});
+impl_object_schema!(StateSnapshot {
+    version: String,
+    address: String,
+    network_id: String,
+    dht: PeerRingSnapshot,
+    peers: Vec<Peer>,
+});
+impl_object_schema!(Rotation { old: Did, new: Did });
+impl_object_schema!(RecordSig {
+    session: Option<Session>,
+    sig: Vec<u8>,
+});
+impl_object_schema!(RotationRecord {
+    data: Rotation,
+    ts_ms: u128,
+    ttl_ms: usize,
+    sigs: Vec<RecordSig>,
+});
+impl_object_schema!(PresenceInfo {
+    did: String,
+    online: bool,
+    via: Option<String>,
+    expires_ms: Option<u128>,
+});
+impl_object_schema!(ManifestInfo {
+    did: String,
+    version: String,
+    network_id: String,
+    protocol_version: u16,
+    min_protocol_version: u16,
+    relay: bool,
+    features: Vec<String>,
+    endpoints: Vec<String>,
+    expires_ms: u128,
+});
+impl_object_schema!(CrawledNode {
+    did: String,
+    alive: bool,
+    rtt_ms: Option<u128>,
+    successors: Vec<String>,
+    predecessor: Option<String>,
+    manifest: Option<ManifestInfo>,
+});
+impl_object_schema!(CrawlReport {
+    nodes: Vec<CrawledNode>,
+    complete: bool,
+    estimated_size: u64,
+});
+impl_object_schema!(KnownPeerInfo {
+    did: String,
+    key: String,
+    first_seen_ms: u128,
+    last_seen_ms: u128,
+    mismatches: u64,
+});
+impl_object_schema!(ServiceProvider {
+    did: String,
+    connected: bool,
+    rtt_ms: Option<u64>,
+    expires_ms: u128,
+});
+impl_object_schema!(GroupInfo {
+    name: String,
+    subring: String,
+    admin: String,
+    members: Vec<String>,
+    updated_ms: u128,
+});
+impl_object_schema!(SendToResult {
+    tx_id: Option<String>,
+});
+impl_object_schema!(HopLatency {
+    node: Did,
+    latency_ms: u128,
+});
+impl_object_schema!(DeliveryReceipt {
+    tx_id: HashStr,
+    id: HashStr,
+    hops: Vec<HopLatency>,
+});
+impl_object_schema!(GroupSendResult {
+    sent: Vec<String>,
+    failed: Vec<String>,
+});
+impl_object_schema!(GroupKeyInfo {
+    name: String,
+    admin: String,
+    epoch: u128,
+    members: Vec<String>,
+    #[default]
+    skipped: Vec<String>,
+});
+impl_object_schema!(TopicRecordInfo {
+    publisher: String,
+    seq: u64,
+    data: String,
+    ts_ms: u128,
+    digest: String,
+});
+impl_object_schema!(TopicForkInfo {
+    publisher: String,
+    seq: u64,
+    kept: String,
+    dropped: Vec<String>,
+});
+impl_object_schema!(TopicInfo {
+    name: String,
+    records: Vec<TopicRecordInfo>,
+    forks: Vec<TopicForkInfo>,
+});
+impl_object_schema!(FileInfo {
+    id: String,
+    name: String,
+    size: u64,
+    chunks: usize,
+});
+impl_object_schema!(ErasureInfo {
+    id: String,
+    len: usize,
+    data_shards: usize,
+    parity_shards: usize,
+});
+impl_object_schema!(ErasureValue {
+    id: String,
+    value: String
+});
+impl_object_schema!(TransferProgress {
+    file_id: Did,
+    name: String,
+    total_chunks: usize,
+    done_chunks: usize,
+    total_bytes: u64,
+    done_bytes: u64,
+});
+impl_object_schema!(CapturedPayload {
+    direction: Direction,
+    peer: Address,
+    tx_id: String,
+    message_type: String,
+    size: usize,
+    path: Vec<Did>,
+    next_hop: Option<Did>,
+    destination: Did,
+    ts_ms: u128,
+    age_ms: u128,
+});
+impl_object_schema!(TrafficCounter {
+    messages: u64,
+    bytes: u64,
+});
+impl_object_schema!(PeerTrafficStats {
+    did: Did,
+    sent: TrafficCounter,
+    received: TrafficCounter,
+    sent_by_type: BTreeMap<String, TrafficCounter>,
+    received_by_type: BTreeMap<String, TrafficCounter>,
+});
+impl_object_schema!(RelayUsage {
+    origin: Did,
+    messages: u64,
+    bytes: u64,
+});
+impl_object_schema!(#[default] HistoryFilter {
+    sender: Option<Did>,
+    tx_id: Option<String>,
+    since_ms: Option<u128>,
+    until_ms: Option<u128>,
+    limit: Option<usize>,
+});
+impl_object_schema!(MessageRecord {
+    tx_id: String,
+    sender: Did,
+    destination: Did,
+    ts_ms: u128,
+    encrypted: bool,
+    data: Vec<u8>,
+});
+impl_object_schema!(HistoryPage {
+    messages: Vec<MessageRecord>,
+    next_cursor: Option<String>,
+});
+impl_object_schema!(StabilizationStatus {
+    running: bool,
+    paused: bool,
+    interval: usize,
+    min_interval: usize,
+    max_interval: usize,
+    last_round_ms: Option<u128>,
+});
+impl_object_schema!(RepairReport {
+    dropped: Vec<Did>,
+    notified: Vec<Did>,
+    fixed_fingers: usize,
+    finger_lookups: usize,
+    moved_vnodes: Vec<Did>,
+    successors: Vec<Did>,
+});
+#[cfg(feature = "chaos")]
+impl_object_schema!(
+    #[default]
+    FaultConfig {
+        drop_ratio: f64,
+        delay_ms: u64,
+        corrupt_ratio: f64,
+        kill_ratio: f64,
+    }
+);
+
+impl_params!(ConnectPeerViaHttpRequest { url: String });
+impl_params!(ConnectWithAddressRequest { address: String });
+impl_params!(ListPeersPageRequest {
+    filter: Option<PeerFilter>,
+    cursor: Option<String>,
+});
+impl_params!(CreateOfferRequest {});
+impl_params!(AnswerOfferRequest { ice_info: String });
+impl_params!(HandshakeNonceRequest {});
+impl_params!(AcceptAnswerRequest {
+    transport_id: String,
+    ice: String,
+});
+impl_params!(SendToRequest, "by-name" {
+    destination: String,
+    text: String,
+    offline_ttl: Option<u64>,
+    encrypt: Option<bool>,
+    receipt: Option<bool>,
+});
+impl_params!(DeliveryReceiptRequest { tx_id: String });
+impl_params!(DisconnectRequest { address: String });
+impl_params!(ClosePendingTransportRequest {
+    transport_id: String,
+});
+impl_params!(ListPendingsPageRequest {
+    filter: Option<PeerFilter>,
+    cursor: Option<String>,
+});
+impl_params!(NodeInfoRequest {});
+impl_params!(DrainRequest {});
+impl_params!(RotateIdentityRequest {
+    record: RotationRecord,
+});
+impl_params!(CapturedPayloadsRequest { clear: Option<bool> });
+impl_params!(PeerTrafficRequest { did: Option<String> });
+impl_params!(RelayUsageRequest {});
+impl_params!(ExportStateRequest {});
+impl_params!(ImportStateRequest {
+    snapshot: StateSnapshot,
+});
+impl_params!(ListMessagesRequest {
+    filter: Option<HistoryFilter>,
+    cursor: Option<String>,
+});
+impl_params!(QueryPresenceRequest { did: String });
+impl_params!(TrackPresenceRequest { did: String });
+impl_params!(UntrackPresenceRequest { did: String });
+impl_params!(CreateGroupRequest {
+    name: String,
+    members: Option<Vec<String>>,
+});
+impl_params!(SendToGroupRequest, "by-name" {
+    group: String,
+    text: String,
+    offline_ttl: Option<u64>,
+});
+impl_params!(FetchGroupRequest { name: String });
+impl_params!(RotateGroupKeyRequest { name: String });
+impl_params!(FetchGroupKeyRequest { name: String });
+impl_params!(PublishTopicRequest {
+    name: String,
+    data: String,
+});
+impl_params!(FetchTopicRequest { name: String });
+impl_params!(SendFileRequest { path: String });
+impl_params!(FetchFileRequest {
+    id: String,
+    output: Option<String>,
+});
+impl_params!(StoreErasureRequest {
+    value: String,
+    data_shards: Option<usize>,
+    parity_shards: Option<usize>,
+});
+impl_params!(FetchErasureRequest { id: String });
+impl_params!(RegisterServiceRequest { name: String });
+impl_params!(UnregisterServiceRequest { name: String });
+impl_params!(ResolveServiceRequest { name: String });
+impl_params!(EnsResolveRequest { name: String });
+impl_params!(EnsReverseRequest { address: String });
+impl_params!(WhoisRequest { did: String });
+impl_params!(TagPeerRequest {
+    did: String,
+    key: String,
+    value: Option<String>,
+});
+impl_params!(ListKnownPeersRequest {});
+impl_params!(CrawlRequest {
+    max_nodes: Option<usize>,
+    with_manifests: Option<bool>,
+});
+impl_params!(BenchmarkRequest {
+    did: String,
+    size: Option<usize>,
+    count: Option<usize>,
+    concurrency: Option<usize>,
+});
+#[cfg(feature = "chaos")]
+impl_params!(InjectFaultsRequest, "by-name" {
+    drop_ratio: Option<f64>,
+    delay_ms: Option<u64>,
+    corrupt_ratio: Option<f64>,
+    kill_ratio: Option<f64>,
+});
+impl_params!(StabilizationStatusRequest {});
+impl_params!(RepairDhtRequest {});
+impl_params!(ListLocalDataRequest {
+    cursor: Option<String>,
+    limit: Option<usize>,
+});
+impl_params!(DeleteLocalDataRequest { key: String });
+impl_params!(DiscoverRequest {});
+
+/// Params are a tagged [StabilizationControl](crate::processor::StabilizationControl).
+impl RequestParams for ControlStabilizationRequest {
+    const STRUCTURE: &'static str = "by-name";
+
+    fn params() -> Vec<Value> {
+        let action = string_enum("action", &["pause", "resume", "trigger", "interval"]);
+        vec![
+            json!({ "name": "action", "required": true, "schema": action }),
+            param::<Option<usize>>("min"),
+            param::<Option<usize>>("max"),
+        ]
+    }
+}
+
+fn method<R>() -> Value
+where
+    R: RequestParams,
+    R::Response: JsonSchema,
+{
+    json!({
+        "name": R::METHOD.as_str(),
+        "summary": R::METHOD.summary(),
+        "paramStructure": R::STRUCTURE,
+        "params": R::params(),
+        "result": { "name": "result", "schema": <R::Response as JsonSchema>::schema() },
+    })
+}
+
+/// Method of a paginated request, which returns all items as `T` without params.
+fn paged_method<R, T>() -> Value
+where
+    R: RequestParams,
+    R::Response: JsonSchema,
+    T: JsonSchema,
+{
+    let mut m = method::<R>();
+    m["result"]["schema"] =
+        json!({ "oneOf": [T::schema(), <R::Response as JsonSchema>::schema()] });
+    m
+}
+
+/// OpenRPC document of all methods served by node.
+pub fn document() -> Value {
+    #[allow(unused_mut)]
+    let mut methods = vec![
+        method::<ConnectPeerViaHttpRequest>(),
+        method::<ConnectWithAddressRequest>(),
+        paged_method::<ListPeersPageRequest, Vec<Peer>>(),
+        method::<CreateOfferRequest>(),
+        method::<AnswerOfferRequest>(),
+        method::<HandshakeNonceRequest>(),
+        method::<AcceptAnswerRequest>(),
+        method::<SendToRequest>(),
+        method::<DeliveryReceiptRequest>(),
+        method::<DisconnectRequest>(),
+        paged_method::<ListPendingsPageRequest, Vec<String>>(),
+        method::<ClosePendingTransportRequest>(),
+        method::<NodeInfoRequest>(),
+        method::<DrainRequest>(),
+        method::<RotateIdentityRequest>(),
+        method::<CapturedPayloadsRequest>(),
+        method::<PeerTrafficRequest>(),
+        method::<RelayUsageRequest>(),
+        method::<ExportStateRequest>(),
+        method::<ImportStateRequest>(),
+        method::<ListMessagesRequest>(),
+        method::<QueryPresenceRequest>(),
+        method::<TrackPresenceRequest>(),
+        method::<UntrackPresenceRequest>(),
+        method::<CreateGroupRequest>(),
+        method::<SendToGroupRequest>(),
+        method::<FetchGroupRequest>(),
+        method::<RotateGroupKeyRequest>(),
+        method::<FetchGroupKeyRequest>(),
+        method::<PublishTopicRequest>(),
+        method::<FetchTopicRequest>(),
+        method::<SendFileRequest>(),
+        method::<FetchFileRequest>(),
+        method::<StoreErasureRequest>(),
+        method::<FetchErasureRequest>(),
+        method::<RegisterServiceRequest>(),
+        method::<UnregisterServiceRequest>(),
+        method::<ResolveServiceRequest>(),
+        method::<EnsResolveRequest>(),
+        method::<EnsReverseRequest>(),
+        method::<WhoisRequest>(),
+        method::<StabilizationStatusRequest>(),
+        method::<ControlStabilizationRequest>(),
+        method::<RepairDhtRequest>(),
+        method::<ListLocalDataRequest>(),
+        method::<DeleteLocalDataRequest>(),
+        method::<TagPeerRequest>(),
+        method::<ListKnownPeersRequest>(),
+        method::<CrawlRequest>(),
+        method::<BenchmarkRequest>(),
+        method::<DiscoverRequest>(),
+    ];
+    #[cfg(feature = "chaos")]
+    methods.push(method::<InjectFaultsRequest>());
+    json!({
+        "openrpc": OPENRPC_VERSION,
+        "info": {
+            "title": "rings-node",
+            "description": "JSON-RPC API of rings-node",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "methods": methods,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::jsonrpc::method::Method;
+
+    /// All methods, the match fails to build once a method is added, so it's listed here too.
+    fn all_methods() -> Vec<Method> {
+        let all = vec![
+            Method::ConnectPeerViaHttp,
+            Method::ConnectWithAddress,
+            Method::ListPeers,
+            Method::CreateOffer,
+            Method::AnswerOffer,
+            Method::HandshakeNonce,
+            Method::AcceptAnswer,
+            Method::SendTo,
+            Method::DeliveryReceipt,
+            Method::Disconnect,
+            Method::ListPendings,
+            Method::ClosePendingTransport,
+            Method::NodeInfo,
+            Method::Drain,
+            Method::RotateIdentity,
+            Method::CapturedPayloads,
+            Method::PeerTraffic,
+            Method::RelayUsage,
+            Method::ExportState,
+            Method::ImportState,
+            Method::ListMessages,
+            Method::QueryPresence,
+            Method::TrackPresence,
+            Method::UntrackPresence,
+            Method::CreateGroup,
+            Method::SendToGroup,
+            Method::FetchGroup,
+            Method::RotateGroupKey,
+            Method::FetchGroupKey,
+            Method::PublishTopic,
+            Method::FetchTopic,
+            Method::SendFile,
+            Method::FetchFile,
+            Method::StoreErasure,
+            Method::FetchErasure,
+            Method::RegisterService,
+            Method::UnregisterService,
+            Method::ResolveService,
+            Method::EnsResolve,
+            Method::EnsReverse,
+            Method::Whois,
+            Method::StabilizationStatus,
+            Method::ControlStabilization,
+            Method::RepairDht,
+            Method::ListLocalData,
+            Method::DeleteLocalData,
+            Method::TagPeer,
+            Method::ListKnownPeers,
+            Method::Crawl,
+            Method::Benchmark,
+            #[cfg(feature = "chaos")]
+            Method::InjectFaults,
+            Method::Discover,
+        ];
+        for m in all.iter() {
+            match m {
+                Method::ConnectPeerViaHttp
+                | Method::ConnectWithAddress
+                | Method::ListPeers
+                | Method::CreateOffer
+                | Method::AnswerOffer
+                | Method::HandshakeNonce
+                | Method::AcceptAnswer
+                | Method::SendTo
+                | Method::DeliveryReceipt
+                | Method::Disconnect
+                | Method::ListPendings
+                | Method::ClosePendingTransport
+                | Method::NodeInfo
+                | Method::Drain
+                | Method::RotateIdentity
+                | Method::CapturedPayloads
+                | Method::PeerTraffic
+                | Method::RelayUsage
+                | Method::ExportState
+                | Method::ImportState
+                | Method::ListMessages
+                | Method::QueryPresence
+                | Method::TrackPresence
+                | Method::UntrackPresence
+                | Method::CreateGroup
+                | Method::SendToGroup
+                | Method::FetchGroup
+                | Method::RotateGroupKey
+                | Method::FetchGroupKey
+                | Method::PublishTopic
+                | Method::FetchTopic
+                | Method::SendFile
+                | Method::FetchFile
+                | Method::StoreErasure
+                | Method::FetchErasure
+                | Method::RegisterService
+                | Method::UnregisterService
+                | Method::ResolveService
+                | Method::EnsResolve
+                | Method::EnsReverse
+                | Method::Whois
+                | Method::StabilizationStatus
+                | Method::ControlStabilization
+                | Method::RepairDht
+                | Method::ListLocalData
+                | Method::DeleteLocalData
+                | Method::TagPeer
+                | Method::ListKnownPeers
+                | Method::Crawl
+                | Method::Benchmark
+                | Method::Discover => {}
+                #[cfg(feature = "chaos")]
+                Method::InjectFaults => {}
+            }
+        }
+        all
+    }
+
+    #[test]
+    fn test_openrpc_document() {
+        let doc = document();
+        assert_eq!(doc["openrpc"], OPENRPC_VERSION);
+        let methods = doc["methods"].as_array().unwrap();
+        let names = methods
+            .iter()
+            .map(|m| m["name"].as_str().unwrap())
+            .collect::<HashSet<_>>();
+        assert_eq!(names.len(), methods.len());
+        for name in names.iter() {
+            assert!(Method::try_from(*name).is_ok(), "unknown method {}", name);
+        }
+        for m in all_methods() {
+            assert!(
+                names.contains(m.as_str()),
+                "{} is not described",
+                m.as_str()
+            );
+        }
+
+        let send_to = methods.iter().find(|m| m["name"] == "sendTo").unwrap();
+        assert_eq!(send_to["paramStructure"], "by-name");
+        assert_eq!(send_to["params"][0]["name"], "destination");
+        assert_eq!(send_to["params"][2]["required"], false);
+        let result = &send_to["result"]["schema"];
+        assert_eq!(result["title"], "SendToResult");
+
+        let receipt = methods
+            .iter()
+            .find(|m| m["name"] == "deliveryReceipt")
+            .unwrap();
+        let result = &receipt["result"]["schema"]["oneOf"][0];
+        assert_eq!(result["properties"]["hops"]["type"], "array");
+
+        let whois = methods.iter().find(|m| m["name"] == "whois").unwrap();
+        assert_eq!(whois["params"][0]["required"], true);
+        let result = &whois["result"]["schema"];
+        assert_eq!(result["title"], "ManifestInfo");
+        assert!(result["required"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("did")));
+
+        let node_info = methods.iter().find(|m| m["name"] == "nodeInfo").unwrap();
+        let result = &node_info["result"]["schema"];
+        assert_eq!(result["properties"]["compression"]["type"], "array");
+        let required = result["required"].as_array().unwrap();
+        assert!(required.contains(&json!("version")));
+        assert!(!required.contains(&json!("compression")));
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use serde::Deserialize;
@@ -6,16 +7,41 @@ use serde_json::Value as JsonValue;
 
 use crate::error::Error;
 use crate::error::Result;
+use crate::prelude::rings_core::dht::vnode::VNodeType;
+use crate::prelude::rings_core::dht::vnode::VirtualNode;
+use crate::prelude::rings_core::dht::Did;
+use crate::prelude::rings_core::dht::PeerRingSnapshot;
+use crate::prelude::rings_core::erasure::ErasureManifest;
+use crate::prelude::rings_core::file::FileManifest;
+use crate::prelude::rings_core::group::GroupKeyRecord;
+use crate::prelude::rings_core::group::GroupRecord;
+use crate::prelude::rings_core::known_peers::KnownPeer;
+use crate::prelude::rings_core::manifest::ManifestRecord;
+use crate::prelude::rings_core::message::codec::CodecStats;
+use crate::prelude::rings_core::message::EchoStats;
 use crate::prelude::rings_core::message::Encoded;
-use crate::prelude::rings_core::prelude::web3::contract::tokens::Tokenizable;
-use crate::prelude::rings_core::prelude::web3::types::Address;
+use crate::prelude::rings_core::prelude::Address;
+use crate::prelude::rings_core::presence::PresenceRecord;
+use crate::prelude::rings_core::replay::ReplayStats;
+use crate::prelude::rings_core::topic::TopicLog;
+use crate::prelude::rings_core::topic::TopicRecord;
 use crate::prelude::rings_core::transports::Transport;
+use crate::prelude::rings_core::types::ice_transport::TransportStats;
+use crate::prelude::rings_core::types::ice_transport::TransportSummary;
 use crate::processor;
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct Peer {
-    pub address: String,
+    /// written as bare hex, as before DIDs, see [bare_hex_did]
+    #[serde(with = "bare_hex_did")]
+    pub address: Did,
     pub transport_id: String,
+    /// local tags of peer, see `tagPeer`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    /// WebRTC stats of transport, candidate types tell if link is relayed by TURN
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<TransportStats>,
 }
 
 impl Peer {
@@ -31,13 +57,48 @@ impl Peer {
     pub fn base64_encode(&self) -> Result<String> {
         Ok(base64::encode(self.to_json_vec()?))
     }
+
+    pub fn with_tags(mut self, tags: BTreeMap<String, String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    pub fn with_stats(mut self, stats: Option<TransportStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+}
+
+/// DID written as 40 hex digits without `0x`, any form of [Did] is accepted on reading.
+mod bare_hex_did {
+    use std::str::FromStr;
+
+    use serde::de;
+    use serde::Deserialize;
+    use serde::Deserializer;
+    use serde::Serializer;
+
+    use crate::prelude::rings_core::dht::Did;
+
+    pub fn serialize<S>(did: &Did, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer {
+        serializer.serialize_str(&format!("{:x}", **did))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Did, D::Error>
+    where D: Deserializer<'de> {
+        let s = String::deserialize(deserializer)?;
+        Did::from_str(&s).map_err(de::Error::custom)
+    }
 }
 
 impl From<(Address, Arc<Transport>)> for Peer {
     fn from((address, transport): (Address, Arc<Transport>)) -> Self {
         Self {
-            address: address.into_token().to_string(),
+            address: (*address).into(),
             transport_id: transport.id.to_string(),
+            tags: BTreeMap::new(),
+            stats: None,
         }
     }
 }
@@ -45,8 +106,10 @@ impl From<(Address, Arc<Transport>)> for Peer {
 impl From<&(Address, Arc<Transport>)> for Peer {
     fn from((address, transport): &(Address, Arc<Transport>)) -> Self {
         Self {
-            address: address.into_token().to_string(),
+            address: (*address).into(),
             transport_id: transport.id.to_string(),
+            tags: BTreeMap::new(),
+            stats: None,
         }
     }
 }
@@ -54,12 +117,57 @@ impl From<&(Address, Arc<Transport>)> for Peer {
 impl From<processor::Peer> for Peer {
     fn from(p: processor::Peer) -> Self {
         Self {
-            address: p.address.into_token().to_string(),
+            address: p.address,
             transport_id: p.transport.id.to_string(),
+            tags: BTreeMap::new(),
+            stats: None,
+        }
+    }
+}
+
+/// One page of `listPeers`, pass `next_cursor` to get the next page.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct PeerPage {
+    pub peers: Vec<Peer>,
+    /// None if there is no more peer.
+    pub next_cursor: Option<String>,
+}
+
+/// One page of `listPendings`, pass `next_cursor` to get the next page.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct PendingPage {
+    pub transport_ids: Vec<String>,
+    /// None if there is no more pending transport.
+    pub next_cursor: Option<String>,
+}
+
+/// A virtual node stored by this node.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct LocalData {
+    /// address of virtual node, hex
+    pub key: String,
+    pub kind: VNodeType,
+    pub data: Vec<Encoded>,
+}
+
+impl From<VirtualNode> for LocalData {
+    fn from(v: VirtualNode) -> Self {
+        Self {
+            key: format!("{:?}", *v.address),
+            kind: v.kind,
+            data: v.data,
         }
     }
 }
 
+/// One page of `listLocalData`, pass `next_cursor` to get the next page.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct LocalDataPage {
+    pub data: Vec<LocalData>,
+    /// None if there is no more stored data.
+    pub next_cursor: Option<String>,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct TransportAndIce {
     pub transport_id: String,
@@ -86,6 +194,11 @@ impl TransportAndIce {
     pub fn base64_encode(&self) -> Result<String> {
         Ok(base64::encode(self.to_json_vec()?))
     }
+
+    pub fn with_tags(mut self, tags: BTreeMap<String, String>) -> Self {
+        self.tags = tags;
+        self
+    }
 }
 
 impl From<(Arc<Transport>, Encoded)> for TransportAndIce {
@@ -96,3 +209,428 @@ impl From<(Arc<Transport>, Encoded)> for TransportAndIce {
         }
     }
 }
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct NodeInfo {
+    /// version of rings-node
+    pub version: String,
+    /// highest supported protocol version
+    pub protocol_version: u16,
+    /// lowest supported protocol version
+    pub min_protocol_version: u16,
+    /// `warn` or `refuse` peers without common protocol version
+    pub version_policy: String,
+    pub address: String,
+    pub network_id: String,
+    pub relay: bool,
+    /// effective compression of payloads sent, by codec
+    #[serde(default)]
+    pub compression: Vec<CodecStats>,
+    /// payloads rejected as stale or replayed
+    #[serde(default)]
+    pub replay: ReplayStats,
+    /// custom messages echoed, if node runs in echo mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub echo: Option<EchoInfo>,
+    /// totals of WebRTC stats of connected transports
+    #[serde(default)]
+    pub transports: TransportSummary,
+}
+
+/// Custom messages echoed by a node in echo mode.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct EchoInfo {
+    pub echoed: u64,
+    pub bytes: u64,
+    pub failed: u64,
+}
+
+impl From<&EchoStats> for EchoInfo {
+    fn from(stats: &EchoStats) -> Self {
+        Self {
+            echoed: stats.echoed(),
+            bytes: stats.bytes(),
+            failed: stats.failed(),
+        }
+    }
+}
+
+/// Throughput and latency of messages echoed by a peer in echo mode.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct BenchmarkReport {
+    /// messages sent, failed ones excluded
+    pub sent: usize,
+    /// echoes received in time
+    pub received: usize,
+    /// messages failed to send
+    pub failed: usize,
+    /// bytes of each message
+    pub size: usize,
+    pub elapsed_ms: u64,
+    /// echoes received per second
+    pub messages_per_sec: f64,
+    /// bytes echoed per second
+    pub bytes_per_sec: f64,
+    pub latency_min_ms: f64,
+    pub latency_avg_ms: f64,
+    pub latency_p50_ms: f64,
+    pub latency_p99_ms: f64,
+    pub latency_max_ms: f64,
+}
+
+impl BenchmarkReport {
+    /// Report of `sent` messages of `size` bytes, echoes of which took `latencies_ms`.
+    pub fn new(
+        sent: usize,
+        failed: usize,
+        size: usize,
+        mut latencies_ms: Vec<f64>,
+        elapsed_ms: u64,
+    ) -> Self {
+        latencies_ms.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let received = latencies_ms.len();
+        let percentile = |p: usize| {
+            latencies_ms
+                .get((received * p / 100).min(received.saturating_sub(1)))
+                .copied()
+                .unwrap_or_default()
+        };
+        let secs = (elapsed_ms.max(1)) as f64 / 1000.0;
+        Self {
+            sent,
+            received,
+            failed,
+            size,
+            elapsed_ms,
+            messages_per_sec: received as f64 / secs,
+            bytes_per_sec: (received * size) as f64 / secs,
+            latency_min_ms: latencies_ms.first().copied().unwrap_or_default(),
+            latency_avg_ms: if received > 0 {
+                latencies_ms.iter().sum::<f64>() / received as f64
+            } else {
+                0.0
+            },
+            latency_p50_ms: percentile(50),
+            latency_p99_ms: percentile(99),
+            latency_max_ms: latencies_ms.last().copied().unwrap_or_default(),
+        }
+    }
+}
+
+/// Snapshot of DHT and connected peers, exported by `exportState`.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct StateSnapshot {
+    /// version of rings-node which exported it
+    pub version: String,
+    pub address: String,
+    pub network_id: String,
+    pub dht: PeerRingSnapshot,
+    /// peers connected when exporting, they are not reconnected by `importState`
+    pub peers: Vec<Peer>,
+}
+
+/// Presence of a DID, `via` and `expires_ms` are set if its record is found.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct PresenceInfo {
+    pub did: String,
+    pub online: bool,
+    pub via: Option<String>,
+    pub expires_ms: Option<u128>,
+}
+
+impl PresenceInfo {
+    pub fn new(did: Did, record: Option<&PresenceRecord>) -> Self {
+        Self {
+            did: format!("{:?}", *did),
+            online: record.map_or(false, |r| r.is_online()),
+            via: record.map(|r| format!("{:?}", *r.presence.via)),
+            expires_ms: record.map(|r| r.expires_ms()),
+        }
+    }
+}
+
+/// A peer seen, with key of its first contact, see
+/// [crate::processor::Processor::list_known_peers].
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct KnownPeerInfo {
+    pub did: String,
+    /// Address of key authorized by the DID, in hex.
+    pub key: String,
+    pub first_seen_ms: u128,
+    pub last_seen_ms: u128,
+    /// Contacts with another key.
+    pub mismatches: u64,
+}
+
+impl KnownPeerInfo {
+    pub fn new(did: Did, peer: KnownPeer) -> Self {
+        Self {
+            did: format!("{:?}", *did),
+            key: peer.key,
+            first_seen_ms: peer.first_seen_ms,
+            last_seen_ms: peer.last_seen_ms,
+            mismatches: peer.mismatches,
+        }
+    }
+}
+
+/// Verified manifest of a node, see [crate::processor::Processor::whois].
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ManifestInfo {
+    pub did: String,
+    pub version: String,
+    pub network_id: String,
+    pub protocol_version: u16,
+    pub min_protocol_version: u16,
+    pub relay: bool,
+    pub features: Vec<String>,
+    pub endpoints: Vec<String>,
+    pub expires_ms: u128,
+}
+
+impl From<&ManifestRecord> for ManifestInfo {
+    fn from(record: &ManifestRecord) -> Self {
+        let m = &record.data;
+        Self {
+            did: format!("{:?}", *m.did),
+            version: m.version.clone(),
+            network_id: m.network_id.clone(),
+            protocol_version: m.protocol_version,
+            min_protocol_version: m.min_protocol_version,
+            relay: m.relay,
+            features: m.features.clone(),
+            endpoints: m.endpoints.clone(),
+            expires_ms: record.expires_ms(),
+        }
+    }
+}
+
+/// A node visited by [crate::processor::Processor::crawl].
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct CrawledNode {
+    pub did: String,
+    /// Node answered query of its neighbours in time.
+    pub alive: bool,
+    pub rtt_ms: Option<u128>,
+    pub successors: Vec<String>,
+    pub predecessor: Option<String>,
+    pub manifest: Option<ManifestInfo>,
+}
+
+/// Nodes visited by crawl, in ring order, and estimated count of nodes on ring.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct CrawlReport {
+    pub nodes: Vec<CrawledNode>,
+    /// Walk went around the whole ring.
+    pub complete: bool,
+    pub estimated_size: u64,
+}
+
+/// A provider of service, see [crate::processor::Processor::resolve_service].
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ServiceProvider {
+    pub did: String,
+    /// Provider is connected to this node directly.
+    pub connected: bool,
+    /// RTT measured by this node, only known for connected providers.
+    pub rtt_ms: Option<u64>,
+    /// When record of provider expires, in milliseconds since epoch.
+    pub expires_ms: u128,
+}
+
+/// Membership of a group.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct GroupInfo {
+    pub name: String,
+    pub subring: String,
+    pub admin: String,
+    pub members: Vec<String>,
+    /// When membership is updated, in milliseconds since epoch.
+    pub updated_ms: u128,
+}
+
+impl From<&GroupRecord> for GroupInfo {
+    fn from(record: &GroupRecord) -> Self {
+        let group = &record.data;
+        Self {
+            name: group.name.clone(),
+            subring: format!("{:?}", *group.subring),
+            admin: format!("{:?}", *group.admin),
+            members: group.members.iter().map(|m| format!("{:?}", **m)).collect(),
+            updated_ms: record.updated_ms(),
+        }
+    }
+}
+
+/// Result of `sendTo`, with `tx_id` of the message if a receipt is asked for it.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SendToResult {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_id: Option<String>,
+}
+
+/// Members a group message is sent or stored to, and those failed.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct GroupSendResult {
+    pub sent: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+/// Key epoch of a group, members it is shared with, and those skipped for lack of manifest.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct GroupKeyInfo {
+    pub name: String,
+    pub admin: String,
+    pub epoch: u128,
+    pub members: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<String>,
+}
+
+impl GroupKeyInfo {
+    pub fn new(record: &GroupKeyRecord, skipped: Vec<String>) -> Self {
+        let shares = &record.data;
+        Self {
+            name: shares.group.clone(),
+            admin: format!("{:?}", *shares.admin),
+            epoch: shares.epoch,
+            members: record
+                .members()
+                .iter()
+                .map(|m| format!("{:?}", **m))
+                .collect(),
+            skipped,
+        }
+    }
+}
+
+/// Record of a topic, referred by its `digest`.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct TopicRecordInfo {
+    pub publisher: String,
+    pub seq: u64,
+    pub data: String,
+    /// When record is signed by its publisher, in milliseconds since epoch.
+    pub ts_ms: u128,
+    pub digest: String,
+}
+
+impl From<&TopicRecord> for TopicRecordInfo {
+    fn from(record: &TopicRecord) -> Self {
+        Self {
+            publisher: format!("{:?}", *record.entry.publisher),
+            seq: record.entry.seq,
+            data: record.entry.data.clone(),
+            ts_ms: record.verification.ts_ms,
+            digest: record.digest(),
+        }
+    }
+}
+
+/// Records of a publisher forked at `seq`, by digests.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct TopicForkInfo {
+    pub publisher: String,
+    pub seq: u64,
+    pub kept: String,
+    pub dropped: Vec<String>,
+}
+
+/// History of a topic, with forks resolved.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct TopicInfo {
+    pub name: String,
+    pub records: Vec<TopicRecordInfo>,
+    pub forks: Vec<TopicForkInfo>,
+}
+
+impl TopicInfo {
+    pub fn new(name: &str, log: &TopicLog) -> Self {
+        Self {
+            name: name.to_owned(),
+            records: log.records.iter().map(TopicRecordInfo::from).collect(),
+            forks: log
+                .forks
+                .iter()
+                .map(|f| TopicForkInfo {
+                    publisher: format!("{:?}", *f.publisher),
+                    seq: f.seq,
+                    kept: f.kept.digest(),
+                    dropped: f.dropped.iter().map(|r| r.digest()).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A file stored on DHT, fetch it by `id`.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct FileInfo {
+    pub id: String,
+    pub name: String,
+    pub size: u64,
+    pub chunks: usize,
+}
+
+impl FileInfo {
+    pub fn new(id: Did, manifest: &FileManifest) -> Self {
+        Self {
+            id: format!("{:?}", *id),
+            name: manifest.name.clone(),
+            size: manifest.size,
+            chunks: manifest.chunks.len(),
+        }
+    }
+}
+
+/// A value erasure coded on DHT, fetch it by `id`.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ErasureInfo {
+    pub id: String,
+    pub len: usize,
+    pub data_shards: usize,
+    pub parity_shards: usize,
+}
+
+impl ErasureInfo {
+    pub fn new(id: Did, manifest: &ErasureManifest) -> Self {
+        Self {
+            id: format!("{:?}", *id),
+            len: manifest.len,
+            data_shards: manifest.data_shards,
+            parity_shards: manifest.parity_shards,
+        }
+    }
+}
+
+/// A value fetched from DHT, in base64.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ErasureValue {
+    pub id: String,
+    pub value: String,
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_peer_address_json() {
+        let hex = "11e807fcc88dd319270493fb2e822e388fe36ab0";
+        let peer = Peer {
+            address: Did::from_str(hex).unwrap(),
+            transport_id: "t".to_owned(),
+            tags: BTreeMap::new(),
+            stats: None,
+        };
+        let json = peer.to_json_obj().unwrap();
+        assert_eq!(json["address"], hex);
+        for address in [format!("0x{}", hex), format!("did:rings:0x{}", hex)] {
+            let json = serde_json::json!({"address": address, "transport_id": "t"});
+            let parsed: Peer = serde_json::from_value(json).unwrap();
+            assert_eq!(parsed.address, peer.address);
+        }
+    }
+}
@@ -1,5 +1,5 @@
 #![warn(missing_docs)]
-use std::str::FromStr;
+use std::collections::HashMap;
 
 use jsonrpc_core::Error;
 use jsonrpc_core::ErrorCode;
@@ -7,23 +7,84 @@ use jsonrpc_core::MetaIoHandler;
 use jsonrpc_core::Params;
 use jsonrpc_core::Result;
 use jsonrpc_core::Value;
+use serde::de::DeserializeOwned;
 
 use super::method::Method;
+use super::openrpc;
+use super::response::ErasureValue;
+use super::response::GroupInfo;
 use super::response::Peer;
+use super::response::SendToResult;
+use super::response::StateSnapshot;
+use super::response::TopicInfo;
+use super::response::TopicRecordInfo;
 use super::response::TransportAndIce;
 use crate::error::Error as ServerError;
-use crate::prelude::rings_core::prelude::Address;
+#[cfg(feature = "chaos")]
+use crate::prelude::rings_core::chaos::FaultConfig;
+use crate::prelude::rings_core::erasure::DEFAULT_DATA_SHARDS;
+use crate::prelude::rings_core::erasure::DEFAULT_PARITY_SHARDS;
+use crate::prelude::rings_core::history::HistoryFilter;
+use crate::prelude::rings_core::message::DEFAULT_INBOX_TTL_MS;
+use crate::prelude::rings_core::rotation::RotationRecord;
+use crate::processor::parse_did;
+use crate::processor::PeerFilter;
 use crate::processor::Processor;
+use crate::processor::StabilizationControl;
 
 pub(crate) async fn build_handler(handler: &mut MetaIoHandler<Processor>) {
     handler.add_method_with_meta(Method::ConnectPeerViaHttp.as_str(), connect_peer_via_http);
     handler.add_method_with_meta(Method::AnswerOffer.as_str(), answer_offer);
+    handler.add_method_with_meta(Method::HandshakeNonce.as_str(), handshake_nonce);
     handler.add_method_with_meta(Method::ConnectWithAddress.as_str(), connect_with_address);
     handler.add_method_with_meta(Method::CreateOffer.as_str(), create_offer);
     handler.add_method_with_meta(Method::AcceptAnswer.as_str(), accept_answer);
     handler.add_method_with_meta(Method::ListPeers.as_str(), list_peers);
+    handler.add_method_with_meta(Method::ListPendings.as_str(), list_pendings);
     handler.add_method_with_meta(Method::Disconnect.as_str(), close_connection);
-    handler.add_method_with_meta(Method::SendTo.as_str(), send_message)
+    handler.add_method_with_meta(Method::SendTo.as_str(), send_message);
+    handler.add_method_with_meta(Method::DeliveryReceipt.as_str(), delivery_receipt);
+    handler.add_method_with_meta(Method::NodeInfo.as_str(), node_info);
+    handler.add_method_with_meta(Method::Drain.as_str(), drain);
+    handler.add_method_with_meta(Method::RotateIdentity.as_str(), rotate_identity);
+    handler.add_method_with_meta(Method::CapturedPayloads.as_str(), captured_payloads);
+    handler.add_method_with_meta(Method::PeerTraffic.as_str(), peer_traffic);
+    handler.add_method_with_meta(Method::RelayUsage.as_str(), relay_usage);
+    handler.add_method_with_meta(Method::ExportState.as_str(), export_state);
+    handler.add_method_with_meta(Method::ImportState.as_str(), import_state);
+    handler.add_method_with_meta(Method::ListMessages.as_str(), list_messages);
+    handler.add_method_with_meta(Method::QueryPresence.as_str(), query_presence);
+    handler.add_method_with_meta(Method::TrackPresence.as_str(), track_presence);
+    handler.add_method_with_meta(Method::UntrackPresence.as_str(), untrack_presence);
+    handler.add_method_with_meta(Method::CreateGroup.as_str(), create_group);
+    handler.add_method_with_meta(Method::SendToGroup.as_str(), send_to_group);
+    handler.add_method_with_meta(Method::FetchGroup.as_str(), fetch_group);
+    handler.add_method_with_meta(Method::RotateGroupKey.as_str(), rotate_group_key);
+    handler.add_method_with_meta(Method::FetchGroupKey.as_str(), fetch_group_key);
+    handler.add_method_with_meta(Method::PublishTopic.as_str(), publish_topic);
+    handler.add_method_with_meta(Method::FetchTopic.as_str(), fetch_topic);
+    handler.add_method_with_meta(Method::SendFile.as_str(), send_file);
+    handler.add_method_with_meta(Method::FetchFile.as_str(), fetch_file);
+    handler.add_method_with_meta(Method::StoreErasure.as_str(), store_erasure);
+    handler.add_method_with_meta(Method::FetchErasure.as_str(), fetch_erasure);
+    handler.add_method_with_meta(Method::RegisterService.as_str(), register_service);
+    handler.add_method_with_meta(Method::UnregisterService.as_str(), unregister_service);
+    handler.add_method_with_meta(Method::ResolveService.as_str(), resolve_service);
+    handler.add_method_with_meta(Method::EnsResolve.as_str(), ens_resolve);
+    handler.add_method_with_meta(Method::EnsReverse.as_str(), ens_reverse);
+    handler.add_method_with_meta(Method::Whois.as_str(), whois);
+    handler.add_method_with_meta(Method::StabilizationStatus.as_str(), stabilization_status);
+    handler.add_method_with_meta(Method::ControlStabilization.as_str(), control_stabilization);
+    handler.add_method_with_meta(Method::RepairDht.as_str(), repair_dht);
+    handler.add_method_with_meta(Method::ListLocalData.as_str(), list_local_data);
+    handler.add_method_with_meta(Method::DeleteLocalData.as_str(), delete_local_data);
+    handler.add_method_with_meta(Method::TagPeer.as_str(), tag_peer);
+    handler.add_method_with_meta(Method::ListKnownPeers.as_str(), list_known_peers);
+    #[cfg(feature = "chaos")]
+    handler.add_method_with_meta(Method::InjectFaults.as_str(), inject_faults);
+    handler.add_method_with_meta(Method::Crawl.as_str(), crawl);
+    handler.add_method_with_meta(Method::Benchmark.as_str(), benchmark);
+    handler.add_method_with_meta(Method::Discover.as_str(), discover)
 }
 
 async fn connect_peer_via_http(params: Params, processor: Processor) -> Result<Value> {
@@ -47,18 +108,25 @@ async fn answer_offer(params: Params, processor: Processor) -> Result<Value> {
         .answer_offer(ice_info)
         .await
         .map_err(Error::from)?;
-    log::debug!("connect_peer_via_ice response: {:?}", r.1);
+    tracing::debug!("connect_peer_via_ice response: {:?}", r.1);
     TransportAndIce::from(r).to_json_obj().map_err(Error::from)
 }
 
+async fn handshake_nonce(_params: Params, processor: Processor) -> Result<Value> {
+    Ok(Value::String(processor.handshake_nonce()?))
+}
+
 async fn connect_with_address(params: Params, processor: Processor) -> Result<Value> {
     let p: Vec<String> = params.parse()?;
     let address_str = p
         .first()
         .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let address_str = processor.resolve_did(address_str).await?;
     processor
         .connect_with_address(
-            &Address::from_str(address_str).map_err(|_| Error::new(ErrorCode::InvalidParams))?,
+            &parse_did(&address_str)
+                .map_err(|_| Error::new(ErrorCode::InvalidParams))?
+                .into(),
             true,
         )
         .await
@@ -83,22 +151,475 @@ async fn accept_answer(params: Params, processor: Processor) -> Result<Value> {
     Err(Error::new(ErrorCode::InvalidParams))
 }
 
-async fn list_peers(_params: Params, processor: Processor) -> Result<Value> {
+/// Parse optional `[filter, cursor]` of paginated methods.
+fn page_params<F: DeserializeOwned + Default>(params: &[Value]) -> Result<(F, Option<String>)> {
+    let arg = |i: usize| params.get(i).cloned().unwrap_or(Value::Null);
+    let filter: Option<F> =
+        serde_json::from_value(arg(0)).map_err(|_| Error::new(ErrorCode::InvalidParams))?;
+    let cursor: Option<String> =
+        serde_json::from_value(arg(1)).map_err(|_| Error::new(ErrorCode::InvalidParams))?;
+    Ok((filter.unwrap_or_default(), cursor))
+}
+
+/// Params are `[filter, cursor]`, both optional. Without params all peers are listed,
+/// otherwise one page of them.
+async fn list_peers(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<Value> = params.parse().unwrap_or_default();
+    if !params.is_empty() {
+        let (filter, cursor) = page_params::<PeerFilter>(&params)?;
+        let r = processor
+            .list_peers_page(&filter, cursor.as_deref())
+            .await?;
+        return serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError));
+    }
+    let tags = processor.swarm.tags();
+    let mut stats = processor
+        .swarm
+        .transport_stats()
+        .await
+        .into_iter()
+        .collect::<HashMap<_, _>>();
     let r = processor
         .list_peers()
         .await?
         .into_iter()
-        .map(|x| x.into())
+        .map(|x| {
+            let did = x.address;
+            Peer::from(x)
+                .with_tags(tags.get(did))
+                .with_stats(stats.remove(&did))
+        })
         .collect::<Vec<Peer>>();
     serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
 }
 
+/// Params are `[filter, cursor]`, both optional. Without params ids of all pending
+/// transports are listed, otherwise one page of them.
+async fn list_pendings(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<Value> = params.parse().unwrap_or_default();
+    if !params.is_empty() {
+        let (filter, cursor) = page_params::<PeerFilter>(&params)?;
+        let r = processor
+            .list_pendings_page(&filter, cursor.as_deref())
+            .await?;
+        return serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError));
+    }
+    let r = processor
+        .list_pendings()
+        .await?
+        .iter()
+        .map(|t| t.id.to_string())
+        .collect::<Vec<_>>();
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+async fn node_info(_params: Params, processor: Processor) -> Result<Value> {
+    let r = processor.node_info().await;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+async fn drain(_params: Params, processor: Processor) -> Result<Value> {
+    processor.drain().await?;
+    Ok(serde_json::json!({}))
+}
+
+/// Params are `[record]`, signed by keys of both DIDs.
+async fn rotate_identity(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<RotationRecord> = params.parse()?;
+    let record = params
+        .first()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    processor.rotate_identity(record).await?;
+    Ok(serde_json::json!({}))
+}
+
+async fn stabilization_status(_params: Params, processor: Processor) -> Result<Value> {
+    let r = processor.stabilization_status();
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+async fn control_stabilization(params: Params, processor: Processor) -> Result<Value> {
+    let control: StabilizationControl = params.parse()?;
+    let r = processor.control_stabilization(control)?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+async fn repair_dht(_params: Params, processor: Processor) -> Result<Value> {
+    let r = processor.repair_dht().await?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+/// Params are `[cursor, limit]`, both optional.
+async fn list_local_data(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<Value> = params.parse().unwrap_or_default();
+    let arg = |i: usize| params.get(i).cloned().unwrap_or(Value::Null);
+    let cursor: Option<String> =
+        serde_json::from_value(arg(0)).map_err(|_| Error::new(ErrorCode::InvalidParams))?;
+    let limit: Option<usize> =
+        serde_json::from_value(arg(1)).map_err(|_| Error::new(ErrorCode::InvalidParams))?;
+    let r = processor.list_local_data(cursor.as_deref(), limit).await?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+async fn delete_local_data(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<String> = params.parse()?;
+    let key = params
+        .first()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let r = processor.delete_local_data(key).await?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+async fn captured_payloads(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<bool> = params.parse().unwrap_or_default();
+    let clear = params.first().copied().unwrap_or(false);
+    let r = processor.captured_payloads(clear)?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+async fn peer_traffic(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<String> = params.parse().unwrap_or_default();
+    let did = match params.first() {
+        Some(did) => Some(processor.resolve_did(did).await?),
+        None => None,
+    };
+    let r = processor.peer_traffic(did.as_deref())?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+async fn relay_usage(_params: Params, processor: Processor) -> Result<Value> {
+    let r = processor.relay_usage();
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+async fn export_state(_params: Params, processor: Processor) -> Result<Value> {
+    let r = processor.export_state().await?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+async fn import_state(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<StateSnapshot> = params.parse()?;
+    let snapshot = params
+        .first()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    processor.import_state(snapshot).await?;
+    Ok(serde_json::json!({}))
+}
+
+/// Params are `[filter, cursor]`, both optional.
+async fn list_messages(params: Params, processor: Processor) -> Result<Value> {
+    let mut params: Vec<Value> = params.parse().unwrap_or_default();
+    // sender of filter may be an ENS name
+    if let Some(sender) = params.get_mut(0).and_then(|f| f.get_mut("sender")) {
+        if let Some(name) = sender.as_str() {
+            let did = processor.resolve_did(name).await?;
+            *sender = Value::String(did);
+        }
+    }
+    let (filter, cursor) = page_params::<HistoryFilter>(&params)?;
+    let r = processor.list_messages(&filter, cursor.as_deref())?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+/// Wait for remote presence record up to 3 seconds.
+const QUERY_PRESENCE_TIMEOUT_MS: u64 = 3000;
+
+async fn query_presence(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<String> = params.parse()?;
+    let did = params
+        .first()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let did = processor.resolve_did(did).await?;
+    let r = processor
+        .query_presence(&did, QUERY_PRESENCE_TIMEOUT_MS)
+        .await?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+/// Returns all tracked DIDs with their last known state.
+async fn track_presence(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<String> = params.parse()?;
+    let did = params
+        .first()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    processor.track_presence(&processor.resolve_did(did).await?)?;
+    serde_json::to_value(processor.tracked_presence())
+        .map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+/// Returns all tracked DIDs with their last known state.
+async fn untrack_presence(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<String> = params.parse()?;
+    let did = params
+        .first()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    processor.untrack_presence(&processor.resolve_did(did).await?)?;
+    serde_json::to_value(processor.tracked_presence())
+        .map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+/// Wait for remote group membership up to 3 seconds.
+const FETCH_GROUP_TIMEOUT_MS: u64 = 3000;
+
+/// Params are `[name, members]`, members are optional.
+async fn create_group(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<Value> = params.parse()?;
+    let name: String = params
+        .first()
+        .cloned()
+        .and_then(|v| serde_json::from_value(v).ok())
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let members: Option<Vec<String>> =
+        serde_json::from_value(params.get(1).cloned().unwrap_or(Value::Null))
+            .map_err(|_| Error::new(ErrorCode::InvalidParams))?;
+    let mut resolved = vec![];
+    for m in members.unwrap_or_default().iter() {
+        resolved.push(processor.resolve_did(m).await?);
+    }
+    let record = processor
+        .create_group(&name, &resolved, FETCH_GROUP_TIMEOUT_MS)
+        .await?;
+    serde_json::to_value(GroupInfo::from(&record))
+        .map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+async fn rotate_group_key(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<String> = params.parse()?;
+    let name = params
+        .first()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let r = processor
+        .rotate_group_key(name, FETCH_GROUP_TIMEOUT_MS)
+        .await?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+async fn fetch_group_key(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<String> = params.parse()?;
+    let name = params
+        .first()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let r = processor
+        .fetch_group_key(name, FETCH_GROUP_TIMEOUT_MS)
+        .await?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+async fn send_to_group(params: Params, processor: Processor) -> Result<Value> {
+    let params: serde_json::Map<String, Value> = params.parse()?;
+    let group = params
+        .get("group")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let text = params
+        .get("text")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    // seconds to keep message in inboxes of offline members
+    let offline_ttl = params
+        .get("offline_ttl")
+        .and_then(|v| v.as_u64())
+        .map_or(DEFAULT_INBOX_TTL_MS, |ttl| ttl as u128 * 1000);
+    let r = processor
+        .send_to_group(group, text.as_bytes(), offline_ttl, FETCH_GROUP_TIMEOUT_MS)
+        .await?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+async fn fetch_group(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<String> = params.parse()?;
+    let name = params
+        .first()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let record = processor.fetch_group(name, FETCH_GROUP_TIMEOUT_MS).await?;
+    serde_json::to_value(GroupInfo::from(&record))
+        .map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+/// Wait for remote history of topic up to 3 seconds.
+const FETCH_TOPIC_TIMEOUT_MS: u64 = 3000;
+
+/// Params are `[name, data]`.
+async fn publish_topic(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<String> = params.parse()?;
+    let (name, data) = match params.as_slice() {
+        [name, data] => (name, data),
+        _ => return Err(Error::new(ErrorCode::InvalidParams)),
+    };
+    let record = processor
+        .publish_topic(name, data, FETCH_TOPIC_TIMEOUT_MS)
+        .await?;
+    serde_json::to_value(TopicRecordInfo::from(&record))
+        .map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+async fn fetch_topic(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<String> = params.parse()?;
+    let name = params
+        .first()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let log = processor.fetch_topic(name, FETCH_TOPIC_TIMEOUT_MS).await?;
+    serde_json::to_value(TopicInfo::new(name, &log))
+        .map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+/// Wait for each chunk of file, or shard of erasure coded value, up to 10 seconds.
+const FETCH_CHUNK_TIMEOUT_MS: u64 = 10000;
+
+async fn send_file(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<String> = params.parse()?;
+    let path = params
+        .first()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let r = processor.send_file(path).await?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+/// Params are `[id, output]`, output is optional.
+async fn fetch_file(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<Value> = params.parse()?;
+    let id = params
+        .first()
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let output = params.get(1).and_then(|v| v.as_str());
+    let r = processor
+        .fetch_file(id, output, FETCH_CHUNK_TIMEOUT_MS)
+        .await?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+/// Params are `[value, data_shards, parity_shards]`, value is in base64, shards are optional.
+async fn store_erasure(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<Value> = params.parse()?;
+    let value = params
+        .first()
+        .and_then(|v| v.as_str())
+        .and_then(|v| base64::decode(v).ok())
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let shards = |i: usize, default: usize| {
+        params
+            .get(i)
+            .and_then(|v| v.as_u64())
+            .map_or(default, |n| n as usize)
+    };
+    let r = processor
+        .store_erasure(
+            &value,
+            shards(1, DEFAULT_DATA_SHARDS),
+            shards(2, DEFAULT_PARITY_SHARDS),
+        )
+        .await?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+async fn fetch_erasure(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<String> = params.parse()?;
+    let id = params
+        .first()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let value = processor.fetch_erasure(id, FETCH_CHUNK_TIMEOUT_MS).await?;
+    serde_json::to_value(ErasureValue {
+        id: id.to_owned(),
+        value: base64::encode(value),
+    })
+    .map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+/// Returns names of all registered services.
+async fn register_service(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<String> = params.parse()?;
+    let name = params
+        .first()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    processor.register_service(name).await?;
+    serde_json::to_value(processor.registered_services())
+        .map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+/// Returns names of all registered services.
+async fn unregister_service(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<String> = params.parse()?;
+    let name = params
+        .first()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    processor.unregister_service(name);
+    serde_json::to_value(processor.registered_services())
+        .map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+/// Wait for remote service records up to 3 seconds.
+const RESOLVE_SERVICE_TIMEOUT_MS: u64 = 3000;
+
+async fn resolve_service(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<String> = params.parse()?;
+    let name = params
+        .first()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let r = processor
+        .resolve_service(name, RESOLVE_SERVICE_TIMEOUT_MS)
+        .await?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+async fn ens_resolve(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<String> = params.parse()?;
+    let name = params
+        .first()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let address = processor.ens_resolve(name).await?;
+    Ok(Value::String(format!("{:?}", address)))
+}
+
+async fn ens_reverse(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<String> = params.parse()?;
+    let address = params
+        .first()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    Ok(Value::String(processor.ens_reverse(address).await?))
+}
+
+/// Wait for remote manifest up to 3 seconds.
+const WHOIS_TIMEOUT_MS: u64 = 3000;
+
+async fn whois(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<String> = params.parse()?;
+    let did = params
+        .first()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let did = processor.resolve_did(did).await?;
+    let r = processor.whois(&did, WHOIS_TIMEOUT_MS).await?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+/// Params are `[did, key, value]`, tag is removed if value is null.
+async fn tag_peer(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<Value> = params.parse()?;
+    let arg = |i: usize| -> Result<Option<String>> {
+        serde_json::from_value(params.get(i).cloned().unwrap_or(Value::Null))
+            .map_err(|_| Error::new(ErrorCode::InvalidParams))
+    };
+    let did = arg(0)?.ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let key = arg(1)?.ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let value = arg(2)?;
+    let did = processor.resolve_did(&did).await?;
+    let r = processor.tag_peer(&did, &key, value.as_deref()).await?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+async fn list_known_peers(_params: Params, processor: Processor) -> Result<Value> {
+    let r = processor.list_known_peers();
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
 async fn close_connection(params: Params, processor: Processor) -> Result<Value> {
     let params: Vec<String> = params.parse()?;
     let address = params
         .first()
         .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
-    processor.disconnect(address).await?;
+    processor
+        .disconnect(&processor.resolve_did(address).await?)
+        .await?;
     Ok(serde_json::json!({}))
 }
 
@@ -109,11 +630,123 @@ async fn send_message(params: Params, processor: Processor) -> Result<Value> {
         .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?
         .as_str()
         .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let destination = &processor.resolve_did(destination).await?;
     let text = params
         .get("text")
         .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?
         .as_str()
         .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
-    processor.send_message(destination, text.as_bytes()).await?;
+    // seconds to keep message in inbox of destination, if it's offline
+    let offline_ttl = params.get("offline_ttl").and_then(|v| v.as_u64());
+    let encrypt = params.get("encrypt").and_then(|v| v.as_bool()) == Some(true);
+    if params.get("receipt").and_then(|v| v.as_bool()) == Some(true) {
+        // receipts are returned by destination online, of plain messages
+        if encrypt || offline_ttl.is_some() {
+            return Err(Error::new(ErrorCode::InvalidParams));
+        }
+        let tx_id = processor
+            .send_message_with_receipt(destination, text.as_bytes())
+            .await?;
+        return serde_json::to_value(SendToResult { tx_id: Some(tx_id) })
+            .map_err(|_| Error::from(ServerError::JsonSerializeError));
+    }
+    if encrypt {
+        let ttl_ms = offline_ttl.map(|ttl| ttl as u128 * 1000);
+        processor
+            .send_encrypted_message(destination, text.as_bytes(), ttl_ms)
+            .await?;
+        return Ok(serde_json::json!({}));
+    }
+    match offline_ttl {
+        Some(ttl) => {
+            processor
+                .send_message_or_store(destination, text.as_bytes(), ttl as u128 * 1000)
+                .await?
+        }
+        None => processor.send_message(destination, text.as_bytes()).await?,
+    }
     Ok(serde_json::json!({}))
 }
+
+/// Params are `[tx_id]`, returns null until the receipt arrives.
+async fn delivery_receipt(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<String> = params.parse()?;
+    let tx_id = params
+        .first()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    serde_json::to_value(processor.delivery_receipt(tx_id))
+        .map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+/// Wait for every visited node up to 3 seconds.
+const CRAWL_TIMEOUT_MS: u64 = 3000;
+/// Nodes visited by crawl, if params don't set it.
+const DEFAULT_CRAWL_MAX_NODES: usize = 64;
+
+/// Params are `[max_nodes, with_manifests]`, both optional.
+async fn crawl(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<Value> = params.parse().unwrap_or_default();
+    let arg = |i: usize| params.get(i).cloned().unwrap_or(Value::Null);
+    let max_nodes: Option<usize> =
+        serde_json::from_value(arg(0)).map_err(|_| Error::new(ErrorCode::InvalidParams))?;
+    let with_manifests: Option<bool> =
+        serde_json::from_value(arg(1)).map_err(|_| Error::new(ErrorCode::InvalidParams))?;
+    let r = processor
+        .crawl(
+            max_nodes.unwrap_or(DEFAULT_CRAWL_MAX_NODES),
+            CRAWL_TIMEOUT_MS,
+            with_manifests.unwrap_or(false),
+        )
+        .await?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+/// Bytes of each benchmark message, if params don't set it.
+const DEFAULT_BENCHMARK_SIZE: usize = 1024;
+/// Benchmark messages, if params don't set it.
+const DEFAULT_BENCHMARK_COUNT: usize = 100;
+/// Benchmark messages in flight, if params don't set it.
+const DEFAULT_BENCHMARK_CONCURRENCY: usize = 8;
+/// Benchmark stops if no echo arrives in 5 seconds.
+const BENCHMARK_TIMEOUT_MS: u64 = 5000;
+
+/// Params are `[did, size, count, concurrency]`, all but did are optional.
+async fn benchmark(params: Params, processor: Processor) -> Result<Value> {
+    let params: Vec<Value> = params.parse()?;
+    let arg = |i: usize| params.get(i).cloned().unwrap_or(Value::Null);
+    let did: String =
+        serde_json::from_value(arg(0)).map_err(|_| Error::new(ErrorCode::InvalidParams))?;
+    let size: Option<usize> =
+        serde_json::from_value(arg(1)).map_err(|_| Error::new(ErrorCode::InvalidParams))?;
+    let count: Option<usize> =
+        serde_json::from_value(arg(2)).map_err(|_| Error::new(ErrorCode::InvalidParams))?;
+    let concurrency: Option<usize> =
+        serde_json::from_value(arg(3)).map_err(|_| Error::new(ErrorCode::InvalidParams))?;
+    let did = processor.resolve_did(&did).await?;
+    let r = processor
+        .benchmark(
+            &did,
+            size.unwrap_or(DEFAULT_BENCHMARK_SIZE),
+            count.unwrap_or(DEFAULT_BENCHMARK_COUNT),
+            concurrency.unwrap_or(DEFAULT_BENCHMARK_CONCURRENCY),
+            BENCHMARK_TIMEOUT_MS,
+        )
+        .await?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+/// Params is a [FaultConfig], faults are only shown if params are not set.
+#[cfg(feature = "chaos")]
+async fn inject_faults(params: Params, processor: Processor) -> Result<Value> {
+    let config: Option<FaultConfig> = match params {
+        Params::None => None,
+        Params::Array(a) if a.is_empty() => None,
+        params => Some(params.parse()?),
+    };
+    let r = processor.inject_faults(config)?;
+    serde_json::to_value(&r).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+async fn discover(_params: Params, _processor: Processor) -> Result<Value> {
+    Ok(openrpc::document())
+}
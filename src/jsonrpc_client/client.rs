@@ -4,7 +4,14 @@
 //! Sample:
 //! let client = Simpleclient::new(reqwest::Client::default(), "http://localhost:5000");
 //! client.call_method("test", params);
+//!
+//! Typed requests of [super::typed] are sent by [SimpleClient::request].
+//!
+//! Connections are kept alive and pooled by the http client, so sequential calls to a node
+//! reuse one connection. Failed calls are retried by [RetryPolicy] if they are idempotent, or
+//! never reached the server.
 use std::sync::Arc;
+use std::time::Duration;
 
 use jsonrpc_core::Error;
 use jsonrpc_core::Params;
@@ -12,13 +19,103 @@ use jsonrpc_core::Value;
 
 use super::request::parse_response;
 use super::request::RequestBuilder;
+use super::typed::RpcRequest;
+use crate::jsonrpc::method::Method;
 use crate::prelude::reqwest::Client as HttpClient;
+use crate::prelude::rings_core::timer::sleep;
+
+/// Connection options of http client, ignored in browser, where fetch manages connections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpOptions {
+    /// Idle connections are closed after it.
+    pub pool_idle_timeout: Duration,
+    /// Idle connections kept for each host.
+    pub pool_max_idle_per_host: usize,
+    /// Interval of TCP keep-alive probes of connections.
+    pub tcp_keepalive: Duration,
+    /// Fail requests if connection is not established in it.
+    pub connect_timeout: Duration,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            pool_idle_timeout: Duration::from_secs(90),
+            pool_max_idle_per_host: 8,
+            tcp_keepalive: Duration::from_secs(60),
+            connect_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl HttpOptions {
+    /// Build a http client with these options, share it by clients to reuse connections.
+    /// Fails if TLS backend of client can't be initialized.
+    #[cfg(feature = "client")]
+    pub fn build(&self) -> RpcResult<HttpClient> {
+        HttpClient::builder()
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .tcp_keepalive(self.tcp_keepalive)
+            .connect_timeout(self.connect_timeout)
+            .build()
+            .map_err(|e| RpcError::Client(e.to_string()))
+    }
+
+    /// Build a http client with these options, share it by clients to reuse connections.
+    #[cfg(feature = "browser")]
+    pub fn build(&self) -> RpcResult<HttpClient> {
+        Ok(HttpClient::default())
+    }
+}
+
+/// How failed requests are retried, only transport errors and timeouts are retried.
+/// Requests of methods not [idempotent](Method::is_idempotent) are retried only if they
+/// failed to connect, which never reached the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt.
+    pub max_retries: usize,
+    /// Wait before the first retry, doubled for each next one.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            backoff: Duration::from_millis(200),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Whether a request failed by `e` is retried, `idempotent` if its method is.
+    pub fn should_retry(e: &RpcError, idempotent: bool) -> bool {
+        match e {
+            RpcError::Connect(_) => true,
+            RpcError::Client(_) | RpcError::Timeout => idempotent,
+            _ => false,
+        }
+    }
+}
+
+/// Whether method of `msg` is [idempotent](Method::is_idempotent), unknown methods are not.
+fn is_idempotent(msg: &RpcMessage) -> bool {
+    msg.method()
+        .and_then(|m| Method::try_from(m).ok())
+        .map_or(false, |m| m.is_idempotent())
+}
 
 /// SimpleClient
 #[derive(Clone)]
 pub struct SimpleClient {
     client: Arc<HttpClient>,
     url: String,
+    #[cfg_attr(feature = "browser", allow(dead_code))]
+    timeout: Option<Duration>,
+    retry: RetryPolicy,
+    admin_token: Option<String>,
 }
 
 impl SimpleClient {
@@ -29,16 +126,48 @@ impl SimpleClient {
         Self {
             client,
             url: url.to_owned(),
+            timeout: None,
+            retry: RetryPolicy::default(),
+            admin_token: None,
         }
     }
 
-    /// Create a new SimpleClient,
+    /// Create a new SimpleClient with a http client of `options`, it's shared by clones of the
+    /// returned client only.
     /// * url: remote jsonrpc_server url
-    pub fn new_with_url(url: &str) -> Self {
-        Self {
-            client: Arc::new(HttpClient::default()),
-            url: url.to_string(),
-        }
+    pub fn new_with_options(url: &str, options: &HttpOptions) -> RpcResult<Self> {
+        Ok(Self::new(Arc::new(options.build()?), url))
+    }
+
+    /// Create a new SimpleClient with a http client of default [HttpOptions], see
+    /// [SimpleClient::new_with_options].
+    pub fn new_with_url(url: &str) -> RpcResult<Self> {
+        Self::new_with_options(url, &HttpOptions::default())
+    }
+
+    /// Fail requests not finished in `timeout` with [RpcError::Timeout].
+    /// It's ignored in browser, where fetch is never timed out by client.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Retry failed requests with `retry`.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Present `token` as bearer of requests, for admin methods of node.
+    pub fn with_admin_token(mut self, token: &str) -> Self {
+        self.admin_token = Some(token.to_owned());
+        self
+    }
+
+    /// Send a typed request, see [super::typed].
+    pub async fn request<R: RpcRequest>(&self, req: &R) -> RpcResult<R::Response> {
+        let value = self.call_method(R::METHOD.as_str(), req.params()).await?;
+        serde_json::from_value(value).map_err(|e| RpcError::ParseError(e.to_string(), Box::new(e)))
     }
 
     /// JSONRpc call_method
@@ -61,6 +190,24 @@ impl SimpleClient {
     }
 
     async fn do_request(&self, msg: &RpcMessage) -> RpcResult<Value> {
+        let idempotent = is_idempotent(msg);
+        let mut backoff = self.retry.backoff;
+        let mut retries = 0;
+        loop {
+            let retry = retries < self.retry.max_retries;
+            match self.do_request_once(msg).await {
+                Err(e) if retry && RetryPolicy::should_retry(&e, idempotent) => {
+                    tracing::debug!(url = %self.url, retries, "retry request: {}", e);
+                }
+                r => return r,
+            }
+            sleep(backoff).await;
+            backoff *= 2;
+            retries += 1;
+        }
+    }
+
+    async fn do_request_once(&self, msg: &RpcMessage) -> RpcResult<Value> {
         let mut request_builder = RequestBuilder::new();
         let request = match msg {
             RpcMessage::Call(call) => request_builder.call_request(call).1,
@@ -72,9 +219,16 @@ impl SimpleClient {
             }
         };
 
-        let resp = self
-            .client
-            .post(self.url.as_str())
+        #[allow(unused_mut)]
+        let mut builder = self.client.post(self.url.as_str());
+        #[cfg(feature = "client")]
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(token) = &self.admin_token {
+            builder = builder.bearer_auth(token);
+        }
+        let resp = builder
             .header(
                 http::header::CONTENT_TYPE,
                 http::header::HeaderValue::from_static("application/json"),
@@ -86,14 +240,15 @@ impl SimpleClient {
             .body(request)
             .send()
             .await
-            .map_err(|e| RpcError::Client(e.to_string()))?;
-        let resp = resp
-            .error_for_status()
-            .map_err(|e| RpcError::Client(e.to_string()))?;
-        let resp = resp
-            .bytes()
-            .await
-            .map_err(|e| RpcError::ParseError(e.to_string(), Box::new(e)))?;
+            .map_err(client_error)?;
+        let resp = resp.error_for_status().map_err(client_error)?;
+        let resp = resp.bytes().await.map_err(|e| {
+            if e.is_timeout() {
+                RpcError::Timeout
+            } else {
+                RpcError::ParseError(e.to_string(), Box::new(e))
+            }
+        })?;
         let resp_str = String::from_utf8_lossy(&resp).into_owned();
         parse_response(&resp_str)
             .map_err(|e| RpcError::ParseError(e.to_string(), Box::new(e)))?
@@ -101,6 +256,17 @@ impl SimpleClient {
     }
 }
 
+fn client_error(e: crate::prelude::reqwest::Error) -> RpcError {
+    if e.is_timeout() {
+        return RpcError::Timeout;
+    }
+    #[cfg(feature = "client")]
+    if e.is_connect() {
+        return RpcError::Connect(e.to_string());
+    }
+    RpcError::Client(e.to_string())
+}
+
 /// The errors returned by the client.
 #[derive(Debug, thiserror::Error)]
 pub enum RpcError {
@@ -113,6 +279,9 @@ pub enum RpcError {
     /// Request timed out.
     #[error("Request timed out")]
     Timeout,
+    /// Failed to connect, request is never sent.
+    #[error("Failed to connect: {0}")]
+    Connect(String),
     /// A general client error.
     #[error("Client error: {0}")]
     Client(String),
@@ -174,6 +343,17 @@ pub enum RpcMessage {
     Subscribe(SubscribeMessage),
 }
 
+impl RpcMessage {
+    /// Name of method called, None for subscriptions.
+    pub fn method(&self) -> Option<&str> {
+        match self {
+            RpcMessage::Call(call) => Some(&call.method),
+            RpcMessage::Notify(notify) => Some(&notify.method),
+            RpcMessage::Subscribe(_) => None,
+        }
+    }
+}
+
 impl From<CallMessage> for RpcMessage {
     fn from(msg: CallMessage) -> Self {
         RpcMessage::Call(msg)
@@ -191,3 +371,37 @@ impl From<SubscribeMessage> for RpcMessage {
         RpcMessage::Subscribe(msg)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn call(method: &str) -> RpcMessage {
+        RpcMessage::Call(CallMessage {
+            method: method.to_owned(),
+            params: Params::None,
+        })
+    }
+
+    #[test]
+    fn test_retry_classification() {
+        assert!(is_idempotent(&call(Method::ListPeers.as_str())));
+        assert!(!is_idempotent(&call(Method::AnswerOffer.as_str())));
+        assert!(!is_idempotent(&call(Method::FetchFile.as_str())));
+        #[cfg(feature = "chaos")]
+        assert!(!is_idempotent(&call(Method::InjectFaults.as_str())));
+        assert!(!is_idempotent(&call("noSuchMethod")));
+
+        // a request never reaching node is always retried
+        let connect = RpcError::Connect("refused".to_owned());
+        assert!(RetryPolicy::should_retry(&connect, false));
+        // one which may have reached node is retried only if it's idempotent
+        for e in [RpcError::Timeout, RpcError::Client("reset".to_owned())] {
+            assert!(RetryPolicy::should_retry(&e, true));
+            assert!(!RetryPolicy::should_retry(&e, false));
+        }
+        // answers of node are never retried
+        let answered = RpcError::JsonRpcError(Error::method_not_found());
+        assert!(!RetryPolicy::should_retry(&answered, true));
+    }
+}
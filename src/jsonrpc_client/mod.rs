@@ -1,5 +1,9 @@
 ///! JSONRpc client
 pub mod client;
 pub mod request;
+pub mod typed;
 
+pub use self::client::HttpOptions;
+pub use self::client::RetryPolicy;
 pub use self::client::SimpleClient;
+pub use self::typed::RpcRequest;
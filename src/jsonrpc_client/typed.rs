@@ -0,0 +1,718 @@
+#![warn(missing_docs)]
+//! Typed requests of every [Method], sent by [SimpleClient::request].
+//!
+//! Sample:
+//! let client = SimpleClient::new_with_url("http://localhost:50000")?;
+//! let info: ManifestInfo = client.request(&WhoisRequest { did: did.into() }).await?;
+//!
+//! [SimpleClient::request]: super::SimpleClient::request
+use std::collections::BTreeMap;
+
+use jsonrpc_core::Params;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::jsonrpc::method::Method;
+use crate::jsonrpc::response::BenchmarkReport;
+use crate::jsonrpc::response::CrawlReport;
+use crate::jsonrpc::response::ErasureInfo;
+use crate::jsonrpc::response::ErasureValue;
+use crate::jsonrpc::response::FileInfo;
+use crate::jsonrpc::response::GroupInfo;
+use crate::jsonrpc::response::GroupKeyInfo;
+use crate::jsonrpc::response::GroupSendResult;
+use crate::jsonrpc::response::KnownPeerInfo;
+use crate::jsonrpc::response::LocalData;
+use crate::jsonrpc::response::LocalDataPage;
+use crate::jsonrpc::response::ManifestInfo;
+use crate::jsonrpc::response::NodeInfo;
+use crate::jsonrpc::response::Peer;
+use crate::jsonrpc::response::PeerPage;
+use crate::jsonrpc::response::PendingPage;
+use crate::jsonrpc::response::PresenceInfo;
+use crate::jsonrpc::response::SendToResult;
+use crate::jsonrpc::response::ServiceProvider;
+use crate::jsonrpc::response::StateSnapshot;
+use crate::jsonrpc::response::TopicInfo;
+use crate::jsonrpc::response::TopicRecordInfo;
+use crate::jsonrpc::response::TransportAndIce;
+use crate::prelude::rings_core::accounting::RelayUsage;
+use crate::prelude::rings_core::capture::CapturedPayload;
+#[cfg(feature = "chaos")]
+use crate::prelude::rings_core::chaos::FaultConfig;
+use crate::prelude::rings_core::dht::RepairReport;
+use crate::prelude::rings_core::dht::StabilizationStatus;
+use crate::prelude::rings_core::file::TransferProgress;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::history::HistoryFilter;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::history::HistoryPage;
+use crate::prelude::rings_core::message::DeliveryReceipt;
+use crate::prelude::rings_core::rotation::RotationRecord;
+use crate::prelude::rings_core::traffic::PeerTrafficStats;
+use crate::processor::PeerFilter;
+use crate::processor::StabilizationControl;
+
+/// A request of a JSON-RPC method, with type of its result.
+pub trait RpcRequest {
+    /// Method called by request.
+    const METHOD: Method;
+    /// Result of method.
+    type Response: DeserializeOwned;
+    /// Params sent with method.
+    fn params(&self) -> Params;
+}
+
+/// Result of methods which return nothing, it's `{}` on wire.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct EmptyResponse {}
+
+macro_rules! impl_request {
+    ($request:ty, $method:ident, $response:ty, |$s:ident| $params:expr) => {
+        impl RpcRequest for $request {
+            const METHOD: Method = Method::$method;
+            type Response = $response;
+
+            fn params(&self) -> Params {
+                let $s = self;
+                $params
+            }
+        }
+    };
+    ($request:ty, $method:ident, $response:ty) => {
+        impl_request!($request, $method, $response, |_s| Params::Array(vec![]));
+    };
+}
+
+/// Connect peer with url of its jsonrpc server, returns id of transport.
+#[derive(Debug, Clone)]
+pub struct ConnectPeerViaHttpRequest {
+    /// url of remote jsonrpc server
+    pub url: String,
+}
+impl_request!(ConnectPeerViaHttpRequest, ConnectPeerViaHttp, String, |s| {
+    Params::Array(vec![json!(s.url)])
+});
+
+/// Connect peer with its address, DID or ENS name.
+#[derive(Debug, Clone)]
+pub struct ConnectWithAddressRequest {
+    /// address, DID or ENS name of peer
+    pub address: String,
+}
+impl_request!(ConnectWithAddressRequest, ConnectWithAddress, (), |s| {
+    Params::Array(vec![json!(s.address)])
+});
+
+/// List connected peers.
+#[derive(Debug, Clone, Default)]
+pub struct ListPeersRequest;
+impl_request!(ListPeersRequest, ListPeers, Vec<Peer>);
+
+/// List connected peers page by page.
+#[derive(Debug, Clone, Default)]
+pub struct ListPeersPageRequest {
+    /// conditions of peers
+    pub filter: PeerFilter,
+    /// `next_cursor` of previous page
+    pub cursor: Option<String>,
+}
+impl_request!(ListPeersPageRequest, ListPeers, PeerPage, |s| {
+    Params::Array(vec![json!(s.filter), json!(s.cursor)])
+});
+
+/// Create offer for manual handshake.
+#[derive(Debug, Clone, Default)]
+pub struct CreateOfferRequest;
+impl_request!(CreateOfferRequest, CreateOffer, TransportAndIce);
+
+/// Answer offer for manual handshake.
+#[derive(Debug, Clone)]
+pub struct AnswerOfferRequest {
+    /// handshake info of offer
+    pub ice_info: String,
+}
+impl_request!(AnswerOfferRequest, AnswerOffer, TransportAndIce, |s| {
+    Params::Array(vec![json!(s.ice_info)])
+});
+
+/// Issue a nonce to be signed in offer sent by [AnswerOfferRequest], used once only.
+#[derive(Debug, Clone, Default)]
+pub struct HandshakeNonceRequest;
+impl_request!(HandshakeNonceRequest, HandshakeNonce, String);
+
+/// Accept answer for manual handshake.
+#[derive(Debug, Clone)]
+pub struct AcceptAnswerRequest {
+    /// id of transport created by offer
+    pub transport_id: String,
+    /// handshake info of answer
+    pub ice: String,
+}
+impl_request!(AcceptAnswerRequest, AcceptAnswer, Peer, |s| {
+    Params::Array(vec![json!(s.transport_id), json!(s.ice)])
+});
+
+/// Send custom message to peer.
+#[derive(Debug, Clone)]
+pub struct SendToRequest {
+    /// address, DID or ENS name of peer
+    pub destination: String,
+    /// text of message
+    pub text: String,
+    /// Seconds to keep message in inbox of destination, if it's offline.
+    pub offline_ttl: Option<u64>,
+    /// Encrypt message to public key which destination published to DHT.
+    pub encrypt: bool,
+    /// Ask destination for a receipt, see [DeliveryReceiptRequest]. It's not stored in inbox
+    /// or encrypted then.
+    pub receipt: bool,
+}
+impl_request!(SendToRequest, SendTo, SendToResult, |s| {
+    let mut params = serde_json::Map::new();
+    params.insert("destination".to_owned(), json!(s.destination));
+    params.insert("text".to_owned(), json!(s.text));
+    if let Some(ttl) = s.offline_ttl {
+        params.insert("offline_ttl".to_owned(), json!(ttl));
+    }
+    if s.encrypt {
+        params.insert("encrypt".to_owned(), json!(true));
+    }
+    if s.receipt {
+        params.insert("receipt".to_owned(), json!(true));
+    }
+    Params::Map(params)
+});
+
+/// Fetch receipt of a message sent with receipt, with latency of each hop, None until it
+/// arrives.
+#[derive(Debug, Clone)]
+pub struct DeliveryReceiptRequest {
+    /// `tx_id` of message returned by [SendToRequest]
+    pub tx_id: String,
+}
+impl_request!(
+    DeliveryReceiptRequest,
+    DeliveryReceipt,
+    Option<DeliveryReceipt>,
+    |s| { Params::Array(vec![json!(s.tx_id)]) }
+);
+
+/// Disconnect peer.
+#[derive(Debug, Clone)]
+pub struct DisconnectRequest {
+    /// address, DID or ENS name of peer
+    pub address: String,
+}
+impl_request!(DisconnectRequest, Disconnect, EmptyResponse, |s| {
+    Params::Array(vec![json!(s.address)])
+});
+
+/// List pending transports.
+#[derive(Debug, Clone, Default)]
+pub struct ListPendingsRequest;
+impl_request!(ListPendingsRequest, ListPendings, Vec<String>);
+
+/// List ids of pending transports page by page.
+#[derive(Debug, Clone, Default)]
+pub struct ListPendingsPageRequest {
+    /// conditions of transports
+    pub filter: PeerFilter,
+    /// `next_cursor` of previous page
+    pub cursor: Option<String>,
+}
+impl_request!(ListPendingsPageRequest, ListPendings, PendingPage, |s| {
+    Params::Array(vec![json!(s.filter), json!(s.cursor)])
+});
+
+/// Close pending transport.
+#[derive(Debug, Clone)]
+pub struct ClosePendingTransportRequest {
+    /// id of pending transport
+    pub transport_id: String,
+}
+impl_request!(
+    ClosePendingTransportRequest,
+    ClosePendingTransport,
+    EmptyResponse,
+    |s| Params::Array(vec![json!(s.transport_id)])
+);
+
+/// Report version and network of node.
+#[derive(Debug, Clone, Default)]
+pub struct NodeInfoRequest;
+impl_request!(NodeInfoRequest, NodeInfo, NodeInfo);
+
+/// Leave ring gracefully and stop node.
+#[derive(Debug, Clone, Default)]
+pub struct DrainRequest;
+impl_request!(DrainRequest, Drain, EmptyResponse);
+
+/// Publish rotation of identity of node to a new DID.
+#[derive(Debug, Clone)]
+pub struct RotateIdentityRequest {
+    /// record signed by keys of node and the new DID
+    pub record: RotationRecord,
+}
+impl_request!(RotateIdentityRequest, RotateIdentity, EmptyResponse, |s| {
+    Params::Array(vec![json!(s.record)])
+});
+
+/// List payloads recorded by packet capture.
+#[derive(Debug, Clone, Default)]
+pub struct CapturedPayloadsRequest {
+    /// clear records after listing them
+    pub clear: bool,
+}
+impl_request!(
+    CapturedPayloadsRequest,
+    CapturedPayloads,
+    Vec<CapturedPayload>,
+    |s| Params::Array(vec![json!(s.clear)])
+);
+
+/// Report traffic with each peer, or with one of them.
+#[derive(Debug, Clone, Default)]
+pub struct PeerTrafficRequest {
+    /// only this peer, if it's set
+    pub did: Option<String>,
+}
+impl_request!(
+    PeerTrafficRequest,
+    PeerTraffic,
+    Vec<PeerTrafficStats>,
+    |s| match &s.did {
+        Some(did) => Params::Array(vec![json!(did)]),
+        None => Params::Array(vec![]),
+    }
+);
+
+/// Report messages and bytes relayed for each peer in current period.
+#[derive(Debug, Clone, Default)]
+pub struct RelayUsageRequest;
+impl_request!(RelayUsageRequest, RelayUsage, Vec<RelayUsage>);
+
+/// Export DHT and peers as a snapshot.
+#[derive(Debug, Clone, Default)]
+pub struct ExportStateRequest;
+impl_request!(ExportStateRequest, ExportState, StateSnapshot);
+
+/// Load DHT of a snapshot.
+#[derive(Debug, Clone)]
+pub struct ImportStateRequest {
+    /// snapshot exported by `exportState`
+    pub snapshot: StateSnapshot,
+}
+impl_request!(ImportStateRequest, ImportState, EmptyResponse, |s| {
+    Params::Array(vec![json!(s.snapshot)])
+});
+
+/// List received custom messages, page by page.
+#[cfg(feature = "client")]
+#[derive(Debug, Clone, Default)]
+pub struct ListMessagesRequest {
+    /// conditions of messages
+    pub filter: HistoryFilter,
+    /// `next_cursor` of previous page
+    pub cursor: Option<String>,
+}
+#[cfg(feature = "client")]
+impl_request!(ListMessagesRequest, ListMessages, HistoryPage, |s| {
+    Params::Array(vec![json!(s.filter), json!(s.cursor)])
+});
+
+/// Query presence of a DID.
+#[derive(Debug, Clone)]
+pub struct QueryPresenceRequest {
+    /// DID or ENS name
+    pub did: String,
+}
+impl_request!(QueryPresenceRequest, QueryPresence, PresenceInfo, |s| {
+    Params::Array(vec![json!(s.did)])
+});
+
+/// Track presence of a DID, returns all tracked DIDs with their last known state.
+#[derive(Debug, Clone)]
+pub struct TrackPresenceRequest {
+    /// DID or ENS name
+    pub did: String,
+}
+impl_request!(
+    TrackPresenceRequest,
+    TrackPresence,
+    Vec<(String, bool)>,
+    |s| Params::Array(vec![json!(s.did)])
+);
+
+/// Stop tracking presence of a DID, returns all tracked DIDs with their last known state.
+#[derive(Debug, Clone)]
+pub struct UntrackPresenceRequest {
+    /// DID or ENS name
+    pub did: String,
+}
+impl_request!(
+    UntrackPresenceRequest,
+    UntrackPresence,
+    Vec<(String, bool)>,
+    |s| Params::Array(vec![json!(s.did)])
+);
+
+/// Create a group, or replace its members.
+#[derive(Debug, Clone)]
+pub struct CreateGroupRequest {
+    /// name of group
+    pub name: String,
+    /// DIDs or ENS names of members
+    pub members: Vec<String>,
+}
+impl_request!(CreateGroupRequest, CreateGroup, GroupInfo, |s| {
+    Params::Array(vec![json!(s.name), json!(s.members)])
+});
+
+/// Send message to members of a group.
+#[derive(Debug, Clone)]
+pub struct SendToGroupRequest {
+    /// name of group
+    pub group: String,
+    /// text of message
+    pub text: String,
+    /// Seconds to keep message in inboxes of offline members.
+    pub offline_ttl: Option<u64>,
+}
+impl_request!(SendToGroupRequest, SendToGroup, GroupSendResult, |s| {
+    let mut params = serde_json::Map::new();
+    params.insert("group".to_owned(), json!(s.group));
+    params.insert("text".to_owned(), json!(s.text));
+    if let Some(ttl) = s.offline_ttl {
+        params.insert("offline_ttl".to_owned(), json!(ttl));
+    }
+    Params::Map(params)
+});
+
+/// Fetch members of a group.
+#[derive(Debug, Clone)]
+pub struct FetchGroupRequest {
+    /// name of group
+    pub name: String,
+}
+impl_request!(FetchGroupRequest, FetchGroup, GroupInfo, |s| {
+    Params::Array(vec![json!(s.name)])
+});
+
+/// Establish a new key of a group this node is admin of.
+#[derive(Debug, Clone)]
+pub struct RotateGroupKeyRequest {
+    /// name of group
+    pub name: String,
+}
+impl_request!(RotateGroupKeyRequest, RotateGroupKey, GroupKeyInfo, |s| {
+    Params::Array(vec![json!(s.name)])
+});
+
+/// Fetch key of a group shared with this node.
+#[derive(Debug, Clone)]
+pub struct FetchGroupKeyRequest {
+    /// name of group
+    pub name: String,
+}
+impl_request!(FetchGroupKeyRequest, FetchGroupKey, GroupKeyInfo, |s| {
+    Params::Array(vec![json!(s.name)])
+});
+
+/// Append a record to a topic.
+#[derive(Debug, Clone)]
+pub struct PublishTopicRequest {
+    /// name of topic
+    pub name: String,
+    /// data of record
+    pub data: String,
+}
+impl_request!(PublishTopicRequest, PublishTopic, TopicRecordInfo, |s| {
+    Params::Array(vec![json!(s.name), json!(s.data)])
+});
+
+/// Fetch history of a topic.
+#[derive(Debug, Clone)]
+pub struct FetchTopicRequest {
+    /// name of topic
+    pub name: String,
+}
+impl_request!(FetchTopicRequest, FetchTopic, TopicInfo, |s| {
+    Params::Array(vec![json!(s.name)])
+});
+
+/// Store a file on DHT.
+#[derive(Debug, Clone)]
+pub struct SendFileRequest {
+    /// path of file on node
+    pub path: String,
+}
+impl_request!(SendFileRequest, SendFile, FileInfo, |s| {
+    Params::Array(vec![json!(s.path)])
+});
+
+/// Fetch a file from DHT.
+#[derive(Debug, Clone)]
+pub struct FetchFileRequest {
+    /// id of file
+    pub id: String,
+    /// path to write file on node
+    pub output: Option<String>,
+}
+impl_request!(FetchFileRequest, FetchFile, TransferProgress, |s| {
+    Params::Array(vec![json!(s.id), json!(s.output)])
+});
+
+/// Store a value erasure coded on DHT.
+#[derive(Debug, Clone)]
+pub struct StoreErasureRequest {
+    /// value in base64
+    pub value: String,
+    /// data shards, 4 if it's None
+    pub data_shards: Option<usize>,
+    /// parity shards, 2 if it's None
+    pub parity_shards: Option<usize>,
+}
+impl_request!(StoreErasureRequest, StoreErasure, ErasureInfo, |s| {
+    Params::Array(vec![
+        json!(s.value),
+        json!(s.data_shards),
+        json!(s.parity_shards),
+    ])
+});
+
+/// Fetch a value erasure coded on DHT.
+#[derive(Debug, Clone)]
+pub struct FetchErasureRequest {
+    /// id of value
+    pub id: String,
+}
+impl_request!(FetchErasureRequest, FetchErasure, ErasureValue, |s| {
+    Params::Array(vec![json!(s.id)])
+});
+
+/// Provide a service, returns names of all registered services.
+#[derive(Debug, Clone)]
+pub struct RegisterServiceRequest {
+    /// name of service
+    pub name: String,
+}
+impl_request!(RegisterServiceRequest, RegisterService, Vec<String>, |s| {
+    Params::Array(vec![json!(s.name)])
+});
+
+/// Stop providing a service, returns names of all registered services.
+#[derive(Debug, Clone)]
+pub struct UnregisterServiceRequest {
+    /// name of service
+    pub name: String,
+}
+impl_request!(
+    UnregisterServiceRequest,
+    UnregisterService,
+    Vec<String>,
+    |s| Params::Array(vec![json!(s.name)])
+);
+
+/// Find providers of a service.
+#[derive(Debug, Clone)]
+pub struct ResolveServiceRequest {
+    /// name of service
+    pub name: String,
+}
+impl_request!(
+    ResolveServiceRequest,
+    ResolveService,
+    Vec<ServiceProvider>,
+    |s| Params::Array(vec![json!(s.name)])
+);
+
+/// Resolve an ENS name to address.
+#[derive(Debug, Clone)]
+pub struct EnsResolveRequest {
+    /// ENS name
+    pub name: String,
+}
+impl_request!(EnsResolveRequest, EnsResolve, String, |s| {
+    Params::Array(vec![json!(s.name)])
+});
+
+/// Look up verified ENS name of an address.
+#[derive(Debug, Clone)]
+pub struct EnsReverseRequest {
+    /// address of peer
+    pub address: String,
+}
+impl_request!(EnsReverseRequest, EnsReverse, String, |s| {
+    Params::Array(vec![json!(s.address)])
+});
+
+/// Fetch verified manifest of a node.
+#[derive(Debug, Clone)]
+pub struct WhoisRequest {
+    /// DID or ENS name
+    pub did: String,
+}
+impl_request!(WhoisRequest, Whois, ManifestInfo, |s| {
+    Params::Array(vec![json!(s.did)])
+});
+
+/// Set or remove a local tag of peer, returns all tags of the peer.
+#[derive(Debug, Clone)]
+pub struct TagPeerRequest {
+    /// DID or ENS name
+    pub did: String,
+    /// key of tag
+    pub key: String,
+    /// tag is removed if it's None
+    pub value: Option<String>,
+}
+impl_request!(TagPeerRequest, TagPeer, BTreeMap<String, String>, |s| {
+    Params::Array(vec![json!(s.did), json!(s.key), json!(s.value)])
+});
+
+/// List peers seen, with public keys of their first contact.
+#[derive(Debug, Clone, Default)]
+pub struct ListKnownPeersRequest;
+impl_request!(ListKnownPeersRequest, ListKnownPeers, Vec<KnownPeerInfo>);
+
+/// Walk the ring, collecting neighbours and liveness of nodes.
+#[derive(Debug, Clone, Default)]
+pub struct CrawlRequest {
+    /// nodes visited at most, default of node if it's None
+    pub max_nodes: Option<usize>,
+    /// fetch manifests of visited nodes
+    pub with_manifests: bool,
+}
+impl_request!(CrawlRequest, Crawl, CrawlReport, |s| {
+    Params::Array(vec![json!(s.max_nodes), json!(s.with_manifests)])
+});
+
+/// Measure throughput and latency of echoes of a peer in echo mode.
+#[derive(Debug, Clone, Default)]
+pub struct BenchmarkRequest {
+    /// peer in echo mode
+    pub did: String,
+    /// bytes of each message, default of node if it's None, capped by node
+    pub size: Option<usize>,
+    /// messages sent, default of node if it's None, capped by node
+    pub count: Option<usize>,
+    /// messages in flight, default of node if it's None
+    pub concurrency: Option<usize>,
+}
+impl_request!(BenchmarkRequest, Benchmark, BenchmarkReport, |s| {
+    Params::Array(vec![
+        json!(s.did),
+        json!(s.size),
+        json!(s.count),
+        json!(s.concurrency),
+    ])
+});
+
+/// Set faults injected by node, or show them if `config` is None.
+#[cfg(feature = "chaos")]
+#[derive(Debug, Clone, Default)]
+pub struct InjectFaultsRequest {
+    /// faults to inject
+    pub config: Option<FaultConfig>,
+}
+#[cfg(feature = "chaos")]
+impl_request!(InjectFaultsRequest, InjectFaults, FaultConfig, |s| {
+    match &s.config {
+        Some(c) => serde_json::from_value(json!(c)).unwrap_or(Params::None),
+        None => Params::None,
+    }
+});
+
+/// Show state of stabilization.
+#[derive(Debug, Clone, Default)]
+pub struct StabilizationStatusRequest;
+impl_request!(
+    StabilizationStatusRequest,
+    StabilizationStatus,
+    StabilizationStatus
+);
+
+/// Pause, resume or trigger stabilization, or change its interval.
+#[derive(Debug, Clone)]
+pub struct ControlStabilizationRequest {
+    /// control
+    pub control: StabilizationControl,
+}
+impl_request!(
+    ControlStabilizationRequest,
+    ControlStabilization,
+    StabilizationStatus,
+    |s| serde_json::from_value(json!(s.control)).unwrap_or(Params::None)
+);
+
+/// Verify successors, fingers and stored data of DHT now.
+#[derive(Debug, Clone, Default)]
+pub struct RepairDhtRequest;
+impl_request!(RepairDhtRequest, RepairDht, RepairReport);
+
+/// List virtual nodes stored by node page by page.
+#[derive(Debug, Clone, Default)]
+pub struct ListLocalDataRequest {
+    /// `next_cursor` of previous page
+    pub cursor: Option<String>,
+    /// items in one page, default of node if it's None
+    pub limit: Option<usize>,
+}
+impl_request!(ListLocalDataRequest, ListLocalData, LocalDataPage, |s| {
+    Params::Array(vec![json!(s.cursor), json!(s.limit)])
+});
+
+/// Delete a virtual node stored by node.
+#[derive(Debug, Clone)]
+pub struct DeleteLocalDataRequest {
+    /// address of virtual node
+    pub key: String,
+}
+impl_request!(DeleteLocalDataRequest, DeleteLocalData, LocalData, |s| {
+    Params::Array(vec![json!(s.key)])
+});
+
+/// Describe all methods as an OpenRPC document.
+#[derive(Debug, Clone, Default)]
+pub struct DiscoverRequest;
+impl_request!(DiscoverRequest, Discover, serde_json::Value);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_request_params() {
+        let req = SendToRequest {
+            destination: "0x11E807fcc88dD319270493fB2e822e388Fe36ab0".to_owned(),
+            text: "hello".to_owned(),
+            offline_ttl: None,
+            encrypt: true,
+            receipt: false,
+        };
+        assert_eq!(SendToRequest::METHOD.as_str(), "sendTo");
+        let params: serde_json::Map<String, serde_json::Value> = req.params().parse().unwrap();
+        assert_eq!(params.get("text"), Some(&json!("hello")));
+        assert!(params.get("offline_ttl").is_none());
+        assert_eq!(params.get("encrypt"), Some(&json!(true)));
+        assert!(params.get("receipt").is_none());
+
+        let req = ControlStabilizationRequest {
+            control: StabilizationControl::Interval { min: 1, max: 10 },
+        };
+        let control: StabilizationControl = req.params().parse().unwrap();
+        assert_eq!(control, req.control);
+
+        let params: Vec<String> = WhoisRequest {
+            did: "alice.eth".to_owned(),
+        }
+        .params()
+        .parse()
+        .unwrap();
+        assert_eq!(params, vec!["alice.eth".to_owned()]);
+        assert!(serde_json::from_value::<EmptyResponse>(json!({})).is_ok());
+    }
+}
@@ -138,6 +138,12 @@
 pub mod browser;
 #[cfg(feature = "client")]
 pub mod cli;
+#[cfg(feature = "client")]
+pub mod config;
+#[cfg(feature = "client")]
+pub mod doctor;
+#[cfg(feature = "client")]
+pub mod ens;
 pub mod error;
 #[cfg(feature = "client")]
 pub mod ethereum;
@@ -145,7 +151,16 @@ pub mod jsonrpc;
 pub mod jsonrpc_client;
 #[cfg(feature = "client")]
 pub mod logger;
+#[cfg(feature = "client")]
+pub mod node;
 pub mod prelude;
 pub mod processor;
 #[cfg(feature = "client")]
 pub mod service;
+
+#[cfg(feature = "client")]
+pub use node::run;
+#[cfg(feature = "client")]
+pub use node::NodeHandle;
+#[cfg(feature = "client")]
+pub use node::NodeSet;
@@ -1,36 +1,113 @@
-use chrono::Local;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
 use clap::ArgEnum;
-use log::Level;
 use log::LevelFilter;
-use log::Log;
-use log::Metadata;
-use log::Record;
-use log::SetLoggerError;
-
-pub struct Logger;
-
-impl Log for Logger {
-    fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= Level::Trace
-    }
-
-    fn log(&self, record: &Record) {
-        if self.enabled(record.metadata()) {
-            println!(
-                "{} [{}] - {}",
-                Local::now().format("%Y-%m-%dT%H:%M:%S"),
-                record.level(),
-                record.args()
-            );
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::EnvFilter;
+
+/// Log file rotated when it grows over `max_size` bytes, a writer of [init_tracing_with_writer].
+/// Rotated files are renamed as `<path>.1`, `<path>.2`, ..., at most `max_files` are kept.
+pub struct RotatingFileWriter {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: Mutex<(File, u64)>,
+}
+
+impl RotatingFileWriter {
+    pub fn new<P: Into<PathBuf>>(
+        path: P,
+        max_size: u64,
+        max_files: usize,
+    ) -> std::io::Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_size,
+            max_files,
+            file: Mutex::new((file, size)),
+        })
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut p = self.path.clone().into_os_string();
+        p.push(format!(".{}", n));
+        p.into()
+    }
+
+    fn rotate(&self, file: &mut File) -> std::io::Result<()> {
+        file.flush()?;
+        if self.max_files == 0 {
+            *file = File::create(&self.path)?;
+            return Ok(());
+        }
+        let _ = fs::remove_file(self.rotated_path(self.max_files));
+        for n in (1..self.max_files).rev() {
+            let from = self.rotated_path(n);
+            if from.exists() {
+                fs::rename(from, self.rotated_path(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))?;
+        *file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        Ok(())
+    }
+
+    fn lock(&self) -> io::Result<std::sync::MutexGuard<'_, (File, u64)>> {
+        self.file
+            .lock()
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "log file is poisoned"))
+    }
+
+    /// Append `buf`, a formatted event, rotate file first if it would grow over `max_size`.
+    fn append(&self, buf: &[u8]) -> io::Result<()> {
+        let mut guard = self.lock()?;
+        let (file, size) = &mut *guard;
+        if *size + buf.len() as u64 > self.max_size && *size > 0 {
+            if let Err(e) = self.rotate(file) {
+                eprintln!("rotate log file failed: {}", e);
+            }
+            *size = 0;
         }
+        file.write_all(buf)?;
+        *size += buf.len() as u64;
+        Ok(())
+    }
+}
+
+/// Writer of events to a [RotatingFileWriter].
+pub struct RotatingFileHandle<'a>(&'a RotatingFileWriter);
+
+impl Write for RotatingFileHandle<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.append(buf)?;
+        Ok(buf.len())
     }
 
-    fn flush(&self) {}
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock()?.0.flush()
+    }
 }
 
-impl Logger {
-    pub fn init(level: LevelFilter) -> Result<(), SetLoggerError> {
-        log::set_boxed_logger(Box::new(Logger)).map(|()| log::set_max_level(level))
+impl<'a> MakeWriter<'a> for RotatingFileWriter {
+    type Writer = RotatingFileHandle<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RotatingFileHandle(self)
     }
 }
 
@@ -57,3 +134,58 @@ impl From<LogLevel> for log::LevelFilter {
         }
     }
 }
+
+/// Output format of [init_tracing].
+#[derive(ArgEnum, Debug, Clone, Copy)]
+#[clap(rename_all = "kebab-case")]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line, for log aggregation.
+    Json,
+}
+
+/// Install a global tracing subscriber, `log` records of dependencies are collected too.
+/// `filter` follows the syntax of `RUST_LOG`, eg: `info,rings_core::swarm=debug`,
+/// and falls back to `level` for all modules when it's not set.
+pub fn init_tracing(
+    level: LogLevel,
+    filter: Option<&str>,
+    format: LogFormat,
+) -> anyhow::Result<()> {
+    let builder = tracing_subscriber::fmt().with_env_filter(env_filter(level, filter)?);
+    match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    }
+    .map_err(|e| anyhow::anyhow!("{}", e))
+}
+
+/// Same as [init_tracing], events are written by `writer` without colors, like to a
+/// [RotatingFileWriter].
+pub fn init_tracing_with_writer<W>(
+    level: LogLevel,
+    filter: Option<&str>,
+    format: LogFormat,
+    writer: W,
+) -> anyhow::Result<()>
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(env_filter(level, filter)?)
+        .with_ansi(false)
+        .with_writer(writer);
+    match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    }
+    .map_err(|e| anyhow::anyhow!("{}", e))
+}
+
+fn env_filter(level: LogLevel, filter: Option<&str>) -> anyhow::Result<EnvFilter> {
+    let directives = match filter {
+        Some(f) if !f.is_empty() => f.to_owned(),
+        _ => LevelFilter::from(level).to_string().to_lowercase(),
+    };
+    Ok(EnvFilter::try_new(directives)?)
+}
@@ -0,0 +1,656 @@
+#![warn(missing_docs)]
+//! Embeddable rings node, see [Node::builder].
+//!
+//! Components of a node depend on each other, and have to be wired in order: [Swarm] first,
+//! then [PeerRing] using its route stats, then [MessageHandler] and [Stabilization] over both.
+//! [NodeBuilder] wires them from a [Config] like `rings-node run` does, and [Node] runs their
+//! tasks and exposes them by [Processor].
+//!
+//! Applications embedding a full node without jsonrpc server can simply [run] it, and talk to
+//! network by the returned [NodeHandle]. Nodes share no state but the runtime, a [NodeSet]
+//! runs many of them in one process, like a gateway hosting many identities.
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::Mutex as SyncMutex;
+use std::time::Duration;
+
+use futures::lock::Mutex;
+use futures::Stream;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::Instrument;
+
+use crate::config::BootstrapConfig;
+use crate::config::Config;
+use crate::config::MetricsConfig;
+use crate::ens::EnsResolver;
+use crate::error::Error;
+use crate::error::Result;
+use crate::jsonrpc_client::HttpOptions;
+use crate::prelude::rings_core::admission::AdmissionPolicy;
+use crate::prelude::rings_core::dht::routing::TagPreferencePolicy;
+use crate::prelude::rings_core::dht::Stabilization;
+use crate::prelude::rings_core::dht::StabilizationHandle;
+use crate::prelude::rings_core::group::GroupKeyring;
+use crate::prelude::rings_core::history::MessageHistory;
+use crate::prelude::rings_core::known_peers::KnownPeers;
+use crate::prelude::rings_core::message::CallbackFilter;
+use crate::prelude::rings_core::prelude::Address;
+use crate::prelude::rings_core::presence::PresenceEvent;
+use crate::prelude::rings_core::pubkey::derive_encryption_key;
+use crate::prelude::rings_core::replay::ReplayGuard;
+use crate::prelude::rings_core::session::Ttl;
+use crate::prelude::rings_core::storage::Storage;
+use crate::prelude::rings_core::storage::StorageCipher;
+use crate::prelude::rings_core::storage::StorageTask;
+use crate::prelude::rings_core::tags::PeerTags;
+use crate::prelude::CustomMessage;
+use crate::prelude::Message;
+use crate::prelude::MessageCallback;
+use crate::prelude::MessageHandler;
+use crate::prelude::MessageListener;
+use crate::prelude::MessagePayload;
+use crate::prelude::PeerRing;
+use crate::prelude::SessionManager;
+use crate::prelude::Swarm;
+use crate::prelude::Transport;
+use crate::processor::parse_did;
+use crate::processor::Peer;
+use crate::processor::Processor;
+use crate::service::run_bootstrap;
+use crate::service::run_idle_sweeper;
+use crate::service::run_metrics_push;
+use crate::service::HandshakeLimiter;
+use crate::service::OfferLimiter;
+
+/// Task persisting stored vnodes of a node, see [StorageTask::run_until].
+type NodeStorageTask = Arc<Mutex<StorageTask<Storage>>>;
+
+/// Wire components of a [Node] from a [Config].
+#[derive(Default)]
+pub struct NodeBuilder {
+    config: Config,
+    callback: Option<Box<dyn MessageCallback + Send + Sync>>,
+}
+
+impl NodeBuilder {
+    /// Build node by `config`, which should have `eth_key` or `keystore` set.
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Receive custom and builtin messages by `callback`.
+    pub fn with_callback(mut self, callback: Box<dyn MessageCallback + Send + Sync>) -> Self {
+        self.callback = Some(callback);
+        self
+    }
+
+    /// Create components of node, nothing runs until [Node::start].
+    pub async fn build(self) -> Result<Node> {
+        let config = self.config;
+        config.validate()?;
+        let key = config.secret_key()?;
+        let (auth, temp_key) =
+            SessionManager::gen_unsign_info(key.address(), Some(Ttl::Never), None)
+                .map_err(Error::NodeBuild)?;
+        let sig = key
+            .sign(&auth.to_string().map_err(Error::NodeBuild)?)
+            .to_vec();
+        let session = SessionManager::new(&sig, &auth, &temp_key);
+
+        let tags = Arc::new(match &config.tags_path {
+            Some(path) => PeerTags::open(path).map_err(Error::PeerTagError)?,
+            None => PeerTags::new(),
+        });
+        let known_peers = Arc::new(match &config.known_peers_path {
+            Some(path) => KnownPeers::open(path).map_err(Error::KnownPeersError)?,
+            None => KnownPeers::new(),
+        });
+        let group_keys = Arc::new(match &config.group_keys_path {
+            Some(path) => {
+                let cipher = config
+                    .storage_cipher(&key, path)?
+                    .unwrap_or_else(|| StorageCipher::from_secret_key(&key));
+                GroupKeyring::open(path, cipher).map_err(Error::GroupError)?
+            }
+            None => GroupKeyring::new(),
+        });
+        let replay = Arc::new(match &config.replay_path {
+            Some(path) => {
+                ReplayGuard::open(config.replay_window_ms, path).map_err(Error::NodeBuild)?
+            }
+            None => ReplayGuard::new(config.replay_window_ms),
+        });
+        let mut builder =
+            Swarm::builder(key.address(), session).with_relay_budget(config.relay_budget);
+        if let Some(policy) = config.relay_policy() {
+            builder = builder.with_relay_policy(Arc::new(policy));
+        }
+        let swarm = Arc::new(
+            builder
+                .with_ice_servers(config.ice_servers.as_str())
+                .with_network_id(config.network_id.as_str())
+                .with_relay(config.features.relay)
+                .with_version_policy(config.version_policy)
+                .with_ice_transport_policy(config.ice_transport_policy)
+                .with_ip_family(config.ip_family)
+                .with_handshake_nonce_required(config.require_handshake_nonce)
+                .with_migration_window(config.migration_window_ms)
+                .with_verify_workers(config.verify_workers)
+                .with_compression(&config.codecs, config.compress_threshold)
+                .with_max_connections(config.max_connections)
+                .with_tags(tags.clone())
+                .with_encryption_key(derive_encryption_key(&key).map_err(Error::NodeBuild)?)
+                .with_known_peers(known_peers)
+                .with_group_keys(group_keys)
+                .with_tofu_policy(config.tofu_policy)
+                .with_capture(config.capture_size)
+                .with_max_clock_skew(config.max_clock_skew_ms)
+                .with_replay_guard(replay)
+                .with_manifest(config.manifest_features(), config.public_endpoints.clone())
+                .build()
+                .map_err(Error::NodeBuild)?,
+        );
+
+        let mut routing = config.routing.build(swarm.route_stats());
+        if let Some((key, value)) = config.prefer_tag() {
+            routing = Arc::new(TagPreferencePolicy::new(tags, key, value, routing));
+        }
+        // stored vnodes are persisted in background, never on handler paths
+        let mut storage_task = None;
+        let peer_ring = match &config.storage_path {
+            Some(path) => {
+                let mut backend = Storage::new_with_path(path)
+                    .await
+                    .map_err(Error::NodeBuild)?;
+                if let Some(c) = config.storage_cipher(&key, path)? {
+                    backend = backend.with_cipher(c);
+                }
+                let (storage, task) = StorageTask::open(backend).await.map_err(Error::NodeBuild)?;
+                storage_task = Some(Arc::new(Mutex::new(task)));
+                PeerRing::new_with_storage(key.address().into(), Arc::new(storage))
+            }
+            None => PeerRing::new(key.address().into()),
+        };
+        let dht = Arc::new(Mutex::new(
+            peer_ring
+                .with_routing_policy(routing)
+                .with_route_cache(swarm.route_cache()),
+        ));
+
+        let mut msg_handler = match self.callback {
+            Some(callback) => {
+                MessageHandler::new_with_callback(dht.clone(), swarm.clone(), callback)
+            }
+            None => MessageHandler::new(dht.clone(), swarm.clone()),
+        }
+        .with_lazy_dial(config.lazy_dial_queue)
+        .with_join_parallelism(config.join_parallelism)
+        .with_topology_policy(config.topology_policy)
+        .with_overload_guard(config.shed_queue, config.shed_cpu_budget)
+        .with_echo(config.features.echo);
+        if config.seed.enabled {
+            let offers = OfferLimiter::from_config(&config.seed);
+            msg_handler = msg_handler
+                .with_store_admission(Box::new(AdmissionPolicy::seed()))
+                .with_offer_admission(Box::new(move |peer| offers.allow(peer)));
+        }
+        if let Some(path) = &config.history_path {
+            let mut history = MessageHistory::open(path).map_err(Error::HistoryError)?;
+            if let Some(c) = config.storage_cipher(&key, path)? {
+                history = history.with_cipher(c);
+            }
+            let history = history
+                .with_retention(config.history_max_age_ms, config.history_max_records)
+                .map_err(Error::HistoryError)?;
+            msg_handler = msg_handler.with_history(Arc::new(history));
+        }
+        let (min_interval, max_interval) = config.stabilize_interval();
+        let stabilization =
+            Stabilization::new(dht, swarm.clone()).with_interval(min_interval, max_interval);
+
+        let ens = match &config.ens_endpoint {
+            Some(endpoint) => Some(Arc::new(EnsResolver::new(endpoint).await?)),
+            None => None,
+        };
+        let http = HttpOptions::from(&config.http_client)
+            .build()
+            .map_err(|e| Error::InvalidConfig(config.location("http_client"), e.to_string()))?;
+        let processor = Processor::from((swarm, Arc::new(msg_handler), Arc::new(stabilization)))
+            .with_http_client(Arc::new(http))
+            .with_ens(ens)
+            .with_admin_token(config.admin_token.clone())
+            .with_share_dir(config.share_dir.clone());
+        let seed = config.seed.enabled.then(|| {
+            (
+                Duration::from_secs(config.seed.idle_timeout_secs),
+                Arc::new(HandshakeLimiter::from_config(&config.seed)),
+            )
+        });
+        Ok(Node {
+            processor,
+            stabilize: config.features.stabilization,
+            bootstrap: config.bootstrap.clone(),
+            metrics: config.metrics.clone(),
+            seed,
+            storage_task,
+            tasks: SyncMutex::new(None),
+        })
+    }
+}
+
+/// Tasks of a started [Node].
+struct NodeTasks {
+    stabilization: Option<StabilizationHandle>,
+    spawned: Vec<JoinHandle<()>>,
+    /// Stops storage task, after it applies writes journaled so far.
+    stop_storage: Option<oneshot::Sender<()>>,
+}
+
+/// A rings node with all components wired, see module doc.
+pub struct Node {
+    processor: Processor,
+    /// Run stabilization on start.
+    stabilize: bool,
+    bootstrap: BootstrapConfig,
+    metrics: MetricsConfig,
+    /// Idle timeout of transports and handshake limiter of seed mode.
+    seed: Option<(Duration, Arc<HandshakeLimiter>)>,
+    storage_task: Option<NodeStorageTask>,
+    tasks: SyncMutex<Option<NodeTasks>>,
+}
+
+impl Node {
+    /// Builder of node, see [NodeBuilder].
+    pub fn builder() -> NodeBuilder {
+        NodeBuilder::default()
+    }
+
+    /// Processor over components of node, to serve jsonrpc or call it directly.
+    pub fn processor(&self) -> &Processor {
+        &self.processor
+    }
+
+    /// Limiter of handshakes of each client IP, if node runs as seed, for
+    /// [run_service](crate::service::run_service).
+    pub fn handshake_limiter(&self) -> Option<Arc<HandshakeLimiter>> {
+        self.seed.as_ref().map(|(_, limiter)| limiter.clone())
+    }
+
+    /// Spawn tasks of listening messages, persisting stored vnodes, dialing bootstrap peers,
+    /// and stabilization, metrics push and sweeping idle transports if they're enabled by
+    /// config. Nothing happens if node is already started.
+    pub fn start(&self) {
+        let mut tasks = match self.tasks.lock() {
+            Ok(tasks) => tasks,
+            Err(_) => return,
+        };
+        if tasks.is_some() {
+            return;
+        }
+        // logs of nodes sharing a process are told apart by span of their tasks
+        let span = tracing::info_span!("node", address = ?self.processor.address());
+        let processor = &self.processor;
+        let mut spawned = vec![
+            tokio::spawn(
+                processor
+                    .msg_handler
+                    .clone()
+                    .listen()
+                    .instrument(span.clone()),
+            ),
+            tokio::spawn(
+                run_bootstrap(self.bootstrap.clone(), processor.clone()).instrument(span.clone()),
+            ),
+        ];
+        if self.metrics.endpoint.is_some() {
+            spawned.push(tokio::spawn(
+                run_metrics_push(self.metrics.clone(), processor.clone()).instrument(span.clone()),
+            ));
+        }
+        if let Some((idle_timeout, _)) = &self.seed {
+            spawned.push(tokio::spawn(
+                run_idle_sweeper(*idle_timeout, processor.clone()).instrument(span.clone()),
+            ));
+        }
+        let stop_storage = self.storage_task.clone().map(|task| {
+            let (stop, stopped) = oneshot::channel::<()>();
+            // a task stopped just before is waited for, until it applies its writes
+            tokio::spawn(
+                async move {
+                    task.lock()
+                        .await
+                        .run_until(async {
+                            stopped.await.ok();
+                        })
+                        .await
+                }
+                .instrument(span),
+            );
+            stop
+        });
+        let stabilization = self
+            .stabilize
+            .then(|| processor.stabilization.clone().spawn());
+        *tasks = Some(NodeTasks {
+            stabilization,
+            spawned,
+            stop_storage,
+        });
+    }
+
+    /// Stop tasks spawned by [Node::start], storage task stops after writes journaled so far
+    /// are applied. Stabilization can't be spawned again, a node started again runs all other
+    /// tasks.
+    pub fn stop(&self) {
+        if let Some(tasks) = self.tasks.lock().ok().and_then(|mut t| t.take()) {
+            for task in tasks.spawned {
+                task.abort();
+            }
+            if let Some(s) = tasks.stabilization {
+                s.stop();
+            }
+            if let Some(stop) = tasks.stop_storage {
+                stop.send(()).ok();
+            }
+        }
+    }
+
+    /// If tasks of node are running.
+    pub fn is_started(&self) -> bool {
+        self.tasks.lock().map(|t| t.is_some()).unwrap_or(false)
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Build and start a node by `config`, like `rings-node run` does without jsonrpc server and
+/// other services.
+pub async fn run(config: Config) -> Result<NodeHandle> {
+    let node = Node::builder().with_config(config).build().await?;
+    node.start();
+    Ok(NodeHandle {
+        node: Arc::new(node),
+    })
+}
+
+/// Handle of a node started by [run], node stops when the last clone of it is dropped.
+#[derive(Clone)]
+pub struct NodeHandle {
+    node: Arc<Node>,
+}
+
+impl NodeHandle {
+    /// Processor of node, for operations not covered by handle.
+    pub fn processor(&self) -> &Processor {
+        self.node.processor()
+    }
+
+    /// Web3 address of node.
+    pub fn address(&self) -> Address {
+        self.processor().address()
+    }
+
+    /// Connect `address` through DHT, waits until the connection is open.
+    pub async fn connect(&self, address: &str) -> Result<Peer> {
+        let address = Address::from(parse_did(address)?);
+        self.processor().connect_with_address(&address, true).await
+    }
+
+    /// Connect a node by its jsonrpc endpoint `url`, like a bootstrap peer.
+    pub async fn connect_url(&self, url: &str) -> Result<Arc<Transport>> {
+        self.processor().connect_peer_via_http(url).await
+    }
+
+    /// Send custom message `msg` to `destination`.
+    pub async fn send(&self, destination: &str, msg: &[u8]) -> Result<()> {
+        self.processor().send_message(destination, msg).await
+    }
+
+    /// Stream of custom messages received from now on, decrypted. Drop it to unsubscribe.
+    pub fn subscribe(
+        &self,
+    ) -> impl Stream<Item = (MessagePayload<Message>, CustomMessage)> + Unpin {
+        self.processor()
+            .msg_handler
+            .subscribe_custom(CallbackFilter::default())
+    }
+
+    /// Stream of presence changes of DIDs tracked by [Processor::track_presence], from now on.
+    /// Drop it to unsubscribe.
+    pub fn subscribe_presence(&self) -> impl Stream<Item = PresenceEvent> + Unpin {
+        self.processor().swarm.presence().subscribe()
+    }
+
+    /// Leave network gracefully, see [Processor::drain], then stop tasks of node.
+    pub async fn shutdown(&self) -> Result<()> {
+        self.processor().drain().await?;
+        self.node.stop();
+        Ok(())
+    }
+}
+
+/// Independent nodes run in one runtime, by their addresses, see module doc.
+#[derive(Clone, Default)]
+pub struct NodeSet {
+    nodes: Arc<SyncMutex<BTreeMap<Address, (NodeHandle, Vec<String>)>>>,
+}
+
+/// Paths a node persists its state to, never shared by nodes of a set.
+fn state_paths(config: &Config) -> Vec<String> {
+    [
+        &config.storage_path,
+        &config.history_path,
+        &config.tags_path,
+        &config.known_peers_path,
+        &config.group_keys_path,
+        &config.replay_path,
+    ]
+    .into_iter()
+    .flatten()
+    .cloned()
+    .collect()
+}
+
+impl NodeSet {
+    /// An empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn check(
+        nodes: &BTreeMap<Address, (NodeHandle, Vec<String>)>,
+        address: Address,
+        paths: &[String],
+    ) -> Result<()> {
+        if nodes.contains_key(&address) {
+            return Err(Error::NodeConflict(format!("{:?} is running", address)));
+        }
+        match nodes
+            .values()
+            .flat_map(|(_, used)| used.iter())
+            .find(|p| paths.contains(p))
+        {
+            Some(p) => Err(Error::NodeConflict(format!("{} is used", p))),
+            None => Ok(()),
+        }
+    }
+
+    /// [run] a node by `config` in the set. Fails if a node of the same key is in the set, or
+    /// it persists state to a path used by another node.
+    pub async fn run(&self, config: Config) -> Result<NodeHandle> {
+        let address = config.secret_key()?.address();
+        let paths = state_paths(&config);
+        Self::check(
+            &*self.nodes.lock().map_err(|_| Error::InternalError)?,
+            address,
+            &paths,
+        )?;
+        let handle = run(config).await?;
+        let mut nodes = self.nodes.lock().map_err(|_| Error::InternalError)?;
+        // another one may be run while this one is started
+        Self::check(&nodes, address, &paths)?;
+        nodes.insert(address, (handle.clone(), paths));
+        Ok(handle)
+    }
+
+    /// Handle of node of `address`.
+    pub fn get(&self, address: &Address) -> Option<NodeHandle> {
+        let nodes = self.nodes.lock().ok()?;
+        nodes.get(address).map(|(h, _)| h.clone())
+    }
+
+    /// Addresses of nodes in the set.
+    pub fn addresses(&self) -> Vec<Address> {
+        self.nodes
+            .lock()
+            .map(|nodes| nodes.keys().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Shutdown node of `address` and remove it from the set, false if it's not in the set.
+    pub async fn remove(&self, address: &Address) -> Result<bool> {
+        let removed = self
+            .nodes
+            .lock()
+            .map_err(|_| Error::InternalError)?
+            .remove(address);
+        match removed {
+            Some((handle, _)) => handle.shutdown().await.map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Shutdown all nodes, and empty the set.
+    pub async fn shutdown(&self) -> Result<()> {
+        for address in self.addresses() {
+            self.remove(&address).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::uuid;
+    use crate::prelude::SecretKey;
+
+    #[tokio::test]
+    async fn test_node_builder() {
+        assert!(Node::builder().build().await.is_err());
+
+        let key = SecretKey::random();
+        let config = Config {
+            eth_key: Some(key.to_string()),
+            ..Default::default()
+        };
+        let node = Node::builder().with_config(config).build().await.unwrap();
+        assert_eq!(node.processor().swarm.address(), key.address());
+        assert!(!node.is_started());
+        node.start();
+        assert!(node.is_started());
+        node.stop();
+        assert!(!node.is_started());
+        assert!(node.handshake_limiter().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_node_restart() {
+        let storage = std::env::temp_dir()
+            .join(format!("rings-storage-{}", uuid::Uuid::new_v4()))
+            .to_string_lossy()
+            .to_string();
+        let mut config = Config {
+            eth_key: Some(SecretKey::random().to_string()),
+            storage_path: Some(storage.clone()),
+            ..Default::default()
+        };
+        config.seed.enabled = true;
+        let node = Node::builder().with_config(config).build().await.unwrap();
+        assert!(node.handshake_limiter().is_some());
+        let storage_task = node.storage_task.clone().unwrap();
+        node.start();
+        tokio::task::yield_now().await;
+        assert!(storage_task.try_lock().is_none());
+        node.stop();
+        // storage task stops and can be run again
+        drop(storage_task.lock().await);
+        node.start();
+        tokio::task::yield_now().await;
+        assert!(node.is_started());
+        assert!(storage_task.try_lock().is_none());
+        node.stop();
+        drop(node);
+        std::fs::remove_dir_all(&storage).ok();
+    }
+
+    #[tokio::test]
+    async fn test_run_node() {
+        let config = Config {
+            eth_key: Some(SecretKey::random().to_string()),
+            ..Default::default()
+        };
+        let handle = run(config).await.unwrap();
+        assert!(handle.node.is_started());
+        assert!(matches!(
+            handle.send("not an address", b"hello").await,
+            Err(Error::InvalidDid(_))
+        ));
+        let _messages = handle.subscribe();
+        assert_eq!(handle.processor().msg_handler.callbacks().names().len(), 1);
+        handle.shutdown().await.unwrap();
+        assert!(!handle.node.is_started());
+    }
+
+    #[tokio::test]
+    async fn test_node_set() {
+        let history = std::env::temp_dir()
+            .join(format!("rings-history-{}", uuid::Uuid::new_v4()))
+            .to_string_lossy()
+            .to_string();
+        let config = |key: &SecretKey| Config {
+            eth_key: Some(key.to_string()),
+            history_path: Some(history.clone()),
+            ..Default::default()
+        };
+        let (key1, key2) = (SecretKey::random(), SecretKey::random());
+
+        let nodes = NodeSet::new();
+        let node1 = nodes.run(config(&key1)).await.unwrap();
+        assert!(matches!(
+            nodes.run(config(&key1)).await,
+            Err(Error::NodeConflict(_))
+        ));
+        assert!(matches!(
+            nodes.run(config(&key2)).await,
+            Err(Error::NodeConflict(_))
+        ));
+        let node2 = nodes
+            .run(Config {
+                history_path: None,
+                ..config(&key2)
+            })
+            .await
+            .unwrap();
+        assert_ne!(node1.address(), node2.address());
+        assert_eq!(nodes.addresses().len(), 2);
+        assert_eq!(
+            nodes.get(&key2.address()).map(|h| h.address()),
+            Some(key2.address())
+        );
+
+        assert!(nodes.remove(&key1.address()).await.unwrap());
+        assert!(!nodes.remove(&key1.address()).await.unwrap());
+        assert!(!node1.node.is_started());
+        nodes.shutdown().await.unwrap();
+        assert!(nodes.addresses().is_empty());
+        drop((node1, node2));
+        std::fs::remove_dir_all(&history).ok();
+    }
+}
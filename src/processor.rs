@@ -1,31 +1,192 @@
 #![warn(missing_docs)]
 //! Processor of rings-node jsonrpc-server.
+use std::collections::BTreeMap;
+#[cfg(feature = "client")]
+use std::collections::HashMap;
+#[cfg(feature = "client")]
+use std::path::Component;
+#[cfg(feature = "client")]
+use std::path::Path;
+#[cfg(feature = "client")]
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
+#[cfg(feature = "client")]
+use futures::StreamExt;
 #[cfg(feature = "client")]
 use jsonrpc_core::Metadata;
+use serde::Deserialize;
+use serde::Serialize;
 
+#[cfg(feature = "client")]
+use crate::ens::is_ens_name;
+#[cfg(feature = "client")]
+use crate::ens::EnsResolver;
 use crate::error::Error;
 use crate::error::Result;
 use crate::jsonrpc::method;
+#[cfg(feature = "client")]
+use crate::jsonrpc::response::BenchmarkReport;
+#[cfg(feature = "client")]
+use crate::jsonrpc::response::CrawlReport;
+#[cfg(feature = "client")]
+use crate::jsonrpc::response::CrawledNode;
+use crate::jsonrpc::response::EchoInfo;
+#[cfg(feature = "client")]
+use crate::jsonrpc::response::ErasureInfo;
+#[cfg(feature = "client")]
+use crate::jsonrpc::response::FileInfo;
+#[cfg(feature = "client")]
+use crate::jsonrpc::response::GroupKeyInfo;
+#[cfg(feature = "client")]
+use crate::jsonrpc::response::GroupSendResult;
+use crate::jsonrpc::response::KnownPeerInfo;
+use crate::jsonrpc::response::LocalData;
+use crate::jsonrpc::response::LocalDataPage;
+#[cfg(feature = "client")]
+use crate::jsonrpc::response::ManifestInfo;
+use crate::jsonrpc::response::NodeInfo;
+use crate::jsonrpc::response::Peer as JsonPeer;
+use crate::jsonrpc::response::PeerPage;
+use crate::jsonrpc::response::PendingPage;
+#[cfg(feature = "client")]
+use crate::jsonrpc::response::PresenceInfo;
+#[cfg(feature = "client")]
+use crate::jsonrpc::response::ServiceProvider;
+use crate::jsonrpc::response::StateSnapshot;
 use crate::jsonrpc::response::TransportAndIce;
+use crate::jsonrpc_client::client::RpcError;
+use crate::jsonrpc_client::typed::HandshakeNonceRequest;
+use crate::jsonrpc_client::RetryPolicy;
 use crate::jsonrpc_client::SimpleClient;
+#[cfg(feature = "client")]
+use crate::prelude::async_trait;
+use crate::prelude::reqwest::Client as HttpClient;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::accounting::RelayUsage;
+use crate::prelude::rings_core::capture::CapturedPayload;
+#[cfg(feature = "chaos")]
+use crate::prelude::rings_core::chaos::FaultConfig;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::dht::vnode::VirtualNode;
+use crate::prelude::rings_core::dht::Did;
+use crate::prelude::rings_core::dht::RepairReport;
 use crate::prelude::rings_core::dht::Stabilization;
+use crate::prelude::rings_core::dht::StabilizationStatus;
+use crate::prelude::rings_core::ecc::HashStr;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::ecc::PublicKey;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::erasure::ErasureManifest;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::err::Error as CoreError;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::err::Result as CoreResult;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::file::FileManifest;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::file::TransferProgress;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::file::DEFAULT_CHUNK_SIZE;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::group::Group;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::group::GroupKey;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::group::GroupKeyRecord;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::group::GroupMessage;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::group::GroupRecord;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::history::HistoryFilter;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::history::HistoryPage;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::manifest::ManifestRecord;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::manifest::DEFAULT_MANIFEST_TTL_MS;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::message::strip_echo;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::message::CallbackFilter;
+use crate::prelude::rings_core::message::DeliveryReceipt;
 use crate::prelude::rings_core::message::Encoded;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::message::MaybeEncrypted;
 use crate::prelude::rings_core::message::Message;
 use crate::prelude::rings_core::message::MessageHandler;
-use crate::prelude::rings_core::message::PayloadSender;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::message::MessagePayload;
+use crate::prelude::rings_core::message::TChordStorage;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::message::TInbox;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::message::TopologyReport;
+use crate::prelude::rings_core::message::DEFAULT_CONNECT_TIMEOUT_MS;
 use crate::prelude::rings_core::prelude::uuid;
-use crate::prelude::rings_core::prelude::web3::contract::tokens::Tokenizable;
-use crate::prelude::rings_core::prelude::web3::ethabi::Token;
-use crate::prelude::rings_core::prelude::web3::types::Address;
+use crate::prelude::rings_core::prelude::Address;
 use crate::prelude::rings_core::prelude::RTCSdpType;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::presence::PresenceRecord;
+use crate::prelude::rings_core::pubkey::PubkeyRecord;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::pubkey::PubkeyResolver;
+use crate::prelude::rings_core::rotation::RotationRecord;
+use crate::prelude::rings_core::service::ServiceRecord;
+use crate::prelude::rings_core::service::DEFAULT_SERVICE_TTL_MS;
 use crate::prelude::rings_core::swarm::Swarm;
 use crate::prelude::rings_core::swarm::TransportManager;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::topic::TopicLog;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::topic::TopicRecord;
+use crate::prelude::rings_core::traffic::PeerTrafficStats;
+use crate::prelude::rings_core::transports::helper::timeout_or_cancel;
+use crate::prelude::rings_core::transports::helper::TricklePayload;
 use crate::prelude::rings_core::transports::Transport;
+use crate::prelude::rings_core::types::ice_transport::HandshakeMeta;
 use crate::prelude::rings_core::types::ice_transport::IceTransport;
 use crate::prelude::rings_core::types::ice_transport::IceTrickleScheme;
+#[cfg(feature = "client")]
+use crate::prelude::rings_core::utils;
+
+/// How many chunks of a file are fetched at the same time.
+#[cfg(feature = "client")]
+const FETCH_CHUNK_WINDOW: usize = 8;
+
+/// Prefix of messages sent by [Processor::benchmark], followed by sequence and padding.
+#[cfg(feature = "client")]
+const BENCHMARK_PREFIX: &[u8] = b"rings-bench:";
+
+/// Wait for public key of a destination up to this long, on sending messages encrypted to it.
+#[cfg(feature = "client")]
+const PUBKEY_TIMEOUT_MS: u64 = 3000;
+
+/// Wait for rotation of a destination out of reach up to this long, on sending messages to it.
+#[cfg(feature = "client")]
+const ROTATION_TIMEOUT_MS: u64 = 1000;
+
+/// Wait for presence of a destination up to this long, on deciding if it's offline.
+#[cfg(feature = "client")]
+const PRESENCE_TIMEOUT_MS: u64 = 1000;
+
+/// Peers in one page, if filter not set it.
+pub const DEFAULT_PEER_PAGE_LIMIT: usize = 100;
+/// Peers in one page at most.
+pub const MAX_PEER_PAGE_LIMIT: usize = 1000;
+/// Messages sent by one [Processor::benchmark] at most.
+pub const MAX_BENCHMARK_COUNT: usize = 10_000;
+/// Bytes of each message of [Processor::benchmark] at most.
+pub const MAX_BENCHMARK_SIZE: usize = 64 * 1024;
+/// Nodes visited by one [Processor::crawl] at most.
+pub const MAX_CRAWL_NODES: usize = 1024;
+/// Rounds of one [Processor::crawl] at most, each going one step of successors further.
+pub const MAX_CRAWL_DEPTH: usize = 256;
+/// Nodes queried at once in each round of [Processor::crawl].
+pub const CRAWL_BREADTH: usize = 4;
 
 /// Processor for rings-node jsonrpc server
 #[derive(Clone)]
@@ -36,11 +197,131 @@ pub struct Processor {
     pub msg_handler: Arc<MessageHandler>,
     /// a stabilization instane,
     pub stabilization: Arc<Stabilization>,
+    /// resolver of ENS names in DID params
+    #[cfg(feature = "client")]
+    pub ens: Option<Arc<EnsResolver>>,
+    /// Caller may use methods reserved for operator of node, see [Processor::require_admin].
+    admin: bool,
+    /// Remote callers presenting this token are admin, see [Processor::authorized].
+    admin_token: Option<Arc<String>>,
+    /// Files are sent from and fetched to this directory only.
+    #[cfg(feature = "client")]
+    share_dir: Option<PathBuf>,
+    /// Http client calling JSON-RPC of other nodes, shared by calls to reuse connections.
+    http: Option<Arc<HttpClient>>,
 }
 
 #[cfg(feature = "client")]
 impl Metadata for Processor {}
 
+/// Conditions of [Processor::list_peers_page] and [Processor::list_pendings_page], unset
+/// fields match all.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PeerFilter {
+    /// ICE connection of transport is established or not.
+    pub connected: Option<bool>,
+    /// Hex prefix of DID, `0x` is optional. Pending transports have no DID yet, they never
+    /// match it.
+    pub did_prefix: Option<String>,
+    /// Peer has all of these tags, see [Processor::tag_peer]. Pending transports have no DID
+    /// yet, they never match it.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    /// Items in one page, [DEFAULT_PEER_PAGE_LIMIT] if unset, [MAX_PEER_PAGE_LIMIT] at most.
+    pub limit: Option<usize>,
+}
+
+impl PeerFilter {
+    fn match_did(&self, did: &str) -> bool {
+        self.did_prefix.as_ref().map_or(true, |p| {
+            let p = p.trim_start_matches("0x").to_lowercase();
+            did.trim_start_matches("0x").starts_with(&p)
+        })
+    }
+
+    fn match_tags(&self, tags: &BTreeMap<String, String>) -> bool {
+        self.tags.iter().all(|(k, v)| tags.get(k) == Some(v))
+    }
+
+    async fn match_transport(&self, transport: &Transport) -> bool {
+        match self.connected {
+            Some(connected) => transport.is_connected().await == connected,
+            None => true,
+        }
+    }
+}
+
+/// Items after `cursor` by key, up to `limit`, with cursor of next page if there are more.
+fn paginate<T>(
+    mut items: Vec<(String, T)>,
+    cursor: Option<&str>,
+    limit: Option<usize>,
+) -> (Vec<T>, Option<String>) {
+    items.sort_by(|a, b| a.0.cmp(&b.0));
+    let limit = limit
+        .unwrap_or(DEFAULT_PEER_PAGE_LIMIT)
+        .clamp(1, MAX_PEER_PAGE_LIMIT);
+    let mut rest = items
+        .into_iter()
+        .filter(|(k, _)| cursor.map_or(true, |c| k.as_str() > c))
+        .peekable();
+    let page = rest.by_ref().take(limit).collect::<Vec<_>>();
+    let next_cursor = rest
+        .peek()
+        .and_then(|_| page.last())
+        .map(|(k, _)| k.clone());
+    (page.into_iter().map(|(_, v)| v).collect(), next_cursor)
+}
+
+/// Connect paths of RPC calls wait for transports this long at most.
+fn connect_timeout() -> Duration {
+    Duration::from_millis(DEFAULT_CONNECT_TIMEOUT_MS as u64)
+}
+
+/// [Error::ConnectTimeout] if waiting for transport timed out, [Error::ConnectError] otherwise.
+fn connect_error(e: CoreError) -> Error {
+    match e {
+        CoreError::TransportConnectTimeout(_) => Error::ConnectTimeout(e),
+        e => Error::ConnectError(e),
+    }
+}
+
+/// Parse DID `s`, like `did:rings:0x...`, `0x...` or bare hex, see [Did].
+pub fn parse_did(s: &str) -> Result<Did> {
+    Did::from_str(s).map_err(Error::InvalidDid)
+}
+
+/// Estimate count of nodes on ring, if `n` nodes are found in span from `origin` to `last`
+/// clockwise. Ids are uniformly distributed, so the span covers about `n / size` of ring.
+pub fn estimate_ring_size(origin: Did, last: Did, n: usize) -> u64 {
+    let span = last - origin;
+    let mut top = [0u8; 8];
+    top.copy_from_slice(&span.as_bytes()[..8]);
+    let span = u64::from_be_bytes(top);
+    if span == 0 {
+        return n as u64;
+    }
+    let fraction = span as f64 / 2f64.powi(64);
+    ((n as f64 / fraction).round() as u64).max(n as u64)
+}
+
+/// Operations of [Processor::control_stabilization].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum StabilizationControl {
+    /// Stop running rounds by interval.
+    Pause,
+    Resume,
+    /// Run a round now.
+    Trigger,
+    /// Run rounds every `min` to `max` seconds.
+    Interval {
+        min: usize,
+        max: usize,
+    },
+}
+
 impl From<(Arc<Swarm>, Arc<MessageHandler>, Arc<Stabilization>)> for Processor {
     fn from(
         (swarm, msg_handler, stabilization): (Arc<Swarm>, Arc<MessageHandler>, Arc<Stabilization>),
@@ -49,16 +330,176 @@ impl From<(Arc<Swarm>, Arc<MessageHandler>, Arc<Stabilization>)> for Processor {
             swarm,
             msg_handler,
             stabilization,
+            #[cfg(feature = "client")]
+            ens: None,
+            admin: true,
+            admin_token: None,
+            #[cfg(feature = "client")]
+            share_dir: None,
+            http: None,
         }
     }
 }
 
+/// Compare `a` and `b` in time independent of where they differ.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Resolve `path` in share directory `dir`. It should be relative without `..`, and it
+/// should not be a link out of `dir`, neither should its parent directory.
+#[cfg(feature = "client")]
+async fn share_path(dir: &Path, path: &Path) -> Result<PathBuf> {
+    let invalid = |reason: String| Error::FileTransfer(format!("{}: {}", path.display(), reason));
+    let relative = path
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    if !relative || path.file_name().is_none() {
+        return Err(invalid("should be a relative path without `..`".to_owned()));
+    }
+    let dir = tokio::fs::canonicalize(dir)
+        .await
+        .map_err(|e| invalid(e.to_string()))?;
+    let joined = dir.join(path);
+    let parent = joined.parent().unwrap_or(&dir);
+    let target = tokio::fs::canonicalize(parent)
+        .await
+        .map_err(|e| invalid(e.to_string()))?
+        .join(joined.file_name().unwrap_or_default());
+    // an existing link is followed on reading or writing, so where it points to counts
+    let target = match tokio::fs::symlink_metadata(&target).await {
+        Ok(_) => tokio::fs::canonicalize(&target)
+            .await
+            .map_err(|e| invalid(e.to_string()))?,
+        Err(_) => target,
+    };
+    if !target.starts_with(&dir) {
+        return Err(invalid("out of share directory".to_owned()));
+    }
+    Ok(target)
+}
+
 impl Processor {
+    /// Remote callers presenting `token` are admin, others never are, see
+    /// [Processor::authorized].
+    pub fn with_admin_token(mut self, token: Option<String>) -> Self {
+        self.admin_token = token.map(Arc::new);
+        self
+    }
+
+    /// Call JSON-RPC of other nodes by `client`, like [Processor::connect_peer_via_http] does,
+    /// so connections are reused. A client of default [HttpOptions] is made for each call
+    /// without it.
+    ///
+    /// [HttpOptions]: crate::jsonrpc_client::HttpOptions
+    pub fn with_http_client(mut self, client: Arc<HttpClient>) -> Self {
+        self.http = Some(client);
+        self
+    }
+
+    /// Confine [Processor::send_file] and [Processor::fetch_file] to `dir`, they are refused
+    /// if it's None.
+    #[cfg(feature = "client")]
+    pub fn with_share_dir(mut self, dir: Option<String>) -> Self {
+        self.share_dir = dir.map(PathBuf::from);
+        self
+    }
+
+    /// Processor of a remote caller presenting `bearer` token, who is admin only if the token
+    /// matches the one configured by [Processor::with_admin_token].
+    pub fn authorized(mut self, bearer: Option<&str>) -> Self {
+        self.admin = match (&self.admin_token, bearer) {
+            (Some(token), Some(bearer)) => constant_time_eq(token.as_bytes(), bearer.as_bytes()),
+            _ => false,
+        };
+        self
+    }
+
+    /// Caller may use methods reserved for operator of node.
+    pub fn is_admin(&self) -> bool {
+        self.admin
+    }
+
+    /// Fail with [Error::Unauthorized] unless caller is admin.
+    pub fn require_admin(&self, method: method::Method) -> Result<()> {
+        match self.admin {
+            true => Ok(()),
+            false => Err(Error::Unauthorized(method.as_str().to_owned())),
+        }
+    }
+
+    #[cfg(feature = "client")]
+    fn share_dir(&self) -> Result<&Path> {
+        self.share_dir
+            .as_deref()
+            .ok_or_else(|| Error::FileTransfer("share directory is not configured".to_owned()))
+    }
+
+    /// Accept ENS names wherever a DID is expected.
+    #[cfg(feature = "client")]
+    pub fn with_ens(mut self, ens: Option<Arc<EnsResolver>>) -> Self {
+        self.ens = ens;
+        self
+    }
+
+    /// Resolve `did` to a hex address if it's an ENS name, others are returned as is.
+    #[cfg(feature = "client")]
+    pub async fn resolve_did(&self, did: &str) -> Result<String> {
+        if !is_ens_name(did) {
+            return Ok(did.to_owned());
+        }
+        let address = self.ens_resolve(did).await?;
+        tracing::debug!(name = did, address = ?address, "ENS name resolved");
+        Ok(format!("{:?}", address))
+    }
+
+    /// Address of ENS `name`.
+    #[cfg(feature = "client")]
+    pub async fn ens_resolve(&self, name: &str) -> Result<Address> {
+        self.ens
+            .as_ref()
+            .ok_or(Error::EnsDisabled)?
+            .resolve(name)
+            .await
+    }
+
+    /// Verified primary ENS name of `address`.
+    #[cfg(feature = "client")]
+    pub async fn ens_reverse(&self, address: &str) -> Result<String> {
+        let address = Address::from(parse_did(address)?);
+        self.ens
+            .as_ref()
+            .ok_or(Error::EnsDisabled)?
+            .reverse(address)
+            .await
+    }
+
     /// Get current address
     pub fn address(&self) -> Address {
         self.swarm.address()
     }
 
+    /// Report version, protocol versions and network of node.
+    pub async fn node_info(&self) -> NodeInfo {
+        let meta = self.swarm.meta();
+        NodeInfo {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            protocol_version: meta.protocol_version,
+            min_protocol_version: meta.min_protocol_version,
+            version_policy: meta.version_policy.to_string(),
+            address: format!("{:?}", self.address()),
+            network_id: meta.network_id.clone(),
+            relay: meta.relay,
+            compression: self.swarm.compression_stats(),
+            replay: self.swarm.replay_stats(),
+            echo: self
+                .msg_handler
+                .echo_stats()
+                .map(|s| EchoInfo::from(s.as_ref())),
+            transports: self.swarm.transport_summary().await,
+        }
+    }
+
     /// Create an Offer and waiting for connection.
     /// The process of manually handshake is:
     /// 1. PeerA: create_offer
@@ -94,10 +535,12 @@ impl Processor {
     }
 
     /// Connect peer with remote rings-node jsonrpc server.
+    /// Fails if transport is not connected in [DEFAULT_CONNECT_TIMEOUT_MS], or swarm is draining,
+    /// then the transport is closed and unregistered.
     /// * peer_url: the remote rings-node jsonrpc server url.
     pub async fn connect_peer_via_http(&self, peer_url: &str) -> Result<Arc<Transport>> {
         // request remote offer and sand answer to remote
-        log::debug!("connect_peer_via_http: {}", peer_url);
+        tracing::debug!("connect_peer_via_http: {}", peer_url);
         let transport = self
             .swarm
             .new_transport()
@@ -119,13 +562,38 @@ impl Processor {
         transport: &Arc<Transport>,
         node_url: &str,
     ) -> Result<String> {
-        let client = SimpleClient::new_with_url(node_url);
+        // answerOffer is not idempotent, so it's retried only if remote is not reached
+        let client = match &self.http {
+            Some(http) => SimpleClient::new(http.clone(), node_url),
+            None => SimpleClient::new_with_url(node_url)
+                .map_err(|e| Error::RemoteRpcError(e.to_string()))?,
+        };
+        let client = client
+            .with_timeout(connect_timeout())
+            .with_retry(RetryPolicy {
+                max_retries: 2,
+                ..Default::default()
+            });
+        // sign nonce of remote in offer, nodes before nonces have no such method
+        let nonce = match client.request(&HandshakeNonceRequest).await {
+            Ok(nonce) => Some(nonce),
+            Err(RpcError::JsonRpcError(e)) if e.code == jsonrpc_core::ErrorCode::MethodNotFound => {
+                None
+            }
+            Err(e) => return Err(Error::RemoteRpcError(e.to_string())),
+        };
+        transport
+            .set_local_meta(HandshakeMeta {
+                nonce,
+                ..self.swarm.meta().clone()
+            })
+            .await;
         let hs_info = transport
             .get_handshake_info(self.swarm.session_manager(), RTCSdpType::Offer)
             .await
             .map_err(Error::CreateOffer)?
             .to_string();
-        log::debug!(
+        tracing::debug!(
             "sending offer and candidate {:?} to {:?}",
             hs_info.to_owned(),
             node_url,
@@ -143,16 +611,23 @@ impl Processor {
             .register_remote_info(Encoded::from_encoded_str(info.ice.as_str()))
             .await
             .map_err(Error::RegisterIceError)?;
-        // transport
-        //     .connect_success_promise()
-        //     .await
-        //     .map_err(Error::ConnectError)?
-        //     .await
-        //     .map_err(Error::ConnectError)?;
         self.swarm
             .register(&addr, Arc::clone(transport))
             .await
             .map_err(Error::RegisterIceError)?;
+        // fail the call instead of hanging if remote never connects, the transport is closed by
+        // caller, and unregistered here unless it's replaced already
+        if let Err(e) = transport
+            .wait_for_connected_timeout(connect_timeout(), self.swarm.connect_cancel())
+            .await
+        {
+            if let Some(t) = self.swarm.get_transport(&addr) {
+                if Arc::ptr_eq(&t, transport) {
+                    self.swarm.remove_transport(&addr);
+                }
+            }
+            return Err(connect_error(e));
+        }
         Ok(addr.to_string())
     }
 
@@ -163,14 +638,45 @@ impl Processor {
     /// 3. PeerB: answer_offer
     /// 4. PeerB: send the handshake info to PeerA.
     /// 5. PeerA: accept_answer.
+    ///
+    /// Offer is verified to be signed by the DID it claims before a transport is created for
+    /// it. Its nonce, if any, should be issued by [Self::handshake_nonce] and never used before.
     pub async fn answer_offer(&self, ice_info: &str) -> Result<(Arc<Transport>, Encoded)> {
-        log::info!("connect peer via ice: {}", ice_info);
+        let encoded = Encoded::from_encoded_str(ice_info);
+        let offer = TricklePayload::decode_checked(&encoded).map_err(Error::RegisterIceError)?;
+        // a retry of the offer answered last, by its transport still registered, gets the same
+        // answer, its nonce is consumed already
+        let answered = self.swarm.answered_offers().get(&offer.addr, &encoded);
+        if let Some((id, answer)) = answered {
+            if let Some(transport) = self.swarm.get_transport(&offer.addr) {
+                if transport.id == id {
+                    tracing::info!(peer = ?offer.addr, "answer retried offer again");
+                    return Ok((transport, answer));
+                }
+            }
+        }
+        match &offer.data.meta.nonce {
+            Some(nonce) if self.swarm.handshake_nonces().consume(nonce) => {}
+            None if !self.swarm.handshake_nonce_required() => {}
+            _ => return Err(Error::InvalidHandshakeNonce),
+        }
+        tracing::info!(peer = ?offer.addr, "connect peer via ice: {}", ice_info);
+        // a new offer of a connected peer replaces its transport once it's answered, see
+        // `TransportManager::register`
+        if self.swarm.get_transport(&offer.addr).is_some() {
+            tracing::info!(peer = ?offer.addr, "replace transport by new offer");
+        }
         let transport = self.swarm.new_transport().await.map_err(|e| {
-            log::error!("new_transport failed: {}", e);
+            tracing::error!("new_transport failed: {}", e);
             Error::NewTransportError
         })?;
         match self.handshake(&transport, ice_info).await {
-            Ok(v) => Ok((transport, v)),
+            Ok(v) => {
+                self.swarm
+                    .answered_offers()
+                    .insert(&offer.addr, &encoded, transport.id, &v);
+                Ok((transport, v))
+            }
             Err(e) => {
                 transport
                     .close()
@@ -181,6 +687,15 @@ impl Processor {
         }
     }
 
+    /// Issue a nonce for a peer to sign in its offer, which is accepted once only, see
+    /// [HandshakeNonces](rings_core::transports::helper::HandshakeNonces).
+    pub fn handshake_nonce(&self) -> Result<String> {
+        self.swarm
+            .handshake_nonces()
+            .issue()
+            .ok_or(Error::HandshakeNoncesExhausted)
+    }
+
     /// Connect peer with web3 address.
     /// There are 3 peers: PeerA, PeerB, PeerC.
     /// 1. PeerA has a connection with PeerB.
@@ -191,17 +706,28 @@ impl Processor {
         address: &Address,
         wait_for_open: bool,
     ) -> Result<Peer> {
-        let transport = self
-            .msg_handler
-            .connect(address)
-            .await
-            .map_err(Error::ConnectWithAddressError)?;
-        log::debug!("wait for transport connected");
+        let started = utils::get_epoch_ms();
+        // wait for answer of remote, and retry through another path once, all waiting shares
+        // one timeout
+        let transport = match wait_for_open {
+            true => {
+                self.msg_handler
+                    .connect_with_timeout(address, DEFAULT_CONNECT_TIMEOUT_MS)
+                    .await
+            }
+            false => self.msg_handler.connect(address).await,
+        }
+        .map_err(Error::ConnectWithAddressError)?;
+        tracing::debug!("wait for transport connected");
         if wait_for_open {
-            transport
-                .wait_for_data_channel_open()
-                .await
-                .map_err(Error::ConnectWithAddressError)?;
+            let elapsed = utils::get_epoch_ms().saturating_sub(started) as u64;
+            timeout_or_cancel(
+                transport.wait_for_data_channel_open(),
+                connect_timeout().saturating_sub(Duration::from_millis(elapsed)),
+                self.swarm.connect_cancel(),
+            )
+            .await
+            .map_err(connect_error)?;
         }
         Ok(Peer::from((*address, transport)))
     }
@@ -214,141 +740,1353 @@ impl Processor {
             .await
             .map_err(Error::RegisterIceError)?;
 
-        log::debug!("register: {}", addr);
+        tracing::debug!(peer = ?addr, "register transport");
         self.swarm
             .register(&addr, Arc::clone(transport))
             .await
-            .map_err(Error::RegisterIceError)?;
+            .map_err(Error::RegisterIceError)?;
+
+        let hs_info = transport
+            .get_handshake_info(self.swarm.session_manager(), RTCSdpType::Answer)
+            .await
+            .map_err(Error::CreateAnswer)?;
+        tracing::debug!("answer hs_info: {:?}", hs_info);
+        Ok(hs_info)
+    }
+
+    /// Accept an answer of a connection.
+    /// The process of manually handshake is:
+    /// 1. PeerA: create_offer
+    /// 2. PeerA: send the handshake info to PeerB.
+    /// 3. PeerB: answer_offer
+    /// 4. PeerB: send the handshake info to PeerA.
+    /// 5. PeerA: accept_answer.
+    pub async fn accept_answer(&self, transport_id: &str, ice: &str) -> Result<Peer> {
+        let ice = Encoded::from_encoded_str(ice);
+        tracing::debug!(transport_id, "accept_answer/ice: {:?}", ice);
+        let transport_id =
+            uuid::Uuid::from_str(transport_id).map_err(|_| Error::InvalidTransportId)?;
+        let transport = self
+            .swarm
+            .find_pending_transport(transport_id)
+            .map_err(Error::PendingTransport)?
+            .ok_or(Error::TransportNotFound)?;
+        let addr = transport
+            .register_remote_info(ice)
+            .await
+            .map_err(Error::RegisterIceError)?;
+        self.swarm
+            .register(&addr, transport.clone())
+            .await
+            .map_err(Error::RegisterIceError)?;
+        if let Err(e) = self.swarm.pop_pending_transport(transport.id) {
+            tracing::warn!("pop_pending_transport err: {}", e)
+        };
+        Ok(Peer::from((addr, transport)))
+    }
+
+    /// List all peers.
+    pub async fn list_peers(&self) -> Result<Vec<Peer>> {
+        let transports = self.swarm.get_transports();
+        tracing::debug!(
+            "addresses: {:?}",
+            transports.iter().map(|(a, _b)| a).collect::<Vec<_>>()
+        );
+        let data = transports.iter().map(|x| x.into()).collect::<Vec<Peer>>();
+        Ok(data)
+    }
+
+    /// List connected peers matching `filter` page by page, ordered by address, pass
+    /// `next_cursor` of a page to get the next one.
+    pub async fn list_peers_page(
+        &self,
+        filter: &PeerFilter,
+        cursor: Option<&str>,
+    ) -> Result<PeerPage> {
+        let mut items = vec![];
+        let peer_tags = self.swarm.tags();
+        let mut stats = self
+            .swarm
+            .transport_stats()
+            .await
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        for (address, transport) in self.swarm.get_transports() {
+            let did = format!("{:?}", address);
+            let tags = peer_tags.get(address.into());
+            if filter.match_did(&did)
+                && filter.match_tags(&tags)
+                && filter.match_transport(&transport).await
+            {
+                let peer = JsonPeer::from((address, transport))
+                    .with_tags(tags)
+                    .with_stats(stats.remove(&Did::from(address)));
+                items.push((did, peer));
+            }
+        }
+        let (peers, next_cursor) = paginate(items, cursor, filter.limit);
+        Ok(PeerPage { peers, next_cursor })
+    }
+
+    /// Set tag `key` of peer `did` to `value`, or remove it if `value` is None, returns all
+    /// tags of the peer. Tags are local, a peer tagged `acl=deny` is refused. Only admin may
+    /// call it.
+    pub async fn tag_peer(
+        &self,
+        did: &str,
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<BTreeMap<String, String>> {
+        self.require_admin(method::Method::TagPeer)?;
+        let did = parse_did(did)?;
+        let tags = self.swarm.tags();
+        match value {
+            Some(v) => tags.set(did, key, v).map_err(Error::PeerTagError)?,
+            None => {
+                tags.remove(did, key).map_err(Error::PeerTagError)?;
+            }
+        }
+        if tags.is_denied(did) {
+            if let Some((_, transport)) = self.swarm.remove_transport(&did.into()) {
+                transport
+                    .close()
+                    .await
+                    .map_err(Error::CloseTransportError)?;
+            }
+        }
+        Ok(tags.get(did))
+    }
+
+    /// Peers seen, with public keys of their first contact, sorted by DID.
+    pub fn list_known_peers(&self) -> Vec<KnownPeerInfo> {
+        let mut peers = self.swarm.known_peers().items();
+        peers.sort_by_key(|(did, _)| *did);
+        peers
+            .into_iter()
+            .map(|(did, peer)| KnownPeerInfo::new(did, peer))
+            .collect()
+    }
+
+    /// Replace faults injected to outbound payloads if `config` is set, returns faults in use.
+    /// Only admin may call it.
+    #[cfg(feature = "chaos")]
+    pub fn inject_faults(&self, config: Option<FaultConfig>) -> Result<FaultConfig> {
+        self.require_admin(method::Method::InjectFaults)?;
+        let faults = self.swarm.faults();
+        if let Some(config) = config {
+            faults.set(config).map_err(Error::FaultError)?;
+        }
+        Ok(faults.config())
+    }
+
+    /// Get peer by remote address
+    pub async fn get_peer(&self, address: &str) -> Result<Peer> {
+        let address = Address::from(parse_did(address)?);
+        let transport = self
+            .swarm
+            .get_transport(&address)
+            .ok_or(Error::TransportNotFound)?;
+        Ok(Peer::from(&(address, transport)))
+    }
+
+    /// Disconnect a peer with web3 address.
+    pub async fn disconnect(&self, address: &str) -> Result<()> {
+        let address = Address::from(parse_did(address)?);
+        let transport = self
+            .swarm
+            .get_transport(&address)
+            .ok_or(Error::TransportNotFound)?;
+        transport
+            .close()
+            .await
+            .map_err(Error::CloseTransportError)?;
+        self.swarm.remove_transport(&address);
+        Ok(())
+    }
+
+    /// List all pending transport.
+    pub async fn list_pendings(&self) -> Result<Vec<Arc<Transport>>> {
+        let pendings = self
+            .swarm
+            .pending_transports()
+            .await
+            .map_err(|_| Error::InternalError)?;
+        Ok(pendings)
+    }
+
+    /// List ids of pending transports matching `filter` page by page, ordered by id, pass
+    /// `next_cursor` of a page to get the next one.
+    pub async fn list_pendings_page(
+        &self,
+        filter: &PeerFilter,
+        cursor: Option<&str>,
+    ) -> Result<PendingPage> {
+        let mut items = vec![];
+        if filter.did_prefix.is_none() && filter.tags.is_empty() {
+            for transport in self.list_pendings().await? {
+                if filter.match_transport(&transport).await {
+                    let id = transport.id.to_string();
+                    items.push((id.clone(), id));
+                }
+            }
+        }
+        let (transport_ids, next_cursor) = paginate(items, cursor, filter.limit);
+        Ok(PendingPage {
+            transport_ids,
+            next_cursor,
+        })
+    }
+
+    /// Close pending transport
+    pub async fn close_pending_transport(&self, transport_id: &str) -> Result<()> {
+        let transport_id =
+            uuid::Uuid::from_str(transport_id).map_err(|_| Error::InvalidTransportId)?;
+        let transport = self
+            .swarm
+            .find_pending_transport(transport_id)
+            .map_err(|_| Error::TransportNotFound)?
+            .ok_or(Error::TransportNotFound)?;
+        if transport.is_connected().await {
+            transport
+                .close()
+                .await
+                .map_err(Error::CloseTransportError)?;
+        }
+        self.swarm
+            .pop_pending_transport(transport_id)
+            .map_err(Error::CloseTransportError)?;
+        Ok(())
+    }
+
+    /// Refuse new connections and messages, hand over DHT data to successor,
+    /// and close all transports, service stops when it's done. Only admin may call it.
+    pub async fn drain(&self) -> Result<()> {
+        self.require_admin(method::Method::Drain)?;
+        self.msg_handler.drain().await.map_err(Error::DrainError)
+    }
+
+    /// Publish rotation of identity of this node by `record`, signed by keys of both DIDs.
+    pub async fn rotate_identity(&self, record: &RotationRecord) -> Result<()> {
+        self.require_admin(method::Method::RotateIdentity)?;
+        self.msg_handler
+            .rotate_identity(record)
+            .await
+            .map_err(Error::RotateIdentity)
+    }
+
+    /// State of stabilization task.
+    pub fn stabilization_status(&self) -> StabilizationStatus {
+        self.stabilization.status()
+    }
+
+    /// Pause, resume or trigger stabilization, or change its interval, returns the new state.
+    /// Only admin may call it.
+    pub fn control_stabilization(
+        &self,
+        control: StabilizationControl,
+    ) -> Result<StabilizationStatus> {
+        self.require_admin(method::Method::ControlStabilization)?;
+        match control {
+            StabilizationControl::Pause => self.stabilization.pause(),
+            StabilizationControl::Resume => self.stabilization.resume(),
+            StabilizationControl::Trigger => self
+                .stabilization
+                .trigger()
+                .map_err(Error::StabilizationError)?,
+            StabilizationControl::Interval { min, max } => {
+                if min == 0 || min > max {
+                    return Err(Error::InvalidStabilizationInterval(min, max));
+                }
+                self.stabilization.set_interval(min, max)
+            }
+        }
+        Ok(self.stabilization.status())
+    }
+
+    /// Run a full round of DHT repair now, returns what's changed. Only admin may call it.
+    pub async fn repair_dht(&self) -> Result<RepairReport> {
+        self.require_admin(method::Method::RepairDht)?;
+        self.stabilization
+            .repair()
+            .await
+            .map_err(Error::StabilizationError)
+    }
+
+    /// List virtual nodes stored by this node page by page, ordered by key, pass `next_cursor`
+    /// of a page to get the next one. For operators to audit what the node is hosting, only
+    /// admin may call it.
+    pub async fn list_local_data(
+        &self,
+        cursor: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<LocalDataPage> {
+        self.require_admin(method::Method::ListLocalData)?;
+        let storage = self.msg_handler.dht().lock().await.storage.clone();
+        // only keys are collected, data is read for the page alone
+        let keys = storage
+            .keys()
+            .into_iter()
+            .map(|k| (format!("{:?}", *k), k))
+            .collect();
+        let (page, next_cursor) = paginate(keys, cursor, limit);
+        let data = page
+            .iter()
+            .filter_map(|k| storage.get(k))
+            .map(LocalData::from)
+            .collect();
+        Ok(LocalDataPage { data, next_cursor })
+    }
+
+    /// Delete virtual node `key` stored by this node, returns what's deleted.
+    /// It's not forwarded to other nodes, publisher may store it again. Only admin may
+    /// call it.
+    pub async fn delete_local_data(&self, key: &str) -> Result<LocalData> {
+        self.require_admin(method::Method::DeleteLocalData)?;
+        let id = parse_did(key)?;
+        let dht = self.msg_handler.dht();
+        let (_, vnode) = dht
+            .lock()
+            .await
+            .storage
+            .remove(&id)
+            .ok_or_else(|| Error::LocalDataNotFound(key.to_owned()))?;
+        tracing::info!(key = %key, kind = ?vnode.kind, "local data deleted");
+        Ok(vnode.into())
+    }
+
+    /// Payloads recorded by packet capture, oldest first, clear the records if `clear` is set.
+    /// Only admin may call it.
+    pub fn captured_payloads(&self, clear: bool) -> Result<Vec<CapturedPayload>> {
+        self.require_admin(method::Method::CapturedPayloads)?;
+        self.swarm
+            .captured_payloads(clear)
+            .ok_or(Error::CaptureDisabled)
+    }
+
+    /// Traffic with each connected peer, or with `did` only if it's given.
+    pub fn peer_traffic(&self, did: Option<&str>) -> Result<Vec<PeerTrafficStats>> {
+        match did {
+            Some(did) => Ok(self
+                .swarm
+                .peer_traffic_of(parse_did(did)?)
+                .into_iter()
+                .collect()),
+            None => Ok(self.swarm.peer_traffic()),
+        }
+    }
+
+    /// Messages and bytes relayed for each peer in current period of accounting.
+    pub fn relay_usage(&self) -> Vec<RelayUsage> {
+        self.swarm.relay_accounting().usages()
+    }
+
+    /// Export DHT tables, keys of stored virtual nodes and connected peers. Only admin may
+    /// call it.
+    pub async fn export_state(&self) -> Result<StateSnapshot> {
+        self.require_admin(method::Method::ExportState)?;
+        let dht = self.msg_handler.dht();
+        let dht = dht.lock().await.snapshot();
+        let peers = self
+            .list_peers()
+            .await?
+            .into_iter()
+            .map(|p| p.into())
+            .collect();
+        Ok(StateSnapshot {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            address: format!("{:?}", self.address()),
+            network_id: self.swarm.meta().network_id.clone(),
+            dht,
+            peers,
+        })
+    }
+
+    /// Load DHT tables of a snapshot, for offline analysis or reproducing a test case.
+    /// Peers of snapshot are not connected, and stored data is not restored. Only admin may
+    /// call it.
+    pub async fn import_state(&self, snapshot: &StateSnapshot) -> Result<()> {
+        self.require_admin(method::Method::ImportState)?;
+        let meta = self.swarm.meta();
+        if snapshot.network_id != meta.network_id {
+            tracing::warn!(
+                network_id = %snapshot.network_id,
+                "importing state of another network"
+            );
+        }
+        let dht = self.msg_handler.dht();
+        dht.lock().await.restore(&snapshot.dht);
+        Ok(())
+    }
+
+    /// List received custom messages matching `filter`, starting after `cursor`.
+    #[cfg(feature = "client")]
+    pub fn list_messages(
+        &self,
+        filter: &HistoryFilter,
+        cursor: Option<&str>,
+    ) -> Result<HistoryPage> {
+        self.msg_handler
+            .history()
+            .ok_or(Error::HistoryDisabled)?
+            .list(filter, cursor)
+            .map_err(Error::HistoryError)
+    }
+
+    /// Presence of `did`, from local cache or DHT, waits up to `timeout_ms` for a remote record.
+    #[cfg(feature = "client")]
+    pub async fn query_presence(&self, did: &str, timeout_ms: u64) -> Result<PresenceInfo> {
+        let did = parse_did(did)?;
+        let record = self
+            .fetch_presence(did, timeout_ms)
+            .await
+            .map_err(Error::PresenceError)?;
+        Ok(PresenceInfo::new(did, record.as_ref()))
+    }
+
+    /// Presence record of `did`, from local cache or DHT, the online one is preferred.
+    #[cfg(feature = "client")]
+    async fn fetch_presence(
+        &self,
+        did: Did,
+        timeout_ms: u64,
+    ) -> CoreResult<Option<PresenceRecord>> {
+        let id = VirtualNode::presence_address(did)?;
+        let online = |v: &VirtualNode| PresenceRecord::from_vnode(v).ok().filter(|r| r.is_online());
+        if let Some(r) = self
+            .msg_handler
+            .check_cache(&id)
+            .await
+            .as_ref()
+            .and_then(online)
+        {
+            return Ok(Some(r));
+        }
+        let vnode = self
+            .fetch_vnode(&id, timeout_ms, |v| online(v).is_some())
+            .await?;
+        Ok(vnode.and_then(|v| PresenceRecord::from_vnode(&v).ok()))
+    }
+
+    /// `destination` is connected, reached by relay, or announced its presence lately.
+    #[cfg(feature = "client")]
+    async fn is_online(&self, destination: Did) -> bool {
+        if self
+            .swarm
+            .get_transport(&Address::from(destination))
+            .is_some()
+            || self.swarm.relayed().is_unreachable(destination)
+        {
+            return true;
+        }
+        match self.fetch_presence(destination, PRESENCE_TIMEOUT_MS).await {
+            Ok(record) => record.map_or(false, |r| r.is_online()),
+            Err(e) => {
+                tracing::debug!(destination = ?destination, "failed to fetch presence: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Fetch vnode `id` from DHT, waits up to `timeout_ms` for a cached one which is `accepted`.
+    /// Stale cache is dropped before fetching, and returned if nothing arrives in time.
+    #[cfg(feature = "client")]
+    async fn fetch_vnode<F>(
+        &self,
+        id: &Did,
+        timeout_ms: u64,
+        accepted: F,
+    ) -> CoreResult<Option<VirtualNode>>
+    where
+        F: Fn(&VirtualNode) -> bool,
+    {
+        let stale = {
+            let dht = self.msg_handler.dht();
+            let dht = dht.lock().await;
+            dht.cache.remove(id).map(|(_, v)| v)
+        };
+        self.msg_handler.fetch(id).await?;
+        let started = std::time::Instant::now();
+        loop {
+            let cached = self.msg_handler.check_cache(id).await;
+            if cached.as_ref().map_or(false, &accepted) {
+                return Ok(cached);
+            }
+            if started.elapsed().as_millis() >= timeout_ms as u128 {
+                return Ok(cached.or(stale));
+            }
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Track presence of `did`, changes are pushed to subscribers of
+    /// [rings_core::presence::PresenceTracker], like [crate::node::NodeHandle::subscribe_presence]
+    /// and `on_presence` of browser client.
+    pub fn track_presence(&self, did: &str) -> Result<()> {
+        self.swarm.presence().track(parse_did(did)?);
+        Ok(())
+    }
+
+    /// Stop tracking presence of `did`.
+    pub fn untrack_presence(&self, did: &str) -> Result<()> {
+        self.swarm.presence().untrack(&parse_did(did)?);
+        Ok(())
+    }
+
+    /// Tracked DIDs with their last known presence.
+    pub fn tracked_presence(&self) -> Vec<(Did, bool)> {
+        self.swarm.presence().tracked()
+    }
+
+    /// Create group `name` administrated by this node, or replace its members if this node is
+    /// admin of it. Fails if a group of another admin is found in `timeout_ms`, which is never
+    /// replaced, see [GroupRecord].
+    #[cfg(feature = "client")]
+    pub async fn create_group(
+        &self,
+        name: &str,
+        members: &[String],
+        timeout_ms: u64,
+    ) -> Result<GroupRecord> {
+        let members = members
+            .iter()
+            .map(|m| parse_did(m))
+            .collect::<Result<Vec<_>>>()?;
+        let me: Did = self.address().into();
+        match self.fetch_group(name, timeout_ms).await {
+            Ok(existing) if existing.data.admin != me => {
+                return Err(Error::GroupNameTaken(name.to_owned()))
+            }
+            Ok(_) | Err(Error::GroupNotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+        let group = Group::new(name, me, &members).map_err(Error::GroupError)?;
+        let record =
+            GroupRecord::new(self.swarm.session_manager(), group).map_err(Error::GroupError)?;
+        let subring = record.data.to_subring().map_err(Error::GroupError)?;
+        self.msg_handler
+            .store(subring.try_into().map_err(Error::GroupError)?)
+            .await
+            .map_err(Error::GroupError)?;
+        self.msg_handler
+            .store(record.to_vnode().map_err(Error::GroupError)?)
+            .await
+            .map_err(Error::GroupError)?;
+        Ok(record)
+    }
+
+    /// Fetch membership of group `name` from DHT, waits up to `timeout_ms`.
+    #[cfg(feature = "client")]
+    pub async fn fetch_group(&self, name: &str, timeout_ms: u64) -> Result<GroupRecord> {
+        let id = VirtualNode::group_address(name).map_err(Error::GroupError)?;
+        let valid = |v: &VirtualNode| GroupRecord::check_vnode(v).ok();
+        self.fetch_vnode(&id, timeout_ms, |v| valid(v).is_some())
+            .await
+            .map_err(Error::GroupError)?
+            .as_ref()
+            .and_then(valid)
+            .ok_or_else(|| Error::GroupNotFound(name.to_owned()))
+    }
+
+    /// Append `data` to topic `name` as this node, after its latest record found in
+    /// `timeout_ms`, see [rings_core::topic].
+    #[cfg(feature = "client")]
+    pub async fn publish_topic(
+        &self,
+        name: &str,
+        data: &str,
+        timeout_ms: u64,
+    ) -> Result<TopicRecord> {
+        let log = self.fetch_topic(name, timeout_ms).await?;
+        let me: Did = self.address().into();
+        let record = TopicRecord::new(self.swarm.session_manager(), name, log.next(me), data)
+            .map_err(Error::TopicError)?;
+        self.msg_handler
+            .store(record.to_vnode().map_err(Error::TopicError)?)
+            .await
+            .map_err(Error::TopicError)?;
+        Ok(record)
+    }
+
+    /// Fetch history of topic `name` from DHT, waits up to `timeout_ms`. Topic without
+    /// records found is empty.
+    #[cfg(feature = "client")]
+    pub async fn fetch_topic(&self, name: &str, timeout_ms: u64) -> Result<TopicLog> {
+        let id = VirtualNode::topic_address(name).map_err(Error::TopicError)?;
+        let vnode = self
+            .fetch_vnode(&id, timeout_ms, |v| TopicLog::from_vnode(v).is_ok())
+            .await
+            .map_err(Error::TopicError)?;
+        match vnode {
+            Some(v) => TopicLog::from_vnode(&v).map_err(Error::TopicError),
+            None => Ok(TopicLog::default()),
+        }
+    }
+
+    /// Establish a new key of group `name`, which this node should be admin of. The key is
+    /// wrapped to session key of every member, found in manifests of them, members without a
+    /// manifest in `timeout_ms` are skipped until next rotation.
+    #[cfg(feature = "client")]
+    pub async fn rotate_group_key(&self, name: &str, timeout_ms: u64) -> Result<GroupKeyInfo> {
+        let record = self.fetch_group(name, timeout_ms).await?;
+        let me: Did = self.address().into();
+        if record.data.admin != me {
+            return Err(Error::NotGroupAdmin(name.to_owned()));
+        }
+        let session_manager = self.swarm.session_manager();
+        let session_key = session_manager.session_key().map_err(Error::GroupError)?;
+        let mut members = vec![(me, session_key.pubkey())];
+        let mut skipped = vec![];
+        for member in record.data.members.iter().filter(|m| **m != me) {
+            let pubkey = self
+                .fetch_manifest(*member, timeout_ms)
+                .await
+                .and_then(|r| r.session_pubkey().map_err(Error::ManifestError));
+            match pubkey {
+                Ok(pubkey) => members.push((*member, pubkey)),
+                Err(e) => {
+                    tracing::warn!(
+                        group = name,
+                        member = ?**member,
+                        "skip member in group key: {}",
+                        e
+                    );
+                    skipped.push(format!("{:?}", **member));
+                }
+            }
+        }
+        let key = GroupKey::random();
+        let key_record = GroupKeyRecord::new(session_manager, &record.data, &key, &members)
+            .map_err(Error::GroupError)?;
+        self.msg_handler
+            .store(key_record.to_vnode().map_err(Error::GroupError)?)
+            .await
+            .map_err(Error::GroupError)?;
+        self.swarm
+            .group_keys()
+            .insert(name, key)
+            .map_err(Error::GroupError)?;
+        Ok(GroupKeyInfo::new(&key_record, skipped))
+    }
+
+    /// Fetch latest key of group `name` from DHT into keyring of node, messages to group are
+    /// sealed by it since then. The key should be established by admin of group and shared
+    /// with this node.
+    #[cfg(feature = "client")]
+    pub async fn fetch_group_key(&self, name: &str, timeout_ms: u64) -> Result<GroupKeyInfo> {
+        let group = self.fetch_group(name, timeout_ms).await?.data;
+        let me: Did = self.address().into();
+        let id = VirtualNode::group_key_address(name).map_err(Error::GroupError)?;
+        let valid = |v: &VirtualNode| {
+            GroupKeyRecord::check_vnode(v)
+                .ok()
+                .filter(|r| r.data.admin == group.admin)
+        };
+        let record = self
+            .fetch_vnode(&id, timeout_ms, |v| valid(v).is_some())
+            .await
+            .map_err(Error::GroupError)?
+            .as_ref()
+            .and_then(valid)
+            .ok_or_else(|| Error::GroupError(CoreError::GroupKeyNotShared(format!("{:?}", *me))))?;
+        let session_key = self
+            .swarm
+            .session_manager()
+            .session_key()
+            .map_err(Error::GroupError)?;
+        let key = record
+            .unwrap_key(me, &session_key)
+            .map_err(Error::GroupError)?;
+        self.swarm
+            .group_keys()
+            .insert(name, key)
+            .map_err(Error::GroupError)?;
+        Ok(GroupKeyInfo::new(&record, vec![]))
+    }
+
+    /// Send message to all other members of group `name`, which this node should be a member of.
+    /// Messages to offline members are stored in their inboxes for `inbox_ttl_ms`. Message
+    /// is sealed by latest key of group in keyring, if there is one, see [Self::fetch_group_key].
+    #[cfg(feature = "client")]
+    pub async fn send_to_group(
+        &self,
+        name: &str,
+        msg: &[u8],
+        inbox_ttl_ms: u128,
+        timeout_ms: u64,
+    ) -> Result<GroupSendResult> {
+        let record = self.fetch_group(name, timeout_ms).await?;
+        let me: Did = self.address().into();
+        if !record.data.is_member(&me) {
+            return Err(Error::NotGroupMember(name.to_owned()));
+        }
+        let data = match self.swarm.group_keys().latest(name) {
+            Some(key) => GroupMessage::sealed(name, &key, msg),
+            None => Ok(GroupMessage {
+                group: name.to_owned(),
+                data: msg.to_vec(),
+                epoch: None,
+            }),
+        }
+        .and_then(|m| m.to_bytes())
+        .map_err(Error::GroupError)?;
+        let mut result = GroupSendResult::default();
+        for member in record.data.members.iter().filter(|m| **m != me) {
+            let destination = format!("{:?}", **member);
+            match self
+                .send_message_or_store(&destination, &data, inbox_ttl_ms)
+                .await
+            {
+                Ok(()) => result.sent.push(destination),
+                Err(e) => {
+                    tracing::warn!(group = name, destination = %destination, "failed to send to member: {}", e);
+                    result.failed.push(destination)
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Split file at `path` in share directory into chunks, and store them with its manifest on
+    /// DHT. Only admin may call it.
+    #[cfg(feature = "client")]
+    pub async fn send_file(&self, path: &str) -> Result<FileInfo> {
+        self.require_admin(method::Method::SendFile)?;
+        let path = share_path(self.share_dir()?, Path::new(path)).await?;
+        let data = tokio::fs::read(&path)
+            .await
+            .map_err(|e| Error::FileTransfer(format!("{}: {}", path.display(), e)))?;
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let (manifest, chunks) = FileManifest::split(&name, &data, DEFAULT_CHUNK_SIZE)
+            .map_err(|e| Error::FileTransfer(e.to_string()))?;
+        let vnode = manifest
+            .to_vnode()
+            .map_err(|e| Error::FileTransfer(e.to_string()))?;
+        let id = vnode.address;
+        for chunk in chunks.into_iter().chain(std::iter::once(vnode)) {
+            self.msg_handler
+                .store(chunk)
+                .await
+                .map_err(|e| Error::FileTransfer(e.to_string()))?;
+        }
+        tracing::info!(file_id = ?id, name = %name, size = manifest.size, "file stored");
+        Ok(FileInfo::new(id, &manifest))
+    }
+
+    /// Fetch file `id` to `output` in share directory, or to name of file there if it's None.
+    /// Chunks are kept in `{output}.part` until all are fetched, so an interrupted transfer
+    /// resumes from there. Progress is pushed to subscribers of [Swarm::file_transfers].
+    /// Only admin may call it.
+    #[cfg(feature = "client")]
+    pub async fn fetch_file(
+        &self,
+        id: &str,
+        output: Option<&str>,
+        timeout_ms: u64,
+    ) -> Result<TransferProgress> {
+        self.require_admin(method::Method::FetchFile)?;
+        let share_dir = self.share_dir()?;
+        let file_id = parse_did(id)?;
+        let manifest = self
+            .fetch_vnode(&file_id, timeout_ms, |v| {
+                FileManifest::from_vnode(&file_id, v).is_ok()
+            })
+            .await
+            .map_err(|e| Error::FileTransfer(e.to_string()))?
+            .and_then(|v| FileManifest::from_vnode(&file_id, &v).ok())
+            .ok_or_else(|| Error::FileNotFound(id.to_owned()))?;
+        // never write outside of share directory, with name from remote or not
+        let output = match output {
+            Some(o) => PathBuf::from(o),
+            None => Path::new(&manifest.name)
+                .file_name()
+                .map(PathBuf::from)
+                .ok_or_else(|| Error::FileTransfer("file has no name".to_owned()))?,
+        };
+        let parts = share_path(share_dir, Path::new(&format!("{}.part", output.display()))).await?;
+        let output = share_path(share_dir, &output).await?;
+        let io_err = |e: std::io::Error| Error::FileTransfer(e.to_string());
+        tokio::fs::create_dir_all(&parts).await.map_err(io_err)?;
+
+        let transfers = self.swarm.file_transfers();
+        let mut progress = TransferProgress::new(file_id, &manifest);
+        let mut missing = vec![];
+        for i in 0..manifest.chunks.len() {
+            match tokio::fs::metadata(parts.join(i.to_string())).await {
+                Ok(m) => {
+                    progress.done_chunks += 1;
+                    progress.done_bytes += m.len();
+                }
+                Err(_) => missing.push(i),
+            }
+        }
+        transfers.update(progress.clone());
+
+        for window in missing.chunks(FETCH_CHUNK_WINDOW) {
+            let fetched = futures::future::join_all(window.iter().map(|i| {
+                let manifest = &manifest;
+                self.fetch_vnode(&manifest.chunks[*i], timeout_ms, move |v| {
+                    manifest.decode_chunk(*i, v).is_ok()
+                })
+            }))
+            .await;
+            for (i, vnode) in window.iter().zip(fetched) {
+                let data = vnode
+                    .ok()
+                    .flatten()
+                    .and_then(|v| manifest.decode_chunk(*i, &v).ok())
+                    .ok_or_else(|| {
+                        Error::FileTransfer(format!("chunk {} of {} not found", i, id))
+                    })?;
+                // rename after written, so a part is never truncated
+                let tmp = parts.join(format!("{}.tmp", i));
+                tokio::fs::write(&tmp, &data).await.map_err(io_err)?;
+                tokio::fs::rename(&tmp, parts.join(i.to_string()))
+                    .await
+                    .map_err(io_err)?;
+                progress.done_chunks += 1;
+                progress.done_bytes += data.len() as u64;
+                transfers.update(progress.clone());
+            }
+        }
+
+        let mut data = Vec::with_capacity(manifest.size as usize);
+        for i in 0..manifest.chunks.len() {
+            data.extend(
+                tokio::fs::read(parts.join(i.to_string()))
+                    .await
+                    .map_err(io_err)?,
+            );
+        }
+        tokio::fs::write(&output, &data).await.map_err(io_err)?;
+        tokio::fs::remove_dir_all(&parts).await.map_err(io_err)?;
+        tracing::info!(file_id = id, output = %output.display(), "file fetched");
+        Ok(progress)
+    }
+
+    /// Store `value` erasure coded on DHT, in `data_shards` shards and `parity_shards` more,
+    /// see [rings_core::erasure].
+    #[cfg(feature = "client")]
+    pub async fn store_erasure(
+        &self,
+        value: &[u8],
+        data_shards: usize,
+        parity_shards: usize,
+    ) -> Result<ErasureInfo> {
+        let manifest = self
+            .msg_handler
+            .store_erasure(value, data_shards, parity_shards)
+            .await
+            .map_err(Error::ErasureError)?;
+        let id = manifest.address().map_err(Error::ErasureError)?;
+        tracing::info!(erasure_id = ?id, len = manifest.len, "erasure coded value stored");
+        Ok(ErasureInfo::new(id, &manifest))
+    }
+
+    /// Fetch value erasure coded at `id`, waits up to `timeout_ms` for each shard. Shards are
+    /// fetched at once, and any `data_shards` valid ones of them reconstruct it.
+    #[cfg(feature = "client")]
+    pub async fn fetch_erasure(&self, id: &str, timeout_ms: u64) -> Result<Vec<u8>> {
+        let key = parse_did(id)?;
+        let manifest = self
+            .fetch_vnode(&key, timeout_ms, |v| ErasureManifest::from_vnode(v).is_ok())
+            .await
+            .map_err(Error::ErasureError)?
+            .and_then(|v| ErasureManifest::from_vnode(&v).ok())
+            .ok_or_else(|| Error::ErasureNotFound(id.to_owned()))?;
+        let addresses = manifest.shard_addresses().map_err(Error::ErasureError)?;
+        let shards = futures::future::join_all(addresses.iter().enumerate().map(|(i, address)| {
+            let manifest = &manifest;
+            self.fetch_vnode(address, timeout_ms, move |v| {
+                manifest.decode_shard(i, v).is_ok()
+            })
+        }))
+        .await
+        .into_iter()
+        .map(|v| v.ok().flatten())
+        .collect::<Vec<_>>();
+        manifest.join(&shards).map_err(Error::ErasureError)
+    }
+
+    /// Send custom message to an address, routed along DHT path if it's not connected.
+    pub async fn send_message(&self, destination: &str, msg: &[u8]) -> Result<()> {
+        tracing::info!(destination, "send_message, text: {:?}", msg);
+        let destination = self.resolve_rotated(parse_did(destination)?).await;
+        let msg = Message::custom(msg, &None).map_err(Error::SendMessage)?;
+        self.deliver_message(destination, msg, None).await
+    }
+
+    /// Send custom message to an address like [Self::send_message], and ask it for a receipt
+    /// with latency of each hop, see [Self::delivery_receipt]. Returns `tx_id` of the message.
+    pub async fn send_message_with_receipt(&self, destination: &str, msg: &[u8]) -> Result<String> {
+        tracing::info!(destination, "send_message_with_receipt, text: {:?}", msg);
+        let destination = self.resolve_rotated(parse_did(destination)?).await;
+        let msg = Message::custom(msg, &None).map_err(Error::SendMessage)?;
+        self.msg_handler
+            .send_with_receipt(msg, destination)
+            .await
+            .map(|tx_id| tx_id.inner())
+            .map_err(Error::SendMessage)
+    }
+
+    /// Receipt of message `tx_id` sent by [Self::send_message_with_receipt], None if it's not
+    /// delivered yet, or it's delivered too long ago.
+    pub fn delivery_receipt(&self, tx_id: &str) -> Option<DeliveryReceipt> {
+        self.msg_handler.delivery_receipt(&HashStr::new(tx_id))
+    }
+
+    /// Send custom message to an address, if it's offline, store the message in its inbox
+    /// for `inbox_ttl_ms`, and it's delivered when the address is online. Stored message is
+    /// encrypted to the address, see [Self::send_encrypted_message].
+    #[cfg(feature = "client")]
+    pub async fn send_message_or_store(
+        &self,
+        destination: &str,
+        msg: &[u8],
+        inbox_ttl_ms: u128,
+    ) -> Result<()> {
+        let destination = self.resolve_rotated(parse_did(destination)?).await;
+        let msg = Message::custom(msg, &None).map_err(Error::SendMessage)?;
+        self.deliver_message(destination, msg, Some(inbox_ttl_ms))
+            .await
+    }
+
+    /// Send custom message encrypted to public key which `destination` published to DHT, so
+    /// it's read by nobody else, even before any direct contact. Stored in inbox of destination
+    /// for `inbox_ttl_ms` if it's given and destination is offline.
+    #[cfg(feature = "client")]
+    pub async fn send_encrypted_message(
+        &self,
+        destination: &str,
+        msg: &[u8],
+        inbox_ttl_ms: Option<u128>,
+    ) -> Result<()> {
+        // encrypted to key of the current identity
+        let destination = self.resolve_rotated(parse_did(destination)?).await;
+        let msg = Message::custom_to(msg, destination, self)
+            .await
+            .map_err(Error::SendMessage)?;
+        self.deliver_message(destination, msg, inbox_ttl_ms).await
+    }
+
+    /// Current DID of `destination`, which may have rotated its identity. Peers rotated while
+    /// this node was away are only known to DHT, they are looked up.
+    async fn resolve_rotated(&self, destination: Did) -> Did {
+        #[cfg(feature = "client")]
+        if self
+            .swarm
+            .get_transport(&Address::from(destination))
+            .is_none()
+            && self.swarm.rotations().needs_lookup(destination)
+        {
+            if let Err(e) = self.fetch_rotation(destination, ROTATION_TIMEOUT_MS).await {
+                tracing::debug!(destination = ?destination, "failed to look up rotation: {}", e);
+            }
+        }
+        self.swarm.rotations().resolve(destination)
+    }
+
+    /// Send `msg` to `destination`, or store it in its inbox for `inbox_ttl_ms` if it's given
+    /// and destination is offline. Callers resolve `destination` by [Self::resolve_rotated].
+    async fn deliver_message(
+        &self,
+        destination: Did,
+        msg: Message,
+        inbox_ttl_ms: Option<u128>,
+    ) -> Result<()> {
+        #[cfg(feature = "client")]
+        if let Some(ttl) = inbox_ttl_ms {
+            if !self.is_online(destination).await {
+                return self.store_in_inbox(destination, msg, ttl).await;
+            }
+        }
+        #[cfg(not(feature = "client"))]
+        let _ = inbox_ttl_ms;
+        self.msg_handler
+            .send_app_message(msg, destination)
+            .await
+            .map_err(Error::SendMessage)
+    }
+
+    /// Store `msg` in inbox of `destination` for `inbox_ttl_ms`, plain message is encrypted to
+    /// public key of destination first, so holders of inbox never read it.
+    #[cfg(feature = "client")]
+    async fn store_in_inbox(
+        &self,
+        destination: Did,
+        msg: Message,
+        inbox_ttl_ms: u128,
+    ) -> Result<()> {
+        let msg = match msg {
+            Message::CustomMessage(MaybeEncrypted::Plain(plain)) => {
+                Message::custom_to(&plain.0, destination, self)
+                    .await
+                    .map_err(Error::SendMessage)?
+            }
+            msg => msg,
+        };
+        tracing::info!(
+            destination = ?destination,
+            "destination is offline, store message in inbox"
+        );
+        let payload = MessagePayload::new_direct(msg, self.swarm.session_manager(), destination)
+            .map_err(Error::MessagePayload)?;
+        self.msg_handler
+            .store_offline(payload, inbox_ttl_ms)
+            .await
+            .map_err(Error::SendMessage)
+    }
 
-        let hs_info = transport
-            .get_handshake_info(self.swarm.session_manager(), RTCSdpType::Answer)
+    /// Provide service `name`, its record is stored now and refreshed by stabilization.
+    pub async fn register_service(&self, name: &str) -> Result<()> {
+        let record = ServiceRecord::new(self.swarm.session_manager(), name, DEFAULT_SERVICE_TTL_MS)
+            .map_err(Error::ServiceError)?;
+        self.msg_handler
+            .store(record.to_vnode().map_err(Error::ServiceError)?)
             .await
-            .map_err(Error::CreateAnswer)?;
-        log::debug!("answer hs_info: {:?}", hs_info);
-        Ok(hs_info)
+            .map_err(Error::ServiceError)?;
+        self.swarm.services().register(name);
+        Ok(())
     }
 
-    /// Accept an answer of a connection.
-    /// The process of manually handshake is:
-    /// 1. PeerA: create_offer
-    /// 2. PeerA: send the handshake info to PeerB.
-    /// 3. PeerB: answer_offer
-    /// 4. PeerB: send the handshake info to PeerA.
-    /// 5. PeerA: accept_answer.
-    pub async fn accept_answer(&self, transport_id: &str, ice: &str) -> Result<Peer> {
-        let ice = Encoded::from_encoded_str(ice);
-        log::debug!("accept_answer/ice: {:?}, uuid: {}", ice, transport_id);
-        let transport_id =
-            uuid::Uuid::from_str(transport_id).map_err(|_| Error::InvalidTransportId)?;
-        let transport = self
-            .swarm
-            .find_pending_transport(transport_id)
-            .map_err(Error::PendingTransport)?
-            .ok_or(Error::TransportNotFound)?;
-        let addr = transport
-            .register_remote_info(ice)
-            .await
-            .map_err(Error::RegisterIceError)?;
-        self.swarm
-            .register(&addr, transport.clone())
-            .await
-            .map_err(Error::RegisterIceError)?;
-        if let Err(e) = self.swarm.pop_pending_transport(transport.id) {
-            log::warn!("pop_pending_transport err: {}", e)
-        };
-        Ok(Peer::from((addr, transport)))
+    /// Stop providing service `name`, returns false if it's not registered.
+    pub fn unregister_service(&self, name: &str) -> bool {
+        self.swarm.services().unregister(name)
     }
 
-    /// List all peers.
-    pub async fn list_peers(&self) -> Result<Vec<Peer>> {
-        let transports = self.swarm.get_transports();
-        log::debug!(
-            "addresses: {:?}",
-            transports.iter().map(|(a, _b)| a).collect::<Vec<_>>()
-        );
-        let data = transports.iter().map(|x| x.into()).collect::<Vec<Peer>>();
-        Ok(data)
+    /// Names of services provided by this node.
+    pub fn registered_services(&self) -> Vec<String> {
+        self.swarm.services().list()
     }
 
-    /// Get peer by remote address
-    pub async fn get_peer(&self, address: &str) -> Result<Peer> {
-        let address = Address::from_str(address).map_err(|_| Error::InvalidAddress)?;
-        let transport = self
-            .swarm
-            .get_transport(&address)
-            .ok_or(Error::TransportNotFound)?;
-        Ok(Peer::from(&(address, transport)))
+    /// Verified manifest of `did`, from local cache or DHT, waits up to `timeout_ms` for a
+    /// remote record.
+    #[cfg(feature = "client")]
+    pub async fn whois(&self, did: &str, timeout_ms: u64) -> Result<ManifestInfo> {
+        let did = parse_did(did)?;
+        if did == self.address().into() {
+            let record = ManifestRecord::new(
+                self.swarm.session_manager(),
+                self.swarm.manifest(),
+                DEFAULT_MANIFEST_TTL_MS,
+            )
+            .map_err(Error::ManifestError)?;
+            return Ok(ManifestInfo::from(&record));
+        }
+        let record = self.fetch_manifest(did, timeout_ms).await?;
+        Ok(ManifestInfo::from(&record))
     }
 
-    /// Disconnect a peer with web3 address.
-    pub async fn disconnect(&self, address: &str) -> Result<()> {
-        let address = Address::from_str(address).map_err(|_| Error::InvalidAddress)?;
-        let transport = self
-            .swarm
-            .get_transport(&address)
-            .ok_or(Error::TransportNotFound)?;
-        transport
-            .close()
+    /// Verified manifest of remote `did` from DHT, waits up to `timeout_ms`.
+    #[cfg(feature = "client")]
+    async fn fetch_manifest(&self, did: Did, timeout_ms: u64) -> Result<ManifestRecord> {
+        let id = VirtualNode::manifest_address(did).map_err(Error::ManifestError)?;
+        // record of another node may be replayed at address of `did`, it's refused by address
+        let valid = |v: &VirtualNode| ManifestRecord::check_vnode(v).ok();
+        let vnode = self
+            .fetch_vnode(&id, timeout_ms, |v| valid(v).is_some())
             .await
-            .map_err(Error::CloseTransportError)?;
-        self.swarm.remove_transport(&address);
-        Ok(())
+            .map_err(Error::ManifestError)?;
+        vnode
+            .as_ref()
+            .and_then(valid)
+            .ok_or_else(|| Error::ManifestNotFound(format!("{:?}", *did)))
     }
 
-    /// List all pending transport.
-    pub async fn list_pendings(&self) -> Result<Vec<Arc<Transport>>> {
-        let pendings = self
-            .swarm
-            .pending_transports()
-            .await
-            .map_err(|_| Error::InternalError)?;
-        Ok(pendings)
+    /// Verified public key of remote `did` from DHT, waits up to `timeout_ms`.
+    #[cfg(feature = "client")]
+    async fn fetch_pubkey(&self, did: Did, timeout_ms: u64) -> CoreResult<PublicKey> {
+        let id = VirtualNode::pubkey_address(did)?;
+        // record of another node may be replayed at address of `did`, it's refused by address
+        let valid = |v: &VirtualNode| PubkeyRecord::check_vnode(v).ok();
+        let vnode = self
+            .fetch_vnode(&id, timeout_ms, |v| valid(v).is_some())
+            .await?;
+        vnode
+            .as_ref()
+            .and_then(valid)
+            .map(|r| r.data.pubkey)
+            .ok_or_else(|| CoreError::PubkeyNotFound(format!("{:?}", *did)))
     }
 
-    /// Close pending transport
-    pub async fn close_pending_transport(&self, transport_id: &str) -> Result<()> {
-        let transport_id =
-            uuid::Uuid::from_str(transport_id).map_err(|_| Error::InvalidTransportId)?;
-        let transport = self
-            .swarm
-            .find_pending_transport(transport_id)
-            .map_err(|_| Error::TransportNotFound)?
-            .ok_or(Error::TransportNotFound)?;
-        if transport.is_connected().await {
-            transport
-                .close()
-                .await
-                .map_err(Error::CloseTransportError)?;
+    /// Learn rotation of remote `did` from DHT, waits up to `timeout_ms`, returns the new DID.
+    #[cfg(feature = "client")]
+    async fn fetch_rotation(&self, did: Did, timeout_ms: u64) -> CoreResult<Option<Did>> {
+        let id = VirtualNode::rotation_address(did)?;
+        let valid = |v: &VirtualNode| RotationRecord::check_vnode(v).ok();
+        let vnode = self
+            .fetch_vnode(&id, timeout_ms, |v| valid(v).is_some())
+            .await?;
+        Ok(vnode.as_ref().and_then(valid).map(|r| {
+            if self.swarm.rotations().insert(&r) {
+                tracing::info!(old = ?r.data.old, new = ?r.data.new, "learned rotation from DHT");
+            }
+            r.data.new
+        }))
+    }
+
+    /// Send `count` messages of `size` bytes to `did`, which should run in echo mode, keeping
+    /// `concurrency` of them in flight, and measure how fast they are echoed. Benchmark stops
+    /// if no echo arrives in `timeout_ms`, messages in flight then are lost. `count` and `size`
+    /// are capped by [MAX_BENCHMARK_COUNT] and [MAX_BENCHMARK_SIZE]. Only admin may call it.
+    #[cfg(feature = "client")]
+    pub async fn benchmark(
+        &self,
+        did: &str,
+        size: usize,
+        count: usize,
+        concurrency: usize,
+        timeout_ms: u64,
+    ) -> Result<BenchmarkReport> {
+        self.require_admin(method::Method::Benchmark)?;
+        let peer = parse_did(did)?;
+        let size = size.min(MAX_BENCHMARK_SIZE);
+        let count = count.min(MAX_BENCHMARK_COUNT);
+        let mut echoes = self
+            .msg_handler
+            .subscribe_custom(CallbackFilter::default().sender(peer));
+        let concurrency = concurrency.max(1);
+        let started = std::time::Instant::now();
+        let mut in_flight = HashMap::new();
+        let mut latencies_ms = vec![];
+        let mut failed = 0;
+        let mut next = 0;
+        while next < count || !in_flight.is_empty() {
+            while in_flight.len() < concurrency && next < count {
+                let msg = Message::custom(&benchmark_data(next as u64, size), &None)
+                    .map_err(Error::SendMessage)?;
+                match self.msg_handler.send_app_message(msg, peer).await {
+                    Ok(()) => {
+                        in_flight.insert(next as u64, std::time::Instant::now());
+                    }
+                    Err(e) => {
+                        tracing::debug!(peer = did, seq = next, "failed to send benchmark: {}", e);
+                        failed += 1;
+                    }
+                }
+                next += 1;
+            }
+            if in_flight.is_empty() {
+                continue;
+            }
+            let echo = tokio::time::timeout(
+                tokio::time::Duration::from_millis(timeout_ms),
+                echoes.next(),
+            )
+            .await;
+            let msg = match echo {
+                Ok(Some((_, msg))) => msg,
+                _ => {
+                    tracing::warn!(peer = did, "{} benchmark messages lost", in_flight.len());
+                    break;
+                }
+            };
+            let sent = strip_echo(&msg.0)
+                .and_then(benchmark_seq)
+                .and_then(|seq| in_flight.remove(&seq));
+            if let Some(sent) = sent {
+                latencies_ms.push(sent.elapsed().as_secs_f64() * 1000.0);
+            }
         }
-        self.swarm
-            .pop_pending_transport(transport_id)
-            .map_err(Error::CloseTransportError)?;
-        Ok(())
+        // messages not sent when benchmark stops early are not counted
+        Ok(BenchmarkReport::new(
+            next - failed,
+            failed,
+            size,
+            latencies_ms,
+            started.elapsed().as_millis() as u64,
+        ))
     }
 
-    /// Send custom message to an address.
-    pub async fn send_message(&self, destination: &str, msg: &[u8]) -> Result<()> {
-        log::info!(
-            "send_message, destination: {}, text: {:?}",
-            destination,
-            msg,
-        );
-        let destination = Address::from_str(destination).map_err(|_| Error::InvalidAddress)?;
-        let msg = Message::custom(msg, &None).map_err(Error::SendMessage)?;
-        // self.swarm.do_send_payload(address, payload)
-        self.swarm
-            .send_direct_message(msg, destination.into())
+    /// Walk ring from this node by rounds, asking at most [CRAWL_BREADTH] nodes at once for
+    /// their neighbours, the nearest unvisited successors reported clockwise are asked in next
+    /// round. It stops when the ring closes, or `max_nodes`, at most [MAX_CRAWL_NODES], are
+    /// visited, or after [MAX_CRAWL_DEPTH] rounds. A node which doesn't answer in `timeout_ms`
+    /// is marked dead. Manifests are fetched if `with_manifests`. Only admin may call it.
+    #[cfg(feature = "client")]
+    pub async fn crawl(
+        &self,
+        max_nodes: usize,
+        timeout_ms: u64,
+        with_manifests: bool,
+    ) -> Result<CrawlReport> {
+        self.require_admin(method::Method::Crawl)?;
+        let max_nodes = max_nodes.min(MAX_CRAWL_NODES);
+        let origin: Did = self.address().into();
+        let mut frontier = {
+            let dht = self.msg_handler.dht();
+            let dht = dht.lock().await;
+            dht.successor.list()
+        };
+        // node without successors starts from peers learned by gossip
+        if frontier.is_empty() {
+            frontier = self
+                .swarm
+                .peer_view()
+                .peers()
+                .into_iter()
+                .map(|s| s.did)
+                .collect();
+        }
+        let mut nodes: Vec<CrawledNode> = vec![];
+        let mut visited = vec![origin];
+        let mut last_alive = origin;
+        let mut complete = false;
+        for _ in 0..MAX_CRAWL_DEPTH {
+            if nodes.len() >= max_nodes {
+                break;
+            }
+            let reported = !frontier.is_empty();
+            frontier.retain(|did| !visited.contains(did));
+            frontier.sort_by_key(|s| s.bias(&origin).pos());
+            frontier.dedup();
+            frontier.truncate(CRAWL_BREADTH.min(max_nodes - nodes.len()));
+            // all reported successors are visited, ring is closed
+            if frontier.is_empty() {
+                complete = reported;
+                break;
+            }
+            visited.extend(frontier.iter().copied());
+            let crawled = futures::future::join_all(
+                frontier
+                    .iter()
+                    .map(|did| self.crawl_node(*did, timeout_ms, with_manifests)),
+            )
+            .await;
+            frontier = vec![];
+            for (did, node, successors) in crawled {
+                if node.alive && did.bias(&origin).pos() > last_alive.bias(&origin).pos() {
+                    last_alive = did;
+                }
+                frontier.extend(successors);
+                nodes.push(node);
+            }
+        }
+        let alive = nodes.iter().filter(|n| n.alive).count();
+        let estimated_size = if complete {
+            alive as u64 + 1
+        } else {
+            estimate_ring_size(origin, last_alive, alive + 1)
+        };
+        Ok(CrawlReport {
+            nodes,
+            complete,
+            estimated_size,
+        })
+    }
+
+    /// Ask `did` for its neighbours and manifest in [Processor::crawl], returns it with
+    /// successors reported.
+    #[cfg(feature = "client")]
+    async fn crawl_node(
+        &self,
+        did: Did,
+        timeout_ms: u64,
+        with_manifest: bool,
+    ) -> (Did, CrawledNode, Vec<Did>) {
+        let report = self.query_topology(did, timeout_ms).await;
+        let manifest = match (with_manifest, &report) {
+            (true, Some(_)) => self.whois(&format!("{:?}", *did), timeout_ms).await.ok(),
+            _ => None,
+        };
+        let node = CrawledNode {
+            did: format!("{:?}", *did),
+            alive: report.is_some(),
+            rtt_ms: report.as_ref().map(|r| r.0),
+            successors: report.as_ref().map_or(vec![], |r| {
+                r.1.successors
+                    .iter()
+                    .map(|s| format!("{:?}", **s))
+                    .collect()
+            }),
+            predecessor: report
+                .as_ref()
+                .and_then(|r| r.1.predecessor)
+                .map(|p| format!("{:?}", *p)),
+            manifest,
+        };
+        (did, node, report.map_or(vec![], |(_, r)| r.successors))
+    }
+
+    /// Ask `did` for its neighbours, waits up to `timeout_ms` for the report, returns it with
+    /// RTT in milliseconds.
+    #[cfg(feature = "client")]
+    async fn query_topology(&self, did: Did, timeout_ms: u64) -> Option<(u128, TopologyReport)> {
+        let sent = utils::get_epoch_ms();
+        if let Err(e) = self.msg_handler.query_topology(did).await {
+            tracing::debug!(did = ?did, "failed to query topology: {}", e);
+            return None;
+        }
+        loop {
+            if let Some((ts, r)) = self.msg_handler.topology_report(did) {
+                if ts >= sent {
+                    return Some((ts - sent, r));
+                }
+            }
+            if utils::get_epoch_ms() - sent >= timeout_ms as u128 {
+                return None;
+            }
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Alive providers of service `name`, waits up to `timeout_ms` for remote records.
+    /// Connected providers come first, then the ones with lower RTT and fresher records.
+    #[cfg(feature = "client")]
+    pub async fn resolve_service(
+        &self,
+        name: &str,
+        timeout_ms: u64,
+    ) -> Result<Vec<ServiceProvider>> {
+        let id = VirtualNode::service_address(name).map_err(Error::ServiceError)?;
+        let alive = |v: &VirtualNode| ServiceRecord::from_vnode(v).map_or(false, |r| !r.is_empty());
+        let records = match self
+            .fetch_vnode(&id, timeout_ms, alive)
             .await
-            .map_err(Error::SendMessage)?;
-        Ok(())
+            .map_err(Error::ServiceError)?
+        {
+            Some(v) => ServiceRecord::from_vnode(&v).map_err(Error::ServiceError)?,
+            None => vec![],
+        };
+        let stats = self.swarm.route_stats();
+        let mut providers = records
+            .iter()
+            .map(|r| {
+                let did = r.service.provider;
+                ServiceProvider {
+                    did: format!("{:?}", *did),
+                    connected: self.swarm.get_transport(&did).is_some(),
+                    rtt_ms: stats.get(&did).and_then(|m| m.rtt_ms),
+                    expires_ms: r.expires_ms(),
+                }
+            })
+            .collect::<Vec<_>>();
+        providers.sort_by_key(|p| {
+            (
+                !p.connected,
+                p.rtt_ms.unwrap_or(u64::MAX),
+                std::cmp::Reverse(p.expires_ms),
+            )
+        });
+        Ok(providers)
+    }
+}
+
+#[cfg(feature = "client")]
+#[async_trait]
+impl PubkeyResolver for Processor {
+    async fn resolve_pubkey(&self, did: Did) -> CoreResult<PublicKey> {
+        self.fetch_pubkey(did, PUBKEY_TIMEOUT_MS).await
     }
 }
 
 /// Peer struct
 #[derive(Clone)]
 pub struct Peer {
-    /// web3 address of a peer.
-    pub address: Token,
+    /// DID of a peer.
+    pub address: Did,
     /// transport of the connection.
     pub transport: Arc<Transport>,
 }
@@ -356,7 +2094,7 @@ pub struct Peer {
 impl From<(Address, Arc<Transport>)> for Peer {
     fn from((address, transport): (Address, Arc<Transport>)) -> Self {
         Self {
-            address: address.into_token(),
+            address: address.into(),
             transport,
         }
     }
@@ -365,23 +2103,42 @@ impl From<(Address, Arc<Transport>)> for Peer {
 impl From<&(Address, Arc<Transport>)> for Peer {
     fn from((address, transport): &(Address, Arc<Transport>)) -> Self {
         Self {
-            address: address.into_token(),
+            address: (*address).into(),
             transport: transport.clone(),
         }
     }
 }
 
+/// Message of benchmark `seq`, padded to `size` bytes if it's shorter.
+#[cfg(feature = "client")]
+fn benchmark_data(seq: u64, size: usize) -> Vec<u8> {
+    let mut data = BENCHMARK_PREFIX.to_vec();
+    data.extend_from_slice(&seq.to_be_bytes());
+    data.resize(size.max(data.len()), 0);
+    data
+}
+
+/// Sequence of benchmark message `data`.
+#[cfg(feature = "client")]
+fn benchmark_seq(data: &[u8]) -> Option<u64> {
+    let seq = data.strip_prefix(BENCHMARK_PREFIX)?.get(..8)?;
+    Some(u64::from_be_bytes(seq.try_into().ok()?))
+}
+
 #[cfg(test)]
 #[cfg(feature = "client")]
 mod test {
     use futures::lock::Mutex;
 
     use super::*;
+    use crate::prelude::rings_core::dht::Chord;
     use crate::prelude::*;
 
     fn new_processor() -> Processor {
-        let key = SecretKey::random();
+        new_processor_with_key(SecretKey::random())
+    }
 
+    fn new_processor_with_key(key: SecretKey) -> Processor {
         let (auth, new_key) = SessionManager::gen_unsign_info(key.address(), None, None).unwrap();
         let sig = key.sign(&auth.to_string().unwrap()).to_vec();
         let session = SessionManager::new(&sig, &auth, &new_key);
@@ -393,7 +2150,7 @@ mod test {
 
         let dht = Arc::new(Mutex::new(PeerRing::new(key.address().into())));
         let msg_handler = MessageHandler::new(dht.clone(), swarm.clone());
-        let stabilization = Stabilization::new(dht, swarm.clone(), 200);
+        let stabilization = Stabilization::new(dht, swarm.clone());
         (swarm, Arc::new(msg_handler), Arc::new(stabilization)).into()
     }
 
@@ -425,6 +2182,128 @@ mod test {
         }
     }
 
+    #[tokio::test]
+    async fn test_processor_list_pendings_page() {
+        let processor = new_processor();
+        let mut ids = vec![];
+        for _ in 0..5 {
+            ids.push(processor.create_offer().await.unwrap().0.id.to_string());
+        }
+        ids.sort();
+
+        let mut filter = PeerFilter {
+            limit: Some(2),
+            ..Default::default()
+        };
+        let mut listed = vec![];
+        let mut cursor = None;
+        loop {
+            let page = processor
+                .list_pendings_page(&filter, cursor.as_deref())
+                .await
+                .unwrap();
+            assert!(page.transport_ids.len() <= 2);
+            listed.extend(page.transport_ids);
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(listed, ids);
+
+        filter.connected = Some(true);
+        let page = processor.list_pendings_page(&filter, None).await.unwrap();
+        assert!(page.transport_ids.is_empty());
+        assert!(page.next_cursor.is_none());
+
+        filter.connected = None;
+        filter.did_prefix = Some("0x".to_owned());
+        let page = processor.list_pendings_page(&filter, None).await.unwrap();
+        assert!(page.transport_ids.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_processor_tag_peer() {
+        let processor = new_processor();
+        let did = format!("{:?}", SecretKey::random().address());
+        let tags = processor
+            .tag_peer(&did, "region", Some("eu"))
+            .await
+            .unwrap();
+        assert_eq!(tags.get("region").map(|v| v.as_str()), Some("eu"));
+        let tags = processor.tag_peer(&did, "region", None).await.unwrap();
+        assert!(tags.is_empty());
+        assert!(processor
+            .tag_peer("not-a-did", "region", None)
+            .await
+            .is_err());
+        assert!(matches!(
+            processor
+                .authorized(None)
+                .tag_peer(&did, "acl", Some("deny"))
+                .await,
+            Err(Error::Unauthorized(_))
+        ));
+    }
+
+    #[test]
+    fn test_processor_list_known_peers() {
+        let processor = new_processor();
+        let key = SecretKey::random();
+        let known_peers = processor.swarm.known_peers();
+        known_peers
+            .observe(key.address().into(), &SecretKey::random().address())
+            .unwrap();
+        let peers = processor.list_known_peers();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].did, format!("{:?}", key.address()));
+        assert_eq!(peers[0].mismatches, 0);
+    }
+
+    #[test]
+    fn test_estimate_ring_size() {
+        let origin = Did::from_str("0x0000000000000000000000000000000000000000").unwrap();
+        let quarter = Did::from_str("0x4000000000000000000000000000000000000000").unwrap();
+        assert_eq!(estimate_ring_size(origin, quarter, 10), 40);
+        // span wraps around zero
+        let a = Did::from_str("0xc000000000000000000000000000000000000000").unwrap();
+        let b = Did::from_str("0x0000000000000000000000000000000000000000").unwrap();
+        assert_eq!(estimate_ring_size(a, b, 5), 20);
+        assert_eq!(estimate_ring_size(origin, origin, 1), 1);
+    }
+
+    #[tokio::test]
+    async fn test_crawl_alone() {
+        let processor = new_processor();
+        let report = processor.crawl(10, 100, false).await.unwrap();
+        assert!(report.nodes.is_empty());
+        assert_eq!(report.estimated_size, 1);
+        assert!(matches!(
+            processor.authorized(None).crawl(10, 100, false).await,
+            Err(Error::Unauthorized(_))
+        ));
+    }
+
+    #[test]
+    fn test_benchmark_data() {
+        let data = benchmark_data(42, 1024);
+        assert_eq!(data.len(), 1024);
+        assert_eq!(benchmark_seq(&data), Some(42));
+        assert_eq!(benchmark_data(1, 0).len(), BENCHMARK_PREFIX.len() + 8);
+        assert_eq!(benchmark_seq(b"hello"), None);
+    }
+
+    #[test]
+    fn test_peer_filter_did_prefix() {
+        let filter = PeerFilter {
+            did_prefix: Some("0xAB".to_owned()),
+            ..Default::default()
+        };
+        assert!(filter.match_did("0xab12"));
+        assert!(!filter.match_did("0xba12"));
+        assert!(PeerFilter::default().match_did("0xba12"));
+    }
+
     #[tokio::test]
     async fn test_processor_close_pending_transport() {
         let processor = new_processor();
@@ -492,6 +2371,227 @@ mod test {
         );
     }
 
+    #[tokio::test]
+    async fn test_processor_control_stabilization() {
+        let processor = new_processor();
+        let status = processor.stabilization_status();
+        assert!(!status.running);
+        assert!(status.last_round_ms.is_none());
+
+        let handle = processor.stabilization.clone().spawn();
+        let status = processor
+            .control_stabilization(StabilizationControl::Pause)
+            .unwrap();
+        assert!(status.paused);
+        let status = processor
+            .control_stabilization(StabilizationControl::Interval { min: 2, max: 30 })
+            .unwrap();
+        assert_eq!(
+            (status.interval, status.min_interval, status.max_interval),
+            (2, 2, 30)
+        );
+        assert!(processor
+            .control_stabilization(StabilizationControl::Interval { min: 5, max: 1 })
+            .is_err());
+        assert!(matches!(
+            processor
+                .clone()
+                .authorized(None)
+                .control_stabilization(StabilizationControl::Resume),
+            Err(Error::Unauthorized(_))
+        ));
+        assert!(processor.stabilization_status().paused);
+
+        // triggered round runs though it's paused
+        processor
+            .control_stabilization(StabilizationControl::Trigger)
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let status = processor.stabilization_status();
+        assert!(status.running);
+        assert!(status.last_round_ms.is_some());
+
+        handle.stop();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(processor
+            .control_stabilization(StabilizationControl::Trigger)
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_processor_repair_dht() {
+        let processor = new_processor();
+        let gone: Did = SecretKey::random().address().into();
+        let dht = processor.msg_handler.dht();
+        dht.lock().await.join(gone);
+        assert_eq!(dht.lock().await.successor.list(), vec![gone]);
+
+        // node not connected is dropped, nothing is left to look up or notify
+        let report = processor.repair_dht().await.unwrap();
+        assert_eq!(report.dropped, vec![gone]);
+        assert!(report.notified.is_empty());
+        assert_eq!(report.finger_lookups, 0);
+        assert!(report.successors.is_empty());
+        assert!(dht.lock().await.finger.is_empty());
+        assert!(matches!(
+            processor.authorized(None).repair_dht().await,
+            Err(Error::Unauthorized(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_processor_admin() {
+        let processor = new_processor().with_admin_token(Some("secret".to_owned()));
+        assert!(processor.is_admin());
+        assert!(!processor.clone().authorized(None).is_admin());
+        assert!(!processor.clone().authorized(Some("secrex")).is_admin());
+        assert!(processor.clone().authorized(Some("secret")).is_admin());
+        let remote = new_processor().authorized(Some(""));
+        assert!(!remote.is_admin());
+        assert!(matches!(
+            remote.send_file("a").await,
+            Err(Error::Unauthorized(_))
+        ));
+        assert!(matches!(
+            remote.benchmark("a", 1, 1, 1, 1).await,
+            Err(Error::Unauthorized(_))
+        ));
+        assert!(matches!(remote.drain().await, Err(Error::Unauthorized(_))));
+        assert!(matches!(
+            remote.captured_payloads(false),
+            Err(Error::Unauthorized(_))
+        ));
+        #[cfg(feature = "chaos")]
+        assert!(matches!(
+            remote.inject_faults(None),
+            Err(Error::Unauthorized(_))
+        ));
+        assert!(matches!(
+            new_processor().send_file("a").await,
+            Err(Error::FileTransfer(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_processor_state_admin() {
+        let processor = new_processor().with_admin_token(Some("secret".to_owned()));
+        let snapshot = processor.export_state().await.unwrap();
+        processor.import_state(&snapshot).await.unwrap();
+
+        let remote = processor.clone().authorized(Some("secrex"));
+        assert!(matches!(
+            remote.export_state().await,
+            Err(Error::Unauthorized(_))
+        ));
+        assert!(matches!(
+            remote.import_state(&snapshot).await,
+            Err(Error::Unauthorized(_))
+        ));
+        let admin = processor.authorized(Some("secret"));
+        assert!(admin.export_state().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_share_path() {
+        let dir = std::env::temp_dir().join(format!("rings-share-{}", uuid::Uuid::new_v4()));
+        tokio::fs::create_dir_all(dir.join("sub")).await.unwrap();
+        let dir = tokio::fs::canonicalize(&dir).await.unwrap();
+        assert_eq!(
+            share_path(&dir, Path::new("a.txt")).await.unwrap(),
+            dir.join("a.txt")
+        );
+        assert_eq!(
+            share_path(&dir, Path::new("./sub/a.txt")).await.unwrap(),
+            dir.join("sub").join("a.txt")
+        );
+        for p in [
+            "../a.txt",
+            "sub/../../a.txt",
+            "/etc/passwd",
+            "",
+            "missing/a.txt",
+        ] {
+            assert!(share_path(&dir, Path::new(p)).await.is_err(), "{}", p);
+        }
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("/etc", dir.join("etc")).unwrap();
+            std::os::unix::fs::symlink("/etc/passwd", dir.join("passwd")).unwrap();
+            assert!(share_path(&dir, Path::new("etc/passwd")).await.is_err());
+            assert!(share_path(&dir, Path::new("passwd")).await.is_err());
+        }
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_processor_local_data() {
+        let processor = new_processor();
+        let dht = processor.msg_handler.dht();
+        let mut keys = vec![];
+        for i in 0..3 {
+            let vnode: VirtualNode = format!("data {}", i).try_into().unwrap();
+            keys.push(format!("{:?}", *vnode.did()));
+            dht.lock().await.storage.set(&vnode.did(), vnode);
+        }
+        keys.sort();
+
+        let page = processor.list_local_data(None, Some(2)).await.unwrap();
+        assert_eq!(page.data.len(), 2);
+        assert_eq!(page.next_cursor.as_deref(), Some(keys[1].as_str()));
+        let page = processor
+            .list_local_data(page.next_cursor.as_deref(), Some(2))
+            .await
+            .unwrap();
+        assert_eq!(page.data[0].key, keys[2]);
+        assert!(page.next_cursor.is_none());
+
+        let deleted = processor.delete_local_data(&keys[0]).await.unwrap();
+        assert_eq!(deleted.key, keys[0]);
+        assert_eq!(dht.lock().await.storage.len(), 2);
+        assert!(matches!(
+            processor.delete_local_data(&keys[0]).await,
+            Err(Error::LocalDataNotFound(_))
+        ));
+        assert!(matches!(
+            processor.delete_local_data("nope").await,
+            Err(Error::InvalidDid(_))
+        ));
+
+        let remote = processor.authorized(None);
+        assert!(matches!(
+            remote.list_local_data(None, None).await,
+            Err(Error::Unauthorized(_))
+        ));
+        assert!(matches!(
+            remote.delete_local_data(&keys[1]).await,
+            Err(Error::Unauthorized(_))
+        ));
+        assert_eq!(dht.lock().await.storage.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_processor_create_group_taken() {
+        let processor = new_processor();
+        let record = processor.create_group("g", &[], 100).await.unwrap();
+        assert_eq!(record.data.admin, Did::from(processor.address()));
+        // admin replaces members of its own group
+        let member = format!("{:?}", SecretKey::random().address());
+        assert!(processor.create_group("g", &[member], 100).await.is_ok());
+
+        let other = SecretKey::random();
+        let session = SessionManager::new_with_seckey(&other).unwrap();
+        let group = Group::new("taken", other.address().into(), &[]).unwrap();
+        let vnode = GroupRecord::new(&session, group)
+            .unwrap()
+            .to_vnode()
+            .unwrap();
+        processor.msg_handler.store(vnode).await.unwrap();
+        assert!(matches!(
+            processor.create_group("taken", &[], 100).await,
+            Err(Error::GroupNameTaken(_))
+        ));
+    }
+
     struct MsgCallbackStruct {
         msgs: Arc<Mutex<Vec<String>>>,
     }
@@ -517,9 +2617,10 @@ mod test {
     #[tokio::test]
     async fn test_processor_handshake_msg() {
         let p1 = new_processor();
-        let p2 = new_processor();
-        let p1_addr = p1.address().into_token().to_string();
-        let p2_addr = p2.address().into_token().to_string();
+        let p2_key = SecretKey::random();
+        let p2 = new_processor_with_key(p2_key);
+        let p1_addr = Did::from(p1.address());
+        let p2_addr = Did::from(p2.address());
         println!("p1_addr: {}", p1_addr);
         println!("p2_addr: {}", p2_addr);
 
@@ -533,6 +2634,11 @@ mod test {
         );
 
         let (transport_2, answer) = p2.answer_offer(offer.as_str()).await.unwrap();
+        // retried offer is answered by the same transport
+        let (retried, answer_again) = p2.answer_offer(offer.as_str()).await.unwrap();
+        assert_eq!(retried.id, transport_2.id);
+        assert_eq!(answer_again, answer);
+        assert_eq!(p2.swarm.get_transport_numbers(), 1);
         let peer = p1
             .accept_answer(transport_1.id.to_string().as_str(), answer.as_str())
             .await
@@ -540,7 +2646,7 @@ mod test {
 
         assert!(peer.transport.id.eq(&transport_1.id), "transport not same");
         assert!(
-            peer.address.to_string().eq(&p2_addr),
+            peer.address == p2_addr,
             "peer.address got {}, expect: {}",
             peer.address,
             p2_addr
@@ -611,7 +2717,7 @@ mod test {
         let test_text2 = "test2";
 
         println!("send_message 1");
-        p1.send_message(p2_addr.as_str(), test_text1.as_bytes())
+        p1.send_message(&p2_addr.to_string(), test_text1.as_bytes())
             .await
             .unwrap();
         println!("send_message 1 done");
@@ -619,7 +2725,7 @@ mod test {
         tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
 
         println!("send_message 2");
-        p2.send_message(p1_addr.as_str(), test_text2.as_bytes())
+        p2.send_message(&p1_addr.to_string(), test_text2.as_bytes())
             .await
             .unwrap();
         println!("send_message 2 done");
@@ -639,8 +2745,7 @@ mod test {
 
         println!("check received");
 
-        let mut msgs2 = msgs2.try_lock().unwrap();
-        let got_msg2 = msgs2.pop().unwrap();
+        let got_msg2 = msgs2.try_lock().unwrap().pop().unwrap();
         assert!(
             got_msg2.eq(test_text1),
             "msg received, expect {}, got {}",
@@ -656,5 +2761,16 @@ mod test {
             test_text2,
             got_msg1
         );
+
+        // message to rotated identity of p2 goes to its new one, without a receipt
+        let old_key = SecretKey::random();
+        let rotation = RotationRecord::new(&old_key, &p2_key).unwrap();
+        assert!(p1.swarm.rotations().insert(&rotation));
+        let old_addr = Did::from(old_key.address());
+        p1.send_message(&old_addr.to_string(), b"test3")
+            .await
+            .unwrap();
+        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        assert_eq!(msgs2.try_lock().unwrap().pop().as_deref(), Some("test3"));
     }
 }
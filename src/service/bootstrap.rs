@@ -0,0 +1,262 @@
+//! Bootstrap of a node into the ring, by mode of [BootstrapConfig].
+//!
+//! - `manual`: peers are connected by `connectPeerViaHttp` only, like before.
+//! - `static`: seeds in config are dialed at startup.
+//! - `dns`: seeds are TXT records of a DNS name, each record a URL of a seed node. They are
+//!   resolved again every `refresh_secs`, new seeds are dialed, and all of them again if the
+//!   node has lost every peer.
+//! - `cache`: seeds connected in previous sessions are dialed at startup.
+//!
+//! With `cache_path` set, seeds connected by any mode are saved there for `cache` mode.
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+
+use super::dns_codec::build_query;
+use super::dns_codec::read_question;
+use super::dns_codec::read_record;
+use super::dns_codec::read_strings;
+use super::dns_codec::read_u16;
+use super::dns_codec::HEADER_LEN;
+use super::dns_codec::TYPE_TXT;
+use crate::config::BootstrapConfig;
+use crate::config::BootstrapMode;
+use crate::prelude::rings_core::swarm::TransportManager;
+use crate::prelude::uuid;
+use crate::processor::Processor;
+
+/// Recursion desired.
+const FLAGS_RD: u16 = 0x0100;
+/// Seeds kept in peer cache, the latest ones.
+const MAX_CACHED_SEEDS: usize = 32;
+const DNS_TIMEOUT_MS: u64 = 3000;
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+/// Strings of TXT answers in response `id`, strings of a record are concatenated.
+fn parse_txt_answers(resp: &[u8], id: u16) -> Option<Vec<String>> {
+    if resp.len() < HEADER_LEN || read_u16(resp, 0)? != id || resp[2] & 0x80 == 0 {
+        return None;
+    }
+    if resp[3] & 0x0f != 0 {
+        return Some(vec![]);
+    }
+    let questions = read_u16(resp, 4)?;
+    let answers = read_u16(resp, 6)?;
+    let mut pos = HEADER_LEN;
+    for _ in 0..questions {
+        pos = read_question(resp, pos)?.end;
+    }
+    let mut records = vec![];
+    for _ in 0..answers {
+        let record = read_record(resp, pos)?;
+        pos = record.end;
+        if record.rtype == TYPE_TXT {
+            records.push(read_strings(record.rdata)?.concat());
+        }
+    }
+    Some(records)
+}
+
+/// First nameserver of system resolver.
+fn system_nameserver() -> Option<SocketAddr> {
+    std::fs::read_to_string(RESOLV_CONF)
+        .ok()?
+        .lines()
+        .filter_map(|l| l.trim().strip_prefix("nameserver"))
+        .find_map(|ip| ip.trim().parse().ok())
+        .map(|ip| SocketAddr::new(ip, 53))
+}
+
+/// TXT records of `name`, asked to `server` or nameserver of system.
+async fn resolve_txt(name: &str, server: Option<&str>) -> anyhow::Result<Vec<String>> {
+    let server: SocketAddr = match server {
+        Some(s) => s.parse()?,
+        None => system_nameserver()
+            .ok_or_else(|| anyhow::anyhow!("no nameserver in {}", RESOLV_CONF))?,
+    };
+    let bind = if server.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(bind).await?;
+    let id = uuid::Uuid::new_v4().as_u128() as u16;
+    let query = build_query(id, FLAGS_RD, name, TYPE_TXT)
+        .ok_or_else(|| anyhow::anyhow!("invalid DNS name {}", name))?;
+    socket.send_to(&query, server).await?;
+    let mut buf = [0u8; 4096];
+    let deadline = tokio::time::Instant::now() + Duration::from_millis(DNS_TIMEOUT_MS);
+    loop {
+        let (n, from) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await??;
+        // responses of other queries or servers are ignored
+        if from != server {
+            continue;
+        }
+        if let Some(records) = parse_txt_answers(&buf[..n], id) {
+            return Ok(records);
+        }
+    }
+}
+
+/// Seeds connected in previous sessions, one URL a line.
+fn load_cache(path: &str) -> Vec<String> {
+    std::fs::read_to_string(path)
+        .map(|s| {
+            s.lines()
+                .map(|l| l.trim().to_owned())
+                .filter(|l| !l.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Save `connected` seeds in front of cached ones.
+fn save_cache(path: &str, connected: &[String]) -> std::io::Result<()> {
+    let mut seeds = connected.to_vec();
+    for s in load_cache(path) {
+        if !seeds.contains(&s) {
+            seeds.push(s);
+        }
+    }
+    seeds.truncate(MAX_CACHED_SEEDS);
+    if let Some(dir) = Path::new(path).parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, seeds.join("\n") + "\n")
+}
+
+/// Seeds of `config` by its mode.
+async fn seeds(config: &BootstrapConfig) -> Vec<String> {
+    match config.mode {
+        BootstrapMode::Manual => vec![],
+        BootstrapMode::Static => config.seeds.clone(),
+        BootstrapMode::Cache => config
+            .cache_path
+            .as_deref()
+            .map(load_cache)
+            .unwrap_or_default(),
+        BootstrapMode::Dns => {
+            let name = config.dns_name.as_deref().unwrap_or_default();
+            match resolve_txt(name, config.dns_server.as_deref()).await {
+                Ok(seeds) => seeds,
+                Err(e) => {
+                    tracing::warn!(name = %name, "failed to resolve seeds: {}", e);
+                    vec![]
+                }
+            }
+        }
+    }
+}
+
+/// Dial `seeds` not connected yet, or all of them if node has no peer. Returns seeds
+/// connected by this round.
+async fn dial(
+    processor: &Processor,
+    seeds: &[String],
+    connected: &mut BTreeSet<String>,
+) -> Vec<String> {
+    if processor.swarm.get_transport_numbers() == 0 {
+        connected.clear();
+    }
+    let pending = seeds
+        .iter()
+        .filter(|s| !connected.contains(*s))
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut dialed = vec![];
+    for seed in pending {
+        match processor.connect_peer_via_http(&seed).await {
+            Ok(_) => {
+                tracing::info!(seed = %seed, "connected seed");
+                connected.insert(seed.clone());
+                dialed.push(seed);
+            }
+            Err(e) => tracing::warn!(seed = %seed, "failed to connect seed: {}", e),
+        }
+    }
+    dialed
+}
+
+/// Bootstrap node by `config`, see module doc. In `dns` mode it runs forever, otherwise it
+/// returns after seeds are dialed once.
+pub async fn run_bootstrap(config: BootstrapConfig, processor: Processor) {
+    let mut connected = BTreeSet::new();
+    loop {
+        let seeds = seeds(&config).await;
+        tracing::debug!(mode = %config.mode, seeds = seeds.len(), "bootstrap");
+        let dialed = dial(&processor, &seeds, &mut connected).await;
+        if let (Some(path), false) = (&config.cache_path, dialed.is_empty()) {
+            if let Err(e) = save_cache(path, &dialed) {
+                tracing::warn!(path = %path, "failed to save peer cache: {}", e);
+            }
+        }
+        if config.mode != BootstrapMode::Dns {
+            return;
+        }
+        tokio::time::sleep(Duration::from_secs(config.refresh_secs)).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::service::dns_codec::push_record;
+    use crate::service::dns_codec::push_strings;
+    use crate::service::dns_codec::CLASS_IN;
+
+    fn txt_query(id: u16, name: &str) -> Vec<u8> {
+        build_query(id, FLAGS_RD, name, TYPE_TXT).unwrap()
+    }
+
+    fn txt_response(id: u16, name: &str, records: &[&[&str]]) -> Vec<u8> {
+        let mut resp = txt_query(id, name);
+        resp[2] |= 0x80;
+        resp[7] = records.len() as u8;
+        for strings in records {
+            let mut rdata = vec![];
+            push_strings(&mut rdata, strings).unwrap();
+            push_record(&mut resp, name, TYPE_TXT, CLASS_IN, 300, &rdata).unwrap();
+        }
+        resp
+    }
+
+    #[test]
+    fn test_parse_txt_answers() {
+        let resp = txt_response(7, "_rings.example.org", &[&["http://seed1:50000"], &[
+            "http://seed2",
+            ":50000",
+        ]]);
+        assert_eq!(
+            parse_txt_answers(&resp, 7),
+            Some(vec![
+                "http://seed1:50000".to_owned(),
+                "http://seed2:50000".to_owned()
+            ])
+        );
+        // other query, or query itself
+        assert_eq!(parse_txt_answers(&resp, 8), None);
+        assert_eq!(
+            parse_txt_answers(&txt_query(7, "_rings.example.org"), 7),
+            None
+        );
+        assert_eq!(parse_txt_answers(&resp[..resp.len() - 3], 7), None);
+        // labels are 63 bytes at most
+        assert!(build_query(7, FLAGS_RD, &"a".repeat(64), TYPE_TXT).is_none());
+    }
+
+    #[test]
+    fn test_peer_cache() {
+        let path = std::env::temp_dir()
+            .join(format!("rings-seeds-{}", uuid::Uuid::new_v4()))
+            .to_string_lossy()
+            .to_string();
+        assert!(load_cache(&path).is_empty());
+        save_cache(&path, &["a".to_owned(), "b".to_owned()]).unwrap();
+        save_cache(&path, &["c".to_owned(), "a".to_owned()]).unwrap();
+        assert_eq!(load_cache(&path), vec!["c", "a", "b"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,212 @@
+//! Control channel of daemon over a local unix socket.
+//! Each connection carries one JSON encoded [ControlRequest] line, and gets one [ControlResponse] line back.
+//! Connections are served by tasks of their own, and closed if the request doesn't arrive in
+//! [READ_TIMEOUT], so an idle client doesn't block others.
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::io::AsyncBufRead;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::net::UnixListener;
+use tokio::net::UnixStream;
+use tokio::sync::Notify;
+
+use crate::prelude::rings_core::swarm::Swarm;
+use crate::prelude::rings_core::swarm::TransportManager;
+
+/// Connection is closed if its request doesn't arrive in this long.
+pub const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// Bytes of a request line at most.
+const MAX_REQUEST_LEN: u64 = 4096;
+
+/// Request sent to control socket.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ControlRequest {
+    /// Query state of daemon.
+    Status,
+    /// Ask daemon to shutdown gracefully.
+    Stop,
+}
+
+/// Response of control socket.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ControlResponse {
+    /// State of daemon.
+    Status(DaemonStatus),
+    /// Daemon is stopping.
+    Stopping,
+    /// Request cannot be handled.
+    Error(String),
+}
+
+/// State of a running daemon.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DaemonStatus {
+    /// Process id.
+    pub pid: u32,
+    /// Address of node.
+    pub address: String,
+    /// Listen address of jsonrpc server.
+    pub http_addr: String,
+    /// Seconds since daemon started.
+    pub uptime: u64,
+    /// Number of connected transports.
+    pub transports: usize,
+}
+
+/// Serve control requests on `path` until a [ControlRequest::Stop] is received,
+/// `shutdown` is notified when that happens.
+pub async fn run_control_socket(
+    path: String,
+    http_addr: String,
+    swarm: Arc<Swarm>,
+    shutdown: Arc<Notify>,
+) -> anyhow::Result<()> {
+    // remove socket file left by previous process
+    if Path::new(&path).exists() {
+        std::fs::remove_file(&path)?;
+    }
+    let listener = UnixListener::bind(&path)?;
+    let started = Instant::now();
+    let stop = Arc::new(Notify::new());
+    tracing::info!("Control socket listening on {}", path);
+    loop {
+        let stream = tokio::select! {
+            accepted = listener.accept() => accepted?.0,
+            _ = stop.notified() => break,
+        };
+        let swarm = swarm.clone();
+        let http_addr = http_addr.clone();
+        let stop = stop.clone();
+        tokio::spawn(async move {
+            let resp_stop = handle_control_stream(stream, READ_TIMEOUT, |req| match req {
+                ControlRequest::Status => ControlResponse::Status(DaemonStatus {
+                    pid: std::process::id(),
+                    address: format!("{:?}", swarm.address()),
+                    http_addr,
+                    uptime: started.elapsed().as_secs(),
+                    transports: swarm.get_transport_numbers(),
+                }),
+                ControlRequest::Stop => ControlResponse::Stopping,
+            })
+            .await;
+            match resp_stop {
+                Ok(true) => stop.notify_one(),
+                Ok(false) => {}
+                Err(e) => tracing::warn!("control socket request failed: {}", e),
+            }
+        });
+    }
+    shutdown.notify_one();
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
+
+/// Read one request in `read_timeout`, and write back the response, return true if it's a stop
+/// request.
+async fn handle_control_stream<F>(
+    stream: UnixStream,
+    read_timeout: Duration,
+    handler: F,
+) -> anyhow::Result<bool>
+where
+    F: FnOnce(ControlRequest) -> ControlResponse,
+{
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let line = read_request_line(&mut reader, read_timeout, MAX_REQUEST_LEN)
+        .await?
+        .unwrap_or_default();
+    let resp = match serde_json::from_str::<ControlRequest>(line.trim()) {
+        Ok(req) => handler(req),
+        Err(e) => ControlResponse::Error(e.to_string()),
+    };
+    let is_stop = matches!(resp, ControlResponse::Stopping);
+    writer
+        .write_all(format!("{}\n", serde_json::to_string(&resp)?).as_bytes())
+        .await?;
+    Ok(is_stop)
+}
+
+/// Read a request line of at most `max_len` bytes in `read_timeout`, None if `reader` is
+/// closed. A longer line fails, instead of being buffered without bound.
+pub(crate) async fn read_request_line<R>(
+    reader: &mut R,
+    read_timeout: Duration,
+    max_len: u64,
+) -> anyhow::Result<Option<String>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut line = String::new();
+    let n = tokio::time::timeout(read_timeout, reader.take(max_len).read_line(&mut line))
+        .await
+        .map_err(|_| anyhow::anyhow!("request not received in {:?}", read_timeout))??;
+    if n == 0 {
+        return Ok(None);
+    }
+    if n as u64 >= max_len && !line.ends_with('\n') {
+        anyhow::bail!("request longer than {} bytes", max_len);
+    }
+    Ok(Some(line))
+}
+
+/// Send a request to control socket at `path`.
+pub async fn send_control_request(
+    path: &str,
+    req: ControlRequest,
+) -> anyhow::Result<ControlResponse> {
+    let stream = UnixStream::connect(path).await?;
+    let (reader, mut writer) = stream.into_split();
+    writer
+        .write_all(format!("{}\n", serde_json::to_string(&req)?).as_bytes())
+        .await?;
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+    Ok(serde_json::from_str(line.trim())?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_control_stream() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let server = tokio::spawn(handle_control_stream(
+            server,
+            READ_TIMEOUT,
+            |req| match req {
+                ControlRequest::Status => ControlResponse::Error("status".to_owned()),
+                ControlRequest::Stop => ControlResponse::Stopping,
+            },
+        ));
+        let (reader, mut writer) = client.into_split();
+        writer.write_all(b"\"stop\"\n").await.unwrap();
+        let mut line = String::new();
+        BufReader::new(reader).read_line(&mut line).await.unwrap();
+        assert!(matches!(
+            serde_json::from_str::<ControlResponse>(line.trim()).unwrap(),
+            ControlResponse::Stopping
+        ));
+        assert!(server.await.unwrap().unwrap());
+
+        // idle client is dropped
+        let (_client, server) = UnixStream::pair().unwrap();
+        let handled = handle_control_stream(server, Duration::from_millis(50), |_| {
+            ControlResponse::Stopping
+        })
+        .await;
+        assert!(handled.is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! DNS stub resolving service names to the gateway.
+//!
+//! `A` or `AAAA` queries of `<name>.rings` are answered with address of the http server of this
+//! node in that family, see [Config::gateway_ips](crate::config::Config::gateway_ips), if
+//! service `name` has providers, so `http://<name>.rings:<port>/` reaches the gateway, which
+//! forwards it to the best provider. Other names are refused, configure the stub only for the
+//! `rings` domain in system resolver.
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use tokio::net::UdpSocket;
+
+use super::dns_codec::read_question;
+use super::dns_codec::read_u16;
+use super::dns_codec::Question;
+use super::dns_codec::HEADER_LEN;
+use super::dns_codec::TYPE_A;
+use super::dns_codec::TYPE_AAAA;
+use super::gateway::service_name;
+use super::gateway::RESOLVE_TIMEOUT_MS;
+use crate::processor::Processor;
+
+const RCODE_NXDOMAIN: u8 = 3;
+const RCODE_REFUSED: u8 = 5;
+/// Answers are short lived, providers come and go.
+const ANSWER_TTL_SECS: u32 = 30;
+
+/// Parse the first question of `query`, which is copied to response up to its end.
+fn parse_question(query: &[u8]) -> Option<Question> {
+    if query.len() < HEADER_LEN || read_u16(query, 4)? == 0 {
+        return None;
+    }
+    read_question(query, HEADER_LEN)
+}
+
+/// Response to `query` with its first question, and an `A` or `AAAA` answer if `ip` is set.
+fn build_response(query: &[u8], question: &Question, rcode: u8, ip: Option<IpAddr>) -> Vec<u8> {
+    let mut resp = Vec::with_capacity(question.end + 16);
+    resp.extend_from_slice(&query[0..2]);
+    // QR, opcode and RD of query, RA
+    resp.push(0x80 | (query[2] & 0x79));
+    resp.push(0x80 | rcode);
+    resp.extend_from_slice(&1u16.to_be_bytes());
+    resp.extend_from_slice(&(ip.is_some() as u16).to_be_bytes());
+    resp.extend_from_slice(&[0, 0, 0, 0]);
+    resp.extend_from_slice(&query[HEADER_LEN..question.end]);
+    if let Some(ip) = ip {
+        // pointer to name in question
+        let rdata = match ip {
+            IpAddr::V4(ip) => ip.octets().to_vec(),
+            IpAddr::V6(ip) => ip.octets().to_vec(),
+        };
+        resp.extend_from_slice(&[0xc0, 0x0c]);
+        resp.extend_from_slice(&record_type(&ip).to_be_bytes());
+        resp.extend_from_slice(&1u16.to_be_bytes());
+        resp.extend_from_slice(&ANSWER_TTL_SECS.to_be_bytes());
+        resp.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        resp.extend_from_slice(&rdata);
+    }
+    resp
+}
+
+/// Type of records answering with `ip`.
+fn record_type(ip: &IpAddr) -> u16 {
+    match ip {
+        IpAddr::V4(_) => TYPE_A,
+        IpAddr::V6(_) => TYPE_AAAA,
+    }
+}
+
+/// Address of `gateway_ips` answering a query of `qtype`.
+fn gateway_ip(gateway_ips: &[IpAddr], qtype: u16) -> Option<IpAddr> {
+    gateway_ips
+        .iter()
+        .find(|ip| record_type(ip) == qtype)
+        .copied()
+}
+
+async fn answer(query: &[u8], gateway_ips: &[IpAddr], processor: &Processor) -> Option<Vec<u8>> {
+    let question = parse_question(query)?;
+    let name = match service_name(&question.name) {
+        Some(n) => n,
+        None => return Some(build_response(query, &question, RCODE_REFUSED, None)),
+    };
+    let found = match processor.resolve_service(name, RESOLVE_TIMEOUT_MS).await {
+        Ok(providers) => !providers.is_empty(),
+        Err(e) => {
+            tracing::warn!(service = %name, "failed to resolve service: {}", e);
+            false
+        }
+    };
+    let ip = gateway_ip(gateway_ips, question.qtype);
+    Some(match found {
+        false => build_response(query, &question, RCODE_NXDOMAIN, None),
+        // name exists, maybe without records of this type
+        true => build_response(query, &question, 0, ip),
+    })
+}
+
+/// Run DNS stub on udp `addr`, names of services with providers resolve to `gateway_ips`, one
+/// of each family at most.
+pub async fn run_dns_stub(
+    addr: String,
+    gateway_ips: Vec<IpAddr>,
+    processor: Processor,
+) -> anyhow::Result<()> {
+    let gateway_ips: Arc<[IpAddr]> = gateway_ips.into();
+    let socket = Arc::new(UdpSocket::bind(&addr).await?);
+    tracing::info!(addr = %addr, "DNS stub listening");
+    let mut buf = [0u8; 512];
+    loop {
+        let (n, peer) = socket.recv_from(&mut buf).await?;
+        let query = buf[..n].to_vec();
+        let socket = socket.clone();
+        let processor = processor.clone();
+        let gateway_ips = gateway_ips.clone();
+        tokio::spawn(async move {
+            match answer(&query, &gateway_ips, &processor).await {
+                Some(resp) => {
+                    if let Err(e) = socket.send_to(&resp, peer).await {
+                        tracing::warn!(peer = %peer, "failed to send DNS response: {}", e);
+                    }
+                }
+                None => tracing::debug!(peer = %peer, "drop malformed DNS query"),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::service::dns_codec::build_query;
+
+    fn query(name: &str, qtype: u16) -> Vec<u8> {
+        build_query(0x1234, 0x0100, name, qtype).unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_answer() {
+        let q = query("web.rings", TYPE_A);
+        let question = parse_question(&q).unwrap();
+        assert_eq!(question.name, "web.rings");
+        assert_eq!(question.qtype, TYPE_A);
+        assert_eq!(question.end, q.len());
+        assert!(parse_question(&q[..q.len() - 3]).is_none());
+        // qtype without qclass
+        assert!(parse_question(&q[..q.len() - 2]).is_none());
+
+        let ip = std::net::Ipv4Addr::new(127, 0, 0, 1);
+        let resp = build_response(&q, &question, 0, Some(ip.into()));
+        assert_eq!(&resp[0..2], &[0x12, 0x34]);
+        assert_eq!(resp[2] & 0x80, 0x80);
+        assert_eq!(resp[3] & 0x0f, 0);
+        assert_eq!(u16::from_be_bytes([resp[6], resp[7]]), 1);
+        assert_eq!(&resp[resp.len() - 4..], &ip.octets());
+
+        let resp = build_response(&q, &question, RCODE_NXDOMAIN, None);
+        assert_eq!(resp[3] & 0x0f, RCODE_NXDOMAIN);
+        assert_eq!(resp.len(), q.len());
+
+        let q = query("web.rings", TYPE_AAAA);
+        let question = parse_question(&q).unwrap();
+        let ip = std::net::Ipv6Addr::LOCALHOST;
+        let resp = build_response(&q, &question, 0, Some(ip.into()));
+        assert_eq!(&resp[q.len() + 2..q.len() + 4], &TYPE_AAAA.to_be_bytes());
+        assert_eq!(&resp[resp.len() - 16..], &ip.octets());
+    }
+
+    #[test]
+    fn test_gateway_ip() {
+        let v4 = IpAddr::from(std::net::Ipv4Addr::LOCALHOST);
+        let v6 = IpAddr::from(std::net::Ipv6Addr::LOCALHOST);
+        assert_eq!(gateway_ip(&[v6, v4], TYPE_A), Some(v4));
+        assert_eq!(gateway_ip(&[v6, v4], TYPE_AAAA), Some(v6));
+        assert_eq!(gateway_ip(&[v4], TYPE_AAAA), None);
+    }
+}
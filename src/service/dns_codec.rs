@@ -0,0 +1,203 @@
+//! Wire format of DNS messages, shared by the DNS stub, bootstrap by TXT records and mDNS.
+//!
+//! Only what these need is here: names, with compression on reading, records, and character
+//! strings of TXT records. Reading returns None on anything malformed, never panics on
+//! untrusted packets.
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_AAAA: u16 = 28;
+pub const CLASS_IN: u16 = 1;
+/// Size of header of a message.
+pub const HEADER_LEN: usize = 12;
+/// Longest label of a name.
+pub const MAX_LABEL_LEN: usize = 63;
+/// Pointers followed in a name, against loops.
+const MAX_POINTERS: usize = 16;
+
+pub fn read_u16(msg: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*msg.get(pos)?, *msg.get(pos + 1)?]))
+}
+
+/// Append `name`, fails if a label of it is empty or longer than [MAX_LABEL_LEN].
+pub fn push_name(buf: &mut Vec<u8>, name: &str) -> Option<()> {
+    let name = name.trim_end_matches('.');
+    if !name.is_empty() {
+        for label in name.split('.') {
+            if label.is_empty() || label.len() > MAX_LABEL_LEN {
+                return None;
+            }
+            buf.push(label.len() as u8);
+            buf.extend_from_slice(label.as_bytes());
+        }
+    }
+    buf.push(0);
+    Some(())
+}
+
+/// Read a possibly compressed name at `pos`, returns it and position after it.
+pub fn read_name(msg: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = vec![];
+    let mut end = None;
+    for _ in 0..MAX_POINTERS {
+        loop {
+            let len = *msg.get(pos)? as usize;
+            if len == 0 {
+                let name = labels.join(".");
+                return Some((name, end.unwrap_or(pos + 1)));
+            }
+            if len & 0xc0 == 0xc0 {
+                end.get_or_insert(pos + 2);
+                pos = ((len & 0x3f) << 8) | *msg.get(pos + 1)? as usize;
+                break;
+            }
+            if len > MAX_LABEL_LEN {
+                return None;
+            }
+            labels.push(String::from_utf8(msg.get(pos + 1..pos + 1 + len)?.to_vec()).ok()?);
+            pos += 1 + len;
+        }
+    }
+    None
+}
+
+/// Query of `qtype` records of `name`, with `flags` of header.
+pub fn build_query(id: u16, flags: u16, name: &str, qtype: u16) -> Option<Vec<u8>> {
+    let mut q = id.to_be_bytes().to_vec();
+    q.extend_from_slice(&flags.to_be_bytes());
+    // one question
+    q.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    push_name(&mut q, name)?;
+    q.extend_from_slice(&qtype.to_be_bytes());
+    q.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Some(q)
+}
+
+/// Question of a message.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Question {
+    pub name: String,
+    pub qtype: u16,
+    /// Position after it.
+    pub end: usize,
+}
+
+/// Read question at `pos`.
+pub fn read_question(msg: &[u8], pos: usize) -> Option<Question> {
+    let (name, next) = read_name(msg, pos)?;
+    let qtype = read_u16(msg, next)?;
+    // qclass
+    read_u16(msg, next + 2)?;
+    Some(Question {
+        name,
+        qtype,
+        end: next + 4,
+    })
+}
+
+/// Resource record of a message.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Record<'a> {
+    pub name: String,
+    pub rtype: u16,
+    pub rdata: &'a [u8],
+    /// Position after it.
+    pub end: usize,
+}
+
+/// Append a record of `name`, fails if `name` is malformed.
+pub fn push_record(
+    buf: &mut Vec<u8>,
+    name: &str,
+    rtype: u16,
+    class: u16,
+    ttl_secs: u32,
+    rdata: &[u8],
+) -> Option<()> {
+    push_name(buf, name)?;
+    buf.extend_from_slice(&rtype.to_be_bytes());
+    buf.extend_from_slice(&class.to_be_bytes());
+    buf.extend_from_slice(&ttl_secs.to_be_bytes());
+    buf.extend_from_slice(&u16::try_from(rdata.len()).ok()?.to_be_bytes());
+    buf.extend_from_slice(rdata);
+    Some(())
+}
+
+/// Read record at `pos`.
+pub fn read_record(msg: &[u8], pos: usize) -> Option<Record<'_>> {
+    let (name, next) = read_name(msg, pos)?;
+    let rtype = read_u16(msg, next)?;
+    let rdlen = read_u16(msg, next + 8)? as usize;
+    let rdata = msg.get(next + 10..next + 10 + rdlen)?;
+    Some(Record {
+        name,
+        rtype,
+        rdata,
+        end: next + 10 + rdlen,
+    })
+}
+
+/// Character strings of TXT record, fails if one is longer than 255 bytes.
+pub fn push_strings<S: AsRef<str>>(buf: &mut Vec<u8>, strings: &[S]) -> Option<()> {
+    for s in strings {
+        let s = s.as_ref().as_bytes();
+        buf.push(u8::try_from(s.len()).ok()?);
+        buf.extend_from_slice(s);
+    }
+    Some(())
+}
+
+/// Character strings of TXT record data.
+pub fn read_strings(rdata: &[u8]) -> Option<Vec<String>> {
+    let mut strings = vec![];
+    let mut i = 0;
+    while i < rdata.len() {
+        let len = rdata[i] as usize;
+        strings.push(String::from_utf8(rdata.get(i + 1..i + 1 + len)?.to_vec()).ok()?);
+        i += 1 + len;
+    }
+    Some(strings)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        let mut buf = vec![];
+        push_name(&mut buf, "web.rings.").unwrap();
+        assert_eq!(buf, b"\x03web\x05rings\x00");
+        assert_eq!(
+            read_name(&buf, 0),
+            Some(("web.rings".to_owned(), buf.len()))
+        );
+
+        let long = "a".repeat(MAX_LABEL_LEN + 1);
+        assert!(push_name(&mut vec![], &long).is_none());
+        assert!(push_name(&mut vec![], &long[1..]).is_some());
+        assert!(push_name(&mut vec![], "web..rings").is_none());
+
+        // pointer to itself
+        assert_eq!(read_name(&[0xc0, 0], 0), None);
+        assert_eq!(read_name(&buf[..buf.len() - 1], 0), None);
+    }
+
+    #[test]
+    fn test_records() {
+        let mut buf = vec![];
+        push_record(&mut buf, "a.b", TYPE_TXT, CLASS_IN, 30, b"\x02hi\x01!").unwrap();
+        let record = read_record(&buf, 0).unwrap();
+        assert_eq!(record.name, "a.b");
+        assert_eq!(record.rtype, TYPE_TXT);
+        assert_eq!(record.end, buf.len());
+        assert_eq!(
+            read_strings(record.rdata),
+            Some(vec!["hi".into(), "!".into()])
+        );
+        assert!(read_record(&buf[..buf.len() - 1], 0).is_none());
+        assert!(read_strings(b"\x03hi").is_none());
+        assert!(push_strings(&mut vec![], &["a".repeat(256)]).is_none());
+    }
+}
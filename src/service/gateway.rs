@@ -0,0 +1,134 @@
+//! Gateway of HTTP services hosted by peers.
+//!
+//! A request to `/peer/:did/*path` is sent to `path` of the HTTP service of peer `did` over a
+//! [TUNNEL_HTTP] stream, peer should be connected directly and serve [super::run_tunnel] with
+//! `http_service` configured. A request with host `<name>.rings` is sent the same way to the
+//! best provider of service `name`, see [Processor::resolve_service].
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::Extension;
+use axum::extract::Path;
+use axum::middleware::Next;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use http::header;
+use http::Request;
+
+use super::http_error::HttpError;
+use super::tunnel::tunnel_stream;
+use super::tunnel::TUNNEL_HTTP;
+use crate::prelude::rings_core::dht::Did;
+use crate::prelude::rings_core::message::MessageHandler;
+use crate::processor::Processor;
+
+/// Top level domain of service names.
+pub const SERVICE_DOMAIN: &str = "rings";
+/// Wait for remote service records up to 3 seconds.
+pub(super) const RESOLVE_TIMEOUT_MS: u64 = 3000;
+
+/// Name of service if `host` is `<name>.rings`, port is ignored.
+pub fn service_name(host: &str) -> Option<&str> {
+    let host = host.split(':').next()?.trim_end_matches('.');
+    let name = host.strip_suffix(SERVICE_DOMAIN)?.strip_suffix('.')?;
+    (!name.is_empty()).then(|| name)
+}
+
+pub(super) async fn peer_gateway_handler(
+    Path((did, path)): Path<(String, String)>,
+    Extension(msg_handler): Extension<Arc<MessageHandler>>,
+    req: Request<Body>,
+) -> Result<Response<Body>, HttpError> {
+    let did = Did::from_str(&did).map_err(|_| HttpError::BadRequest)?;
+    let path = format!("/{}", path.trim_start_matches('/'));
+    forward(&msg_handler, did, &path, req).await
+}
+
+/// Send requests with host `<name>.rings` to provider of service `name`, others are passed to
+/// `next`.
+pub(super) async fn service_host_gateway(
+    req: Request<Body>,
+    next: Next<Body>,
+    processor: Processor,
+) -> Response {
+    let name = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .and_then(service_name)
+        .map(|n| n.to_owned());
+    match name {
+        Some(name) => match forward_to_service(&processor, &name, req).await {
+            Ok(r) => r.into_response(),
+            Err(e) => e.into_response(),
+        },
+        None => next.run(req).await,
+    }
+}
+
+async fn forward_to_service(
+    processor: &Processor,
+    name: &str,
+    req: Request<Body>,
+) -> Result<Response<Body>, HttpError> {
+    let providers = processor
+        .resolve_service(name, RESOLVE_TIMEOUT_MS)
+        .await
+        .map_err(|e| {
+            tracing::warn!(service = %name, "failed to resolve service: {}", e);
+            HttpError::BadGateway
+        })?;
+    let provider = providers.first().ok_or(HttpError::NotFound)?;
+    let did = Did::from_str(&provider.did).map_err(|_| HttpError::Internal)?;
+    let path = req.uri().path().to_owned();
+    forward(&processor.msg_handler, did, &path, req).await
+}
+
+/// Send `req` to `path` of HTTP service of `did`.
+async fn forward(
+    msg_handler: &MessageHandler,
+    did: Did,
+    path: &str,
+    req: Request<Body>,
+) -> Result<Response<Body>, HttpError> {
+    let path_and_query = match req.uri().query() {
+        Some(q) => format!("{}?{}", path, q),
+        None => path.to_owned(),
+    };
+    let (mut parts, body) = req.into_parts();
+    parts.uri = path_and_query.parse().map_err(|_| HttpError::BadRequest)?;
+
+    let stream = tunnel_stream(msg_handler, did, TUNNEL_HTTP)
+        .await
+        .map_err(|e| {
+            tracing::warn!(peer = ?did, "failed to open gateway stream: {}", e);
+            HttpError::BadGateway
+        })?;
+    let (mut sender, conn) = hyper::client::conn::handshake(stream).await?;
+    tokio::spawn(async move {
+        if let Err(e) = conn.await {
+            tracing::debug!(peer = ?did, "gateway connection closed: {}", e);
+        }
+    });
+    tracing::debug!(peer = ?did, uri = %parts.uri, "forward request to peer");
+    Ok(sender
+        .send_request(Request::from_parts(parts, body))
+        .await?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_service_name() {
+        assert_eq!(service_name("web.rings"), Some("web"));
+        assert_eq!(service_name("web.rings.:50000"), Some("web"));
+        assert_eq!(service_name("api.web.rings"), Some("api.web"));
+        assert_eq!(service_name("rings"), None);
+        assert_eq!(service_name(".rings"), None);
+        assert_eq!(service_name("webrings"), None);
+        assert_eq!(service_name("127.0.0.1:50000"), None);
+    }
+}
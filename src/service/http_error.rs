@@ -5,6 +5,10 @@ use axum::response::Response;
 #[derive(Debug)]
 pub enum HttpError {
     BadRequest,
+    Unauthorized,
+    NotFound,
+    BadGateway,
+    TooManyRequests,
     Internal,
 }
 
@@ -12,6 +16,10 @@ impl IntoResponse for HttpError {
     fn into_response(self) -> Response {
         let (code, msg) = match self {
             HttpError::BadRequest => (StatusCode::BAD_REQUEST, "Bad Request"),
+            HttpError::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized"),
+            HttpError::NotFound => (StatusCode::NOT_FOUND, "Not Found"),
+            HttpError::BadGateway => (StatusCode::BAD_GATEWAY, "Bad Gateway"),
+            HttpError::TooManyRequests => (StatusCode::TOO_MANY_REQUESTS, "Too Many Requests"),
             HttpError::Internal => (StatusCode::INTERNAL_SERVER_ERROR, "Internal error"),
         };
 
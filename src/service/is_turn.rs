@@ -13,13 +13,19 @@ use turn::server::*;
 use turn::Error;
 use webrtc_util::vnet::net::*;
 
+use super::turn_credential::TurnCredentials;
+
 struct AuthBuilder {
     cred_map: HashMap<String, Vec<u8>>,
+    credentials: Option<Arc<TurnCredentials>>,
 }
 
 impl AuthBuilder {
-    fn new(cred_map: HashMap<String, Vec<u8>>) -> Self {
-        Self { cred_map }
+    fn new(cred_map: HashMap<String, Vec<u8>>, credentials: Option<Arc<TurnCredentials>>) -> Self {
+        Self {
+            cred_map,
+            credentials,
+        }
     }
 }
 
@@ -27,11 +33,13 @@ impl AuthHandler for AuthBuilder {
     fn auth_handle(
         &self,
         username: &str,
-        _realm: &str,
+        realm: &str,
         _src_addr: SocketAddr,
     ) -> Result<Vec<u8>, Error> {
         if let Some(pw) = self.cred_map.get(username) {
             Ok(pw.to_vec())
+        } else if let Some(credentials) = &self.credentials {
+            credentials.auth_key(username, realm)
         } else {
             Err(Error::ErrFakeErr)
         }
@@ -40,12 +48,14 @@ impl AuthHandler for AuthBuilder {
 
 /// Run a udp turn server.
 /// more about turn server: https://docs.rs/turn/latest/turn/server/struct.Server.html
+/// Besides the static `username` and `password`, ephemeral `credentials` are accepted if given.
 pub async fn run_udp_turn(
     public_ip: &str,
     port: u16,
     username: &str,
     password: &str,
     realm: &str,
+    credentials: Option<Arc<TurnCredentials>>,
 ) -> Result<Server, Error> {
     let conn = Arc::new(UdpSocket::bind(format!("0.0.0.0:{}", port)).await?);
     let mut cred_map = HashMap::new();
@@ -62,7 +72,7 @@ pub async fn run_udp_turn(
             }),
         }],
         realm: realm.to_owned(),
-        auth_handler: Arc::new(AuthBuilder::new(cred_map)),
+        auth_handler: Arc::new(AuthBuilder::new(cred_map, credentials)),
         channel_bind_timeout: Duration::from_secs(0),
     })
     .await?;
@@ -0,0 +1,327 @@
+//! LAN discovery of nodes by mDNS.
+//!
+//! Every node advertises an instance of service `_rings._tcp.local` named by its DID, with a
+//! TXT record of its DID, network and port of its http server. Nodes query the service every
+//! [QUERY_INTERVAL_SECS], answer queries of others, and connect to nodes of the same network
+//! found on LAN via `connectPeerViaHttp`, at the address the answer came from. Of two nodes
+//! only the one with lower DID dials, so they don't race to connect to each other.
+//!
+//! Only PTR and TXT records are used, other DNS-SD browsers list the nodes but can't resolve
+//! them. Nodes found on LAN should serve http on an address reachable from LAN, like
+//! `0.0.0.0:50000`.
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+use std::time::Instant;
+
+use socket2::Domain;
+use socket2::Protocol;
+use socket2::Socket;
+use socket2::Type;
+use tokio::net::UdpSocket;
+
+use super::dns_codec::build_query;
+use super::dns_codec::push_name;
+use super::dns_codec::push_record;
+use super::dns_codec::push_strings;
+use super::dns_codec::read_question;
+use super::dns_codec::read_record;
+use super::dns_codec::read_strings;
+use super::dns_codec::read_u16;
+use super::dns_codec::CLASS_IN;
+use super::dns_codec::HEADER_LEN;
+use super::dns_codec::TYPE_PTR;
+use super::dns_codec::TYPE_TXT;
+use super::seed::RateLimiter;
+use crate::prelude::rings_core::prelude::Address;
+use crate::prelude::rings_core::swarm::TransportManager;
+use crate::processor::Processor;
+
+const MDNS_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+/// Service advertised by nodes.
+pub const SERVICE: &str = "_rings._tcp.local";
+/// Nodes query and announce themselves this often, in seconds.
+pub const QUERY_INTERVAL_SECS: u64 = 30;
+/// Failed dials of a node are retried after this many seconds.
+const RETRY_SECS: u64 = 60;
+/// Packets handled from each source in a second, others are dropped.
+const MAX_PACKETS_PER_SOURCE: u32 = 8;
+/// Nodes dialed at most in [RETRY_SECS], adverts of others are ignored until then.
+const MAX_DIALED: usize = 64;
+/// Cache flush bit of class, TXT record of an instance is unique.
+const CACHE_FLUSH: u16 = 0x8000;
+const RECORD_TTL_SECS: u32 = 120;
+
+/// TXT record of a node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Advert {
+    /// Address of node in hex, without `0x`.
+    did: String,
+    network: String,
+    /// Port of http server.
+    port: u16,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Packet {
+    /// A query, and whether it asks for [SERVICE].
+    Query(bool),
+    /// Nodes in answers of a response.
+    Response(Vec<Advert>),
+}
+
+impl Advert {
+    fn instance(&self) -> String {
+        format!("{}.{}", self.did, SERVICE)
+    }
+
+    fn to_strings(&self) -> Vec<String> {
+        vec![
+            format!("did={}", self.did),
+            format!("network={}", self.network),
+            format!("port={}", self.port),
+        ]
+    }
+
+    fn from_strings(strings: &[String]) -> Option<Self> {
+        let get = |key: &str| {
+            strings
+                .iter()
+                .find_map(|s| s.strip_prefix(key)?.strip_prefix('='))
+        };
+        Some(Self {
+            did: get("did")?.to_owned(),
+            network: get("network")?.to_owned(),
+            port: get("port")?.parse().ok()?,
+        })
+    }
+}
+
+/// Query of PTR records of [SERVICE].
+fn build_service_query() -> Vec<u8> {
+    build_query(0, 0, SERVICE, TYPE_PTR).unwrap_or_default()
+}
+
+/// Unsolicited response of PTR and TXT records of `advert`, None if DID or network of it
+/// doesn't fit in DNS.
+fn build_announcement(advert: &Advert) -> Option<Vec<u8>> {
+    // authoritative answer, two answers
+    let mut resp = vec![0, 0, 0x84, 0, 0, 0, 0, 2, 0, 0, 0, 0];
+    let instance = advert.instance();
+    let mut ptr = vec![];
+    push_name(&mut ptr, &instance)?;
+    push_record(
+        &mut resp,
+        SERVICE,
+        TYPE_PTR,
+        CLASS_IN,
+        RECORD_TTL_SECS,
+        &ptr,
+    )?;
+    let mut txt = vec![];
+    push_strings(&mut txt, &advert.to_strings())?;
+    push_record(
+        &mut resp,
+        &instance,
+        TYPE_TXT,
+        CLASS_IN | CACHE_FLUSH,
+        RECORD_TTL_SECS,
+        &txt,
+    )?;
+    Some(resp)
+}
+
+fn parse_packet(msg: &[u8]) -> Option<Packet> {
+    if msg.len() < HEADER_LEN {
+        return None;
+    }
+    let is_response = msg[2] & 0x80 != 0;
+    let questions = read_u16(msg, 4)?;
+    // answers, authorities and additionals
+    let records = (6..12)
+        .step_by(2)
+        .map(|p| read_u16(msg, p).map(|n| n as usize))
+        .sum::<Option<usize>>()?;
+    let mut pos = HEADER_LEN;
+    let mut asks_service = false;
+    for _ in 0..questions {
+        let question = read_question(msg, pos)?;
+        asks_service |= question.name.eq_ignore_ascii_case(SERVICE) && question.qtype == TYPE_PTR;
+        pos = question.end;
+    }
+    if !is_response {
+        return Some(Packet::Query(asks_service));
+    }
+    let suffix = format!(".{}", SERVICE);
+    let mut adverts = vec![];
+    for _ in 0..records {
+        let record = read_record(msg, pos)?;
+        pos = record.end;
+        if record.rtype != TYPE_TXT || !record.name.to_ascii_lowercase().ends_with(&suffix) {
+            continue;
+        }
+        adverts.extend(Advert::from_strings(&read_strings(record.rdata)?));
+    }
+    Some(Packet::Response(adverts))
+}
+
+/// Socket joined mDNS group, shared with other responders on this host.
+fn multicast_socket() -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, MDNS_PORT)).into())?;
+    socket.join_multicast_v4(&MDNS_ADDR, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// Connect to nodes in `adverts` answered from `from`, see module doc for which ones.
+fn dial(
+    processor: &Processor,
+    local: &Advert,
+    dialed: &mut HashMap<String, Instant>,
+    adverts: Vec<Advert>,
+    from: SocketAddr,
+) {
+    dialed.retain(|_, t| t.elapsed() < Duration::from_secs(RETRY_SECS));
+    for advert in adverts {
+        if advert.network != local.network
+            || advert.did.to_ascii_lowercase() <= local.did
+            || dialed.contains_key(&advert.did)
+            || dialed.len() >= MAX_DIALED
+        {
+            continue;
+        }
+        match Address::from_str(&advert.did) {
+            Ok(address) if processor.swarm.get_transport(&address).is_none() => {}
+            _ => continue,
+        }
+        dialed.insert(advert.did.clone(), Instant::now());
+        // brackets IPv6 address
+        let url = format!("http://{}", SocketAddr::new(from.ip(), advert.port));
+        let processor = processor.clone();
+        tokio::spawn(async move {
+            match processor.connect_peer_via_http(&url).await {
+                Ok(_) => tracing::info!(did = %advert.did, "connected node on LAN at {}", url),
+                Err(e) => tracing::debug!(did = %advert.did, "failed to connect {}: {}", url, e),
+            }
+        });
+    }
+}
+
+/// Send `packet` to mDNS `group`, a failure is logged, discovery goes on with next packet.
+async fn send(socket: &UdpSocket, packet: &[u8], group: SocketAddr) {
+    if let Err(e) = socket.send_to(packet, group).await {
+        tracing::warn!("failed to send mdns packet: {}", e);
+    }
+}
+
+/// Advertise this node, whose http server listens on `http_addr`, and connect to nodes of the
+/// same network found on LAN. Packets are limited for each source, and queries are answered
+/// at most once a second. It runs until socket fails to receive.
+pub async fn run_mdns(http_addr: String, processor: Processor) -> anyhow::Result<()> {
+    let local = Advert {
+        did: format!("{:x}", processor.address()),
+        network: processor.swarm.meta().network_id.clone(),
+        port: SocketAddr::from_str(&http_addr)?.port(),
+    };
+    let socket = multicast_socket()?;
+    let group = SocketAddr::from((MDNS_ADDR, MDNS_PORT));
+    let announcement = build_announcement(&local)
+        .ok_or_else(|| anyhow::anyhow!("network {} is too long to announce", local.network))?;
+    let query = build_service_query();
+    let mut dialed: HashMap<String, Instant> = HashMap::new();
+    let sources = RateLimiter::new(MAX_PACKETS_PER_SOURCE, 1000);
+    let answers = RateLimiter::new(1, 1000);
+    let mut interval = tokio::time::interval(Duration::from_secs(QUERY_INTERVAL_SECS));
+    let mut buf = [0u8; 9000];
+    tracing::info!(did = %local.did, "mdns discovery on {}", group);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                send(&socket, &announcement, group).await;
+                send(&socket, &query, group).await;
+            }
+            r = socket.recv_from(&mut buf) => {
+                let (n, from) = r?;
+                if !sources.allow(from.ip()) {
+                    continue;
+                }
+                match parse_packet(&buf[..n]) {
+                    Some(Packet::Query(true)) if answers.allow(()) => {
+                        send(&socket, &announcement, group).await;
+                    }
+                    Some(Packet::Response(adverts)) => {
+                        dial(&processor, &local, &mut dialed, adverts, from);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn advert() -> Advert {
+        Advert {
+            did: "11e807fcc88dd319270493fb2e822e388fe36ab0".to_owned(),
+            network: "rings".to_owned(),
+            port: 50000,
+        }
+    }
+
+    #[test]
+    fn test_announcement() {
+        let announcement = build_announcement(&advert()).unwrap();
+        assert_eq!(
+            parse_packet(&announcement),
+            Some(Packet::Response(vec![advert()]))
+        );
+        assert_eq!(
+            parse_packet(&build_service_query()),
+            Some(Packet::Query(true))
+        );
+        assert_eq!(parse_packet(&announcement[..announcement.len() - 1]), None);
+    }
+
+    #[test]
+    fn test_compressed_names() {
+        // TXT record whose name points to PTR data
+        let mut resp = vec![0, 0, 0x84, 0, 0, 0, 0, 2, 0, 0, 0, 0];
+        let mut ptr = vec![];
+        push_name(&mut ptr, &advert().instance()).unwrap();
+        push_record(
+            &mut resp,
+            SERVICE,
+            TYPE_PTR,
+            CLASS_IN,
+            RECORD_TTL_SECS,
+            &ptr,
+        )
+        .unwrap();
+        let rdata_at = resp.len() - ptr.len();
+        resp.extend_from_slice(&[0xc0 | (rdata_at >> 8) as u8, rdata_at as u8]);
+        resp.extend_from_slice(&TYPE_TXT.to_be_bytes());
+        resp.extend_from_slice(&CLASS_IN.to_be_bytes());
+        resp.extend_from_slice(&RECORD_TTL_SECS.to_be_bytes());
+        let txt = b"\x03a=b\x0dnetwork=rings\x0aport=50000\x2cdid=11e807fcc88dd319270493fb2e822e388fe36ab0";
+        resp.extend_from_slice(&(txt.len() as u16).to_be_bytes());
+        resp.extend_from_slice(txt);
+        assert_eq!(parse_packet(&resp), Some(Packet::Response(vec![advert()])));
+
+        // pointer to itself
+        let mut looped = resp[..12].to_vec();
+        looped[5] = 1;
+        looped.extend_from_slice(&[0xc0, 12, 0, 12, 0, 1]);
+        assert_eq!(parse_packet(&looped), None);
+    }
+}
@@ -0,0 +1,364 @@
+//! Push of metrics of node, for operators who can't scrape it, like nodes behind NAT.
+//!
+//! Every `interval_secs` of [MetricsConfig], [run_metrics_push] takes [NodeInfo] of node,
+//! flattens it to [Metric]s, and pushes them to `endpoint` by its protocol:
+//! - `statsd`: lines of `{prefix}.{name}:{value}|g` of gauges, and `|c` of increments of
+//!   counters since the last push, over UDP, packed in datagrams of at most [MAX_DATAGRAM]
+//!   bytes.
+//! - `otlp`: OTLP/HTTP request with JSON encoding, with address and network of node as
+//!   attributes of resource. Counters are monotonic cumulative sums.
+//!
+//! Running totals, like bytes sent, are counters. A total going back, like when transports
+//! are closed, is taken as a reset of its counter, which starts again from it, see
+//! [Counters]. A failed push is logged and skipped, the next one carries its increments.
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde_json::json;
+use serde_json::Value;
+use tokio::net::UdpSocket;
+
+use crate::config::MetricsConfig;
+use crate::config::MetricsProtocol;
+use crate::jsonrpc::response::NodeInfo;
+use crate::prelude::reqwest;
+use crate::prelude::rings_core::utils;
+use crate::processor::Processor;
+
+/// Largest statsd datagram, safe from fragmentation on common MTUs.
+pub const MAX_DATAGRAM: usize = 1432;
+const PUSH_TIMEOUT_MS: u64 = 5000;
+/// Cumulative temporality of OTLP sums.
+const AGGREGATION_TEMPORALITY_CUMULATIVE: u8 = 2;
+
+/// Type of a [Metric].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MetricKind {
+    /// Value at the moment.
+    Gauge,
+    /// Running total, see [Counters].
+    Counter,
+}
+
+/// A metric of node, named without prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Metric {
+    name: String,
+    kind: MetricKind,
+    value: u64,
+}
+
+impl Metric {
+    fn gauge(name: impl Into<String>, value: u64) -> Self {
+        Self {
+            name: name.into(),
+            kind: MetricKind::Gauge,
+            value,
+        }
+    }
+
+    fn counter(name: impl Into<String>, value: u64) -> Self {
+        Self {
+            name: name.into(),
+            kind: MetricKind::Counter,
+            value,
+        }
+    }
+}
+
+/// Metrics of `info`.
+fn metrics(info: &NodeInfo) -> Vec<Metric> {
+    let t = &info.transports;
+    let mut metrics = vec![
+        Metric::gauge("transports.count", t.transports as u64),
+        Metric::gauge("transports.relayed", t.relayed as u64),
+        Metric::counter("transports.bytes_sent", t.bytes_sent),
+        Metric::counter("transports.bytes_received", t.bytes_received),
+        Metric::counter("transports.packets_lost", t.packets_lost),
+        Metric::counter("replay.stale", info.replay.stale),
+        Metric::counter("replay.duplicate", info.replay.duplicate),
+    ];
+    if let Some(rtt) = t.avg_rtt_ms {
+        metrics.push(Metric::gauge("transports.avg_rtt_ms", rtt));
+    }
+    for c in info.compression.iter() {
+        metrics.extend([
+            Metric::counter(format!("compression.{}.payloads", c.codec), c.payloads),
+            Metric::counter(format!("compression.{}.raw_bytes", c.codec), c.raw_bytes),
+            Metric::counter(
+                format!("compression.{}.encoded_bytes", c.codec),
+                c.encoded_bytes,
+            ),
+        ]);
+    }
+    if let Some(echo) = &info.echo {
+        metrics.extend([
+            Metric::counter("echo.echoed", echo.echoed),
+            Metric::counter("echo.bytes", echo.bytes),
+            Metric::counter("echo.failed", echo.failed),
+        ]);
+    }
+    metrics
+}
+
+/// Counter of a push, its increment since the last push, and since when it counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CounterPoint {
+    increment: u64,
+    start_ms: u128,
+}
+
+/// Totals of counters pushed last time, and since when each counts.
+#[derive(Debug, Clone, Default)]
+struct Counters {
+    last: HashMap<String, (u64, u128)>,
+}
+
+impl Counters {
+    /// Points of counters of `metrics` observed at `now_ms`, and totals to keep once they are
+    /// pushed. A counter first seen counts from `now_ms` with all its total, and so does one
+    /// going back.
+    fn points(&self, metrics: &[Metric], now_ms: u128) -> (HashMap<String, CounterPoint>, Self) {
+        let mut points = HashMap::new();
+        let mut next = Self::default();
+        for m in metrics.iter().filter(|m| m.kind == MetricKind::Counter) {
+            let point = match self.last.get(&m.name) {
+                Some((last, start_ms)) if m.value >= *last => CounterPoint {
+                    increment: m.value - last,
+                    start_ms: *start_ms,
+                },
+                _ => CounterPoint {
+                    increment: m.value,
+                    start_ms: now_ms,
+                },
+            };
+            next.last.insert(m.name.clone(), (m.value, point.start_ms));
+            points.insert(m.name.clone(), point);
+        }
+        (points, next)
+    }
+}
+
+fn metric_name(prefix: &str, name: &str) -> String {
+    match prefix {
+        "" => name.to_owned(),
+        p => format!("{}.{}", p, name),
+    }
+}
+
+/// Statsd lines of `metrics`, packed in datagrams of at most [MAX_DATAGRAM] bytes. Counters
+/// are sent as their increments in `points`.
+fn statsd_datagrams(
+    prefix: &str,
+    metrics: &[Metric],
+    points: &HashMap<String, CounterPoint>,
+) -> Vec<String> {
+    let mut datagrams = vec![];
+    let mut current = String::new();
+    for m in metrics {
+        let name = metric_name(prefix, &m.name);
+        let line = match m.kind {
+            MetricKind::Gauge => format!("{}:{}|g", name, m.value),
+            MetricKind::Counter => match points.get(&m.name) {
+                Some(p) => format!("{}:{}|c", name, p.increment),
+                None => continue,
+            },
+        };
+        if !current.is_empty() && current.len() + 1 + line.len() > MAX_DATAGRAM {
+            datagrams.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(&line);
+    }
+    if !current.is_empty() {
+        datagrams.push(current);
+    }
+    datagrams
+}
+
+/// OTLP/HTTP JSON request of `metrics` of `info`, observed at `now_ms`. Counters are
+/// cumulative since their start in `points`.
+fn otlp_request(
+    prefix: &str,
+    info: &NodeInfo,
+    metrics: &[Metric],
+    points: &HashMap<String, CounterPoint>,
+    now_ms: u128,
+) -> Value {
+    let attr = |key: &str, value: &str| json!({"key": key, "value": {"stringValue": value}});
+    // 64 bit integers are strings in JSON encoding of OTLP
+    let nanos = |ms: u128| (ms * 1_000_000).to_string();
+    let time = nanos(now_ms);
+    let metrics = metrics
+        .iter()
+        .map(|m| {
+            let name = metric_name(prefix, &m.name);
+            let value = m.value.to_string();
+            match m.kind {
+                MetricKind::Gauge => json!({
+                    "name": name,
+                    "gauge": {"dataPoints": [{"timeUnixNano": time, "asInt": value}]},
+                }),
+                MetricKind::Counter => {
+                    let start = points.get(&m.name).map_or(now_ms, |p| p.start_ms);
+                    json!({
+                        "name": name,
+                        "sum": {
+                            "aggregationTemporality": AGGREGATION_TEMPORALITY_CUMULATIVE,
+                            "isMonotonic": true,
+                            "dataPoints": [{
+                                "startTimeUnixNano": nanos(start),
+                                "timeUnixNano": time,
+                                "asInt": value,
+                            }],
+                        },
+                    })
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "resourceMetrics": [{
+            "resource": {"attributes": [
+                attr("service.name", "rings-node"),
+                attr("service.version", &info.version),
+                attr("rings.address", &info.address),
+                attr("rings.network_id", &info.network_id),
+            ]},
+            "scopeMetrics": [{
+                "scope": {"name": "rings-node", "version": info.version},
+                "metrics": metrics,
+            }],
+        }],
+    })
+}
+
+/// Push metrics of `info`, totals of counters are kept in `counters` once it's pushed.
+async fn push(
+    config: &MetricsConfig,
+    endpoint: &str,
+    info: &NodeInfo,
+    counters: &mut Counters,
+) -> anyhow::Result<()> {
+    let metrics = metrics(info);
+    let now_ms = utils::get_epoch_ms();
+    let (points, next) = counters.points(&metrics, now_ms);
+    match config.protocol {
+        MetricsProtocol::Statsd => {
+            let socket = UdpSocket::bind("0.0.0.0:0").await?;
+            socket.connect(endpoint).await?;
+            for datagram in statsd_datagrams(&config.prefix, &metrics, &points) {
+                socket.send(datagram.as_bytes()).await?;
+            }
+        }
+        MetricsProtocol::Otlp => {
+            let body = otlp_request(&config.prefix, info, &metrics, &points, now_ms);
+            reqwest::Client::new()
+                .post(endpoint)
+                .timeout(Duration::from_millis(PUSH_TIMEOUT_MS))
+                .json(&body)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+    }
+    *counters = next;
+    Ok(())
+}
+
+/// Push metrics of node by `config` forever, see module doc. Returns at once if it has no
+/// endpoint.
+pub async fn run_metrics_push(config: MetricsConfig, processor: Processor) {
+    let endpoint = match &config.endpoint {
+        Some(e) => e.to_owned(),
+        None => return,
+    };
+    tracing::info!(protocol = %config.protocol, endpoint = %endpoint, "push metrics");
+    let mut counters = Counters::default();
+    loop {
+        tokio::time::sleep(Duration::from_secs(config.interval_secs)).await;
+        let info = processor.node_info().await;
+        if let Err(e) = push(&config, &endpoint, &info, &mut counters).await {
+            tracing::warn!(endpoint = %endpoint, "failed to push metrics: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn node_info() -> NodeInfo {
+        serde_json::from_value(json!({
+            "version": "0.1.0",
+            "protocol_version": 1,
+            "min_protocol_version": 1,
+            "version_policy": "warn",
+            "address": "0x11E807fcc88dD319270493fB2e822e388Fe36ab0",
+            "network_id": "rings",
+            "relay": false,
+            "transports": {"transports": 3, "relayed": 1, "bytes_sent": 10, "bytes_received": 20,
+                "packets_lost": 0, "avg_rtt_ms": 42},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_statsd_datagrams() {
+        let info = node_info();
+        let metrics = metrics(&info);
+        assert!(metrics.contains(&Metric::gauge("transports.avg_rtt_ms", 42)));
+        let (points, counters) = Counters::default().points(&metrics, 0);
+        let datagrams = statsd_datagrams("rings", &metrics, &points);
+        assert_eq!(datagrams.len(), 1);
+        assert!(datagrams[0].starts_with(
+            "rings.transports.count:3|g\nrings.transports.relayed:1|g\n\
+             rings.transports.bytes_sent:10|c"
+        ));
+
+        // increments since the last push, a total going back counts again from it
+        let mut info = node_info();
+        info.transports.bytes_sent = 25;
+        info.transports.bytes_received = 5;
+        let metrics = super::metrics(&info);
+        let (points, _) = counters.points(&metrics, 1000);
+        let lines = statsd_datagrams("", &metrics, &points).join("\n");
+        assert!(lines.contains("transports.bytes_sent:15|c"));
+        assert!(lines.contains("transports.bytes_received:5|c"));
+        assert!(lines.contains("replay.stale:0|c"));
+
+        let many = (0..200)
+            .map(|i| Metric::gauge(format!("gauge.{}", i), i))
+            .collect::<Vec<_>>();
+        let datagrams = statsd_datagrams("", &many, &HashMap::new());
+        assert!(datagrams.len() > 1);
+        assert!(datagrams.iter().all(|d| d.len() <= MAX_DATAGRAM));
+        assert_eq!(
+            datagrams.iter().map(|d| d.lines().count()).sum::<usize>(),
+            200
+        );
+    }
+
+    #[test]
+    fn test_otlp_request() {
+        let info = node_info();
+        let metrics = super::metrics(&info);
+        let (points, counters) = Counters::default().points(&metrics, 500);
+        let (points, _) = counters.points(&metrics, 1000);
+        let request = otlp_request("rings", &info, &metrics, &points, 1000);
+        let metrics = &request["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+        assert_eq!(metrics[0]["name"], "rings.transports.count");
+        let point = &metrics[0]["gauge"]["dataPoints"][0];
+        assert_eq!(point["asInt"], "3");
+        assert_eq!(point["timeUnixNano"], "1000000000");
+
+        assert_eq!(metrics[2]["name"], "rings.transports.bytes_sent");
+        let sum = &metrics[2]["sum"];
+        assert_eq!(sum["isMonotonic"], true);
+        assert_eq!(sum["aggregationTemporality"], 2);
+        assert_eq!(sum["dataPoints"][0]["asInt"], "10");
+        assert_eq!(sum["dataPoints"][0]["startTimeUnixNano"], "500000000");
+    }
+}
@@ -1,78 +1,229 @@
 #![warn(missing_docs)]
 //! rings-node server
+mod bootstrap;
+#[cfg(unix)]
+pub mod control;
+mod dns;
+mod dns_codec;
+mod gateway;
 mod http_error;
 #[cfg(feature = "daemon")]
 mod is_turn;
+mod mdns;
+mod metrics;
+mod seed;
+mod socks5;
+mod tunnel;
+#[cfg(feature = "daemon")]
+pub mod turn_credential;
+#[cfg(unix)]
+mod uds;
 
+use std::net::IpAddr;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
+use axum::extract::ConnectInfo;
 use axum::extract::Extension;
+use axum::middleware;
 use axum::response::IntoResponse;
+use axum::routing::any;
 use axum::routing::post;
 use axum::Router;
+pub use bootstrap::run_bootstrap;
+pub use dns::run_dns_stub;
 use http::header;
 use http::header::HeaderValue;
+use http::HeaderMap;
 #[cfg(feature = "daemon")]
 pub use is_turn::run_udp_turn;
 use jsonrpc_core::MetaIoHandler;
+pub use mdns::run_mdns;
+pub use metrics::run_metrics_push;
+pub use seed::run_idle_sweeper;
+pub use seed::HandshakeLimiter;
+pub use seed::OfferLimiter;
+pub use seed::RateLimiter;
+use socket2::Domain;
+use socket2::Protocol;
+use socket2::Socket;
+use socket2::Type;
+pub use socks5::run_socks5_proxy;
 use tower_http::cors::CorsLayer;
+pub use tunnel::run_tunnel;
+#[cfg(unix)]
+pub use uds::run_uds_service;
 
 use self::http_error::HttpError;
-use crate::prelude::rings_core::dht::Stabilization;
-use crate::prelude::rings_core::message::MessageHandler;
+use crate::jsonrpc::method::Method;
+use crate::prelude::rings_core::swarm::DrainState;
 use crate::prelude::rings_core::swarm::Swarm;
 use crate::processor::Processor;
 
-/// Run a web server to handle jsonrpc request
+/// Nonces issued by `handshakeNonce` to each client IP in [NONCE_WINDOW_MS] at most, so no
+/// client holds all nonces waiting for offers.
+const MAX_NONCES_PER_IP: u32 = 10;
+/// Window of [MAX_NONCES_PER_IP], in ms.
+const NONCE_WINDOW_MS: u128 = 60 * 1000;
+
+/// Limits nonces issued for each client IP.
+struct NonceLimiter(RateLimiter<IpAddr>);
+
+/// Run a web server to handle jsonrpc request with `processor`.
+/// If `uds_path` is set, the same jsonrpc handler is also served on that unix socket, whose
+/// callers are admin. HTTP callers are admin only with the admin token of `processor`, see
+/// [Processor::authorized].
+/// If `seed` is set, offers over HTTP are limited by it for each client IP. Handshake nonces
+/// are always limited for each client IP.
 pub async fn run_service(
     addr: String,
-    swarm: Arc<Swarm>,
-    msg_handler: Arc<MessageHandler>,
-    stabilization: Arc<Stabilization>,
+    uds_path: Option<String>,
+    processor: Processor,
+    seed: Option<Arc<HandshakeLimiter>>,
+) -> anyhow::Result<()> {
+    run_service_with_routes(addr, uds_path, processor, seed, Router::new()).await
+}
+
+/// Listen on `addr`, `[::]` accepts IPv4 clients too, whatever default of system is.
+fn tcp_listener(addr: SocketAddr) -> std::io::Result<std::net::TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(false)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+/// Resolve once `swarm` is drained, see
+/// [crate::prelude::rings_core::message::MessageHandler::drain].
+async fn wait_drained(swarm: Arc<Swarm>) {
+    while swarm.drain_state() != DrainState::Drained {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+    tracing::info!("Swarm drained, stopping service");
+}
+
+/// Same as [run_service], with extra `routes` merged into the web server.
+/// Service stops after swarm is drained.
+pub async fn run_service_with_routes(
+    addr: String,
+    uds_path: Option<String>,
+    processor: Processor,
+    seed: Option<Arc<HandshakeLimiter>>,
+    routes: Router,
 ) -> anyhow::Result<()> {
-    let binding_addr = addr.parse().unwrap();
+    let binding_addr: SocketAddr = addr.parse()?;
+    let swarm = processor.swarm.clone();
 
-    let swarm_layer = Extension(swarm.clone());
-    let msg_handler_layer = Extension(msg_handler.clone());
-    let stabilization_layer = Extension(stabilization.clone());
+    let msg_handler_layer = Extension(processor.msg_handler.clone());
 
     let mut jsonrpc_handler: MetaIoHandler<Processor> = MetaIoHandler::default();
     crate::jsonrpc::build_handler(&mut jsonrpc_handler).await;
-    let jsonrpc_handler_layer = Extension(Arc::new(jsonrpc_handler));
+    let jsonrpc_handler = Arc::new(jsonrpc_handler);
+    let jsonrpc_handler_layer = Extension(jsonrpc_handler.clone());
+    let seed_layer = Extension(seed);
+    let nonce_layer = Extension(Arc::new(NonceLimiter(RateLimiter::new(
+        MAX_NONCES_PER_IP,
+        NONCE_WINDOW_MS,
+    ))));
+    let processor_layer = Extension(processor.clone());
+    let gateway_processor = processor.clone();
 
     let axum_make_service = Router::new()
         .route(
             "/",
             post(jsonrpc_io_handler)
-                .layer(&swarm_layer)
-                .layer(&msg_handler_layer)
-                .layer(&stabilization_layer)
-                .layer(&jsonrpc_handler_layer),
+                .layer(&processor_layer)
+                .layer(&jsonrpc_handler_layer)
+                .layer(&seed_layer)
+                .layer(&nonce_layer),
         )
+        .route(
+            "/peer/:did/*path",
+            any(gateway::peer_gateway_handler).layer(&msg_handler_layer),
+        )
+        .merge(routes)
+        .layer(middleware::from_fn(move |req, next| {
+            gateway::service_host_gateway(req, next, gateway_processor.clone())
+        }))
         .layer(CorsLayer::permissive())
-        .into_make_service();
+        .into_make_service_with_connect_info::<SocketAddr>();
 
-    println!("Server listening on http://{}", addr);
-    axum::Server::bind(&binding_addr)
-        .serve(axum_make_service)
-        .await?;
+    tracing::info!(addr = %addr, "Server listening on http");
+    let drained = wait_drained(swarm);
+    let http_server = async {
+        axum::Server::from_tcp(tcp_listener(binding_addr)?)?
+            .serve(axum_make_service)
+            .with_graceful_shutdown(drained)
+            .await?;
+        anyhow::Result::<()>::Ok(())
+    };
+    match uds_path {
+        #[cfg(unix)]
+        Some(path) => {
+            tokio::select! {
+                r = http_server => r?,
+                r = run_uds_service(path, processor, jsonrpc_handler) => r?,
+            }
+        }
+        #[cfg(not(unix))]
+        Some(_) => anyhow::bail!("unix socket is not supported on this platform"),
+        None => http_server.await?,
+    }
     Ok(())
 }
 
 async fn jsonrpc_io_handler(
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     body: String,
-    Extension(swarm): Extension<Arc<Swarm>>,
-    Extension(msg_handler): Extension<Arc<MessageHandler>>,
-    Extension(stabilization): Extension<Arc<Stabilization>>,
+    Extension(processor): Extension<Processor>,
     Extension(io_handler): Extension<Arc<MetaIoHandler<Processor>>>,
+    Extension(seed): Extension<Option<Arc<HandshakeLimiter>>>,
+    Extension(nonces): Extension<Arc<NonceLimiter>>,
 ) -> Result<JsonResponse, HttpError> {
+    if let Some(limiter) = seed {
+        if (0..calls(&body, Method::AnswerOffer)).any(|_| !limiter.allow_ip(client.ip())) {
+            tracing::info!(client = %client, "too many handshakes");
+            return Err(HttpError::TooManyRequests);
+        }
+    }
+    if (0..calls(&body, Method::HandshakeNonce)).any(|_| !nonces.0.allow_ip(client.ip())) {
+        tracing::info!(client = %client, "too many handshake nonces");
+        return Err(HttpError::TooManyRequests);
+    }
+    let processor = processor.authorized(bearer(&headers));
     let r = io_handler
-        .handle_request(&body, (swarm, msg_handler, stabilization).into())
+        .handle_request(&body, processor)
         .await
         .ok_or(HttpError::BadRequest)?;
     Ok(JsonResponse(r))
 }
 
+/// Token of `Authorization: Bearer <token>` header.
+fn bearer(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+/// Calls of `method` in jsonrpc request `body`, which may be a batch.
+fn calls(body: &str, method: Method) -> usize {
+    let is_call =
+        |r: &serde_json::Value| r.get("method").and_then(|m| m.as_str()) == Some(method.as_str());
+    match serde_json::from_str::<serde_json::Value>(body) {
+        Ok(serde_json::Value::Array(batch)) => batch.iter().filter(|r| is_call(r)).count(),
+        Ok(r) => is_call(&r) as usize,
+        Err(_) => 0,
+    }
+}
+
 #[derive(Debug, Clone)]
 struct JsonResponse(String);
 
@@ -88,3 +239,18 @@ impl IntoResponse for JsonResponse {
             .into_response()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_calls() {
+        let nonce = r#"{"jsonrpc":"2.0","id":1,"method":"handshakeNonce","params":[]}"#;
+        assert_eq!(calls(nonce, Method::HandshakeNonce), 1);
+        assert_eq!(calls(nonce, Method::AnswerOffer), 0);
+        let batch = format!("[{},{}]", nonce, nonce);
+        assert_eq!(calls(&batch, Method::HandshakeNonce), 2);
+        assert_eq!(calls("garbage", Method::HandshakeNonce), 0);
+    }
+}
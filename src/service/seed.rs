@@ -0,0 +1,251 @@
+//! Seed mode, for public entry points of a network, by [SeedConfig].
+//!
+//! A seed answers offers of anyone, so it protects itself from abuse of them: offers answered
+//! over HTTP are limited for each client IP by [HandshakeLimiter], IPv6 clients by their /64
+//! prefix, offers relayed over DHT are limited for each relaying peer by [OfferLimiter], and
+//! transports which received nothing for `idle_timeout_secs` are closed by [run_idle_sweeper],
+//! so clients make room for others once they have joined the ring. Only virtual nodes keeping
+//! ring and identities working are stored by seeds, see
+//! [AdmissionPolicy::seed](crate::prelude::rings_core::admission::AdmissionPolicy::seed).
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::config::SeedConfig;
+use crate::prelude::rings_core::dht::Did;
+use crate::prelude::rings_core::swarm::TransportManager;
+use crate::prelude::rings_core::types::ice_transport::IceTransport;
+use crate::prelude::rings_core::utils;
+use crate::processor::Processor;
+
+/// Windows of keys kept at most, requests of new keys are refused while it's full of
+/// unexpired ones.
+const MAX_TRACKED_KEYS: usize = 4096;
+
+/// Windows of keys, and when expired ones are pruned last time.
+#[derive(Debug)]
+struct Windows<K> {
+    /// Start of current window and requests in it, of each key.
+    keys: HashMap<K, (u128, u32)>,
+    pruned_ms: u128,
+}
+
+/// Limits requests of each key, like client IP, in a window.
+#[derive(Debug)]
+pub struct RateLimiter<K> {
+    max: u32,
+    window_ms: u128,
+    windows: Mutex<Windows<K>>,
+}
+
+/// Limits offers answered for each client IP in a window, see [RateLimiter::allow_ip].
+pub type HandshakeLimiter = RateLimiter<IpAddr>;
+
+/// Limits offers relayed over DHT by each peer in a window.
+pub type OfferLimiter = RateLimiter<Did>;
+
+/// Key of client IP for limits. IPv6 clients are keyed by their /64 prefix, which one of them
+/// usually owns entirely.
+pub fn client_prefix(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => {
+                let mut segments = v6.segments();
+                segments[4..].fill(0);
+                IpAddr::V6(segments.into())
+            }
+        },
+        v4 => v4,
+    }
+}
+
+impl<K> RateLimiter<K>
+where K: Hash + Eq
+{
+    /// At most `max` requests of a key in `window_ms`.
+    pub fn new(max: u32, window_ms: u128) -> Self {
+        Self {
+            max,
+            window_ms,
+            windows: Mutex::new(Windows {
+                keys: HashMap::new(),
+                pruned_ms: 0,
+            }),
+        }
+    }
+
+    /// Count a request of `key`, false if it's over limit.
+    pub fn allow(&self, key: K) -> bool {
+        self.allow_at(key, utils::get_epoch_ms())
+    }
+
+    fn allow_at(&self, key: K, now: u128) -> bool {
+        let mut windows = match self.windows.lock() {
+            Ok(w) => w,
+            Err(_) => return false,
+        };
+        let window_ms = self.window_ms;
+        // pruned at most once a window, so a full map isn't scanned on every request
+        if windows.keys.len() >= MAX_TRACKED_KEYS
+            && now.saturating_sub(windows.pruned_ms) >= window_ms
+        {
+            windows
+                .keys
+                .retain(|_, (start, _)| now.saturating_sub(*start) < window_ms);
+            windows.pruned_ms = now;
+        }
+        if windows.keys.len() >= MAX_TRACKED_KEYS && !windows.keys.contains_key(&key) {
+            return false;
+        }
+        let (start, count) = windows.keys.entry(key).or_insert((now, 0));
+        if now.saturating_sub(*start) >= window_ms {
+            *start = now;
+            *count = 0;
+        }
+        if *count >= self.max {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+}
+
+impl HandshakeLimiter {
+    /// Limiter of `config`.
+    pub fn from_config(config: &SeedConfig) -> Self {
+        Self::new(
+            config.max_handshakes_per_ip,
+            config.handshake_window_secs as u128 * 1000,
+        )
+    }
+
+    /// Count a request of client `ip`, by its [client_prefix].
+    pub fn allow_ip(&self, ip: IpAddr) -> bool {
+        self.allow(client_prefix(ip))
+    }
+}
+
+impl OfferLimiter {
+    /// Limiter of `config`, each peer relays as many offers as a client IP sends.
+    pub fn from_config(config: &SeedConfig) -> Self {
+        Self::new(
+            config.max_handshakes_per_ip,
+            config.handshake_window_secs as u128 * 1000,
+        )
+    }
+}
+
+/// Peers which received nothing for a while, by counts of payloads received from them.
+#[derive(Debug, Default)]
+struct IdleTracker {
+    /// Payloads received from a peer, and since when it's unchanged.
+    seen: HashMap<Did, (u64, u128)>,
+}
+
+impl IdleTracker {
+    /// Peers of `received` counts unchanged for `idle_ms` by `now`, others are tracked again.
+    fn idle(&mut self, received: &[(Did, u64)], now: u128, idle_ms: u128) -> Vec<Did> {
+        self.seen
+            .retain(|did, _| received.iter().any(|(d, _)| d == did));
+        let mut idle = vec![];
+        for (did, count) in received {
+            let (last, since) = self.seen.entry(*did).or_insert((*count, now));
+            if *last != *count {
+                *last = *count;
+                *since = now;
+            } else if now - *since >= idle_ms {
+                idle.push(*did);
+            }
+        }
+        for did in idle.iter() {
+            self.seen.remove(did);
+        }
+        idle
+    }
+}
+
+/// Close transports which received nothing for `idle_timeout`, and remove their peers from
+/// DHT, checked every half of it.
+pub async fn run_idle_sweeper(idle_timeout: Duration, processor: Processor) {
+    let mut tracker = IdleTracker::default();
+    let period = (idle_timeout / 2).max(Duration::from_secs(1));
+    loop {
+        tokio::time::sleep(period).await;
+        let swarm = &processor.swarm;
+        let received = swarm
+            .get_addresses()
+            .into_iter()
+            .map(|a| {
+                let did = Did::from(a);
+                let count = swarm
+                    .peer_traffic_of(did)
+                    .map(|s| s.received.messages)
+                    .unwrap_or(0);
+                (did, count)
+            })
+            .collect::<Vec<_>>();
+        let idle = tracker.idle(&received, utils::get_epoch_ms(), idle_timeout.as_millis());
+        for did in idle {
+            tracing::info!(peer = ?did, "close idle transport");
+            if let Some(transport) = swarm.get_transport(&did.into()) {
+                if let Err(e) = transport.close().await {
+                    tracing::warn!(peer = ?did, "failed to close idle transport: {}", e);
+                }
+            }
+            processor.msg_handler.disconnect(did.into()).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::rings_core::ecc::SecretKey;
+
+    #[test]
+    fn test_handshake_limiter() {
+        let limiter = HandshakeLimiter::new(2, 1000);
+        let ip: IpAddr = "1.2.3.4".parse().unwrap();
+        let other: IpAddr = "1.2.3.5".parse().unwrap();
+        assert!(limiter.allow_at(ip, 0));
+        assert!(limiter.allow_at(ip, 10));
+        assert!(!limiter.allow_at(ip, 20));
+        assert!(limiter.allow_at(other, 20));
+        assert!(limiter.allow_at(ip, 1000));
+
+        // addresses of a /64 share their limit
+        let a: IpAddr = "2001:db8:1:2:aaaa::1".parse().unwrap();
+        let b: IpAddr = "2001:db8:1:2:bbbb::2".parse().unwrap();
+        assert_eq!(client_prefix(a), client_prefix(b));
+        assert_ne!(
+            client_prefix(a),
+            client_prefix("2001:db8:1:3::1".parse().unwrap())
+        );
+        assert_eq!(client_prefix("::ffff:1.2.3.4".parse().unwrap()), ip);
+
+        // new keys are refused while it's full
+        let limiter = RateLimiter::new(1, 1000);
+        for i in 0..MAX_TRACKED_KEYS {
+            assert!(limiter.allow_at(i, 0));
+        }
+        assert!(!limiter.allow_at(MAX_TRACKED_KEYS, 10));
+        assert!(!limiter.allow_at(0, 10));
+        // until windows expire
+        assert!(limiter.allow_at(MAX_TRACKED_KEYS, 1000));
+    }
+
+    #[test]
+    fn test_idle_tracker() {
+        let a: Did = SecretKey::random().address().into();
+        let b: Did = SecretKey::random().address().into();
+        let mut tracker = IdleTracker::default();
+        assert!(tracker.idle(&[(a, 0), (b, 0)], 0, 100).is_empty());
+        assert!(tracker.idle(&[(a, 0), (b, 3)], 60, 100).is_empty());
+        assert_eq!(tracker.idle(&[(a, 0), (b, 3)], 120, 100), vec![a]);
+        assert!(tracker.idle(&[(b, 3)], 150, 100).is_empty());
+        assert_eq!(tracker.idle(&[(b, 3)], 160, 100), vec![b]);
+    }
+}
@@ -0,0 +1,381 @@
+//! SOCKS5 proxy tunneling TCP connections through an exit peer.
+//!
+//! [run_socks5_proxy] accepts SOCKS5 `CONNECT` requests on a local listener, opens a
+//! [TUNNEL_SOCKS5] stream to the exit peer, which should be connected directly, and sends the
+//! target as a length prefixed `host:port`. Exit peer serves those streams by
+//! [super::run_tunnel], only for peers it authorized, and replies one status byte before
+//! relaying bytes.
+//!
+//! Clients of proxy authenticate by username and password, RFC 1929, so it's never an open
+//! proxy. Exit peer connects only to public addresses of target by default, private,
+//! loopback and link-local ones of its own network are refused, even if a public name resolves
+//! to them.
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+
+use super::tunnel::relay;
+use super::tunnel::tunnel_stream;
+use super::tunnel::TunnelStream;
+use super::tunnel::TUNNEL_SOCKS5;
+use crate::prelude::rings_core::dht::Did;
+use crate::prelude::rings_core::message::MessageHandler;
+use crate::processor::constant_time_eq;
+
+const SOCKS_VERSION: u8 = 0x05;
+const METHOD_NO_AUTH: u8 = 0x00;
+const METHOD_PASSWORD: u8 = 0x02;
+const METHOD_UNACCEPTABLE: u8 = 0xff;
+const PASSWORD_VERSION: u8 = 0x01;
+const PASSWORD_OK: u8 = 0x00;
+const PASSWORD_FAILED: u8 = 0x01;
+const CMD_CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+const REP_SUCCEEDED: u8 = 0x00;
+const REP_NOT_ALLOWED: u8 = 0x02;
+const REP_HOST_UNREACHABLE: u8 = 0x04;
+const REP_CMD_NOT_SUPPORTED: u8 = 0x07;
+
+/// Status replied by exit peer.
+const EXIT_OK: u8 = 0;
+const EXIT_DENIED: u8 = 1;
+const EXIT_UNREACHABLE: u8 = 2;
+
+/// Run a SOCKS5 proxy on `addr` for clients authenticated by `auth`, username and password,
+/// connections are tunneled through `exit`.
+pub async fn run_socks5_proxy(
+    addr: String,
+    exit: Did,
+    auth: (String, String),
+    msg_handler: Arc<MessageHandler>,
+) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(&addr).await?;
+    let auth = Arc::new(auth);
+    tracing::info!(addr = %addr, exit = ?exit, "SOCKS5 proxy listening");
+    loop {
+        let (conn, peer_addr) = listener.accept().await?;
+        let msg_handler = msg_handler.clone();
+        let auth = auth.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_socks5_conn(conn, exit, &auth, msg_handler).await {
+                tracing::warn!(client = %peer_addr, "SOCKS5 connection failed: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle_socks5_conn(
+    mut conn: TcpStream,
+    exit: Did,
+    auth: &(String, String),
+    msg_handler: Arc<MessageHandler>,
+) -> anyhow::Result<()> {
+    let target = match read_socks5_request(&mut conn, auth).await? {
+        Some(t) => t,
+        None => return Ok(()),
+    };
+    tracing::debug!(target = %target, exit = ?exit, "SOCKS5 connect");
+    let mut stream = match tunnel_stream(&msg_handler, exit, TUNNEL_SOCKS5).await {
+        Ok(s) => s,
+        Err(e) => {
+            write_socks5_reply(&mut conn, REP_HOST_UNREACHABLE).await?;
+            return Err(e);
+        }
+    };
+    stream
+        .write_all(&(target.len() as u16).to_be_bytes())
+        .await?;
+    stream.write_all(target.as_bytes()).await?;
+    stream.flush().await?;
+    let mut status = [0u8; 1];
+    stream.read_exact(&mut status).await?;
+    let rep = match status[0] {
+        EXIT_OK => REP_SUCCEEDED,
+        EXIT_DENIED => REP_NOT_ALLOWED,
+        _ => REP_HOST_UNREACHABLE,
+    };
+    write_socks5_reply(&mut conn, rep).await?;
+    if rep != REP_SUCCEEDED {
+        return Ok(());
+    }
+    relay(conn, stream).await
+}
+
+/// Serve a [TUNNEL_SOCKS5] stream as exit, refused if its peer is not `authorized`, or if
+/// target is private and not `allow_private`.
+pub(super) async fn handle_exit_stream(
+    mut stream: TunnelStream,
+    authorized: bool,
+    allow_private: bool,
+) -> anyhow::Result<()> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len).await?;
+    let mut target = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut target).await?;
+    let target = String::from_utf8(target)?;
+    if !authorized {
+        tracing::warn!(peer = ?stream.peer(), target = %target, "refuse unauthorized peer");
+        stream.write_all(&[EXIT_DENIED]).await?;
+        stream.shutdown().await?;
+        return Ok(());
+    }
+    let addrs = match tokio::net::lookup_host(&target).await {
+        Ok(addrs) => addrs
+            .filter(|a| allow_private || is_public(&a.ip()))
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            stream.write_all(&[EXIT_UNREACHABLE]).await?;
+            stream.shutdown().await?;
+            return Err(e.into());
+        }
+    };
+    if addrs.is_empty() {
+        tracing::warn!(peer = ?stream.peer(), target = %target, "refuse private target");
+        stream.write_all(&[EXIT_DENIED]).await?;
+        stream.shutdown().await?;
+        return Ok(());
+    }
+    // connect to addresses checked, a name can't resolve to another one in between
+    let conn = match TcpStream::connect(&addrs[..]).await {
+        Ok(c) => c,
+        Err(e) => {
+            stream.write_all(&[EXIT_UNREACHABLE]).await?;
+            stream.shutdown().await?;
+            return Err(e.into());
+        }
+    };
+    stream.write_all(&[EXIT_OK]).await?;
+    stream.flush().await?;
+    tracing::debug!(peer = ?stream.peer(), target = %target, "SOCKS5 exit connected");
+    relay(conn, stream).await
+}
+
+/// Address is reachable from the internet, not one of the network of this node, like
+/// private, loopback, link-local, shared or unspecified ones.
+fn is_public(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.segments() {
+            // IPv4-mapped
+            [0, 0, 0, 0, 0, 0xffff, hi, lo] => {
+                is_public_v4(&Ipv4Addr::from(((hi as u32) << 16) | lo as u32))
+            }
+            [s0, ..] => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // unique local fc00::/7
+                    || (s0 & 0xfe00) == 0xfc00
+                    // link-local fe80::/10
+                    || (s0 & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+fn is_public_v4(ip: &Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // "this network" 0.0.0.0/8
+        || a == 0
+        // shared address space 100.64.0.0/10
+        || (a == 100 && (b & 0xc0) == 64))
+}
+
+/// Negotiate with a SOCKS5 client authenticated by `auth`, username and password, returns
+/// target `host:port` of its `CONNECT` request, or None if the request is refused and replied.
+async fn read_socks5_request<S>(
+    conn: &mut S,
+    auth: &(String, String),
+) -> anyhow::Result<Option<String>>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let mut head = [0u8; 2];
+    conn.read_exact(&mut head).await?;
+    if head[0] != SOCKS_VERSION {
+        anyhow::bail!("unsupported SOCKS version {}", head[0]);
+    }
+    let mut methods = vec![0u8; head[1] as usize];
+    conn.read_exact(&mut methods).await?;
+    if !methods.contains(&METHOD_PASSWORD) {
+        conn.write_all(&[SOCKS_VERSION, METHOD_UNACCEPTABLE])
+            .await?;
+        return Ok(None);
+    }
+    conn.write_all(&[SOCKS_VERSION, METHOD_PASSWORD]).await?;
+    if !read_password(conn, auth).await? {
+        conn.write_all(&[PASSWORD_VERSION, PASSWORD_FAILED]).await?;
+        anyhow::bail!("wrong username or password");
+    }
+    conn.write_all(&[PASSWORD_VERSION, PASSWORD_OK]).await?;
+
+    let mut req = [0u8; 4];
+    conn.read_exact(&mut req).await?;
+    let host = match req[3] {
+        ATYP_IPV4 => {
+            let mut ip = [0u8; 4];
+            conn.read_exact(&mut ip).await?;
+            std::net::Ipv4Addr::from(ip).to_string()
+        }
+        ATYP_IPV6 => {
+            let mut ip = [0u8; 16];
+            conn.read_exact(&mut ip).await?;
+            format!("[{}]", std::net::Ipv6Addr::from(ip))
+        }
+        ATYP_DOMAIN => {
+            let mut len = [0u8; 1];
+            conn.read_exact(&mut len).await?;
+            let mut domain = vec![0u8; len[0] as usize];
+            conn.read_exact(&mut domain).await?;
+            String::from_utf8(domain)?
+        }
+        atyp => anyhow::bail!("unsupported address type {}", atyp),
+    };
+    let mut port = [0u8; 2];
+    conn.read_exact(&mut port).await?;
+    if req[1] != CMD_CONNECT {
+        write_socks5_reply(conn, REP_CMD_NOT_SUPPORTED).await?;
+        return Ok(None);
+    }
+    Ok(Some(format!("{}:{}", host, u16::from_be_bytes(port))))
+}
+
+/// Read username and password of client, RFC 1929, check if they are `auth`.
+async fn read_password<S>(conn: &mut S, auth: &(String, String)) -> anyhow::Result<bool>
+where S: tokio::io::AsyncRead + Unpin {
+    let mut head = [0u8; 2];
+    conn.read_exact(&mut head).await?;
+    if head[0] != PASSWORD_VERSION {
+        anyhow::bail!("unsupported password auth version {}", head[0]);
+    }
+    let mut user = vec![0u8; head[1] as usize];
+    conn.read_exact(&mut user).await?;
+    let mut len = [0u8; 1];
+    conn.read_exact(&mut len).await?;
+    let mut password = vec![0u8; len[0] as usize];
+    conn.read_exact(&mut password).await?;
+    // both are compared in full, time doesn't tell which one is wrong
+    let user_ok = constant_time_eq(&user, auth.0.as_bytes());
+    let password_ok = constant_time_eq(&password, auth.1.as_bytes());
+    Ok(user_ok & password_ok)
+}
+
+async fn write_socks5_reply<S>(conn: &mut S, rep: u8) -> anyhow::Result<()>
+where S: tokio::io::AsyncWrite + Unpin {
+    // bound address is not meaningful through a tunnel
+    conn.write_all(&[SOCKS_VERSION, rep, 0x00, ATYP_IPV4, 0, 0, 0, 0, 0, 0])
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn auth() -> (String, String) {
+        ("user".to_owned(), "secret".to_owned())
+    }
+
+    async fn write_password<S>(client: &mut S, password: &[u8])
+    where S: tokio::io::AsyncWrite + Unpin {
+        client.write_all(&[1, 4]).await.unwrap();
+        client.write_all(b"user").await.unwrap();
+        client.write_all(&[password.len() as u8]).await.unwrap();
+        client.write_all(password).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_read_socks5_request() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        client.write_all(&[5, 1, METHOD_PASSWORD]).await.unwrap();
+        write_password(&mut client, b"secret").await;
+        client.write_all(&[5, 1, 0, ATYP_DOMAIN, 11]).await.unwrap();
+        client.write_all(b"example.com").await.unwrap();
+        client.write_all(&443u16.to_be_bytes()).await.unwrap();
+        let target = read_socks5_request(&mut server, &auth()).await.unwrap();
+        assert_eq!(target, Some("example.com:443".to_owned()));
+        let mut reply = [0u8; 4];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply, [5, METHOD_PASSWORD, 1, PASSWORD_OK]);
+
+        // bind is not supported
+        client.write_all(&[5, 1, METHOD_PASSWORD]).await.unwrap();
+        write_password(&mut client, b"secret").await;
+        client
+            .write_all(&[5, 2, 0, ATYP_IPV4, 127, 0, 0, 1, 0, 80])
+            .await
+            .unwrap();
+        assert_eq!(
+            read_socks5_request(&mut server, &auth()).await.unwrap(),
+            None
+        );
+        let mut reply = [0u8; 14];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[5], REP_CMD_NOT_SUPPORTED);
+    }
+
+    #[tokio::test]
+    async fn test_socks5_auth_required() {
+        // no auth is never accepted
+        let (mut client, mut server) = tokio::io::duplex(64);
+        client.write_all(&[5, 1, METHOD_NO_AUTH]).await.unwrap();
+        assert_eq!(
+            read_socks5_request(&mut server, &auth()).await.unwrap(),
+            None
+        );
+        let mut reply = [0u8; 2];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply, [5, METHOD_UNACCEPTABLE]);
+
+        let (mut client, mut server) = tokio::io::duplex(64);
+        client.write_all(&[5, 1, METHOD_PASSWORD]).await.unwrap();
+        write_password(&mut client, b"guess").await;
+        assert!(read_socks5_request(&mut server, &auth()).await.is_err());
+        let mut reply = [0u8; 4];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply, [5, METHOD_PASSWORD, 1, PASSWORD_FAILED]);
+    }
+
+    #[test]
+    fn test_exit_destination() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+        ] {
+            assert!(!is_public(&ip.parse().unwrap()), "{} is private", ip);
+        }
+        for ip in [
+            "1.1.1.1",
+            "93.184.216.34",
+            "2606:4700::1111",
+            "::ffff:1.1.1.1",
+        ] {
+            assert!(is_public(&ip.parse().unwrap()), "{} is public", ip);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Services of this node tunneled to peers over [Stream].
+//!
+//! The first byte of every stream opened by [tunnel_stream] is a service tag, [run_tunnel]
+//! accepts streams and dispatches them by the tag, to SOCKS5 exit or the local HTTP service.
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+
+use futures::ready;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::io::ReadBuf;
+use tokio::net::TcpStream;
+
+use super::socks5;
+use crate::prelude::rings_core::dht::Did;
+use crate::prelude::rings_core::message::MessageHandler;
+use crate::prelude::rings_core::message::Stream;
+
+/// Stream to SOCKS5 exit.
+pub const TUNNEL_SOCKS5: u8 = 1;
+/// Stream to local HTTP service, see [crate::config::Config::http_service].
+pub const TUNNEL_HTTP: u8 = 2;
+
+/// [Stream] with tokio io traits.
+pub struct TunnelStream(Stream);
+
+impl TunnelStream {
+    /// Peer of the other end.
+    pub fn peer(&self) -> Did {
+        self.0.peer()
+    }
+}
+
+impl AsyncRead for TunnelStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let n = ready!(futures::io::AsyncRead::poll_read(
+            Pin::new(&mut self.get_mut().0),
+            cx,
+            buf.initialize_unfilled()
+        ))?;
+        buf.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for TunnelStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        futures::io::AsyncWrite::poll_write(Pin::new(&mut self.get_mut().0), cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        futures::io::AsyncWrite::poll_flush(Pin::new(&mut self.get_mut().0), cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        futures::io::AsyncWrite::poll_close(Pin::new(&mut self.get_mut().0), cx)
+    }
+}
+
+/// Open a stream to service `tag` of `peer`.
+pub async fn tunnel_stream(
+    msg_handler: &MessageHandler,
+    peer: Did,
+    tag: u8,
+) -> anyhow::Result<TunnelStream> {
+    let mut stream = TunnelStream(msg_handler.open_stream(peer).await?);
+    stream.write_all(&[tag]).await?;
+    Ok(stream)
+}
+
+/// Copy bytes between `conn` and `stream` until both directions are closed.
+pub(super) async fn relay(mut conn: TcpStream, mut stream: TunnelStream) -> anyhow::Result<()> {
+    tokio::io::copy_bidirectional(&mut conn, &mut stream).await?;
+    Ok(())
+}
+
+/// Accept streams of peers, SOCKS5 exit is served to `exit_peers` only, reaching private
+/// addresses only if `allow_private`, and `http_service` to all peers.
+pub async fn run_tunnel(
+    exit_peers: Vec<Did>,
+    allow_private: bool,
+    http_service: Option<String>,
+    msg_handler: Arc<MessageHandler>,
+) -> anyhow::Result<()> {
+    tracing::info!(exit_peers = ?exit_peers, http_service = ?http_service, "serving tunnels");
+    while let Some(stream) = msg_handler.accept_stream().await {
+        let authorized = exit_peers.contains(&stream.peer());
+        let http_service = http_service.clone();
+        tokio::spawn(async move {
+            let peer = stream.peer();
+            let stream = TunnelStream(stream);
+            if let Err(e) = handle_tunnel(stream, authorized, allow_private, http_service).await {
+                tracing::warn!(peer = ?peer, "tunnel failed: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+async fn handle_tunnel(
+    mut stream: TunnelStream,
+    authorized: bool,
+    allow_private: bool,
+    http_service: Option<String>,
+) -> anyhow::Result<()> {
+    match (stream.read_u8().await?, http_service) {
+        (TUNNEL_SOCKS5, _) => socks5::handle_exit_stream(stream, authorized, allow_private).await,
+        (TUNNEL_HTTP, Some(addr)) => relay(TcpStream::connect(&addr).await?, stream).await,
+        (tag, _) => {
+            stream.shutdown().await?;
+            anyhow::bail!("unsupported tunnel {}", tag)
+        }
+    }
+}
@@ -0,0 +1,298 @@
+//! Ephemeral TURN credentials, in the style of TURN REST API (draft-uberti-behave-turn-rest, RFC 7635).
+//! Username is `<expire timestamp>:<user>`, password is `base64(hmac-sha1(shared_secret, username))`,
+//! so TURN server can verify any credential without storing it.
+//!
+//! Credentials are issued over HTTP to callers presenting admin token of node, or to peers
+//! signing a [CredentialRequest] with their sessions, at most [MAX_ISSUES] times in
+//! [ISSUE_WINDOW_MS] for each client IP and each DID.
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use axum::extract::ConnectInfo;
+use axum::extract::Extension;
+use axum::extract::Query;
+use axum::routing::get;
+use axum::Json;
+use axum::Router;
+use http::HeaderMap;
+use ring::hmac;
+use serde::Deserialize;
+use serde::Serialize;
+use turn::auth::generate_auth_key;
+use turn::Error;
+
+use super::bearer;
+use super::http_error::HttpError;
+use super::RateLimiter;
+use crate::prelude::rings_core::dht::Did;
+use crate::prelude::rings_core::ecc::signers;
+use crate::prelude::rings_core::err::Result as CoreResult;
+use crate::prelude::rings_core::session::Session;
+use crate::prelude::rings_core::session::SessionManager;
+use crate::prelude::rings_core::session::Signer;
+use crate::prelude::rings_core::utils;
+use crate::processor::constant_time_eq;
+
+/// Credentials issued to a client IP, or to a DID, in [ISSUE_WINDOW_MS] at most.
+pub const MAX_ISSUES: u32 = 10;
+/// Window of [MAX_ISSUES], in milliseconds.
+pub const ISSUE_WINDOW_MS: u128 = 60_000;
+/// Signed requests are accepted if they are made within this long from now, in milliseconds.
+const REQUEST_SKEW_MS: u128 = 60_000;
+
+/// Credential issued to client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TurnCredential {
+    /// `<expire timestamp>:<user>`
+    pub username: String,
+    /// base64 encoded hmac of username
+    pub password: String,
+    /// lifetime, in seconds
+    pub ttl: u64,
+    /// TURN server urls
+    pub uris: Vec<String>,
+}
+
+/// Issue and verify time-limited TURN credentials, with optional usage quota of each credential.
+pub struct TurnCredentials {
+    key: hmac::Key,
+    ttl: Duration,
+    quota: Option<u64>,
+    uris: Vec<String>,
+    usage: Mutex<HashMap<String, u64>>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn expire_of(username: &str) -> Option<u64> {
+    username.split(':').next()?.parse().ok()
+}
+
+impl TurnCredentials {
+    /// Create with shared secret, lifetime of credential, quota of authenticated requests and server urls.
+    pub fn new(shared_secret: &str, ttl: Duration, quota: Option<u64>, uris: Vec<String>) -> Self {
+        Self {
+            key: hmac::Key::new(
+                hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+                shared_secret.as_bytes(),
+            ),
+            ttl,
+            quota,
+            uris,
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn password(&self, username: &str) -> String {
+        base64::encode(hmac::sign(&self.key, username.as_bytes()).as_ref())
+    }
+
+    /// Issue a new credential for `user`.
+    pub fn issue(&self, user: &str) -> TurnCredential {
+        let username = format!("{}:{}", now() + self.ttl.as_secs(), user);
+        TurnCredential {
+            password: self.password(&username),
+            username,
+            ttl: self.ttl.as_secs(),
+            uris: self.uris.clone(),
+        }
+    }
+
+    /// Number of authenticated requests made with `username`.
+    pub fn usage(&self, username: &str) -> u64 {
+        self.usage
+            .lock()
+            .map(|u| u.get(username).copied().unwrap_or(0))
+            .unwrap_or(0)
+    }
+
+    /// Check expiration and quota of `username`, and count this use, return its password.
+    pub fn verify(&self, username: &str) -> Result<String, Error> {
+        let ts = now();
+        let expire = expire_of(username).ok_or(Error::ErrFakeErr)?;
+        if expire < ts {
+            return Err(Error::ErrFakeErr);
+        }
+        let mut usage = self.usage.lock().map_err(|_| Error::ErrFakeErr)?;
+        // forget expired credentials
+        usage.retain(|k, _| expire_of(k).map(|e| e >= ts).unwrap_or(false));
+        let count = usage.entry(username.to_owned()).or_insert(0);
+        if let Some(quota) = self.quota {
+            if *count >= quota {
+                tracing::warn!("turn credential {} exceeds quota {}", username, quota);
+                return Err(Error::ErrFakeErr);
+            }
+        }
+        *count += 1;
+        Ok(self.password(username))
+    }
+
+    /// Long term auth key of `username`, used by TURN server.
+    pub fn auth_key(&self, username: &str, realm: &str) -> Result<Vec<u8>, Error> {
+        let password = self.verify(username)?;
+        Ok(generate_auth_key(username, realm, &password))
+    }
+}
+
+/// Request of a credential by a peer, signed by key of its session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CredentialRequest {
+    pub session: Session,
+    /// Time of request, in milliseconds.
+    pub ts_ms: u128,
+    /// Signature of [CredentialRequest::message] by session key.
+    pub sig: Vec<u8>,
+}
+
+impl CredentialRequest {
+    /// Message signed by a request made at `ts_ms`.
+    pub fn message(ts_ms: u128) -> String {
+        format!("rings-turn:{}", ts_ms)
+    }
+
+    /// Request signed by `session`.
+    pub fn new(session: &SessionManager) -> CoreResult<Self> {
+        let ts_ms = utils::get_epoch_ms();
+        Ok(Self {
+            session: session.session()?,
+            ts_ms,
+            sig: session.sign(&Self::message(ts_ms))?,
+        })
+    }
+
+    /// DID of requester, if session and signature are valid at `now`.
+    fn verify(&self, now: u128) -> Option<Did> {
+        let fresh = self.ts_ms.max(now) - self.ts_ms.min(now) <= REQUEST_SKEW_MS;
+        if !fresh || self.session.is_expired() || !self.session.verify() {
+            return None;
+        }
+        let msg = Self::message(self.ts_ms);
+        let signed = match self.session.auth.signer {
+            Signer::DEFAULT => signers::default::verify(&msg, &self.session.auth.addr, &self.sig),
+            Signer::EIP712 => signers::eip712::verify(&msg, &self.session.auth.addr, &self.sig),
+        };
+        signed.then(|| self.session.auth.authorizer.into())
+    }
+}
+
+/// Issues credentials to callers of HTTP, see module doc.
+struct CredentialIssuer {
+    credentials: Arc<TurnCredentials>,
+    admin_token: Option<String>,
+    by_ip: RateLimiter<IpAddr>,
+    by_did: RateLimiter<Did>,
+}
+
+impl CredentialIssuer {
+    fn is_admin(&self, headers: &HeaderMap) -> bool {
+        match (&self.admin_token, bearer(headers)) {
+            (Some(token), Some(b)) => constant_time_eq(token.as_bytes(), b.as_bytes()),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CredentialQuery {
+    username: Option<String>,
+}
+
+/// Routes of TURN REST API, returning a [TurnCredential]:
+/// * `GET /turn?username=<user>`, for callers presenting `admin_token` as bearer,
+/// * `POST /turn` of a [CredentialRequest], for peers, whose DID is the user.
+pub fn turn_credential_router(
+    credentials: Arc<TurnCredentials>,
+    admin_token: Option<String>,
+) -> Router {
+    let issuer = Arc::new(CredentialIssuer {
+        credentials,
+        admin_token,
+        by_ip: RateLimiter::new(MAX_ISSUES, ISSUE_WINDOW_MS),
+        by_did: RateLimiter::new(MAX_ISSUES, ISSUE_WINDOW_MS),
+    });
+    Router::new().route(
+        "/turn",
+        get(issue_credential)
+            .post(issue_credential)
+            .layer(Extension(issuer)),
+    )
+}
+
+async fn issue_credential(
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<CredentialQuery>,
+    request: Option<Json<CredentialRequest>>,
+    Extension(issuer): Extension<Arc<CredentialIssuer>>,
+) -> Result<Json<TurnCredential>, HttpError> {
+    if issuer.is_admin(&headers) {
+        let user = query.username.unwrap_or_else(|| "rings".to_owned());
+        return Ok(Json(issuer.credentials.issue(&user)));
+    }
+    if !issuer.by_ip.allow_ip(client.ip()) {
+        tracing::info!(client = %client, "too many turn credential requests");
+        return Err(HttpError::TooManyRequests);
+    }
+    let did = request
+        .and_then(|Json(r)| r.verify(utils::get_epoch_ms()))
+        .ok_or(HttpError::Unauthorized)?;
+    if !issuer.by_did.allow(did) {
+        tracing::info!(did = %did, "too many turn credential requests");
+        return Err(HttpError::TooManyRequests);
+    }
+    Ok(Json(issuer.credentials.issue(&did.to_string())))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::rings_core::ecc::SecretKey;
+
+    #[test]
+    fn test_issue_and_verify() {
+        let creds = TurnCredentials::new("secret", Duration::from_secs(60), Some(2), vec![]);
+        let c = creds.issue("alice");
+        assert!(c.username.ends_with(":alice"));
+        assert_eq!(creds.verify(&c.username).unwrap(), c.password);
+        assert_eq!(creds.usage(&c.username), 1);
+        assert!(creds.verify(&c.username).is_ok());
+        // quota exceeded
+        assert!(creds.verify(&c.username).is_err());
+    }
+
+    #[test]
+    fn test_credential_request() {
+        let key = SecretKey::random();
+        let (auth, new_key) = SessionManager::gen_unsign_info(key.address(), None, None).unwrap();
+        let sig = key.sign(&auth.to_string().unwrap()).to_vec();
+        let session = SessionManager::new(&sig, &auth, &new_key);
+        let request = CredentialRequest::new(&session).unwrap();
+        assert_eq!(request.verify(request.ts_ms), Some(key.address().into()));
+        assert_eq!(request.verify(request.ts_ms + REQUEST_SKEW_MS + 1), None);
+
+        let mut forged = request.clone();
+        forged.ts_ms += 1;
+        assert_eq!(forged.verify(request.ts_ms), None);
+        let mut forged = request;
+        forged.session.auth.authorizer = SecretKey::random().address();
+        assert_eq!(forged.verify(forged.ts_ms), None);
+    }
+
+    #[test]
+    fn test_expired() {
+        let creds = TurnCredentials::new("secret", Duration::from_secs(60), None, vec![]);
+        assert!(creds.verify("1:alice").is_err());
+        assert!(creds.verify("alice").is_err());
+    }
+}
@@ -0,0 +1,146 @@
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use jsonrpc_core::MetaIoHandler;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::net::UnixListener;
+use tokio::net::UnixStream;
+
+use super::control::read_request_line;
+use super::control::READ_TIMEOUT;
+use crate::processor::Processor;
+
+/// Bytes of a request line at most, larger than the limit of control socket, for requests
+/// carrying snapshots or messages.
+const MAX_REQUEST_LEN: u64 = 1 << 20;
+
+/// Run jsonrpc over a unix domain socket, every request and response is a single line of json.
+/// Local tools can talk to node without opening a TCP port. Callers are admin, so the socket
+/// is accessible to its owner only, and a connection is closed if a request doesn't arrive in
+/// [READ_TIMEOUT].
+pub async fn run_uds_service(
+    path: String,
+    processor: Processor,
+    io_handler: Arc<MetaIoHandler<Processor>>,
+) -> anyhow::Result<()> {
+    // remove socket file left by previous process
+    if Path::new(&path).exists() {
+        std::fs::remove_file(&path)?;
+    }
+    let listener = UnixListener::bind(&path)?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    tracing::info!(path = %path, "Server listening on unix socket");
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let processor = processor.clone();
+        let io_handler = io_handler.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_uds_stream(stream, processor, io_handler, READ_TIMEOUT).await {
+                tracing::warn!("jsonrpc over unix socket failed: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle_uds_stream(
+    stream: UnixStream,
+    processor: Processor,
+    io_handler: Arc<MetaIoHandler<Processor>>,
+    read_timeout: Duration,
+) -> anyhow::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    while let Some(line) = read_request_line(&mut reader, read_timeout, MAX_REQUEST_LEN).await? {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        // notifications have no response
+        if let Some(r) = io_handler.handle_request(line, processor.clone()).await {
+            writer.write_all(r.as_bytes()).await?;
+            writer.write_all(b"\n").await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use futures::lock::Mutex;
+    use tokio::io::AsyncBufReadExt;
+
+    use super::*;
+    use crate::prelude::rings_core::dht::Stabilization;
+    use crate::prelude::*;
+
+    #[tokio::test]
+    async fn test_uds_jsonrpc() {
+        let key = SecretKey::random();
+        let (auth, new_key) = SessionManager::gen_unsign_info(key.address(), None, None).unwrap();
+        let sig = key.sign(&auth.to_string().unwrap()).to_vec();
+        let session = SessionManager::new(&sig, &auth, &new_key);
+        let swarm = Arc::new(Swarm::new(
+            "stun://stun.l.google.com:19302",
+            key.address(),
+            session,
+        ));
+        let dht = Arc::new(Mutex::new(PeerRing::new(key.address().into())));
+        let msg_handler = Arc::new(MessageHandler::new(dht.clone(), swarm.clone()));
+        let stabilization = Arc::new(Stabilization::new(dht, swarm.clone()));
+        let processor: Processor = (swarm, msg_handler, stabilization).into();
+
+        let mut io_handler: MetaIoHandler<Processor> = MetaIoHandler::default();
+        crate::jsonrpc::build_handler(&mut io_handler).await;
+
+        let (client, server) = UnixStream::pair().unwrap();
+        let io_handler = Arc::new(io_handler);
+        tokio::spawn(handle_uds_stream(
+            server,
+            processor.clone(),
+            io_handler.clone(),
+            READ_TIMEOUT,
+        ));
+        let (reader, mut writer) = client.into_split();
+        writer
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"method\":\"listPeers\",\"params\":[],\"id\":1}\n")
+            .await
+            .unwrap();
+        let line = BufReader::new(reader)
+            .lines()
+            .next_line()
+            .await
+            .unwrap()
+            .unwrap();
+        let resp: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(resp["id"], 1);
+        assert_eq!(resp["result"], serde_json::json!([]));
+
+        // idle client is dropped
+        let (_client, server) = UnixStream::pair().unwrap();
+        let handled = handle_uds_stream(
+            server,
+            processor.clone(),
+            io_handler.clone(),
+            Duration::from_millis(50),
+        )
+        .await;
+        assert!(handled.is_err());
+
+        // request line is bounded
+        let (client, server) = UnixStream::pair().unwrap();
+        let served = tokio::spawn(handle_uds_stream(
+            server,
+            processor,
+            io_handler,
+            READ_TIMEOUT,
+        ));
+        let (_reader, mut writer) = client.into_split();
+        let huge = vec![b'x'; MAX_REQUEST_LEN as usize + 1];
+        // server may close before the whole line is written
+        let _ = writer.write_all(&huge).await;
+        assert!(served.await.unwrap().is_err());
+    }
+}
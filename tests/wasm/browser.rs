@@ -3,7 +3,6 @@ use rings_node::browser;
 use rings_node::browser::Peer;
 use rings_node::browser::SignerMode;
 use rings_node::browser::TransportAndIce;
-use rings_node::prelude::rings_core::prelude::web3::contract::tokens::Tokenizable;
 use rings_node::prelude::wasm_bindgen_futures::JsFuture;
 use rings_node::prelude::*;
 use wasm_bindgen_test::*;
@@ -13,7 +12,7 @@ wasm_bindgen_test_configure!(run_in_browser);
 fn new_client() -> browser::Client {
     let key = SecretKey::random();
     let unsigned_info = browser::UnsignedInfo::new_with_signer(
-        key.address().into_token().to_string(),
+        format!("{:x}", key.address()),
         Some(SignerMode::DEFAULT),
     )
     .ok()
@@ -90,3 +89,17 @@ async fn test_two_client_connect_and_list() {
     let peers = get_peers(&client1).await;
     assert_eq!(peers.len(), 0);
 }
+
+#[wasm_bindgen_test]
+async fn test_client_persist_and_restore() {
+    let client = new_client();
+    JsFuture::from(client.persist()).await.unwrap();
+    let restored = JsFuture::from(browser::Client::restore()).await.unwrap();
+    assert!(!restored.is_null());
+    let peers = JsFuture::from(client.resume()).await.unwrap();
+    assert_eq!(js_sys::Array::from(&peers).length(), 0);
+
+    JsFuture::from(browser::Client::forget()).await.unwrap();
+    let restored = JsFuture::from(browser::Client::restore()).await.unwrap();
+    assert!(restored.is_null());
+}
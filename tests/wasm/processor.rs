@@ -5,11 +5,11 @@ use futures::lock::Mutex;
 use rings_node::prelude::rings_core::async_trait;
 // use rings_node::browser::IntervalHandle;
 // use rings_node::prelude::rings_core;
+use rings_node::prelude::rings_core::dht::Did;
 use rings_node::prelude::rings_core::dht::Stabilization;
 use rings_node::prelude::rings_core::dht::TStabilize;
 use rings_node::prelude::rings_core::message::MessageCallback;
 use rings_node::prelude::rings_core::swarm::TransportManager;
-use rings_node::prelude::web3::contract::tokens::Tokenizable;
 // use rings_node::prelude::wasm_bindgen::prelude::Closure;
 // use rings_node::prelude::wasm_bindgen_futures::spawn_local;
 // use rings_node::prelude::web_sys::window;
@@ -34,7 +34,7 @@ fn new_processor() -> Processor {
 
     let dht = Arc::new(Mutex::new(PeerRing::new(key.address().into())));
     let msg_handler = MessageHandler::new(dht.clone(), swarm.clone());
-    let stab = Arc::new(Stabilization::new(dht, swarm.clone(), 20));
+    let stab = Arc::new(Stabilization::new(dht, swarm.clone()));
     (swarm, Arc::new(msg_handler), stab).into()
 }
 
@@ -120,8 +120,8 @@ async fn test_processor_handshake_and_msg() {
     let test_text4 = "test4";
     let test_text5 = "test5";
 
-    let p1_addr = p1.address().into_token().to_string();
-    let p2_addr = p2.address().into_token().to_string();
+    let p1_addr = format!("{:x}", p1.address());
+    let p2_addr = format!("{:x}", p2.address());
     console_log!("p1_addr: {}", p1_addr);
     console_log!("p2_addr: {}", p2_addr);
 
@@ -187,11 +187,11 @@ async fn test_processor_handshake_and_msg() {
 async fn test_processor_connect_with_address() {
     super::setup_log();
     let p1 = new_processor();
-    console_log!("p1 address: {}", p1.address().into_token().to_string());
+    console_log!("p1 address: {:x}", p1.address());
     let p2 = new_processor();
-    console_log!("p2 address: {}", p2.address().into_token().to_string());
+    console_log!("p2 address: {:x}", p2.address());
     let p3 = new_processor();
-    console_log!("p3 address: {}", p3.address().into_token().to_string());
+    console_log!("p3 address: {:x}", p3.address());
 
     listen(&p1).await;
     listen(&p2).await;
@@ -206,10 +206,9 @@ async fn test_processor_connect_with_address() {
 
     let p1_peers = p1.list_peers().await.unwrap();
     assert!(
-        p1_peers.iter().any(|p| p
-            .address
-            .to_string()
-            .eq(&p2.address().into_token().to_string())),
+        p1_peers
+            .iter()
+            .any(|p| p.address == Did::from(p2.address())),
         "p2 not in p1's peer list"
     );
 
@@ -228,11 +227,7 @@ async fn test_processor_connect_with_address() {
 
     let peers = p1.list_peers().await.unwrap();
     assert!(
-        peers.iter().any(|p| p.address.to_string().eq(p3
-            .address()
-            .into_token()
-            .to_string()
-            .as_str())),
+        peers.iter().any(|p| p.address == Did::from(p3.address())),
         "peer list dose NOT contains p3 address"
     );
     futures::join!(